@@ -0,0 +1,282 @@
+//! Encrypted event archives.
+//!
+//! Before old events are pruned from the local store they are written to an
+//! archive file so nothing is destroyed unrecoverably. An archive is:
+//!
+//! ```text
+//! "LSARCHV1" | header length (u32 LE) | header JSON | nonce (12 bytes) | AES-GCM ciphertext
+//! ```
+//!
+//! The header is plaintext so archives can be listed without the key; the
+//! ciphertext holds the events as JSON.
+
+use crate::database::{Database, StoredEvent};
+use crate::encryption::{CryptoManager, EncryptedData};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const ARCHIVE_MAGIC: &[u8; 8] = b"LSARCHV1";
+const ARCHIVE_VERSION: u32 = 1;
+const NONCE_LEN: usize = 12;
+pub const ARCHIVE_EXTENSION: &str = "lsarchive";
+
+/// Setting that enables uploading archives to the sync server ("true")
+pub const ARCHIVE_UPLOAD_SETTING: &str = "archive_upload";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveHeader {
+  pub version: u32,
+  /// Start time of the oldest archived event
+  pub range_start: DateTime<Utc>,
+  /// Exclusive upper bound of the archived range (the pruning cutoff)
+  pub range_end: DateTime<Utc>,
+  pub event_count: usize,
+  pub created_at: DateTime<Utc>,
+}
+
+/// An archive written during pruning
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportedArchive {
+  pub path: PathBuf,
+  pub header: ArchiveHeader,
+  /// Events removed from the local store after the archive was verified
+  pub pruned_count: usize,
+}
+
+/// Serialize and encrypt events into the archive format
+pub fn encode_archive(crypto: &CryptoManager, header: &ArchiveHeader, events: &[StoredEvent]) -> Result<Vec<u8>> {
+  let header_json = serde_json::to_vec(header)?;
+  let encrypted = crypto.encrypt(&serde_json::to_vec(events)?)?;
+
+  let mut bytes = Vec::with_capacity(12 + header_json.len() + NONCE_LEN + encrypted.ciphertext.len());
+  bytes.extend_from_slice(ARCHIVE_MAGIC);
+  bytes.extend_from_slice(&(header_json.len() as u32).to_le_bytes());
+  bytes.extend_from_slice(&header_json);
+  bytes.extend_from_slice(&encrypted.nonce);
+  bytes.extend_from_slice(&encrypted.ciphertext);
+  Ok(bytes)
+}
+
+/// Read only the plaintext header of an archive
+pub fn decode_header(bytes: &[u8]) -> Result<(ArchiveHeader, usize)> {
+  if bytes.len() < 12 || &bytes[..8] != ARCHIVE_MAGIC {
+    bail!("Not a Lifespan archive");
+  }
+
+  let header_len = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+  let body_start = 12 + header_len;
+  if bytes.len() < body_start + NONCE_LEN {
+    bail!("Archive is truncated");
+  }
+
+  let header: ArchiveHeader = serde_json::from_slice(&bytes[12..body_start])?;
+  if header.version != ARCHIVE_VERSION {
+    bail!("Unsupported archive version {}", header.version);
+  }
+
+  Ok((header, body_start))
+}
+
+/// Decrypt an archive back into its header and events
+pub fn decode_archive(crypto: &CryptoManager, bytes: &[u8]) -> Result<(ArchiveHeader, Vec<StoredEvent>)> {
+  let (header, body_start) = decode_header(bytes)?;
+  let encrypted = EncryptedData {
+    nonce: bytes[body_start..body_start + NONCE_LEN].to_vec(),
+    ciphertext: bytes[body_start + NONCE_LEN..].to_vec(),
+  };
+
+  let events: Vec<StoredEvent> = serde_json::from_slice(&crypto.decrypt(&encrypted)?)?;
+  if events.len() != header.event_count {
+    bail!("Archive holds {} events, header says {}", events.len(), header.event_count);
+  }
+
+  Ok((header, events))
+}
+
+/// Archive every closed event before `cutoff` into `archive_dir`, then delete
+/// them locally. Nothing is deleted unless the archive was written and reads
+/// back intact. Returns None when there is nothing to prune.
+pub fn export_and_prune(
+  db: &Database,
+  crypto: &CryptoManager,
+  archive_dir: &Path,
+  cutoff: DateTime<Utc>,
+) -> Result<Option<ExportedArchive>> {
+  let events = db.get_closed_events_before(cutoff)?;
+  let Some((path, header)) = write_verified_archive(crypto, archive_dir, cutoff, &events)? else {
+    return Ok(None);
+  };
+
+  let event_ids: Vec<String> = events.into_iter().map(|event| event.id).collect();
+  let pruned_count = db.delete_events_sync(&event_ids)?;
+  tracing::info!("Archived and pruned {} events before {} to {}", pruned_count, cutoff, path.display());
+
+  Ok(Some(ExportedArchive { path, header, pruned_count }))
+}
+
+/// Write `events` (all before `cutoff`, oldest first) to an archive in
+/// `archive_dir` and check it reads back with the same events. Returns None
+/// when there are no events.
+pub fn write_verified_archive(
+  crypto: &CryptoManager,
+  archive_dir: &Path,
+  cutoff: DateTime<Utc>,
+  events: &[StoredEvent],
+) -> Result<Option<(PathBuf, ArchiveHeader)>> {
+  let Some(oldest) = events.first() else {
+    return Ok(None);
+  };
+
+  let header = ArchiveHeader {
+    version: ARCHIVE_VERSION,
+    range_start: oldest.timestamp,
+    range_end: cutoff,
+    event_count: events.len(),
+    created_at: Utc::now(),
+  };
+  let bytes = encode_archive(crypto, &header, events)?;
+
+  fs::create_dir_all(archive_dir)?;
+  let path = archive_dir.join(format!(
+    "events-{}-{}.{}",
+    header.range_start.timestamp_millis(),
+    header.range_end.timestamp_millis(),
+    ARCHIVE_EXTENSION
+  ));
+  write_atomically(&path, &bytes)?;
+
+  // Verify what actually landed on disk before anything is deleted
+  let (_, archived) = decode_archive(crypto, &fs::read(&path)?)
+    .map_err(|e| anyhow!("Archive verification failed, nothing pruned: {}", e))?;
+  if archived.iter().zip(events).any(|(archived, event)| archived.id != event.id) {
+    bail!("Archive verification failed, nothing pruned: event ids differ");
+  }
+
+  Ok(Some((path, header)))
+}
+
+/// Write to a temp file and rename so a crash never leaves a partial archive
+fn write_atomically(path: &Path, bytes: &[u8]) -> Result<()> {
+  let tmp_path = path.with_extension("tmp");
+  {
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+  }
+  fs::rename(&tmp_path, path)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::{NewEvent, StorageBackend};
+  use chrono::TimeZone;
+  use tempfile::{NamedTempFile, TempDir};
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn test_crypto() -> CryptoManager {
+    CryptoManager::new(b"test_key_32_bytes_long_123456789").unwrap()
+  }
+
+  fn event_at(timestamp: DateTime<Utc>, app: &str) -> NewEvent {
+    NewEvent {
+      event_type: "app_usage".to_string(),
+      timestamp,
+      duration: 120,
+      app_name: app.to_string(),
+      window_title: Some("notes.md".to_string()),
+      url_domain: None,
+      remote_session: false,
+    }
+  }
+
+  #[test]
+  fn test_export_and_prune_round_trip() {
+    let (db, _temp) = create_test_db();
+    let dir = TempDir::new().unwrap();
+    let crypto = test_crypto();
+    let old = Utc.with_ymd_and_hms(2024, 1, 10, 9, 0, 0).unwrap();
+    let recent = Utc.with_ymd_and_hms(2024, 6, 10, 9, 0, 0).unwrap();
+    db.insert_events(&[event_at(old, "code.exe"), event_at(recent, "chrome.exe")]).unwrap();
+
+    let cutoff = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+    let exported = export_and_prune(&db, &crypto, dir.path(), cutoff).unwrap().unwrap();
+
+    assert_eq!(exported.pruned_count, 1);
+    assert_eq!(exported.header.range_start, old);
+    assert_eq!(exported.header.range_end, cutoff);
+    assert_eq!(db.get_event_count().unwrap(), 1);
+
+    let (header, events) = decode_archive(&crypto, &fs::read(&exported.path).unwrap()).unwrap();
+    assert_eq!(header, exported.header);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].app_name, "code.exe");
+    assert_eq!(events[0].window_title.as_deref(), Some("notes.md"));
+  }
+
+  #[test]
+  fn test_nothing_to_prune() {
+    let (db, _temp) = create_test_db();
+    let dir = TempDir::new().unwrap();
+    let cutoff = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+
+    assert!(export_and_prune(&db, &test_crypto(), dir.path(), cutoff).unwrap().is_none());
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+  }
+
+  #[test]
+  fn test_open_events_are_kept() {
+    let (db, _temp) = create_test_db();
+    let dir = TempDir::new().unwrap();
+    let old = Utc.with_ymd_and_hms(2024, 1, 10, 9, 0, 0).unwrap();
+    db.open_system_event_sync("afk", old).unwrap();
+
+    let cutoff = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+    assert!(export_and_prune(&db, &test_crypto(), dir.path(), cutoff).unwrap().is_none());
+    assert_eq!(db.get_event_count().unwrap(), 1);
+  }
+
+  #[test]
+  fn test_header_readable_without_key() {
+    let crypto = test_crypto();
+    let header = ArchiveHeader {
+      version: ARCHIVE_VERSION,
+      range_start: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+      range_end: Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap(),
+      event_count: 0,
+      created_at: Utc.with_ymd_and_hms(2024, 2, 2, 0, 0, 0).unwrap(),
+    };
+    let bytes = encode_archive(&crypto, &header, &[]).unwrap();
+
+    assert_eq!(decode_header(&bytes).unwrap().0, header);
+
+    let other = CryptoManager::new(b"different_key_32_bytes_123456789").unwrap();
+    assert!(decode_archive(&other, &bytes).is_err());
+  }
+
+  #[test]
+  fn test_rejects_foreign_and_truncated_files() {
+    assert!(decode_header(b"not an archive").is_err());
+
+    let header = ArchiveHeader {
+      version: ARCHIVE_VERSION,
+      range_start: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+      range_end: Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap(),
+      event_count: 0,
+      created_at: Utc.with_ymd_and_hms(2024, 2, 2, 0, 0, 0).unwrap(),
+    };
+    let bytes = encode_archive(&test_crypto(), &header, &[]).unwrap();
+    let (_, body_start) = decode_header(&bytes).unwrap();
+    assert!(decode_header(&bytes[..body_start]).is_err());
+  }
+}
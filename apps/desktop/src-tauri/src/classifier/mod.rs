@@ -0,0 +1,229 @@
+//! On-device category classifier.
+//!
+//! A multinomial Naive Bayes model over app name and window title tokens,
+//! trained on the user's own category corrections (`category_corrections`).
+//! It is small enough to retrain on every suggestion, so there is no model
+//! file to keep in sync. Off unless the "category_classifier_enabled"
+//! setting is "true"; while off, or when the model is unsure, suggestions
+//! come from the category rules as before. Nothing here leaves the device.
+
+use crate::database::{CategoryCorrection, Database, UNCATEGORIZED};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Setting that enables model suggestions ("true"); off by default
+pub const CLASSIFIER_ENABLED_SETTING: &str = "category_classifier_enabled";
+
+/// Model suggestions below this confidence fall back to the rules
+const MIN_CONFIDENCE: f64 = 0.6;
+
+/// Lowercase word tokens of the app name (prefixed "app:") and title
+pub fn tokenize(app_name: &str, window_title: Option<&str>) -> Vec<String> {
+  let words = |text: &str| -> Vec<String> {
+    text
+      .to_lowercase()
+      .split(|c: char| !c.is_alphanumeric())
+      .filter(|word| word.chars().count() >= 2 && !word.chars().all(|c| c.is_ascii_digit()))
+      .map(str::to_string)
+      .collect()
+  };
+
+  let app_name = app_name.trim().to_lowercase();
+  let app_name = app_name.strip_suffix(".exe").unwrap_or(&app_name);
+  let mut tokens: Vec<String> = words(app_name).into_iter().map(|word| format!("app:{}", word)).collect();
+  if let Some(title) = window_title {
+    tokens.extend(words(title));
+  }
+  tokens
+}
+
+#[derive(Debug, Default)]
+struct CategoryCounts {
+  examples: usize,
+  tokens: HashMap<String, usize>,
+  total_tokens: usize,
+}
+
+/// Naive Bayes with Laplace smoothing
+#[derive(Debug, Default)]
+pub struct Classifier {
+  categories: BTreeMap<String, CategoryCounts>,
+  vocabulary: usize,
+  examples: usize,
+}
+
+impl Classifier {
+  pub fn train(corrections: &[CategoryCorrection]) -> Self {
+    let mut classifier = Self::default();
+    let mut vocabulary = HashSet::new();
+    for correction in corrections {
+      let counts = classifier.categories.entry(correction.category.clone()).or_default();
+      counts.examples += 1;
+      for token in tokenize(&correction.app_name, correction.window_title.as_deref()) {
+        counts.total_tokens += 1;
+        *counts.tokens.entry(token.clone()).or_default() += 1;
+        vocabulary.insert(token);
+      }
+      classifier.examples += 1;
+    }
+    classifier.vocabulary = vocabulary.len();
+    classifier
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.examples == 0
+  }
+
+  /// Most likely category and its posterior probability; None without
+  /// training data or when no token of the input was ever seen
+  pub fn predict(&self, app_name: &str, window_title: Option<&str>) -> Option<(String, f64)> {
+    let tokens = tokenize(app_name, window_title);
+    let known = tokens
+      .iter()
+      .any(|token| self.categories.values().any(|counts| counts.tokens.contains_key(token)));
+    if !known {
+      return None;
+    }
+
+    let scores: Vec<(&String, f64)> = self
+      .categories
+      .iter()
+      .map(|(category, counts)| {
+        let prior = (counts.examples as f64 / self.examples as f64).ln();
+        let denominator = (counts.total_tokens + self.vocabulary) as f64;
+        let likelihood: f64 = tokens
+          .iter()
+          .map(|token| ((counts.tokens.get(token).copied().unwrap_or(0) + 1) as f64 / denominator).ln())
+          .sum();
+        (category, prior + likelihood)
+      })
+      .collect();
+
+    // Softmax over log scores
+    let max = scores.iter().map(|(_, score)| *score).fold(f64::NEG_INFINITY, f64::max);
+    let total: f64 = scores.iter().map(|(_, score)| (score - max).exp()).sum();
+    scores
+      .into_iter()
+      .max_by(|a, b| a.1.total_cmp(&b.1))
+      .map(|(category, score)| (category.clone(), (score - max).exp() / total))
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuggestionSource {
+  Model,
+  Rule,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategorySuggestion {
+  pub category: String,
+  /// Model posterior; 1.0 for rule matches
+  pub confidence: f64,
+  pub source: SuggestionSource,
+}
+
+pub fn is_enabled(db: &Database) -> Result<bool> {
+  Ok(db
+    .get_setting(CLASSIFIER_ENABLED_SETTING)?
+    .is_some_and(|value| value == "true"))
+}
+
+/// Category suggestion for an app and title: the model's when enabled and
+/// confident, otherwise the matching category rule; None when neither knows
+pub fn suggest_category(db: &Database, app_name: &str, window_title: Option<&str>) -> Result<Option<CategorySuggestion>> {
+  if is_enabled(db)? {
+    let classifier = Classifier::train(&db.get_category_corrections()?);
+    if let Some((category, confidence)) = classifier.predict(app_name, window_title) {
+      if confidence >= MIN_CONFIDENCE {
+        return Ok(Some(CategorySuggestion {
+          category,
+          confidence,
+          source: SuggestionSource::Model,
+        }));
+      }
+    }
+  }
+
+  let category = db.get_category_rules()?.categorize(app_name);
+  Ok((category != UNCATEGORIZED).then_some(CategorySuggestion {
+    category,
+    confidence: 1.0,
+    source: SuggestionSource::Rule,
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::Utc;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn correction(app_name: &str, window_title: &str, category: &str) -> CategoryCorrection {
+    CategoryCorrection {
+      id: 0,
+      app_name: app_name.to_string(),
+      window_title: Some(window_title.to_string()),
+      category: category.to_string(),
+      created_at: Utc::now(),
+    }
+  }
+
+  #[test]
+  fn test_tokenize() {
+    assert_eq!(
+      tokenize("Google Chrome.exe", Some("Rust 1.75 docs - a 2024 guide")),
+      vec!["app:google", "app:chrome", "rust", "docs", "guide"]
+    );
+    assert_eq!(tokenize("Code.exe", None), vec!["app:code"]);
+  }
+
+  #[test]
+  fn test_predict_learns_titles() {
+    let classifier = Classifier::train(&[
+      correction("chrome.exe", "Rust documentation", "learning"),
+      correction("chrome.exe", "Rust by Example", "learning"),
+      correction("chrome.exe", "YouTube - cat videos", "entertainment"),
+      correction("chrome.exe", "YouTube - music mix", "entertainment"),
+    ]);
+
+    let (category, confidence) = classifier.predict("chrome.exe", Some("Rust async book")).unwrap();
+    assert_eq!(category, "learning");
+    assert!(confidence > MIN_CONFIDENCE);
+
+    let (category, _) = classifier.predict("chrome.exe", Some("YouTube - trailers")).unwrap();
+    assert_eq!(category, "entertainment");
+
+    assert_eq!(classifier.predict("unseen.exe", Some("nothing known")), None);
+    assert!(Classifier::train(&[]).is_empty());
+  }
+
+  #[test]
+  fn test_suggest_falls_back_to_rules() {
+    let (db, _temp) = create_test_db();
+    for _ in 0..3 {
+      db.add_category_correction("slack.exe", Some("standup"), "meetings", Utc::now())
+        .unwrap();
+    }
+
+    // Disabled: the rules decide
+    let suggestion = suggest_category(&db, "slack.exe", Some("standup")).unwrap().unwrap();
+    assert_eq!(suggestion.source, SuggestionSource::Rule);
+    assert_eq!(suggestion.category, "communication");
+
+    db.set_setting(CLASSIFIER_ENABLED_SETTING, "true").unwrap();
+    let suggestion = suggest_category(&db, "slack.exe", Some("standup")).unwrap().unwrap();
+    assert_eq!(suggestion.source, SuggestionSource::Model);
+    assert_eq!(suggestion.category, "meetings");
+
+    assert_eq!(suggest_category(&db, "unknown.exe", None).unwrap(), None);
+  }
+}
@@ -0,0 +1,177 @@
+//! Camera and microphone usage detection.
+//!
+//! Windows records capability access per app under the CapabilityAccessManager
+//! consent store: an app whose `LastUsedTimeStop` is 0 after a non-zero
+//! `LastUsedTimeStart` is holding the device right now. The tracking loop
+//! keeps a `camera_on` / `mic_on` event open for as long as some app does, so
+//! meetings and recordings stand out from regular app usage.
+
+use anyhow::Result;
+
+/// Whether this platform can report camera/microphone use
+pub const SUPPORTED: bool = cfg!(windows);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureDevice {
+  Camera,
+  Microphone,
+}
+
+impl CaptureDevice {
+  pub const ALL: [CaptureDevice; 2] = [CaptureDevice::Camera, CaptureDevice::Microphone];
+
+  pub fn event_type(&self) -> &'static str {
+    match self {
+      CaptureDevice::Camera => "camera_on",
+      CaptureDevice::Microphone => "mic_on",
+    }
+  }
+
+  #[cfg_attr(not(windows), allow(dead_code))]
+  fn consent_store(&self) -> &'static str {
+    match self {
+      CaptureDevice::Camera => "webcam",
+      CaptureDevice::Microphone => "microphone",
+    }
+  }
+}
+
+/// App name for a consent store key: desktop apps are keyed by their exe path
+/// with '#' for '\' ("C:#Program Files#Zoom#bin#Zoom.exe"), packaged apps by
+/// family name ("Microsoft.WindowsCamera_8wekyb3d8bbwe")
+#[cfg_attr(not(windows), allow(dead_code))]
+fn app_name_from_consent_key(key: &str, non_packaged: bool) -> String {
+  let name = if non_packaged {
+    key.rsplit('#').next()
+  } else {
+    key.split('_').next()
+  };
+  name.filter(|name| !name.is_empty()).unwrap_or(key).to_string()
+}
+
+/// The app currently using `device`, if any
+#[cfg(windows)]
+pub fn app_using(device: CaptureDevice) -> Result<Option<String>> {
+  windows_impl::app_using(device)
+}
+
+#[cfg(not(windows))]
+pub fn app_using(_device: CaptureDevice) -> Result<Option<String>> {
+  Ok(None)
+}
+
+#[cfg(windows)]
+mod windows_impl {
+  use super::{app_name_from_consent_key, CaptureDevice};
+  use anyhow::{anyhow, Result};
+  use std::ffi::c_void;
+  use windows::core::{HSTRING, PCWSTR, PWSTR};
+  use windows::Win32::System::Registry::{
+    RegCloseKey, RegEnumKeyExW, RegGetValueW, RegOpenKeyExW, HKEY, HKEY_CURRENT_USER, KEY_READ, RRF_RT_REG_QWORD,
+  };
+
+  const CONSENT_STORE: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\CapabilityAccessManager\\ConsentStore";
+
+  /// An open registry key, closed on drop
+  struct Key(HKEY);
+
+  impl Key {
+    fn open(parent: HKEY, path: &str) -> Option<Key> {
+      let mut key = HKEY::default();
+      let status = unsafe { RegOpenKeyExW(parent, &HSTRING::from(path), 0, KEY_READ, &mut key) };
+      status.is_ok().then_some(Key(key))
+    }
+
+    fn subkeys(&self) -> Vec<String> {
+      let mut names = Vec::new();
+      let mut buffer = [0u16; 512];
+      for index in 0.. {
+        let mut len = buffer.len() as u32;
+        let status = unsafe {
+          RegEnumKeyExW(self.0, index, PWSTR(buffer.as_mut_ptr()), &mut len, None, PWSTR::null(), None, None)
+        };
+        if status.is_err() {
+          break;
+        }
+        names.push(String::from_utf16_lossy(&buffer[..len as usize]));
+      }
+      names
+    }
+
+    fn read_qword(&self, name: &str) -> Option<u64> {
+      let mut value: u64 = 0;
+      let mut size = std::mem::size_of::<u64>() as u32;
+      let status = unsafe {
+        RegGetValueW(
+          self.0,
+          PCWSTR::null(),
+          &HSTRING::from(name),
+          RRF_RT_REG_QWORD,
+          None,
+          Some(&mut value as *mut u64 as *mut c_void),
+          Some(&mut size),
+        )
+      };
+      status.is_ok().then_some(value)
+    }
+
+    /// Started using the device and hasn't stopped since
+    fn in_use(&self) -> bool {
+      matches!(
+        (self.read_qword("LastUsedTimeStart"), self.read_qword("LastUsedTimeStop")),
+        (Some(start), Some(0)) if start != 0
+      )
+    }
+  }
+
+  impl Drop for Key {
+    fn drop(&mut self) {
+      unsafe {
+        let _ = RegCloseKey(self.0);
+      }
+    }
+  }
+
+  pub fn app_using(device: CaptureDevice) -> Result<Option<String>> {
+    let path = format!("{}\\{}", CONSENT_STORE, device.consent_store());
+    let store = Key::open(HKEY_CURRENT_USER, &path).ok_or_else(|| anyhow!("Consent store missing: {}", path))?;
+
+    for name in store.subkeys() {
+      if name == "NonPackaged" {
+        let Some(desktop_apps) = Key::open(store.0, &name) else {
+          continue;
+        };
+        for app in desktop_apps.subkeys() {
+          if Key::open(desktop_apps.0, &app).is_some_and(|key| key.in_use()) {
+            return Ok(Some(app_name_from_consent_key(&app, true)));
+          }
+        }
+      } else if Key::open(store.0, &name).is_some_and(|key| key.in_use()) {
+        return Ok(Some(app_name_from_consent_key(&name, false)));
+      }
+    }
+
+    Ok(None)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_app_name_from_consent_key() {
+    assert_eq!(app_name_from_consent_key("C:#Program Files#Zoom#bin#Zoom.exe", true), "Zoom.exe");
+    assert_eq!(
+      app_name_from_consent_key("Microsoft.WindowsCamera_8wekyb3d8bbwe", false),
+      "Microsoft.WindowsCamera"
+    );
+    assert_eq!(app_name_from_consent_key("", true), "");
+  }
+
+  #[test]
+  fn test_event_types() {
+    assert_eq!(CaptureDevice::Camera.event_type(), "camera_on");
+    assert_eq!(CaptureDevice::Microphone.event_type(), "mic_on");
+  }
+}
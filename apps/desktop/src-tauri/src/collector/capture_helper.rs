@@ -0,0 +1,380 @@
+//! Out-of-process window capture.
+//!
+//! The main app (which holds the database and network access) delegates every
+//! query about other applications' windows and the user's input to a child
+//! process started with [`HELPER_ARG`]: the foreground window, its title and
+//! browser tab, full-screen and remote-session state, the time since the last
+//! input, and the `SetWinEventHook` foreground change hook. The helper answers
+//! requests over its stdin/stdout pipes and pushes foreground changes over
+//! stdout as they happen; if it crashes or hangs it is restarted on the next
+//! poll without taking the tracking loop down.
+//!
+//! This is crash and fault isolation, not a sandbox: the helper runs with the
+//! same user token as the app, and only the split of responsibilities keeps the
+//! UI queries out of the process that talks to the server.
+
+use super::idle_detector::IdleDetector;
+use super::window_tracker::{WindowInfo, WindowTracker};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::{error, info};
+
+/// Command-line flag that starts the binary in capture helper mode
+pub const HELPER_ARG: &str = "--capture-helper";
+
+const ACTIVE_WINDOW_REQUEST: &str = "active_window";
+/// Same as ACTIVE_WINDOW_REQUEST, also reading the active browser tab's domain
+const ACTIVE_WINDOW_WITH_DOMAIN_REQUEST: &str = "active_window_with_domain";
+const IDLE_TIME_REQUEST: &str = "idle_time";
+
+/// How long a request may take before the helper is considered hung
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HelperRequest {
+  ActiveWindow { with_domain: bool },
+  IdleTime,
+}
+
+impl HelperRequest {
+  fn parse(line: &str) -> Option<Self> {
+    match line.trim() {
+      ACTIVE_WINDOW_REQUEST => Some(Self::ActiveWindow { with_domain: false }),
+      ACTIVE_WINDOW_WITH_DOMAIN_REQUEST => Some(Self::ActiveWindow { with_domain: true }),
+      IDLE_TIME_REQUEST => Some(Self::IdleTime),
+      _ => None,
+    }
+  }
+
+  fn as_str(self) -> &'static str {
+    match self {
+      Self::ActiveWindow { with_domain: false } => ACTIVE_WINDOW_REQUEST,
+      Self::ActiveWindow { with_domain: true } => ACTIVE_WINDOW_WITH_DOMAIN_REQUEST,
+      Self::IdleTime => IDLE_TIME_REQUEST,
+    }
+  }
+}
+
+/// A line the helper writes: the answer to a request, or a foreground change
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum HelperMessage {
+  Window(WindowInfo),
+  /// Milliseconds since the last keyboard or mouse input
+  IdleTime(u64),
+  Error(String),
+  /// Pushed unasked whenever another window comes to the foreground
+  ForegroundChanged,
+}
+
+/// Entry point of the helper process: answer capture requests until stdin closes
+pub fn run_helper() -> Result<()> {
+  let tracker = WindowTracker::new()?;
+  let idle_detector = IdleDetector::new()?;
+  let writer = Arc::new(Mutex::new(std::io::stdout()));
+
+  // Dropped (unhooked) when stdin closes
+  #[cfg(windows)]
+  let _foreground_hook = forward_foreground_changes(writer.clone());
+
+  let stdin = std::io::stdin();
+  serve(stdin.lock(), &writer, |request| match request {
+    HelperRequest::ActiveWindow { with_domain } => {
+      tracker.set_browser_domain_capture(with_domain);
+      tracker.get_active_window_info().map(HelperMessage::Window)
+    }
+    HelperRequest::IdleTime => idle_detector
+      .idle_time()
+      .map(|idle| HelperMessage::IdleTime(idle.as_millis() as u64)),
+  })
+}
+
+/// Install the foreground hook in the helper and push its notifications to
+/// the app; without it the app keeps polling
+#[cfg(windows)]
+fn forward_foreground_changes(writer: Arc<Mutex<std::io::Stdout>>) -> Option<super::foreground_hook::ForegroundHook> {
+  let (hook, mut changes) = super::foreground_hook::ForegroundHook::start().ok()?;
+  std::thread::Builder::new()
+    .name("foreground-forwarder".to_string())
+    .spawn(move || {
+      while changes.blocking_recv().is_some() {
+        if write_message(&writer, &HelperMessage::ForegroundChanged).is_err() {
+          break;
+        }
+      }
+    })
+    .ok()?;
+  Some(hook)
+}
+
+fn write_message<W: Write>(writer: &Mutex<W>, message: &HelperMessage) -> Result<()> {
+  let mut writer = writer.lock().unwrap();
+  serde_json::to_writer(&mut *writer, message)?;
+  writer.write_all(b"\n")?;
+  writer.flush()?;
+  Ok(())
+}
+
+fn serve<R, W, F>(reader: R, writer: &Mutex<W>, handle: F) -> Result<()>
+where
+  R: BufRead,
+  W: Write,
+  F: Fn(HelperRequest) -> Result<HelperMessage>,
+{
+  for line in reader.lines() {
+    let line = line?;
+    let message = match HelperRequest::parse(&line) {
+      Some(request) => handle(request).unwrap_or_else(|e| HelperMessage::Error(e.to_string())),
+      None => HelperMessage::Error(format!("Unknown request: {}", line.trim())),
+    };
+    write_message(writer, &message)?;
+  }
+
+  Ok(())
+}
+
+/// Where the helper's foreground changes go; replaced when the tracking loop restarts
+type ForegroundSender = Arc<Mutex<Option<UnboundedSender<()>>>>;
+
+struct HelperProcess {
+  child: Child,
+  stdin: ChildStdin,
+  responses: Receiver<HelperMessage>,
+}
+
+impl HelperProcess {
+  fn spawn(foreground: ForegroundSender) -> Result<Self> {
+    let exe = std::env::current_exe()?;
+    let mut child = Command::new(exe)
+      .arg(HELPER_ARG)
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::null())
+      .spawn()?;
+
+    let stdin = child.stdin.take().ok_or_else(|| anyhow!("Capture helper has no stdin"))?;
+    let stdout = child.stdout.take().ok_or_else(|| anyhow!("Capture helper has no stdout"))?;
+    info!("Started capture helper process (pid {})", child.id());
+
+    // Answers go to the waiting request, foreground changes to the tracking loop;
+    // the thread ends when the helper's stdout closes
+    let (responses_tx, responses) = std::sync::mpsc::channel();
+    std::thread::Builder::new()
+      .name("capture-helper-reader".to_string())
+      .spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+          let Ok(line) = line else {
+            break;
+          };
+          match serde_json::from_str(&line) {
+            Ok(HelperMessage::ForegroundChanged) => {
+              if let Some(sender) = foreground.lock().unwrap().as_ref() {
+                let _ = sender.send(());
+              }
+            }
+            Ok(message) => {
+              if responses_tx.send(message).is_err() {
+                break;
+              }
+            }
+            Err(e) => {
+              error!("Unreadable message from capture helper: {}", e);
+              break;
+            }
+          }
+        }
+      })?;
+
+    Ok(Self { child, stdin, responses })
+  }
+
+  /// Err: the helper is unusable (exited, hung or garbled)
+  fn request(&mut self, request: HelperRequest) -> Result<HelperMessage> {
+    writeln!(self.stdin, "{}", request.as_str())?;
+    self.stdin.flush()?;
+
+    match self.responses.recv_timeout(RESPONSE_TIMEOUT) {
+      Ok(message) => Ok(message),
+      Err(RecvTimeoutError::Timeout) => Err(anyhow!("Capture helper stopped responding")),
+      Err(RecvTimeoutError::Disconnected) => Err(anyhow!("Capture helper exited")),
+    }
+  }
+}
+
+impl Drop for HelperProcess {
+  fn drop(&mut self) {
+    let _ = self.child.kill();
+    let _ = self.child.wait();
+  }
+}
+
+/// Client side of the capture helper, restarted lazily after a crash
+pub struct CaptureHelper {
+  process: Mutex<Option<HelperProcess>>,
+  foreground: ForegroundSender,
+}
+
+impl CaptureHelper {
+  pub fn new() -> Self {
+    Self {
+      process: Mutex::new(None),
+      foreground: Arc::new(Mutex::new(None)),
+    }
+  }
+
+  /// Notifications of foreground window changes, across helper restarts; a
+  /// new call takes them over from the previous receiver
+  pub fn foreground_changes(&self) -> UnboundedReceiver<()> {
+    let (tx, rx) = unbounded_channel();
+    *self.foreground.lock().unwrap() = Some(tx);
+    rx
+  }
+
+  pub fn get_active_window_info(&self, with_domain: bool) -> Result<WindowInfo> {
+    match self.request(HelperRequest::ActiveWindow { with_domain })? {
+      HelperMessage::Window(info) => Ok(info),
+      HelperMessage::Error(e) => Err(anyhow!(e)),
+      other => Err(anyhow!("Unexpected capture helper answer: {:?}", other)),
+    }
+  }
+
+  /// Time since the last keyboard or mouse input
+  pub fn idle_time(&self) -> Result<Duration> {
+    match self.request(HelperRequest::IdleTime)? {
+      HelperMessage::IdleTime(millis) => Ok(Duration::from_millis(millis)),
+      HelperMessage::Error(e) => Err(anyhow!(e)),
+      other => Err(anyhow!("Unexpected capture helper answer: {:?}", other)),
+    }
+  }
+
+  fn request(&self, request: HelperRequest) -> Result<HelperMessage> {
+    let mut process = self.process.lock().unwrap();
+
+    if process.is_none() {
+      *process = Some(HelperProcess::spawn(self.foreground.clone())?);
+    }
+
+    match process.as_mut().map(|p| p.request(request)) {
+      Some(Ok(message)) => Ok(message),
+      Some(Err(e)) => {
+        // Drop the broken helper; the next poll starts a fresh one
+        error!("Capture helper failed, restarting on next poll: {}", e);
+        *process = None;
+        Err(e)
+      }
+      None => Err(anyhow!("Capture helper not running")),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::Utc;
+
+  fn test_window_info() -> WindowInfo {
+    WindowInfo {
+      process_name: "code.exe".to_string(),
+      window_title: "main.rs - lifespan".to_string(),
+      timestamp: Utc::now(),
+      ..Default::default()
+    }
+  }
+
+  fn lines(output: Mutex<Vec<u8>>) -> Vec<HelperMessage> {
+    let output = output.into_inner().unwrap();
+    std::str::from_utf8(&output)
+      .unwrap()
+      .lines()
+      .map(|line| serde_json::from_str(line).unwrap())
+      .collect()
+  }
+
+  fn window_answer(request: HelperRequest) -> Result<HelperMessage> {
+    match request {
+      HelperRequest::ActiveWindow { .. } => Ok(HelperMessage::Window(test_window_info())),
+      HelperRequest::IdleTime => Ok(HelperMessage::IdleTime(1500)),
+    }
+  }
+
+  #[test]
+  fn test_serve_answers_requests() {
+    let input = b"active_window\nidle_time\n";
+    let output = Mutex::new(Vec::new());
+
+    serve(&input[..], &output, window_answer).unwrap();
+
+    let messages = lines(output);
+    assert_eq!(messages.len(), 2);
+    assert!(matches!(&messages[0], HelperMessage::Window(info) if info.process_name == "code.exe"));
+    assert!(matches!(messages[1], HelperMessage::IdleTime(1500)));
+  }
+
+  #[test]
+  fn test_serve_passes_domain_flag() {
+    let input = b"active_window_with_domain\nactive_window\n";
+    let output = Mutex::new(Vec::new());
+
+    serve(&input[..], &output, |request| {
+      let mut info = test_window_info();
+      info.url_domain = (request == HelperRequest::ActiveWindow { with_domain: true }).then(|| "github.com".to_string());
+      Ok(HelperMessage::Window(info))
+    })
+    .unwrap();
+
+    let domains: Vec<Option<String>> = lines(output)
+      .into_iter()
+      .map(|message| match message {
+        HelperMessage::Window(info) => info.url_domain,
+        other => panic!("unexpected message: {:?}", other),
+      })
+      .collect();
+    assert_eq!(domains, vec![Some("github.com".to_string()), None]);
+  }
+
+  #[test]
+  fn test_serve_reports_capture_errors() {
+    let input = b"active_window\n";
+    let output = Mutex::new(Vec::new());
+
+    serve(&input[..], &output, |_| Err(anyhow!("No active window found"))).unwrap();
+
+    assert!(matches!(&lines(output)[..], [HelperMessage::Error(e)] if *e == "No active window found"));
+  }
+
+  #[test]
+  fn test_serve_rejects_unknown_requests() {
+    let input = b"read_database\n";
+    let output = Mutex::new(Vec::new());
+
+    serve(&input[..], &output, window_answer).unwrap();
+
+    assert!(matches!(&lines(output)[..], [HelperMessage::Error(e)] if e.contains("Unknown request")));
+  }
+
+  #[test]
+  fn test_requests_round_trip() {
+    for request in [
+      HelperRequest::ActiveWindow { with_domain: false },
+      HelperRequest::ActiveWindow { with_domain: true },
+      HelperRequest::IdleTime,
+    ] {
+      assert_eq!(HelperRequest::parse(request.as_str()), Some(request));
+    }
+  }
+
+  #[test]
+  fn test_foreground_changes_are_pushed_as_their_own_lines() {
+    let output = Mutex::new(Vec::new());
+    write_message(&output, &HelperMessage::ForegroundChanged).unwrap();
+    serve(&b"idle_time\n"[..], &output, window_answer).unwrap();
+
+    assert!(matches!(&lines(output)[..], [HelperMessage::ForegroundChanged, HelperMessage::IdleTime(1500)]));
+  }
+}
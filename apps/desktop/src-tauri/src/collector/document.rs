@@ -0,0 +1,152 @@
+//! Open document detection for editors and office apps.
+//!
+//! Known editors put the open file in their window title. The tracking loop
+//! parses it out of the already redacted title into the event's `document`
+//! field, so time can be reported per file. Editors that also show the
+//! workspace (VS Code, JetBrains IDEs, Sublime Text) yield "project/file";
+//! file names never contain '/', so the project is everything before it.
+
+/// Titles VS Code shows for its own tabs rather than files
+const VSCODE_NON_DOCUMENTS: &[&str] = &["Welcome", "Settings", "Keyboard Shortcuts", "Extensions", "Release Notes"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Editor {
+  VsCode,
+  JetBrains,
+  Sublime,
+  Office,
+  Photoshop,
+  Notepad,
+}
+
+fn editor_for(process_name: &str) -> Option<Editor> {
+  let name = process_name.trim().to_lowercase();
+  let name = name.strip_suffix(".exe").unwrap_or(&name);
+  match name {
+    "code" | "code - insiders" | "codium" | "vscodium" | "cursor" => Some(Editor::VsCode),
+    "idea" | "idea64" | "pycharm" | "pycharm64" | "webstorm" | "webstorm64" | "clion" | "clion64" | "goland"
+    | "goland64" | "rider" | "rider64" | "rustrover" | "rustrover64" | "phpstorm" | "phpstorm64" => Some(Editor::JetBrains),
+    "sublime_text" | "subl" => Some(Editor::Sublime),
+    "winword" | "excel" | "powerpnt" => Some(Editor::Office),
+    "photoshop" => Some(Editor::Photoshop),
+    "notepad" | "notepad++" => Some(Editor::Notepad),
+    _ => None,
+  }
+}
+
+/// Last path component, for titles that show a full path
+fn file_name(path: &str) -> &str {
+  path.rsplit(['/', '\\']).next().unwrap_or(path).trim()
+}
+
+fn with_project(project: Option<&str>, file: &str) -> String {
+  match project.map(str::trim).filter(|project| !project.is_empty()) {
+    Some(project) => format!("{}/{}", project, file),
+    None => file.to_string(),
+  }
+}
+
+/// The document open in `process_name`, parsed from its window title; None
+/// for unknown apps and titles that don't name a file
+pub fn document_from_title(process_name: &str, title: &str) -> Option<String> {
+  let title = title.trim();
+  let document = match editor_for(process_name)? {
+    // "● main.rs - lifespan - Visual Studio Code"
+    Editor::VsCode => {
+      let parts: Vec<&str> = title.trim_start_matches('●').trim().split(" - ").collect();
+      let (file, project) = match parts.as_slice() {
+        [file, project, _app] => (*file, Some(*project)),
+        [file, _app] if file.contains('.') => (*file, None),
+        _ => return None,
+      };
+      (!VSCODE_NON_DOCUMENTS.contains(&file)).then(|| with_project(project, file))
+    }
+    // "lifespan – src/main.rs" (en dash), or just "lifespan" with no file open
+    Editor::JetBrains => title
+      .split_once(" – ")
+      .map(|(project, path)| with_project(Some(project), file_name(path))),
+    // "~/code/lifespan/src/main.rs (lifespan) - Sublime Text", "• " marks unsaved
+    Editor::Sublime => title.rsplit_once(" - ").map(|(rest, _app)| {
+      let rest = rest.trim_start_matches('•').trim();
+      match rest.strip_suffix(')').and_then(|rest| rest.rsplit_once(" (")) {
+        Some((path, project)) => with_project(Some(project), file_name(path)),
+        None => file_name(rest).to_string(),
+      }
+    }),
+    // "Report.docx - Word", "Budget.xlsx [Read-Only] - Excel"
+    Editor::Office => title.split(" - ").next().map(|file| {
+      file
+        .replace("[Read-Only]", "")
+        .replace("[Compatibility Mode]", "")
+        .replace("  ", " ")
+        .trim()
+        .to_string()
+    }),
+    // "poster.psd @ 66.7% (Layer 1, RGB/8) *"
+    Editor::Photoshop => title.split_once(" @ ").map(|(file, _view)| file.to_string()),
+    // "*C:\notes\todo.txt - Notepad++", "*" marks unsaved
+    Editor::Notepad => title
+      .rsplit_once(" - ")
+      .map(|(path, _app)| file_name(path.trim_start_matches('*')).to_string()),
+  };
+
+  document.filter(|document| !document.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_vscode() {
+    assert_eq!(
+      document_from_title("Code.exe", "● main.rs - lifespan - Visual Studio Code"),
+      Some("lifespan/main.rs".to_string())
+    );
+    assert_eq!(
+      document_from_title("code", "notes.md - Visual Studio Code"),
+      Some("notes.md".to_string())
+    );
+    assert_eq!(document_from_title("Code.exe", "lifespan - Visual Studio Code"), None);
+    assert_eq!(document_from_title("Code.exe", "Welcome - lifespan - Visual Studio Code"), None);
+  }
+
+  #[test]
+  fn test_jetbrains_and_sublime() {
+    assert_eq!(
+      document_from_title("idea64.exe", "lifespan – src/main/App.kt"),
+      Some("lifespan/App.kt".to_string())
+    );
+    assert_eq!(document_from_title("rustrover64.exe", "lifespan"), None);
+    assert_eq!(
+      document_from_title("sublime_text.exe", "• ~/code/lifespan/src/main.rs (lifespan) - Sublime Text"),
+      Some("lifespan/main.rs".to_string())
+    );
+  }
+
+  #[test]
+  fn test_office_photoshop_notepad() {
+    assert_eq!(
+      document_from_title("WINWORD.EXE", "Report.docx - Word"),
+      Some("Report.docx".to_string())
+    );
+    assert_eq!(
+      document_from_title("EXCEL.EXE", "Budget.xlsx [Read-Only] - Excel"),
+      Some("Budget.xlsx".to_string())
+    );
+    assert_eq!(
+      document_from_title("Photoshop.exe", "poster.psd @ 66.7% (Layer 1, RGB/8) *"),
+      Some("poster.psd".to_string())
+    );
+    assert_eq!(
+      document_from_title("notepad++.exe", "*C:\\notes\\todo.txt - Notepad++"),
+      Some("todo.txt".to_string())
+    );
+  }
+
+  #[test]
+  fn test_unknown_apps_have_no_document() {
+    assert_eq!(document_from_title("chrome.exe", "main.rs - lifespan - GitHub"), None);
+    assert_eq!(document_from_title("WINWORD.EXE", ""), None);
+  }
+}
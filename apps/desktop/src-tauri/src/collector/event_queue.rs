@@ -5,7 +5,12 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::{Mutex, Semaphore};
 
-/// In-memory event queue with bounded size
+/// In-memory event queue with bounded size.
+///
+/// The collector queues new app_usage events here instead of writing each one
+/// to SQLite; `Database::store_queued_events_sync` writes a batch in one
+/// transaction. Events closed before they are flushed are written closed.
+#[derive(Clone)]
 pub struct EventQueue {
   events: Arc<Mutex<Vec<QueuedEvent>>>,
   max_size: usize,
@@ -18,6 +23,9 @@ pub struct QueuedEvent {
   pub window_info: WindowInfo,
   pub queued_at: DateTime<Utc>,
   pub retry_count: u32,
+  /// Set when the event is closed while still queued
+  #[serde(default)]
+  pub ended_at: Option<DateTime<Utc>>,
 }
 
 impl EventQueue {
@@ -29,22 +37,50 @@ impl EventQueue {
     }
   }
 
-  /// Add an event to the queue
-  pub async fn enqueue(&self, window_info: WindowInfo) -> Result<()> {
-    // Acquire permit to enforce max size
-    let _permit = self.semaphore.acquire().await.unwrap();
-
+  /// Add an event to the queue and return its id (the id it will be stored under)
+  pub async fn enqueue(&self, window_info: WindowInfo) -> Result<String> {
     let event = QueuedEvent {
       id: uuid::Uuid::new_v4().to_string(),
       window_info,
       queued_at: Utc::now(),
       retry_count: 0,
+      ended_at: None,
     };
+    let id = event.id.clone();
+    self.push(event).await;
+    Ok(id)
+  }
+
+  /// Put events back after a failed flush, ahead of anything queued since
+  pub async fn requeue(&self, mut failed: Vec<QueuedEvent>) {
+    for _ in 0..failed.len() {
+      // Hold a permit per queued event; `drain` gives them back
+      self.semaphore.acquire().await.unwrap().forget();
+    }
+    let mut events = self.events.lock().await;
+    failed.append(&mut events);
+    *events = failed;
+  }
+
+  async fn push(&self, event: QueuedEvent) {
+    // Acquire permit to enforce max size; released by `drain`
+    self.semaphore.acquire().await.unwrap().forget();
 
     let mut events = self.events.lock().await;
     events.push(event);
+  }
 
-    Ok(())
+  /// Record the end time of a still-queued event; false if it is not queued
+  /// (already flushed), in which case it must be closed in the database
+  pub async fn close(&self, id: &str, ended_at: DateTime<Utc>) -> bool {
+    let mut events = self.events.lock().await;
+    match events.iter_mut().find(|e| e.id == id) {
+      Some(event) => {
+        event.ended_at = Some(ended_at);
+        true
+      }
+      None => false,
+    }
   }
 
   /// Get all events from the queue
@@ -94,6 +130,7 @@ mod tests {
           process_name: format!("app{}", i),
           window_title: format!("Window {}", i),
           timestamp: Utc::now(),
+          ..Default::default()
         };
         queue.enqueue(window_info).await.unwrap();
       }
@@ -117,6 +154,7 @@ mod tests {
         process_name: "test_app".to_string(),
         window_title: "Test Window".to_string(),
         timestamp: Utc::now(),
+        ..Default::default()
       };
 
       queue.enqueue(window_info).await.unwrap();
@@ -139,6 +177,7 @@ mod tests {
         process_name: "test_app".to_string(),
         window_title: "Test Window".to_string(),
         timestamp: Utc::now(),
+        ..Default::default()
       };
 
       queue.enqueue(window_info).await.unwrap();
@@ -151,6 +190,7 @@ mod tests {
         process_name: "app2".to_string(),
         window_title: "Window 2".to_string(),
         timestamp: Utc::now(),
+        ..Default::default()
       };
       queue.enqueue(window_info2).await.unwrap();
 
@@ -180,9 +220,11 @@ mod tests {
         process_name: "test_app".to_string(),
         window_title: "Test Window".to_string(),
         timestamp: Utc::now(),
+        ..Default::default()
       },
       queued_at: Utc::now(),
       retry_count: 0,
+      ended_at: None,
     };
 
     let serialized = serde_json::to_string(&event).unwrap();
@@ -192,4 +234,41 @@ mod tests {
     assert_eq!(deserialized.window_info.process_name, event.window_info.process_name);
     assert_eq!(deserialized.retry_count, event.retry_count);
   }
+
+  fn window(process_name: &str) -> WindowInfo {
+    WindowInfo {
+      process_name: process_name.to_string(),
+      window_title: "Window".to_string(),
+      timestamp: Utc::now(),
+      ..Default::default()
+    }
+  }
+
+  #[tokio::test]
+  async fn test_close_queued_event() {
+    let queue = EventQueue::new(10);
+    let id = queue.enqueue(window("app")).await.unwrap();
+    let ended_at = Utc::now();
+
+    assert!(queue.close(&id, ended_at).await);
+    assert_eq!(queue.get_event(&id).await.unwrap().ended_at, Some(ended_at));
+
+    queue.drain().await;
+    assert!(!queue.close(&id, ended_at).await);
+  }
+
+  #[tokio::test]
+  async fn test_requeue_keeps_order_and_capacity() {
+    let queue = EventQueue::new(3);
+    queue.enqueue(window("first")).await.unwrap();
+    queue.enqueue(window("second")).await.unwrap();
+    let failed = queue.drain().await;
+
+    queue.enqueue(window("third")).await.unwrap();
+    queue.requeue(failed).await;
+
+    let names: Vec<String> = queue.drain().await.into_iter().map(|e| e.window_info.process_name).collect();
+    assert_eq!(names, vec!["first", "second", "third"]);
+    assert_eq!(queue.semaphore.available_permits(), 3);
+  }
 }
@@ -0,0 +1,124 @@
+//! Opt-in SHA-256 hashing of the executables behind tracked windows.
+//!
+//! Events store the executable path; the hash lives in a per-path cache, so
+//! a renamed or portable copy of an app can still be grouped with the
+//! original. Hashing runs on the blocking pool after a window switch and is
+//! skipped when the file's size and modification time haven't changed.
+
+use crate::database::{Database, ExecutableHash};
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+use tracing::debug;
+
+/// Setting that enables executable hashing ("true"); off by default
+pub const EXECUTABLE_HASH_SETTING: &str = "executable_hashing";
+
+/// Larger files are never read
+const MAX_HASHED_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Hash of the executable at `path`, computed only if the cached one is stale
+pub fn executable_hash(db: &Database, path: &str) -> Result<String> {
+  let metadata = std::fs::metadata(path)?;
+  let size = metadata.len();
+  if size > MAX_HASHED_BYTES {
+    bail!("{} is too large to hash", path);
+  }
+  let modified_at = metadata
+    .modified()?
+    .duration_since(UNIX_EPOCH)
+    .map(|elapsed| elapsed.as_millis() as i64)
+    .unwrap_or_default();
+
+  if let Some(cached) = db.get_executable_hash(path)? {
+    if cached.size == size && cached.modified_at == modified_at {
+      return Ok(cached.sha256);
+    }
+  }
+
+  let mut file = std::fs::File::open(path)?;
+  let mut hasher = Sha256::new();
+  let mut buffer = vec![0u8; 64 * 1024];
+  loop {
+    let read = file.read(&mut buffer)?;
+    if read == 0 {
+      break;
+    }
+    hasher.update(&buffer[..read]);
+  }
+
+  let sha256 = hex::encode(hasher.finalize());
+  db.set_executable_hash(
+    path,
+    &ExecutableHash {
+      size,
+      modified_at,
+      sha256: sha256.clone(),
+    },
+  )?;
+  Ok(sha256)
+}
+
+/// Hashes executables in the background, one job per path at a time
+#[derive(Clone)]
+pub struct ExecutableHasher {
+  db: Arc<Database>,
+  in_flight: Arc<Mutex<HashSet<String>>>,
+}
+
+impl ExecutableHasher {
+  pub fn new(db: Arc<Database>) -> Self {
+    Self {
+      db,
+      in_flight: Arc::new(Mutex::new(HashSet::new())),
+    }
+  }
+
+  pub fn hash_in_background(&self, path: &str) {
+    if !self.in_flight.lock().unwrap().insert(path.to_string()) {
+      return;
+    }
+
+    let hasher = self.clone();
+    let path = path.to_string();
+    tokio::task::spawn_blocking(move || {
+      if let Err(e) = executable_hash(&hasher.db, &path) {
+        debug!("Failed to hash executable: {}", e);
+      }
+      hasher.in_flight.lock().unwrap().remove(&path);
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Write;
+  use tempfile::NamedTempFile;
+
+  #[test]
+  fn test_executable_hash_is_cached_until_file_changes() {
+    let db_file = NamedTempFile::new().unwrap();
+    let db = Database::new(db_file.path()).unwrap();
+    let mut binary = NamedTempFile::new().unwrap();
+    binary.write_all(b"abc").unwrap();
+    let path = binary.path().to_string_lossy().into_owned();
+
+    let hash = executable_hash(&db, &path).unwrap();
+    assert_eq!(hash, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+    // A stale-looking cache entry with the same size and mtime is trusted
+    let mut cached = db.get_executable_hash(&path).unwrap().unwrap();
+    cached.sha256 = "cached".to_string();
+    db.set_executable_hash(&path, &cached).unwrap();
+    assert_eq!(executable_hash(&db, &path).unwrap(), "cached");
+
+    // A different size means the file changed
+    binary.write_all(b"def").unwrap();
+    binary.flush().unwrap();
+    assert_ne!(executable_hash(&db, &path).unwrap(), "cached");
+  }
+}
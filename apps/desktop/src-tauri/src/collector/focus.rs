@@ -0,0 +1,331 @@
+//! Pomodoro-style focus timer.
+//!
+//! `start_focus` opens a "focus_session" event in local_events; when the timer
+//! runs out (or the user stops it early) the event is closed with the apps
+//! used during the session as its title, most used first. Each completed
+//! session triggers a break suggestion, sent to the UI as a Tauri event and
+//! kept in the status until the next session starts: a short break, and a
+//! long one after every fourth session of the day.
+
+use crate::database::{Database, StorageBackend};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tracing::{error, info};
+
+pub const FOCUS_SESSION_EVENT_TYPE: &str = "focus_session";
+
+/// Tauri event the UI listens on to suggest a break
+pub const BREAK_SUGGESTION_EVENT: &str = "focus-break-suggested";
+
+pub const MAX_FOCUS_MINUTES: u32 = 4 * 60;
+
+const SHORT_BREAK_MINUTES: u32 = 5;
+const LONG_BREAK_MINUTES: u32 = 15;
+/// Every this many completed sessions of a day earn a long break
+const SESSIONS_PER_LONG_BREAK: u32 = 4;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BreakSuggestion {
+  pub minutes: u32,
+  /// Sessions completed today, including the one just finished
+  pub sessions_completed_today: u32,
+  pub suggested_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FocusStatus {
+  pub active: bool,
+  pub started_at: Option<DateTime<Utc>>,
+  pub ends_at: Option<DateTime<Utc>>,
+  pub remaining_seconds: Option<i64>,
+  pub sessions_completed_today: u32,
+  /// Break suggested after the last completed session; cleared by the next start
+  pub break_suggestion: Option<BreakSuggestion>,
+}
+
+/// A finished (or stopped) focus session
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FocusSession {
+  pub event_id: String,
+  pub started_at: DateTime<Utc>,
+  pub ended_at: DateTime<Utc>,
+  pub planned_minutes: u32,
+  /// Ran for the full planned duration
+  pub completed: bool,
+  /// Apps in the foreground during the session, most used first
+  pub apps: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct ActiveFocus {
+  event_id: String,
+  started_at: DateTime<Utc>,
+  ends_at: DateTime<Utc>,
+  planned_minutes: u32,
+}
+
+#[derive(Debug, Default)]
+struct FocusState {
+  active: Option<ActiveFocus>,
+  /// Local day and number of sessions completed on it
+  completed: Option<(NaiveDate, u32)>,
+  break_suggestion: Option<BreakSuggestion>,
+}
+
+pub struct FocusTimer {
+  db: Arc<Database>,
+  app_handle: Option<AppHandle>,
+  state: Mutex<FocusState>,
+}
+
+impl FocusTimer {
+  pub fn new(db: Arc<Database>, app_handle: Option<AppHandle>) -> Self {
+    Self {
+      db,
+      app_handle,
+      state: Mutex::new(FocusState::default()),
+    }
+  }
+
+  pub fn start_focus(&self, duration_minutes: u32) -> Result<FocusStatus> {
+    self.start_focus_at(Utc::now(), duration_minutes)
+  }
+
+  fn start_focus_at(&self, now: DateTime<Utc>, duration_minutes: u32) -> Result<FocusStatus> {
+    if duration_minutes == 0 || duration_minutes > MAX_FOCUS_MINUTES {
+      bail!("Focus duration must be between 1 and {} minutes", MAX_FOCUS_MINUTES);
+    }
+
+    let mut state = self.state.lock().unwrap();
+    if state.active.is_some() {
+      bail!("A focus session is already running");
+    }
+
+    let event_id = self.db.open_system_event_sync(FOCUS_SESSION_EVENT_TYPE, now)?;
+    info!("Focus session started for {} minutes", duration_minutes);
+    state.active = Some(ActiveFocus {
+      event_id,
+      started_at: now,
+      ends_at: now + chrono::Duration::minutes(duration_minutes as i64),
+      planned_minutes: duration_minutes,
+    });
+    state.break_suggestion = None;
+    Ok(status_of(&state, now))
+  }
+
+  /// End the running session early; None when no session is running
+  pub fn stop_focus(&self) -> Result<Option<FocusSession>> {
+    let now = Utc::now();
+    let mut state = self.state.lock().unwrap();
+    match state.active.take() {
+      Some(active) => self.finish(&active, now, false).map(Some),
+      None => Ok(None),
+    }
+  }
+
+  pub fn status(&self) -> FocusStatus {
+    status_of(&self.state.lock().unwrap(), Utc::now())
+  }
+
+  /// Finish the running session if its time is up, and suggest a break
+  fn tick(&self, now: DateTime<Utc>) -> Result<Option<(FocusSession, BreakSuggestion)>> {
+    let mut state = self.state.lock().unwrap();
+    let active = match &state.active {
+      Some(active) if now >= active.ends_at => active.clone(),
+      _ => return Ok(None),
+    };
+    state.active = None;
+
+    let session = self.finish(&active, active.ends_at, true)?;
+    let today = now.with_timezone(&Local).date_naive();
+    let completed = match state.completed {
+      Some((day, count)) if day == today => count + 1,
+      _ => 1,
+    };
+    state.completed = Some((today, completed));
+
+    let suggestion = BreakSuggestion {
+      minutes: if completed % SESSIONS_PER_LONG_BREAK == 0 {
+        LONG_BREAK_MINUTES
+      } else {
+        SHORT_BREAK_MINUTES
+      },
+      sessions_completed_today: completed,
+      suggested_at: now,
+    };
+    state.break_suggestion = Some(suggestion.clone());
+    Ok(Some((session, suggestion)))
+  }
+
+  /// Close the session's event with the apps used during it
+  fn finish(&self, active: &ActiveFocus, ended_at: DateTime<Utc>, completed: bool) -> Result<FocusSession> {
+    let apps = apps_used(&self.db, active.started_at, ended_at)?;
+    self.db.finish_focus_session_sync(&active.event_id, ended_at, &apps)?;
+    info!(
+      "Focus session {} after {} minutes",
+      if completed { "completed" } else { "stopped" },
+      (ended_at - active.started_at).num_minutes()
+    );
+
+    Ok(FocusSession {
+      event_id: active.event_id.clone(),
+      started_at: active.started_at,
+      ended_at,
+      planned_minutes: active.planned_minutes,
+      completed,
+      apps,
+    })
+  }
+
+  /// Check every CHECK_INTERVAL whether the running session is over
+  pub fn start_scheduler(self: Arc<Self>) {
+    tauri::async_runtime::spawn(async move {
+      let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+      loop {
+        ticker.tick().await;
+
+        match self.tick(Utc::now()) {
+          Ok(Some((_session, suggestion))) => {
+            if let Some(app) = &self.app_handle {
+              if let Err(e) = app.emit(BREAK_SUGGESTION_EVENT, &suggestion) {
+                error!("Failed to send break suggestion: {}", e);
+              }
+            }
+          }
+          Ok(None) => {}
+          Err(e) => error!("Failed to finish focus session: {}", e),
+        }
+      }
+    });
+  }
+}
+
+fn status_of(state: &FocusState, now: DateTime<Utc>) -> FocusStatus {
+  let today = now.with_timezone(&Local).date_naive();
+  FocusStatus {
+    active: state.active.is_some(),
+    started_at: state.active.as_ref().map(|active| active.started_at),
+    ends_at: state.active.as_ref().map(|active| active.ends_at),
+    remaining_seconds: state
+      .active
+      .as_ref()
+      .map(|active| (active.ends_at - now).num_seconds().max(0)),
+    sessions_completed_today: match state.completed {
+      Some((day, count)) if day == today => count,
+      _ => 0,
+    },
+    break_suggestion: state.break_suggestion.clone(),
+  }
+}
+
+/// Apps with app_usage events starting in [start, end), most used first;
+/// the still open event counts as used up to `end`
+fn apps_used(db: &Database, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<String>> {
+  let mut seconds: HashMap<String, i64> = HashMap::new();
+  for event in db.events_between(start, end)? {
+    if event.event_type != "app_usage" {
+      continue;
+    }
+    let duration = match event.duration {
+      0 => (end - event.timestamp).num_seconds(),
+      duration => duration as i64,
+    };
+    *seconds.entry(event.app_name).or_default() += duration;
+  }
+
+  let mut apps: Vec<(String, i64)> = seconds.into_iter().collect();
+  apps.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+  Ok(apps.into_iter().map(|(app, _)| app).collect())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use tempfile::NamedTempFile;
+
+  fn create_test_timer() -> (FocusTimer, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Arc::new(Database::new(temp_file.path()).unwrap());
+    (FocusTimer::new(db, None), temp_file)
+  }
+
+  fn store_usage(db: &Database, app: &str, seconds: i64) {
+    let id = db
+      .store_event_sync(&WindowInfo {
+        process_name: app.to_string(),
+        window_title: "Window".to_string(),
+        timestamp: Utc::now(),
+        ..Default::default()
+      })
+      .unwrap();
+    let started = db.get_event(&id).unwrap().unwrap().timestamp;
+    db.close_event_sync(&id, started + chrono::Duration::seconds(seconds)).unwrap();
+  }
+
+  #[test]
+  fn test_session_completes_with_apps_and_break() {
+    let (timer, _temp) = create_test_timer();
+    let start = Utc::now() - chrono::Duration::seconds(1);
+    timer.start_focus_at(start, 25).unwrap();
+    assert!(timer.start_focus_at(start, 25).is_err());
+    store_usage(&timer.db, "chrome.exe", 60);
+    store_usage(&timer.db, "Code.exe", 600);
+
+    assert!(timer.tick(start + chrono::Duration::minutes(10)).unwrap().is_none());
+    assert!(timer.status().active);
+
+    let (session, suggestion) = timer.tick(start + chrono::Duration::minutes(25)).unwrap().unwrap();
+    assert!(session.completed);
+    assert_eq!(session.apps, vec!["Code.exe", "chrome.exe"]);
+    assert_eq!(suggestion.minutes, SHORT_BREAK_MINUTES);
+
+    let event = timer.db.get_event(&session.event_id).unwrap().unwrap();
+    assert_eq!(event.event_type, FOCUS_SESSION_EVENT_TYPE);
+    assert_eq!(event.duration, 25 * 60);
+    assert_eq!(event.window_title.as_deref(), Some("Code.exe, chrome.exe"));
+
+    let status = timer.status();
+    assert!(!status.active);
+    assert_eq!(status.sessions_completed_today, 1);
+    assert_eq!(status.break_suggestion, Some(suggestion));
+  }
+
+  #[test]
+  fn test_every_fourth_session_earns_long_break() {
+    let (timer, _temp) = create_test_timer();
+    let mut now = Utc::now();
+    let mut breaks = Vec::new();
+    for _ in 0..4 {
+      timer.start_focus_at(now, 1).unwrap();
+      now += chrono::Duration::minutes(1);
+      breaks.push(timer.tick(now).unwrap().unwrap().1.minutes);
+    }
+    assert_eq!(
+      breaks,
+      vec![SHORT_BREAK_MINUTES, SHORT_BREAK_MINUTES, SHORT_BREAK_MINUTES, LONG_BREAK_MINUTES]
+    );
+  }
+
+  #[test]
+  fn test_stop_ends_session_early() {
+    let (timer, _temp) = create_test_timer();
+    assert!(timer.stop_focus().unwrap().is_none());
+    assert!(timer.start_focus(0).is_err());
+    assert!(timer.start_focus(MAX_FOCUS_MINUTES + 1).is_err());
+
+    timer.start_focus(25).unwrap();
+    let session = timer.stop_focus().unwrap().unwrap();
+    assert!(!session.completed);
+    assert!(!timer.status().active);
+    assert_eq!(timer.status().sessions_completed_today, 0);
+    assert!(timer.status().break_suggestion.is_none());
+  }
+}
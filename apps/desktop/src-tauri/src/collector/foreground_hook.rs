@@ -0,0 +1,114 @@
+//! Event-driven foreground window change notifications on Windows.
+//!
+//! A dedicated thread installs a `SetWinEventHook(EVENT_SYSTEM_FOREGROUND)`
+//! listener and pumps messages; each foreground change is forwarded over a
+//! channel so the tracking loop wakes up immediately instead of waiting for
+//! its next poll. It runs in the capture helper, which pushes the changes on
+//! to the app (see `capture_helper`).
+
+use anyhow::{anyhow, Result};
+use std::cell::RefCell;
+use std::thread::JoinHandle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::{debug, error};
+use windows::Win32::Foundation::{HMODULE, HWND, LPARAM, WPARAM};
+use windows::Win32::System::Threading::GetCurrentThreadId;
+use windows::Win32::UI::Accessibility::{SetWinEventHook, UnhookWinEvent, HWINEVENTHOOK};
+use windows::Win32::UI::WindowsAndMessaging::{
+  DispatchMessageW, GetMessageW, PostThreadMessageW, TranslateMessage, EVENT_SYSTEM_FOREGROUND, MSG,
+  WINEVENT_OUTOFCONTEXT, WINEVENT_SKIPOWNPROCESS, WM_QUIT,
+};
+
+thread_local! {
+  // Out-of-context hooks are called on the thread that installed them
+  static FOREGROUND_SENDER: RefCell<Option<UnboundedSender<()>>> = const { RefCell::new(None) };
+}
+
+unsafe extern "system" fn on_foreground_change(
+  _hook: HWINEVENTHOOK,
+  _event: u32,
+  _hwnd: HWND,
+  _id_object: i32,
+  _id_child: i32,
+  _event_thread: u32,
+  _event_time: u32,
+) {
+  FOREGROUND_SENDER.with(|sender| {
+    if let Some(sender) = sender.borrow().as_ref() {
+      let _ = sender.send(());
+    }
+  });
+}
+
+/// Owns the hook thread; dropping it unhooks and stops the thread
+pub struct ForegroundHook {
+  thread_id: u32,
+  thread: Option<JoinHandle<()>>,
+}
+
+impl ForegroundHook {
+  /// Install the hook and return a receiver that fires on every foreground change
+  pub fn start() -> Result<(Self, UnboundedReceiver<()>)> {
+    let (tx, rx) = unbounded_channel();
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel::<Result<u32, String>>();
+
+    let thread = std::thread::Builder::new()
+      .name("foreground-hook".to_string())
+      .spawn(move || unsafe {
+        FOREGROUND_SENDER.with(|sender| *sender.borrow_mut() = Some(tx));
+
+        let hook = SetWinEventHook(
+          EVENT_SYSTEM_FOREGROUND,
+          EVENT_SYSTEM_FOREGROUND,
+          HMODULE::default(),
+          Some(on_foreground_change),
+          0,
+          0,
+          WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS,
+        );
+        if hook.is_invalid() {
+          let _ = ready_tx.send(Err("SetWinEventHook failed".to_string()));
+          return;
+        }
+        let _ = ready_tx.send(Ok(GetCurrentThreadId()));
+
+        // Hook callbacks are delivered through this thread's message queue
+        let mut msg = MSG::default();
+        while GetMessageW(&mut msg, HWND::default(), 0, 0).as_bool() {
+          let _ = TranslateMessage(&msg);
+          DispatchMessageW(&msg);
+        }
+
+        let _ = UnhookWinEvent(hook);
+        FOREGROUND_SENDER.with(|sender| *sender.borrow_mut() = None);
+        debug!("Foreground hook thread exited");
+      })?;
+
+    let thread_id = ready_rx
+      .recv()
+      .map_err(|e| anyhow!("Foreground hook thread died: {}", e))?
+      .map_err(|e| anyhow!(e))?;
+
+    Ok((
+      Self {
+        thread_id,
+        thread: Some(thread),
+      },
+      rx,
+    ))
+  }
+}
+
+impl Drop for ForegroundHook {
+  fn drop(&mut self) {
+    unsafe {
+      if let Err(e) = PostThreadMessageW(self.thread_id, WM_QUIT, WPARAM(0), LPARAM(0)) {
+        error!("Failed to stop foreground hook thread: {}", e);
+        return;
+      }
+    }
+    if let Some(thread) = self.thread.take() {
+      let _ = thread.join();
+    }
+  }
+}
@@ -1,4 +1,6 @@
+use super::capture_helper::CaptureHelper;
 use anyhow::Result;
+use std::sync::Arc;
 use std::time::Duration;
 use thiserror::Error;
 
@@ -6,16 +8,39 @@ use thiserror::Error;
 pub enum IdleDetectorError {
   #[error("Failed to get last input info")]
   GetLastInputFailed,
+  #[error("Idle backend error: {0}")]
+  Backend(String),
 }
 
-pub struct IdleDetector;
+/// Reads the time since the last input, in-process or through the capture helper
+#[derive(Clone, Default)]
+pub struct IdleDetector {
+  helper: Option<Arc<CaptureHelper>>,
+}
 
 impl IdleDetector {
   pub fn new() -> Result<Self> {
-    Ok(Self)
+    Ok(Self::default())
+  }
+
+  /// Ask the capture helper, so this process never queries input state itself
+  pub(super) fn isolated(helper: Arc<CaptureHelper>) -> Self {
+    Self { helper: Some(helper) }
   }
 
   pub fn is_idle(&self, threshold: Duration) -> Result<bool> {
+    Ok(self.idle_time()? > threshold)
+  }
+
+  /// Time since the last keyboard or mouse input
+  pub fn idle_time(&self) -> Result<Duration> {
+    match &self.helper {
+      Some(helper) => helper.idle_time(),
+      None => Self::local_idle_time(),
+    }
+  }
+
+  fn local_idle_time() -> Result<Duration> {
     #[cfg(windows)]
     {
       use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
@@ -30,23 +55,22 @@ impl IdleDetector {
         if GetLastInputInfo(&mut lii).as_bool() {
           let current_tick = GetTickCount64();
           let idle_millis = current_tick.saturating_sub(lii.dwTime as u64);
-          Ok(Duration::from_millis(idle_millis) > threshold)
+          Ok(Duration::from_millis(idle_millis))
         } else {
           Err(IdleDetectorError::GetLastInputFailed.into())
         }
       }
     }
 
-    #[cfg(not(windows))]
+    #[cfg(target_os = "linux")]
     {
-      // On non-Windows, assume not idle
-      Ok(false)
+      super::linux::idle_duration()
     }
-  }
-}
 
-impl Clone for IdleDetector {
-  fn clone(&self) -> Self {
-    Self
+    #[cfg(not(any(windows, target_os = "linux")))]
+    {
+      // On other platforms, assume not idle
+      Ok(Duration::ZERO)
+    }
   }
 }
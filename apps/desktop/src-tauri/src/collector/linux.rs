@@ -0,0 +1,333 @@
+//! Linux display-server backends for window tracking and idle detection.
+//!
+//! The session type is detected at runtime: Wayland sessions query the
+//! GNOME Shell introspection D-Bus API and logind, X11 sessions (and
+//! XWayland as a fallback) use EWMH properties and the MIT-SCREEN-SAVER
+//! extension.
+
+use super::idle_detector::IdleDetectorError;
+use super::window_tracker::WindowTrackerError;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::debug;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionType {
+  X11,
+  Wayland,
+  Unknown,
+}
+
+/// Detect the graphical session type from the environment
+pub fn detect_session_type() -> SessionType {
+  session_type_from(
+    std::env::var("XDG_SESSION_TYPE").ok().as_deref(),
+    std::env::var_os("WAYLAND_DISPLAY").is_some(),
+    std::env::var_os("DISPLAY").is_some(),
+  )
+}
+
+fn session_type_from(xdg_session_type: Option<&str>, has_wayland_display: bool, has_x_display: bool) -> SessionType {
+  match xdg_session_type {
+    Some("wayland") => SessionType::Wayland,
+    Some("x11") => SessionType::X11,
+    _ if has_wayland_display => SessionType::Wayland,
+    _ if has_x_display => SessionType::X11,
+    _ => SessionType::Unknown,
+  }
+}
+
+/// Whether an X server is reachable (native X11 or XWayland)
+pub fn has_x_display() -> bool {
+  std::env::var_os("DISPLAY").is_some()
+}
+
+/// Get (process name, raw window title, executable path) of the focused window
+pub fn active_window() -> Result<(String, String, Option<String>)> {
+  match detect_session_type() {
+    SessionType::Wayland => match gnome_shell_active_window() {
+      Ok(window) => Ok(window),
+      Err(e) if has_x_display() => {
+        // XWayland only sees X clients, but it's better than nothing
+        debug!("Wayland backend unavailable ({}), falling back to X11", e);
+        x11_active_window()
+      }
+      Err(e) => Err(e),
+    },
+    SessionType::X11 => x11_active_window(),
+    SessionType::Unknown => Err(WindowTrackerError::DisplayServer("No graphical session detected".to_string()).into()),
+  }
+}
+
+/// Query the focused window through EWMH root window properties
+fn x11_active_window() -> Result<(String, String, Option<String>)> {
+  use x11rb::connection::Connection;
+  use x11rb::protocol::xproto::{AtomEnum, ConnectionExt};
+
+  let display_err = |e: &dyn std::fmt::Display| WindowTrackerError::DisplayServer(e.to_string());
+
+  let (conn, screen_num) = x11rb::connect(None).map_err(|e| display_err(&e))?;
+  let root = conn.setup().roots[screen_num].root;
+
+  let intern = |name: &[u8]| -> Result<u32> {
+    Ok(conn.intern_atom(false, name).map_err(|e| display_err(&e))?
+      .reply().map_err(|e| display_err(&e))?
+      .atom)
+  };
+
+  let net_active_window = intern(b"_NET_ACTIVE_WINDOW")?;
+  let net_wm_name = intern(b"_NET_WM_NAME")?;
+  let net_wm_pid = intern(b"_NET_WM_PID")?;
+  let utf8_string = intern(b"UTF8_STRING")?;
+
+  let active = conn
+    .get_property(false, root, net_active_window, AtomEnum::WINDOW, 0, 1)
+    .map_err(|e| display_err(&e))?
+    .reply()
+    .map_err(|e| display_err(&e))?;
+  let window = active
+    .value32()
+    .and_then(|mut values| values.next())
+    .filter(|window| *window != 0)
+    .ok_or(WindowTrackerError::NoActiveWindow)?;
+
+  // Prefer the UTF-8 EWMH title, fall back to the legacy WM_NAME
+  let mut title = conn
+    .get_property(false, window, net_wm_name, utf8_string, 0, u32::MAX)
+    .map_err(|e| display_err(&e))?
+    .reply()
+    .map_err(|e| display_err(&e))?
+    .value;
+  if title.is_empty() {
+    title = conn
+      .get_property(false, window, AtomEnum::WM_NAME, AtomEnum::STRING, 0, u32::MAX)
+      .map_err(|e| display_err(&e))?
+      .reply()
+      .map_err(|e| display_err(&e))?
+      .value;
+  }
+  let window_title = String::from_utf8_lossy(&title).into_owned();
+
+  let pid = conn
+    .get_property(false, window, net_wm_pid, AtomEnum::CARDINAL, 0, 1)
+    .map_err(|e| display_err(&e))?
+    .reply()
+    .map_err(|e| display_err(&e))?
+    .value32()
+    .and_then(|mut values| values.next());
+
+  let process_name = match pid.and_then(process_name_for_pid) {
+    Some(name) => name,
+    None => {
+      // WM_CLASS is "instance\0class\0"; the class is the friendlier name
+      let class = conn
+        .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, u32::MAX)
+        .map_err(|e| display_err(&e))?
+        .reply()
+        .map_err(|e| display_err(&e))?
+        .value;
+      String::from_utf8_lossy(&class)
+        .split('\0')
+        .filter(|part| !part.is_empty())
+        .last()
+        .map(|s| s.to_string())
+        .ok_or_else(|| WindowTrackerError::ProcessQueryFailed("Unknown window owner".to_string()))?
+    }
+  };
+
+  Ok((process_name, window_title, pid.and_then(process_path_for_pid)))
+}
+
+/// Query the focused window through org.gnome.Shell.Introspect.
+///
+/// Recent GNOME versions only answer this for allow-listed callers, in which
+/// case the error is returned and the caller falls back to XWayland.
+fn gnome_shell_active_window() -> Result<(String, String, Option<String>)> {
+  use zbus::zvariant::OwnedValue;
+
+  let display_err = |e: &dyn std::fmt::Display| WindowTrackerError::DisplayServer(e.to_string());
+
+  let conn = zbus::blocking::Connection::session().map_err(|e| display_err(&e))?;
+  let reply = conn
+    .call_method(
+      Some("org.gnome.Shell"),
+      "/org/gnome/Shell/Introspect",
+      Some("org.gnome.Shell.Introspect"),
+      "GetWindows",
+      &(),
+    )
+    .map_err(|e| display_err(&e))?;
+
+  let windows: HashMap<u64, HashMap<String, OwnedValue>> =
+    reply.body().deserialize().map_err(|e| display_err(&e))?;
+
+  let focused = windows
+    .values()
+    .find(|props| {
+      props
+        .get("has-focus")
+        .and_then(|v| v.downcast_ref::<bool>().ok())
+        .unwrap_or(false)
+    })
+    .ok_or(WindowTrackerError::NoActiveWindow)?;
+
+  let string_prop = |key: &str| -> Option<String> {
+    focused
+      .get(key)
+      .and_then(|v| v.downcast_ref::<&str>().ok())
+      .map(|s| s.to_string())
+      .filter(|s| !s.is_empty())
+  };
+
+  let process_name = string_prop("app-id")
+    .map(|id| id.trim_end_matches(".desktop").to_string())
+    .or_else(|| string_prop("wm-class"))
+    .ok_or_else(|| WindowTrackerError::ProcessQueryFailed("Unknown window owner".to_string()))?;
+  let window_title = string_prop("title").unwrap_or_default();
+
+  // Introspect doesn't report the owning process
+  Ok((process_name, window_title, None))
+}
+
+/// 1-based number of the current workspace (EWMH `_NET_CURRENT_DESKTOP`).
+/// Wayland compositors only publish it on the XWayland root window, if at all.
+pub fn current_workspace() -> Option<u32> {
+  use x11rb::connection::Connection;
+  use x11rb::protocol::xproto::{AtomEnum, ConnectionExt};
+
+  if !has_x_display() {
+    return None;
+  }
+  let (conn, screen_num) = x11rb::connect(None).ok()?;
+  let root = conn.setup().roots[screen_num].root;
+  let net_current_desktop = conn.intern_atom(false, b"_NET_CURRENT_DESKTOP").ok()?.reply().ok()?.atom;
+
+  let desktop = conn
+    .get_property(false, root, net_current_desktop, AtomEnum::CARDINAL, 0, 1)
+    .ok()?
+    .reply()
+    .ok()?
+    .value32()?
+    .next()?;
+  Some(desktop + 1)
+}
+
+/// Time since the last user input in the current graphical session.
+/// Returns zero when there is no session to query.
+pub fn idle_duration() -> Result<Duration> {
+  match detect_session_type() {
+    SessionType::Wayland => match logind_idle_duration() {
+      Ok(idle) => Ok(idle),
+      Err(e) if has_x_display() => {
+        debug!("logind idle query failed ({}), falling back to XScreenSaver", e);
+        x11_idle_duration()
+      }
+      Err(e) => Err(e),
+    },
+    SessionType::X11 => match x11_idle_duration() {
+      Ok(idle) => Ok(idle),
+      Err(e) => {
+        debug!("XScreenSaver idle query failed ({}), falling back to logind", e);
+        logind_idle_duration()
+      }
+    },
+    SessionType::Unknown => Ok(Duration::ZERO),
+  }
+}
+
+/// XScreenSaverQueryInfo: milliseconds since the last input event
+fn x11_idle_duration() -> Result<Duration> {
+  use x11rb::connection::Connection;
+  use x11rb::protocol::screensaver::ConnectionExt;
+
+  let idle_err = |e: &dyn std::fmt::Display| IdleDetectorError::Backend(e.to_string());
+
+  let (conn, screen_num) = x11rb::connect(None).map_err(|e| idle_err(&e))?;
+  let root = conn.setup().roots[screen_num].root;
+
+  let info = conn
+    .screensaver_query_info(root)
+    .map_err(|e| idle_err(&e))?
+    .reply()
+    .map_err(|e| idle_err(&e))?;
+
+  Ok(Duration::from_millis(info.ms_since_user_input as u64))
+}
+
+/// logind IdleHint/IdleSinceHint of the caller's session (works on Wayland)
+fn logind_idle_duration() -> Result<Duration> {
+  let idle_err = |e: &dyn std::fmt::Display| IdleDetectorError::Backend(e.to_string());
+
+  let conn = zbus::blocking::Connection::system().map_err(|e| idle_err(&e))?;
+  let proxy = zbus::blocking::Proxy::new(
+    &conn,
+    "org.freedesktop.login1",
+    "/org/freedesktop/login1/session/auto",
+    "org.freedesktop.login1.Session",
+  )
+  .map_err(|e| idle_err(&e))?;
+
+  let idle_hint: bool = proxy.get_property("IdleHint").map_err(|e| idle_err(&e))?;
+  if !idle_hint {
+    return Ok(Duration::ZERO);
+  }
+
+  // IdleSinceHint is CLOCK_REALTIME in microseconds
+  let idle_since: u64 = proxy.get_property("IdleSinceHint").map_err(|e| idle_err(&e))?;
+  let now = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .unwrap_or_default();
+
+  Ok(now.saturating_sub(Duration::from_micros(idle_since)))
+}
+
+/// Resolve a process name from /proc
+fn process_name_for_pid(pid: u32) -> Option<String> {
+  std::fs::read_to_string(format!("/proc/{}/comm", pid))
+    .ok()
+    .map(|name| name.trim().to_string())
+    .filter(|name| !name.is_empty())
+}
+
+/// Executable behind /proc/<pid>/exe
+fn process_path_for_pid(pid: u32) -> Option<String> {
+  std::fs::read_link(format!("/proc/{}/exe", pid))
+    .ok()
+    .map(|path| path.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_session_type_from_xdg() {
+    assert_eq!(session_type_from(Some("wayland"), false, true), SessionType::Wayland);
+    assert_eq!(session_type_from(Some("x11"), true, true), SessionType::X11);
+  }
+
+  #[test]
+  fn test_session_type_from_display_vars() {
+    assert_eq!(session_type_from(None, true, true), SessionType::Wayland);
+    assert_eq!(session_type_from(Some("tty"), false, true), SessionType::X11);
+    assert_eq!(session_type_from(None, false, false), SessionType::Unknown);
+  }
+
+  #[test]
+  fn test_process_name_for_current_pid() {
+    let name = process_name_for_pid(std::process::id());
+    assert!(name.is_some());
+  }
+
+  #[test]
+  fn test_process_name_for_missing_pid() {
+    assert!(process_name_for_pid(u32::MAX).is_none());
+  }
+
+  #[test]
+  fn test_process_path_for_current_pid() {
+    let path = process_path_for_pid(std::process::id()).unwrap();
+    assert_eq!(std::path::PathBuf::from(path), std::env::current_exe().unwrap());
+  }
+}
@@ -0,0 +1,101 @@
+//! Media playback detection.
+//!
+//! Windows reads the current session from GlobalSystemMediaTransportControls,
+//! the same source the volume flyout's media controls use. The tracking loop
+//! keeps a `media_playback` event open for the playing app and track, whatever
+//! window has focus, so background music and podcasts show up next to app usage.
+
+use anyhow::Result;
+
+/// What the OS media session reports as playing
+#[cfg_attr(not(windows), allow(dead_code))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NowPlaying {
+  pub app_name: String,
+  /// "Artist - Title", or just the title when there is no artist
+  pub track: Option<String>,
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+pub struct MediaMonitor {
+  #[cfg(windows)]
+  manager: windows::Media::Control::GlobalSystemMediaTransportControlsSessionManager,
+}
+
+impl MediaMonitor {
+  #[cfg(windows)]
+  pub fn new() -> Result<Self> {
+    use windows::Media::Control::GlobalSystemMediaTransportControlsSessionManager;
+
+    Ok(Self {
+      manager: GlobalSystemMediaTransportControlsSessionManager::RequestAsync()?.get()?,
+    })
+  }
+
+  #[cfg(not(windows))]
+  pub fn new() -> Result<Self> {
+    Err(anyhow::anyhow!("Media session detection is not supported on this platform"))
+  }
+
+  /// The current session if it is playing; paused or stopped sessions count as nothing playing
+  #[cfg(windows)]
+  pub fn now_playing(&self) -> Result<Option<NowPlaying>> {
+    use windows::Media::Control::GlobalSystemMediaTransportControlsSessionPlaybackStatus as PlaybackStatus;
+
+    // No session at all comes back as a null object
+    let Ok(session) = self.manager.GetCurrentSession() else {
+      return Ok(None);
+    };
+    if session.GetPlaybackInfo()?.PlaybackStatus()? != PlaybackStatus::Playing {
+      return Ok(None);
+    }
+
+    let app_name = app_name_from_aumid(&session.SourceAppUserModelId()?.to_string());
+    let properties = session.TryGetMediaPropertiesAsync()?.get()?;
+    let track = format_track(&properties.Artist()?.to_string(), &properties.Title()?.to_string());
+
+    Ok(Some(NowPlaying { app_name, track }))
+  }
+
+  #[cfg(not(windows))]
+  pub fn now_playing(&self) -> Result<Option<NowPlaying>> {
+    Ok(None)
+  }
+}
+
+/// Turn an AppUserModelID into an app name: desktop apps report their exe
+/// ("Spotify.exe"), packaged apps "Family_hash!AppId", which becomes "AppId"
+#[cfg_attr(not(windows), allow(dead_code))]
+fn app_name_from_aumid(aumid: &str) -> String {
+  aumid.rsplit('!').next().unwrap_or(aumid).to_string()
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+fn format_track(artist: &str, title: &str) -> Option<String> {
+  match (artist.trim(), title.trim()) {
+    (_, "") => None,
+    ("", title) => Some(title.to_string()),
+    (artist, title) => Some(format!("{} - {}", artist, title)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_app_name_from_aumid() {
+    assert_eq!(app_name_from_aumid("Spotify.exe"), "Spotify.exe");
+    assert_eq!(
+      app_name_from_aumid("Microsoft.ZuneMusic_8wekyb3d8bbwe!Microsoft.ZuneMusic"),
+      "Microsoft.ZuneMusic"
+    );
+  }
+
+  #[test]
+  fn test_format_track() {
+    assert_eq!(format_track("Artist", "Song"), Some("Artist - Song".to_string()));
+    assert_eq!(format_track(" ", "Episode 12"), Some("Episode 12".to_string()));
+    assert_eq!(format_track("Artist", ""), None);
+  }
+}
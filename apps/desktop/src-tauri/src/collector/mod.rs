@@ -1,24 +1,143 @@
+pub mod browser;
+mod capture_devices;
+pub mod capture_helper;
+mod document;
+pub mod executables;
+pub mod focus;
 pub mod event_queue;
 pub mod idle_detector;
 pub mod window_tracker;
 
-use crate::database::Database;
-use anyhow::Result;
+#[cfg(windows)]
+mod foreground_hook;
+#[cfg(target_os = "linux")]
+mod linux;
+mod media_monitor;
+mod power_monitor;
+pub mod power_profile;
+mod projects;
+pub mod recorder;
+mod redaction;
+pub mod remote_session;
+pub mod schedule;
+pub mod self_report;
+
+use crate::database::{current_utc_offset_minutes, Database, TitlePolicy};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, Utc};
+use capture_devices::CaptureDevice;
 use event_queue::EventQueue;
+use focus::FocusStatus;
 use idle_detector::IdleDetector;
+use media_monitor::{MediaMonitor, NowPlaying};
+use power_monitor::{PowerEvent, PowerMonitor};
+use power_profile::PowerProfile;
+use projects::ProjectMatcher;
+use recorder::{EventRecorder, TrackerSample};
+use redaction::TitleRedactor;
+use schedule::TrackingSchedule;
+use self_report::ActivityMeter;
 use serde::Serialize;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
 use tracing::{info, debug, error};
 use window_tracker::WindowTracker;
 
+/// Polling interval when no foreground change notifications are available
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Safety-net poll interval when foreground changes are event-driven
+const HOOK_FALLBACK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A wall-clock gap between loop ticks longer than this is treated as a
+/// suspend when no OS power notifications are available
+const SUSPEND_GAP_THRESHOLD: Duration = Duration::from_secs(60);
+
+/// Queued events are written to the database at least this often...
+const QUEUE_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Longest `stop_and_wait` waits for the loop to notice it was stopped;
+/// the stretched poll intervals are well below this
+const STOP_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// ...or as soon as this many are waiting
+const QUEUE_FLUSH_THRESHOLD: usize = 50;
+
+/// Flush attempts before a queued event is dropped
+const MAX_FLUSH_RETRIES: u32 = 3;
+
+/// How often the tracking schedule is rechecked while paused outside it
+const SCHEDULE_RECHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How often the OS media session is checked for playback changes
+const MEDIA_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often camera and microphone use is checked
+const CAPTURE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Poll interval while a full-screen app has the foreground, if slow polling is enabled
+const FULLSCREEN_POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Setting ("true"/"false") to poll less often during full-screen games and videos
+pub const FULLSCREEN_SLOW_POLL_SETTING: &str = "fullscreen_slow_polling";
+
+/// Setting: seconds without input after which the user counts as AFK
+pub const IDLE_THRESHOLD_SETTING: &str = "idle_threshold_seconds";
+const DEFAULT_IDLE_THRESHOLD_SECS: u64 = 300;
+/// Shorter thresholds would split ordinary reading into AFK periods
+const MIN_IDLE_THRESHOLD_SECS: u64 = 30;
+
+/// How often the idle threshold is re-read, so changes apply without restarting tracking
+const IDLE_THRESHOLD_RECHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Setting: minutes of idleness after which collection stops until the next input; 0 disables
+pub const AUTO_STOP_IDLE_SETTING: &str = "auto_stop_idle_minutes";
+const DEFAULT_AUTO_STOP_IDLE_MINUTES: u64 = 4 * 60;
+
+/// Idle check interval while collection is stopped after a long absence
+const DORMANT_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often the power profile is re-read
+const POWER_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Poll interval on battery or in power-saver mode
+const BATTERY_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Interval between idle checks while AFK
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Interval between idle checks while AFK on battery or in power-saver mode
+const BATTERY_IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Longest pause `pause_tracking` accepts (one day)
+pub const MAX_PAUSE_MINUTES: u32 = 24 * 60;
+
+/// A user-requested pause: recording stopped at `started_at` and resumes at `until`
+#[derive(Debug, Clone, Copy)]
+struct Pause {
+  started_at: DateTime<Utc>,
+  until: DateTime<Utc>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct CollectorStatus {
   pub is_running: bool,
   pub events_collected: i64,
   pub last_sync_at: Option<String>,
   pub active_window: Option<String>,
+  /// Running, but paused because it is outside the tracking schedule
+  pub paused_by_schedule: bool,
+  /// Seconds left on a `pause_tracking` pause; None when not paused
+  pub pause_remaining_seconds: Option<i64>,
+  /// Polling is stretched unless this is `ac_power`
+  pub power_profile: PowerProfile,
+  /// Stopped after a long absence; collection restarts on the next input
+  pub dormant: bool,
+  /// Focus timer state; filled in by the `get_status` command, which owns the timer
+  pub focus: Option<FocusStatus>,
 }
 
 pub struct Collector {
@@ -29,18 +148,37 @@ pub struct Collector {
   is_running: Arc<Mutex<bool>>,
   events_collected: Arc<Mutex<i64>>,
   active_window: Arc<Mutex<Option<String>>>,
+  paused_by_schedule: Arc<Mutex<bool>>,
+  pause: Arc<Mutex<Option<Pause>>>,
+  power_profile: Arc<Mutex<PowerProfile>>,
+  dormant: Arc<Mutex<bool>>,
+  activity: ActivityMeter,
+  recorder: EventRecorder,
+  tracking_loop: Arc<Mutex<Option<JoinHandle<()>>>>,
 }
 
 impl Collector {
   pub fn new(db: Arc<Database>) -> Result<Self> {
+    Self::with_window_tracker(db, WindowTracker::new()?)
+  }
+
+  /// Create a collector with a specific window capture strategy (e.g. an isolated helper)
+  pub fn with_window_tracker(db: Arc<Database>, window_tracker: WindowTracker) -> Result<Self> {
     Ok(Self {
       db,
-      window_tracker: WindowTracker::new()?,
-      idle_detector: IdleDetector::new()?,
+      idle_detector: window_tracker.idle_detector(),
+      window_tracker,
       event_queue: EventQueue::new(10_000),
       is_running: Arc::new(Mutex::new(false)),
       events_collected: Arc::new(Mutex::new(0)),
       active_window: Arc::new(Mutex::new(None)),
+      paused_by_schedule: Arc::new(Mutex::new(false)),
+      pause: Arc::new(Mutex::new(None)),
+      power_profile: Arc::new(Mutex::new(PowerProfile::default())),
+      dormant: Arc::new(Mutex::new(false)),
+      activity: ActivityMeter::default(),
+      recorder: EventRecorder::default(),
+      tracking_loop: Arc::new(Mutex::new(None)),
     })
   }
 
@@ -52,6 +190,40 @@ impl Collector {
     *is_running = true;
     drop(is_running);
 
+    // Browser domain capture is opt-in
+    let capture_domains = self
+      .db
+      .get_setting(browser::BROWSER_DOMAIN_SETTING)
+      .unwrap_or(None)
+      .is_some_and(|value| value == "true");
+    self.window_tracker.set_browser_domain_capture(capture_domains);
+
+    // User redaction and project rules; edits take effect the next time tracking starts
+    let redactor = TitleRedactor::load(&self.db);
+    let project_matcher = ProjectMatcher::load(&self.db);
+
+    // Executable hashing is opt-in
+    let executable_hasher = self
+      .db
+      .get_setting(executables::EXECUTABLE_HASH_SETTING)
+      .unwrap_or(None)
+      .is_some_and(|value| value == "true")
+      .then(|| executables::ExecutableHasher::new(self.db.clone()));
+
+    // Fewer polls while a game runs full-screen, so tracking never costs frame time
+    let slow_poll_fullscreen = self
+      .db
+      .get_setting(FULLSCREEN_SLOW_POLL_SETTING)
+      .unwrap_or(None)
+      .is_some_and(|value| value == "true");
+
+    // Machines left on overnight shouldn't keep polling
+    let auto_stop_after = auto_stop_idle_limit(&self.db);
+
+    // AFK detection; re-read by the loop when the setting changes
+    let mut idle_threshold = load_idle_threshold(&self.db);
+    info!("Idle threshold: {:?}", idle_threshold);
+
     // Spawn tracking task
     let db = self.db.clone();
     let window_tracker = self.window_tracker.clone();
@@ -59,11 +231,58 @@ impl Collector {
     let is_running = self.is_running.clone();
     let events_collected = self.events_collected.clone();
     let active_window = self.active_window.clone();
+    let paused_by_schedule = self.paused_by_schedule.clone();
+    let pause = self.pause.clone();
+    let shared_power_profile = self.power_profile.clone();
+    let dormant = self.dormant.clone();
+    let event_queue = self.event_queue.clone();
+    let activity = self.activity.clone();
+    let recorder = self.recorder.clone();
 
     info!("Collector tracking loop started");
 
-    tokio::spawn(async move {
+    let tracking_loop = tokio::spawn(async move {
       let mut last_window: Option<String> = None;
+      let mut last_utc_offset = current_utc_offset_minutes();
+      // Event currently accumulating time; closed on window change, idle, suspend and stop
+      let mut open_event: Option<String> = None;
+      // AFK period in progress; closed when activity resumes, on suspend and stop
+      let mut afk_event: Option<String> = None;
+      // Media playing in the background and its open media_playback event
+      let mut media_playing: Option<NowPlaying> = None;
+      let mut media_event: Option<String> = None;
+      // App holding the camera / microphone (CaptureDevice::ALL order) and its open event
+      let mut capture_apps: [Option<String>; 2] = Default::default();
+      let mut capture_events: [Option<String>; 2] = Default::default();
+      let mut last_tick = Utc::now();
+      let mut last_flush = std::time::Instant::now();
+      let mut last_media_check: Option<std::time::Instant> = None;
+      let mut last_capture_check: Option<std::time::Instant> = None;
+      let mut last_power_check: Option<std::time::Instant> = None;
+      let mut last_idle_threshold_check = std::time::Instant::now();
+      let mut power_profile = PowerProfile::default();
+      let mut fullscreen = false;
+
+      let media_monitor = match MediaMonitor::new() {
+        Ok(monitor) => Some(monitor),
+        Err(e) => {
+          debug!("Media playback detection unavailable: {}", e);
+          None
+        }
+      };
+
+      // Suspend/resume notifications; without them, fall back to detecting wall-clock gaps
+      let (_power_monitor, mut power_events) = match PowerMonitor::start() {
+        Ok((monitor, events)) => (Some(monitor), Some(events)),
+        Err(e) => {
+          debug!("Power notifications unavailable, using gap detection: {}", e);
+          (None, None)
+        }
+      };
+
+      // Prefer foreground change notifications (pushed by the capture helper)
+      // over fixed-interval polling
+      let mut foreground_changes = window_tracker.foreground_changes();
 
       loop {
         // Check if still running
@@ -75,15 +294,212 @@ impl Collector {
           }
         }
 
-        // Check if idle
-        let should_wait = match idle_detector.is_idle(Duration::from_secs(300)) {
+        if last_flush.elapsed() >= QUEUE_FLUSH_INTERVAL {
+          flush_event_queue(&db, &event_queue).await;
+          last_flush = std::time::Instant::now();
+        }
+
+        // Record suspend/resume and close the open event at suspend time
+        let now = Utc::now();
+        let power_changes = match power_events.as_mut() {
+          Some(events) => {
+            let mut changes = Vec::new();
+            while let Ok(event) = events.try_recv() {
+              changes.push(event);
+            }
+            changes
+          }
+          None => detect_suspend_gap(last_tick, now),
+        };
+        last_tick = now;
+
+        // Save power on battery: stretch polling and idle checks
+        if last_power_check.map_or(true, |checked| checked.elapsed() >= POWER_CHECK_INTERVAL) {
+          last_power_check = Some(std::time::Instant::now());
+          let profile = tokio::task::spawn_blocking(power_profile::current_power_profile)
+            .await
+            .unwrap_or_default();
+          if profile != power_profile {
+            info!("Power profile changed: {:?} -> {:?}", power_profile, profile);
+            power_profile = profile;
+            *shared_power_profile.lock().await = profile;
+          }
+        }
+
+        for event in power_changes {
+          if let PowerEvent::Suspend(at) = event {
+            recorder.record(TrackerSample::Suspend { at });
+            last_window = None;
+            close_open_event(&db, &mut afk_event, at, "afk").await;
+            close_open_event(&db, &mut media_event, at, "media_playback").await;
+            media_playing = None;
+            close_capture_events(&db, &mut capture_apps, &mut capture_events, at).await;
+          }
+          record_power_event(&db, &event_queue, event, &mut open_event).await;
+        }
+
+        // Record a marker when the system timezone changes (e.g. travel)
+        let utc_offset = current_utc_offset_minutes();
+        if utc_offset != last_utc_offset {
+          let detail = format!("{} -> {}", format_utc_offset(last_utc_offset), format_utc_offset(utc_offset));
+          info!("Timezone changed: {}", detail);
+          if let Err(e) = db.store_marker_event("timezone_change", &detail, Utc::now()).await {
+            error!("Failed to store timezone change: {}", e);
+          }
+          last_utc_offset = utc_offset;
+        }
+
+        // Honour a temporary pause; it lifts itself once its time is up
+        let active_pause = {
+          let mut pause = pause.lock().await;
+          match *pause {
+            Some(current) if Utc::now() < current.until => Some(current),
+            Some(_) => {
+              info!("Pause ended, resuming tracking");
+              *pause = None;
+              None
+            }
+            None => None,
+          }
+        };
+        if let Some(Pause { started_at, until }) = active_pause {
+          // Recording stopped when the pause was requested, not when this tick noticed it
+          close_app_event(&db, &event_queue, &mut open_event, started_at).await;
+          close_open_event(&db, &mut afk_event, started_at, "afk").await;
+          close_open_event(&db, &mut media_event, started_at, "media_playback").await;
+          media_playing = None;
+          close_capture_events(&db, &mut capture_apps, &mut capture_events, started_at).await;
+          last_window = None;
+          *active_window.lock().await = None;
+
+          let remaining = (until - Utc::now()).to_std().unwrap_or_default();
+          tokio::time::sleep(remaining.min(POLL_INTERVAL)).await;
+          continue;
+        }
+
+        // Pause outside the user's tracking windows
+        if !in_tracking_schedule(&db) {
+          let now = Utc::now();
+          close_app_event(&db, &event_queue, &mut open_event, now).await;
+          close_open_event(&db, &mut afk_event, now, "afk").await;
+          close_open_event(&db, &mut media_event, now, "media_playback").await;
+          media_playing = None;
+          close_capture_events(&db, &mut capture_apps, &mut capture_events, now).await;
+          last_window = None;
+          *active_window.lock().await = None;
+
+          let mut paused = paused_by_schedule.lock().await;
+          if !*paused {
+            info!("Outside tracking schedule, pausing");
+            *paused = true;
+          }
+          drop(paused);
+
+          tokio::time::sleep(SCHEDULE_RECHECK_INTERVAL).await;
+          continue;
+        }
+        {
+          let mut paused = paused_by_schedule.lock().await;
+          if *paused {
+            info!("Inside tracking schedule, resuming");
+            *paused = false;
+          }
+        }
+
+        // Background playback counts whether or not the user is idle or the player has focus
+        if let Some(monitor) = &media_monitor {
+          if last_media_check.map_or(true, |checked| checked.elapsed() >= MEDIA_POLL_INTERVAL) {
+            last_media_check = Some(std::time::Instant::now());
+            let playing = monitor.now_playing().unwrap_or_else(|e| {
+              debug!("Failed to read media session: {}", e);
+              None
+            });
+            if playing != media_playing {
+              let now = Utc::now();
+              close_open_event(&db, &mut media_event, now, "media_playback").await;
+              if let Some(current) = &playing {
+                media_event = open_media_event(&db, &redactor, current, now).await;
+              }
+              media_playing = playing;
+            }
+          }
+        }
+
+        // Camera / microphone in use, like media, regardless of focus or idleness
+        if capture_devices::SUPPORTED
+          && last_capture_check.map_or(true, |checked| checked.elapsed() >= CAPTURE_POLL_INTERVAL)
+        {
+          last_capture_check = Some(std::time::Instant::now());
+          for (i, device) in CaptureDevice::ALL.into_iter().enumerate() {
+            let app = capture_devices::app_using(device).unwrap_or_else(|e| {
+              debug!("Failed to read {} state: {}", device.event_type(), e);
+              None
+            });
+            if app != capture_apps[i] {
+              let now = Utc::now();
+              close_open_event(&db, &mut capture_events[i], now, device.event_type()).await;
+              if let Some(app_name) = &app {
+                capture_events[i] = open_device_event(&db, device, app_name, now).await;
+              }
+              capture_apps[i] = app;
+            }
+          }
+        }
+
+        // Check if idle; the threshold is re-read now and then so edits take effect
+        if last_idle_threshold_check.elapsed() >= IDLE_THRESHOLD_RECHECK_INTERVAL {
+          last_idle_threshold_check = std::time::Instant::now();
+          let threshold = load_idle_threshold(&db);
+          if threshold != idle_threshold {
+            info!("Idle threshold changed: {:?} -> {:?}", idle_threshold, threshold);
+            idle_threshold = threshold;
+          }
+        }
+        let should_wait = match idle_detector.is_idle(idle_threshold) {
           Ok(is_idle) => {
             if is_idle {
-              debug!("User is idle, waiting 5 seconds...");
+              // The user stopped interacting a full threshold ago; the AFK period starts there
+              if afk_event.is_none() {
+                let idle_since = Utc::now() - chrono::Duration::from_std(idle_threshold).unwrap_or_default();
+                close_app_event(&db, &event_queue, &mut open_event, idle_since).await;
+                last_window = None;
+
+                info!("User went AFK at {}", idle_since);
+                recorder.record(TrackerSample::Afk { since: idle_since });
+                match db.open_system_event("afk", idle_since).await {
+                  Ok(id) => afk_event = Some(id),
+                  Err(e) => error!("Failed to store afk event: {}", e),
+                }
+              }
+
+              // Gone for hours: stop everything but a cheap idle check until input returns
+              if let Some(limit) = auto_stop_after.filter(|limit| matches!(idle_detector.is_idle(*limit), Ok(true))) {
+                let now = Utc::now();
+                close_open_event(&db, &mut media_event, now, "media_playback").await;
+                media_playing = None;
+                close_capture_events(&db, &mut capture_apps, &mut capture_events, now).await;
+                flush_event_queue(&db, &event_queue).await;
+
+                info!("Idle for over {:?}, collection stopped until the next input", limit);
+                *dormant.lock().await = true;
+                wait_for_input(&idle_detector, &is_running, limit).await;
+                *dormant.lock().await = false;
+                info!("Collection restarted");
+
+                // The dormant stretch is not a suspend
+                last_tick = Utc::now();
+              }
+
               // User is idle, wait and check again
-              tokio::time::sleep(Duration::from_secs(5)).await;
+              let recheck = if power_profile.is_saving() { BATTERY_IDLE_CHECK_INTERVAL } else { IDLE_CHECK_INTERVAL };
+              debug!("User is idle, waiting {:?}...", recheck);
+              tokio::time::sleep(recheck).await;
               true
             } else {
+              if afk_event.is_some() {
+                info!("User returned from AFK");
+                close_open_event(&db, &mut afk_event, Utc::now(), "afk").await;
+              }
               false
             }
           }
@@ -100,13 +516,31 @@ impl Collector {
         // Get active window
         let window_result = window_tracker.get_active_window_info();
         match window_result {
-          Ok(window_info) => {
-            let current_window = Some(window_info.process_name.clone());
+          Ok(mut window_info) => {
+            if recorder.is_recording() {
+              let excluded = should_skip(&db, &window_info).await;
+              let policy = db.title_policy(&window_info.process_name).await.unwrap_or(TitlePolicy::None);
+              recorder.record_window(&window_info, excluded, &redactor, policy);
+            }
+            window_info.project = project_matcher.project_for(&window_info.window_title);
+            window_info.window_title = redactor.redact(&window_info.window_title);
+            window_info.document = document::document_from_title(&window_info.process_name, &window_info.window_title);
+            if window_info.fullscreen != fullscreen {
+              debug!("Full-screen app {}", if window_info.fullscreen { "entered" } else { "left" });
+              fullscreen = window_info.fullscreen;
+            }
+
+            let current_window = Some(window_key(&window_info));
 
             debug!("Current window: {:?}, Last window: {:?}", current_window, last_window);
 
-            // Check if window changed
-            if last_window != current_window {
+            if last_window != current_window && should_skip(&db, &window_info).await {
+              // Never record excluded apps (or private windows if so configured); only end the previous event
+              debug!("Skipping excluded app or private window");
+              last_window = current_window;
+              *active_window.lock().await = None;
+              close_app_event(&db, &event_queue, &mut open_event, window_info.timestamp).await;
+            } else if last_window != current_window {
               // ALWAYS increment counter on window change (including first window)
               let mut count = events_collected.lock().await;
               *count += 1;
@@ -122,6 +556,17 @@ impl Collector {
 
               last_window = current_window.clone();
 
+              // Store only as much of the title as the app's policy allows; errors store none
+              let policy = db.title_policy(&window_info.process_name).await.unwrap_or_else(|e| {
+                error!("Failed to load title policy: {}", e);
+                TitlePolicy::None
+              });
+              window_info.window_title = policy.apply(&window_info.process_name, &window_info.window_title);
+              // The document comes from the title, so it is only kept when the full title is
+              if policy != TitlePolicy::Full {
+                window_info.document = None;
+              }
+
               // Update active window
               let mut active = active_window.lock().await;
               *active = Some(format!(
@@ -130,12 +575,21 @@ impl Collector {
                 window_info.window_title
               ));
 
-              // Store event in database
-              debug!("Storing event in database...");
-              if let Err(e) = db.store_event(&window_info).await {
-                error!("Failed to store event: {}", e);
-              } else {
-                debug!("Event stored successfully");
+              if let (Some(hasher), Some(path)) = (&executable_hasher, &window_info.process_path) {
+                hasher.hash_in_background(path);
+              }
+
+              // Close the previous event at the moment of the switch
+              close_app_event(&db, &event_queue, &mut open_event, window_info.timestamp).await;
+
+              // Queue the event; it reaches the database with the next batch
+              match event_queue.enqueue(window_info).await {
+                Ok(id) => open_event = Some(id),
+                Err(e) => error!("Failed to queue event: {}", e),
+              }
+              if event_queue.len().await >= QUEUE_FLUSH_THRESHOLD {
+                flush_event_queue(&db, &event_queue).await;
+                last_flush = std::time::Instant::now();
               }
             } else {
               debug!("Window unchanged: {:?}", current_window);
@@ -146,12 +600,34 @@ impl Collector {
           }
         }
 
-        // Wait before next poll
-        tokio::time::sleep(Duration::from_secs(1)).await;
+        // Counts toward the next self-report prompt
+        activity.record(Utc::now(), open_event.as_deref());
+
+        // Wait for a foreground change or the next poll
+        let stretched = [
+          (slow_poll_fullscreen && fullscreen).then_some(FULLSCREEN_POLL_INTERVAL),
+          power_profile.is_saving().then_some(BATTERY_POLL_INTERVAL),
+        ]
+        .into_iter()
+        .flatten()
+        .max();
+        if wait_for_next_poll(foreground_changes.as_mut(), stretched).await {
+          error!("Foreground hook stopped, falling back to polling");
+          foreground_changes = None;
+        }
       }
 
+      let stopped_at = Utc::now();
+      recorder.record(TrackerSample::Stopped { at: stopped_at });
+      close_app_event(&db, &event_queue, &mut open_event, stopped_at).await;
+      close_open_event(&db, &mut afk_event, stopped_at, "afk").await;
+      close_open_event(&db, &mut media_event, stopped_at, "media_playback").await;
+      close_capture_events(&db, &mut capture_apps, &mut capture_events, stopped_at).await;
+      flush_event_queue(&db, &event_queue).await;
+
       info!("Collector tracking loop ended");
     });
+    *self.tracking_loop.lock().await = Some(tracking_loop);
 
     Ok(())
   }
@@ -164,26 +640,366 @@ impl Collector {
     // Clear active window
     let mut active = self.active_window.lock().await;
     *active = None;
+    *self.paused_by_schedule.lock().await = false;
+    *self.pause.lock().await = None;
+    *self.dormant.lock().await = false;
 
     info!("Collector stop completed");
     Ok(())
   }
 
+  /// Stop, then wait until the tracking loop has closed its open events and
+  /// written out the queue, e.g. before another process takes over
+  pub async fn stop_and_wait(&self) -> Result<()> {
+    self.stop().await?;
+    let Some(tracking_loop) = self.tracking_loop.lock().await.take() else {
+      return Ok(());
+    };
+    match tokio::time::timeout(STOP_WAIT_TIMEOUT, tracking_loop).await {
+      Ok(Ok(())) => Ok(()),
+      Ok(Err(e)) => bail!("The collector loop failed while stopping: {}", e),
+      Err(_) => bail!("The collector didn't stop within {:?}", STOP_WAIT_TIMEOUT),
+    }
+  }
+
+  /// Stop recording now and resume automatically after `duration_minutes`.
+  /// Pausing again while paused restarts the countdown.
+  pub async fn pause_tracking(&self, duration_minutes: u32) -> Result<()> {
+    if duration_minutes == 0 || duration_minutes > MAX_PAUSE_MINUTES {
+      bail!("Pause must be between 1 and {} minutes", MAX_PAUSE_MINUTES);
+    }
+    if !*self.is_running.lock().await {
+      bail!("Tracking is not running");
+    }
+
+    let now = Utc::now();
+    let mut pause = self.pause.lock().await;
+    let started_at = pause.map_or(now, |current| current.started_at);
+    *pause = Some(Pause {
+      started_at,
+      until: now + chrono::Duration::minutes(duration_minutes as i64),
+    });
+    drop(pause);
+
+    *self.active_window.lock().await = None;
+    info!("Tracking paused for {} minutes", duration_minutes);
+    Ok(())
+  }
+
+  /// End a `pause_tracking` pause early
+  pub async fn resume_tracking(&self) -> Result<()> {
+    if self.pause.lock().await.take().is_some() {
+      info!("Tracking resumed before pause ended");
+    }
+    Ok(())
+  }
+
+  /// Active time since the last self-report prompt
+  pub fn activity_meter(&self) -> ActivityMeter {
+    self.activity.clone()
+  }
+
+  /// Developer recording of the tracker event stream
+  pub fn event_recorder(&self) -> EventRecorder {
+    self.recorder.clone()
+  }
+
   pub async fn get_status(&self) -> Result<CollectorStatus> {
     let is_running = *self.is_running.lock().await;
     let events_collected = *self.events_collected.lock().await;
     let active_window = self.active_window.lock().await.clone();
+    let paused_by_schedule = *self.paused_by_schedule.lock().await;
+    let now = Utc::now();
+    let pause_remaining_seconds = self
+      .pause
+      .lock()
+      .await
+      .filter(|pause| now < pause.until)
+      .map(|pause| (pause.until - now).num_seconds());
     let last_sync_at = self.db.get_last_sync_time().await?.map(|t| t.to_rfc3339());
+    let power_profile = *self.power_profile.lock().await;
+    let dormant = *self.dormant.lock().await;
 
     Ok(CollectorStatus {
       is_running,
       events_collected,
       last_sync_at,
       active_window,
+      paused_by_schedule,
+      pause_remaining_seconds,
+      power_profile,
+      dormant,
+      focus: None,
     })
   }
 }
 
+/// Time without input after which the user is AFK; invalid values fall back
+/// to the default, values below the minimum are raised to it
+fn load_idle_threshold(db: &Database) -> Duration {
+  let seconds = db
+    .get_setting(IDLE_THRESHOLD_SETTING)
+    .unwrap_or(None)
+    .and_then(|value| value.trim().parse::<u64>().ok())
+    .unwrap_or(DEFAULT_IDLE_THRESHOLD_SECS);
+  Duration::from_secs(seconds.max(MIN_IDLE_THRESHOLD_SECS))
+}
+
+/// Idle time after which collection stops; None when auto-stop is disabled
+fn auto_stop_idle_limit(db: &Database) -> Option<Duration> {
+  let minutes = db
+    .get_setting(AUTO_STOP_IDLE_SETTING)
+    .unwrap_or(None)
+    .and_then(|value| value.parse::<u64>().ok())
+    .unwrap_or(DEFAULT_AUTO_STOP_IDLE_MINUTES);
+  (minutes > 0).then(|| Duration::from_secs(minutes * 60))
+}
+
+/// Sleep until there has been input within `limit`, the idle check fails, or tracking stops
+async fn wait_for_input(idle_detector: &IdleDetector, is_running: &Mutex<bool>, limit: Duration) {
+  loop {
+    tokio::time::sleep(DORMANT_CHECK_INTERVAL).await;
+    if !*is_running.lock().await || !matches!(idle_detector.is_idle(limit), Ok(true)) {
+      return;
+    }
+  }
+}
+
+/// Whether the tracking schedule allows recording now; errors keep tracking on
+fn in_tracking_schedule(db: &Database) -> bool {
+  match TrackingSchedule::load(db) {
+    Ok(schedule) => schedule.is_active_at(Local::now().naive_local()),
+    Err(e) => {
+      error!("Failed to load tracking schedule: {}", e);
+      true
+    }
+  }
+}
+
+/// What identifies the foreground window for change detection: a different
+/// site, a private window, another document or another project in the same
+/// app, or the same window on another virtual desktop, counts as a window change
+fn window_key(window_info: &window_tracker::WindowInfo) -> String {
+  let key = match (&window_info.url_domain, &window_info.document) {
+    (Some(domain), _) => format!("{} ({})", window_info.process_name, domain),
+    (None, _) if window_info.window_title == browser::PRIVATE_BROWSING_TITLE => {
+      format!("{} {}", window_info.process_name, browser::PRIVATE_BROWSING_TITLE)
+    }
+    (None, Some(document)) => format!("{} [{}]", window_info.process_name, document),
+    (None, None) => window_info.process_name.clone(),
+  };
+  let key = match &window_info.project {
+    Some(project) => format!("{} <{}>", key, project),
+    None => key,
+  };
+  match &window_info.virtual_desktop {
+    Some(desktop) => format!("{} @{}", key, desktop),
+    None => key,
+  }
+}
+
+/// Whether this window must not be recorded: the app is excluded, or it is a
+/// private browser window and the user chose to skip those. Errors count as no.
+async fn should_skip(db: &Database, window_info: &window_tracker::WindowInfo) -> bool {
+  if window_info.window_title == browser::PRIVATE_BROWSING_TITLE {
+    let skip_private = db
+      .get_setting(browser::PRIVATE_BROWSING_SETTING)
+      .unwrap_or(None)
+      .is_some_and(|mode| mode == "skip");
+    if skip_private {
+      return true;
+    }
+  }
+
+  db.is_app_excluded(&window_info.process_name).await.unwrap_or_else(|e| {
+    error!("Failed to check app exclusions: {}", e);
+    false
+  })
+}
+
+/// Sleep until the foreground window changes or the poll interval elapses;
+/// `stretched` replaces both intervals (full-screen app, battery).
+/// Returns true if the change notification channel has closed.
+async fn wait_for_next_poll(foreground_changes: Option<&mut UnboundedReceiver<()>>, stretched: Option<Duration>) -> bool {
+  match foreground_changes {
+    Some(changes) => {
+      let fallback = stretched.unwrap_or(HOOK_FALLBACK_INTERVAL);
+      let closed = tokio::select! {
+        change = changes.recv() => change.is_none(),
+        _ = tokio::time::sleep(fallback) => false,
+      };
+      // Coalesce bursts of notifications (e.g. alt-tab cycling) into one poll
+      while changes.try_recv().is_ok() {}
+      closed
+    }
+    None => {
+      tokio::time::sleep(stretched.unwrap_or(POLL_INTERVAL)).await;
+      false
+    }
+  }
+}
+
+/// Treat a wall-clock jump between ticks as a suspend at the last tick and a resume now
+fn detect_suspend_gap(last_tick: DateTime<Utc>, now: DateTime<Utc>) -> Vec<PowerEvent> {
+  let gap = now.signed_duration_since(last_tick);
+  match gap.to_std() {
+    Ok(gap) if gap > SUSPEND_GAP_THRESHOLD => {
+      vec![PowerEvent::Suspend(last_tick), PowerEvent::Resume(now)]
+    }
+    _ => Vec::new(),
+  }
+}
+
+/// Close the event in `slot` (if any) at `ended_at`
+async fn close_open_event(db: &Database, slot: &mut Option<String>, ended_at: DateTime<Utc>, kind: &str) {
+  if let Some(id) = slot.take() {
+    if let Err(e) = db.close_event(&id, ended_at).await {
+      error!("Failed to close {} event: {}", kind, e);
+    }
+  }
+}
+
+/// Close the app_usage event in `slot` (if any) at `ended_at`, in the queue
+/// if it hasn't been flushed yet
+async fn close_app_event(db: &Database, queue: &EventQueue, slot: &mut Option<String>, ended_at: DateTime<Utc>) {
+  if let Some(id) = slot.as_deref() {
+    if queue.close(id, ended_at).await {
+      *slot = None;
+      return;
+    }
+  }
+  close_open_event(db, slot, ended_at, "app_usage").await;
+}
+
+/// Open a media_playback event for what is playing, unless the player is
+/// excluded; the track goes through the same redaction and title policy as
+/// window titles
+async fn open_media_event(
+  db: &Database,
+  redactor: &TitleRedactor,
+  playing: &NowPlaying,
+  started_at: DateTime<Utc>,
+) -> Option<String> {
+  let excluded = db.is_app_excluded(&playing.app_name).await.unwrap_or_else(|e| {
+    error!("Failed to check app exclusions: {}", e);
+    false
+  });
+  if excluded {
+    return None;
+  }
+
+  let policy = db.title_policy(&playing.app_name).await.unwrap_or_else(|e| {
+    error!("Failed to load title policy: {}", e);
+    TitlePolicy::None
+  });
+  let track = playing
+    .track
+    .as_deref()
+    .map(|track| policy.apply(&playing.app_name, &redactor.redact(track)))
+    .filter(|track| !track.is_empty());
+
+  info!("Media playing in {}", playing.app_name);
+  match db.open_media_event(&playing.app_name, track.as_deref(), started_at).await {
+    Ok(id) => Some(id),
+    Err(e) => {
+      error!("Failed to store media_playback event: {}", e);
+      None
+    }
+  }
+}
+
+/// Open a camera_on / mic_on event for the app holding the device, unless it is excluded
+async fn open_device_event(
+  db: &Database,
+  device: CaptureDevice,
+  app_name: &str,
+  started_at: DateTime<Utc>,
+) -> Option<String> {
+  let excluded = db.is_app_excluded(app_name).await.unwrap_or_else(|e| {
+    error!("Failed to check app exclusions: {}", e);
+    false
+  });
+  if excluded {
+    return None;
+  }
+
+  info!("{} started by {}", device.event_type(), app_name);
+  match db.open_device_event(device.event_type(), app_name, started_at).await {
+    Ok(id) => Some(id),
+    Err(e) => {
+      error!("Failed to store {} event: {}", device.event_type(), e);
+      None
+    }
+  }
+}
+
+/// Close the camera / microphone events and forget which apps held the devices
+async fn close_capture_events(
+  db: &Database,
+  apps: &mut [Option<String>; 2],
+  events: &mut [Option<String>; 2],
+  ended_at: DateTime<Utc>,
+) {
+  for (device, slot) in CaptureDevice::ALL.into_iter().zip(events.iter_mut()) {
+    close_open_event(db, slot, ended_at, device.event_type()).await;
+  }
+  *apps = Default::default();
+}
+
+/// Write queued events in one transaction; a failed batch is retried on the
+/// next flush, and events that keep failing are dropped
+async fn flush_event_queue(db: &Database, queue: &EventQueue) {
+  let events = queue.drain().await;
+  if events.is_empty() {
+    return;
+  }
+
+  let count = events.len();
+  match db.store_queued_events(events.clone()).await {
+    Ok(()) => debug!("Flushed {} queued events", count),
+    Err(e) => {
+      error!("Failed to flush {} queued events: {}", count, e);
+      let (retry, dropped): (Vec<_>, Vec<_>) = events
+        .into_iter()
+        .map(|mut event| {
+          event.retry_count += 1;
+          event
+        })
+        .partition(|event| event.retry_count < MAX_FLUSH_RETRIES);
+      if !dropped.is_empty() {
+        error!("Dropping {} events after {} failed flushes", dropped.len(), MAX_FLUSH_RETRIES);
+      }
+      queue.requeue(retry).await;
+    }
+  }
+}
+
+/// Write a system_suspend/system_resume marker; on suspend, close the open event first
+async fn record_power_event(db: &Database, queue: &EventQueue, event: PowerEvent, open_event: &mut Option<String>) {
+  match event {
+    PowerEvent::Suspend(at) => {
+      info!("System suspending at {}", at);
+      close_app_event(db, queue, open_event, at).await;
+      if let Err(e) = db.store_marker_event("system_suspend", "", at).await {
+        error!("Failed to store suspend marker: {}", e);
+      }
+    }
+    PowerEvent::Resume(at) => {
+      info!("System resumed at {}", at);
+      if let Err(e) = db.store_marker_event("system_resume", "", at).await {
+        error!("Failed to store resume marker: {}", e);
+      }
+    }
+  }
+}
+
+/// Format a UTC offset in minutes as "UTC+08:00"
+fn format_utc_offset(minutes: i32) -> String {
+  let sign = if minutes < 0 { '-' } else { '+' };
+  let minutes = minutes.abs();
+  format!("UTC{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -196,6 +1012,11 @@ mod tests {
       events_collected: 100,
       last_sync_at: Some("2024-01-01T00:00:00Z".to_string()),
       active_window: Some("chrome.exe - Google Search".to_string()),
+      paused_by_schedule: false,
+      pause_remaining_seconds: None,
+      power_profile: PowerProfile::AcPower,
+      dormant: false,
+      focus: None,
     };
 
     let serialized = serde_json::to_string(&status);
@@ -215,6 +1036,11 @@ mod tests {
       events_collected: 0,
       last_sync_at: None,
       active_window: None,
+      paused_by_schedule: false,
+      pause_remaining_seconds: None,
+      power_profile: PowerProfile::AcPower,
+      dormant: false,
+      focus: None,
     };
 
     let serialized = serde_json::to_string(&status).unwrap();
@@ -268,6 +1094,212 @@ mod tests {
     assert!(status.active_window.is_none());
   }
 
+  #[tokio::test]
+  async fn test_pause_tracking_requires_running_collector() {
+    let temp_file = tempfile::NamedTempFile::new().unwrap();
+    let db = Arc::new(Database::new(temp_file.path()).unwrap());
+
+    let collector = Collector::new(db).unwrap();
+    assert!(collector.pause_tracking(10).await.is_err());
+
+    collector.start().await.unwrap();
+    assert!(collector.pause_tracking(0).await.is_err());
+    assert!(collector.pause_tracking(MAX_PAUSE_MINUTES + 1).await.is_err());
+    collector.stop().await.unwrap();
+  }
+
+  #[test]
+  fn test_auto_stop_idle_limit() {
+    let temp_file = tempfile::NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+
+    assert_eq!(auto_stop_idle_limit(&db), Some(Duration::from_secs(4 * 60 * 60)));
+    db.set_setting(AUTO_STOP_IDLE_SETTING, "90").unwrap();
+    assert_eq!(auto_stop_idle_limit(&db), Some(Duration::from_secs(90 * 60)));
+    db.set_setting(AUTO_STOP_IDLE_SETTING, "0").unwrap();
+    assert_eq!(auto_stop_idle_limit(&db), None);
+  }
+
+  #[test]
+  fn test_idle_threshold_setting() {
+    let temp_file = tempfile::NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+
+    // Seeded by the schema
+    assert_eq!(load_idle_threshold(&db), Duration::from_secs(300));
+    db.set_setting(IDLE_THRESHOLD_SETTING, "120").unwrap();
+    assert_eq!(load_idle_threshold(&db), Duration::from_secs(120));
+    db.set_setting(IDLE_THRESHOLD_SETTING, "5").unwrap();
+    assert_eq!(load_idle_threshold(&db), Duration::from_secs(MIN_IDLE_THRESHOLD_SECS));
+    db.set_setting(IDLE_THRESHOLD_SETTING, "soon").unwrap();
+    assert_eq!(load_idle_threshold(&db), Duration::from_secs(DEFAULT_IDLE_THRESHOLD_SECS));
+  }
+
+  #[tokio::test]
+  async fn test_pause_tracking_reports_remaining_time() {
+    let temp_file = tempfile::NamedTempFile::new().unwrap();
+    let db = Arc::new(Database::new(temp_file.path()).unwrap());
+
+    let collector = Collector::new(db).unwrap();
+    collector.start().await.unwrap();
+    collector.pause_tracking(10).await.unwrap();
+
+    let status = collector.get_status().await.unwrap();
+    assert!(status.is_running);
+    let remaining = status.pause_remaining_seconds.unwrap();
+    assert!(remaining > 590 && remaining <= 600);
+
+    collector.resume_tracking().await.unwrap();
+    assert!(collector.get_status().await.unwrap().pause_remaining_seconds.is_none());
+
+    // Stopping clears any pause
+    collector.pause_tracking(5).await.unwrap();
+    collector.stop().await.unwrap();
+    assert!(collector.get_status().await.unwrap().pause_remaining_seconds.is_none());
+  }
+
+  #[tokio::test]
+  async fn test_wait_for_next_poll_wakes_on_change() {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    tx.send(()).unwrap();
+    tx.send(()).unwrap();
+
+    let started = std::time::Instant::now();
+    let closed = wait_for_next_poll(Some(&mut rx), None).await;
+
+    assert!(!closed);
+    assert!(started.elapsed() < HOOK_FALLBACK_INTERVAL);
+    // Burst was coalesced
+    assert!(rx.try_recv().is_err());
+  }
+
+  #[tokio::test]
+  async fn test_wait_for_next_poll_reports_closed_channel() {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<()>();
+    drop(tx);
+
+    assert!(wait_for_next_poll(Some(&mut rx), None).await);
+  }
+
+  #[test]
+  fn test_detect_suspend_gap() {
+    let last_tick = Utc::now();
+
+    assert!(detect_suspend_gap(last_tick, last_tick + chrono::Duration::seconds(5)).is_empty());
+    assert!(detect_suspend_gap(last_tick, last_tick - chrono::Duration::seconds(600)).is_empty());
+
+    let resumed = last_tick + chrono::Duration::minutes(30);
+    assert_eq!(
+      detect_suspend_gap(last_tick, resumed),
+      vec![PowerEvent::Suspend(last_tick), PowerEvent::Resume(resumed)]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_record_power_event_closes_open_event() {
+    let temp = tempfile::NamedTempFile::new().unwrap();
+    let db = Database::new(temp.path()).unwrap();
+    let info = window_tracker::WindowInfo {
+      process_name: "code.exe".to_string(),
+      window_title: "main.rs".to_string(),
+      timestamp: Utc::now(),
+      ..Default::default()
+    };
+    let id = db.store_event(&info).await.unwrap();
+    let started = db.get_event(&id).unwrap().unwrap().timestamp;
+
+    let mut open_event = Some(id.clone());
+    let suspended_at = started + chrono::Duration::seconds(120);
+    let queue = EventQueue::new(10);
+    record_power_event(&db, &queue, PowerEvent::Suspend(suspended_at), &mut open_event).await;
+    record_power_event(&db, &queue, PowerEvent::Resume(suspended_at + chrono::Duration::hours(1)), &mut open_event).await;
+
+    assert!(open_event.is_none());
+    assert_eq!(db.get_event(&id).unwrap().unwrap().duration, 120);
+
+    let types: Vec<String> = db.get_events(10, 0).unwrap().into_iter().map(|e| e.event_type).collect();
+    assert!(types.contains(&"system_suspend".to_string()));
+    assert!(types.contains(&"system_resume".to_string()));
+  }
+
+  #[test]
+  fn test_project_changes_window_key() {
+    let temp = tempfile::NamedTempFile::new().unwrap();
+    let db = Database::new(temp.path()).unwrap();
+    db.add_project_rule(r"JIRA-\d+", "Tickets").unwrap();
+    let matcher = ProjectMatcher::load(&db);
+
+    let window = |title: &str| {
+      let mut info = window_tracker::WindowInfo {
+        process_name: "chrome.exe".to_string(),
+        window_title: title.to_string(),
+        timestamp: Utc::now(),
+        ..Default::default()
+      };
+      info.project = matcher.project_for(&info.window_title);
+      info
+    };
+
+    let ticket = window("JIRA-123: Login broken");
+    assert_eq!(ticket.project.as_deref(), Some("Tickets"));
+    assert_ne!(window_key(&ticket), window_key(&window("Inbox")));
+    assert_eq!(window_key(&ticket), window_key(&window("JIRA-456: Crash")));
+
+    let id = db.store_event_sync(&ticket).unwrap();
+    assert_eq!(db.get_event(&id).unwrap().unwrap().project.as_deref(), Some("Tickets"));
+  }
+
+  #[test]
+  fn test_virtual_desktop_changes_window_key() {
+    let temp = tempfile::NamedTempFile::new().unwrap();
+    let db = Database::new(temp.path()).unwrap();
+
+    let window = |desktop: Option<&str>| window_tracker::WindowInfo {
+      process_name: "slack.exe".to_string(),
+      window_title: "general".to_string(),
+      timestamp: Utc::now(),
+      virtual_desktop: desktop.map(str::to_string),
+      ..Default::default()
+    };
+
+    assert_ne!(window_key(&window(Some("1"))), window_key(&window(Some("2"))));
+    assert_eq!(window_key(&window(Some("2"))), window_key(&window(Some("2"))));
+
+    let id = db.store_event_sync(&window(Some("2"))).unwrap();
+    assert_eq!(db.get_event(&id).unwrap().unwrap().virtual_desktop.as_deref(), Some("2"));
+  }
+
+  #[tokio::test]
+  async fn test_open_media_event_applies_title_policy_and_exclusions() {
+    let temp = tempfile::NamedTempFile::new().unwrap();
+    let db = Database::new(temp.path()).unwrap();
+    let redactor = TitleRedactor::new(&[]);
+    let playing = |app_name: &str| NowPlaying {
+      app_name: app_name.to_string(),
+      track: Some("Artist - Song".to_string()),
+    };
+
+    let id = open_media_event(&db, &redactor, &playing("Spotify.exe"), Utc::now()).await.unwrap();
+    let event = db.get_event(&id).unwrap().unwrap();
+    assert_eq!(event.event_type, "media_playback");
+    assert_eq!(event.window_title.as_deref(), Some("Artist - Song"));
+
+    db.set_title_policy("Podcasts", TitlePolicy::None).unwrap();
+    let id = open_media_event(&db, &redactor, &playing("Podcasts"), Utc::now()).await.unwrap();
+    assert!(db.get_event(&id).unwrap().unwrap().window_title.is_none());
+
+    db.add_excluded_app("Secret.exe").unwrap();
+    assert!(open_media_event(&db, &redactor, &playing("Secret.exe"), Utc::now()).await.is_none());
+  }
+
+  #[test]
+  fn test_format_utc_offset() {
+    assert_eq!(format_utc_offset(0), "UTC+00:00");
+    assert_eq!(format_utc_offset(480), "UTC+08:00");
+    assert_eq!(format_utc_offset(-210), "UTC-03:30");
+    assert_eq!(format_utc_offset(345), "UTC+05:45");
+  }
+
   #[test]
   fn test_window_tracker_new() {
     let tracker = WindowTracker::new();
@@ -294,6 +1326,7 @@ mod tests {
       process_name: "test_app".to_string(),
       window_title: "Test Window".to_string(),
       timestamp: chrono::Utc::now(),
+      ..Default::default()
     };
 
     queue.enqueue(window_info).await.unwrap();
@@ -324,13 +1357,13 @@ mod tests {
     assert!(result.is_ok());
   }
 
-  #[cfg(not(windows))]
+  #[cfg(not(any(windows, target_os = "linux")))]
   #[test]
-  fn test_idle_detector_non_windows() {
+  fn test_idle_detector_unsupported_platform() {
     let detector = IdleDetector::new().unwrap();
     let result = detector.is_idle(Duration::from_secs(300));
     assert!(result.is_ok());
-    // On non-Windows, should return false (not idle)
+    // On unsupported platforms, should return false (not idle)
     assert!(!result.unwrap());
   }
 
@@ -0,0 +1,190 @@
+//! System suspend/resume notifications.
+//!
+//! Windows uses `PowerRegisterSuspendResumeNotification`, Linux listens for
+//! logind's `PrepareForSleep` signal. Each event carries the wall-clock time
+//! it was observed, since the tracking loop may only see it after resume.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerEvent {
+  Suspend(DateTime<Utc>),
+  Resume(DateTime<Utc>),
+}
+
+/// Keeps the OS subscription alive; dropping it unsubscribes
+pub struct PowerMonitor {
+  #[cfg(windows)]
+  registration: windows_impl::Registration,
+}
+
+impl PowerMonitor {
+  pub fn start() -> Result<(Self, UnboundedReceiver<PowerEvent>)> {
+    let (tx, rx) = unbounded_channel();
+    let monitor = Self::subscribe(tx)?;
+    Ok((monitor, rx))
+  }
+
+  #[cfg(windows)]
+  fn subscribe(tx: UnboundedSender<PowerEvent>) -> Result<Self> {
+    Ok(Self {
+      registration: windows_impl::Registration::new(tx)?,
+    })
+  }
+
+  #[cfg(target_os = "linux")]
+  fn subscribe(tx: UnboundedSender<PowerEvent>) -> Result<Self> {
+    linux_impl::spawn_listener(tx)?;
+    Ok(Self {})
+  }
+
+  #[cfg(not(any(windows, target_os = "linux")))]
+  fn subscribe(_tx: UnboundedSender<PowerEvent>) -> Result<Self> {
+    Err(anyhow::anyhow!("Suspend/resume notifications are not supported on this platform"))
+  }
+}
+
+#[cfg(windows)]
+mod windows_impl {
+  use super::PowerEvent;
+  use anyhow::{anyhow, Result};
+  use chrono::Utc;
+  use std::ffi::c_void;
+  use tokio::sync::mpsc::UnboundedSender;
+  use windows::Win32::Foundation::HANDLE;
+  use windows::Win32::System::Power::{
+    PowerRegisterSuspendResumeNotification, PowerUnregisterSuspendResumeNotification,
+    DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS, HPOWERNOTIFY,
+  };
+  use windows::Win32::UI::WindowsAndMessaging::DEVICE_NOTIFY_CALLBACK;
+
+  const PBT_APMSUSPEND: u32 = 0x0004;
+  const PBT_APMRESUMEAUTOMATIC: u32 = 0x0012;
+
+  unsafe extern "system" fn on_power_event(context: *const c_void, event_type: u32, _setting: *const c_void) -> u32 {
+    let sender = &*(context as *const UnboundedSender<PowerEvent>);
+    let event = match event_type {
+      PBT_APMSUSPEND => Some(PowerEvent::Suspend(Utc::now())),
+      PBT_APMRESUMEAUTOMATIC => Some(PowerEvent::Resume(Utc::now())),
+      _ => None,
+    };
+    if let Some(event) = event {
+      let _ = sender.send(event);
+    }
+    0
+  }
+
+  pub struct Registration {
+    handle: *mut c_void,
+    // Both must outlive the registration; freed in Drop after unregistering
+    sender: *mut UnboundedSender<PowerEvent>,
+    params: *mut DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS,
+  }
+
+  // The raw pointers are only touched on registration and drop
+  unsafe impl Send for Registration {}
+
+  impl Registration {
+    pub fn new(tx: UnboundedSender<PowerEvent>) -> Result<Self> {
+      let sender = Box::into_raw(Box::new(tx));
+      let params = Box::into_raw(Box::new(DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS {
+        Callback: Some(on_power_event),
+        Context: sender as *mut c_void,
+      }));
+
+      let mut handle: *mut c_void = std::ptr::null_mut();
+      let status = unsafe {
+        PowerRegisterSuspendResumeNotification(DEVICE_NOTIFY_CALLBACK, HANDLE(params as *mut c_void), &mut handle)
+      };
+
+      if status.is_err() {
+        unsafe {
+          drop(Box::from_raw(params));
+          drop(Box::from_raw(sender));
+        }
+        return Err(anyhow!("PowerRegisterSuspendResumeNotification failed: {:?}", status));
+      }
+
+      Ok(Self { handle, sender, params })
+    }
+  }
+
+  impl Drop for Registration {
+    fn drop(&mut self) {
+      unsafe {
+        let _ = PowerUnregisterSuspendResumeNotification(HPOWERNOTIFY(self.handle as _));
+        drop(Box::from_raw(self.params));
+        drop(Box::from_raw(self.sender));
+      }
+    }
+  }
+}
+
+#[cfg(target_os = "linux")]
+mod linux_impl {
+  use super::PowerEvent;
+  use anyhow::{anyhow, Result};
+  use chrono::Utc;
+  use tokio::sync::mpsc::UnboundedSender;
+  use tracing::{debug, error};
+
+  /// Listen for logind PrepareForSleep(start) on a background thread.
+  /// The thread ends at the first signal after the receiver is dropped.
+  pub fn spawn_listener(tx: UnboundedSender<PowerEvent>) -> Result<()> {
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel::<Result<(), String>>();
+
+    std::thread::Builder::new()
+      .name("power-monitor".to_string())
+      .spawn(move || {
+        let subscribe = || -> zbus::Result<_> {
+          let conn = zbus::blocking::Connection::system()?;
+          let proxy = zbus::blocking::Proxy::new_owned(
+            conn,
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1",
+            "org.freedesktop.login1.Manager",
+          )?;
+          proxy.receive_signal("PrepareForSleep")
+        };
+
+        let signals = match subscribe() {
+          Ok(signals) => {
+            let _ = ready_tx.send(Ok(()));
+            signals
+          }
+          Err(e) => {
+            let _ = ready_tx.send(Err(e.to_string()));
+            return;
+          }
+        };
+
+        for message in signals {
+          let starting: bool = match message.body().deserialize() {
+            Ok(starting) => starting,
+            Err(e) => {
+              error!("Invalid PrepareForSleep signal: {}", e);
+              continue;
+            }
+          };
+
+          let event = if starting {
+            PowerEvent::Suspend(Utc::now())
+          } else {
+            PowerEvent::Resume(Utc::now())
+          };
+          if tx.send(event).is_err() {
+            break;
+          }
+        }
+
+        debug!("Power monitor thread exited");
+      })?;
+
+    ready_rx
+      .recv()
+      .map_err(|e| anyhow!("Power monitor thread died: {}", e))?
+      .map_err(|e| anyhow!("logind unavailable: {}", e))
+  }
+}
@@ -0,0 +1,104 @@
+//! Battery and power-saver detection.
+//!
+//! Windows reads `GetSystemPowerStatus` (AC line status and the battery saver
+//! flag). Linux asks UPower whether the machine runs on battery and
+//! power-profiles-daemon whether the "power-saver" profile is active. When
+//! either says to save power, the tracking loop polls less often and auto-sync
+//! holds off until the machine is back on AC.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerProfile {
+  /// Plugged in, or power state unknown
+  #[default]
+  AcPower,
+  Battery,
+  /// The OS power-saver / battery saver mode is on
+  PowerSaver,
+}
+
+impl PowerProfile {
+  /// Whether collection should slow down and auto-sync pause
+  pub fn is_saving(&self) -> bool {
+    *self != PowerProfile::AcPower
+  }
+}
+
+#[cfg_attr(not(any(windows, target_os = "linux")), allow(dead_code))]
+fn profile_from(on_battery: bool, power_saver: bool) -> PowerProfile {
+  if power_saver {
+    PowerProfile::PowerSaver
+  } else if on_battery {
+    PowerProfile::Battery
+  } else {
+    PowerProfile::AcPower
+  }
+}
+
+/// Current power profile; blocking, and AC power when it can't be determined
+#[cfg(windows)]
+pub fn current_power_profile() -> PowerProfile {
+  use windows::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+  const AC_LINE_OFFLINE: u8 = 0;
+  const BATTERY_SAVER_ON: u8 = 1;
+
+  let mut status = SYSTEM_POWER_STATUS::default();
+  if unsafe { GetSystemPowerStatus(&mut status) }.is_err() {
+    return PowerProfile::AcPower;
+  }
+  profile_from(
+    status.ACLineStatus == AC_LINE_OFFLINE,
+    status.SystemStatusFlag == BATTERY_SAVER_ON,
+  )
+}
+
+/// Current power profile; blocking, and AC power when it can't be determined
+#[cfg(target_os = "linux")]
+pub fn current_power_profile() -> PowerProfile {
+  let Ok(conn) = zbus::blocking::Connection::system() else {
+    return PowerProfile::AcPower;
+  };
+
+  let on_battery = zbus::blocking::Proxy::new(
+    &conn,
+    "org.freedesktop.UPower",
+    "/org/freedesktop/UPower",
+    "org.freedesktop.UPower",
+  )
+  .and_then(|proxy| proxy.get_property::<bool>("OnBattery"))
+  .unwrap_or(false);
+
+  let power_saver = zbus::blocking::Proxy::new(
+    &conn,
+    "net.hadess.PowerProfiles",
+    "/net/hadess/PowerProfiles",
+    "net.hadess.PowerProfiles",
+  )
+  .and_then(|proxy| proxy.get_property::<String>("ActiveProfile"))
+  .is_ok_and(|profile| profile == "power-saver");
+
+  profile_from(on_battery, power_saver)
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn current_power_profile() -> PowerProfile {
+  PowerProfile::AcPower
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_profile_from() {
+    assert_eq!(profile_from(false, false), PowerProfile::AcPower);
+    assert_eq!(profile_from(true, false), PowerProfile::Battery);
+    // Power saver can be on while plugged in; it still asks for less work
+    assert_eq!(profile_from(false, true), PowerProfile::PowerSaver);
+    assert!(!PowerProfile::AcPower.is_saving());
+    assert!(PowerProfile::Battery.is_saving());
+  }
+}
@@ -0,0 +1,109 @@
+//! Applies the user's project rules to window titles.
+//!
+//! Enabled rules are compiled once when the collector starts, like redaction
+//! rules. Titles are matched before redaction so a rule can key on text that
+//! redaction hides (e.g. ticket numbers); only the rule's label is stored,
+//! never the matched text, so the label is kept whatever the title policy.
+
+use crate::database::{Database, ProjectRule};
+use regex::{Regex, RegexSet};
+use tracing::error;
+
+#[derive(Debug, Clone)]
+pub struct ProjectMatcher {
+  set: RegexSet,
+  projects: Vec<String>,
+}
+
+impl Default for ProjectMatcher {
+  fn default() -> Self {
+    Self {
+      set: RegexSet::empty(),
+      projects: Vec::new(),
+    }
+  }
+}
+
+impl ProjectMatcher {
+  pub fn new(rules: &[ProjectRule]) -> Self {
+    let compiled: Vec<(Regex, String)> = rules
+      .iter()
+      .filter(|rule| rule.enabled)
+      .filter_map(|rule| match Regex::new(&rule.pattern) {
+        Ok(regex) => Some((regex, rule.project.clone())),
+        Err(e) => {
+          error!("Skipping project rule {}: {}", rule.id, e);
+          None
+        }
+      })
+      .collect();
+
+    match RegexSet::new(compiled.iter().map(|(regex, _)| regex.as_str())) {
+      Ok(set) => Self {
+        set,
+        projects: compiled.into_iter().map(|(_, project)| project).collect(),
+      },
+      Err(e) => {
+        error!("Failed to compile project rules: {}", e);
+        Self::default()
+      }
+    }
+  }
+
+  /// Matcher for the rules stored in `db`; errors leave events without a project
+  pub fn load(db: &Database) -> Self {
+    match db.get_project_rules() {
+      Ok(rules) => Self::new(&rules),
+      Err(e) => {
+        error!("Failed to load project rules: {}", e);
+        Self::default()
+      }
+    }
+  }
+
+  /// Project of the first rule matching `title`
+  pub fn project_for(&self, title: &str) -> Option<String> {
+    self.set.matches(title).iter().next().map(|index| self.projects[index].clone())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rule(id: i64, pattern: &str, project: &str, enabled: bool) -> ProjectRule {
+    ProjectRule {
+      id,
+      pattern: pattern.to_string(),
+      project: project.to_string(),
+      enabled,
+    }
+  }
+
+  #[test]
+  fn test_first_matching_rule_wins() {
+    let matcher = ProjectMatcher::new(&[
+      rule(1, "lifespan —", "Lifespan", true),
+      rule(2, r"JIRA-\d+", "Tickets", true),
+      rule(3, "(?i)budget", "Finance", true),
+    ]);
+
+    assert_eq!(matcher.project_for("lifespan — main.rs"), Some("Lifespan".to_string()));
+    assert_eq!(matcher.project_for("JIRA-123 lifespan — fix"), Some("Lifespan".to_string()));
+    assert_eq!(matcher.project_for("JIRA-123: Login broken"), Some("Tickets".to_string()));
+    assert_eq!(matcher.project_for("BUDGET.xlsx - Excel"), Some("Finance".to_string()));
+    assert_eq!(matcher.project_for("Inbox"), None);
+  }
+
+  #[test]
+  fn test_disabled_and_invalid_rules_skipped() {
+    let matcher = ProjectMatcher::new(&[
+      rule(1, "inbox", "Mail", false),
+      rule(2, "(unclosed", "Broken", true),
+      rule(3, "notes", "Notes", true),
+    ]);
+
+    assert_eq!(matcher.project_for("inbox"), None);
+    assert_eq!(matcher.project_for("notes.md"), Some("Notes".to_string()));
+  }
+}
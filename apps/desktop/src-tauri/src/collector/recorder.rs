@@ -0,0 +1,475 @@
+//! Tracker event stream recording and replay, for reproducing bugs.
+//!
+//! A developer tool: with the "developer_event_recording" setting on, the
+//! tracking loop can record what the window tracker and idle / power
+//! detection reported for a while. Excluded apps are recorded only as a
+//! placeholder, and titles after redaction and the app's title policy unless
+//! raw titles are requested. A stopped recording is saved encrypted with the
+//! sync key:
+//!
+//! ```text
+//! "LSRECRD1" | nonce (12 bytes) | AES-GCM ciphertext of the Recording JSON
+//! ```
+//!
+//! Replaying feeds the samples through the same change detection, exclusion
+//! and title policy steps as the live loop into a fresh database, so an
+//! aggregation or sync bug seen on one machine can be reproduced anywhere.
+
+use super::projects::ProjectMatcher;
+use super::redaction::TitleRedactor;
+use super::window_tracker::WindowInfo;
+use super::{document, should_skip, window_key};
+use crate::database::{Database, NewEvent, StorageBackend, TitlePolicy};
+use crate::encryption::{CryptoManager, EncryptedData};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+/// Setting that allows recording ("true"); off by default
+pub const DEV_RECORDING_SETTING: &str = "developer_event_recording";
+
+pub const RECORDING_EXTENSION: &str = "lsrec";
+
+pub const MAX_RECORDING_MINUTES: u32 = 8 * 60;
+
+/// App name recorded in place of an excluded app or skipped private window
+const EXCLUDED_APP: &str = "<excluded>";
+
+const RECORDING_MAGIC: &[u8; 8] = b"LSRECRD1";
+const NONCE_LEN: usize = 12;
+
+/// One observation of the tracking loop
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TrackerSample {
+  /// Foreground window as reported by the window tracker
+  Window { window: WindowInfo },
+  /// The user went idle; the AFK period starts at `since`
+  Afk { since: DateTime<Utc> },
+  Suspend { at: DateTime<Utc> },
+  Stopped { at: DateTime<Utc> },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+  pub started_at: DateTime<Utc>,
+  /// Samples after this are not recorded
+  pub until: DateTime<Utc>,
+  /// Set when the recording is stopped
+  pub ended_at: Option<DateTime<Utc>>,
+  /// Titles were recorded before redaction
+  pub raw_titles: bool,
+  pub samples: Vec<TrackerSample>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecordingStatus {
+  pub recording: bool,
+  pub started_at: Option<DateTime<Utc>>,
+  pub until: Option<DateTime<Utc>>,
+  pub raw_titles: bool,
+  pub sample_count: usize,
+}
+
+/// A recording written to disk
+#[derive(Debug, Clone, Serialize)]
+pub struct SavedRecording {
+  pub path: PathBuf,
+  pub sample_count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReplayReport {
+  pub sample_count: usize,
+  pub events_written: usize,
+}
+
+/// A recording replayed into its own database
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayedRecording {
+  pub database_path: PathBuf,
+  #[serde(flatten)]
+  pub report: ReplayReport,
+}
+
+pub fn is_enabled(db: &Database) -> Result<bool> {
+  Ok(db
+    .get_setting(DEV_RECORDING_SETTING)?
+    .is_some_and(|value| value == "true"))
+}
+
+/// Shared between the tracking loop, which records, and the commands that
+/// start and stop it
+#[derive(Clone, Default)]
+pub struct EventRecorder {
+  recording: Arc<Mutex<Option<Recording>>>,
+}
+
+impl EventRecorder {
+  pub fn start(&self, db: &Database, duration_minutes: u32, raw_titles: bool) -> Result<RecordingStatus> {
+    if !is_enabled(db)? {
+      bail!("Event recording is a developer setting and is turned off");
+    }
+    if duration_minutes == 0 || duration_minutes > MAX_RECORDING_MINUTES {
+      bail!("Recording duration must be between 1 and {} minutes", MAX_RECORDING_MINUTES);
+    }
+
+    let mut recording = self.recording.lock().unwrap();
+    if recording.is_some() {
+      bail!("A recording is already running");
+    }
+    let now = Utc::now();
+    *recording = Some(Recording {
+      started_at: now,
+      until: now + chrono::Duration::minutes(duration_minutes as i64),
+      ended_at: None,
+      raw_titles,
+      samples: Vec::new(),
+    });
+    info!("Event recording started for {} minutes", duration_minutes);
+    Ok(status_of(recording.as_ref()))
+  }
+
+  /// Take the current recording; None when not recording
+  pub fn stop(&self) -> Option<Recording> {
+    let mut recording = self.recording.lock().unwrap().take()?;
+    recording.ended_at = Some(Utc::now().min(recording.until));
+    info!("Event recording stopped with {} samples", recording.samples.len());
+    Some(recording)
+  }
+
+  pub fn status(&self) -> RecordingStatus {
+    status_of(self.recording.lock().unwrap().as_ref())
+  }
+
+  pub fn record(&self, sample: TrackerSample) {
+    let mut recording = self.recording.lock().unwrap();
+    if let Some(recording) = recording.as_mut().filter(|recording| Utc::now() < recording.until) {
+      recording.samples.push(sample);
+    }
+  }
+
+  /// Whether a recording is taking samples
+  pub fn is_recording(&self) -> bool {
+    self.recording.lock().unwrap().as_ref().is_some_and(|recording| Utc::now() < recording.until)
+  }
+
+  /// Record a window as captured. An excluded one is recorded only as the
+  /// switch away from the previous window; other titles are redacted and cut
+  /// to the app's title `policy` unless raw titles were requested.
+  pub fn record_window(&self, window: &WindowInfo, excluded: bool, redactor: &TitleRedactor, policy: TitlePolicy) {
+    let mut recording = self.recording.lock().unwrap();
+    if let Some(recording) = recording.as_mut().filter(|recording| Utc::now() < recording.until) {
+      let window = if excluded {
+        WindowInfo {
+          process_name: EXCLUDED_APP.to_string(),
+          window_title: String::new(),
+          timestamp: window.timestamp,
+          fullscreen: window.fullscreen,
+          ..Default::default()
+        }
+      } else if recording.raw_titles {
+        window.clone()
+      } else {
+        let mut window = window.clone();
+        window.window_title = policy.apply(&window.process_name, &redactor.redact(&window.window_title));
+        window
+      };
+      recording.samples.push(TrackerSample::Window { window });
+    }
+  }
+}
+
+fn status_of(recording: Option<&Recording>) -> RecordingStatus {
+  RecordingStatus {
+    recording: recording.is_some(),
+    started_at: recording.map(|recording| recording.started_at),
+    until: recording.map(|recording| recording.until),
+    raw_titles: recording.is_some_and(|recording| recording.raw_titles),
+    sample_count: recording.map_or(0, |recording| recording.samples.len()),
+  }
+}
+
+pub fn encode_recording(crypto: &CryptoManager, recording: &Recording) -> Result<Vec<u8>> {
+  let encrypted = crypto.encrypt(&serde_json::to_vec(recording)?)?;
+
+  let mut bytes = Vec::with_capacity(8 + NONCE_LEN + encrypted.ciphertext.len());
+  bytes.extend_from_slice(RECORDING_MAGIC);
+  bytes.extend_from_slice(&encrypted.nonce);
+  bytes.extend_from_slice(&encrypted.ciphertext);
+  Ok(bytes)
+}
+
+pub fn decode_recording(crypto: &CryptoManager, bytes: &[u8]) -> Result<Recording> {
+  if bytes.len() < 8 + NONCE_LEN || &bytes[..8] != RECORDING_MAGIC {
+    bail!("Not a Lifespan event recording");
+  }
+
+  let encrypted = EncryptedData {
+    nonce: bytes[8..8 + NONCE_LEN].to_vec(),
+    ciphertext: bytes[8 + NONCE_LEN..].to_vec(),
+  };
+  Ok(serde_json::from_slice(&crypto.decrypt(&encrypted)?)?)
+}
+
+/// Close a replayed event at `ended_at`
+fn close_replayed(slot: &mut Option<NewEvent>, ended_at: DateTime<Utc>, events: &mut Vec<NewEvent>) {
+  if let Some(mut event) = slot.take() {
+    event.duration = (ended_at - event.timestamp).num_seconds().clamp(0, i32::MAX as i64) as i32;
+    events.push(event);
+  }
+}
+
+/// Feed a recording through the tracking loop's change detection into `db`
+/// (meant to be a fresh database), using that database's exclusions, title
+/// policies, redaction and project rules
+pub async fn replay(db: &Database, recording: &Recording) -> Result<ReplayReport> {
+  let redactor = TitleRedactor::load(db);
+  let project_matcher = ProjectMatcher::load(db);
+  let mut events = Vec::new();
+  let mut open_event: Option<NewEvent> = None;
+  let mut afk_event: Option<NewEvent> = None;
+  let mut last_window: Option<String> = None;
+
+  for sample in &recording.samples {
+    match sample {
+      TrackerSample::Window { window } => {
+        close_replayed(&mut afk_event, window.timestamp, &mut events);
+
+        let mut window = window.clone();
+        window.project = project_matcher.project_for(&window.window_title);
+        if recording.raw_titles {
+          window.window_title = redactor.redact(&window.window_title);
+        }
+        window.document = document::document_from_title(&window.process_name, &window.window_title);
+
+        let current_window = Some(window_key(&window));
+        if current_window == last_window {
+          continue;
+        }
+        last_window = current_window;
+        close_replayed(&mut open_event, window.timestamp, &mut events);
+        if window.process_name == EXCLUDED_APP || should_skip(db, &window).await {
+          continue;
+        }
+
+        let policy = db.title_policy(&window.process_name).await.unwrap_or(TitlePolicy::None);
+        let title = policy.apply(&window.process_name, &window.window_title);
+        open_event = Some(NewEvent {
+          event_type: "app_usage".to_string(),
+          timestamp: window.timestamp,
+          duration: 0,
+          app_name: window.process_name.clone(),
+          window_title: (!title.is_empty()).then_some(title),
+          url_domain: window.url_domain.clone(),
+          remote_session: window.remote_session,
+        });
+      }
+      TrackerSample::Afk { since } => {
+        close_replayed(&mut open_event, *since, &mut events);
+        last_window = None;
+        if afk_event.is_none() {
+          afk_event = Some(NewEvent {
+            event_type: "afk".to_string(),
+            timestamp: *since,
+            duration: 0,
+            app_name: "system".to_string(),
+            window_title: None,
+            url_domain: None,
+            remote_session: false,
+          });
+        }
+      }
+      TrackerSample::Suspend { at } | TrackerSample::Stopped { at } => {
+        close_replayed(&mut open_event, *at, &mut events);
+        close_replayed(&mut afk_event, *at, &mut events);
+        last_window = None;
+      }
+    }
+  }
+
+  let ended_at = recording.ended_at.unwrap_or(recording.until);
+  close_replayed(&mut open_event, ended_at, &mut events);
+  close_replayed(&mut afk_event, ended_at, &mut events);
+
+  db.insert_events(&events)?;
+  Ok(ReplayReport {
+    sample_count: recording.samples.len(),
+    events_written: events.len(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::RedactionRule;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn window(process_name: &str, title: &str, at: DateTime<Utc>) -> TrackerSample {
+    TrackerSample::Window {
+      window: WindowInfo {
+        process_name: process_name.to_string(),
+        window_title: title.to_string(),
+        timestamp: at,
+        ..Default::default()
+      },
+    }
+  }
+
+  fn recording(samples: Vec<TrackerSample>) -> Recording {
+    let started_at = Utc::now() - chrono::Duration::hours(1);
+    Recording {
+      started_at,
+      until: started_at + chrono::Duration::hours(1),
+      ended_at: Some(started_at + chrono::Duration::minutes(30)),
+      raw_titles: false,
+      samples,
+    }
+  }
+
+  #[test]
+  fn test_recording_requires_developer_setting() {
+    let (db, _temp) = create_test_db();
+    let recorder = EventRecorder::default();
+    assert!(recorder.start(&db, 10, false).is_err());
+
+    db.set_setting(DEV_RECORDING_SETTING, "true").unwrap();
+    assert!(recorder.start(&db, 0, false).is_err());
+    recorder.start(&db, 10, false).unwrap();
+    assert!(recorder.start(&db, 10, false).is_err());
+
+    recorder.record(TrackerSample::Suspend { at: Utc::now() });
+    assert_eq!(recorder.status().sample_count, 1);
+    let recording = recorder.stop().unwrap();
+    assert_eq!(recording.samples.len(), 1);
+    assert!(recording.ended_at.is_some());
+    assert!(!recorder.status().recording);
+  }
+
+  #[test]
+  fn test_window_titles_redacted_unless_raw() {
+    let (db, _temp) = create_test_db();
+    db.set_setting(DEV_RECORDING_SETTING, "true").unwrap();
+    let redactor = TitleRedactor::new(&[RedactionRule {
+      id: 1,
+      pattern: r"\S+@\S+".to_string(),
+      replacement: "[email]".to_string(),
+      enabled: true,
+    }]);
+    let TrackerSample::Window { window } = window("Outlook.exe", "Mail from alice@example.com", Utc::now()) else {
+      unreachable!()
+    };
+    let recorder = EventRecorder::default();
+
+    recorder.start(&db, 10, false).unwrap();
+    recorder.record_window(&window, false, &redactor, TitlePolicy::Full);
+    recorder.record_window(&window, false, &redactor, TitlePolicy::AppName);
+    let samples = recorder.stop().unwrap().samples;
+    let titles: Vec<&str> = samples
+      .iter()
+      .map(|sample| match sample {
+        TrackerSample::Window { window } => window.window_title.as_str(),
+        other => panic!("unexpected sample: {:?}", other),
+      })
+      .collect();
+    assert_eq!(titles, vec!["Mail from [email]", "Outlook.exe"]);
+
+    recorder.start(&db, 10, true).unwrap();
+    recorder.record_window(&window, false, &redactor, TitlePolicy::None);
+    assert_eq!(recorder.stop().unwrap().samples, vec![TrackerSample::Window { window }]);
+  }
+
+  #[tokio::test]
+  async fn test_excluded_apps_are_recorded_as_placeholders() {
+    let (db, _temp) = create_test_db();
+    db.set_setting(DEV_RECORDING_SETTING, "true").unwrap();
+    let TrackerSample::Window { window: secret } = window("Secret.exe", "Hidden", Utc::now()) else {
+      unreachable!()
+    };
+    let recorder = EventRecorder::default();
+
+    // Not even raw titles include an excluded app
+    recorder.start(&db, 10, true).unwrap();
+    recorder.record_window(&secret, true, &TitleRedactor::new(&[]), TitlePolicy::Full);
+    let recording = recorder.stop().unwrap();
+    let TrackerSample::Window { window: recorded } = &recording.samples[0] else {
+      unreachable!()
+    };
+    assert_eq!(recorded.process_name, EXCLUDED_APP);
+    assert!(recorded.window_title.is_empty());
+
+    // It still ends the previous window's event on replay
+    let started = Utc::now() - chrono::Duration::hours(1);
+    let replayed = Recording {
+      samples: vec![
+        window("Code.exe", "main.rs", started),
+        TrackerSample::Window {
+          window: WindowInfo { timestamp: started + chrono::Duration::minutes(5), ..recorded.clone() },
+        },
+        TrackerSample::Stopped { at: started + chrono::Duration::minutes(10) },
+      ],
+      ..recording
+    };
+    assert_eq!(replay(&db, &replayed).await.unwrap().events_written, 1);
+    let events = db.events_between(started - chrono::Duration::minutes(1), Utc::now()).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].duration, 300);
+  }
+
+  #[test]
+  fn test_encode_decode_round_trip() {
+    let crypto = CryptoManager::new(b"test_key_32_bytes_long_123456789").unwrap();
+    let recording = recording(vec![window("Code.exe", "main.rs", Utc::now())]);
+
+    let bytes = encode_recording(&crypto, &recording).unwrap();
+    assert_eq!(decode_recording(&crypto, &bytes).unwrap(), recording);
+
+    let other = CryptoManager::new(b"different_key_32_bytes_123456789").unwrap();
+    assert!(decode_recording(&other, &bytes).is_err());
+    assert!(decode_recording(&crypto, b"not a recording").is_err());
+  }
+
+  #[tokio::test]
+  async fn test_replay_reproduces_events() {
+    let (db, _temp) = create_test_db();
+    db.add_excluded_app("Secret.exe").unwrap();
+    let t = |minutes: i64| Utc::now() - chrono::Duration::hours(1) + chrono::Duration::minutes(minutes);
+    let recording = recording(vec![
+      window("Code.exe", "main.rs", t(0)),
+      window("Code.exe", "main.rs", t(1)),
+      window("chrome.exe", "Docs", t(5)),
+      window("Secret.exe", "Hidden", t(8)),
+      TrackerSample::Afk { since: t(10) },
+      window("Code.exe", "lib.rs", t(20)),
+      TrackerSample::Stopped { at: t(25) },
+    ]);
+
+    let report = replay(&db, &recording).await.unwrap();
+    assert_eq!(report.sample_count, 7);
+    assert_eq!(report.events_written, 4);
+
+    let events = db.events_between(t(-1), t(30)).unwrap();
+    let summary: Vec<(&str, &str, i32)> = events
+      .iter()
+      .map(|event| (event.event_type.as_str(), event.app_name.as_str(), event.duration))
+      .collect();
+    assert_eq!(
+      summary,
+      vec![
+        ("app_usage", "Code.exe", 300),
+        ("app_usage", "chrome.exe", 180),
+        ("afk", "system", 600),
+        ("app_usage", "Code.exe", 300),
+      ]
+    );
+  }
+}
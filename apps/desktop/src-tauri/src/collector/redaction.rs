@@ -0,0 +1,119 @@
+//! Applies the user's title redaction rules before events are stored.
+//!
+//! Enabled rules are compiled once when the collector starts. A RegexSet
+//! finds which rules match a title in a single pass; only those are then
+//! applied, in rule order. Rules whose pattern no longer compiles are skipped.
+
+use crate::database::{Database, RedactionRule};
+use regex::{Regex, RegexSet};
+use tracing::error;
+
+#[derive(Debug, Clone)]
+pub struct TitleRedactor {
+  set: RegexSet,
+  rules: Vec<(Regex, String)>,
+}
+
+impl Default for TitleRedactor {
+  fn default() -> Self {
+    Self {
+      set: RegexSet::empty(),
+      rules: Vec::new(),
+    }
+  }
+}
+
+impl TitleRedactor {
+  pub fn new(rules: &[RedactionRule]) -> Self {
+    let compiled: Vec<(Regex, String)> = rules
+      .iter()
+      .filter(|rule| rule.enabled)
+      .filter_map(|rule| match Regex::new(&rule.pattern) {
+        Ok(regex) => Some((regex, rule.replacement.clone())),
+        Err(e) => {
+          error!("Skipping redaction rule {}: {}", rule.id, e);
+          None
+        }
+      })
+      .collect();
+
+    match RegexSet::new(compiled.iter().map(|(regex, _)| regex.as_str())) {
+      Ok(set) => Self { set, rules: compiled },
+      Err(e) => {
+        error!("Failed to compile redaction rules: {}", e);
+        Self::default()
+      }
+    }
+  }
+
+  /// Redactor for the rules stored in `db`; errors leave titles unredacted
+  pub fn load(db: &Database) -> Self {
+    match db.get_redaction_rules() {
+      Ok(rules) => Self::new(&rules),
+      Err(e) => {
+        error!("Failed to load redaction rules: {}", e);
+        Self::default()
+      }
+    }
+  }
+
+  pub fn redact(&self, title: &str) -> String {
+    let matches = self.set.matches(title);
+    if !matches.matched_any() {
+      return title.to_string();
+    }
+
+    let mut redacted = title.to_string();
+    for index in matches.iter() {
+      let (regex, replacement) = &self.rules[index];
+      redacted = regex.replace_all(&redacted, replacement.as_str()).into_owned();
+    }
+    redacted
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rule(id: i64, pattern: &str, replacement: &str, enabled: bool) -> RedactionRule {
+    RedactionRule {
+      id,
+      pattern: pattern.to_string(),
+      replacement: replacement.to_string(),
+      enabled,
+    }
+  }
+
+  #[test]
+  fn test_matching_rules_applied_in_order() {
+    let redactor = TitleRedactor::new(&[
+      rule(1, r"[\w.]+@[\w.]+", "[email]", true),
+      rule(2, r"(?i)invoice #(\d+)", "invoice #…", true),
+      rule(3, r"secret", "[hidden]", false),
+    ]);
+
+    assert_eq!(
+      redactor.redact("Inbox - alice@example.com - Invoice #4411 - secret"),
+      "Inbox - [email] - invoice #… - secret"
+    );
+    assert_eq!(redactor.redact("main.rs - lifespan"), "main.rs - lifespan");
+  }
+
+  #[test]
+  fn test_capture_groups_in_replacement() {
+    let redactor = TitleRedactor::new(&[rule(1, r"(PROJ)-\d+", "$1-xxx", true)]);
+    assert_eq!(redactor.redact("PROJ-123: fix login"), "PROJ-xxx: fix login");
+  }
+
+  #[test]
+  fn test_invalid_rule_skipped() {
+    let redactor = TitleRedactor::new(&[rule(1, "(unclosed", "x", true), rule(2, "token=\\w+", "token=…", true)]);
+    assert_eq!(redactor.redact("callback?token=abc123"), "callback?token=…");
+  }
+
+  #[test]
+  fn test_no_rules_leaves_title_unchanged() {
+    assert_eq!(TitleRedactor::default().redact("Anything at all"), "Anything at all");
+  }
+}
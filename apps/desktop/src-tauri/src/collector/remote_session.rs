@@ -0,0 +1,84 @@
+//! Remote desktop / VM viewer detection.
+//!
+//! When the foreground process is an RDP, Citrix, VNC or VM console client the
+//! process name (e.g. mstsc.exe) says nothing about what is actually being used,
+//! so such events are tagged with a `remote_session` flag.
+
+/// Process names (lowercase, without extension) of remote desktop and VM viewers
+const REMOTE_VIEWER_PROCESSES: &[&str] = &[
+  // Microsoft Remote Desktop
+  "mstsc",
+  "msrdc",
+  "microsoft remote desktop",
+  "windows app",
+  "remmina",
+  "xfreerdp",
+  // Citrix
+  "wfica32",
+  "cdviewer",
+  "citrix viewer",
+  // VNC and remote support tools
+  "vncviewer",
+  "tvnviewer",
+  "anydesk",
+  "teamviewer",
+  "rustdesk",
+  // VM consoles
+  "vmconnect",
+  "vmware",
+  "vmware-vmx",
+  "vmware fusion",
+  "virtualboxvm",
+  "prl_client_app",
+  "parallels desktop",
+  "virt-viewer",
+  "remote-viewer",
+];
+
+/// Whether the foreground process is a remote desktop or VM viewer
+pub fn is_remote_viewer(process_name: &str) -> bool {
+  let name = process_name.trim().to_lowercase();
+  let name = name.strip_suffix(".exe").unwrap_or(&name);
+  REMOTE_VIEWER_PROCESSES.contains(&name)
+}
+
+/// Whether activity in `process_name` is happening in a remote session, either
+/// through a viewer window or because this app itself runs inside an RDP session
+pub fn is_remote_session(process_name: &str) -> bool {
+  is_remote_viewer(process_name) || running_in_remote_session()
+}
+
+#[cfg(windows)]
+fn running_in_remote_session() -> bool {
+  use windows::Win32::UI::WindowsAndMessaging::{GetSystemMetrics, SM_REMOTESESSION};
+
+  unsafe { GetSystemMetrics(SM_REMOTESESSION) != 0 }
+}
+
+#[cfg(not(windows))]
+fn running_in_remote_session() -> bool {
+  false
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_detects_remote_viewers() {
+    assert!(is_remote_viewer("mstsc.exe"));
+    assert!(is_remote_viewer("MSTSC.EXE"));
+    assert!(is_remote_viewer("wfica32.exe"));
+    assert!(is_remote_viewer("vmconnect.exe"));
+    assert!(is_remote_viewer("Citrix Viewer"));
+    assert!(is_remote_viewer("remmina"));
+  }
+
+  #[test]
+  fn test_ignores_local_apps() {
+    assert!(!is_remote_viewer("chrome.exe"));
+    assert!(!is_remote_viewer("code"));
+    assert!(!is_remote_viewer("mstsc-notes.exe"));
+    assert!(!is_remote_viewer(""));
+  }
+}
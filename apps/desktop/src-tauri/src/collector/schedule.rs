@@ -0,0 +1,174 @@
+//! Weekly tracking windows (e.g. Mon–Fri 09:00–18:00).
+//!
+//! The schedule is stored as JSON in a (versioned) setting. An empty schedule
+//! means tracking is always on. A window whose end is before its start runs
+//! past midnight and belongs to the day it starts on.
+
+use crate::database::Database;
+use anyhow::{bail, Result};
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+pub const TRACKING_SCHEDULE_SETTING: &str = "tracking_schedule";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackingWindow {
+  /// Days the window starts on, e.g. ["Mon", "Tue"]
+  pub days: Vec<Weekday>,
+  /// Local start time, "HH:MM"
+  pub start: String,
+  /// Local end time, "HH:MM" (exclusive)
+  pub end: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackingSchedule {
+  pub windows: Vec<TrackingWindow>,
+}
+
+fn parse_time(value: &str) -> Option<NaiveTime> {
+  NaiveTime::parse_from_str(value, "%H:%M").ok()
+}
+
+impl TrackingWindow {
+  fn validate(&self) -> Result<()> {
+    if self.days.is_empty() {
+      bail!("Tracking window needs at least one day");
+    }
+    let (Some(start), Some(end)) = (parse_time(&self.start), parse_time(&self.end)) else {
+      bail!("Invalid tracking window {}-{}, expected HH:MM", self.start, self.end);
+    };
+    if start == end {
+      bail!("Tracking window {}-{} is empty", self.start, self.end);
+    }
+    Ok(())
+  }
+
+  fn contains(&self, at: NaiveDateTime) -> bool {
+    let (Some(start), Some(end)) = (parse_time(&self.start), parse_time(&self.end)) else {
+      return false;
+    };
+    let time = at.time();
+
+    if start < end {
+      self.days.contains(&at.weekday()) && time >= start && time < end
+    } else {
+      // Overnight: the evening part on a listed day, or the morning part after one
+      let yesterday = (at - Duration::days(1)).weekday();
+      (self.days.contains(&at.weekday()) && time >= start) || (self.days.contains(&yesterday) && time < end)
+    }
+  }
+}
+
+impl TrackingSchedule {
+  pub fn validate(&self) -> Result<()> {
+    self.windows.iter().try_for_each(TrackingWindow::validate)
+  }
+
+  /// Whether tracking should run at local time `at`
+  pub fn is_active_at(&self, at: NaiveDateTime) -> bool {
+    self.windows.is_empty() || self.windows.iter().any(|window| window.contains(at))
+  }
+
+  /// Stored schedule; a missing or unreadable one means always on
+  pub fn load(db: &Database) -> Result<Self> {
+    let Some(json) = db.get_setting(TRACKING_SCHEDULE_SETTING)? else {
+      return Ok(Self::default());
+    };
+    Ok(serde_json::from_str(&json).unwrap_or_else(|e| {
+      error!("Ignoring unreadable tracking schedule: {}", e);
+      Self::default()
+    }))
+  }
+
+  pub fn save(&self, db: &Database) -> Result<()> {
+    self.validate()?;
+    db.set_setting(TRACKING_SCHEDULE_SETTING, &serde_json::to_string(self)?)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::NaiveDate;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn workdays(start: &str, end: &str) -> TrackingWindow {
+    TrackingWindow {
+      days: vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri],
+      start: start.to_string(),
+      end: end.to_string(),
+    }
+  }
+
+  /// 2024-06-10 is a Monday
+  fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2024, 6, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+  }
+
+  #[test]
+  fn test_empty_schedule_is_always_active() {
+    assert!(TrackingSchedule::default().is_active_at(at(15, 3, 0)));
+  }
+
+  #[test]
+  fn test_work_hours() {
+    let schedule = TrackingSchedule { windows: vec![workdays("09:00", "18:00")] };
+
+    assert!(schedule.is_active_at(at(10, 9, 0)));
+    assert!(schedule.is_active_at(at(14, 17, 59)));
+    assert!(!schedule.is_active_at(at(10, 8, 59)));
+    assert!(!schedule.is_active_at(at(10, 18, 0)));
+    // Saturday
+    assert!(!schedule.is_active_at(at(15, 12, 0)));
+  }
+
+  #[test]
+  fn test_overnight_window() {
+    let schedule = TrackingSchedule {
+      windows: vec![TrackingWindow {
+        days: vec![Weekday::Fri],
+        start: "22:00".to_string(),
+        end: "02:00".to_string(),
+      }],
+    };
+
+    assert!(schedule.is_active_at(at(14, 23, 0)));
+    // Early Saturday belongs to Friday's window
+    assert!(schedule.is_active_at(at(15, 1, 30)));
+    assert!(!schedule.is_active_at(at(15, 2, 0)));
+    // Early Friday would belong to Thursday's window
+    assert!(!schedule.is_active_at(at(14, 1, 0)));
+  }
+
+  #[test]
+  fn test_validation() {
+    assert!(TrackingSchedule { windows: vec![workdays("9am", "18:00")] }.validate().is_err());
+    assert!(TrackingSchedule { windows: vec![workdays("09:00", "09:00")] }.validate().is_err());
+
+    let mut no_days = workdays("09:00", "18:00");
+    no_days.days.clear();
+    assert!(TrackingSchedule { windows: vec![no_days] }.validate().is_err());
+  }
+
+  #[test]
+  fn test_save_and_load() {
+    let (db, _temp) = create_test_db();
+    assert_eq!(TrackingSchedule::load(&db).unwrap(), TrackingSchedule::default());
+
+    let schedule = TrackingSchedule { windows: vec![workdays("09:00", "18:00")] };
+    schedule.save(&db).unwrap();
+    assert_eq!(TrackingSchedule::load(&db).unwrap(), schedule);
+
+    let invalid = TrackingSchedule { windows: vec![workdays("25:00", "18:00")] };
+    assert!(invalid.save(&db).is_err());
+    assert_eq!(TrackingSchedule::load(&db).unwrap(), schedule);
+  }
+}
@@ -0,0 +1,279 @@
+//! Periodic "what are you doing?" prompts for combined automatic and manual tracking.
+//!
+//! The collector feeds an ActivityMeter while the user is active. Once the
+//! configured number of active minutes has passed (outside quiet hours), the
+//! UI is sent a prompt naming the current block; the one-line answer is
+//! stored as an annotation on that event.
+
+use crate::database::Database;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tracing::{debug, error};
+
+pub const SELF_REPORT_SETTING: &str = "self_report";
+
+/// Tauri event the UI listens on to show the prompt
+pub const SELF_REPORT_EVENT: &str = "self-report-prompt";
+
+/// Activity samples further apart than this are not counted as continuous activity
+const ACTIVITY_GAP: Duration = Duration::from_secs(15);
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+  /// Local time, "HH:MM"; may be later than `end` to span midnight
+  pub start: String,
+  /// Local time, "HH:MM" (exclusive)
+  pub end: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelfReportSettings {
+  pub enabled: bool,
+  /// Minutes of activity between prompts
+  pub interval_minutes: u32,
+  #[serde(default)]
+  pub quiet_hours: Option<QuietHours>,
+}
+
+impl Default for SelfReportSettings {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      interval_minutes: 60,
+      quiet_hours: None,
+    }
+  }
+}
+
+fn parse_time(value: &str) -> Option<NaiveTime> {
+  NaiveTime::parse_from_str(value, "%H:%M").ok()
+}
+
+impl QuietHours {
+  fn contains(&self, time: NaiveTime) -> bool {
+    let (Some(start), Some(end)) = (parse_time(&self.start), parse_time(&self.end)) else {
+      return false;
+    };
+    if start <= end {
+      time >= start && time < end
+    } else {
+      time >= start || time < end
+    }
+  }
+}
+
+impl SelfReportSettings {
+  pub fn validate(&self) -> Result<()> {
+    if !(5..=24 * 60).contains(&self.interval_minutes) {
+      bail!("Prompt interval must be between 5 minutes and 24 hours");
+    }
+    if let Some(quiet) = &self.quiet_hours {
+      if parse_time(&quiet.start).is_none() || parse_time(&quiet.end).is_none() {
+        bail!("Invalid quiet hours {}-{}, expected HH:MM", quiet.start, quiet.end);
+      }
+    }
+    Ok(())
+  }
+
+  pub fn load(db: &Database) -> Result<Self> {
+    let Some(json) = db.get_setting(SELF_REPORT_SETTING)? else {
+      return Ok(Self::default());
+    };
+    Ok(serde_json::from_str(&json).unwrap_or_else(|e| {
+      error!("Ignoring unreadable self-report settings: {}", e);
+      Self::default()
+    }))
+  }
+
+  pub fn save(&self, db: &Database) -> Result<()> {
+    self.validate()?;
+    db.set_setting(SELF_REPORT_SETTING, &serde_json::to_string(self)?)
+  }
+}
+
+/// Sent to the UI when a self-report is due
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SelfReportPrompt {
+  /// Event the answer should be attached to
+  pub event_id: Option<String>,
+  pub active_minutes: u32,
+  pub prompted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct MeterState {
+  active: Duration,
+  last_seen: Option<DateTime<Utc>>,
+  current_event: Option<String>,
+}
+
+/// Active time since the last prompt, fed by the collector loop
+#[derive(Debug, Clone, Default)]
+pub struct ActivityMeter {
+  state: Arc<Mutex<MeterState>>,
+}
+
+impl ActivityMeter {
+  /// The user was active at `now` in `event_id`
+  pub fn record(&self, now: DateTime<Utc>, event_id: Option<&str>) {
+    let mut state = self.state.lock().unwrap();
+    if let Some(last_seen) = state.last_seen {
+      if let Ok(elapsed) = (now - last_seen).to_std() {
+        if elapsed <= ACTIVITY_GAP {
+          state.active += elapsed;
+        }
+      }
+    }
+    state.last_seen = Some(now);
+    state.current_event = event_id.map(str::to_string);
+  }
+
+  /// A prompt if enough activity has accumulated outside quiet hours; resets the meter
+  pub fn take_due_prompt(
+    &self,
+    settings: &SelfReportSettings,
+    now: DateTime<Utc>,
+    local_time: NaiveTime,
+  ) -> Option<SelfReportPrompt> {
+    if !settings.enabled || settings.quiet_hours.as_ref().is_some_and(|quiet| quiet.contains(local_time)) {
+      return None;
+    }
+
+    let mut state = self.state.lock().unwrap();
+    if state.active < Duration::from_secs(settings.interval_minutes as u64 * 60) {
+      return None;
+    }
+
+    let active_minutes = (state.active.as_secs() / 60) as u32;
+    state.active = Duration::ZERO;
+    Some(SelfReportPrompt {
+      event_id: state.current_event.clone(),
+      active_minutes,
+      prompted_at: now,
+    })
+  }
+}
+
+/// Check every CHECK_INTERVAL whether a prompt is due and send it to the UI
+pub fn start_prompt_scheduler(db: Arc<Database>, meter: ActivityMeter, app: AppHandle) {
+  tauri::async_runtime::spawn(async move {
+    let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+      ticker.tick().await;
+
+      let settings = match SelfReportSettings::load(&db) {
+        Ok(settings) => settings,
+        Err(e) => {
+          error!("Failed to load self-report settings: {}", e);
+          continue;
+        }
+      };
+      if let Some(prompt) = meter.take_due_prompt(&settings, Utc::now(), Local::now().time()) {
+        debug!("Self-report due after {} active minutes", prompt.active_minutes);
+        if let Err(e) = app.emit(SELF_REPORT_EVENT, &prompt) {
+          error!("Failed to send self-report prompt: {}", e);
+        }
+      }
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::TimeZone;
+  use tempfile::NamedTempFile;
+
+  fn settings(interval_minutes: u32, quiet_hours: Option<(&str, &str)>) -> SelfReportSettings {
+    SelfReportSettings {
+      enabled: true,
+      interval_minutes,
+      quiet_hours: quiet_hours.map(|(start, end)| QuietHours {
+        start: start.to_string(),
+        end: end.to_string(),
+      }),
+    }
+  }
+
+  fn time(hour: u32, minute: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+  }
+
+  /// Record activity every 5 seconds for `minutes`
+  fn feed(meter: &ActivityMeter, start: DateTime<Utc>, minutes: i64, event_id: &str) -> DateTime<Utc> {
+    let mut now = start;
+    for _ in 0..=(minutes * 12) {
+      meter.record(now, Some(event_id));
+      now += chrono::Duration::seconds(5);
+    }
+    now - chrono::Duration::seconds(5)
+  }
+
+  #[test]
+  fn test_prompt_after_active_interval() {
+    let meter = ActivityMeter::default();
+    let start = Utc.with_ymd_and_hms(2024, 6, 10, 9, 0, 0).unwrap();
+    let settings = settings(30, None);
+
+    let now = feed(&meter, start, 20, "a");
+    assert!(meter.take_due_prompt(&settings, now, time(9, 20)).is_none());
+
+    let now = feed(&meter, now, 10, "b");
+    let prompt = meter.take_due_prompt(&settings, now, time(9, 30)).unwrap();
+    assert_eq!(prompt.event_id.as_deref(), Some("b"));
+    assert_eq!(prompt.active_minutes, 30);
+
+    // The meter restarts after a prompt
+    assert!(meter.take_due_prompt(&settings, now, time(9, 30)).is_none());
+  }
+
+  #[test]
+  fn test_idle_gaps_do_not_count() {
+    let meter = ActivityMeter::default();
+    let start = Utc.with_ymd_and_hms(2024, 6, 10, 9, 0, 0).unwrap();
+
+    meter.record(start, Some("a"));
+    // An hour away from the keyboard
+    let later = start + chrono::Duration::hours(1);
+    meter.record(later, Some("a"));
+
+    assert!(meter.take_due_prompt(&settings(5, None), later, time(10, 0)).is_none());
+  }
+
+  #[test]
+  fn test_quiet_hours_and_disabled() {
+    let meter = ActivityMeter::default();
+    let start = Utc.with_ymd_and_hms(2024, 6, 10, 22, 0, 0).unwrap();
+    let now = feed(&meter, start, 10, "a");
+
+    let overnight = settings(5, Some(("21:00", "07:00")));
+    assert!(meter.take_due_prompt(&overnight, now, time(22, 10)).is_none());
+    assert!(meter.take_due_prompt(&overnight, now, time(6, 59)).is_none());
+
+    let disabled = SelfReportSettings { enabled: false, ..settings(5, None) };
+    assert!(meter.take_due_prompt(&disabled, now, time(12, 0)).is_none());
+
+    // Activity during quiet hours is still owed a prompt afterwards
+    assert!(meter.take_due_prompt(&overnight, now, time(7, 0)).is_some());
+  }
+
+  #[test]
+  fn test_settings_validation_and_storage() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    assert_eq!(SelfReportSettings::load(&db).unwrap(), SelfReportSettings::default());
+
+    assert!(settings(1, None).save(&db).is_err());
+    assert!(settings(30, Some(("9pm", "07:00"))).save(&db).is_err());
+
+    let valid = settings(45, Some(("21:00", "07:00")));
+    valid.save(&db).unwrap();
+    assert_eq!(SelfReportSettings::load(&db).unwrap(), valid);
+  }
+}
@@ -1,7 +1,14 @@
+use super::browser;
+use super::capture_helper::CaptureHelper;
+use super::idle_detector::IdleDetector;
+use super::remote_session::is_remote_session;
 use anyhow::Result;
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use thiserror::Error;
+use tokio::sync::mpsc::UnboundedReceiver;
 
 #[derive(Debug, Error)]
 pub enum WindowTrackerError {
@@ -9,24 +16,111 @@ pub enum WindowTrackerError {
   NoActiveWindow,
   #[error("Process query failed: {0}")]
   ProcessQueryFailed(String),
+  #[error("Display server error: {0}")]
+  DisplayServer(String),
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
 pub struct WindowInfo {
   pub process_name: String,
   pub window_title: String,
   pub timestamp: chrono::DateTime<chrono::Utc>,
+  /// Active tab's domain when the window is a browser and capture is enabled
+  #[serde(default)]
+  pub url_domain: Option<String>,
+  /// A full-screen app (exclusive-mode game, video, presentation) has the foreground
+  #[serde(default)]
+  pub fullscreen: bool,
+  /// File open in a known editor, parsed from the redacted title by the tracking loop
+  #[serde(default)]
+  pub document: Option<String>,
+  /// Label from the user's project rules, matched against the redacted title by the tracking loop
+  #[serde(default)]
+  pub project: Option<String>,
+  /// Virtual desktop the window is on: the desktop GUID on Windows, the
+  /// 1-based workspace number on Linux; None where it can't be read
+  #[serde(default)]
+  pub virtual_desktop: Option<String>,
+  /// Full path of the process's executable, when the platform exposes it
+  #[serde(default)]
+  pub process_path: Option<String>,
+  /// The window is a remote desktop or VM viewer, or this machine is itself
+  /// used over remote desktop; checked where the window is captured
+  #[serde(default)]
+  pub remote_session: bool,
 }
 
-pub struct WindowTracker;
+#[derive(Clone)]
+pub struct WindowTracker {
+  helper: Option<Arc<CaptureHelper>>,
+  browser_domains: Arc<AtomicBool>,
+}
 
 impl WindowTracker {
+  /// Capture windows in-process
   pub fn new() -> Result<Self> {
-    Ok(Self)
+    Ok(Self {
+      helper: None,
+      browser_domains: Arc::new(AtomicBool::new(false)),
+    })
+  }
+
+  /// Capture windows through a separate helper process, so this process
+  /// never needs to query other applications' windows itself
+  pub fn isolated() -> Self {
+    Self {
+      helper: Some(Arc::new(CaptureHelper::new())),
+      browser_domains: Arc::new(AtomicBool::new(false)),
+    }
+  }
+
+  /// Enable or disable reading the active browser tab's domain (off by default)
+  pub fn set_browser_domain_capture(&self, enabled: bool) {
+    self.browser_domains.store(enabled, Ordering::Relaxed);
+  }
+
+  fn captures_browser_domains(&self) -> bool {
+    self.browser_domains.load(Ordering::Relaxed)
+  }
+
+  /// Input idle detection through the same process windows are captured in
+  pub fn idle_detector(&self) -> IdleDetector {
+    match &self.helper {
+      Some(helper) => IdleDetector::isolated(helper.clone()),
+      None => IdleDetector::default(),
+    }
+  }
+
+  /// Foreground change notifications, when capture runs in the helper (the
+  /// only place the hook is installed); None means poll
+  pub fn foreground_changes(&self) -> Option<UnboundedReceiver<()>> {
+    self.helper.as_ref().map(|helper| helper.foreground_changes())
   }
 
-  #[cfg(windows)]
   pub fn get_active_window_info(&self) -> Result<WindowInfo> {
+    match &self.helper {
+      Some(helper) => helper.get_active_window_info(self.captures_browser_domains()),
+      None => self.capture_active_window(),
+    }
+  }
+
+  /// Sanitized domain of the active tab, if enabled and the process is a browser
+  #[cfg_attr(not(any(windows, target_os = "macos")), allow(dead_code))]
+  fn browser_domain(
+    &self,
+    process_name: &str,
+    sanitized_title: &str,
+    read_url: impl FnOnce() -> Option<String>,
+  ) -> Option<String> {
+    if !self.captures_browser_domains() || !browser::is_browser(process_name) {
+      return None;
+    }
+    let domain = read_url().and_then(|url| browser::domain_from_url(&url));
+    browser::sanitize_domain(domain, sanitized_title)
+  }
+
+  #[cfg(windows)]
+  fn capture_active_window(&self) -> Result<WindowInfo> {
     use windows::Win32::System::ProcessStatus::GetModuleBaseNameW;
     use windows::Win32::System::Threading::OpenProcess;
     use windows::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowTextW};
@@ -59,6 +153,7 @@ impl WindowTracker {
         &mut name_buffer,
       );
       let process_name = String::from_utf16_lossy(&name_buffer[..len as usize]);
+      let process_path = Self::process_image_path(handle);
 
       // Get window title
       let mut title_buffer = [0u16; 512];
@@ -66,19 +161,208 @@ impl WindowTracker {
       let window_title = String::from_utf16_lossy(&title_buffer[..len as usize]);
 
       // Sanitize window title for privacy
-      let window_title = Self::sanitize_title(&window_title);
+      let window_title = Self::sanitize_window_title(&process_name, &window_title, || browser::has_private_badge(hwnd));
+      let url_domain = self.browser_domain(&process_name, &window_title, || browser::active_tab_url(hwnd));
+      let remote_session = is_remote_session(&process_name);
 
       Ok(WindowInfo {
         process_name,
         window_title,
         timestamp: Utc::now(),
+        url_domain,
+        fullscreen: Self::foreground_is_fullscreen(),
+        document: None,
+        project: None,
+        virtual_desktop: Self::window_desktop(hwnd),
+        process_path,
+        remote_session,
       })
     }
   }
 
-  #[cfg(not(windows))]
-  pub fn get_active_window_info(&self) -> Result<WindowInfo> {
-    Err("Window tracking is only supported on Windows".into())
+  /// Full Win32 path of the process's executable
+  #[cfg(windows)]
+  fn process_image_path(handle: windows::Win32::Foundation::HANDLE) -> Option<String> {
+    use windows::core::PWSTR;
+    use windows::Win32::System::Threading::{QueryFullProcessImageNameW, PROCESS_NAME_WIN32};
+
+    let mut path_buffer = [0u16; 1024];
+    let mut len = path_buffer.len() as u32;
+    unsafe { QueryFullProcessImageNameW(handle, PROCESS_NAME_WIN32, PWSTR(path_buffer.as_mut_ptr()), &mut len) }.ok()?;
+    Some(String::from_utf16_lossy(&path_buffer[..len as usize]))
+  }
+
+  /// The shell's notification state reports exclusive Direct3D full-screen
+  /// apps, and "busy" for other full-screen windows on the primary display
+  #[cfg(windows)]
+  fn foreground_is_fullscreen() -> bool {
+    use windows::Win32::UI::Shell::{
+      SHQueryUserNotificationState, QUNS_BUSY, QUNS_PRESENTATION_MODE, QUNS_RUNNING_D3D_FULL_SCREEN,
+    };
+
+    unsafe { SHQueryUserNotificationState() }
+      .is_ok_and(|state| state == QUNS_RUNNING_D3D_FULL_SCREEN || state == QUNS_BUSY || state == QUNS_PRESENTATION_MODE)
+  }
+
+  /// The virtual desktop GUID the window belongs to, from the shell's
+  /// IVirtualDesktopManager
+  #[cfg(windows)]
+  fn window_desktop(hwnd: windows::Win32::Foundation::HWND) -> Option<String> {
+    use windows::core::GUID;
+    use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_MULTITHREADED};
+    use windows::Win32::UI::Shell::{IVirtualDesktopManager, VirtualDesktopManager};
+
+    unsafe {
+      // S_FALSE / RPC_E_CHANGED_MODE just mean COM is already initialized on this thread
+      let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+      let manager: IVirtualDesktopManager = CoCreateInstance(&VirtualDesktopManager, None, CLSCTX_ALL).ok()?;
+      let desktop = manager.GetWindowDesktopId(hwnd).ok()?;
+      // Windows that belong to no particular desktop (pinned, shell UI) report the zero GUID
+      (desktop != GUID::zeroed()).then(|| format!("{:?}", desktop))
+    }
+  }
+
+  #[cfg(target_os = "macos")]
+  fn capture_active_window(&self) -> Result<WindowInfo> {
+    use core_foundation::base::{CFType, TCFType};
+    use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
+    use core_foundation::number::CFNumber;
+    use core_foundation::string::CFString;
+    use core_graphics::window::{
+      copy_window_info, kCGNullWindowID, kCGWindowListExcludeDesktopElements,
+      kCGWindowListOptionOnScreenOnly,
+    };
+
+    // Window list is returned in front-to-back order
+    let windows = copy_window_info(
+      kCGWindowListOptionOnScreenOnly | kCGWindowListExcludeDesktopElements,
+      kCGNullWindowID,
+    )
+    .ok_or(WindowTrackerError::NoActiveWindow)?;
+
+    let layer_key = CFString::from_static_string("kCGWindowLayer");
+    let pid_key = CFString::from_static_string("kCGWindowOwnerPID");
+    let owner_key = CFString::from_static_string("kCGWindowOwnerName");
+    let name_key = CFString::from_static_string("kCGWindowName");
+
+    for item in windows.iter() {
+      let dict: CFDictionary<CFString, CFType> =
+        unsafe { CFDictionary::wrap_under_get_rule(*item as CFDictionaryRef) };
+
+      // Layer 0 is the normal application layer; skip menu bar, dock, overlays
+      let layer = dict
+        .find(&layer_key)
+        .and_then(|v| v.downcast::<CFNumber>())
+        .and_then(|n| n.to_i64())
+        .unwrap_or(-1);
+      if layer != 0 {
+        continue;
+      }
+
+      let owner_name = dict
+        .find(&owner_key)
+        .and_then(|v| v.downcast::<CFString>())
+        .map(|s| s.to_string())
+        .unwrap_or_default();
+
+      let pid = dict
+        .find(&pid_key)
+        .and_then(|v| v.downcast::<CFNumber>())
+        .and_then(|n| n.to_i32());
+
+      let process_path = pid.and_then(Self::process_path_for_pid);
+      let process_name = process_path
+        .as_deref()
+        .and_then(|path| std::path::Path::new(path).file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or(owner_name);
+
+      if process_name.is_empty() {
+        return Err(WindowTrackerError::ProcessQueryFailed("Unknown window owner".to_string()).into());
+      }
+
+      // kCGWindowName is only populated with the Screen Recording permission
+      let window_title = dict
+        .find(&name_key)
+        .and_then(|v| v.downcast::<CFString>())
+        .map(|s| s.to_string())
+        .unwrap_or_default();
+
+      // Sanitize window title for privacy
+      let window_title = Self::sanitize_window_title(&process_name, &window_title, || false);
+      let url_domain = self.browser_domain(&process_name, &window_title, || pid.and_then(browser::active_tab_url));
+      let remote_session = is_remote_session(&process_name);
+
+      return Ok(WindowInfo {
+        process_name,
+        window_title,
+        timestamp: Utc::now(),
+        url_domain,
+        fullscreen: false,
+        document: None,
+        project: None,
+        virtual_desktop: None,
+        process_path,
+        remote_session,
+      });
+    }
+
+    Err(WindowTrackerError::NoActiveWindow.into())
+  }
+
+  /// Resolve the executable path of a process (e.g. "/Applications/Safari.app/Contents/MacOS/Safari")
+  #[cfg(target_os = "macos")]
+  fn process_path_for_pid(pid: i32) -> Option<String> {
+    let mut path_buffer = [0u8; libc::PROC_PIDPATHINFO_MAXSIZE as usize];
+    let len = unsafe {
+      libc::proc_pidpath(
+        pid,
+        path_buffer.as_mut_ptr() as *mut libc::c_void,
+        path_buffer.len() as u32,
+      )
+    };
+    if len <= 0 {
+      return None;
+    }
+
+    Some(String::from_utf8_lossy(&path_buffer[..len as usize]).into_owned())
+  }
+
+  #[cfg(target_os = "linux")]
+  fn capture_active_window(&self) -> Result<WindowInfo> {
+    let (process_name, window_title, process_path) = super::linux::active_window()?;
+
+    // Sanitize window title for privacy
+    let window_title = Self::sanitize_window_title(&process_name, &window_title, || false);
+    let remote_session = is_remote_session(&process_name);
+
+    Ok(WindowInfo {
+      process_name,
+      window_title,
+      timestamp: Utc::now(),
+      url_domain: None,
+      fullscreen: false,
+      document: None,
+      project: None,
+      virtual_desktop: super::linux::current_workspace().map(|workspace| workspace.to_string()),
+      process_path,
+      remote_session,
+    })
+  }
+
+  #[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+  fn capture_active_window(&self) -> Result<WindowInfo> {
+    Err(anyhow::anyhow!("Window tracking is not supported on this platform"))
+  }
+
+  /// Title pipeline: private/incognito browser windows are labelled (title
+  /// heuristics, then the platform's badge check), everything else is sanitized
+  #[cfg_attr(not(any(windows, target_os = "macos", target_os = "linux")), allow(dead_code))]
+  fn sanitize_window_title(process_name: &str, title: &str, has_private_badge: impl FnOnce() -> bool) -> String {
+    if browser::is_browser(process_name) && (browser::is_private_title(title) || has_private_badge()) {
+      return browser::PRIVATE_BROWSING_TITLE.to_string();
+    }
+    Self::sanitize_title(title)
   }
 
   fn sanitize_title(title: &str) -> String {
@@ -105,12 +389,6 @@ impl WindowTracker {
   }
 }
 
-impl Clone for WindowTracker {
-  fn clone(&self) -> Self {
-    Self
-  }
-}
-
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -184,6 +462,27 @@ mod tests {
     assert_eq!(WindowTracker::sanitize_title("\nFinance\n\n"), "[Protected App]");
   }
 
+  #[test]
+  fn test_sanitize_window_title_labels_private_browser_windows() {
+    assert_eq!(
+      WindowTracker::sanitize_window_title("chrome.exe", "Inbox - Google Chrome (Incognito)", || false),
+      "[Private Browsing]"
+    );
+    assert_eq!(
+      WindowTracker::sanitize_window_title("msedge.exe", "New tab - Microsoft Edge", || true),
+      "[Private Browsing]"
+    );
+    // Not a browser: the badge check is never consulted
+    assert_eq!(
+      WindowTracker::sanitize_window_title("code.exe", "notes (Incognito)", || panic!("badge checked")),
+      "notes (Incognito)"
+    );
+    assert_eq!(
+      WindowTracker::sanitize_window_title("firefox.exe", "Bank of America — Mozilla Firefox", || false),
+      "[Protected App]"
+    );
+  }
+
   #[test]
   fn test_window_tracker_new() {
     let tracker = WindowTracker::new();
@@ -205,6 +504,7 @@ mod tests {
       process_name: "test.exe".to_string(),
       window_title: "Test Window".to_string(),
       timestamp: Utc::now(),
+      ..Default::default()
     };
 
     let serialized = serde_json::to_string(&info);
@@ -223,6 +523,7 @@ mod tests {
       process_name: "chrome.exe".to_string(),
       window_title: "Google Search".to_string(),
       timestamp: Utc::now(),
+      ..Default::default()
     };
 
     let info2 = info1.clone();
@@ -231,11 +532,11 @@ mod tests {
   }
 
   #[test]
-  #[cfg(not(windows))]
-  fn test_get_active_window_info_non_windows() {
+  #[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+  fn test_get_active_window_info_unsupported_platform() {
     let tracker = WindowTracker::new().unwrap();
     let result = tracker.get_active_window_info();
     assert!(result.is_err());
-    assert_eq!(result.unwrap_err().to_string(), "Window tracking is only supported on Windows");
+    assert_eq!(result.unwrap_err().to_string(), "Window tracking is not supported on this platform");
   }
 }
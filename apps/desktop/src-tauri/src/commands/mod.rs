@@ -1,8 +1,50 @@
+use crate::archive::ExportedArchive;
+use crate::classifier::{self, CategorySuggestion};
+use crate::collector::schedule::TrackingSchedule;
+use crate::collector::self_report::SelfReportSettings;
+use crate::collector::focus::{FocusSession, FocusStatus, FocusTimer};
+use crate::collector::recorder::{self, EventRecorder, RecordingStatus, ReplayedRecording, SavedRecording};
+use crate::collector::executables;
 use crate::collector::CollectorStatus;
 use crate::collector::Collector;
-use crate::sync::{SyncClient, SyncStatus, ServerConfig};
+use crate::database::{
+    Annotation, ApiScope, ApiToken, AppRollup, AppSession, AppTitlePolicy, AppUsageTotal, BackupInfo, CategoryCorrection,
+    CategoryRollup, CategoryRule, ConfigChange, ConfigDiff, CreatedApiToken, CustomEvent, Database, DayTotal, DbStats,
+    DeletionReason, EventFilter, Goal, GoalScope, MaintenanceReport, PendingDeletion, ProjectRule, RecoveryReport,
+    RedactionRule, RestoreReport, RetentionPolicy, RollupGranularity, StorageStats, StoredEvent, StoredNotification,
+    Tag, TimelineEvent, TitlePolicy, DEFAULT_SEARCH_LIMIT,
+};
+use crate::encryption::{
+    self, KeyStore, KeyStoreStatus, SecretKey, DERIVED_KEY_STATE_KEY, RECOVERY_CODE_STATE_KEY,
+    WRAPPED_KEY_PENDING_STATE_KEY, WRAPPED_KEY_STATE_KEY,
+};
+#[cfg(feature = "parquet-export")]
+use crate::export::{self, ParquetExport};
+use crate::goals::{self, GoalStatus};
+use crate::guard::{AppLockStatus, CommandGuard};
+#[cfg(feature = "editor-heartbeats")]
+use crate::heartbeat::{HeartbeatListener, HeartbeatSettings};
+use crate::import::{self, CsvColumnMapping, CsvPreset, ImportReport};
+#[cfg(feature = "load-generator")]
+use crate::loadgen::{self, LoadReport};
+use crate::diagnostics::{self, SelfTestReport};
+use crate::notifications::{NotificationCenter, NotificationKind, NotificationSettings};
+use crate::profiles::{self, Profile, ProfileStore, RunningProfile};
+use crate::reports::{
+    self, CategoryTotal, DesktopTotal, DeviceTotals, DocumentGrouping, DocumentTotal, ExecutableTotal, Forecast, RulesMode,
+    UsageTrend,
+};
+use crate::session::{self, CrashReport, SessionLock};
+use crate::statements::{self, MonthlyStatement};
+use crate::sync::overlap::OverlapPolicy;
+use crate::sync::{SyncClient, SyncFieldPolicy, SyncStatus, ServerConfig};
+use crate::sync::client::SyncError;
+use crate::theme::{self, ReportTheme, SystemTheme, ThemeService};
+use std::collections::HashMap;
 use std::sync::Arc;
+use tauri::Manager;
 use tokio::sync::Mutex;
+use zeroize::Zeroizing;
 
 /// Start tracking window usage
 #[tauri::command]
@@ -22,20 +64,71 @@ pub async fn stop_tracking(
     collector.stop().await.map_err(|e| e.to_string())
 }
 
+/// Pause tracking for `duration_minutes`; it resumes on its own afterwards
+#[tauri::command]
+pub async fn pause_tracking(
+    collector: tauri::State<'_, Arc<Mutex<Collector>>>,
+    duration_minutes: u32,
+) -> Result<(), String> {
+    let collector = collector.lock().await;
+    collector.pause_tracking(duration_minutes).await.map_err(|e| e.to_string())
+}
+
+/// End a pause early
+#[tauri::command]
+pub async fn resume_tracking(
+    collector: tauri::State<'_, Arc<Mutex<Collector>>>,
+) -> Result<(), String> {
+    let collector = collector.lock().await;
+    collector.resume_tracking().await.map_err(|e| e.to_string())
+}
+
 /// Get current collector status
 #[tauri::command]
 pub async fn get_status(
     collector: tauri::State<'_, Arc<Mutex<Collector>>>,
+    focus_timer: tauri::State<'_, Arc<FocusTimer>>,
 ) -> Result<CollectorStatus, String> {
     let collector = collector.lock().await;
-    collector.get_status().await.map_err(|e| e.to_string())
+    let mut status = collector.get_status().await.map_err(|e| e.to_string())?;
+    status.focus = Some(focus_timer.status());
+    Ok(status)
+}
+
+/// Start a focus session of `duration_minutes`
+#[tauri::command]
+pub async fn start_focus(
+    focus_timer: tauri::State<'_, Arc<FocusTimer>>,
+    duration_minutes: u32,
+) -> Result<FocusStatus, String> {
+    focus_timer.start_focus(duration_minutes).map_err(|e| e.to_string())
+}
+
+/// End the running focus session early
+#[tauri::command]
+pub async fn stop_focus(
+    focus_timer: tauri::State<'_, Arc<FocusTimer>>,
+) -> Result<Option<FocusSession>, String> {
+    focus_timer.stop_focus().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_focus_status(
+    focus_timer: tauri::State<'_, Arc<FocusTimer>>,
+) -> Result<FocusStatus, String> {
+    Ok(focus_timer.status())
 }
 
 /// Sync events to server now
 #[tauri::command]
 pub async fn sync_now(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
     sync_client: tauri::State<'_, SyncClient>,
+    notifications: tauri::State<'_, Arc<NotificationCenter>>,
 ) -> Result<SyncStatus, String> {
+    guard.check(&db, "sync_now").map_err(|e| e.to_string())?;
+
     // Perform sync
     let sync_result = sync_client.sync_events().await;
 
@@ -45,6 +138,10 @@ pub async fn sync_now(
 
     // If sync failed, update error in status
     if let Err(e) = sync_result {
+        if let Err(notify_err) = notifications.push(NotificationKind::SyncError, "Sync failed", &e.to_string()) {
+            tracing::error!("Failed to record sync error notification: {}", notify_err);
+        }
+
         let error_status = SyncStatus {
             last_error: Some(e.to_string()),
             ..status
@@ -77,9 +174,13 @@ pub async fn get_server_config(
 /// Set server configuration
 #[tauri::command]
 pub async fn set_server_config(
+    db: tauri::State<'_, Arc<Database>>,
     sync_client: tauri::State<'_, SyncClient>,
+    guard: tauri::State<'_, CommandGuard>,
     config: ServerConfig,
 ) -> Result<SyncStatus, String> {
+    guard.check(&db, "set_server_config").map_err(|e| e.to_string())?;
+
     // Set configuration
     sync_client.set_config(config).await
         .map_err(|e| e.to_string())?;
@@ -88,3 +189,1496 @@ pub async fn set_server_config(
     sync_client.get_status().await
         .map_err(|e| e.to_string())
 }
+
+/// Get which event fields sync uploads
+#[tauri::command]
+pub async fn get_sync_field_policy(
+    sync_client: tauri::State<'_, SyncClient>,
+) -> Result<SyncFieldPolicy, String> {
+    sync_client.get_field_policy()
+        .map_err(|e| e.to_string())
+}
+
+/// Opt individual fields in or out of sync uploads
+#[tauri::command]
+pub async fn set_sync_field_policy(
+    sync_client: tauri::State<'_, SyncClient>,
+    policy: SyncFieldPolicy,
+) -> Result<SyncStatus, String> {
+    sync_client.set_field_policy(&policy)
+        .map_err(|e| e.to_string())?;
+
+    // Return updated status so the UI can show the new uploaded fields
+    sync_client.get_status().await
+        .map_err(|e| e.to_string())
+}
+
+/// Change how many events each sync request uploads; sync keeps sending
+/// batches until the backlog is drained
+#[tauri::command]
+pub async fn set_sync_batch_size(
+    sync_client: tauri::State<'_, SyncClient>,
+    batch_size: usize,
+) -> Result<SyncStatus, String> {
+    sync_client.set_batch_size(batch_size)
+        .map_err(|e| e.to_string())?;
+
+    sync_client.get_status().await
+        .map_err(|e| e.to_string())
+}
+
+/// Get the current OS theme (light/dark and accent color)
+#[tauri::command]
+pub async fn get_system_theme() -> Result<SystemTheme, String> {
+    Ok(theme::get_system_theme())
+}
+
+/// Get the theme used for generated reports
+#[tauri::command]
+pub async fn get_report_theme(
+    theme_service: tauri::State<'_, ThemeService>,
+) -> Result<ReportTheme, String> {
+    theme_service.report_theme()
+        .map_err(|e| e.to_string())
+}
+
+/// Override the report theme (pass null to follow the OS theme)
+#[tauri::command]
+pub async fn set_report_theme(
+    theme_service: tauri::State<'_, ThemeService>,
+    theme: Option<ReportTheme>,
+) -> Result<ReportTheme, String> {
+    theme_service.set_override(theme.as_ref())
+        .map_err(|e| e.to_string())?;
+
+    theme_service.report_theme()
+        .map_err(|e| e.to_string())
+}
+
+/// Get the notification feed (newest first)
+#[tauri::command]
+pub async fn get_notification_feed(
+    notifications: tauri::State<'_, Arc<NotificationCenter>>,
+    unread_only: Option<bool>,
+    limit: Option<i32>,
+) -> Result<Vec<StoredNotification>, String> {
+    notifications.feed(unread_only.unwrap_or(false), limit.unwrap_or(100))
+        .map_err(|e| e.to_string())
+}
+
+/// Mark notifications as read
+#[tauri::command]
+pub async fn mark_notifications_read(
+    notifications: tauri::State<'_, Arc<NotificationCenter>>,
+    ids: Vec<String>,
+) -> Result<(), String> {
+    notifications.mark_read(&ids)
+        .map_err(|e| e.to_string())
+}
+
+/// Get notification delivery settings
+#[tauri::command]
+pub async fn get_notification_settings(
+    notifications: tauri::State<'_, Arc<NotificationCenter>>,
+) -> Result<NotificationSettings, String> {
+    notifications.get_settings()
+        .map_err(|e| e.to_string())
+}
+
+/// Set notification delivery settings (digest mode and time)
+#[tauri::command]
+pub async fn set_notification_settings(
+    notifications: tauri::State<'_, Arc<NotificationCenter>>,
+    settings: NotificationSettings,
+) -> Result<NotificationSettings, String> {
+    notifications.set_settings(&settings)
+        .map_err(|e| e.to_string())?;
+
+    notifications.get_settings()
+        .map_err(|e| e.to_string())
+}
+
+/// Run the pipeline self-test (queue -> database -> encryption -> mock sync -> mark synced)
+#[tauri::command]
+pub async fn self_test(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+    sync_client: tauri::State<'_, SyncClient>,
+) -> Result<SelfTestReport, String> {
+    guard.check(&db, "self_test").map_err(|e| e.to_string())?;
+    Ok(diagnostics::run_self_test(db.inner().clone(), &sync_client).await)
+}
+
+/// Insert synthetic events at `events_per_sec` for `duration_secs`, optionally
+/// syncing them, and report throughput; the events are deleted afterwards
+#[cfg(feature = "load-generator")]
+#[tauri::command]
+pub async fn generate_load(
+    db: tauri::State<'_, Arc<Database>>,
+    sync_client: tauri::State<'_, SyncClient>,
+    events_per_sec: u32,
+    duration_secs: u32,
+    sync: bool,
+) -> Result<LoadReport, String> {
+    loadgen::generate_load(db.inner().clone(), sync.then_some(sync_client.inner()), events_per_sec, duration_secs)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Write events starting in [start, end) (Unix millis, both optional) to a Parquet file at `path`
+#[cfg(feature = "parquet-export")]
+#[tauri::command]
+pub async fn export_events_parquet(
+    db: tauri::State<'_, Arc<Database>>,
+    path: String,
+    start: Option<i64>,
+    end: Option<i64>,
+) -> Result<ParquetExport, String> {
+    let start = start
+        .map(|start| chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time"))
+        .transpose()?;
+    let end = end
+        .map(|end| chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time"))
+        .transpose()?;
+
+    let db = db.inner().clone();
+    tokio::task::spawn_blocking(move || export::export_events_parquet(&db, std::path::Path::new(&path), start, end))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Backfill app usage from another tracker's CSV export at `path`, using a
+/// preset or an explicit column mapping
+#[tauri::command]
+pub async fn import_csv(
+    db: tauri::State<'_, Arc<Database>>,
+    path: String,
+    preset: Option<CsvPreset>,
+    mapping: Option<CsvColumnMapping>,
+) -> Result<ImportReport, String> {
+    let mapping = mapping
+        .or_else(|| preset.map(CsvPreset::mapping))
+        .ok_or("Choose a preset or a column mapping")?;
+
+    let db = db.inner().clone();
+    tokio::task::spawn_blocking(move || import::import_csv_file(&db, std::path::Path::new(&path), &mapping))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Parquet export is only available in builds with the `parquet-export` feature
+#[cfg(not(feature = "parquet-export"))]
+#[tauri::command]
+pub async fn export_events_parquet() -> Result<(), String> {
+    Err("Parquet export is not enabled in this build".to_string())
+}
+
+/// Load generation is only available in builds with the `load-generator` feature
+#[cfg(not(feature = "load-generator"))]
+#[tauri::command]
+pub async fn generate_load() -> Result<(), String> {
+    Err("Load generator is not enabled in this build".to_string())
+}
+
+/// Get the history of settings changes (newest first)
+#[tauri::command]
+pub async fn get_settings_history(
+    db: tauri::State<'_, Arc<Database>>,
+    since: Option<i64>,
+    limit: Option<i32>,
+) -> Result<Vec<ConfigChange>, String> {
+    let since = since.and_then(chrono::DateTime::from_timestamp_millis);
+    db.get_config_history(None, since, limit.unwrap_or(100))
+        .map_err(|e| e.to_string())
+}
+
+/// Diff settings between two history versions
+#[tauri::command]
+pub async fn diff_settings_versions(
+    db: tauri::State<'_, Arc<Database>>,
+    from_version: i64,
+    to_version: i64,
+) -> Result<Vec<ConfigDiff>, String> {
+    db.diff_config_versions(from_version, to_version)
+        .map_err(|e| e.to_string())
+}
+
+/// Restore settings to a previous history version
+#[tauri::command]
+pub async fn restore_settings_version(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+    version: i64,
+) -> Result<Vec<ConfigDiff>, String> {
+    guard.check(&db, "restore_settings_version").map_err(|e| e.to_string())?;
+    db.restore_settings_version(version)
+        .map_err(|e| e.to_string())
+}
+
+/// Get the current app categorization rules
+#[tauri::command]
+pub async fn get_category_rules(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Vec<CategoryRule>, String> {
+    db.get_category_rules()
+        .map(|rules| rules.to_vec())
+        .map_err(|e| e.to_string())
+}
+
+/// Add or change a categorization rule
+#[tauri::command]
+pub async fn set_category_rule(
+    db: tauri::State<'_, Arc<Database>>,
+    pattern: String,
+    category: String,
+) -> Result<(), String> {
+    db.set_category_rule(&pattern, &category)
+        .map_err(|e| e.to_string())
+}
+
+/// Record that `app_name` (with `window_title`) belongs in `category`; trains the classifier
+#[tauri::command]
+pub async fn record_category_correction(
+    db: tauri::State<'_, Arc<Database>>,
+    app_name: String,
+    window_title: Option<String>,
+    category: String,
+) -> Result<i64, String> {
+    db.add_category_correction(&app_name, window_title.as_deref(), &category, chrono::Utc::now())
+        .map_err(|e| e.to_string())
+}
+
+/// Corrections the classifier learns from, oldest first
+#[tauri::command]
+pub async fn get_category_corrections(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Vec<CategoryCorrection>, String> {
+    db.get_category_corrections().map_err(|e| e.to_string())
+}
+
+/// Forget a correction
+#[tauri::command]
+pub async fn delete_category_correction(
+    db: tauri::State<'_, Arc<Database>>,
+    id: i64,
+) -> Result<(), String> {
+    db.delete_category_correction(id).map_err(|e| e.to_string())
+}
+
+/// Suggested category for an app and title, from the classifier or the rules
+#[tauri::command]
+pub async fn suggest_category(
+    db: tauri::State<'_, Arc<Database>>,
+    app_name: String,
+    window_title: Option<String>,
+) -> Result<Option<CategorySuggestion>, String> {
+    classifier::suggest_category(&db, &app_name, window_title.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_category_classifier_enabled(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<bool, String> {
+    classifier::is_enabled(&db).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_category_classifier_enabled(
+    db: tauri::State<'_, Arc<Database>>,
+    enabled: bool,
+) -> Result<(), String> {
+    db.set_setting(classifier::CLASSIFIER_ENABLED_SETTING, if enabled { "true" } else { "false" })
+        .map_err(|e| e.to_string())
+}
+
+/// Remove a categorization rule
+#[tauri::command]
+pub async fn delete_category_rule(
+    db: tauri::State<'_, Arc<Database>>,
+    pattern: String,
+) -> Result<(), String> {
+    db.delete_category_rule(&pattern)
+        .map_err(|e| e.to_string())
+}
+
+/// Move every event of `app_name`, past and future, into `category`;
+/// returns how many recorded events changed
+#[tauri::command]
+pub async fn recategorize_app(
+    db: tauri::State<'_, Arc<Database>>,
+    app_name: String,
+    category: String,
+) -> Result<usize, String> {
+    db.recategorize_app(&app_name, &category)
+        .map_err(|e| e.to_string())
+}
+
+/// Process names the collector never records
+#[tauri::command]
+pub async fn get_excluded_apps(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Vec<String>, String> {
+    db.get_excluded_apps().map_err(|e| e.to_string())
+}
+
+/// Stop recording an app; takes effect on the next window switch
+#[tauri::command]
+pub async fn add_excluded_app(
+    db: tauri::State<'_, Arc<Database>>,
+    process_name: String,
+) -> Result<(), String> {
+    db.add_excluded_app(&process_name).map_err(|e| e.to_string())
+}
+
+/// Resume recording an app; returns false if it was not excluded
+#[tauri::command]
+pub async fn remove_excluded_app(
+    db: tauri::State<'_, Arc<Database>>,
+    process_name: String,
+) -> Result<bool, String> {
+    db.remove_excluded_app(&process_name).map_err(|e| e.to_string())
+}
+
+/// Apps that store less than the full window title
+#[tauri::command]
+pub async fn get_title_policies(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Vec<AppTitlePolicy>, String> {
+    db.get_title_policies().map_err(|e| e.to_string())
+}
+
+/// Set how much of an app's window title is stored; takes effect on the next window switch
+#[tauri::command]
+pub async fn set_title_policy(
+    db: tauri::State<'_, Arc<Database>>,
+    process_name: String,
+    policy: TitlePolicy,
+) -> Result<(), String> {
+    db.set_title_policy(&process_name, policy).map_err(|e| e.to_string())
+}
+
+/// Store an app's full title again; returns false if it had no policy
+#[tauri::command]
+pub async fn remove_title_policy(
+    db: tauri::State<'_, Arc<Database>>,
+    process_name: String,
+) -> Result<bool, String> {
+    db.remove_title_policy(&process_name).map_err(|e| e.to_string())
+}
+
+/// Window title redaction rules, in the order they are applied
+#[tauri::command]
+pub async fn get_redaction_rules(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Vec<RedactionRule>, String> {
+    db.get_redaction_rules().map_err(|e| e.to_string())
+}
+
+/// Add a redaction rule; rule changes take effect the next time tracking starts
+#[tauri::command]
+pub async fn add_redaction_rule(
+    db: tauri::State<'_, Arc<Database>>,
+    pattern: String,
+    replacement: String,
+) -> Result<RedactionRule, String> {
+    db.add_redaction_rule(&pattern, &replacement).map_err(|e| e.to_string())
+}
+
+/// Enable or disable a redaction rule; returns false if there is no such rule
+#[tauri::command]
+pub async fn set_redaction_rule_enabled(
+    db: tauri::State<'_, Arc<Database>>,
+    id: i64,
+    enabled: bool,
+) -> Result<bool, String> {
+    db.set_redaction_rule_enabled(id, enabled).map_err(|e| e.to_string())
+}
+
+/// Delete a redaction rule; returns false if there is no such rule
+#[tauri::command]
+pub async fn delete_redaction_rule(
+    db: tauri::State<'_, Arc<Database>>,
+    id: i64,
+) -> Result<bool, String> {
+    db.delete_redaction_rule(id).map_err(|e| e.to_string())
+}
+
+/// Project rules, in the order they are tried
+#[tauri::command]
+pub async fn get_project_rules(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Vec<ProjectRule>, String> {
+    db.get_project_rules().map_err(|e| e.to_string())
+}
+
+/// Add a project rule; rule changes take effect the next time tracking starts
+#[tauri::command]
+pub async fn add_project_rule(
+    db: tauri::State<'_, Arc<Database>>,
+    pattern: String,
+    project: String,
+) -> Result<ProjectRule, String> {
+    db.add_project_rule(&pattern, &project).map_err(|e| e.to_string())
+}
+
+/// Enable or disable a project rule; returns false if there is no such rule
+#[tauri::command]
+pub async fn set_project_rule_enabled(
+    db: tauri::State<'_, Arc<Database>>,
+    id: i64,
+    enabled: bool,
+) -> Result<bool, String> {
+    db.set_project_rule_enabled(id, enabled).map_err(|e| e.to_string())
+}
+
+/// Delete a project rule; returns false if there is no such rule
+#[tauri::command]
+pub async fn delete_project_rule(
+    db: tauri::State<'_, Arc<Database>>,
+    id: i64,
+) -> Result<bool, String> {
+    db.delete_project_rule(id).map_err(|e| e.to_string())
+}
+
+/// Weekly windows the collector records in; empty means always
+#[tauri::command]
+pub async fn get_tracking_schedule(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<TrackingSchedule, String> {
+    TrackingSchedule::load(&db).map_err(|e| e.to_string())
+}
+
+/// Replace the tracking schedule; the collector picks it up within seconds
+#[tauri::command]
+pub async fn set_tracking_schedule(
+    db: tauri::State<'_, Arc<Database>>,
+    schedule: TrackingSchedule,
+) -> Result<(), String> {
+    schedule.save(&db).map_err(|e| e.to_string())
+}
+
+/// Frequency and quiet hours of self-report prompts
+#[tauri::command]
+pub async fn get_self_report_settings(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<SelfReportSettings, String> {
+    SelfReportSettings::load(&db).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_self_report_settings(
+    db: tauri::State<'_, Arc<Database>>,
+    settings: SelfReportSettings,
+) -> Result<(), String> {
+    settings.save(&db).map_err(|e| e.to_string())
+}
+
+/// Attach a one-line note to an event (e.g. the answer to a self-report prompt)
+#[tauri::command]
+pub async fn add_annotation(
+    db: tauri::State<'_, Arc<Database>>,
+    event_id: Option<String>,
+    text: String,
+) -> Result<Annotation, String> {
+    db.add_annotation(event_id.as_deref(), &text, chrono::Utc::now())
+        .map_err(|e| e.to_string())
+}
+
+/// Annotations created in [start, end) (Unix millis)
+#[tauri::command]
+pub async fn get_annotations(
+    db: tauri::State<'_, Arc<Database>>,
+    start: i64,
+    end: i64,
+) -> Result<Vec<Annotation>, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    db.get_annotations_between(start, end).map_err(|e| e.to_string())
+}
+
+/// Every tag with the number of events carrying it
+#[tauri::command]
+pub async fn get_tags(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Vec<Tag>, String> {
+    db.get_tags().map_err(|e| e.to_string())
+}
+
+/// Add `tag` to events, creating it on first use; returns how many gained it
+#[tauri::command]
+pub async fn tag_events(
+    db: tauri::State<'_, Arc<Database>>,
+    event_ids: Vec<String>,
+    tag: String,
+) -> Result<usize, String> {
+    db.tag_events(&event_ids, &tag).map_err(|e| e.to_string())
+}
+
+/// Remove `tag` from events; returns how many lost it
+#[tauri::command]
+pub async fn untag_events(
+    db: tauri::State<'_, Arc<Database>>,
+    event_ids: Vec<String>,
+    tag: String,
+) -> Result<usize, String> {
+    db.untag_events(&event_ids, &tag).map_err(|e| e.to_string())
+}
+
+/// Delete a tag and remove it from every event
+#[tauri::command]
+pub async fn delete_tag(
+    db: tauri::State<'_, Arc<Database>>,
+    tag: String,
+) -> Result<(), String> {
+    db.delete_tag(&tag).map_err(|e| e.to_string())
+}
+
+/// Tags on one event
+#[tauri::command]
+pub async fn get_event_tags(
+    db: tauri::State<'_, Arc<Database>>,
+    event_id: String,
+) -> Result<Vec<String>, String> {
+    db.get_event_tags(&event_id).map_err(|e| e.to_string())
+}
+
+/// Events carrying `tag` in [start, end) (Unix millis)
+#[tauri::command]
+pub async fn get_events_with_tag(
+    db: tauri::State<'_, Arc<Database>>,
+    tag: String,
+    start: i64,
+    end: i64,
+) -> Result<Vec<StoredEvent>, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    db.get_events_with_tag(&tag, start, end).map_err(|e| e.to_string())
+}
+
+/// Usage per category for [start, end) (Unix millis), using current or as-of rules
+#[tauri::command]
+pub async fn get_category_summary(
+    db: tauri::State<'_, Arc<Database>>,
+    start: i64,
+    end: i64,
+    rules: Option<RulesMode>,
+) -> Result<Vec<CategoryTotal>, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    reports::category_totals(&db, start, end, rules.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
+
+/// Total app usage per app for [start, end) (Unix millis), largest first
+#[tauri::command]
+pub async fn get_app_totals(
+    db: tauri::State<'_, Arc<Database>>,
+    start: i64,
+    end: i64,
+) -> Result<Vec<AppUsageTotal>, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    db.get_app_totals(start, end).map_err(|e| e.to_string())
+}
+
+/// Total app usage per local day for [start, end) (Unix millis), oldest first
+#[tauri::command]
+pub async fn get_daily_totals(
+    db: tauri::State<'_, Arc<Database>>,
+    start: i64,
+    end: i64,
+) -> Result<Vec<DayTotal>, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    db.get_daily_totals(start, end).map_err(|e| e.to_string())
+}
+
+/// Time per editor document, or per project, for [start, end) (Unix millis)
+#[tauri::command]
+pub async fn get_document_summary(
+    db: tauri::State<'_, Arc<Database>>,
+    start: i64,
+    end: i64,
+    group_by: Option<DocumentGrouping>,
+) -> Result<Vec<DocumentTotal>, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    reports::document_totals(&db, start, end, group_by.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
+
+/// Time per virtual desktop / workspace, with app usage on each, for [start, end) (Unix millis)
+#[tauri::command]
+pub async fn get_desktop_summary(
+    db: tauri::State<'_, Arc<Database>>,
+    start: i64,
+    end: i64,
+) -> Result<Vec<DesktopTotal>, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    reports::desktop_totals(&db, start, end).map_err(|e| e.to_string())
+}
+
+/// This device's and the user's other devices' events starting in [start, end)
+/// (Unix millis), oldest first; other devices' events arrive with sync. App
+/// usage several devices recorded at once is shared out by the overlap policy.
+#[tauri::command]
+pub async fn get_timeline(
+    db: tauri::State<'_, Arc<Database>>,
+    start: i64,
+    end: i64,
+) -> Result<Vec<TimelineEvent>, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    reports::timeline(&db, start, end).map_err(|e| e.to_string())
+}
+
+/// Who gets time several devices recorded at once
+#[tauri::command]
+pub async fn get_overlap_policy(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<OverlapPolicy, String> {
+    OverlapPolicy::load(&db).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_overlap_policy(
+    db: tauri::State<'_, Arc<Database>>,
+    policy: OverlapPolicy,
+) -> Result<(), String> {
+    policy.save(&db).map_err(|e| e.to_string())
+}
+
+/// App usage per device for [start, end) (Unix millis), with overlapping time shared out
+#[tauri::command]
+pub async fn get_device_summary(
+    db: tauri::State<'_, Arc<Database>>,
+    start: i64,
+    end: i64,
+) -> Result<DeviceTotals, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    reports::device_totals(&db, start, end).map_err(|e| e.to_string())
+}
+
+/// Events starting in [start, end) (Unix millis), oldest first, narrowed by
+/// app, category and event type, e.g. for a day timeline
+#[tauri::command]
+pub async fn get_events_between(
+    db: tauri::State<'_, Arc<Database>>,
+    start: i64,
+    end: i64,
+    filter: Option<EventFilter>,
+) -> Result<Vec<StoredEvent>, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    db.get_events_between(start, end, &filter.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
+
+/// Events whose app or window title matches `query`, most recent first;
+/// optionally limited to [start, end) (Unix millis)
+#[tauri::command]
+pub async fn search_events(
+    db: tauri::State<'_, Arc<Database>>,
+    query: String,
+    start: Option<i64>,
+    end: Option<i64>,
+    limit: Option<u32>,
+) -> Result<Vec<StoredEvent>, String> {
+    let start = start
+        .map(|start| chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time"))
+        .transpose()?;
+    let end = end
+        .map(|end| chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time"))
+        .transpose()?;
+    db.search_events(&query, start, end, limit.unwrap_or(DEFAULT_SEARCH_LIMIT))
+        .map_err(|e| e.to_string())
+}
+
+/// Hourly or daily app usage for buckets starting in [start, end) (Unix millis)
+#[tauri::command]
+pub async fn get_app_rollups(
+    db: tauri::State<'_, Arc<Database>>,
+    granularity: RollupGranularity,
+    start: i64,
+    end: i64,
+) -> Result<Vec<AppRollup>, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    db.get_app_rollups(granularity, start, end).map_err(|e| e.to_string())
+}
+
+/// Hourly or daily time per category (current rules) for buckets starting in [start, end)
+#[tauri::command]
+pub async fn get_category_rollups(
+    db: tauri::State<'_, Arc<Database>>,
+    granularity: RollupGranularity,
+    start: i64,
+    end: i64,
+) -> Result<Vec<CategoryRollup>, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    db.get_category_rollups(granularity, start, end).map_err(|e| e.to_string())
+}
+
+/// App usage per executable for [start, end) (Unix millis); renamed or
+/// relocated copies of the same binary count as one app once hashed
+#[tauri::command]
+pub async fn get_executable_summary(
+    db: tauri::State<'_, Arc<Database>>,
+    start: i64,
+    end: i64,
+) -> Result<Vec<ExecutableTotal>, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    reports::executable_totals(&db, start, end).map_err(|e| e.to_string())
+}
+
+/// Whether executables of tracked apps are hashed (applies from the next tracking start)
+#[tauri::command]
+pub async fn get_executable_hashing_enabled(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<bool, String> {
+    db.get_setting(executables::EXECUTABLE_HASH_SETTING)
+        .map(|value| value.is_some_and(|value| value == "true"))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_executable_hashing_enabled(
+    db: tauri::State<'_, Arc<Database>>,
+    enabled: bool,
+) -> Result<(), String> {
+    db.set_setting(executables::EXECUTABLE_HASH_SETTING, if enabled { "true" } else { "false" })
+        .map_err(|e| e.to_string())
+}
+
+/// Whether app names and titles are stored as salted hashes
+#[tauri::command]
+pub async fn get_anonymized_storage(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<bool, String> {
+    db.is_anonymized_storage().map_err(|e| e.to_string())
+}
+
+/// Turn anonymized storage on (hashing what is already recorded) or off;
+/// returns how many events were rewritten
+#[tauri::command]
+pub async fn set_anonymized_storage(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+    enabled: bool,
+) -> Result<usize, String> {
+    guard.check(&db, "set_anonymized_storage").map_err(|e| e.to_string())?;
+    let db = db.inner().clone();
+    tokio::task::spawn_blocking(move || db.set_anonymized_storage(enabled))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Readable values for hashed app names and titles, keyed by hash
+#[tauri::command]
+pub async fn resolve_display_names(
+    db: tauri::State<'_, Arc<Database>>,
+    values: Vec<String>,
+) -> Result<HashMap<String, String>, String> {
+    db.resolve_display_names(&values).map_err(|e| e.to_string())
+}
+
+/// App usage for [start, end) (Unix millis) with coverage metadata for down-sampled ranges
+#[tauri::command]
+pub async fn get_usage_trend(
+    db: tauri::State<'_, Arc<Database>>,
+    start: i64,
+    end: i64,
+) -> Result<UsageTrend, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    reports::usage_trend(&db, start, end).map_err(|e| e.to_string())
+}
+
+/// Expected hours for a category over the coming days, with this week's on-track status
+#[tauri::command]
+pub async fn get_forecast(
+    db: tauri::State<'_, Arc<Database>>,
+    category: String,
+    horizon_days: u32,
+) -> Result<Forecast, String> {
+    reports::forecast(&db, &category, horizon_days, chrono::Utc::now())
+        .map_err(|e| e.to_string())
+}
+
+/// Frozen statement for a completed month ("YYYY-MM"), or None if it has not been frozen
+#[tauri::command]
+pub async fn get_monthly_statement(
+    db: tauri::State<'_, Arc<Database>>,
+    month: String,
+) -> Result<Option<MonthlyStatement>, String> {
+    statements::get_monthly_statement(&db, &month).map_err(|e| e.to_string())
+}
+
+/// Months that have a frozen statement, oldest first
+#[tauri::command]
+pub async fn list_monthly_statements(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Vec<String>, String> {
+    db.list_statement_months().map_err(|e| e.to_string())
+}
+
+/// Daily and weekly time budgets per category
+#[tauri::command]
+pub async fn get_goals(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Vec<Goal>, String> {
+    db.get_goals().map_err(|e| e.to_string())
+}
+
+/// Add or replace the goal for a category and scope
+#[tauri::command]
+pub async fn set_goal(
+    db: tauri::State<'_, Arc<Database>>,
+    goal: Goal,
+) -> Result<(), String> {
+    db.set_goal(&goal).map_err(|e| e.to_string())
+}
+
+/// Remove a goal; returns false if it did not exist
+#[tauri::command]
+pub async fn delete_goal(
+    db: tauri::State<'_, Arc<Database>>,
+    category: String,
+    scope: GoalScope,
+) -> Result<bool, String> {
+    db.delete_goal(&category, scope).map_err(|e| e.to_string())
+}
+
+/// Today's budget and usage for every goal, including carry-over
+#[tauri::command]
+pub async fn get_goal_status(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Vec<GoalStatus>, String> {
+    goals::goal_statuses(&db, chrono::Utc::now()).map_err(|e| e.to_string())
+}
+
+/// Write counters for the buffered status writes
+#[tauri::command]
+pub async fn get_db_stats(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<DbStats, String> {
+    Ok(db.get_db_stats())
+}
+
+/// Database file and WAL sizes, row counts per table and reclaimable space
+#[tauri::command]
+pub async fn get_storage_stats(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<StorageStats, String> {
+    db.get_storage_stats().map_err(|e| e.to_string())
+}
+
+/// Each run of the app with its machine and app version, newest first
+#[tauri::command]
+pub async fn get_app_sessions(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Vec<AppSession>, String> {
+    db.get_app_sessions().map_err(|e| e.to_string())
+}
+
+/// Outcome of the last scheduled database maintenance run
+#[tauri::command]
+pub async fn get_maintenance_status(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Option<MaintenanceReport>, String> {
+    db.get_last_maintenance().map_err(|e| e.to_string())
+}
+
+/// What was salvaged the last time a damaged database was recovered at startup
+#[tauri::command]
+pub async fn get_last_recovery(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Option<RecoveryReport>, String> {
+    db.get_last_recovery().map_err(|e| e.to_string())
+}
+
+/// Archive closed events before `before` (Unix millis) to an encrypted file, then prune them
+#[tauri::command]
+pub async fn archive_events_before(
+    profile: tauri::State<'_, RunningProfile>,
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+    sync_client: tauri::State<'_, SyncClient>,
+    before: i64,
+) -> Result<Option<ExportedArchive>, String> {
+    guard.check(&db, "archive_events_before").map_err(|e| e.to_string())?;
+    let cutoff = chrono::DateTime::from_timestamp_millis(before).ok_or("Invalid cutoff time")?;
+    let archive_dir = profile.data_dir.join("archives");
+
+    sync_client
+        .archive_and_prune(&archive_dir, cutoff)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// How long raw events are kept and what happens to them after
+#[tauri::command]
+pub async fn get_retention_policy(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<RetentionPolicy, String> {
+    db.get_retention_policy().map_err(|e| e.to_string())
+}
+
+/// Set the retention policy; it is applied on the next daily retention pass
+#[tauri::command]
+pub async fn set_retention_policy(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+    policy: RetentionPolicy,
+) -> Result<(), String> {
+    guard.check(&db, "set_retention_policy").map_err(|e| e.to_string())?;
+    db.set_retention_policy(&policy).map_err(|e| e.to_string())
+}
+
+/// Copy the whole local database to `path`, e.g. to move to a new machine
+#[tauri::command]
+pub async fn backup_database(
+    db: tauri::State<'_, Arc<Database>>,
+    path: String,
+) -> Result<BackupInfo, String> {
+    let db = db.inner().clone();
+    tokio::task::spawn_blocking(move || db.backup(std::path::Path::new(&path)))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Replace the local database with a backup made by `backup_database`
+#[tauri::command]
+pub async fn restore_database(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+    path: String,
+) -> Result<RestoreReport, String> {
+    guard.check(&db, "restore_database").map_err(|e| e.to_string())?;
+    let db = db.inner().clone();
+    tokio::task::spawn_blocking(move || db.restore(std::path::Path::new(&path)))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Delete events; ones already uploaded are also removed from the server on the next sync
+#[tauri::command]
+pub async fn delete_events(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+    ids: Vec<String>,
+) -> Result<usize, String> {
+    guard.check(&db, "delete_events").map_err(|e| e.to_string())?;
+    db.delete_events_propagated(&ids, DeletionReason::Manual, chrono::Utc::now())
+        .map_err(|e| e.to_string())
+}
+
+/// Delete events starting in [start, end) (Unix millis) that match `filter`, locally and
+/// on the server; with `dry_run` only counts them
+#[tauri::command]
+pub async fn delete_events_matching(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+    start: i64,
+    end: i64,
+    filter: Option<EventFilter>,
+    dry_run: Option<bool>,
+) -> Result<usize, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    let filter = filter.unwrap_or_default();
+    if dry_run.unwrap_or(false) {
+        return db.count_events_matching(start, end, &filter).map_err(|e| e.to_string());
+    }
+    guard.check(&db, "delete_events_matching").map_err(|e| e.to_string())?;
+    db.delete_events_matching(start, end, &filter, chrono::Utc::now())
+        .map_err(|e| e.to_string())
+}
+
+/// Allow (or forbid) tracker event recording; a developer setting
+#[tauri::command]
+pub async fn set_event_recording_enabled(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+    enabled: bool,
+) -> Result<(), String> {
+    guard.check(&db, "set_event_recording_enabled").map_err(|e| e.to_string())?;
+    db.set_setting(recorder::DEV_RECORDING_SETTING, if enabled { "true" } else { "false" })
+        .map_err(|e| e.to_string())
+}
+
+/// Record the tracker event stream for `duration_minutes`; titles are redacted unless `raw_titles`
+#[tauri::command]
+pub async fn start_event_recording(
+    db: tauri::State<'_, Arc<Database>>,
+    recorder: tauri::State<'_, EventRecorder>,
+    duration_minutes: u32,
+    raw_titles: Option<bool>,
+) -> Result<RecordingStatus, String> {
+    recorder
+        .start(&db, duration_minutes, raw_titles.unwrap_or(false))
+        .map_err(|e| e.to_string())
+}
+
+/// Stop recording and save the recording, encrypted, under the profile's data dir
+#[tauri::command]
+pub async fn stop_event_recording(
+    profile: tauri::State<'_, RunningProfile>,
+    recorder: tauri::State<'_, EventRecorder>,
+    sync_client: tauri::State<'_, SyncClient>,
+) -> Result<Option<SavedRecording>, String> {
+    let Some(recording) = recorder.stop() else {
+        return Ok(None);
+    };
+    let dir = profile.data_dir.join("recordings");
+
+    sync_client
+        .save_recording(&dir, &recording)
+        .await
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_event_recording_status(
+    recorder: tauri::State<'_, EventRecorder>,
+) -> Result<RecordingStatus, String> {
+    Ok(recorder.status())
+}
+
+/// Replay a saved recording into a fresh database next to it
+#[tauri::command]
+pub async fn replay_event_recording(
+    db: tauri::State<'_, Arc<Database>>,
+    sync_client: tauri::State<'_, SyncClient>,
+    path: String,
+) -> Result<ReplayedRecording, String> {
+    if !recorder::is_enabled(&db).map_err(|e| e.to_string())? {
+        return Err("Event recording is a developer setting and is turned off".to_string());
+    }
+    let path = std::path::PathBuf::from(path);
+    let recording = sync_client.load_recording(&path).await.map_err(|e| e.to_string())?;
+
+    let database_path = path.with_file_name(format!("replay-{}.db", chrono::Utc::now().timestamp_millis()));
+    let replay_db = Database::new(&database_path).map_err(|e| e.to_string())?;
+    let report = recorder::replay(&replay_db, &recording).await.map_err(|e| e.to_string())?;
+    Ok(ReplayedRecording { database_path, report })
+}
+
+/// Erase all events starting in [start, end) (Unix millis), locally and on the server
+#[tauri::command]
+pub async fn purge_events(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+    start: i64,
+    end: i64,
+) -> Result<usize, String> {
+    guard.check(&db, "purge_events").map_err(|e| e.to_string())?;
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    db.purge_events_between(start, end, chrono::Utc::now())
+        .map_err(|e| e.to_string())
+}
+
+/// Deletions the server has not acknowledged yet
+#[tauri::command]
+pub async fn get_unconfirmed_deletions(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Vec<PendingDeletion>, String> {
+    db.get_unconfirmed_deletions()
+        .map_err(|e| e.to_string())
+}
+
+/// Recovery report from the most recent unclean exit, if any
+#[tauri::command]
+pub async fn get_last_crash_info(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Option<CrashReport>, String> {
+    session::last_crash_info(&db).map_err(|e| e.to_string())
+}
+
+/// Create a scoped token for the local API; the returned token is only shown once
+#[tauri::command]
+pub async fn create_api_token(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+    name: String,
+    scopes: Vec<ApiScope>,
+    expires_in_days: Option<u32>,
+) -> Result<CreatedApiToken, String> {
+    guard.check(&db, "create_api_token").map_err(|e| e.to_string())?;
+    db.create_api_token(&name, &scopes, expires_in_days).map_err(|e| e.to_string())
+}
+
+/// Local API tokens (without secrets)
+#[tauri::command]
+pub async fn list_api_tokens(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<Vec<ApiToken>, String> {
+    db.list_api_tokens().map_err(|e| e.to_string())
+}
+
+/// Log an event the collector can't see (commute, reading on paper); start
+/// and end are Unix millis and metadata an optional JSON object kept locally
+#[tauri::command]
+pub async fn add_custom_event(
+    db: tauri::State<'_, Arc<Database>>,
+    event_type: String,
+    label: Option<String>,
+    start: i64,
+    end: i64,
+    metadata: Option<serde_json::Value>,
+) -> Result<CustomEvent, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    db.add_custom_event(&event_type, label.as_deref(), start, end, metadata.as_ref())
+        .map_err(|e| e.to_string())
+}
+
+/// Custom events starting in [start, end) (Unix millis)
+#[tauri::command]
+pub async fn get_custom_events(
+    db: tauri::State<'_, Arc<Database>>,
+    start: i64,
+    end: i64,
+) -> Result<Vec<CustomEvent>, String> {
+    let start = chrono::DateTime::from_timestamp_millis(start).ok_or("Invalid start time")?;
+    let end = chrono::DateTime::from_timestamp_millis(end).ok_or("Invalid end time")?;
+    db.get_custom_events(start, end).map_err(|e| e.to_string())
+}
+
+/// Revoke a local API token; returns false if there is no such token
+#[tauri::command]
+pub async fn revoke_api_token(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+    id: String,
+) -> Result<bool, String> {
+    guard.check(&db, "revoke_api_token").map_err(|e| e.to_string())?;
+    db.revoke_api_token(&id).map_err(|e| e.to_string())
+}
+
+/// Whether editor heartbeats and shell integration are on, and the listener port
+#[cfg(feature = "editor-heartbeats")]
+#[tauri::command]
+pub async fn get_editor_heartbeat_settings(
+    db: tauri::State<'_, Arc<Database>>,
+) -> Result<HeartbeatSettings, String> {
+    HeartbeatSettings::load(&db).map_err(|e| e.to_string())
+}
+
+/// Turn editor heartbeats or shell integration on or off (or move the listener), applying the change immediately
+#[cfg(feature = "editor-heartbeats")]
+#[tauri::command]
+pub async fn set_editor_heartbeat_settings(
+    db: tauri::State<'_, Arc<Database>>,
+    listener: tauri::State<'_, Arc<HeartbeatListener>>,
+    settings: HeartbeatSettings,
+) -> Result<(), String> {
+    settings.save(&db).map_err(|e| e.to_string())?;
+    listener.apply_settings().await.map_err(|e| e.to_string())
+}
+
+/// Editor heartbeats are only available in builds with the `editor-heartbeats` feature
+#[cfg(not(feature = "editor-heartbeats"))]
+#[tauri::command]
+pub async fn get_editor_heartbeat_settings() -> Result<(), String> {
+    Err("Editor heartbeats are not enabled in this build".to_string())
+}
+
+/// Editor heartbeats are only available in builds with the `editor-heartbeats` feature
+#[cfg(not(feature = "editor-heartbeats"))]
+#[tauri::command]
+pub async fn set_editor_heartbeat_settings() -> Result<(), String> {
+    Err("Editor heartbeats are not enabled in this build".to_string())
+}
+
+/// Whether an app lock PIN is set and currently unlocked
+#[tauri::command]
+pub async fn get_app_lock_status(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+) -> Result<AppLockStatus, String> {
+    guard.status(&db).map_err(|e| e.to_string())
+}
+
+/// Unlock destructive commands for a few minutes
+#[tauri::command]
+pub async fn unlock_app(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+    pin: String,
+) -> Result<(), String> {
+    guard.check(&db, "unlock_app").map_err(|e| e.to_string())?;
+    guard.unlock(&db, &pin).map_err(|e| e.to_string())
+}
+
+/// Lock destructive commands again
+#[tauri::command]
+pub async fn lock_app(
+    guard: tauri::State<'_, CommandGuard>,
+) -> Result<(), String> {
+    guard.lock();
+    Ok(())
+}
+
+/// Set, change or (with no PIN) remove the app lock; needs a recent unlock if one is set
+#[tauri::command]
+pub async fn set_app_lock_pin(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+    pin: Option<String>,
+) -> Result<(), String> {
+    guard.check(&db, "set_app_lock_pin").map_err(|e| e.to_string())?;
+    guard.set_pin(&db, pin.as_deref()).map_err(|e| e.to_string())
+}
+
+/// Unlock the sync key with the passphrase and use it; with `remember` the
+/// key is also kept in the system keyring for the next start, and the local
+/// database is re-keyed under it. The device's first unlock makes a new key
+/// (sync swaps in the account's if it already has one) and, once the key is
+/// verified against the account, returns the recovery code, to be shown to
+/// the user once. A key the account doesn't use is refused.
+#[tauri::command]
+pub async fn unlock_sync_key(
+    db: tauri::State<'_, Arc<Database>>,
+    sync_client: tauri::State<'_, SyncClient>,
+    profile: tauri::State<'_, RunningProfile>,
+    passphrase: String,
+    remember: bool,
+) -> Result<Option<String>, String> {
+    let passphrase = Zeroizing::new(passphrase);
+    let wrapped = sync_state(&db, WRAPPED_KEY_STATE_KEY)?;
+    let first_unlock = sync_state(&db, RECOVERY_CODE_STATE_KEY)?.is_none();
+    // Unlocked before without a wrapped key: a device from before wrapped keys
+    let derived = sync_state(&db, DERIVED_KEY_STATE_KEY)?.is_some() || (!first_unlock && wrapped.is_none());
+    // Argon2id is slow by design; keep it off the async runtime
+    let (key, created) = tokio::task::spawn_blocking(move || {
+        encryption::unlock_or_create(&passphrase, wrapped.as_deref(), derived)
+    })
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+    if let Some(created) = created {
+        db.update_sync_state(WRAPPED_KEY_STATE_KEY, &created).map_err(|e| e.to_string())?;
+        db.flush_status_writes().map_err(|e| e.to_string())?;
+    }
+    sync_client.set_crypto_key(key.clone()).await.map_err(|e| e.to_string())?;
+    // Only a key the account uses gets a recovery code; if the server can't
+    // be asked now, the code waits for a later unlock
+    let verified = first_unlock && match sync_client.verify_account_key().await {
+        Ok(()) => true,
+        Err(e @ SyncError::KeyMismatch) => return Err(e.to_string()),
+        Err(e) => {
+            tracing::warn!("Recovery code postponed, the sync key couldn't be verified: {}", e);
+            false
+        }
+    };
+    remember_sync_key(&db, &profile, remember.then_some(&key)).await?;
+
+    if !verified {
+        return Ok(None);
+    }
+    db.update_sync_state(RECOVERY_CODE_STATE_KEY, &chrono::Utc::now().to_rfc3339())
+        .map_err(|e| e.to_string())?;
+    db.flush_status_writes().map_err(|e| e.to_string())?;
+    Ok(Some(encryption::recovery_code(&key).to_string()))
+}
+
+/// A sync_state value; empty counts as unset
+fn sync_state(db: &Database, key: &str) -> Result<Option<String>, String> {
+    Ok(db.get_sync_state(key).map_err(|e| e.to_string())?.filter(|value| !value.is_empty()))
+}
+
+/// Show the recovery code for the unlocked sync key again
+#[tauri::command]
+pub async fn get_recovery_code(
+    db: tauri::State<'_, Arc<Database>>,
+    sync_client: tauri::State<'_, SyncClient>,
+    guard: tauri::State<'_, CommandGuard>,
+) -> Result<String, String> {
+    guard.check(&db, "get_recovery_code").map_err(|e| e.to_string())?;
+    let key = sync_client.current_key().await
+        .ok_or_else(|| "Unlock the sync key to see its recovery code".to_string())?;
+    db.update_sync_state(RECOVERY_CODE_STATE_KEY, &chrono::Utc::now().to_rfc3339())
+        .map_err(|e| e.to_string())?;
+    Ok(encryption::recovery_code(&key).to_string())
+}
+
+/// Restore the sync key from its recovery code after the passphrase was
+/// forgotten; `new_passphrase` unlocks it from now on
+#[tauri::command]
+pub async fn recover_sync_key(
+    db: tauri::State<'_, Arc<Database>>,
+    sync_client: tauri::State<'_, SyncClient>,
+    guard: tauri::State<'_, CommandGuard>,
+    profile: tauri::State<'_, RunningProfile>,
+    recovery_code: String,
+    new_passphrase: String,
+    remember: bool,
+) -> Result<(), String> {
+    guard.check(&db, "recover_sync_key").map_err(|e| e.to_string())?;
+    let recovery_code = Zeroizing::new(recovery_code);
+    let new_passphrase = Zeroizing::new(new_passphrase);
+    let (key, wrapped) = tokio::task::spawn_blocking(move || encryption::recover_with_code(&recovery_code, &new_passphrase))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+    sync_client.set_crypto_key(key.clone()).await.map_err(|e| e.to_string())?;
+
+    // The next sync replaces the account's wrapped key with this one
+    db.update_sync_state(WRAPPED_KEY_STATE_KEY, &wrapped).map_err(|e| e.to_string())?;
+    db.update_sync_state(WRAPPED_KEY_PENDING_STATE_KEY, "1").map_err(|e| e.to_string())?;
+    db.update_sync_state(RECOVERY_CODE_STATE_KEY, &chrono::Utc::now().to_rfc3339())
+        .map_err(|e| e.to_string())?;
+    db.flush_status_writes().map_err(|e| e.to_string())?;
+    remember_sync_key(&db, &profile, remember.then_some(&key)).await
+}
+
+/// Change the passphrase that unlocks the sync key. The key itself stays the
+/// same, so synced data and a key remembered in the keyring stay valid; the
+/// next sync replaces the account's wrapped key, after which the old
+/// passphrase no longer unlocks it on any device.
+#[tauri::command]
+pub async fn change_passphrase(
+    db: tauri::State<'_, Arc<Database>>,
+    sync_client: tauri::State<'_, SyncClient>,
+    guard: tauri::State<'_, CommandGuard>,
+    profile: tauri::State<'_, RunningProfile>,
+    old_passphrase: String,
+    new_passphrase: String,
+) -> Result<(), String> {
+    guard.check(&db, "change_passphrase").map_err(|e| e.to_string())?;
+    let current_key = sync_client.current_key().await
+        .ok_or_else(|| "Unlock the sync key before changing its passphrase".to_string())?;
+    let old_passphrase = Zeroizing::new(old_passphrase);
+    let new_passphrase = Zeroizing::new(new_passphrase);
+    let wrapped = sync_state(&db, WRAPPED_KEY_STATE_KEY)?;
+
+    let rewrapped = tokio::task::spawn_blocking(move || {
+        encryption::change_passphrase(&current_key, &old_passphrase, &new_passphrase, wrapped.as_deref())
+    })
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+
+    // Written through at once: a lost write would leave only the old passphrase working
+    db.update_sync_state(WRAPPED_KEY_STATE_KEY, &rewrapped).map_err(|e| e.to_string())?;
+    db.update_sync_state(WRAPPED_KEY_PENDING_STATE_KEY, "1").map_err(|e| e.to_string())?;
+    db.flush_status_writes().map_err(|e| e.to_string())?;
+
+    // The key is unchanged; make sure the database is under the remembered one
+    if let Some(key) = KeyStore::new(&profile.name).load().map_err(|e| e.to_string())? {
+        db.write(move |db| db.rekey_local(&key)).await.map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Remove the sync key from the system keyring; the passphrase is asked for at the next start
+#[tauri::command]
+pub async fn forget_sync_key(
+    db: tauri::State<'_, Arc<Database>>,
+    guard: tauri::State<'_, CommandGuard>,
+    profile: tauri::State<'_, RunningProfile>,
+) -> Result<(), String> {
+    guard.check(&db, "forget_sync_key").map_err(|e| e.to_string())?;
+    remember_sync_key(&db, &profile, None).await
+}
+
+/// Remember `key` in the system keyring, or with None forget the remembered
+/// one, and key the local database to match. At the next start the database
+/// opens with the remembered key or else the default one, so it follows the
+/// keyring; the steps are ordered so it still opens if the app stops halfway.
+async fn remember_sync_key(db: &Database, profile: &RunningProfile, key: Option<&SecretKey>) -> Result<(), String> {
+    let store = KeyStore::new(&profile.name);
+    match key {
+        Some(key) => {
+            store.save(key).map_err(|e| e.to_string())?;
+            let key = key.clone();
+            db.write(move |db| db.rekey_local(&key)).await.map_err(|e| e.to_string())
+        }
+        None => {
+            db.write(|db| db.rekey_local(encryption::DEFAULT_SYNC_KEY)).await.map_err(|e| e.to_string())?;
+            store.forget().map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Whether this build can remember the sync key and whether one is remembered
+#[tauri::command]
+pub async fn get_sync_key_status(
+    profile: tauri::State<'_, RunningProfile>,
+) -> Result<KeyStoreStatus, String> {
+    KeyStore::new(&profile.name).status().map_err(|e| e.to_string())
+}
+
+fn profile_store(app: &tauri::AppHandle) -> Result<ProfileStore, String> {
+    let base_dir = app.path().app_local_data_dir().map_err(|e| e.to_string())?;
+    Ok(ProfileStore::new(&base_dir))
+}
+
+/// Every profile; `active` marks the one selected for the next start
+#[tauri::command]
+pub async fn list_profiles(app: tauri::AppHandle) -> Result<Vec<Profile>, String> {
+    profile_store(&app)?.list().map_err(|e| e.to_string())
+}
+
+/// Add a profile with its own empty database
+#[tauri::command]
+pub async fn create_profile(app: tauri::AppHandle, name: String) -> Result<Profile, String> {
+    profile_store(&app)?.create(&name).map_err(|e| e.to_string())
+}
+
+/// Name of the profile this instance is running
+#[tauri::command]
+pub async fn get_current_profile(
+    profile: tauri::State<'_, RunningProfile>,
+) -> Result<String, String> {
+    Ok(profile.name.clone())
+}
+
+/// Make `name` the active profile and relaunch into it, so the collector,
+/// sync client and schedulers restart against that profile's database. This
+/// process closes its events, flushes and releases its session lock first;
+/// the new one waits for it to exit.
+#[tauri::command]
+pub async fn switch_profile(
+    app: tauri::AppHandle,
+    db: tauri::State<'_, Arc<Database>>,
+    profile: tauri::State<'_, RunningProfile>,
+    collector: tauri::State<'_, Arc<Mutex<Collector>>>,
+    session_lock: tauri::State<'_, Arc<SessionLock>>,
+    name: String,
+) -> Result<(), String> {
+    let store = profile_store(&app)?;
+    let target = store.get(&name).map_err(|e| e.to_string())?;
+    if target.name == profile.name {
+        return store.set_active(&target.name).map_err(|e| e.to_string());
+    }
+
+    // Leave this profile as a clean exit would, before the next process starts;
+    // the selection only changes once that succeeded
+    collector.lock().await.stop_and_wait().await.map_err(|e| e.to_string())?;
+    db.close_open_events_sync(chrono::Utc::now()).map_err(|e| e.to_string())?;
+    db.flush_status_writes().map_err(|e| e.to_string())?;
+    session_lock.release().map_err(|e| e.to_string())?;
+    store.set_active(&target.name).map_err(|e| e.to_string())?;
+
+    let args = profiles::relaunch_args(std::env::args(), &target.name, std::process::id());
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    std::process::Command::new(exe)
+        .args(&args[1..])
+        .spawn()
+        .map_err(|e| format!("Failed to relaunch into profile {}: {}", target.name, e))?;
+    app.exit(0);
+    Ok(())
+}
@@ -0,0 +1,375 @@
+//! Embedded web dashboard for headless machines.
+//!
+//! Serves a single static page plus a small JSON API (summary, timeline, sync
+//! status) on localhost, backed by the same database and report code as the
+//! Tauri UI. Started with `--dashboard --db <path> [--port <port>]
+//! [--profile <name>]`; the database is opened with the sync key the profile
+//! remembers in the system keyring, if any.
+//!
+//! `/api/*` requests must send `Authorization: Bearer <token>`: either an API
+//! token with the route's scope or the launch token printed at startup, which
+//! the page reads from its `#token=` fragment. Requests addressed to any host
+//! other than localhost are refused (see `is_local_api_host`).
+
+use crate::database::{is_local_api_host, ApiScope, AppUsageTotal, Database, LaunchToken, StorageBackend, StoredEvent};
+use crate::encryption::{KeyStore, SecretKey, DEFAULT_SYNC_KEY};
+use crate::profiles::{DEFAULT_PROFILE, PROFILE_ARG};
+use crate::reports::{self, CategoryTotal, RulesMode};
+use crate::sync::{SyncClient, SyncStatus};
+use anyhow::{anyhow, Context, Result};
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{DateTime, Local, Utc};
+use serde::Deserialize;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::info;
+
+pub const DASHBOARD_ARG: &str = "--dashboard";
+const DEFAULT_PORT: u16 = 7420;
+
+const INDEX_HTML: &str = include_str!("assets/index.html");
+
+#[derive(Clone)]
+struct DashboardState {
+  db: Arc<Database>,
+  sync_client: Arc<SyncClient>,
+  port: u16,
+  launch_token: Arc<LaunchToken>,
+}
+
+/// Command-line options for headless dashboard mode
+#[derive(Debug, PartialEq)]
+pub struct DashboardOptions {
+  pub db_path: PathBuf,
+  pub port: u16,
+  /// Profile whose remembered sync key opens the database
+  pub profile: String,
+}
+
+impl DashboardOptions {
+  pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self> {
+    let mut db_path = None;
+    let mut port = DEFAULT_PORT;
+    let mut profile = DEFAULT_PROFILE.to_string();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+      match arg.as_str() {
+        "--db" => db_path = Some(PathBuf::from(args.next().context("--db requires a path")?)),
+        "--port" => {
+          port = args
+            .next()
+            .context("--port requires a value")?
+            .parse()
+            .context("Invalid --port value")?;
+        }
+        PROFILE_ARG => profile = args.next().context("--profile requires a name")?,
+        _ => {}
+      }
+    }
+
+    Ok(Self {
+      db_path: db_path.ok_or_else(|| anyhow!("--db <path> is required in dashboard mode"))?,
+      port,
+      profile,
+    })
+  }
+}
+
+/// Time range query parameters (Unix millis); defaults to the current local day
+#[derive(Debug, Default, Deserialize)]
+struct RangeQuery {
+  start: Option<i64>,
+  end: Option<i64>,
+  rules: Option<RulesMode>,
+}
+
+impl RangeQuery {
+  fn resolve(&self, now: DateTime<Local>) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+    let day_start = now
+      .date_naive()
+      .and_hms_opt(0, 0, 0)
+      .and_then(|t| t.and_local_timezone(Local).earliest())
+      .map(|t| t.with_timezone(&Utc))
+      .unwrap_or_else(|| now.with_timezone(&Utc));
+
+    let start = match self.start {
+      Some(ms) => DateTime::from_timestamp_millis(ms).context("Invalid start time")?,
+      None => day_start,
+    };
+    let end = match self.end {
+      Some(ms) => DateTime::from_timestamp_millis(ms).context("Invalid end time")?,
+      None => day_start + chrono::Duration::days(1),
+    };
+    Ok((start, end))
+  }
+}
+
+#[derive(Debug)]
+enum DashboardError {
+  Unauthorized,
+  ForeignHost,
+  Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for DashboardError {
+  fn from(e: anyhow::Error) -> Self {
+    Self::Internal(e)
+  }
+}
+
+impl IntoResponse for DashboardError {
+  fn into_response(self) -> Response {
+    match self {
+      Self::Unauthorized => (StatusCode::UNAUTHORIZED, "Missing or invalid API token").into_response(),
+      Self::ForeignHost => (StatusCode::FORBIDDEN, "The dashboard only answers requests to localhost").into_response(),
+      Self::Internal(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+  }
+}
+
+/// Require a request to localhost with the launch token or an API token granting `scope`
+fn authorize(state: &DashboardState, headers: &HeaderMap, scope: ApiScope) -> Result<(), DashboardError> {
+  let host = headers.get(header::HOST).and_then(|value| value.to_str().ok());
+  if !is_local_api_host(host, state.port) {
+    return Err(DashboardError::ForeignHost);
+  }
+
+  let token = headers
+    .get(header::AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Bearer "))
+    .map(str::trim);
+  match token {
+    Some(token) if state.launch_token.matches(token) => Ok(()),
+    Some(token) if state.db.verify_api_token(token, scope, Utc::now())? => Ok(()),
+    _ => Err(DashboardError::Unauthorized),
+  }
+}
+
+fn router(state: DashboardState) -> Router {
+  Router::new()
+    .route("/", get(index))
+    .route("/api/summary", get(summary))
+    .route("/api/apps", get(apps))
+    .route("/api/timeline", get(timeline))
+    .route("/api/sync", get(sync_status))
+    .with_state(state)
+}
+
+async fn index() -> Html<&'static str> {
+  Html(INDEX_HTML)
+}
+
+async fn summary(
+  State(state): State<DashboardState>,
+  headers: HeaderMap,
+  Query(query): Query<RangeQuery>,
+) -> Result<Json<Vec<CategoryTotal>>, DashboardError> {
+  authorize(&state, &headers, ApiScope::Summary)?;
+  let (start, end) = query.resolve(Local::now())?;
+  let totals = reports::category_totals(&state.db, start, end, query.rules.unwrap_or_default())?;
+  Ok(Json(totals))
+}
+
+async fn apps(
+  State(state): State<DashboardState>,
+  headers: HeaderMap,
+  Query(query): Query<RangeQuery>,
+) -> Result<Json<Vec<AppUsageTotal>>, DashboardError> {
+  authorize(&state, &headers, ApiScope::Summary)?;
+  let (start, end) = query.resolve(Local::now())?;
+  Ok(Json(state.db.app_usage_totals(start, end)?))
+}
+
+async fn timeline(
+  State(state): State<DashboardState>,
+  headers: HeaderMap,
+  Query(query): Query<RangeQuery>,
+) -> Result<Json<Vec<StoredEvent>>, DashboardError> {
+  authorize(&state, &headers, ApiScope::Timeline)?;
+  let (start, end) = query.resolve(Local::now())?;
+  Ok(Json(state.db.events_between(start, end)?))
+}
+
+async fn sync_status(
+  State(state): State<DashboardState>,
+  headers: HeaderMap,
+) -> Result<Json<SyncStatus>, DashboardError> {
+  authorize(&state, &headers, ApiScope::Sync)?;
+  Ok(Json(state.sync_client.get_status().await?))
+}
+
+/// Serve the dashboard on 127.0.0.1 until the process is stopped
+pub async fn serve(db: Arc<Database>, port: u16) -> Result<()> {
+  let state = DashboardState {
+    sync_client: Arc::new(SyncClient::new(db.clone())),
+    db,
+    port,
+    launch_token: Arc::new(LaunchToken::generate()),
+  };
+
+  let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+  let listener = tokio::net::TcpListener::bind(addr)
+    .await
+    .with_context(|| format!("Failed to bind dashboard to {}", addr))?;
+
+  info!("Dashboard listening on http://{}", addr);
+  // Printed rather than logged so the token stays out of log files
+  println!("Open http://{}/#token={}", addr, state.launch_token.as_str());
+  axum::serve(listener, router(state)).await?;
+  Ok(())
+}
+
+/// Entry point for headless dashboard mode (no Tauri window)
+pub fn run(options: DashboardOptions) -> Result<()> {
+  let sync_key = KeyStore::new(&options.profile)
+    .load()?
+    .unwrap_or_else(|| SecretKey::new(*DEFAULT_SYNC_KEY));
+  let db = Arc::new(Database::open_local(&options.db_path, &sync_key)?);
+  let rt = tokio::runtime::Runtime::new()?;
+  rt.block_on(serve(db, options.port))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::TimeZone;
+  use tempfile::NamedTempFile;
+
+  fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+  }
+
+  fn create_test_state() -> (DashboardState, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Arc::new(Database::new(temp_file.path()).unwrap());
+    let state = DashboardState {
+      sync_client: Arc::new(SyncClient::new(db.clone())),
+      db,
+      port: DEFAULT_PORT,
+      launch_token: Arc::new(LaunchToken::generate()),
+    };
+    (state, temp_file)
+  }
+
+  #[test]
+  fn test_options_from_args() {
+    let options = DashboardOptions::from_args(args(&["lifespan", "--dashboard", "--db", "/tmp/local.db"])).unwrap();
+    assert_eq!(options.db_path, PathBuf::from("/tmp/local.db"));
+    assert_eq!(options.port, DEFAULT_PORT);
+    assert_eq!(options.profile, DEFAULT_PROFILE);
+
+    let options = DashboardOptions::from_args(args(&["--dashboard", "--port", "8080", "--db", "a.db", "--profile", "work"])).unwrap();
+    assert_eq!(options.port, 8080);
+    assert_eq!(options.profile, "work");
+
+    assert!(DashboardOptions::from_args(args(&["--dashboard"])).is_err());
+    assert!(DashboardOptions::from_args(args(&["--db", "a.db", "--port", "x"])).is_err());
+  }
+
+  #[test]
+  fn test_range_defaults_to_local_day() {
+    let now = Local.with_ymd_and_hms(2024, 6, 15, 15, 30, 0).unwrap();
+    let (start, end) = RangeQuery::default().resolve(now).unwrap();
+
+    assert_eq!(start.with_timezone(&Local), Local.with_ymd_and_hms(2024, 6, 15, 0, 0, 0).unwrap());
+    assert_eq!(end - start, chrono::Duration::days(1));
+
+    let explicit = RangeQuery { start: Some(1_000), end: Some(2_000), rules: None };
+    let (start, end) = explicit.resolve(now).unwrap();
+    assert_eq!(start.timestamp_millis(), 1_000);
+    assert_eq!(end.timestamp_millis(), 2_000);
+  }
+
+  #[tokio::test]
+  async fn test_api_handlers() {
+    let (state, _temp) = create_test_state();
+    let id = state
+      .db
+      .store_event_sync(&crate::collector::window_tracker::WindowInfo {
+        process_name: "code.exe".to_string(),
+        window_title: "main.rs".to_string(),
+        timestamp: Utc::now(),
+        ..Default::default()
+      })
+      .unwrap();
+    state.db.close_event_sync(&id, Utc::now()).unwrap();
+
+    let launch = bearer(state.launch_token.as_str());
+    let Json(events) = timeline(State(state.clone()), launch.clone(), Query(RangeQuery::default())).await.unwrap();
+    assert_eq!(events.len(), 1);
+
+    let Json(totals) = summary(State(state.clone()), launch.clone(), Query(RangeQuery::default())).await.unwrap();
+    assert_eq!(totals[0].category, "development");
+
+    let Json(apps) = apps(State(state.clone()), launch.clone(), Query(RangeQuery::default())).await.unwrap();
+    assert_eq!(apps[0].app_name, "code.exe");
+
+    let Json(status) = sync_status(State(state), launch).await.unwrap();
+    assert_eq!(status.pending_events, 1);
+  }
+
+  /// Headers of a request to the dashboard on localhost
+  fn local_request() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::HOST, format!("127.0.0.1:{}", DEFAULT_PORT).parse().unwrap());
+    headers
+  }
+
+  fn bearer(token: &str) -> HeaderMap {
+    let mut headers = local_request();
+    headers.insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+    headers
+  }
+
+  #[tokio::test]
+  async fn test_token_required_before_any_is_created() {
+    let (state, _temp) = create_test_state();
+    assert!(state.db.list_api_tokens().unwrap().is_empty());
+
+    let result = timeline(State(state.clone()), local_request(), Query(RangeQuery::default())).await;
+    assert!(matches!(result, Err(DashboardError::Unauthorized)));
+    let result = sync_status(State(state.clone()), bearer("lst_bogus_token")).await;
+    assert!(matches!(result, Err(DashboardError::Unauthorized)));
+
+    // A rebound host name is refused even with the right token
+    let mut rebound = bearer(state.launch_token.as_str());
+    rebound.insert(header::HOST, format!("attacker.example:{}", DEFAULT_PORT).parse().unwrap());
+    let result = timeline(State(state.clone()), rebound, Query(RangeQuery::default())).await;
+    assert!(matches!(result, Err(DashboardError::ForeignHost)));
+    let mut no_host = bearer(state.launch_token.as_str());
+    no_host.remove(header::HOST);
+    let result = summary(State(state), no_host, Query(RangeQuery::default())).await;
+    assert!(matches!(result, Err(DashboardError::ForeignHost)));
+  }
+
+  #[tokio::test]
+  async fn test_api_token_scopes() {
+    let (state, _temp) = create_test_state();
+    let created = state.db.create_api_token("widget", &[ApiScope::Summary], None).unwrap();
+
+    let result = summary(State(state.clone()), local_request(), Query(RangeQuery::default())).await;
+    assert!(matches!(result, Err(DashboardError::Unauthorized)));
+
+    assert!(summary(State(state.clone()), bearer(&created.token), Query(RangeQuery::default())).await.is_ok());
+    assert!(apps(State(state.clone()), bearer(&created.token), Query(RangeQuery::default())).await.is_ok());
+
+    // Out of scope
+    let result = timeline(State(state.clone()), bearer(&created.token), Query(RangeQuery::default())).await;
+    assert!(matches!(result, Err(DashboardError::Unauthorized)));
+    let result = sync_status(State(state), bearer("lst_bogus_token")).await;
+    assert!(matches!(result, Err(DashboardError::Unauthorized)));
+  }
+
+  #[tokio::test]
+  async fn test_index_serves_page() {
+    let Html(page) = index().await;
+    assert!(page.contains("/api/summary"));
+  }
+}
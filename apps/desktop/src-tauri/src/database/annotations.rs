@@ -0,0 +1,94 @@
+//! One-line notes the user attaches to tracked time (e.g. self-report answers).
+
+use super::Database;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Longest note accepted, in characters
+const MAX_ANNOTATION_CHARS: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Annotation {
+  pub id: String,
+  /// Event (time block) the note describes, if one was active
+  pub event_id: Option<String>,
+  pub text: String,
+  pub created_at: DateTime<Utc>,
+}
+
+impl Database {
+  pub fn add_annotation(&self, event_id: Option<&str>, text: &str, created_at: DateTime<Utc>) -> Result<Annotation> {
+    let text = text.trim();
+    if text.is_empty() {
+      bail!("Annotation cannot be empty");
+    }
+    if text.chars().count() > MAX_ANNOTATION_CHARS {
+      bail!("Annotation is longer than {} characters", MAX_ANNOTATION_CHARS);
+    }
+
+    let annotation = Annotation {
+      id: uuid::Uuid::new_v4().to_string(),
+      event_id: event_id.map(str::to_string),
+      text: text.to_string(),
+      created_at,
+    };
+
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      "INSERT INTO annotations (id, event_id, text, created_at) VALUES (?1, ?2, ?3, ?4)",
+      (&annotation.id, &annotation.event_id, &annotation.text, created_at.timestamp_millis()),
+    )?;
+    Ok(annotation)
+  }
+
+  /// Annotations created in [start, end), oldest first
+  pub fn get_annotations_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<Annotation>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      "SELECT id, event_id, text, created_at FROM annotations WHERE created_at >= ?1 AND created_at < ?2 ORDER BY created_at",
+    )?;
+    let rows = stmt.query_map((start.timestamp_millis(), end.timestamp_millis()), |row| {
+      Ok(Annotation {
+        id: row.get(0)?,
+        event_id: row.get(1)?,
+        text: row.get(2)?,
+        created_at: DateTime::from_timestamp_millis(row.get(3)?).unwrap_or_default(),
+      })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::{Duration, TimeZone};
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  #[test]
+  fn test_add_and_query() {
+    let (db, _temp) = create_test_db();
+    let at = Utc.with_ymd_and_hms(2024, 6, 10, 9, 30, 0).unwrap();
+
+    let note = db.add_annotation(Some("event-1"), "  Reviewing the sync PR ", at).unwrap();
+    db.add_annotation(None, "Standup", at + Duration::hours(2)).unwrap();
+
+    assert_eq!(note.text, "Reviewing the sync PR");
+    assert_eq!(db.get_annotations_between(at, at + Duration::hours(1)).unwrap(), vec![note]);
+    assert_eq!(db.get_annotations_between(at, at + Duration::days(1)).unwrap().len(), 2);
+  }
+
+  #[test]
+  fn test_invalid_text_rejected() {
+    let (db, _temp) = create_test_db();
+    assert!(db.add_annotation(None, "   ", Utc::now()).is_err());
+    assert!(db.add_annotation(None, &"x".repeat(501), Utc::now()).is_err());
+  }
+}
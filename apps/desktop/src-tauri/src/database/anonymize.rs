@@ -0,0 +1,576 @@
+//! Anonymized storage for shared or managed machines.
+//!
+//! While it is on, app names and window titles are written as keyed hashes
+//! ("anon:<hex>", HMAC-SHA256 under a subkey of the sync key that is never
+//! stored), so neither the database file nor sync payloads contain them in
+//! readable form, and they can't be confirmed by hashing guesses without the
+//! key. Equal values hash alike, so per-app totals still work. Each hash's
+//! readable value is kept in `anonymized_values`, encrypted with a key derived
+//! from the sync key, for display on this device only; when the sync key
+//! changes, every hash is recomputed from it. Categories are assigned from the
+//! readable name before it is hashed.
+//!
+//! Turning it on also hashes the activity already recorded (events, rollups,
+//! downsampled daily usage and other devices' pulled events). Domains,
+//! documents and executable paths are controlled by their own capture
+//! settings and are not hashed.
+
+use super::Database;
+use crate::encryption::CryptoManager;
+use anyhow::{bail, Result};
+use hmac::{Hmac, Mac};
+use rusqlite::{Connection, OptionalExtension};
+use sha2::Sha256;
+use std::collections::HashMap;
+
+pub const ANONYMIZED_STORAGE_SETTING: &str = "anonymized_storage";
+
+/// sync_state key of the salt earlier versions hashed with, kept in plaintext;
+/// dropped once their hashes are recomputed
+const LEGACY_SALT_KEY: &str = "anonymization_salt";
+
+const TOKEN_PREFIX: &str = "anon:";
+
+/// Purpose string the display lookup key was derived with before HKDF subkeys
+pub(super) const LEGACY_DISPLAY_KEY_PURPOSE: &str = "lifespan display names";
+
+/// Whether a stored app name or title is a hash rather than the real value
+pub fn is_anonymized(value: &str) -> bool {
+  value.starts_with(TOKEN_PREFIX)
+}
+
+/// The hash stored in place of `value` under `hash_key`
+fn hash_token(hash_key: &[u8; 32], value: &str) -> String {
+  let mut mac = Hmac::<Sha256>::new_from_slice(hash_key).expect("HMAC takes keys of any length");
+  mac.update(value.as_bytes());
+  format!("{}{}", TOKEN_PREFIX, hex::encode(&mac.finalize().into_bytes()[..16]))
+}
+
+/// Hashes values for storage and records how to display them
+pub(crate) struct Anonymizer {
+  hash_key: [u8; 32],
+  lookup: Option<CryptoManager>,
+}
+
+impl Drop for Anonymizer {
+  fn drop(&mut self) {
+    zeroize::Zeroize::zeroize(&mut self.hash_key);
+  }
+}
+
+impl Anonymizer {
+  /// The anonymizer to write with on `conn`, or None when the mode is off.
+  /// While it is on, writing without the hash key is an error rather than
+  /// storing readable names.
+  pub(super) fn load(conn: &Connection, display_key: Option<&[u8; 32]>, hash_key: Option<&[u8; 32]>) -> Result<Option<Self>> {
+    let enabled: Option<String> = conn
+      .query_row("SELECT value FROM local_settings WHERE key = ?1", [ANONYMIZED_STORAGE_SETTING], |row| row.get(0))
+      .optional()?;
+    if enabled.as_deref() != Some("true") {
+      return Ok(None);
+    }
+    let Some(hash_key) = hash_key else {
+      bail!("Anonymized storage needs the app's key to hash names");
+    };
+
+    Ok(Some(Self {
+      hash_key: *hash_key,
+      lookup: display_key.map(CryptoManager::new).transpose()?,
+    }))
+  }
+
+  /// The value to store in place of `value`; already hashed values pass through
+  pub(super) fn token(&self, conn: &Connection, value: &str) -> Result<String> {
+    if is_anonymized(value) {
+      return Ok(value.to_string());
+    }
+
+    let token = hash_token(&self.hash_key, value);
+    if let Some(lookup) = &self.lookup {
+      conn.execute(
+        "INSERT OR IGNORE INTO anonymized_values (token, value) VALUES (?1, ?2)",
+        (&token, lookup.encrypt_to_base64(value.as_bytes())?),
+      )?;
+    }
+    Ok(token)
+  }
+
+  /// `token` for an optional title; empty titles stay empty
+  pub(super) fn title_token(&self, conn: &Connection, title: Option<&str>) -> Result<Option<String>> {
+    title.filter(|title| !title.is_empty()).map(|title| self.token(conn, title)).transpose()
+  }
+}
+
+/// App name and title as stored: hashed while `anonymizer` is set, and an
+/// empty title as None
+pub(super) fn stored_names(
+  anonymizer: Option<&Anonymizer>,
+  conn: &Connection,
+  app_name: &str,
+  title: Option<&str>,
+) -> Result<(String, Option<String>)> {
+  match anonymizer {
+    Some(anonymizer) => Ok((anonymizer.token(conn, app_name)?, anonymizer.title_token(conn, title)?)),
+    None => Ok((app_name.to_string(), title.filter(|title| !title.is_empty()).map(str::to_string))),
+  }
+}
+
+/// Move everything stored under the hash `from` to `to`: events, other
+/// devices' events, rollups, daily usage and the display lookup
+fn rename_token(conn: &Connection, from: &str, to: &str) -> Result<()> {
+  conn.execute(
+    "INSERT OR IGNORE INTO anonymized_values (token, value) SELECT ?2, value FROM anonymized_values WHERE token = ?1",
+    (from, to),
+  )?;
+  conn.execute("DELETE FROM anonymized_values WHERE token = ?1", [from])?;
+
+  // Triggers move the rollups and the search index along with each event
+  conn.execute("UPDATE local_events SET app_name = ?2 WHERE app_name = ?1", (from, to))?;
+  conn.execute("UPDATE local_events SET window_title = ?2 WHERE window_title = ?1", (from, to))?;
+  conn.execute("UPDATE remote_events SET app_name = ?2 WHERE app_name = ?1", (from, to))?;
+  conn.execute("UPDATE remote_events SET title = ?2 WHERE title = ?1", (from, to))?;
+  move_app_totals(conn, from, to)
+}
+
+/// Merge the rollup and daily usage rows of `from` into those of `to`
+fn move_app_totals(conn: &Connection, from: &str, to: &str) -> Result<()> {
+  conn.execute(
+    r#"
+    INSERT INTO usage_rollups (granularity, bucket_start, app_name, process_path, duration_seconds, event_count)
+    SELECT granularity, bucket_start, ?2, process_path, duration_seconds, event_count
+    FROM usage_rollups WHERE app_name = ?1 AND true
+    ON CONFLICT (granularity, bucket_start, app_name, process_path) DO UPDATE SET
+      duration_seconds = duration_seconds + excluded.duration_seconds,
+      event_count = event_count + excluded.event_count
+    "#,
+    (from, to),
+  )?;
+  conn.execute("DELETE FROM usage_rollups WHERE app_name = ?1", [from])?;
+
+  conn.execute(
+    r#"
+    INSERT INTO daily_app_usage (day, app_name, duration_seconds, event_count)
+    SELECT day, ?2, duration_seconds, event_count
+    FROM daily_app_usage WHERE app_name = ?1 AND true
+    ON CONFLICT (day, app_name) DO UPDATE SET
+      duration_seconds = duration_seconds + excluded.duration_seconds,
+      event_count = event_count + excluded.event_count
+    "#,
+    (from, to),
+  )?;
+  conn.execute("DELETE FROM daily_app_usage WHERE app_name = ?1", [from])?;
+  Ok(())
+}
+
+impl Database {
+  pub fn is_anonymized_storage(&self) -> Result<bool> {
+    Ok(self.get_setting(ANONYMIZED_STORAGE_SETTING)?.as_deref() == Some("true"))
+  }
+
+  /// Turn anonymized storage on or off. Turning it on hashes the activity
+  /// already recorded and returns how many events were rewritten; turning it
+  /// off only affects new events.
+  pub fn set_anonymized_storage(&self, enabled: bool) -> Result<usize> {
+    if !enabled {
+      self.set_setting(ANONYMIZED_STORAGE_SETTING, "false")?;
+      return Ok(0);
+    }
+    if self.display_key().is_none() || self.hash_key().is_none() {
+      bail!("Anonymized storage needs the app's key to keep names displayable");
+    }
+    self.set_setting(ANONYMIZED_STORAGE_SETTING, "true")?;
+
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    let anonymizer = Anonymizer::load(&tx, self.display_key().as_deref(), self.hash_key().as_deref())?
+      .expect("anonymized storage was just enabled");
+
+    // Triggers move the rollups and the search index along with each event
+    let events = {
+      let mut stmt = tx.prepare(
+        "SELECT id, app_name, window_title FROM local_events WHERE app_name NOT LIKE 'anon:%' OR window_title NOT LIKE 'anon:%'",
+      )?;
+      let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?))
+      })?;
+      rows.collect::<Result<Vec<_>, _>>()?
+    };
+    {
+      let mut update = tx.prepare_cached("UPDATE local_events SET app_name = ?2, window_title = ?3 WHERE id = ?1")?;
+      for (id, app_name, title) in &events {
+        let app_name = anonymizer.token(&tx, app_name)?;
+        let title = anonymizer.title_token(&tx, title.as_deref())?;
+        update.execute((id, app_name, title))?;
+      }
+    }
+
+    // Other devices' events, pulled readable
+    let remote = {
+      let mut stmt = tx.prepare(
+        "SELECT id, app_name, title FROM remote_events WHERE app_name NOT LIKE 'anon:%' OR title NOT LIKE 'anon:%'",
+      )?;
+      let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?, row.get::<_, Option<String>>(2)?))
+      })?;
+      rows.collect::<Result<Vec<_>, _>>()?
+    };
+    {
+      let mut update = tx.prepare_cached("UPDATE remote_events SET app_name = ?2, title = ?3 WHERE id = ?1")?;
+      for (id, app_name, title) in &remote {
+        let app_name = anonymizer.title_token(&tx, app_name.as_deref())?;
+        let title = anonymizer.title_token(&tx, title.as_deref())?;
+        update.execute((id, app_name, title))?;
+      }
+    }
+
+    // Rollup rows left under readable names (emptied above, or outliving their events)
+    let mut readable_apps = distinct_readable_apps(&tx, "usage_rollups")?;
+    readable_apps.extend(distinct_readable_apps(&tx, "daily_app_usage")?);
+    readable_apps.sort_unstable();
+    readable_apps.dedup();
+    for app_name in readable_apps {
+      move_app_totals(&tx, &app_name, &anonymizer.token(&tx, &app_name)?)?;
+    }
+
+    // Rewrite the index so no segment keeps the old terms
+    tx.execute_batch("INSERT INTO events_fts (events_fts) VALUES ('optimize');")?;
+    tx.commit()?;
+    Ok(events.len())
+  }
+
+  /// Readable values of the hashed names and titles among `values`, keyed by
+  /// hash; values that aren't hashes or can't be resolved are left out
+  pub fn resolve_display_names(&self, values: &[String]) -> Result<HashMap<String, String>> {
+    let Some(key) = self.display_key() else {
+      return Ok(HashMap::new());
+    };
+    let crypto = CryptoManager::new(&key)?;
+
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached("SELECT value FROM anonymized_values WHERE token = ?1")?;
+    let mut names = HashMap::new();
+    for token in values.iter().filter(|value| is_anonymized(value)) {
+      let Some(encrypted) = stmt.query_row([token], |row| row.get::<_, String>(0)).optional()? else {
+        continue;
+      };
+      if let Ok(value) = crypto.decrypt_from_base64(&encrypted) {
+        names.insert(token.clone(), String::from_utf8_lossy(&value).into_owned());
+      }
+    }
+    Ok(names)
+  }
+}
+
+impl Database {
+  /// Recompute the hashes whose readable value is known under the current
+  /// keys, after the sync key changed or from the salted hashes of earlier
+  /// versions, and move what is stored under them; returns how many changed
+  pub(super) fn rehash_anonymized_values(&self) -> Result<usize> {
+    let (Some(display_key), Some(hash_key)) = (self.display_key(), self.hash_key()) else {
+      return Ok(0);
+    };
+    let crypto = CryptoManager::new(&display_key)?;
+
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    let rows: Vec<(String, String)> = tx
+      .prepare("SELECT token, value FROM anonymized_values")?
+      .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+      .collect::<Result<_, _>>()?;
+
+    let mut renamed = 0;
+    for (token, encrypted) in rows {
+      let Ok(value) = crypto.decrypt_from_base64(&encrypted) else {
+        continue;
+      };
+      let rehashed = hash_token(&hash_key, &String::from_utf8_lossy(&value));
+      if rehashed != token {
+        rename_token(&tx, &token, &rehashed)?;
+        renamed += 1;
+      }
+    }
+    tx.execute("DELETE FROM sync_state WHERE key = ?1", [LEGACY_SALT_KEY])?;
+    tx.commit()?;
+    Ok(renamed)
+  }
+
+  /// Re-encrypt readable values still under `legacy_key` (the pre-HKDF
+  /// display key, or the one in use before a re-key) with the current one;
+  /// returns how many were moved
+  pub(super) fn reencrypt_display_names(&self, legacy_key: &[u8; 32]) -> Result<usize> {
+    let Some(key) = self.display_key() else {
+      return Ok(0);
+    };
+    let crypto = CryptoManager::new(&key)?;
+    let legacy = CryptoManager::new(legacy_key)?;
+
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    let rows: Vec<(String, String)> = tx
+      .prepare("SELECT token, value FROM anonymized_values")?
+      .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+      .collect::<Result<_, _>>()?;
+
+    let mut moved = 0;
+    for (token, encrypted) in rows {
+      if crypto.decrypt_from_base64(&encrypted).is_ok() {
+        continue;
+      }
+      if let Ok(value) = legacy.decrypt_from_base64(&encrypted) {
+        tx.execute(
+          "UPDATE anonymized_values SET value = ?2 WHERE token = ?1",
+          (&token, crypto.encrypt_to_base64(&value)?),
+        )?;
+        moved += 1;
+      }
+    }
+    tx.commit()?;
+    Ok(moved)
+  }
+}
+
+fn distinct_readable_apps(conn: &Connection, table: &str) -> Result<Vec<String>> {
+  let mut stmt = conn.prepare(&format!("SELECT DISTINCT app_name FROM {} WHERE app_name NOT LIKE 'anon:%'", table))?;
+  let rows = stmt.query_map([], |row| row.get(0))?;
+  rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use crate::database::RemoteEvent;
+  use crate::encryption::{derive_key, derive_subkey, KeyPurpose, DEFAULT_SYNC_KEY};
+  use chrono::Utc;
+  use sha2::Digest;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::open_local(temp_file.path(), DEFAULT_SYNC_KEY).unwrap();
+    (db, temp_file)
+  }
+
+  fn window(app: &str, title: &str) -> WindowInfo {
+    WindowInfo {
+      process_name: app.to_string(),
+      window_title: title.to_string(),
+      timestamp: Utc::now(),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn test_names_under_the_legacy_key_are_moved_on_open() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let token = {
+      let db = Database::open_local(temp_file.path(), DEFAULT_SYNC_KEY).unwrap();
+      db.set_anonymized_storage(true).unwrap();
+      let id = db.store_event_sync(&window("code.exe", "")).unwrap();
+      let token = db.get_event(&id).unwrap().unwrap().app_name;
+
+      // As an older version would have stored it
+      let legacy = CryptoManager::new(&derive_key(DEFAULT_SYNC_KEY, LEGACY_DISPLAY_KEY_PURPOSE)).unwrap();
+      let conn = db.conn.lock().unwrap();
+      conn
+        .execute(
+          "UPDATE anonymized_values SET value = ?2 WHERE token = ?1",
+          (&token, legacy.encrypt_to_base64(b"code.exe").unwrap()),
+        )
+        .unwrap();
+      token
+    };
+
+    let db = Database::open_local(temp_file.path(), DEFAULT_SYNC_KEY).unwrap();
+    let names = db.resolve_display_names(&[token.clone()]).unwrap();
+    assert_eq!(names.get(&token).map(String::as_str), Some("code.exe"));
+    assert_eq!(db.reencrypt_display_names(&derive_key(DEFAULT_SYNC_KEY, LEGACY_DISPLAY_KEY_PURPOSE)).unwrap(), 0);
+  }
+
+  #[test]
+  fn test_names_follow_the_database_to_a_new_key() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let user_key: &[u8; 32] = b"user_key_32_bytes_long_123456789";
+    let (id, old_token) = {
+      let db = Database::open_local(temp_file.path(), DEFAULT_SYNC_KEY).unwrap();
+      db.set_anonymized_storage(true).unwrap();
+      let id = db.store_event_sync(&window("code.exe", "")).unwrap();
+      db.close_event_sync(&id, Utc::now() + chrono::Duration::minutes(5)).unwrap();
+      let old_token = db.get_event(&id).unwrap().unwrap().app_name;
+
+      db.rekey_local(user_key).unwrap();
+      let event = db.get_event(&id).unwrap().unwrap();
+      assert_ne!(event.app_name, old_token);
+      let names = db.resolve_display_names(&[event.app_name.clone()]).unwrap();
+      assert_eq!(names.get(&event.app_name).map(String::as_str), Some("code.exe"));
+      (id, old_token)
+    };
+
+    let db = Database::open_local(temp_file.path(), user_key).unwrap();
+    let event = db.get_event(&id).unwrap().unwrap();
+    assert_eq!(event.app_name, hash_token(&derive_subkey(user_key, KeyPurpose::AnonymizedNames, None), "code.exe"));
+    let names = db.resolve_display_names(&[event.app_name.clone(), old_token]).unwrap();
+    assert_eq!(names.get(&event.app_name).map(String::as_str), Some("code.exe"));
+    assert_eq!(names.len(), 1);
+
+    // The app's total moved to the new hash with it
+    let conn = db.conn.lock().unwrap();
+    let total: i64 = conn
+      .query_row(
+        "SELECT SUM(duration_seconds) FROM usage_rollups WHERE granularity = 'day' AND app_name = ?1",
+        [&event.app_name],
+        |row| row.get(0),
+      )
+      .unwrap();
+    assert_eq!(total, i64::from(event.duration));
+  }
+
+  #[test]
+  fn test_hashes_need_the_key() {
+    let (db, _temp) = create_test_db();
+    db.set_anonymized_storage(true).unwrap();
+    let id = db.store_event_sync(&window("code.exe", "")).unwrap();
+    let token = db.get_event(&id).unwrap().unwrap().app_name;
+
+    // Not a plain hash of the name that a guess could be checked against
+    let unkeyed = format!("{}{}", TOKEN_PREFIX, hex::encode(&Sha256::digest(b"code.exe")[..16]));
+    assert_ne!(token, unkeyed);
+    assert_eq!(token, hash_token(&db.hash_key().unwrap(), "code.exe"));
+
+    // Nothing the hashes could be recomputed from is stored
+    let conn = db.conn.lock().unwrap();
+    let salts: i64 = conn
+      .query_row("SELECT COUNT(*) FROM sync_state WHERE key = ?1", [LEGACY_SALT_KEY], |row| row.get(0))
+      .unwrap();
+    assert_eq!(salts, 0);
+  }
+
+  #[test]
+  fn test_salted_hashes_of_earlier_versions_are_rehashed_on_open() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let salted = format!("{}{}", TOKEN_PREFIX, "0".repeat(32));
+    let id = {
+      let db = Database::open_local(temp_file.path(), DEFAULT_SYNC_KEY).unwrap();
+      db.set_anonymized_storage(true).unwrap();
+      let id = db.store_event_sync(&window("code.exe", "")).unwrap();
+      let token = db.get_event(&id).unwrap().unwrap().app_name;
+
+      // As an older version would have stored it
+      let conn = db.conn.lock().unwrap();
+      conn
+        .execute("INSERT INTO sync_state (key, value, updated_at) VALUES (?1, 'salt', 0)", [LEGACY_SALT_KEY])
+        .unwrap();
+      rename_token(&conn, &token, &salted).unwrap();
+      id
+    };
+
+    let db = Database::open_local(temp_file.path(), DEFAULT_SYNC_KEY).unwrap();
+    let event = db.get_event(&id).unwrap().unwrap();
+    assert_eq!(event.app_name, hash_token(&db.hash_key().unwrap(), "code.exe"));
+    let conn = db.conn.lock().unwrap();
+    let salts: i64 = conn
+      .query_row("SELECT COUNT(*) FROM sync_state WHERE key = ?1", [LEGACY_SALT_KEY], |row| row.get(0))
+      .unwrap();
+    assert_eq!(salts, 0);
+  }
+
+  #[test]
+  fn test_pulled_events_are_hashed() {
+    let (db, _temp) = create_test_db();
+    db.set_anonymized_storage(true).unwrap();
+
+    let pulled = RemoteEvent {
+      id: "pulled".to_string(),
+      device_id: "laptop".to_string(),
+      event_type: "app_usage".to_string(),
+      timestamp: Utc::now(),
+      duration: 60,
+      app_name: Some("chrome.exe".to_string()),
+      title: Some("Bank statement".to_string()),
+      category: None,
+      domain: None,
+      remote_session: false,
+    };
+    db.store_remote_events(&[pulled], Utc::now()).unwrap();
+
+    let conn = db.conn.lock().unwrap();
+    let (app_name, title): (String, String) = conn
+      .query_row("SELECT app_name, title FROM remote_events WHERE id = 'pulled'", [], |row| {
+        Ok((row.get(0)?, row.get(1)?))
+      })
+      .unwrap();
+    drop(conn);
+    assert!(is_anonymized(&app_name));
+    assert!(is_anonymized(&title));
+    let names = db.resolve_display_names(&[title.clone()]).unwrap();
+    assert_eq!(names.get(&title).map(String::as_str), Some("Bank statement"));
+  }
+
+  #[test]
+  fn test_new_events_are_hashed_and_resolvable() {
+    let (db, _temp) = create_test_db();
+    assert!(!db.is_anonymized_storage().unwrap());
+    db.set_anonymized_storage(true).unwrap();
+
+    let first = db.store_event_sync(&window("code.exe", "salary review.xlsx")).unwrap();
+    let second = db.store_event_sync(&window("code.exe", "")).unwrap();
+    let first = db.get_event(&first).unwrap().unwrap();
+    let second = db.get_event(&second).unwrap().unwrap();
+
+    assert!(is_anonymized(&first.app_name));
+    assert_eq!(first.app_name, second.app_name);
+    assert!(is_anonymized(first.window_title.as_deref().unwrap()));
+    assert_eq!(second.window_title, None);
+    // Categorized from the readable name
+    assert_eq!(first.category.as_deref(), Some("development"));
+
+    let names = db
+      .resolve_display_names(&[first.app_name.clone(), first.window_title.clone().unwrap(), "plain".to_string()])
+      .unwrap();
+    assert_eq!(names.get(&first.app_name).map(String::as_str), Some("code.exe"));
+    assert_eq!(names.get(first.window_title.as_ref().unwrap()).map(String::as_str), Some("salary review.xlsx"));
+    assert_eq!(names.len(), 2);
+
+    // The lookup table holds no readable values either
+    let conn = db.conn.lock().unwrap();
+    let leaked: i64 = conn
+      .query_row("SELECT COUNT(*) FROM anonymized_values WHERE value LIKE '%salary%'", [], |row| row.get(0))
+      .unwrap();
+    assert_eq!(leaked, 0);
+  }
+
+  #[test]
+  fn test_enabling_hashes_recorded_activity() {
+    let (db, _temp) = create_test_db();
+    let id = db.store_event_sync(&window("chrome.exe", "Bank statement")).unwrap();
+    db.close_event_sync(&id, Utc::now() + chrono::Duration::minutes(5)).unwrap();
+
+    assert_eq!(db.set_anonymized_storage(true).unwrap(), 1);
+
+    let event = db.get_event(&id).unwrap().unwrap();
+    assert!(is_anonymized(&event.app_name));
+    assert!(db.search_events("Bank", None, None, 10).unwrap().is_empty());
+    let conn = db.conn.lock().unwrap();
+    let readable: i64 = conn
+      .query_row("SELECT COUNT(*) FROM usage_rollups WHERE app_name NOT LIKE 'anon:%'", [], |row| row.get(0))
+      .unwrap();
+    assert_eq!(readable, 0);
+    let total: i64 = conn
+      .query_row("SELECT SUM(duration_seconds) FROM usage_rollups WHERE granularity = 'day'", [], |row| row.get(0))
+      .unwrap();
+    assert_eq!(total, i64::from(event.duration));
+  }
+
+  #[test]
+  fn test_turning_off_keeps_new_events_readable() {
+    let (db, _temp) = create_test_db();
+    db.set_anonymized_storage(true).unwrap();
+    db.set_anonymized_storage(false).unwrap();
+
+    let id = db.store_event_sync(&window("code.exe", "notes")).unwrap();
+    assert_eq!(db.get_event(&id).unwrap().unwrap().app_name, "code.exe");
+
+    let temp_file = NamedTempFile::new().unwrap();
+    assert!(Database::new(temp_file.path()).unwrap().set_anonymized_storage(true).is_err());
+  }
+}
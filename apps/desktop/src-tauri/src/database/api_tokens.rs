@@ -0,0 +1,304 @@
+//! Scoped tokens for third-party integrations using the local API.
+//!
+//! Each token is a `local_settings` row keyed `api_token:<id>` holding its
+//! metadata and the SHA-256 of the secret; the plaintext is returned once at
+//! creation and never stored. Tokens look like `lst_<id>_<secret>`.
+//!
+//! The local servers (dashboard, heartbeat listener) always require a token
+//! and only answer requests addressed to `127.0.0.1:<port>` or
+//! `localhost:<port>`, so a web page can't reach them through DNS rebinding.
+
+use super::Database;
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub(crate) const API_TOKEN_KEY_PREFIX: &str = "api_token:";
+const TOKEN_PREFIX: &str = "lst";
+
+/// What a token may do with the local API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiScope {
+  /// Category and per-app totals
+  Summary,
+  /// Individual events, including window titles
+  Timeline,
+  /// Sync status
+  Sync,
+  /// Send editor heartbeats and shell command pings
+  Heartbeats,
+}
+
+/// Token metadata; never includes the secret
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiToken {
+  pub id: String,
+  pub name: String,
+  pub scopes: Vec<ApiScope>,
+  pub created_at: DateTime<Utc>,
+  pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+  fn is_expired(&self, now: DateTime<Utc>) -> bool {
+    self.expires_at.is_some_and(|expires_at| now >= expires_at)
+  }
+}
+
+/// A newly created token; `token` is shown to the user once
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedApiToken {
+  pub token: String,
+  #[serde(flatten)]
+  pub info: ApiToken,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredApiToken {
+  #[serde(flatten)]
+  info: ApiToken,
+  secret_hash: String,
+}
+
+fn random_hex(bytes: usize) -> String {
+  let mut buf = vec![0u8; bytes];
+  OsRng.fill_bytes(&mut buf);
+  hex::encode(buf)
+}
+
+fn hash_secret(secret: &str) -> String {
+  hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// Compare without exiting early on the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Split `lst_<id>_<secret>` into id and secret
+fn parse_token(token: &str) -> Option<(&str, &str)> {
+  let rest = token.strip_prefix(TOKEN_PREFIX)?.strip_prefix('_')?;
+  let (id, secret) = rest.split_once('_')?;
+  (!id.is_empty() && !secret.is_empty()).then_some((id, secret))
+}
+
+/// Whether a request's Host header names the local server on `port`.
+/// Rebinding attacks send the attacker's own host name, which this rejects.
+#[cfg_attr(not(any(feature = "dashboard", feature = "editor-heartbeats")), allow(dead_code))]
+pub fn is_local_api_host(host: Option<&str>, port: u16) -> bool {
+  let Some(host) = host else {
+    return false;
+  };
+  let Some((name, host_port)) = host.rsplit_once(':') else {
+    return false;
+  };
+  host_port == port.to_string() && (name == "127.0.0.1" || name.eq_ignore_ascii_case("localhost"))
+}
+
+/// A secret that lasts until the process exits, for a local server to hand to
+/// whoever started it; it grants every scope
+#[cfg_attr(not(feature = "dashboard"), allow(dead_code))]
+pub struct LaunchToken(String);
+
+#[cfg_attr(not(feature = "dashboard"), allow(dead_code))]
+impl LaunchToken {
+  pub fn generate() -> Self {
+    Self(format!("{}_launch_{}", TOKEN_PREFIX, random_hex(32)))
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+
+  pub fn matches(&self, token: &str) -> bool {
+    constant_time_eq(self.0.as_bytes(), token.as_bytes())
+  }
+}
+
+impl Database {
+  /// Create a token; with `expires_in_days` it stops working after that many days
+  pub fn create_api_token(
+    &self,
+    name: &str,
+    scopes: &[ApiScope],
+    expires_in_days: Option<u32>,
+  ) -> Result<CreatedApiToken> {
+    let name = name.trim();
+    if name.is_empty() {
+      bail!("Token name cannot be empty");
+    }
+    if scopes.is_empty() {
+      bail!("Token needs at least one scope");
+    }
+    if expires_in_days == Some(0) {
+      bail!("Token expiry must be at least one day");
+    }
+
+    let mut unique_scopes = Vec::new();
+    for scope in scopes {
+      if !unique_scopes.contains(scope) {
+        unique_scopes.push(*scope);
+      }
+    }
+    let now = Utc::now();
+    let info = ApiToken {
+      id: random_hex(4),
+      name: name.to_string(),
+      scopes: unique_scopes,
+      created_at: now,
+      expires_at: expires_in_days.map(|days| now + Duration::days(days as i64)),
+    };
+    let secret = random_hex(32);
+
+    let stored = StoredApiToken {
+      info: info.clone(),
+      secret_hash: hash_secret(&secret),
+    };
+    self.set_setting(
+      &format!("{}{}", API_TOKEN_KEY_PREFIX, info.id),
+      &serde_json::to_string(&stored)?,
+    )?;
+
+    Ok(CreatedApiToken {
+      token: format!("{}_{}_{}", TOKEN_PREFIX, info.id, secret),
+      info,
+    })
+  }
+
+  /// All tokens (including expired ones), oldest first
+  pub fn list_api_tokens(&self) -> Result<Vec<ApiToken>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached("SELECT value FROM local_settings WHERE key LIKE ?1")?;
+    let values = stmt.query_map([format!("{}%", API_TOKEN_KEY_PREFIX)], |row| row.get::<_, String>(0))?;
+
+    let mut tokens = Vec::new();
+    for value in values {
+      if let Ok(stored) = serde_json::from_str::<StoredApiToken>(&value?) {
+        tokens.push(stored.info);
+      }
+    }
+    tokens.sort_by_key(|token| token.created_at);
+    Ok(tokens)
+  }
+
+  /// Returns false if there is no such token
+  pub fn revoke_api_token(&self, id: &str) -> Result<bool> {
+    let conn = self.conn.lock().unwrap();
+    let deleted = conn.execute(
+      "DELETE FROM local_settings WHERE key = ?1",
+      [format!("{}{}", API_TOKEN_KEY_PREFIX, id)],
+    )?;
+    Ok(deleted > 0)
+  }
+
+  /// Whether `token` is valid at `now` and grants `scope`
+  pub fn verify_api_token(&self, token: &str, scope: ApiScope, now: DateTime<Utc>) -> Result<bool> {
+    let Some((id, secret)) = parse_token(token) else {
+      return Ok(false);
+    };
+    let Some(value) = self.get_setting(&format!("{}{}", API_TOKEN_KEY_PREFIX, id))? else {
+      return Ok(false);
+    };
+    let Ok(stored) = serde_json::from_str::<StoredApiToken>(&value) else {
+      return Ok(false);
+    };
+
+    Ok(
+      constant_time_eq(hash_secret(secret).as_bytes(), stored.secret_hash.as_bytes())
+        && !stored.info.is_expired(now)
+        && stored.info.scopes.contains(&scope),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  #[test]
+  fn test_local_api_host() {
+    assert!(is_local_api_host(Some("127.0.0.1:7420"), 7420));
+    assert!(is_local_api_host(Some("LocalHost:7420"), 7420));
+    assert!(!is_local_api_host(Some("127.0.0.1:7421"), 7420));
+    assert!(!is_local_api_host(Some("attacker.example:7420"), 7420));
+    assert!(!is_local_api_host(Some("localhost"), 7420));
+    assert!(!is_local_api_host(None, 7420));
+  }
+
+  #[test]
+  fn test_launch_token() {
+    let (db, _temp) = create_test_db();
+    let launch = LaunchToken::generate();
+    assert!(launch.matches(launch.as_str()));
+    assert!(!launch.matches(&LaunchToken::generate().0));
+    // Not a stored token
+    assert!(!db.verify_api_token(launch.as_str(), ApiScope::Summary, Utc::now()).unwrap());
+  }
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  #[test]
+  fn test_create_list_revoke() {
+    let (db, _temp) = create_test_db();
+    let created = db.create_api_token("Raycast", &[ApiScope::Summary], Some(30)).unwrap();
+
+    assert!(created.token.starts_with("lst_"));
+    assert_eq!(db.list_api_tokens().unwrap(), vec![created.info.clone()]);
+    assert_eq!(
+      created.info.expires_at.unwrap() - created.info.created_at,
+      Duration::days(30)
+    );
+
+    assert!(db.revoke_api_token(&created.info.id).unwrap());
+    assert!(!db.revoke_api_token(&created.info.id).unwrap());
+    assert!(db.list_api_tokens().unwrap().is_empty());
+    assert!(!db.verify_api_token(&created.token, ApiScope::Summary, Utc::now()).unwrap());
+  }
+
+  #[test]
+  fn test_secret_is_not_stored() {
+    let (db, _temp) = create_test_db();
+    let version = db.current_config_version().unwrap();
+    let created = db.create_api_token("script", &[ApiScope::Sync], None).unwrap();
+    let (_, secret) = parse_token(&created.token).unwrap();
+
+    let stored = db
+      .get_setting(&format!("{}{}", API_TOKEN_KEY_PREFIX, created.info.id))
+      .unwrap()
+      .unwrap();
+    assert!(!stored.contains(secret));
+    // Token rows are not versioned, so no hash ends up in config history either
+    assert_eq!(db.current_config_version().unwrap(), version);
+  }
+
+  #[test]
+  fn test_verify_checks_secret_scope_and_expiry() {
+    let (db, _temp) = create_test_db();
+    let created = db.create_api_token("widget", &[ApiScope::Summary, ApiScope::Sync], Some(1)).unwrap();
+    let now = Utc::now();
+
+    assert!(db.verify_api_token(&created.token, ApiScope::Summary, now).unwrap());
+    assert!(!db.verify_api_token(&created.token, ApiScope::Timeline, now).unwrap());
+    assert!(!db.verify_api_token(&created.token, ApiScope::Summary, now + Duration::days(2)).unwrap());
+
+    let forged = format!("lst_{}_{}", created.info.id, "0".repeat(64));
+    assert!(!db.verify_api_token(&forged, ApiScope::Summary, now).unwrap());
+    assert!(!db.verify_api_token("not-a-token", ApiScope::Summary, now).unwrap());
+  }
+
+  #[test]
+  fn test_invalid_tokens_rejected() {
+    let (db, _temp) = create_test_db();
+    assert!(db.create_api_token(" ", &[ApiScope::Summary], None).is_err());
+    assert!(db.create_api_token("x", &[], None).is_err());
+    assert!(db.create_api_token("x", &[ApiScope::Summary], Some(0)).is_err());
+  }
+}
@@ -0,0 +1,143 @@
+//! Which machine and app version recorded each event.
+//!
+//! Every start of the app adds a row with the hostname, OS and app version,
+//! and events inserted from then on reference it through `session_id` (set by
+//! a trigger, so every insert path is covered). Sync sends the sessions along
+//! with the events that reference them, so after sync a multi-device user can
+//! tell which machine activity came from, and support can match bugs to
+//! versions.
+
+use super::Database;
+use crate::sync::ClientInfo;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rusqlite::{OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppSession {
+  pub id: String,
+  pub started_at: DateTime<Utc>,
+  pub hostname: Option<String>,
+  pub os: String,
+  pub os_version: Option<String>,
+  pub app_version: String,
+}
+
+fn map_session_row(row: &Row<'_>) -> rusqlite::Result<AppSession> {
+  Ok(AppSession {
+    id: row.get(0)?,
+    started_at: DateTime::from_timestamp_millis(row.get(1)?).unwrap_or_default(),
+    hostname: row.get(2)?,
+    os: row.get(3)?,
+    os_version: row.get(4)?,
+    app_version: row.get(5)?,
+  })
+}
+
+impl Database {
+  /// Record the start of this run of the app; events stored from now on
+  /// reference it. Returns the session id.
+  pub fn start_app_session(&self, client: &ClientInfo, started_at: DateTime<Utc>) -> Result<String> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      r#"
+      INSERT INTO app_sessions (id, started_at, hostname, os, os_version, app_version)
+      VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+      "#,
+      (
+        &id,
+        started_at.timestamp_millis(),
+        &client.hostname,
+        &client.os,
+        &client.os_version,
+        &client.app_version,
+      ),
+    )?;
+    Ok(id)
+  }
+
+  /// Every recorded session, newest first
+  pub fn get_app_sessions(&self) -> Result<Vec<AppSession>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      "SELECT id, started_at, hostname, os, os_version, app_version FROM app_sessions ORDER BY started_at DESC",
+    )?;
+    let rows = stmt.query_map([], map_session_row)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  /// The sessions with these ids; unknown ids are skipped
+  pub fn get_app_sessions_by_id(&self, ids: &[String]) -> Result<Vec<AppSession>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      "SELECT id, started_at, hostname, os, os_version, app_version FROM app_sessions WHERE id = ?1",
+    )?;
+    let mut sessions = Vec::with_capacity(ids.len());
+    for id in ids {
+      if let Some(session) = stmt.query_row([id], map_session_row).optional()? {
+        sessions.push(session);
+      }
+    }
+    Ok(sessions)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use chrono::Duration;
+  use tempfile::NamedTempFile;
+
+  fn client(app_version: &str) -> ClientInfo {
+    ClientInfo {
+      app_version: app_version.to_string(),
+      os: "windows".to_string(),
+      os_version: Some("build 22631".to_string()),
+      arch: "x86_64".to_string(),
+      hostname: Some("work-laptop".to_string()),
+    }
+  }
+
+  fn store(db: &Database, title: &str) -> String {
+    db.store_event_sync(&WindowInfo {
+      process_name: "code.exe".to_string(),
+      window_title: title.to_string(),
+      timestamp: Utc::now(),
+      ..Default::default()
+    })
+    .unwrap()
+  }
+
+  #[test]
+  fn test_events_reference_the_latest_session() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    let before_any = store(&db, "before any session");
+
+    let started = Utc::now() - Duration::hours(1);
+    let first = db.start_app_session(&client("0.1.0"), started).unwrap();
+    let old = store(&db, "old version");
+    let second = db.start_app_session(&client("0.2.0"), started + Duration::minutes(30)).unwrap();
+    let new = store(&db, "new version");
+    db.store_marker_event_sync("timezone_change", "+60", Utc::now()).unwrap();
+
+    assert_eq!(db.get_event(&before_any).unwrap().unwrap().session_id, None);
+    assert_eq!(db.get_event(&old).unwrap().unwrap().session_id, Some(first.clone()));
+    assert_eq!(db.get_event(&new).unwrap().unwrap().session_id, Some(second.clone()));
+    // Every insert path is covered, markers included
+    let marker = db.get_events(1, 0).unwrap().remove(0);
+    assert_eq!(marker.event_type, "timezone_change");
+    assert_eq!(marker.session_id, Some(second));
+
+    let sessions = db.get_app_sessions().unwrap();
+    assert_eq!(sessions.iter().map(|s| s.app_version.as_str()).collect::<Vec<_>>(), vec!["0.2.0", "0.1.0"]);
+    assert_eq!(sessions[0].hostname.as_deref(), Some("work-laptop"));
+
+    let by_id = db.get_app_sessions_by_id(&[first.clone(), "missing".to_string()]).unwrap();
+    assert_eq!(by_id.len(), 1);
+    assert_eq!(by_id[0].id, first);
+  }
+}
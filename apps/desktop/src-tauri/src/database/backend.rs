@@ -0,0 +1,200 @@
+//! Storage backend abstraction for the event store.
+//!
+//! The collector, sync and report layers only need a handful of event store
+//! operations. `StorageBackend` captures them so an analytics-oriented
+//! (DuckDB) or always-on (Postgres) store can be added alongside SQLite
+//! without touching those layers. Settings and history stay SQLite-only.
+
+use super::anonymize::{stored_names, Anonymizer};
+use super::rules::load_category_rules;
+use super::{current_utc_offset_minutes, Database, EventFilter, StoredEvent};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A closed event to insert in bulk (imports, replays, backfills)
+#[derive(Debug, Clone)]
+pub struct NewEvent {
+  pub event_type: String,
+  pub timestamp: DateTime<Utc>,
+  pub duration: i32,
+  pub app_name: String,
+  pub window_title: Option<String>,
+  pub url_domain: Option<String>,
+  pub remote_session: bool,
+}
+
+/// Total recorded time for one app over a range
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppUsageTotal {
+  pub app_name: String,
+  pub duration_seconds: i64,
+  pub event_count: i64,
+}
+
+pub trait StorageBackend: Send + Sync {
+  /// Short backend identifier for diagnostics (e.g. "sqlite")
+  fn name(&self) -> &'static str;
+
+  /// Insert events atomically; returns their ids in input order
+  fn insert_events(&self, events: &[NewEvent]) -> Result<Vec<String>>;
+
+  /// Events starting in [start, end), oldest first
+  fn events_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<StoredEvent>>;
+
+  /// Closed events not yet uploaded, oldest first
+  fn unsynced_events(&self) -> Result<Vec<StoredEvent>>;
+
+  fn mark_synced(&self, event_ids: &[String]) -> Result<()>;
+
+  /// app_usage time per app for events starting in [start, end), largest first
+  fn app_usage_totals(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<AppUsageTotal>>;
+}
+
+impl StorageBackend for Database {
+  fn name(&self) -> &'static str {
+    "sqlite"
+  }
+
+  fn insert_events(&self, events: &[NewEvent]) -> Result<Vec<String>> {
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    let utc_offset = current_utc_offset_minutes();
+    let rules = load_category_rules(&tx)?;
+    let anonymizer = Anonymizer::load(&tx, self.display_key().as_deref(), self.hash_key().as_deref())?;
+    let mut ids = Vec::with_capacity(events.len());
+
+    {
+      let mut stmt = tx.prepare_cached(
+        r#"
+        INSERT INTO local_events (id, event_type, timestamp, duration, app_name, window_title, utc_offset_minutes, remote_session, url_domain, category)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+        "#,
+      )?;
+
+      for event in events {
+        let id = uuid::Uuid::new_v4().to_string();
+        let (app_name, title) = stored_names(anonymizer.as_ref(), &tx, &event.app_name, event.window_title.as_deref())?;
+        stmt.execute((
+          &id,
+          &event.event_type,
+          event.timestamp.timestamp_millis(),
+          event.duration,
+          app_name,
+          title,
+          utc_offset,
+          event.remote_session,
+          &event.url_domain,
+          (event.event_type == "app_usage").then(|| rules.categorize(&event.app_name)),
+        ))?;
+        ids.push(id);
+      }
+    }
+
+    tx.commit()?;
+    Ok(ids)
+  }
+
+  fn events_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<StoredEvent>> {
+    self.get_events_between(start, end, &EventFilter::default())
+  }
+
+  fn unsynced_events(&self) -> Result<Vec<StoredEvent>> {
+    self.get_unsynced_events()
+  }
+
+  fn mark_synced(&self, event_ids: &[String]) -> Result<()> {
+    self.mark_as_synced(event_ids)
+  }
+
+  fn app_usage_totals(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<AppUsageTotal>> {
+    self.get_app_totals(start, end)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_backend() -> (Box<dyn StorageBackend>, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (Box::new(db), temp_file)
+  }
+
+  fn new_event(app: &str, timestamp: DateTime<Utc>, duration: i32) -> NewEvent {
+    NewEvent {
+      event_type: "app_usage".to_string(),
+      timestamp,
+      duration,
+      app_name: app.to_string(),
+      window_title: Some("Window".to_string()),
+      url_domain: None,
+      remote_session: false,
+    }
+  }
+
+  #[test]
+  fn test_insert_and_query_range() {
+    let (backend, _temp) = create_test_backend();
+    let base = DateTime::parse_from_rfc3339("2024-05-01T09:00:00Z").unwrap().with_timezone(&Utc);
+
+    let ids = backend
+      .insert_events(&[
+        new_event("code.exe", base, 600),
+        new_event("chrome.exe", base + chrono::Duration::minutes(10), 300),
+        new_event("code.exe", base + chrono::Duration::hours(3), 900),
+      ])
+      .unwrap();
+    assert_eq!(ids.len(), 3);
+    assert_eq!(backend.name(), "sqlite");
+
+    let events = backend.events_between(base, base + chrono::Duration::hours(1)).unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].id, ids[0]);
+    assert_eq!(events[1].duration, 300);
+  }
+
+  #[test]
+  fn test_inserted_events_are_syncable() {
+    let (backend, _temp) = create_test_backend();
+    let ids = backend.insert_events(&[new_event("code.exe", Utc::now(), 60)]).unwrap();
+
+    assert_eq!(backend.unsynced_events().unwrap().len(), 1);
+    backend.mark_synced(&ids).unwrap();
+    assert!(backend.unsynced_events().unwrap().is_empty());
+  }
+
+  #[test]
+  fn test_app_usage_totals() {
+    let (backend, _temp) = create_test_backend();
+    let base = DateTime::parse_from_rfc3339("2024-05-01T09:00:00Z").unwrap().with_timezone(&Utc);
+
+    let mut afk = new_event("system", base, 1200);
+    afk.event_type = "afk".to_string();
+    backend
+      .insert_events(&[
+        new_event("code.exe", base, 600),
+        new_event("chrome.exe", base + chrono::Duration::minutes(10), 300),
+        new_event("code.exe", base + chrono::Duration::minutes(20), 900),
+        afk,
+      ])
+      .unwrap();
+
+    let totals = backend.app_usage_totals(base, base + chrono::Duration::days(1)).unwrap();
+    assert_eq!(
+      totals,
+      vec![
+        AppUsageTotal { app_name: "code.exe".to_string(), duration_seconds: 1500, event_count: 2 },
+        AppUsageTotal { app_name: "chrome.exe".to_string(), duration_seconds: 300, event_count: 1 },
+      ]
+    );
+  }
+
+  #[test]
+  fn test_empty_batch() {
+    let (backend, _temp) = create_test_backend();
+    assert!(backend.insert_events(&[]).unwrap().is_empty());
+  }
+}
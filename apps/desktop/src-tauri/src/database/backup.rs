@@ -0,0 +1,241 @@
+//! Full-database backups with SQLite's online backup API, and restoring from them.
+//!
+//! A backup is a page-for-page copy, so in SQLCipher builds it is encrypted
+//! with the same key as the live database. Restoring copies the backup over
+//! the live database in place and then runs any migrations it is missing, so
+//! a backup from an older version of the app can be restored.
+
+use super::connection::{migrate, schema_version, SCHEMA_VERSION};
+use super::Database;
+use anyhow::{bail, Context, Result};
+use rusqlite::backup::Backup;
+use rusqlite::OpenFlags;
+use serde::Serialize;
+use std::path::Path;
+use std::time::Duration;
+
+/// Pages copied per backup step
+const PAGES_PER_STEP: std::os::raw::c_int = 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+  pub path: String,
+  pub schema_version: i64,
+  pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreReport {
+  /// Schema version the backup was written with
+  pub schema_version: i64,
+  pub event_count: i64,
+}
+
+impl Database {
+  /// Copy the whole database to `path`, replacing any file there
+  pub fn backup(&self, path: &Path) -> Result<BackupInfo> {
+    if let Some(parent) = path.parent() {
+      std::fs::create_dir_all(parent)?;
+    }
+    // Buffered status values belong in the backup too
+    self.flush_status_writes()?;
+
+    let tmp_path = path.with_extension("tmp");
+    if tmp_path.exists() {
+      std::fs::remove_file(&tmp_path)?;
+    }
+
+    let schema_version = {
+      let mut dst = self.open_companion(
+        &tmp_path,
+        OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE,
+      )?;
+      let conn = self.conn.lock().unwrap();
+      Backup::new(&conn, &mut dst)?.run_to_completion(PAGES_PER_STEP, Duration::ZERO, None)?;
+      schema_version(&dst)?
+    };
+
+    std::fs::rename(&tmp_path, path).context("Failed to move the backup into place")?;
+    Ok(BackupInfo {
+      path: path.to_string_lossy().into_owned(),
+      schema_version,
+      size_bytes: std::fs::metadata(path)?.len(),
+    })
+  }
+
+  /// Replace the contents of this database with the backup at `path`.
+  /// The backup is checked first; on failure the database is unchanged.
+  pub fn restore(&self, path: &Path) -> Result<RestoreReport> {
+    if !path.is_file() {
+      bail!("Backup file {} not found", path.display());
+    }
+    let src = self
+      .open_companion(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+      .context("Failed to open the backup")?;
+
+    let check: String = src
+      .query_row("PRAGMA quick_check", [], |row| row.get(0))
+      .context("The file is not a readable database backup")?;
+    if check != "ok" {
+      bail!("The backup is damaged: {}", check);
+    }
+
+    let backup_version = schema_version(&src)?;
+    if backup_version > SCHEMA_VERSION {
+      bail!(
+        "The backup was made by a newer version of the app (schema {}, this app supports {})",
+        backup_version,
+        SCHEMA_VERSION
+      );
+    }
+    let has_events: bool = src.query_row(
+      "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'local_events')",
+      [],
+      |row| row.get(0),
+    )?;
+    if !has_events {
+      bail!("The file is not a Lifespan backup");
+    }
+
+    // Write pending status values now so they can't land on top of the restored data later
+    self.flush_status_writes()?;
+
+    let mut conn = self.conn.lock().unwrap();
+    Backup::new(&src, &mut conn)?.run_to_completion(PAGES_PER_STEP, Duration::ZERO, None)?;
+    migrate(&conn)?;
+
+    let event_count = conn.query_row("SELECT COUNT(*) FROM local_events", [], |row| row.get(0))?;
+    tracing::info!(
+      "Restored the database from {} (schema {}, {} events)",
+      path.display(),
+      backup_version,
+      event_count
+    );
+    Ok(RestoreReport {
+      schema_version: backup_version,
+      event_count,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use chrono::Utc;
+  use rusqlite::Connection;
+  use tempfile::TempDir;
+
+  fn window(title: &str) -> WindowInfo {
+    WindowInfo {
+      process_name: "code.exe".to_string(),
+      window_title: title.to_string(),
+      timestamp: Utc::now(),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn test_backup_and_restore_roundtrip() {
+    let dir = TempDir::new().unwrap();
+    let db = Database::new(&dir.path().join("local.db")).unwrap();
+    let kept = db.store_event_sync(&window("before backup")).unwrap();
+    db.set_setting("idle_threshold_seconds", "120").unwrap();
+
+    let backup_path = dir.path().join("backups").join("lifespan.db");
+    let info = db.backup(&backup_path).unwrap();
+    assert_eq!(info.schema_version, SCHEMA_VERSION);
+    assert!(info.size_bytes > 0);
+
+    // Changes after the backup are lost on restore
+    let added = db.store_event_sync(&window("after backup")).unwrap();
+    db.set_setting("idle_threshold_seconds", "600").unwrap();
+
+    // Restoring on another machine: a fresh database
+    let other = Database::new(&dir.path().join("other.db")).unwrap();
+    let report = other.restore(&backup_path).unwrap();
+    assert_eq!(report.event_count, 1);
+    assert!(other.get_event(&kept).unwrap().is_some());
+    assert!(other.get_event(&added).unwrap().is_none());
+    assert_eq!(other.get_setting("idle_threshold_seconds").unwrap().as_deref(), Some("120"));
+
+    let report = db.restore(&backup_path).unwrap();
+    assert_eq!(report.event_count, 1);
+    assert!(db.get_event(&added).unwrap().is_none());
+  }
+
+  #[test]
+  fn test_restore_upgrades_older_backup() {
+    let dir = TempDir::new().unwrap();
+    let db = Database::new(&dir.path().join("local.db")).unwrap();
+    let id = db.store_event_sync(&window("old schema")).unwrap();
+    let backup_path = dir.path().join("old.db");
+    db.backup(&backup_path).unwrap();
+    {
+      let conn = Connection::open(&backup_path).unwrap();
+      conn
+        .execute_batch(
+          r#"
+          DROP TRIGGER usage_rollups_after_insert;
+          DROP TRIGGER usage_rollups_after_update;
+          DROP TRIGGER events_fts_after_insert;
+          DROP TRIGGER events_fts_after_delete;
+          DROP TRIGGER events_fts_after_update;
+          DROP TABLE usage_rollups;
+          DROP TABLE events_fts;
+          DROP INDEX idx_local_events_unsynced_timestamp;
+          DROP INDEX idx_local_events_category;
+          DROP TABLE category_overrides;
+          DROP TRIGGER event_tags_after_event_delete;
+          DROP TABLE event_tags;
+          DROP TABLE tags;
+          DROP TABLE anonymized_values;
+          DROP TRIGGER events_session_after_insert;
+          DROP TABLE app_sessions;
+          ALTER TABLE local_events DROP COLUMN session_id;
+          ALTER TABLE local_events DROP COLUMN category;
+          PRAGMA user_version = 2;
+          "#,
+        )
+        .unwrap();
+    }
+
+    let other = Database::new(&dir.path().join("other.db")).unwrap();
+    let report = other.restore(&backup_path).unwrap();
+
+    assert_eq!(report.schema_version, 2);
+    assert_eq!(report.event_count, 1);
+    assert_eq!(other.search_events("schema", None, None, 10).unwrap().len(), 1);
+    // Events from before stored categories get one from the rules
+    let event = other.get_event(&id).unwrap().unwrap();
+    assert_eq!(event.category.as_deref(), Some("development"));
+    let conn = other.conn.lock().unwrap();
+    assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+  }
+
+  #[test]
+  fn test_restore_rejects_newer_or_foreign_files() {
+    let dir = TempDir::new().unwrap();
+    let db = Database::new(&dir.path().join("local.db")).unwrap();
+    let id = db.store_event_sync(&window("kept")).unwrap();
+
+    let newer = dir.path().join("newer.db");
+    db.backup(&newer).unwrap();
+    Connection::open(&newer)
+      .unwrap()
+      .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+      .unwrap();
+    assert!(db.restore(&newer).is_err());
+
+    let foreign = dir.path().join("foreign.db");
+    Connection::open(&foreign).unwrap().execute_batch("CREATE TABLE notes (body TEXT);").unwrap();
+    assert!(db.restore(&foreign).is_err());
+
+    let garbage = dir.path().join("garbage.db");
+    std::fs::write(&garbage, b"not a database at all, just some text").unwrap();
+    assert!(db.restore(&garbage).is_err());
+
+    assert!(db.restore(&dir.path().join("missing.db")).is_err());
+    assert!(db.get_event(&id).unwrap().is_some());
+  }
+}
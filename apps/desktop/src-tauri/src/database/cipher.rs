@@ -0,0 +1,244 @@
+//! At-rest encryption of the local database with SQLCipher (`sqlcipher` feature).
+//!
+//! The database is keyed with a raw 256-bit key, so no passphrase KDF runs
+//! on open. A plaintext database from an earlier build is encrypted on first
+//! open: it is exported into an encrypted copy with `sqlcipher_export`, which
+//! then replaces the original. Blocks the old file occupied are not wiped.
+//! When the user's sync key is unlocked and remembered, the open database is
+//! moved from the default key's subkey to that key's with `PRAGMA rekey`.
+
+use super::Database;
+use crate::encryption::SecretKey;
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OpenFlags};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// First bytes of every unencrypted SQLite file
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// SQLCipher's literal form for a raw key
+fn key_literal(key: &[u8; 32]) -> Zeroizing<String> {
+  Zeroizing::new(format!("x'{}'", *Zeroizing::new(hex::encode(key))))
+}
+
+fn is_plaintext_database(path: &Path) -> Result<bool> {
+  let mut header = [0u8; 16];
+  match fs::File::open(path)?.read_exact(&mut header) {
+    Ok(()) => Ok(&header == SQLITE_HEADER),
+    // Empty or truncated files hold nothing to migrate
+    Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+    Err(e) => Err(e.into()),
+  }
+}
+
+fn sidecar(path: &Path, suffix: &str) -> PathBuf {
+  let mut name = path.as_os_str().to_owned();
+  name.push(suffix);
+  PathBuf::from(name)
+}
+
+/// The key given doesn't open the database; not a sign of damage
+#[derive(Debug, thiserror::Error)]
+#[error("The local database can't be opened with this key")]
+pub struct WrongKeyError;
+
+/// Key the connection and check the key opens the file
+pub(super) fn apply_key(conn: &Connection, key: &[u8; 32]) -> Result<()> {
+  conn.execute_batch(&Zeroizing::new(format!("PRAGMA key = \"{}\";", *key_literal(key))))?;
+  // SQLCipher only checks the key on first read
+  conn
+    .query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0))
+    .map_err(|_| WrongKeyError)?;
+  Ok(())
+}
+
+/// Re-key the database open on `conn`, already keyed, to `new_key`
+fn rekey_connection(conn: &Connection, new_key: &[u8; 32]) -> Result<()> {
+  conn.execute_batch(&Zeroizing::new(format!("PRAGMA rekey = \"{}\";", *key_literal(new_key))))?;
+  Ok(())
+}
+
+/// Re-key the database at `path` from `old_key` to `new_key`. Returns false,
+/// changing nothing, if `old_key` doesn't open it.
+pub(super) fn rekey(path: &Path, old_key: &[u8; 32], new_key: &[u8; 32]) -> Result<bool> {
+  if !path.exists() {
+    return Ok(false);
+  }
+  let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+  if apply_key(&conn, old_key).is_err() {
+    return Ok(false);
+  }
+  rekey_connection(&conn, new_key)?;
+  tracing::info!("Moved the local database at {} to its new key", path.display());
+  Ok(true)
+}
+
+/// Replace the plaintext database at `path` with an encrypted copy
+fn encrypt_in_place(path: &Path, key: &[u8; 32]) -> Result<()> {
+  let encrypted_path = sidecar(path, ".encrypting");
+  if encrypted_path.exists() {
+    fs::remove_file(&encrypted_path)?;
+  }
+
+  {
+    let conn = Connection::open(path)?;
+    // Fold the WAL into the main file so the export sees everything
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    let user_version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    conn.execute_batch(&Zeroizing::new(format!(
+      "ATTACH DATABASE '{}' AS encrypted KEY \"{}\";",
+      encrypted_path.to_string_lossy().replace('\'', "''"),
+      *key_literal(key)
+    )))?;
+    conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+    // sqlcipher_export doesn't carry the schema version over
+    conn.execute_batch(&format!("PRAGMA encrypted.user_version = {};", user_version))?;
+    conn.execute_batch("DETACH DATABASE encrypted;")?;
+  }
+
+  fs::rename(&encrypted_path, path).context("Failed to replace the plaintext database")?;
+  for suffix in ["-wal", "-shm"] {
+    let leftover = sidecar(path, suffix);
+    if leftover.exists() {
+      fs::remove_file(leftover)?;
+    }
+  }
+  tracing::info!("Encrypted the local database at {}", path.display());
+  Ok(())
+}
+
+impl Database {
+  /// Open (or create) a database encrypted with `key`, first encrypting it
+  /// if it is a plaintext database
+  pub fn open_encrypted(db_path: &Path, key: &[u8; 32]) -> Result<Self> {
+    if let Some(parent) = db_path.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    if db_path.exists() && is_plaintext_database(db_path)? {
+      encrypt_in_place(db_path, key)?;
+    }
+
+    let conn = Connection::open_with_flags(
+      db_path,
+      OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE,
+    )?;
+    apply_key(&conn, key)?;
+    let db = Self::from_connection(conn, db_path)?;
+    *db.key.write().unwrap() = Some(SecretKey::new(*key));
+    Ok(db)
+  }
+
+  /// Re-key the open database to `key`; false if it already uses it
+  pub(super) fn rekey_in_place(&self, key: &[u8; 32]) -> Result<bool> {
+    let mut current = self.key.write().unwrap();
+    if current.as_deref() == Some(key) {
+      return Ok(false);
+    }
+    rekey_connection(&self.conn.lock().unwrap(), key)?;
+    // Pooled readers were keyed with the old key
+    self.readers.discard_idle();
+    *current = Some(SecretKey::new(*key));
+    tracing::info!("Moved the local database to a new key");
+    Ok(true)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use chrono::Utc;
+  use tempfile::TempDir;
+
+  const KEY: &[u8; 32] = b"test_key_32_bytes_long_123456789";
+
+  fn window(title: &str) -> WindowInfo {
+    WindowInfo {
+      process_name: "code.exe".to_string(),
+      window_title: title.to_string(),
+      timestamp: Utc::now(),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn test_new_database_is_encrypted() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("local.db");
+    {
+      let db = Database::open_encrypted(&path, KEY).unwrap();
+      db.store_event_sync(&window("secret plans")).unwrap();
+    }
+
+    assert!(!is_plaintext_database(&path).unwrap());
+    let db = Database::open_encrypted(&path, KEY).unwrap();
+    assert_eq!(db.get_event_count().unwrap(), 1);
+    assert!(Database::open_encrypted(&path, b"different_key_32_bytes_123456789").is_err());
+  }
+
+  #[test]
+  fn test_plaintext_database_is_migrated() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("local.db");
+    let id = {
+      let db = Database::new(&path).unwrap();
+      db.store_event_sync(&window("secret plans")).unwrap()
+    };
+    assert!(is_plaintext_database(&path).unwrap());
+
+    let db = Database::open_encrypted(&path, KEY).unwrap();
+
+    assert!(!is_plaintext_database(&path).unwrap());
+    assert!(!sidecar(&path, ".encrypting").exists());
+    let event = db.get_event(&id).unwrap().unwrap();
+    assert_eq!(event.window_title.as_deref(), Some("secret plans"));
+    let conn = db.conn.lock().unwrap();
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+    assert_eq!(version, super::super::connection::SCHEMA_VERSION);
+  }
+
+  #[test]
+  fn test_database_under_the_legacy_key_is_rekeyed() {
+    use crate::encryption::{derive_key, derive_subkey, KeyPurpose};
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("local.db");
+    let id = {
+      let db = Database::open_encrypted(&path, &derive_key(KEY, "lifespan local database")).unwrap();
+      db.store_event_sync(&window("secret plans")).unwrap()
+    };
+
+    let db = Database::open_local(&path, KEY).unwrap();
+    assert!(db.get_event(&id).unwrap().is_some());
+    drop(db);
+    let subkey = derive_subkey(KEY, KeyPurpose::LocalDatabase, None);
+    assert!(Database::open_encrypted(&path, &subkey).is_ok());
+    assert!(!rekey(&path, b"different_key_32_bytes_123456789", &subkey).unwrap());
+  }
+
+  #[test]
+  fn test_database_under_the_default_key_is_rekeyed_on_unlock() {
+    use crate::encryption::DEFAULT_SYNC_KEY;
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("local.db");
+    let db = Database::open_local(&path, DEFAULT_SYNC_KEY).unwrap();
+    let id = db.store_event_sync(&window("secret plans")).unwrap();
+    // Leaves a reader keyed with the default key in the pool
+    assert!(db.get_event(&id).unwrap().is_some());
+
+    db.rekey_local(KEY).unwrap();
+    assert!(db.get_event(&id).unwrap().is_some());
+    drop(db);
+
+    let error = Database::open_local(&path, DEFAULT_SYNC_KEY).err().unwrap();
+    assert!(error.is::<WrongKeyError>());
+    // Not mistaken for damage and moved aside
+    assert!(Database::open_or_recover(&path, DEFAULT_SYNC_KEY).is_err());
+    let db = Database::open_or_recover(&path, KEY).unwrap();
+    let event = db.get_event(&id).unwrap().unwrap();
+    assert_eq!(event.window_title.as_deref(), Some("secret plans"));
+  }
+}
@@ -1,17 +1,37 @@
+use super::anonymize::{stored_names, Anonymizer, LEGACY_DISPLAY_KEY_PURPOSE};
+use super::backend::AppUsageTotal;
+use super::deletions::DeletionReason;
+use super::history::{is_versioned_setting, record_config_change, SETTING_SCOPE};
+use super::readers::ReadPool;
+use super::rules::{load_category_rules, DEFAULT_CATEGORY_RULES, RULE_SCOPE, UNCATEGORIZED};
+use super::write_buffer::{is_buffered_setting, StatusTable, WriteBuffer};
+use super::writer::DbWriter;
+use crate::collector::event_queue::QueuedEvent;
 use crate::collector::window_tracker::WindowInfo;
-use anyhow::Result;
-use chrono::{DateTime, Utc};
-use rusqlite::{Connection, OpenFlags};
-use serde::Serialize;
+use crate::encryption::{derive_key, derive_subkey, KeyPurpose, SecretKey, DEFAULT_SYNC_KEY};
+use anyhow::{bail, Result};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, Utc};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
 use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 
 #[derive(Clone)]
 pub struct Database {
   pub(crate) conn: Arc<Mutex<Connection>>,
+  pub(crate) status_writes: Arc<WriteBuffer>,
+  pub(crate) readers: Arc<ReadPool>,
+  pub(crate) writer: Arc<DbWriter>,
+  /// SQLCipher key, needed to open backups of this database
+  #[cfg(feature = "sqlcipher")]
+  pub(crate) key: Arc<RwLock<Option<SecretKey>>>,
+  /// Key for the display lookup of anonymized names; set by `open_local`
+  pub(crate) display_key: Arc<RwLock<Option<SecretKey>>>,
+  /// Key anonymized names are hashed with; set by `open_local`, never stored
+  pub(crate) hash_key: Arc<RwLock<Option<SecretKey>>>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct StoredEvent {
   pub id: String,
   pub event_type: String,
@@ -19,6 +39,624 @@ pub struct StoredEvent {
   pub duration: i32,
   pub app_name: String,
   pub window_title: Option<String>,
+  /// Local UTC offset (minutes) in effect when the event was recorded
+  pub utc_offset_minutes: Option<i32>,
+  /// Recorded through a remote desktop / VM viewer (or inside an RDP session)
+  pub remote_session: bool,
+  /// Active browser tab's domain, when domain capture is enabled
+  pub url_domain: Option<String>,
+  /// A full-screen app (game, video, presentation) had the foreground
+  pub fullscreen: bool,
+  /// File open in a known editor (e.g. "lifespan/main.rs"); never uploaded
+  pub document: Option<String>,
+  /// Label from the first matching project rule; never uploaded
+  pub project: Option<String>,
+  /// Virtual desktop / workspace the window was on (Windows desktop GUID or
+  /// 1-based workspace number); never uploaded
+  pub virtual_desktop: Option<String>,
+  /// Full path of the app's executable, where the platform exposes it; never uploaded
+  pub process_path: Option<String>,
+  /// Category from the rules when recorded, or from a later re-categorization
+  /// of the app; None for event types that aren't categorized
+  pub category: Option<String>,
+  /// App session (machine and app version, see `app_sessions`) that recorded the event
+  pub session_id: Option<String>,
+}
+
+impl StoredEvent {
+  /// Calendar day of the event in the timezone that was in effect when it was recorded.
+  /// Events from before offsets were stored fall back to the current local timezone.
+  pub fn local_date(&self) -> NaiveDate {
+    match self.utc_offset_minutes.and_then(|m| FixedOffset::east_opt(m * 60)) {
+      Some(offset) => self.timestamp.with_timezone(&offset).date_naive(),
+      None => self.timestamp.with_timezone(&Local).date_naive(),
+    }
+  }
+}
+
+/// Narrows an event range query; an empty list matches everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventFilter {
+  /// App names, matched exactly
+  #[serde(default)]
+  pub apps: Vec<String>,
+  /// Categories as stored with each event, or as overridden for its app
+  #[serde(default)]
+  pub categories: Vec<String>,
+  /// Event types such as "app_usage" or "afk"
+  #[serde(default)]
+  pub event_types: Vec<String>,
+}
+
+/// Total app usage in one category
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CategoryTotal {
+  pub category: String,
+  pub duration_seconds: i64,
+  pub event_count: i64,
+}
+
+/// Total app usage on one local calendar day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DayTotal {
+  pub day: NaiveDate,
+  pub duration_seconds: i64,
+  pub event_count: i64,
+}
+
+/// Current local UTC offset in minutes
+pub fn current_utc_offset_minutes() -> i32 {
+  Local::now().offset().local_minus_utc() / 60
+}
+
+/// Purpose string the database key was derived with before HKDF subkeys
+#[cfg(feature = "sqlcipher")]
+const LEGACY_DATABASE_KEY_PURPOSE: &str = "lifespan local database";
+
+/// Longest duration the sync server accepts for a single event (24 hours)
+pub(crate) const MAX_EVENT_DURATION_SECS: i64 = 86_400;
+
+/// An open app_usage event for the same app and title starting this close to
+/// a new one is taken to be the same window (a retried write or double poll)
+const DUPLICATE_EVENT_WINDOW_MS: i64 = 5_000;
+
+pub(crate) const EVENT_COLUMNS: &str =
+  "id, event_type, timestamp, duration, app_name, window_title, utc_offset_minutes, remote_session, url_domain, fullscreen, document, project, virtual_desktop, process_path, category, session_id";
+
+/// An event's category: its app's override if there is one, otherwise the
+/// category stored when it was recorded or last recategorized
+const EFFECTIVE_CATEGORY: &str =
+  "COALESCE((SELECT category FROM category_overrides WHERE app_name = local_events.app_name), category)";
+
+pub(crate) fn map_event_row(row: &Row<'_>) -> rusqlite::Result<StoredEvent> {
+  Ok(StoredEvent {
+    id: row.get(0)?,
+    event_type: row.get(1)?,
+    timestamp: DateTime::from_timestamp_millis(row.get::<_, i64>(2)?)
+      .unwrap_or_default(),
+    duration: row.get(3)?,
+    app_name: row.get(4)?,
+    window_title: row.get(5)?,
+    utc_offset_minutes: row.get(6)?,
+    remote_session: row.get(7)?,
+    url_domain: row.get(8)?,
+    fullscreen: row.get(9)?,
+    document: row.get(10)?,
+    project: row.get(11)?,
+    virtual_desktop: row.get(12)?,
+    process_path: row.get(13)?,
+    category: row.get(14)?,
+    session_id: row.get(15)?,
+  })
+}
+
+/// Add a column to an existing table if an older install doesn't have it yet
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+  let exists = conn
+    .prepare(&format!("PRAGMA table_info({})", table))?
+    .query_map([], |row| row.get::<_, String>(1))?
+    .collect::<Result<Vec<_>, _>>()?
+    .iter()
+    .any(|name| name == column);
+
+  if !exists {
+    conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))?;
+  }
+
+  Ok(())
+}
+
+/// One schema upgrade step, run inside a transaction
+type Migration = fn(&Connection) -> Result<()>;
+
+/// Schema upgrades in order; `PRAGMA user_version` records how many have run.
+/// Append new steps and never change shipped ones. Databases from before
+/// versioning report version 0 and may already hold any part of version 2,
+/// so steps up to 2 are idempotent; later steps can assume the prior schema.
+const MIGRATIONS: &[Migration] = &[
+  migrate_v1_initial,
+  migrate_v2_unversioned_additions,
+  migrate_v3_usage_rollups,
+  migrate_v4_event_search,
+  migrate_v5_unsynced_order,
+  migrate_v6_event_category,
+  migrate_v7_event_tags,
+  migrate_v8_anonymized_values,
+  migrate_v9_app_sessions,
+  migrate_v10_remote_events,
+  migrate_v11_remote_event_sessions,
+];
+
+pub(crate) const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+pub(super) fn schema_version(conn: &Connection) -> Result<i64> {
+  Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+/// Run every migration the database hasn't seen yet
+pub(super) fn migrate(conn: &Connection) -> Result<()> {
+  let version = schema_version(conn)?;
+  if version > SCHEMA_VERSION {
+    bail!(
+      "Database schema version {} is newer than this app supports ({})",
+      version,
+      SCHEMA_VERSION
+    );
+  }
+
+  for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+    let tx = conn.unchecked_transaction()?;
+    migration(&tx)?;
+    tx.pragma_update(None, "user_version", index as i64 + 1)?;
+    tx.commit()?;
+  }
+  Ok(())
+}
+
+/// The initial release: events, sync state and settings
+fn migrate_v1_initial(conn: &Connection) -> Result<()> {
+  conn.execute_batch(
+    r#"
+    CREATE TABLE IF NOT EXISTS local_events (
+      id TEXT PRIMARY KEY,
+      event_type TEXT NOT NULL,
+      timestamp INTEGER NOT NULL,
+      duration INTEGER NOT NULL,
+      app_name TEXT NOT NULL,
+      window_title TEXT,
+      synced INTEGER DEFAULT 0,
+      created_at INTEGER DEFAULT (strftime('%s', 'now') * 1000)
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_local_events_timestamp
+      ON local_events(timestamp DESC);
+
+    CREATE INDEX IF NOT EXISTS idx_local_events_synced
+      ON local_events(synced) WHERE synced = 0;
+
+    CREATE TABLE IF NOT EXISTS sync_state (
+      key TEXT PRIMARY KEY,
+      value TEXT NOT NULL,
+      updated_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS local_settings (
+      key TEXT PRIMARY KEY,
+      value TEXT NOT NULL,
+      updated_at INTEGER NOT NULL
+    );
+
+    INSERT OR IGNORE INTO local_settings (key, value, updated_at)
+      VALUES ('idle_threshold_seconds', '300', strftime('%s', 'now') * 1000);
+    "#,
+  )?;
+  Ok(())
+}
+
+/// Everything added before migrations were versioned
+fn migrate_v2_unversioned_additions(conn: &Connection) -> Result<()> {
+  conn.execute_batch(
+    r#"
+    CREATE TABLE IF NOT EXISTS notifications (
+      id TEXT PRIMARY KEY,
+      kind TEXT NOT NULL,
+      title TEXT NOT NULL,
+      body TEXT NOT NULL,
+      created_at INTEGER NOT NULL,
+      is_read INTEGER NOT NULL DEFAULT 0,
+      digested INTEGER NOT NULL DEFAULT 0
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_notifications_created_at
+      ON notifications(created_at DESC);
+
+    CREATE TABLE IF NOT EXISTS config_history (
+      version INTEGER PRIMARY KEY AUTOINCREMENT,
+      scope TEXT NOT NULL,
+      key TEXT NOT NULL,
+      old_value TEXT,
+      new_value TEXT,
+      changed_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_config_history_scope_key
+      ON config_history(scope, key, version);
+
+    CREATE TABLE IF NOT EXISTS category_rules (
+      pattern TEXT PRIMARY KEY,
+      category TEXT NOT NULL,
+      updated_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS daily_app_usage (
+      day TEXT NOT NULL,
+      app_name TEXT NOT NULL,
+      duration_seconds INTEGER NOT NULL,
+      event_count INTEGER NOT NULL,
+      PRIMARY KEY (day, app_name)
+    );
+
+    CREATE TABLE IF NOT EXISTS goals (
+      category TEXT NOT NULL,
+      scope TEXT NOT NULL CHECK (scope IN ('daily', 'weekly')),
+      limit_minutes INTEGER NOT NULL,
+      carry_over INTEGER NOT NULL DEFAULT 0,
+      updated_at INTEGER NOT NULL,
+      PRIMARY KEY (category, scope)
+    );
+
+    CREATE TABLE IF NOT EXISTS excluded_apps (
+      process_name TEXT PRIMARY KEY,
+      added_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS annotations (
+      id TEXT PRIMARY KEY,
+      event_id TEXT,
+      text TEXT NOT NULL,
+      created_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_annotations_created_at
+      ON annotations(created_at);
+
+    CREATE TABLE IF NOT EXISTS title_policies (
+      process_name TEXT PRIMARY KEY,
+      policy TEXT NOT NULL CHECK (policy IN ('full', 'app_name', 'none')),
+      updated_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS redaction_rules (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      pattern TEXT NOT NULL,
+      replacement TEXT NOT NULL,
+      enabled INTEGER NOT NULL DEFAULT 1,
+      created_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS project_rules (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      pattern TEXT NOT NULL,
+      project TEXT NOT NULL,
+      enabled INTEGER NOT NULL DEFAULT 1,
+      created_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS executable_hashes (
+      path TEXT PRIMARY KEY,
+      size INTEGER NOT NULL,
+      modified_at INTEGER NOT NULL,
+      sha256 TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS monthly_statements (
+      month TEXT PRIMARY KEY,
+      body TEXT NOT NULL,
+      hash TEXT NOT NULL,
+      created_at INTEGER NOT NULL
+    );
+
+    CREATE TRIGGER IF NOT EXISTS monthly_statements_no_update
+      BEFORE UPDATE ON monthly_statements
+      BEGIN SELECT RAISE(ABORT, 'monthly statements are immutable'); END;
+
+    CREATE TRIGGER IF NOT EXISTS monthly_statements_no_delete
+      BEFORE DELETE ON monthly_statements
+      BEGIN SELECT RAISE(ABORT, 'monthly statements are immutable'); END;
+
+    CREATE TABLE IF NOT EXISTS pending_deletions (
+      event_id TEXT PRIMARY KEY,
+      reason TEXT NOT NULL,
+      deleted_at INTEGER NOT NULL,
+      attempts INTEGER NOT NULL DEFAULT 0,
+      last_sent_at INTEGER,
+      confirmed_at INTEGER
+    );
+
+    CREATE TABLE IF NOT EXISTS category_corrections (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      app_name TEXT NOT NULL,
+      window_title TEXT,
+      category TEXT NOT NULL,
+      created_at INTEGER NOT NULL
+    );
+
+    "#,
+  )?;
+
+  // Seed built-in categorization rules once; later edits (including deletions) are kept
+  let rules_touched: bool = conn.query_row(
+    "SELECT EXISTS(SELECT 1 FROM category_rules) OR EXISTS(SELECT 1 FROM config_history WHERE scope = ?)",
+    [RULE_SCOPE],
+    |row| row.get(0),
+  )?;
+  if !rules_touched {
+    let now = Utc::now().timestamp_millis();
+    for (pattern, category) in DEFAULT_CATEGORY_RULES {
+      conn.execute(
+        "INSERT INTO category_rules (pattern, category, updated_at) VALUES (?1, ?2, ?3)",
+        (pattern, category, now),
+      )?;
+    }
+  }
+
+  // Columns added after the initial release
+  add_column_if_missing(conn, "local_events", "utc_offset_minutes", "INTEGER")?;
+  add_column_if_missing(conn, "local_events", "remote_session", "INTEGER NOT NULL DEFAULT 0")?;
+  add_column_if_missing(conn, "local_events", "is_open", "INTEGER NOT NULL DEFAULT 0")?;
+  add_column_if_missing(conn, "local_events", "url_domain", "TEXT")?;
+  add_column_if_missing(conn, "local_events", "fullscreen", "INTEGER NOT NULL DEFAULT 0")?;
+  add_column_if_missing(conn, "local_events", "document", "TEXT")?;
+  add_column_if_missing(conn, "local_events", "project", "TEXT")?;
+  add_column_if_missing(conn, "local_events", "virtual_desktop", "TEXT")?;
+  add_column_if_missing(conn, "local_events", "metadata", "TEXT")?;
+  add_column_if_missing(conn, "local_events", "process_path", "TEXT")?;
+
+  Ok(())
+}
+
+/// Hourly and daily app usage rollups, maintained by triggers (see `rollups`)
+fn migrate_v3_usage_rollups(conn: &Connection) -> Result<()> {
+  conn.execute_batch(
+    r#"
+    CREATE TABLE usage_rollups (
+      granularity TEXT NOT NULL CHECK (granularity IN ('hour', 'day')),
+      bucket_start INTEGER NOT NULL,
+      app_name TEXT NOT NULL,
+      process_path TEXT NOT NULL DEFAULT '',
+      duration_seconds INTEGER NOT NULL,
+      event_count INTEGER NOT NULL,
+      PRIMARY KEY (granularity, bucket_start, app_name, process_path)
+    );
+
+    CREATE TRIGGER usage_rollups_after_insert
+      AFTER INSERT ON local_events
+      WHEN NEW.event_type = 'app_usage' AND NEW.is_open = 0
+    BEGIN
+      INSERT INTO usage_rollups (granularity, bucket_start, app_name, process_path, duration_seconds, event_count)
+      VALUES
+        ('hour', NEW.timestamp / 3600000 * 3600000, NEW.app_name, COALESCE(NEW.process_path, ''), NEW.duration, 1),
+        ('day', NEW.timestamp / 86400000 * 86400000, NEW.app_name, COALESCE(NEW.process_path, ''), NEW.duration, 1)
+      ON CONFLICT (granularity, bucket_start, app_name, process_path) DO UPDATE SET
+        duration_seconds = duration_seconds + excluded.duration_seconds,
+        event_count = event_count + excluded.event_count;
+    END;
+
+    CREATE TRIGGER usage_rollups_after_update
+      AFTER UPDATE OF event_type, timestamp, duration, app_name, is_open, process_path ON local_events
+      WHEN (OLD.event_type = 'app_usage' AND OLD.is_open = 0) OR (NEW.event_type = 'app_usage' AND NEW.is_open = 0)
+    BEGIN
+      UPDATE usage_rollups
+      SET duration_seconds = duration_seconds - OLD.duration, event_count = event_count - 1
+      WHERE OLD.event_type = 'app_usage' AND OLD.is_open = 0
+        AND app_name = OLD.app_name AND process_path = COALESCE(OLD.process_path, '')
+        AND ((granularity = 'hour' AND bucket_start = OLD.timestamp / 3600000 * 3600000)
+          OR (granularity = 'day' AND bucket_start = OLD.timestamp / 86400000 * 86400000));
+
+      INSERT INTO usage_rollups (granularity, bucket_start, app_name, process_path, duration_seconds, event_count)
+      SELECT 'hour', NEW.timestamp / 3600000 * 3600000, NEW.app_name, COALESCE(NEW.process_path, ''), NEW.duration, 1
+      WHERE NEW.event_type = 'app_usage' AND NEW.is_open = 0
+      ON CONFLICT (granularity, bucket_start, app_name, process_path) DO UPDATE SET
+        duration_seconds = duration_seconds + excluded.duration_seconds,
+        event_count = event_count + excluded.event_count;
+
+      INSERT INTO usage_rollups (granularity, bucket_start, app_name, process_path, duration_seconds, event_count)
+      SELECT 'day', NEW.timestamp / 86400000 * 86400000, NEW.app_name, COALESCE(NEW.process_path, ''), NEW.duration, 1
+      WHERE NEW.event_type = 'app_usage' AND NEW.is_open = 0
+      ON CONFLICT (granularity, bucket_start, app_name, process_path) DO UPDATE SET
+        duration_seconds = duration_seconds + excluded.duration_seconds,
+        event_count = event_count + excluded.event_count;
+    END;
+
+    INSERT INTO usage_rollups (granularity, bucket_start, app_name, process_path, duration_seconds, event_count)
+    SELECT 'hour', timestamp / 3600000 * 3600000, app_name, COALESCE(process_path, ''), SUM(duration), COUNT(*)
+    FROM local_events
+    WHERE event_type = 'app_usage' AND is_open = 0
+    GROUP BY 2, 3, 4;
+
+    INSERT INTO usage_rollups (granularity, bucket_start, app_name, process_path, duration_seconds, event_count)
+    SELECT 'day', timestamp / 86400000 * 86400000, app_name, COALESCE(process_path, ''), SUM(duration), COUNT(*)
+    FROM local_events
+    WHERE event_type = 'app_usage' AND is_open = 0
+    GROUP BY 2, 3, 4;
+    "#,
+  )?;
+  Ok(())
+}
+
+/// Full-text index over app names and window titles (see `search`)
+fn migrate_v4_event_search(conn: &Connection) -> Result<()> {
+  conn.execute_batch(
+    r#"
+    CREATE VIRTUAL TABLE events_fts USING fts5(
+      app_name,
+      window_title,
+      content = 'local_events',
+      content_rowid = 'rowid',
+      tokenize = 'unicode61 remove_diacritics 2'
+    );
+
+    CREATE TRIGGER events_fts_after_insert AFTER INSERT ON local_events
+    BEGIN
+      INSERT INTO events_fts (rowid, app_name, window_title) VALUES (NEW.rowid, NEW.app_name, NEW.window_title);
+    END;
+
+    CREATE TRIGGER events_fts_after_delete AFTER DELETE ON local_events
+    BEGIN
+      INSERT INTO events_fts (events_fts, rowid, app_name, window_title)
+      VALUES ('delete', OLD.rowid, OLD.app_name, OLD.window_title);
+    END;
+
+    CREATE TRIGGER events_fts_after_update AFTER UPDATE OF app_name, window_title ON local_events
+    BEGIN
+      INSERT INTO events_fts (events_fts, rowid, app_name, window_title)
+      VALUES ('delete', OLD.rowid, OLD.app_name, OLD.window_title);
+      INSERT INTO events_fts (rowid, app_name, window_title) VALUES (NEW.rowid, NEW.app_name, NEW.window_title);
+    END;
+
+    INSERT INTO events_fts (events_fts) VALUES ('rebuild');
+    "#,
+  )?;
+  Ok(())
+}
+
+/// Lets the sync client read the oldest unsynced events without sorting the whole backlog
+fn migrate_v5_unsynced_order(conn: &Connection) -> Result<()> {
+  conn.execute_batch(
+    r#"
+    CREATE INDEX idx_local_events_unsynced_timestamp
+      ON local_events(timestamp, id) WHERE synced = 0;
+    "#,
+  )?;
+  Ok(())
+}
+
+/// Store each app_usage event's category, with per-app overrides that beat
+/// the rules. Existing events are categorized with the rules they have now.
+fn migrate_v6_event_category(conn: &Connection) -> Result<()> {
+  conn.execute_batch(
+    r#"
+    CREATE TABLE category_overrides (
+      app_name TEXT PRIMARY KEY COLLATE NOCASE,
+      category TEXT NOT NULL,
+      updated_at INTEGER NOT NULL
+    );
+
+    ALTER TABLE local_events ADD COLUMN category TEXT;
+    CREATE INDEX idx_local_events_category ON local_events(category, timestamp);
+    "#,
+  )?;
+
+  let rules = load_category_rules(conn)?;
+  let apps = conn
+    .prepare("SELECT DISTINCT app_name, process_path FROM local_events WHERE event_type = 'app_usage'")?
+    .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)))?
+    .collect::<Result<Vec<_>, _>>()?;
+  let mut stmt = conn.prepare(
+    "UPDATE local_events SET category = ?1 WHERE event_type = 'app_usage' AND app_name = ?2 AND process_path IS ?3",
+  )?;
+  for (app_name, process_path) in apps {
+    let category = rules.categorize_with_path(&app_name, process_path.as_deref());
+    stmt.execute((category, &app_name, &process_path))?;
+  }
+  Ok(())
+}
+
+/// User tags on events (see `tags`); an event's tags go when it is deleted
+fn migrate_v7_event_tags(conn: &Connection) -> Result<()> {
+  conn.execute_batch(
+    r#"
+    CREATE TABLE tags (
+      id INTEGER PRIMARY KEY,
+      name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+      created_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE event_tags (
+      event_id TEXT NOT NULL,
+      tag_id INTEGER NOT NULL,
+      created_at INTEGER NOT NULL,
+      PRIMARY KEY (event_id, tag_id)
+    ) WITHOUT ROWID;
+    CREATE INDEX idx_event_tags_tag ON event_tags(tag_id, event_id);
+
+    CREATE TRIGGER event_tags_after_event_delete AFTER DELETE ON local_events
+    BEGIN
+      DELETE FROM event_tags WHERE event_id = OLD.id;
+    END;
+    "#,
+  )?;
+  Ok(())
+}
+
+/// Display lookup for anonymized storage (see `anonymize`)
+fn migrate_v8_anonymized_values(conn: &Connection) -> Result<()> {
+  conn.execute_batch(
+    r#"
+    CREATE TABLE anonymized_values (
+      token TEXT PRIMARY KEY,
+      value TEXT NOT NULL
+    ) WITHOUT ROWID;
+    "#,
+  )?;
+  Ok(())
+}
+
+/// Machine and app version behind each run of the app; new events reference
+/// the latest one (see `app_sessions`)
+fn migrate_v9_app_sessions(conn: &Connection) -> Result<()> {
+  conn.execute_batch(
+    r#"
+    CREATE TABLE app_sessions (
+      id TEXT PRIMARY KEY,
+      started_at INTEGER NOT NULL,
+      hostname TEXT,
+      os TEXT NOT NULL,
+      os_version TEXT,
+      app_version TEXT NOT NULL
+    );
+    CREATE INDEX idx_app_sessions_started ON app_sessions(started_at);
+
+    ALTER TABLE local_events ADD COLUMN session_id TEXT;
+
+    CREATE TRIGGER events_session_after_insert AFTER INSERT ON local_events
+      WHEN NEW.session_id IS NULL AND EXISTS (SELECT 1 FROM app_sessions)
+    BEGIN
+      UPDATE local_events
+      SET session_id = (SELECT id FROM app_sessions ORDER BY started_at DESC LIMIT 1)
+      WHERE rowid = NEW.rowid;
+    END;
+    "#,
+  )?;
+  Ok(())
+}
+
+/// Events other devices synced, pulled from the server (see `remote_events`)
+fn migrate_v10_remote_events(conn: &Connection) -> Result<()> {
+  conn.execute_batch(
+    r#"
+    CREATE TABLE remote_events (
+      id TEXT PRIMARY KEY,
+      device_id TEXT NOT NULL,
+      event_type TEXT NOT NULL,
+      timestamp INTEGER NOT NULL,
+      duration INTEGER NOT NULL,
+      app_name TEXT,
+      title TEXT,
+      category TEXT,
+      domain TEXT,
+      received_at INTEGER NOT NULL
+    );
+    CREATE INDEX idx_remote_events_timestamp ON remote_events(timestamp);
+    "#,
+  )?;
+  Ok(())
+}
+
+/// Whether a pulled event was recorded in a remote desktop / VM viewer
+fn migrate_v11_remote_event_sessions(conn: &Connection) -> Result<()> {
+  conn.execute_batch("ALTER TABLE remote_events ADD COLUMN remote_session INTEGER NOT NULL DEFAULT 0")?;
+  Ok(())
 }
 
 impl Database {
@@ -34,8 +672,80 @@ impl Database {
       OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE,
     )?;
 
+    Self::from_connection(conn, db_path)
+  }
+
+  /// Open the app's own database. In builds with the `sqlcipher` feature it is
+  /// encrypted with a subkey of `sync_key`; otherwise it is plaintext. Data
+  /// keyed the pre-HKDF way is moved to the subkeys on the way.
+  pub fn open_local(db_path: &Path, sync_key: &[u8; 32]) -> Result<Self> {
+    #[cfg(feature = "sqlcipher")]
+    let db = {
+      let key = derive_subkey(sync_key, KeyPurpose::LocalDatabase, None);
+      match Self::open_encrypted(db_path, &key) {
+        Ok(db) => db,
+        Err(e) => {
+          if !super::cipher::rekey(db_path, &derive_key(sync_key, LEGACY_DATABASE_KEY_PURPOSE), &key)? {
+            return Err(e);
+          }
+          Self::open_encrypted(db_path, &key)?
+        }
+      }
+    };
+    #[cfg(not(feature = "sqlcipher"))]
+    let db = Self::new(db_path)?;
+
+    *db.display_key.write().unwrap() = Some(derive_subkey(sync_key, KeyPurpose::DisplayNames, None));
+    *db.hash_key.write().unwrap() = Some(derive_subkey(sync_key, KeyPurpose::AnonymizedNames, None));
+    db.reencrypt_display_names(&derive_key(sync_key, LEGACY_DISPLAY_KEY_PURPOSE))?;
+    // Earlier versions kept names under the default key whatever key was in use
+    if sync_key != DEFAULT_SYNC_KEY {
+      db.reencrypt_display_names(&derive_subkey(DEFAULT_SYNC_KEY, KeyPurpose::DisplayNames, None))?;
+    }
+    db.rehash_anonymized_values()?;
+    Ok(db)
+  }
+
+  /// Move a database opened with `open_local` to the subkeys of `sync_key`:
+  /// the file is re-keyed in place (with the `sqlcipher` feature) and the
+  /// readable names are re-encrypted and their hashes recomputed. Nothing
+  /// changes if it already uses them.
+  pub fn rekey_local(&self, sync_key: &[u8; 32]) -> Result<()> {
+    #[cfg(feature = "sqlcipher")]
+    self.rekey_in_place(&derive_subkey(sync_key, KeyPurpose::LocalDatabase, None))?;
+
+    let display_key = derive_subkey(sync_key, KeyPurpose::DisplayNames, None);
+    let old_display_key = self.display_key.write().unwrap().replace(display_key);
+    if let Some(old_display_key) = old_display_key {
+      self.reencrypt_display_names(&old_display_key)?;
+    }
+    *self.hash_key.write().unwrap() = Some(derive_subkey(sync_key, KeyPurpose::AnonymizedNames, None));
+    self.rehash_anonymized_values()?;
+    Ok(())
+  }
+
+  /// Key for the display lookup of anonymized names, if the database has one
+  pub(crate) fn display_key(&self) -> Option<SecretKey> {
+    self.display_key.read().unwrap().clone()
+  }
+
+  /// Key anonymized names are hashed with, if the database has one
+  pub(crate) fn hash_key(&self) -> Option<SecretKey> {
+    self.hash_key.read().unwrap().clone()
+  }
+
+  /// Wrap an open write connection to the database at `db_path` and bring
+  /// its schema up to date
+  pub(super) fn from_connection(conn: Connection, db_path: &Path) -> Result<Self> {
     let db = Self {
       conn: Arc::new(Mutex::new(conn)),
+      status_writes: Arc::new(WriteBuffer::default()),
+      readers: Arc::new(ReadPool::new(db_path)),
+      writer: Arc::new(DbWriter::spawn()?),
+      #[cfg(feature = "sqlcipher")]
+      key: Arc::default(),
+      display_key: Arc::default(),
+      hash_key: Arc::default(),
     };
 
     // Initialize schema
@@ -47,9 +757,11 @@ impl Database {
   fn init_schema(&self) -> Result<()> {
     let conn = self.conn.lock().unwrap();
 
-    // Enable WAL mode for better concurrency
+    // Enable WAL mode for better concurrency; incremental auto-vacuum only
+    // takes effect on a new file (see `incremental_vacuum_sync`)
     conn.execute_batch(
       r#"
+      PRAGMA auto_vacuum = INCREMENTAL;
       PRAGMA journal_mode = WAL;
       PRAGMA synchronous = NORMAL;
       PRAGMA cache_size = -64000;
@@ -58,129 +770,525 @@ impl Database {
       "#,
     )?;
 
-    // Create tables
-    conn.execute_batch(
+    migrate(&conn)
+  }
+
+  /// Write a batch of queued app_usage events in one transaction. Events closed
+  /// while queued are written closed; the rest stay open until `close_event_sync`.
+  pub(crate) fn store_queued_events_sync(&self, events: &[QueuedEvent]) -> Result<()> {
+    if events.is_empty() {
+      return Ok(());
+    }
+
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    {
+      let mut stmt = tx.prepare_cached(
+        r#"
+        INSERT OR IGNORE INTO local_events (id, event_type, timestamp, duration, app_name, window_title, utc_offset_minutes, remote_session, is_open, url_domain, fullscreen, document, project, virtual_desktop, process_path, category)
+        VALUES (?1, 'app_usage', ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
+        "#,
+      )?;
+
+      let utc_offset = current_utc_offset_minutes();
+      let rules = load_category_rules(&tx)?;
+      let anonymizer = Anonymizer::load(&tx, self.display_key().as_deref(), self.hash_key().as_deref())?;
+      for event in events {
+        let window_info = &event.window_info;
+        let duration = event.ended_at.map_or(0, |ended_at| {
+          ended_at
+            .signed_duration_since(window_info.timestamp)
+            .num_seconds()
+            .clamp(0, MAX_EVENT_DURATION_SECS)
+        });
+
+        let (app_name, title) = stored_names(
+          anonymizer.as_ref(),
+          &tx,
+          &window_info.process_name,
+          Some(&window_info.window_title),
+        )?;
+
+        stmt.execute((
+          &event.id,
+          window_info.timestamp.timestamp_millis(),
+          duration,
+          app_name,
+          title,
+          utc_offset,
+          window_info.remote_session,
+          event.ended_at.is_none(),
+          &window_info.url_domain,
+          window_info.fullscreen,
+          &window_info.document,
+          &window_info.project,
+          &window_info.virtual_desktop,
+          &window_info.process_path,
+          rules.categorize_with_path(&window_info.process_name, window_info.process_path.as_deref()),
+        ))?;
+      }
+    }
+    tx.commit()?;
+    Ok(())
+  }
+
+  /// Store an open app_usage event starting at the window's timestamp and return
+  /// its id; an empty title is stored as NULL.
+  /// It is held back from sync until closed with `close_event_sync`.
+  pub(crate) fn store_event_sync(&self, window_info: &WindowInfo) -> Result<String> {
+    let mut ids = self.store_events_sync(std::slice::from_ref(window_info))?;
+    Ok(ids.remove(0))
+  }
+
+  /// Store many open app_usage events in one transaction, each starting at its
+  /// window's timestamp; returns their ids in input order. All or none are stored.
+  /// A window matching a still-open event (same app and title, starting within
+  /// DUPLICATE_EVENT_WINDOW_MS) isn't stored again; that event's id is returned.
+  pub(crate) fn store_events_sync(&self, windows: &[WindowInfo]) -> Result<Vec<String>> {
+    if windows.is_empty() {
+      return Ok(Vec::new());
+    }
+
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    let utc_offset = current_utc_offset_minutes();
+    let rules = load_category_rules(&tx)?;
+    let anonymizer = Anonymizer::load(&tx, self.display_key().as_deref(), self.hash_key().as_deref())?;
+    let mut ids = Vec::with_capacity(windows.len());
+
+    {
+      let mut stmt = tx.prepare_cached(
+        r#"
+        INSERT INTO local_events (id, event_type, timestamp, duration, app_name, window_title, utc_offset_minutes, remote_session, is_open, url_domain, fullscreen, document, project, virtual_desktop, process_path, category)
+        VALUES (?1, 'app_usage', ?2, 0, ?3, ?4, ?5, ?6, 1, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+        "#,
+      )?;
+      let mut existing = tx.prepare_cached(
+        r#"
+        SELECT id FROM local_events
+        WHERE event_type = 'app_usage' AND is_open = 1
+          AND timestamp BETWEEN ?1 - ?4 AND ?1 + ?4
+          AND app_name = ?2 AND window_title IS ?3
+        ORDER BY timestamp DESC
+        LIMIT 1
+        "#,
+      )?;
+
+      for window_info in windows {
+        let (app_name, title) = stored_names(
+          anonymizer.as_ref(),
+          &tx,
+          &window_info.process_name,
+          Some(&window_info.window_title),
+        )?;
+        let timestamp = window_info.timestamp.timestamp_millis();
+        if let Some(id) = existing
+          .query_row((timestamp, &app_name, &title, DUPLICATE_EVENT_WINDOW_MS), |row| row.get(0))
+          .optional()?
+        {
+          ids.push(id);
+          continue;
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        stmt.execute((
+          &id,
+          timestamp,
+          app_name,
+          title,
+          utc_offset,
+          window_info.remote_session,
+          &window_info.url_domain,
+          window_info.fullscreen,
+          &window_info.document,
+          &window_info.project,
+          &window_info.virtual_desktop,
+          &window_info.process_path,
+          rules.categorize_with_path(&window_info.process_name, window_info.process_path.as_deref()),
+        ))?;
+        ids.push(id);
+      }
+    }
+
+    tx.commit()?;
+    Ok(ids)
+  }
+
+  /// Store a zero-duration system marker event (e.g. "timezone_change")
+  pub(crate) fn store_marker_event_sync(&self, event_type: &str, detail: &str, timestamp: DateTime<Utc>) -> Result<()> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let timestamp = timestamp.timestamp_millis();
+
+    let conn = self.conn.lock().unwrap();
+
+    let mut stmt = conn.prepare_cached(
       r#"
-      CREATE TABLE IF NOT EXISTS local_events (
-        id TEXT PRIMARY KEY,
-        event_type TEXT NOT NULL,
-        timestamp INTEGER NOT NULL,
-        duration INTEGER NOT NULL,
-        app_name TEXT NOT NULL,
-        window_title TEXT,
-        synced INTEGER DEFAULT 0,
-        created_at INTEGER DEFAULT (strftime('%s', 'now') * 1000)
-      );
-
-      CREATE INDEX IF NOT EXISTS idx_local_events_timestamp
-        ON local_events(timestamp DESC);
-
-      CREATE INDEX IF NOT EXISTS idx_local_events_synced
-        ON local_events(synced) WHERE synced = 0;
-
-      CREATE TABLE IF NOT EXISTS sync_state (
-        key TEXT PRIMARY KEY,
-        value TEXT NOT NULL,
-        updated_at INTEGER NOT NULL
-      );
-
-      CREATE TABLE IF NOT EXISTS local_settings (
-        key TEXT PRIMARY KEY,
-        value TEXT NOT NULL,
-        updated_at INTEGER NOT NULL
-      );
-
-      INSERT OR IGNORE INTO local_settings (key, value, updated_at)
-        VALUES ('idle_threshold_seconds', '300', strftime('%s', 'now') * 1000);
+      INSERT INTO local_events (id, event_type, timestamp, duration, app_name, window_title, utc_offset_minutes)
+      VALUES (?1, ?2, ?3, 0, 'system', ?4, ?5)
       "#,
     )?;
 
+    stmt.execute((&id, event_type, timestamp, detail, current_utc_offset_minutes()))?;
+
     Ok(())
   }
 
-  pub(crate) fn store_event_sync(&self, window_info: &WindowInfo) -> Result<()> {
+  /// Store an open system event (e.g. "afk") starting at `started_at` and return its id;
+  /// like app_usage events it is synced once closed
+  pub(crate) fn open_system_event_sync(&self, event_type: &str, started_at: DateTime<Utc>) -> Result<String> {
     let id = uuid::Uuid::new_v4().to_string();
-    let timestamp = Utc::now().timestamp_millis();
-    let event_type = "app_usage";
-    let duration = 0; // Will be updated when window changes
 
     let conn = self.conn.lock().unwrap();
+    conn.execute(
+      r#"
+      INSERT INTO local_events (id, event_type, timestamp, duration, app_name, utc_offset_minutes, is_open)
+      VALUES (?1, ?2, ?3, 0, 'system', ?4, 1)
+      "#,
+      (&id, event_type, started_at.timestamp_millis(), current_utc_offset_minutes()),
+    )?;
 
-    let mut stmt = conn.prepare_cached(
+    Ok(id)
+  }
+
+  /// Store an open coding event from an editor heartbeat: the editor as app,
+  /// the language as title, the file name as document
+  #[cfg_attr(not(feature = "editor-heartbeats"), allow(dead_code))]
+  pub(crate) fn open_coding_event_sync(
+    &self,
+    editor: &str,
+    language: Option<&str>,
+    file: Option<&str>,
+    project: Option<&str>,
+    started_at: DateTime<Utc>,
+  ) -> Result<String> {
+    let id = uuid::Uuid::new_v4().to_string();
+
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
       r#"
-      INSERT INTO local_events (id, event_type, timestamp, duration, app_name, window_title)
-      VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+      INSERT INTO local_events (id, event_type, timestamp, duration, app_name, window_title, utc_offset_minutes, is_open, document, project)
+      VALUES (?1, 'coding', ?2, 0, ?3, ?4, ?5, 1, ?6, ?7)
       "#,
+      (&id, started_at.timestamp_millis(), editor, language, current_utc_offset_minutes(), file, project),
     )?;
 
-    stmt.execute((
-      &id,
-      event_type,
-      timestamp,
-      duration,
-      &window_info.process_name,
-      &window_info.window_title,
-    ))?;
+    Ok(id)
+  }
+
+  /// Store an open terminal event from a shell hook: the shell as app, the
+  /// program (never its arguments) as title, the working directory as document
+  #[cfg_attr(not(feature = "editor-heartbeats"), allow(dead_code))]
+  pub(crate) fn open_terminal_event_sync(
+    &self,
+    shell: &str,
+    program: &str,
+    cwd: Option<&str>,
+    started_at: DateTime<Utc>,
+  ) -> Result<String> {
+    let id = uuid::Uuid::new_v4().to_string();
 
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      r#"
+      INSERT INTO local_events (id, event_type, timestamp, duration, app_name, window_title, utc_offset_minutes, is_open, document)
+      VALUES (?1, 'terminal', ?2, 0, ?3, ?4, ?5, 1, ?6)
+      "#,
+      (&id, started_at.timestamp_millis(), shell, program, current_utc_offset_minutes(), cwd),
+    )?;
+
+    Ok(id)
+  }
+
+  /// Store an open media_playback event for the app playing audio, with the
+  /// track as its title; closed when playback stops or changes
+  pub(crate) fn open_media_event_sync(
+    &self,
+    app_name: &str,
+    title: Option<&str>,
+    started_at: DateTime<Utc>,
+  ) -> Result<String> {
+    let id = uuid::Uuid::new_v4().to_string();
+
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      r#"
+      INSERT INTO local_events (id, event_type, timestamp, duration, app_name, window_title, utc_offset_minutes, is_open)
+      VALUES (?1, 'media_playback', ?2, 0, ?3, ?4, ?5, 1)
+      "#,
+      (&id, started_at.timestamp_millis(), app_name, title, current_utc_offset_minutes()),
+    )?;
+
+    Ok(id)
+  }
+
+  /// Store an open camera_on / mic_on event for the app holding the device;
+  /// closed when the device is released
+  pub(crate) fn open_device_event_sync(&self, event_type: &str, app_name: &str, started_at: DateTime<Utc>) -> Result<String> {
+    let id = uuid::Uuid::new_v4().to_string();
+
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      r#"
+      INSERT INTO local_events (id, event_type, timestamp, duration, app_name, utc_offset_minutes, is_open)
+      VALUES (?1, ?2, ?3, 0, ?4, ?5, 1)
+      "#,
+      (&id, event_type, started_at.timestamp_millis(), app_name, current_utc_offset_minutes()),
+    )?;
+
+    Ok(id)
+  }
+
+  /// Close a focus_session event, listing the apps used during it as its title
+  pub(crate) fn finish_focus_session_sync(&self, id: &str, ended_at: DateTime<Utc>, apps: &[String]) -> Result<()> {
+    {
+      let conn = self.conn.lock().unwrap();
+      conn.execute(
+        "UPDATE local_events SET window_title = ?2 WHERE id = ?1",
+        (id, (!apps.is_empty()).then(|| apps.join(", "))),
+      )?;
+    }
+    self.close_event_sync(id, ended_at)
+  }
+
+  /// Close an open event by setting its duration (seconds) up to `ended_at`,
+  /// capped at the server's per-event maximum, which makes it eligible for sync
+  pub(crate) fn close_event_sync(&self, id: &str, ended_at: DateTime<Utc>) -> Result<()> {
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      r#"
+      UPDATE local_events
+      SET duration = MIN(?3, MAX(0, (?2 - timestamp) / 1000)), is_open = 0
+      WHERE id = ?1
+      "#,
+      (id, ended_at.timestamp_millis(), MAX_EVENT_DURATION_SECS),
+    )?;
     Ok(())
   }
 
-  pub fn get_events(&self, limit: i32, offset: i32) -> Result<Vec<StoredEvent>> {
+  /// Close every event still open (left dangling by a crash) at `ended_at`, or
+  /// at the start of the next event of its type if that is earlier; returns how many
+  pub(crate) fn close_open_events_sync(&self, ended_at: DateTime<Utc>) -> Result<usize> {
     let conn = self.conn.lock().unwrap();
+    let closed = conn.execute(
+      r#"
+      UPDATE local_events
+      SET duration = MIN(?2, MAX(0, (MIN(?1, COALESCE(
+            (SELECT MIN(later.timestamp) FROM local_events later
+             WHERE later.event_type = local_events.event_type AND later.timestamp > local_events.timestamp),
+            ?1)) - timestamp) / 1000)),
+        is_open = 0
+      WHERE is_open = 1
+      "#,
+      (ended_at.timestamp_millis(), MAX_EVENT_DURATION_SECS),
+    )?;
+    Ok(closed)
+  }
 
-    let mut stmt = conn.prepare_cached(
+  /// Events with start time in [start, end) that match `filter`, oldest first
+  pub fn get_events_between(
+    &self,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    filter: &EventFilter,
+  ) -> Result<Vec<StoredEvent>> {
+    let mut sql = format!("SELECT {} FROM local_events WHERE timestamp >= ?1 AND timestamp < ?2", EVENT_COLUMNS);
+    let mut params: Vec<rusqlite::types::Value> = vec![start.timestamp_millis().into(), end.timestamp_millis().into()];
+    for (column, values) in [
+      ("app_name", &filter.apps),
+      ("event_type", &filter.event_types),
+      (EFFECTIVE_CATEGORY, &filter.categories),
+    ] {
+      if values.is_empty() {
+        continue;
+      }
+      let placeholders: Vec<String> = (0..values.len()).map(|i| format!("?{}", params.len() + i + 1)).collect();
+      sql.push_str(&format!(" AND {} IN ({})", column, placeholders.join(", ")));
+      params.extend(values.iter().cloned().map(rusqlite::types::Value::from));
+    }
+    sql.push_str(" ORDER BY timestamp ASC");
+
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(params), map_event_row)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  /// Up to `limit` events with start time in [start, end), ordered by start
+  /// time then id and continuing after `after` (the last event of the
+  /// previous page), so large ranges can be read in chunks
+  pub fn get_events_page(
+    &self,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    after: Option<&StoredEvent>,
+    limit: usize,
+  ) -> Result<Vec<StoredEvent>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(&format!(
+      r#"
+      SELECT {}
+      FROM local_events
+      WHERE timestamp >= ?1 AND timestamp < ?2 AND (timestamp, id) > (?3, ?4)
+      ORDER BY timestamp ASC, id ASC
+      LIMIT ?5
+      "#,
+      EVENT_COLUMNS
+    ))?;
+
+    let (after_timestamp, after_id) =
+      after.map_or((i64::MIN, ""), |event| (event.timestamp.timestamp_millis(), event.id.as_str()));
+    let events = stmt.query_map(
+      (start.timestamp_millis(), end.timestamp_millis(), after_timestamp, after_id, limit as i64),
+      map_event_row,
+    )?;
+    events.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  pub fn get_events(&self, limit: i32, offset: i32) -> Result<Vec<StoredEvent>> {
+    let conn = self.reader()?;
+
+    let mut stmt = conn.prepare_cached(&format!(
       r#"
-      SELECT id, event_type, timestamp, duration, app_name, window_title
+      SELECT {}
       FROM local_events
       ORDER BY timestamp DESC
       LIMIT ?1 OFFSET ?2
       "#,
+      EVENT_COLUMNS
+    ))?;
+
+    let events = stmt.query_map((limit, offset), map_event_row)?;
+
+    events.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  pub fn get_event(&self, id: &str) -> Result<Option<StoredEvent>> {
+    let conn = self.reader()?;
+
+    let mut stmt = conn.prepare_cached(&format!(
+      "SELECT {} FROM local_events WHERE id = ?1",
+      EVENT_COLUMNS
+    ))?;
+
+    let mut events = stmt.query_map([id], map_event_row)?;
+    events.next().transpose().map_err(|e| e.into())
+  }
+
+  pub fn is_event_synced(&self, id: &str) -> Result<bool> {
+    let conn = self.reader()?;
+    let synced: i64 = conn.query_row(
+      "SELECT synced FROM local_events WHERE id = ?1",
+      [id],
+      |row| row.get(0),
+    )?;
+    Ok(synced != 0)
+  }
+
+  /// Delete one event; if it was already uploaded, a tombstone makes the next
+  /// sync remove the server copy too (see `delete_events_propagated`)
+  pub(crate) fn delete_event_sync(&self, id: &str) -> Result<()> {
+    self.delete_events_propagated(&[id.to_string()], DeletionReason::Manual, Utc::now())?;
+    Ok(())
+  }
+
+  /// app_usage time per app for events starting in [start, end), largest first
+  pub fn get_app_totals(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<AppUsageTotal>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      r#"
+      SELECT app_name, SUM(duration), COUNT(*)
+      FROM local_events
+      WHERE event_type = 'app_usage' AND timestamp >= ?1 AND timestamp < ?2
+      GROUP BY app_name
+      ORDER BY SUM(duration) DESC, app_name ASC
+      "#,
     )?;
 
-    let events = stmt.query_map((limit, offset), |row| {
-      Ok(StoredEvent {
-        id: row.get(0)?,
-        event_type: row.get(1)?,
-        timestamp: DateTime::from_timestamp_millis(row.get::<_, i64>(2)?)
-          .unwrap_or_default(),
-        duration: row.get(3)?,
-        app_name: row.get(4)?,
-        window_title: row.get(5)?,
+    let totals = stmt.query_map((start.timestamp_millis(), end.timestamp_millis()), |row| {
+      Ok(AppUsageTotal {
+        app_name: row.get(0)?,
+        duration_seconds: row.get(1)?,
+        event_count: row.get(2)?,
       })
     })?;
 
-    events.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+    totals.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  /// app_usage time per category (see EFFECTIVE_CATEGORY) for events
+  /// starting in [start, end), largest first
+  pub fn get_category_totals(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<CategoryTotal>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(&format!(
+      r#"
+      SELECT COALESCE({}, ?3) AS effective_category, SUM(duration), COUNT(*)
+      FROM local_events
+      WHERE event_type = 'app_usage' AND timestamp >= ?1 AND timestamp < ?2
+      GROUP BY effective_category
+      ORDER BY SUM(duration) DESC, effective_category ASC
+      "#,
+      EFFECTIVE_CATEGORY
+    ))?;
+    let totals = stmt.query_map((start.timestamp_millis(), end.timestamp_millis(), UNCATEGORIZED), |row| {
+      Ok(CategoryTotal {
+        category: row.get(0)?,
+        duration_seconds: row.get(1)?,
+        event_count: row.get(2)?,
+      })
+    })?;
+    totals.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  /// app_usage time per local calendar day for events starting in [start, end),
+  /// oldest first. Each event counts toward the day it started on in the UTC
+  /// offset it was recorded in (see `StoredEvent::local_date`).
+  pub fn get_daily_totals(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<DayTotal>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      r#"
+      SELECT date(timestamp / 1000 + COALESCE(utc_offset_minutes, ?3) * 60, 'unixepoch') AS day,
+        SUM(duration), COUNT(*)
+      FROM local_events
+      WHERE event_type = 'app_usage' AND timestamp >= ?1 AND timestamp < ?2
+      GROUP BY day
+      ORDER BY day ASC
+      "#,
+    )?;
+
+    let totals = stmt.query_map(
+      (start.timestamp_millis(), end.timestamp_millis(), current_utc_offset_minutes()),
+      |row| {
+        Ok(DayTotal {
+          day: row.get(0)?,
+          duration_seconds: row.get(1)?,
+          event_count: row.get(2)?,
+        })
+      },
+    )?;
+
+    totals.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
   }
 
   pub fn get_event_count(&self) -> Result<i64> {
-    let conn = self.conn.lock().unwrap();
+    let conn = self.reader()?;
     let count: i64 = conn.query_row("SELECT COUNT(*) FROM local_events", [], |row| row.get(0))?;
     Ok(count)
   }
 
   pub fn get_unsynced_events(&self) -> Result<Vec<StoredEvent>> {
-    let conn = self.conn.lock().unwrap();
+    let conn = self.reader()?;
 
-    let mut stmt = conn.prepare_cached(
+    let mut stmt = conn.prepare_cached(&format!(
       r#"
-      SELECT id, event_type, timestamp, duration, app_name, window_title
+      SELECT {}
       FROM local_events
-      WHERE synced = 0
+      WHERE synced = 0 AND is_open = 0
       ORDER BY timestamp ASC
       "#,
-    )?;
+      EVENT_COLUMNS
+    ))?;
 
-    let events = stmt.query_map([], |row| {
-      Ok(StoredEvent {
-        id: row.get(0)?,
-        event_type: row.get(1)?,
-        timestamp: DateTime::from_timestamp_millis(row.get::<_, i64>(2)?)
-          .unwrap_or_default(),
-        duration: row.get(3)?,
-        app_name: row.get(4)?,
-        window_title: row.get(5)?,
-      })
-    })?;
+    let events = stmt.query_map([], map_event_row)?;
 
     events.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
   }
@@ -202,39 +1310,45 @@ impl Database {
   }
 
   pub(crate) fn get_last_sync_time_sync(&self) -> Result<Option<DateTime<Utc>>> {
-    let conn = self.conn.lock().unwrap();
-
-    let result: Option<String> = conn
-      .query_row(
-        "SELECT value FROM sync_state WHERE key = 'last_sync_at'",
-        [],
-        |row| row.get(0),
-      )
-      .ok();
+    let result = match self.status_writes.get(StatusTable::SyncState, "last_sync_at") {
+      Some(value) => Some(value),
+      None => {
+        let conn = self.reader()?;
+        conn
+          .query_row(
+            "SELECT value FROM sync_state WHERE key = 'last_sync_at'",
+            [],
+            |row| row.get(0),
+          )
+          .ok()
+      }
+    };
 
     Ok(result.and_then(|ts| ts.parse::<i64>().ok()).and_then(|ts| DateTime::from_timestamp_millis(ts)))
   }
 
+  /// Sync bookkeeping is write-behind; see `flush_status_writes`
   pub fn update_sync_state(&self, key: &str, value: &str) -> Result<()> {
-    let conn = self.conn.lock().unwrap();
-    let now = Utc::now().timestamp_millis();
+    self.status_writes.put(StatusTable::SyncState, key, value);
+    Ok(())
+  }
 
-    conn.execute(
-      r#"
-      INSERT INTO sync_state (key, value, updated_at)
-      VALUES (?1, ?2, ?3)
-      ON CONFLICT(key) DO UPDATE SET
-        value = excluded.value,
-        updated_at = excluded.updated_at
-      "#,
-      (key, value, now),
-    )?;
+  /// A sync_state value, including one still waiting in the write buffer
+  pub fn get_sync_state(&self, key: &str) -> Result<Option<String>> {
+    if let Some(value) = self.status_writes.get(StatusTable::SyncState, key) {
+      return Ok(Some(value));
+    }
 
-    Ok(())
+    let conn = self.reader()?;
+    Ok(conn.query_row("SELECT value FROM sync_state WHERE key = ?1", [key], |row| row.get(0)).optional()?)
   }
 
   pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
-    let conn = self.conn.lock().unwrap();
+    if let Some(value) = self.status_writes.get(StatusTable::Settings, key) {
+      return Ok(Some(value));
+    }
+
+    let conn = self.reader()?;
 
     let result: Option<String> = conn
       .query_row("SELECT value FROM local_settings WHERE key = ?", [key], |row| row.get(0))
@@ -244,10 +1358,20 @@ impl Database {
   }
 
   pub fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+    if is_buffered_setting(key) {
+      self.status_writes.put(StatusTable::Settings, key, value);
+      return Ok(());
+    }
+
     let conn = self.conn.lock().unwrap();
     let now = Utc::now().timestamp_millis();
+    let tx = conn.unchecked_transaction()?;
 
-    conn.execute(
+    let old_value: Option<String> = tx
+      .query_row("SELECT value FROM local_settings WHERE key = ?", [key], |row| row.get(0))
+      .ok();
+
+    tx.execute(
       r#"
       INSERT INTO local_settings (key, value, updated_at)
       VALUES (?1, ?2, ?3)
@@ -258,19 +1382,82 @@ impl Database {
       (key, value, now),
     )?;
 
+    if is_versioned_setting(key) {
+      record_config_change(&tx, SETTING_SCOPE, key, old_value.as_deref(), Some(value), now)?;
+    }
+
+    tx.commit()?;
     Ok(())
   }
 
-  /// Synchronous wrapper for get_unsynced_events
-  /// This method exists to be called from spawn_blocking in async contexts
-  pub fn get_unsynced_events_sync(&self) -> Result<Vec<StoredEvent>> {
-    self.get_unsynced_events()
+  /// Remove a setting; returns false if it was not set
+  pub fn delete_setting(&self, key: &str) -> Result<bool> {
+    let conn = self.conn.lock().unwrap();
+    let now = Utc::now().timestamp_millis();
+    let tx = conn.unchecked_transaction()?;
+
+    let old_value: Option<String> = tx
+      .query_row("SELECT value FROM local_settings WHERE key = ?", [key], |row| row.get(0))
+      .ok();
+    tx.execute("DELETE FROM local_settings WHERE key = ?", [key])?;
+
+    if old_value.is_some() && is_versioned_setting(key) {
+      record_config_change(&tx, SETTING_SCOPE, key, old_value.as_deref(), None, now)?;
+    }
+
+    tx.commit()?;
+    Ok(old_value.is_some())
+  }
+
+  /// The oldest `limit` closed events not yet synced, for one upload batch.
+  /// Blocking; use `Database::read` from async code
+  pub fn get_unsynced_events_sync(&self, limit: usize) -> Result<Vec<StoredEvent>> {
+    let conn = self.reader()?;
+
+    let mut stmt = conn.prepare_cached(&format!(
+      r#"
+      SELECT {}
+      FROM local_events
+      WHERE synced = 0 AND is_open = 0
+      ORDER BY timestamp ASC, id ASC
+      LIMIT ?1
+      "#,
+      EVENT_COLUMNS
+    ))?;
+
+    let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+    let events = stmt.query_map([limit], map_event_row)?;
+
+    events.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  /// Number of closed events waiting to be synced
+  pub fn count_unsynced(&self) -> Result<i64> {
+    let conn = self.reader()?;
+    let count = conn.query_row(
+      "SELECT COUNT(*) FROM local_events WHERE synced = 0 AND is_open = 0",
+      [],
+      |row| row.get(0),
+    )?;
+    Ok(count)
+  }
+
+  /// Number of closed events from `app_name` waiting to be synced
+  pub fn count_unsynced_for_app(&self, app_name: &str) -> Result<i64> {
+    let conn = self.reader()?;
+    let count = conn.query_row(
+      "SELECT COUNT(*) FROM local_events WHERE synced = 0 AND is_open = 0 AND app_name = ?1",
+      [app_name],
+      |row| row.get(0),
+    )?;
+    Ok(count)
   }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::database::RollupGranularity;
   use tempfile::NamedTempFile;
 
   fn create_test_db() -> (Database, NamedTempFile) {
@@ -284,6 +1471,7 @@ mod tests {
       process_name: process_name.to_string(),
       window_title: window_title.to_string(),
       timestamp: Utc::now(),
+      ..Default::default()
     }
   }
 
@@ -310,6 +1498,8 @@ mod tests {
     assert!(tables.contains(&"local_events".to_string()));
     assert!(tables.contains(&"sync_state".to_string()));
     assert!(tables.contains(&"local_settings".to_string()));
+    assert!(tables.contains(&"notifications".to_string()));
+    assert!(tables.contains(&"config_history".to_string()));
   }
 
   #[test]
@@ -333,6 +1523,36 @@ mod tests {
     assert_eq!(db.get_event_count().unwrap(), 5);
   }
 
+  #[test]
+  fn test_store_queued_events() {
+    let (db, _temp) = create_test_db();
+    let started = Utc::now() - chrono::Duration::minutes(5);
+    let queued = |id: &str, ended_at: Option<DateTime<Utc>>| QueuedEvent {
+      id: id.to_string(),
+      window_info: WindowInfo {
+        timestamp: started,
+        ..create_test_window_info("code.exe", "main.rs")
+      },
+      queued_at: started,
+      retry_count: 0,
+      ended_at,
+    };
+
+    let batch = vec![queued("closed", Some(started + chrono::Duration::seconds(90))), queued("open", None)];
+    db.store_queued_events_sync(&batch).unwrap();
+    // Re-flushing the same batch (e.g. after a retry) doesn't duplicate events
+    db.store_queued_events_sync(&batch).unwrap();
+
+    assert_eq!(db.get_event_count().unwrap(), 2);
+    let closed = db.get_event("closed").unwrap().unwrap();
+    assert_eq!(closed.duration, 90);
+    assert_eq!(closed.timestamp.timestamp_millis(), started.timestamp_millis());
+    // Only the closed event can sync
+    let unsynced = db.get_unsynced_events().unwrap();
+    assert_eq!(unsynced.len(), 1);
+    assert_eq!(unsynced[0].id, "closed");
+  }
+
   #[test]
   fn test_get_events_with_limit() {
     let (db, _temp) = create_test_db();
@@ -385,15 +1605,255 @@ mod tests {
   fn test_get_unsynced_events() {
     let (db, _temp) = create_test_db();
 
-    // Store 3 events
+    // Store 3 closed events
     for i in 0..3 {
       let window_info = create_test_window_info(&format!("app{}", i), &format!("Window {}", i));
-      db.store_event_sync(&window_info).unwrap();
+      let id = db.store_event_sync(&window_info).unwrap();
+      db.close_event_sync(&id, Utc::now()).unwrap();
     }
 
     // All should be unsynced initially
     let unsynced = db.get_unsynced_events().unwrap();
     assert_eq!(unsynced.len(), 3);
+    assert_eq!(db.count_unsynced().unwrap(), 3);
+    assert_eq!(db.count_unsynced_for_app("app1").unwrap(), 1);
+    assert_eq!(db.count_unsynced_for_app("missing").unwrap(), 0);
+  }
+
+  #[test]
+  fn test_category_totals_and_filters_follow_stored_and_overridden_categories() {
+    use crate::database::{NewEvent, StorageBackend};
+
+    let (db, _temp) = create_test_db();
+    let now = Utc::now();
+    let event = |app: &str, duration: i32| NewEvent {
+      event_type: "app_usage".to_string(),
+      timestamp: now,
+      duration,
+      app_name: app.to_string(),
+      window_title: None,
+      url_domain: None,
+      remote_session: false,
+    };
+    db.insert_events(&[event("code.exe", 600), event("chrome.exe", 300)]).unwrap();
+    let (start, end) = (now - chrono::Duration::hours(1), now + chrono::Duration::hours(1));
+    let totals = |db: &Database| -> Vec<(String, i64)> {
+      db.get_category_totals(start, end)
+        .unwrap()
+        .into_iter()
+        .map(|total| (total.category, total.duration_seconds))
+        .collect()
+    };
+    let chrome_category = db.get_category_rules().unwrap().categorize("chrome.exe");
+    assert_eq!(totals(&db), vec![("development".to_string(), 600), (chrome_category.clone(), 300)]);
+
+    // A rule change only affects new events, not the category stored with old ones
+    db.set_category_rule("code.exe", "games").unwrap();
+    assert_eq!(totals(&db)[0].0, "development");
+
+    // An override wins even over the category stored with the event
+    db.conn
+      .lock()
+      .unwrap()
+      .execute("INSERT INTO category_overrides (app_name, category, updated_at) VALUES ('CODE.EXE', 'writing', 0)", [])
+      .unwrap();
+    assert_eq!(totals(&db), vec![("writing".to_string(), 600), (chrome_category, 300)]);
+
+    let filter = |category: &str| EventFilter {
+      categories: vec![category.to_string()],
+      ..EventFilter::default()
+    };
+    let writing = db.get_events_between(start, end, &filter("writing")).unwrap();
+    assert_eq!(writing.len(), 1);
+    assert_eq!(writing[0].app_name, "code.exe");
+    assert!(db.get_events_between(start, end, &filter("development")).unwrap().is_empty());
+    assert!(db.get_events_between(start, end, &filter("games")).unwrap().is_empty());
+  }
+
+  #[test]
+  fn test_usage_totals_per_app_category_and_day() {
+    use crate::database::{NewEvent, StorageBackend};
+
+    let (db, _temp) = create_test_db();
+    let day = DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z").unwrap().with_timezone(&Utc);
+    let event = |app: &str, at: DateTime<Utc>, duration: i32| NewEvent {
+      event_type: "app_usage".to_string(),
+      timestamp: at,
+      duration,
+      app_name: app.to_string(),
+      window_title: None,
+      url_domain: None,
+      remote_session: false,
+    };
+    db.insert_events(&[
+      event("code.exe", day, 600),
+      event("chrome.exe", day + chrono::Duration::minutes(20), 300),
+      event("code.exe", day + chrono::Duration::days(1), 900),
+      event("slack.exe", day + chrono::Duration::days(1), 120),
+    ])
+    .unwrap();
+    let (start, end) = (day - chrono::Duration::days(1), day + chrono::Duration::days(3));
+
+    let apps: Vec<_> = db
+      .get_app_totals(start, end)
+      .unwrap()
+      .into_iter()
+      .map(|total| (total.app_name, total.duration_seconds, total.event_count))
+      .collect();
+    assert_eq!(
+      apps,
+      vec![("code.exe".to_string(), 1500, 2), ("chrome.exe".to_string(), 300, 1), ("slack.exe".to_string(), 120, 1)]
+    );
+
+    let categories = db.get_category_totals(start, end).unwrap();
+    assert_eq!(categories[0].category, "development");
+    assert_eq!(categories[0].duration_seconds, 1500);
+    assert_eq!(categories.iter().map(|c| c.duration_seconds).sum::<i64>(), 1920);
+
+    let offset = FixedOffset::east_opt(current_utc_offset_minutes() * 60).unwrap();
+    let local_day = |at: DateTime<Utc>| at.with_timezone(&offset).date_naive();
+    let days: Vec<_> = db
+      .get_daily_totals(start, end)
+      .unwrap()
+      .into_iter()
+      .map(|total| (total.day, total.duration_seconds, total.event_count))
+      .collect();
+    assert_eq!(
+      days,
+      vec![(local_day(day), 900, 2), (local_day(day + chrono::Duration::days(1)), 1020, 2)]
+    );
+    assert!(db.get_daily_totals(end, end + chrono::Duration::days(1)).unwrap().is_empty());
+  }
+
+  #[test]
+  fn test_get_events_between_with_filters() {
+    let (db, _temp) = create_test_db();
+    let start = Utc::now() - chrono::Duration::hours(1);
+    let windows: Vec<_> = ["code.exe", "chrome.exe", "slack.exe"]
+      .iter()
+      .enumerate()
+      .map(|(i, app)| {
+        let mut window_info = create_test_window_info(app, "Window");
+        window_info.timestamp = start + chrono::Duration::minutes(i as i64);
+        window_info
+      })
+      .collect();
+    db.store_events_sync(&windows).unwrap();
+    db.open_system_event_sync("afk", start + chrono::Duration::minutes(5)).unwrap();
+    let end = start + chrono::Duration::minutes(10);
+
+    let apps = |filter: EventFilter| -> Vec<String> {
+      db.get_events_between(start, end, &filter)
+        .unwrap()
+        .into_iter()
+        .map(|event| event.app_name)
+        .collect()
+    };
+
+    assert_eq!(apps(EventFilter::default()), vec!["code.exe", "chrome.exe", "slack.exe", "system"]);
+    assert_eq!(
+      apps(EventFilter {
+        apps: vec!["slack.exe".to_string(), "code.exe".to_string()],
+        ..EventFilter::default()
+      }),
+      vec!["code.exe", "slack.exe"]
+    );
+    assert_eq!(
+      apps(EventFilter {
+        event_types: vec!["afk".to_string()],
+        ..EventFilter::default()
+      }),
+      vec!["system"]
+    );
+    assert_eq!(
+      apps(EventFilter {
+        categories: vec!["development".to_string()],
+        event_types: vec!["app_usage".to_string()],
+        ..EventFilter::default()
+      }),
+      vec!["code.exe"]
+    );
+    assert!(db
+      .get_events_between(end, end + chrono::Duration::hours(1), &EventFilter::default())
+      .unwrap()
+      .is_empty());
+  }
+
+  #[test]
+  fn test_store_events_batch() {
+    let (db, _temp) = create_test_db();
+    let start = Utc::now() - chrono::Duration::hours(2);
+
+    let windows: Vec<_> = (0..3)
+      .map(|i| {
+        let mut window_info = create_test_window_info(&format!("app{}", i), if i == 1 { "" } else { "Title" });
+        window_info.timestamp = start + chrono::Duration::minutes(i);
+        window_info
+      })
+      .collect();
+    let ids = db.store_events_sync(&windows).unwrap();
+    assert_eq!(ids.len(), 3);
+    assert!(db.store_events_sync(&[]).unwrap().is_empty());
+
+    for (i, id) in ids.iter().enumerate() {
+      let event = db.get_event(id).unwrap().unwrap();
+      assert_eq!(event.app_name, format!("app{}", i));
+      assert_eq!(event.timestamp.timestamp_millis(), windows[i].timestamp.timestamp_millis());
+      assert_eq!(event.window_title.is_none(), i == 1);
+    }
+    // Stored open, like single events
+    assert_eq!(db.count_unsynced().unwrap(), 0);
+    db.close_event_sync(&ids[0], start + chrono::Duration::minutes(5)).unwrap();
+    assert_eq!(db.count_unsynced().unwrap(), 1);
+  }
+
+  #[test]
+  fn test_duplicate_open_events_are_not_stored_twice() {
+    let (db, _temp) = create_test_db();
+    let window_info = create_test_window_info("app", "Window");
+
+    // A retried write or double poll of the same window
+    let id = db.store_event_sync(&window_info).unwrap();
+    let mut polled_again = window_info.clone();
+    polled_again.timestamp = window_info.timestamp + chrono::Duration::seconds(1);
+    assert_eq!(db.store_event_sync(&polled_again).unwrap(), id);
+    assert_eq!(db.store_events_sync(&[window_info.clone(), polled_again]).unwrap(), vec![id.clone(), id.clone()]);
+    assert_eq!(db.get_event_count().unwrap(), 1);
+
+    // Another title, a later start or a closed event is a new event
+    db.store_event_sync(&create_test_window_info("app", "Other")).unwrap();
+    let mut later = window_info.clone();
+    later.timestamp = window_info.timestamp + chrono::Duration::seconds(30);
+    assert_ne!(db.store_event_sync(&later).unwrap(), id);
+    db.close_event_sync(&id, window_info.timestamp + chrono::Duration::seconds(2)).unwrap();
+    assert_ne!(db.store_event_sync(&window_info).unwrap(), id);
+    assert_eq!(db.get_event_count().unwrap(), 4);
+  }
+
+  #[test]
+  fn test_get_unsynced_events_limited_oldest_first() {
+    let (db, _temp) = create_test_db();
+    let start = Utc::now() - chrono::Duration::hours(1);
+
+    for i in (0..5).rev() {
+      let mut window_info = create_test_window_info("app", &format!("Window {}", i));
+      window_info.timestamp = start + chrono::Duration::minutes(i);
+      let id = db.store_event_sync(&window_info).unwrap();
+      db.close_event_sync(&id, window_info.timestamp + chrono::Duration::seconds(30)).unwrap();
+    }
+    // Open events are never uploaded
+    db.store_event_sync(&create_test_window_info("app", "Still open")).unwrap();
+
+    assert_eq!(db.count_unsynced().unwrap(), 5);
+    let batch = db.get_unsynced_events_sync(2).unwrap();
+    let titles: Vec<_> = batch.iter().map(|e| e.window_title.clone().unwrap()).collect();
+    assert_eq!(titles, vec!["Window 0", "Window 1"]);
+
+    db.mark_as_synced(&batch.iter().map(|e| e.id.clone()).collect::<Vec<_>>()).unwrap();
+    assert_eq!(db.count_unsynced().unwrap(), 3);
+    let batch = db.get_unsynced_events_sync(10).unwrap();
+    assert_eq!(batch.len(), 3);
+    assert_eq!(batch[0].window_title.as_deref(), Some("Window 2"));
   }
 
   #[test]
@@ -404,7 +1864,8 @@ mod tests {
     let mut event_ids = Vec::new();
     for _ in 0..3 {
       let window_info = create_test_window_info("test_app", "Test Window");
-      db.store_event_sync(&window_info).unwrap();
+      let id = db.store_event_sync(&window_info).unwrap();
+      db.close_event_sync(&id, Utc::now()).unwrap();
 
       // Get the event ID
       let events = db.get_unsynced_events().unwrap();
@@ -453,6 +1914,7 @@ mod tests {
 
     db.update_sync_state("test_key", "value1").unwrap();
     db.update_sync_state("test_key", "value2").unwrap();
+    db.flush_status_writes().unwrap();
 
     let conn = db.conn.lock().unwrap();
     let value: String = conn
@@ -508,6 +1970,7 @@ mod tests {
       process_name: "test_app".to_string(),
       window_title: "Test 🌍 日本語 ~!@#$%^&*()".to_string(),
       timestamp: Utc::now(),
+      ..Default::default()
     };
 
     db.store_event_sync(&window_info).unwrap();
@@ -540,6 +2003,7 @@ mod tests {
       process_name: "test_app".to_string(),
       window_title: "".to_string(),
       timestamp: Utc::now(),
+      ..Default::default()
     };
 
     db.store_event_sync(&window_info).unwrap();
@@ -555,6 +2019,7 @@ mod tests {
       process_name: long_name.clone(),
       window_title: "Test".to_string(),
       timestamp: Utc::now(),
+      ..Default::default()
     };
 
     db.store_event_sync(&window_info).unwrap();
@@ -575,6 +2040,10 @@ mod tests {
     // Check synchronous setting
     let sync: String = conn.query_row("PRAGMA synchronous", [], |row| row.get(0)).unwrap();
     assert_eq!(sync, "1"); // NORMAL = 1
+
+    // New files use incremental auto-vacuum
+    let auto_vacuum: i64 = conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0)).unwrap();
+    assert_eq!(auto_vacuum, 2); // INCREMENTAL = 2
   }
 
   #[test]
@@ -601,7 +2070,8 @@ mod tests {
 
     // Store a valid event first
     let window_info = create_test_window_info("app1", "Window 1");
-    db.store_event_sync(&window_info).unwrap();
+    let id = db.store_event_sync(&window_info).unwrap();
+    db.close_event_sync(&id, Utc::now()).unwrap();
 
     // Try to mark non-existent IDs as synced (should not affect valid data)
     let fake_ids = vec!["fake-id-1".to_string(), "fake-id-2".to_string()];
@@ -611,4 +2081,342 @@ mod tests {
     let unsynced = db.get_unsynced_events().unwrap();
     assert_eq!(unsynced.len(), 1);
   }
+
+  #[test]
+  fn test_event_records_utc_offset() {
+    let (db, _temp) = create_test_db();
+    db.store_event_sync(&create_test_window_info("app", "Window")).unwrap();
+
+    let events = db.get_events(1, 0).unwrap();
+    assert_eq!(events[0].utc_offset_minutes, Some(current_utc_offset_minutes()));
+  }
+
+  #[test]
+  fn test_store_marker_event() {
+    let (db, _temp) = create_test_db();
+    db.store_marker_event_sync("timezone_change", "UTC+08:00 -> UTC+09:00", Utc::now()).unwrap();
+
+    let events = db.get_events(1, 0).unwrap();
+    assert_eq!(events[0].event_type, "timezone_change");
+    assert_eq!(events[0].app_name, "system");
+    assert_eq!(events[0].duration, 0);
+  }
+
+  #[test]
+  fn test_local_date_uses_recorded_offset() {
+    // 2024-01-01 20:00 UTC is already Jan 2 in UTC+8 but still Jan 1 in UTC-5
+    let timestamp = DateTime::parse_from_rfc3339("2024-01-01T20:00:00Z").unwrap().with_timezone(&Utc);
+    let mut event = StoredEvent {
+      id: "id".to_string(),
+      event_type: "app_usage".to_string(),
+      timestamp,
+      duration: 0,
+      app_name: "app".to_string(),
+      window_title: None,
+      utc_offset_minutes: Some(8 * 60),
+      remote_session: false,
+      url_domain: None,
+      fullscreen: false,
+      document: None,
+      project: None,
+      virtual_desktop: None,
+      process_path: None,
+      category: None,
+      session_id: None,
+    };
+    assert_eq!(event.local_date(), NaiveDate::from_ymd_opt(2024, 1, 2).unwrap());
+
+    event.utc_offset_minutes = Some(-5 * 60);
+    assert_eq!(event.local_date(), NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+  }
+
+  #[test]
+  fn test_add_column_to_legacy_table() {
+    let temp_file = NamedTempFile::new().unwrap();
+    {
+      let conn = Connection::open(temp_file.path()).unwrap();
+      conn.execute_batch(
+        r#"
+        CREATE TABLE local_events (
+          id TEXT PRIMARY KEY,
+          event_type TEXT NOT NULL,
+          timestamp INTEGER NOT NULL,
+          duration INTEGER NOT NULL,
+          app_name TEXT NOT NULL,
+          window_title TEXT,
+          synced INTEGER DEFAULT 0,
+          created_at INTEGER DEFAULT (strftime('%s', 'now') * 1000)
+        );
+        "#,
+      ).unwrap();
+    }
+
+    let db = Database::new(temp_file.path()).unwrap();
+    db.store_event_sync(&create_test_window_info("app", "Window")).unwrap();
+    assert_eq!(db.get_event_count().unwrap(), 1);
+    assert!(!db.get_events(1, 0).unwrap()[0].remote_session);
+  }
+
+  #[test]
+  fn test_store_event_keeps_url_domain() {
+    let (db, _temp) = create_test_db();
+    let mut info = create_test_window_info("chrome.exe", "Pull requests");
+    info.url_domain = Some("github.com".to_string());
+    let id = db.store_event_sync(&info).unwrap();
+
+    assert_eq!(db.get_event(&id).unwrap().unwrap().url_domain.as_deref(), Some("github.com"));
+  }
+
+  #[test]
+  fn test_store_event_flags_remote_session() {
+    let (db, _temp) = create_test_db();
+    // Flagged where the window was captured
+    let mut info = create_test_window_info("mstsc.exe", "Remote Desktop");
+    info.remote_session = true;
+    let remote = db.store_event_sync(&info).unwrap();
+
+    assert!(db.get_event(&remote).unwrap().unwrap().remote_session);
+  }
+
+  #[test]
+  fn test_store_event_keeps_fullscreen_flag() {
+    let (db, _temp) = create_test_db();
+    let mut info = create_test_window_info("game.exe", "Game");
+    info.fullscreen = true;
+    let game = db.store_event_sync(&info).unwrap();
+    let editor = db.store_event_sync(&create_test_window_info("code.exe", "main.rs")).unwrap();
+
+    assert!(db.get_event(&game).unwrap().unwrap().fullscreen);
+    assert!(!db.get_event(&editor).unwrap().unwrap().fullscreen);
+  }
+
+  #[test]
+  fn test_get_event_by_id() {
+    let (db, _temp) = create_test_db();
+    let id = db.store_event_sync(&create_test_window_info("app", "Window")).unwrap();
+
+    let event = db.get_event(&id).unwrap().unwrap();
+    assert_eq!(event.id, id);
+    assert_eq!(event.app_name, "app");
+
+    assert!(db.get_event("missing").unwrap().is_none());
+  }
+
+  #[test]
+  fn test_is_event_synced_and_delete() {
+    let (db, _temp) = create_test_db();
+    let id = db.store_event_sync(&create_test_window_info("app", "Window")).unwrap();
+
+    assert!(!db.is_event_synced(&id).unwrap());
+    db.mark_as_synced(&[id.clone()]).unwrap();
+    assert!(db.is_event_synced(&id).unwrap());
+
+    db.delete_event_sync(&id).unwrap();
+    assert_eq!(db.get_event_count().unwrap(), 0);
+    assert_eq!(db.count_unconfirmed_deletions().unwrap(), 1);
+  }
+
+  #[test]
+  fn test_close_event_sets_duration() {
+    let (db, _temp) = create_test_db();
+    let id = db.store_event_sync(&create_test_window_info("app", "Window")).unwrap();
+
+    let started = db.get_event(&id).unwrap().unwrap().timestamp;
+    db.close_event_sync(&id, started + chrono::Duration::seconds(90)).unwrap();
+    assert_eq!(db.get_event(&id).unwrap().unwrap().duration, 90);
+
+    // Clock going backwards never yields negative durations
+    db.close_event_sync(&id, started - chrono::Duration::seconds(10)).unwrap();
+    assert_eq!(db.get_event(&id).unwrap().unwrap().duration, 0);
+
+    // Durations are capped at what the server accepts
+    db.close_event_sync(&id, started + chrono::Duration::days(3)).unwrap();
+    assert_eq!(db.get_event(&id).unwrap().unwrap().duration as i64, MAX_EVENT_DURATION_SECS);
+  }
+
+  #[test]
+  fn test_afk_event_duration() {
+    let (db, _temp) = create_test_db();
+    let idle_since = Utc::now() - chrono::Duration::minutes(5);
+    let id = db.open_system_event_sync("afk", idle_since).unwrap();
+    assert!(db.get_unsynced_events().unwrap().is_empty());
+
+    db.close_event_sync(&id, idle_since + chrono::Duration::minutes(20)).unwrap();
+
+    let event = db.get_event(&id).unwrap().unwrap();
+    assert_eq!(event.event_type, "afk");
+    assert_eq!(event.app_name, "system");
+    assert_eq!(event.duration, 20 * 60);
+    assert_eq!(db.get_unsynced_events().unwrap().len(), 1);
+  }
+
+  #[test]
+  fn test_media_event_keeps_app_and_track() {
+    let (db, _temp) = create_test_db();
+    let started = Utc::now() - chrono::Duration::minutes(4);
+    let id = db.open_media_event_sync("Spotify", Some("Artist - Song"), started).unwrap();
+    assert!(db.get_unsynced_events().unwrap().is_empty());
+
+    db.close_event_sync(&id, started + chrono::Duration::minutes(3)).unwrap();
+
+    let event = db.get_event(&id).unwrap().unwrap();
+    assert_eq!(event.event_type, "media_playback");
+    assert_eq!(event.app_name, "Spotify");
+    assert_eq!(event.window_title.as_deref(), Some("Artist - Song"));
+    assert_eq!(event.duration, 3 * 60);
+  }
+
+  #[test]
+  fn test_device_event_records_app() {
+    let (db, _temp) = create_test_db();
+    let started = Utc::now() - chrono::Duration::minutes(30);
+    let id = db.open_device_event_sync("camera_on", "Zoom.exe", started).unwrap();
+
+    db.close_event_sync(&id, started + chrono::Duration::minutes(25)).unwrap();
+
+    let event = db.get_event(&id).unwrap().unwrap();
+    assert_eq!(event.event_type, "camera_on");
+    assert_eq!(event.app_name, "Zoom.exe");
+    assert!(event.window_title.is_none());
+    assert_eq!(event.duration, 25 * 60);
+  }
+
+  #[test]
+  fn test_open_events_are_not_synced() {
+    let (db, _temp) = create_test_db();
+    let id = db.store_event_sync(&create_test_window_info("app", "Window")).unwrap();
+    db.store_marker_event_sync("system_resume", "", Utc::now()).unwrap();
+
+    let unsynced = db.get_unsynced_events().unwrap();
+    assert_eq!(unsynced.len(), 1);
+    assert_eq!(unsynced[0].event_type, "system_resume");
+
+    db.close_event_sync(&id, Utc::now()).unwrap();
+    assert_eq!(db.get_unsynced_events().unwrap().len(), 2);
+  }
+
+  #[test]
+  fn test_get_events_page_continues_after_last_event() {
+    let (db, _temp) = create_test_db();
+    let start = Utc::now() - chrono::Duration::seconds(1);
+    for i in 0..5 {
+      db.store_event_sync(&create_test_window_info("app", &format!("Window {}", i))).unwrap();
+    }
+    let end = Utc::now() + chrono::Duration::seconds(1);
+
+    let mut titles = Vec::new();
+    let mut last: Option<StoredEvent> = None;
+    loop {
+      let page = db.get_events_page(start, end, last.as_ref(), 2).unwrap();
+      if page.is_empty() {
+        break;
+      }
+      assert!(page.len() <= 2);
+      titles.extend(page.iter().filter_map(|event| event.window_title.clone()));
+      last = page.into_iter().last();
+    }
+
+    titles.sort();
+    assert_eq!(titles, (0..5).map(|i| format!("Window {}", i)).collect::<Vec<_>>());
+  }
+
+  fn user_version(path: &Path) -> i64 {
+    let conn = Connection::open(path).unwrap();
+    schema_version(&conn).unwrap()
+  }
+
+  #[test]
+  fn test_fresh_database_at_latest_version() {
+    let (_db, temp) = create_test_db();
+    assert_eq!(user_version(temp.path()), SCHEMA_VERSION);
+  }
+
+  #[test]
+  fn test_upgrade_from_every_prior_version() {
+    for version in 0..SCHEMA_VERSION {
+      let temp_file = NamedTempFile::new().unwrap();
+      {
+        let conn = Connection::open(temp_file.path()).unwrap();
+        for migration in &MIGRATIONS[..version as usize] {
+          migration(&conn).unwrap();
+        }
+        conn.pragma_update(None, "user_version", version).unwrap();
+        if version >= 1 {
+          conn
+            .execute(
+              "INSERT INTO local_events (id, event_type, timestamp, duration, app_name, window_title)
+               VALUES ('old', 'app_usage', 1000, 60, 'old.exe', 'Old window')",
+              [],
+            )
+            .unwrap();
+        }
+      }
+
+      let db = Database::new(temp_file.path()).unwrap();
+      assert_eq!(user_version(temp_file.path()), SCHEMA_VERSION, "from version {}", version);
+      db.store_event_sync(&create_test_window_info("new.exe", "New window")).unwrap();
+      if version >= 1 {
+        let old = db.get_event("old").unwrap().unwrap();
+        assert_eq!(old.app_name, "old.exe");
+        assert_eq!(old.duration, 60);
+        assert_eq!(old.category.as_deref(), Some(crate::database::UNCATEGORIZED));
+        assert_eq!(db.get_event_count().unwrap(), 2);
+
+        // Existing events are backfilled into the rollups
+        let epoch = DateTime::from_timestamp_millis(0).unwrap();
+        let rollups = db
+          .get_app_rollups(RollupGranularity::Day, epoch, epoch + chrono::Duration::days(1))
+          .unwrap();
+        assert_eq!(rollups[0].app_name, "old.exe");
+        assert_eq!(rollups[0].duration_seconds, 60);
+      } else {
+        assert_eq!(db.get_event_count().unwrap(), 1);
+      }
+      assert_eq!(db.get_category_rules().unwrap().categorize("code.exe"), "development");
+      assert_eq!(db.get_setting("idle_threshold_seconds").unwrap().as_deref(), Some("300"));
+    }
+  }
+
+  #[test]
+  fn test_unversioned_install_upgrades_in_place() {
+    let temp_file = NamedTempFile::new().unwrap();
+    {
+      // An install from before versioning with some later columns already added
+      let conn = Connection::open(temp_file.path()).unwrap();
+      migrate_v1_initial(&conn).unwrap();
+      add_column_if_missing(&conn, "local_events", "utc_offset_minutes", "INTEGER").unwrap();
+      conn
+        .execute_batch("CREATE TABLE category_rules (pattern TEXT PRIMARY KEY, category TEXT NOT NULL, updated_at INTEGER NOT NULL)")
+        .unwrap();
+      conn
+        .execute("INSERT INTO category_rules VALUES ('mine', 'custom', 1)", [])
+        .unwrap();
+    }
+
+    let db = Database::new(temp_file.path()).unwrap();
+    assert_eq!(user_version(temp_file.path()), SCHEMA_VERSION);
+    // Existing rules aren't overwritten by the built-in defaults
+    let rules = db.get_category_rules().unwrap();
+    assert_eq!(rules.categorize("mine.exe"), "custom");
+    assert_eq!(rules.categorize("code.exe"), "other");
+    db.store_event_sync(&create_test_window_info("app", "Window")).unwrap();
+  }
+
+  #[test]
+  fn test_reopening_keeps_version() {
+    let (db, temp) = create_test_db();
+    drop(db);
+    Database::new(temp.path()).unwrap();
+    assert_eq!(user_version(temp.path()), SCHEMA_VERSION);
+  }
+
+  #[test]
+  fn test_newer_schema_rejected() {
+    let temp_file = NamedTempFile::new().unwrap();
+    {
+      let conn = Connection::open(temp_file.path()).unwrap();
+      conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1).unwrap();
+    }
+    assert!(Database::new(temp_file.path()).is_err());
+  }
 }
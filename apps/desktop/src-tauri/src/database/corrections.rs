@@ -0,0 +1,96 @@
+//! Category corrections made by the user.
+//!
+//! Each correction says "this app (and title) belongs in that category". They
+//! are the training data for the optional on-device classifier; see
+//! `crate::classifier`.
+
+use super::Database;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CategoryCorrection {
+  pub id: i64,
+  pub app_name: String,
+  pub window_title: Option<String>,
+  pub category: String,
+  pub created_at: DateTime<Utc>,
+}
+
+impl Database {
+  pub fn add_category_correction(
+    &self,
+    app_name: &str,
+    window_title: Option<&str>,
+    category: &str,
+    created_at: DateTime<Utc>,
+  ) -> Result<i64> {
+    let app_name = app_name.trim();
+    let category = category.trim();
+    if app_name.is_empty() || category.is_empty() {
+      bail!("App name and category must not be empty");
+    }
+
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      "INSERT INTO category_corrections (app_name, window_title, category, created_at) VALUES (?1, ?2, ?3, ?4)",
+      (app_name, window_title, category, created_at.timestamp_millis()),
+    )?;
+    Ok(conn.last_insert_rowid())
+  }
+
+  /// All corrections, oldest first
+  pub fn get_category_corrections(&self) -> Result<Vec<CategoryCorrection>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      "SELECT id, app_name, window_title, category, created_at FROM category_corrections ORDER BY id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+      Ok(CategoryCorrection {
+        id: row.get(0)?,
+        app_name: row.get(1)?,
+        window_title: row.get(2)?,
+        category: row.get(3)?,
+        created_at: DateTime::from_timestamp_millis(row.get(4)?).unwrap_or_default(),
+      })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  pub fn delete_category_correction(&self, id: i64) -> Result<()> {
+    let conn = self.conn.lock().unwrap();
+    conn.execute("DELETE FROM category_corrections WHERE id = ?1", [id])?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  #[test]
+  fn test_corrections_round_trip() {
+    let (db, _temp) = create_test_db();
+    let id = db
+      .add_category_correction(" figma.exe ", Some("Landing page"), "design", Utc::now())
+      .unwrap();
+    db.add_category_correction("obsidian.exe", None, "learning", Utc::now()).unwrap();
+    assert!(db.add_category_correction("x.exe", None, " ", Utc::now()).is_err());
+
+    let corrections = db.get_category_corrections().unwrap();
+    assert_eq!(corrections.len(), 2);
+    assert_eq!(corrections[0].app_name, "figma.exe");
+    assert_eq!(corrections[0].window_title.as_deref(), Some("Landing page"));
+
+    db.delete_category_correction(id).unwrap();
+    assert_eq!(db.get_category_corrections().unwrap().len(), 1);
+  }
+}
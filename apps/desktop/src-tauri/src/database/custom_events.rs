@@ -0,0 +1,191 @@
+//! Events the user or a script logs directly (a commute, reading on paper),
+//! for time the collector can't see.
+//!
+//! Stored as closed "custom" events with the user's type as app and the label
+//! as title, so they sync and show up in reports like any other event. The
+//! optional metadata object stays local.
+
+use super::connection::MAX_EVENT_DURATION_SECS;
+use super::{current_utc_offset_minutes, Database};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub const CUSTOM_EVENT_TYPE: &str = "custom";
+
+/// Longest user event type, in characters
+const MAX_TYPE_CHARS: usize = 50;
+const MAX_LABEL_CHARS: usize = 500;
+const MAX_METADATA_BYTES: usize = 16 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomEvent {
+  pub id: String,
+  /// User-chosen type, e.g. "commute"
+  pub event_type: String,
+  pub label: Option<String>,
+  pub start: DateTime<Utc>,
+  pub end: DateTime<Utc>,
+  pub metadata: Option<serde_json::Value>,
+}
+
+impl Database {
+  pub fn add_custom_event(
+    &self,
+    event_type: &str,
+    label: Option<&str>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    metadata: Option<&serde_json::Value>,
+  ) -> Result<CustomEvent> {
+    let event_type = event_type.trim();
+    if event_type.is_empty() {
+      bail!("Event type cannot be empty");
+    }
+    if event_type.chars().count() > MAX_TYPE_CHARS {
+      bail!("Event type is longer than {} characters", MAX_TYPE_CHARS);
+    }
+    let label = label.map(str::trim).filter(|label| !label.is_empty());
+    if label.is_some_and(|label| label.chars().count() > MAX_LABEL_CHARS) {
+      bail!("Label is longer than {} characters", MAX_LABEL_CHARS);
+    }
+
+    if end <= start {
+      bail!("Event end must be after start");
+    }
+    let duration = (end - start).num_seconds();
+    if duration > MAX_EVENT_DURATION_SECS {
+      bail!("Event cannot be longer than 24 hours");
+    }
+    if end > Utc::now() + chrono::Duration::minutes(1) {
+      bail!("Event cannot end in the future");
+    }
+
+    let metadata_json = match metadata {
+      Some(value) if !value.is_object() => bail!("Metadata must be a JSON object"),
+      Some(value) => {
+        let json = serde_json::to_string(value)?;
+        if json.len() > MAX_METADATA_BYTES {
+          bail!("Metadata is larger than {} bytes", MAX_METADATA_BYTES);
+        }
+        Some(json)
+      }
+      None => None,
+    };
+
+    let event = CustomEvent {
+      id: uuid::Uuid::new_v4().to_string(),
+      event_type: event_type.to_string(),
+      label: label.map(str::to_string),
+      start,
+      end,
+      metadata: metadata.cloned(),
+    };
+
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      r#"
+      INSERT INTO local_events (id, event_type, timestamp, duration, app_name, window_title, utc_offset_minutes, metadata)
+      VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+      "#,
+      (
+        &event.id,
+        CUSTOM_EVENT_TYPE,
+        start.timestamp_millis(),
+        duration,
+        &event.event_type,
+        &event.label,
+        current_utc_offset_minutes(),
+        &metadata_json,
+      ),
+    )?;
+    Ok(event)
+  }
+
+  /// Custom events starting in [start, end), oldest first
+  pub fn get_custom_events(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<CustomEvent>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      r#"
+      SELECT id, app_name, window_title, timestamp, duration, metadata
+      FROM local_events
+      WHERE event_type = ?1 AND timestamp >= ?2 AND timestamp < ?3
+      ORDER BY timestamp
+      "#,
+    )?;
+    let rows = stmt.query_map((CUSTOM_EVENT_TYPE, start.timestamp_millis(), end.timestamp_millis()), |row| {
+      let start = DateTime::from_timestamp_millis(row.get(3)?).unwrap_or_default();
+      let metadata: Option<String> = row.get(5)?;
+      Ok(CustomEvent {
+        id: row.get(0)?,
+        event_type: row.get(1)?,
+        label: row.get(2)?,
+        start,
+        end: start + chrono::Duration::seconds(row.get(4)?),
+        metadata: metadata.and_then(|json| serde_json::from_str(&json).ok()),
+      })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::StorageBackend;
+  use serde_json::json;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  #[test]
+  fn test_custom_event_round_trip() {
+    let (db, _temp) = create_test_db();
+    let end = Utc::now() - chrono::Duration::minutes(5);
+    let start = end - chrono::Duration::minutes(40);
+    let metadata = json!({ "mode": "train", "line": 4 });
+
+    let event = db
+      .add_custom_event(" commute ", Some("Home to office"), start, end, Some(&metadata))
+      .unwrap();
+    assert_eq!(event.event_type, "commute");
+
+    let from = start - chrono::Duration::hours(1);
+    let stored = db.get_custom_events(from, Utc::now()).unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].id, event.id);
+    assert_eq!(stored[0].label.as_deref(), Some("Home to office"));
+    assert_eq!(stored[0].end.timestamp(), end.timestamp());
+    assert_eq!(stored[0].metadata, Some(metadata));
+
+    // A regular closed event, so it syncs like the rest
+    let unsynced = db.unsynced_events().unwrap();
+    assert_eq!(unsynced.len(), 1);
+    assert_eq!(unsynced[0].event_type, "custom");
+    assert_eq!(unsynced[0].app_name, "commute");
+    assert_eq!(unsynced[0].duration, 40 * 60);
+  }
+
+  #[test]
+  fn test_custom_event_validation() {
+    let (db, _temp) = create_test_db();
+    let end = Utc::now() - chrono::Duration::minutes(5);
+    let start = end - chrono::Duration::minutes(10);
+
+    assert!(db.add_custom_event("  ", None, start, end, None).is_err());
+    assert!(db.add_custom_event(&"x".repeat(51), None, start, end, None).is_err());
+    assert!(db.add_custom_event("reading", None, end, start, None).is_err());
+    assert!(db
+      .add_custom_event("reading", None, end - chrono::Duration::hours(25), end, None)
+      .is_err());
+    assert!(db
+      .add_custom_event("reading", None, start, Utc::now() + chrono::Duration::hours(1), None)
+      .is_err());
+    assert!(db.add_custom_event("reading", None, start, end, Some(&json!([1, 2]))).is_err());
+    assert!(db.add_custom_event("reading", None, start, end, None).is_ok());
+  }
+}
@@ -0,0 +1,299 @@
+//! Deletions that must reach the server.
+//!
+//! Deleting an event that was already uploaded leaves a tombstone row (id and
+//! deletion time) in `pending_deletions`. Tombstones go out with the next event
+//! upload, or on their own when there is nothing to upload, and are marked
+//! confirmed once the server acknowledges it no longer holds the events, so
+//! the user can see which deletions have not taken effect remotely yet.
+
+use super::rollups::subtract_from_rollups;
+use super::{Database, EventFilter};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletionReason {
+  /// The user deleted specific events
+  Manual,
+  /// The user erased all data in a time range
+  Purge,
+}
+
+impl DeletionReason {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      DeletionReason::Manual => "manual",
+      DeletionReason::Purge => "purge",
+    }
+  }
+}
+
+/// A deletion the server has not confirmed yet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingDeletion {
+  pub event_id: String,
+  pub reason: String,
+  pub deleted_at: DateTime<Utc>,
+  /// How many times the deletion was sent without confirmation
+  pub attempts: i64,
+  pub last_sent_at: Option<DateTime<Utc>>,
+}
+
+impl Database {
+  /// Delete events locally and queue the synced ones for deletion on the
+  /// server; returns the number removed locally
+  pub fn delete_events_propagated(&self, event_ids: &[String], reason: DeletionReason, now: DateTime<Utc>) -> Result<usize> {
+    if event_ids.is_empty() {
+      return Ok(0);
+    }
+
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    let mut deleted = 0;
+
+    {
+      // Events that never left the device need no server-side deletion
+      let mut queue = tx.prepare_cached(
+        r#"
+        INSERT OR IGNORE INTO pending_deletions (event_id, reason, deleted_at)
+        SELECT id, ?2, ?3 FROM local_events WHERE id = ?1 AND synced = 1
+        "#,
+      )?;
+      let mut delete = tx.prepare_cached("DELETE FROM local_events WHERE id = ?1")?;
+      for id in event_ids {
+        queue.execute((id, reason.as_str(), now.timestamp_millis()))?;
+        subtract_from_rollups(&tx, id)?;
+        deleted += delete.execute([id])?;
+      }
+    }
+
+    tx.commit()?;
+    Ok(deleted)
+  }
+
+  /// Delete every event starting in [start, end); see `delete_events_propagated`
+  pub fn purge_events_between(&self, start: DateTime<Utc>, end: DateTime<Utc>, now: DateTime<Utc>) -> Result<usize> {
+    let ids = {
+      let conn = self.conn.lock().unwrap();
+      let mut stmt = conn.prepare_cached("SELECT id FROM local_events WHERE timestamp >= ?1 AND timestamp < ?2")?;
+      let ids = stmt.query_map((start.timestamp_millis(), end.timestamp_millis()), |row| row.get(0))?;
+      ids.collect::<Result<Vec<String>, _>>()?
+    };
+    self.delete_events_propagated(&ids, DeletionReason::Purge, now)
+  }
+
+  /// How many events `delete_events_matching` would remove, for a dry run
+  pub fn count_events_matching(&self, start: DateTime<Utc>, end: DateTime<Utc>, filter: &EventFilter) -> Result<usize> {
+    Ok(self.get_events_between(start, end, filter)?.len())
+  }
+
+  /// Delete the events starting in [start, end) that match `filter` (apps,
+  /// categories, event types); see `delete_events_propagated`
+  pub fn delete_events_matching(
+    &self,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    filter: &EventFilter,
+    now: DateTime<Utc>,
+  ) -> Result<usize> {
+    let ids: Vec<String> = self.get_events_between(start, end, filter)?.into_iter().map(|event| event.id).collect();
+    self.delete_events_propagated(&ids, DeletionReason::Manual, now)
+  }
+
+  /// Ids of unconfirmed deletions to send, oldest first
+  pub fn get_deletions_to_send(&self, limit: usize) -> Result<Vec<String>> {
+    let conn = self.conn.lock().unwrap();
+    let mut stmt = conn.prepare_cached(
+      "SELECT event_id FROM pending_deletions WHERE confirmed_at IS NULL ORDER BY deleted_at LIMIT ?1",
+    )?;
+    let ids = stmt.query_map([limit as i64], |row| row.get(0))?;
+    ids.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  /// Record that these deletions were sent, whether or not the server answered
+  pub fn record_deletions_sent(&self, event_ids: &[String], sent_at: DateTime<Utc>) -> Result<()> {
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    {
+      let mut stmt = tx.prepare_cached(
+        "UPDATE pending_deletions SET attempts = attempts + 1, last_sent_at = ?2 WHERE event_id = ?1",
+      )?;
+      for id in event_ids {
+        stmt.execute((id, sent_at.timestamp_millis()))?;
+      }
+    }
+    tx.commit()?;
+    Ok(())
+  }
+
+  /// Mark deletions the server acknowledged
+  pub fn confirm_deletions(&self, event_ids: &[String], confirmed_at: DateTime<Utc>) -> Result<()> {
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    {
+      let mut stmt = tx.prepare_cached(
+        "UPDATE pending_deletions SET confirmed_at = ?2 WHERE event_id = ?1 AND confirmed_at IS NULL",
+      )?;
+      for id in event_ids {
+        stmt.execute((id, confirmed_at.timestamp_millis()))?;
+      }
+    }
+    tx.commit()?;
+    Ok(())
+  }
+
+  /// Deletions still present on the server as far as we know, oldest first
+  pub fn get_unconfirmed_deletions(&self) -> Result<Vec<PendingDeletion>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      r#"
+      SELECT event_id, reason, deleted_at, attempts, last_sent_at
+      FROM pending_deletions
+      WHERE confirmed_at IS NULL
+      ORDER BY deleted_at
+      "#,
+    )?;
+    let rows = stmt.query_map([], |row| {
+      Ok(PendingDeletion {
+        event_id: row.get(0)?,
+        reason: row.get(1)?,
+        deleted_at: DateTime::from_timestamp_millis(row.get(2)?).unwrap_or_default(),
+        attempts: row.get(3)?,
+        last_sent_at: row.get::<_, Option<i64>>(4)?.and_then(DateTime::from_timestamp_millis),
+      })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  pub fn count_unconfirmed_deletions(&self) -> Result<i64> {
+    let conn = self.reader()?;
+    let count = conn.query_row(
+      "SELECT COUNT(*) FROM pending_deletions WHERE confirmed_at IS NULL",
+      [],
+      |row| row.get(0),
+    )?;
+    Ok(count)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::{NewEvent, StorageBackend};
+  use chrono::TimeZone;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn event_at(timestamp: DateTime<Utc>) -> NewEvent {
+    NewEvent {
+      event_type: "app_usage".to_string(),
+      timestamp,
+      duration: 60,
+      app_name: "code.exe".to_string(),
+      window_title: None,
+      url_domain: None,
+      remote_session: false,
+    }
+  }
+
+  #[test]
+  fn test_only_synced_deletions_are_queued() {
+    let (db, _temp) = create_test_db();
+    let at = Utc.with_ymd_and_hms(2024, 5, 1, 9, 0, 0).unwrap();
+    let ids = db.insert_events(&[event_at(at), event_at(at)]).unwrap();
+    db.mark_as_synced(&ids[..1]).unwrap();
+
+    let deleted = db.delete_events_propagated(&ids, DeletionReason::Manual, Utc::now()).unwrap();
+
+    assert_eq!(deleted, 2);
+    assert_eq!(db.get_event_count().unwrap(), 0);
+    let pending = db.get_unconfirmed_deletions().unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].event_id, ids[0]);
+    assert_eq!(pending[0].reason, "manual");
+  }
+
+  #[test]
+  fn test_deletion_confirmation_lifecycle() {
+    let (db, _temp) = create_test_db();
+    let at = Utc.with_ymd_and_hms(2024, 5, 1, 9, 0, 0).unwrap();
+    let ids = db.insert_events(&[event_at(at), event_at(at)]).unwrap();
+    db.mark_as_synced(&ids).unwrap();
+    db.delete_events_propagated(&ids, DeletionReason::Manual, Utc::now()).unwrap();
+
+    let to_send = db.get_deletions_to_send(10).unwrap();
+    assert_eq!(to_send.len(), 2);
+    db.record_deletions_sent(&to_send, Utc::now()).unwrap();
+    db.confirm_deletions(&to_send[..1], Utc::now()).unwrap();
+
+    let pending = db.get_unconfirmed_deletions().unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].attempts, 1);
+    assert!(pending[0].last_sent_at.is_some());
+    assert_eq!(db.count_unconfirmed_deletions().unwrap(), 1);
+    assert_eq!(db.get_deletions_to_send(10).unwrap(), vec![to_send[1].clone()]);
+  }
+
+  #[test]
+  fn test_purge_range() {
+    let (db, _temp) = create_test_db();
+    let inside = Utc.with_ymd_and_hms(2024, 5, 1, 9, 0, 0).unwrap();
+    let outside = Utc.with_ymd_and_hms(2024, 6, 1, 9, 0, 0).unwrap();
+    let ids = db.insert_events(&[event_at(inside), event_at(outside)]).unwrap();
+    db.mark_as_synced(&ids).unwrap();
+
+    let start = Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap();
+    let end = Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+    assert_eq!(db.purge_events_between(start, end, Utc::now()).unwrap(), 1);
+
+    assert_eq!(db.get_event_count().unwrap(), 1);
+    let pending = db.get_unconfirmed_deletions().unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].reason, "purge");
+  }
+
+  #[test]
+  fn test_delete_matching_app_and_category() {
+    let (db, _temp) = create_test_db();
+    let at = Utc.with_ymd_and_hms(2024, 5, 1, 9, 0, 0).unwrap();
+    let private = NewEvent {
+      app_name: "firefox.exe".to_string(),
+      ..event_at(at)
+    };
+    let game = NewEvent {
+      app_name: "steam.exe".to_string(),
+      ..event_at(at)
+    };
+    let ids = db.insert_events(&[event_at(at), private, game]).unwrap();
+    db.mark_as_synced(&ids).unwrap();
+    let (start, end) = (at - chrono::Duration::hours(1), at + chrono::Duration::hours(1));
+
+    let by_app = EventFilter {
+      apps: vec!["firefox.exe".to_string()],
+      ..Default::default()
+    };
+    assert_eq!(db.count_events_matching(start, end, &by_app).unwrap(), 1);
+    assert_eq!(db.get_event_count().unwrap(), 3);
+    assert_eq!(db.delete_events_matching(start, end, &by_app, Utc::now()).unwrap(), 1);
+    assert!(db.get_event(&ids[1]).unwrap().is_none());
+
+    let by_category = EventFilter {
+      categories: vec!["gaming".to_string()],
+      ..Default::default()
+    };
+    assert_eq!(db.delete_events_matching(end, end + chrono::Duration::hours(1), &by_category, Utc::now()).unwrap(), 0);
+    assert_eq!(db.delete_events_matching(start, end, &by_category, Utc::now()).unwrap(), 1);
+
+    assert_eq!(db.get_event_count().unwrap(), 1);
+    assert!(db.get_event(&ids[0]).unwrap().is_some());
+    // Both were uploaded, so both are tombstoned for the server
+    assert_eq!(db.count_unconfirmed_deletions().unwrap(), 2);
+  }
+}
@@ -0,0 +1,295 @@
+//! Down-sampling of old app usage into daily aggregates.
+//!
+//! Raw app_usage events older than the policy age (365 days by default) are
+//! folded into per-app daily totals (local calendar days, in the UTC offset
+//! each event was recorded in) and deleted. Only events that
+//! already reached the sync server are folded, so nothing is lost that isn't
+//! kept elsewhere. Everything before the `downsampled_before` boundary is
+//! therefore only available at daily resolution; see `reports::usage_trend`.
+
+use super::connection::current_utc_offset_minutes;
+use super::Database;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use tracing::{debug, error, info};
+
+/// Days of raw history to keep before down-sampling
+pub const DOWNSAMPLE_AFTER_DAYS_SETTING: &str = "downsample_after_days";
+const DEFAULT_DOWNSAMPLE_AFTER_DAYS: i64 = 365;
+
+/// sync_state key holding the aggregated/raw boundary (Unix millis, UTC midnight)
+const DOWNSAMPLED_BEFORE_KEY: &str = "downsampled_before";
+
+const DOWNSAMPLE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// Aggregated app usage for one local calendar day
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DailyUsage {
+  pub day: NaiveDate,
+  pub app_name: String,
+  pub duration_seconds: i64,
+  pub event_count: i64,
+}
+
+/// Start of the UTC day containing `at`
+pub(super) fn utc_day_start(at: DateTime<Utc>) -> DateTime<Utc> {
+  at.date_naive().and_time(NaiveTime::MIN).and_utc()
+}
+
+/// Move the aggregated/raw boundary to `cutoff`; it only moves forward
+pub(super) fn advance_downsampled_before(conn: &Connection, cutoff: DateTime<Utc>) -> Result<()> {
+  conn.execute(
+    r#"
+    INSERT INTO sync_state (key, value, updated_at)
+    VALUES (?1, ?2, ?3)
+    ON CONFLICT(key) DO UPDATE SET
+      value = MAX(CAST(value AS INTEGER), CAST(excluded.value AS INTEGER)),
+      updated_at = excluded.updated_at
+    "#,
+    (DOWNSAMPLED_BEFORE_KEY, cutoff.timestamp_millis().to_string(), Utc::now().timestamp_millis()),
+  )?;
+  Ok(())
+}
+
+impl Database {
+  /// Everything before this instant is only kept as daily aggregates
+  pub fn get_downsampled_before(&self) -> Result<Option<DateTime<Utc>>> {
+    let conn = self.reader()?;
+    let value: Option<String> = conn
+      .query_row("SELECT value FROM sync_state WHERE key = ?1", [DOWNSAMPLED_BEFORE_KEY], |row| row.get(0))
+      .optional()?;
+
+    Ok(value.and_then(|v| v.parse::<i64>().ok()).and_then(DateTime::from_timestamp_millis))
+  }
+
+  /// Cutoff the configured policy implies at `now`, aligned to a UTC day
+  pub fn downsample_cutoff(&self, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let days = self
+      .get_setting(DOWNSAMPLE_AFTER_DAYS_SETTING)?
+      .and_then(|value| value.parse::<i64>().ok())
+      .unwrap_or(DEFAULT_DOWNSAMPLE_AFTER_DAYS);
+    if days < 1 {
+      bail!("{} must be at least 1", DOWNSAMPLE_AFTER_DAYS_SETTING);
+    }
+    Ok(utc_day_start(now - Duration::days(days)))
+  }
+
+  /// Fold synced app_usage events before `cutoff` (rounded down to a UTC day)
+  /// into daily aggregates, each counting toward the day it started on in
+  /// its recorded offset; returns the number of raw events replaced
+  pub fn downsample_before_sync(&self, cutoff: DateTime<Utc>) -> Result<usize> {
+    let cutoff = utc_day_start(cutoff);
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+
+    tx.execute(
+      r#"
+      INSERT INTO daily_app_usage (day, app_name, duration_seconds, event_count)
+      SELECT date(timestamp / 1000 + COALESCE(utc_offset_minutes, ?2) * 60, 'unixepoch'), app_name, SUM(duration), COUNT(*)
+      FROM local_events
+      WHERE event_type = 'app_usage' AND synced = 1 AND is_open = 0 AND timestamp < ?1
+      GROUP BY 1, 2
+      ON CONFLICT(day, app_name) DO UPDATE SET
+        duration_seconds = duration_seconds + excluded.duration_seconds,
+        event_count = event_count + excluded.event_count
+      "#,
+      (cutoff.timestamp_millis(), current_utc_offset_minutes()),
+    )?;
+
+    let folded = tx.execute(
+      "DELETE FROM local_events WHERE event_type = 'app_usage' AND synced = 1 AND is_open = 0 AND timestamp < ?1",
+      [cutoff.timestamp_millis()],
+    )?;
+
+    advance_downsampled_before(&tx, cutoff)?;
+    tx.commit()?;
+    Ok(folded)
+  }
+
+  /// Daily aggregates for local days in [first_day, last_day]
+  pub fn get_daily_usage_between(&self, first_day: NaiveDate, last_day: NaiveDate) -> Result<Vec<DailyUsage>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      r#"
+      SELECT day, app_name, duration_seconds, event_count
+      FROM daily_app_usage
+      WHERE day >= ?1 AND day <= ?2
+      ORDER BY day ASC, app_name ASC
+      "#,
+    )?;
+
+    let rows = stmt.query_map((first_day.to_string(), last_day.to_string()), |row| {
+      Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+    })?;
+
+    let mut usage = Vec::new();
+    for row in rows {
+      let (day, app_name, duration_seconds, event_count) = row?;
+      usage.push(DailyUsage {
+        day: day.parse()?,
+        app_name,
+        duration_seconds,
+        event_count,
+      });
+    }
+    Ok(usage)
+  }
+
+  /// Apply the down-sampling policy once a day
+  pub fn start_downsampler(&self) {
+    let db = self.clone();
+    tauri::async_runtime::spawn(async move {
+      let mut ticker = tokio::time::interval(DOWNSAMPLE_INTERVAL);
+
+      loop {
+        ticker.tick().await;
+
+        let run_db = db.clone();
+        let result = tokio::task::spawn_blocking(move || {
+          let cutoff = run_db.downsample_cutoff(Utc::now())?;
+          run_db.downsample_before_sync(cutoff)
+        })
+        .await;
+
+        match result {
+          Ok(Ok(0)) => debug!("No events to down-sample"),
+          Ok(Ok(folded)) => info!("Down-sampled {} old events into daily aggregates", folded),
+          Ok(Err(e)) => error!("Failed to down-sample old events: {}", e),
+          Err(e) => error!("Down-sampling task failed: {}", e),
+        }
+      }
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::{NewEvent, StorageBackend};
+  use chrono::TimeZone;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn app_event(app: &str, timestamp: DateTime<Utc>, duration: i32) -> NewEvent {
+    NewEvent {
+      event_type: "app_usage".to_string(),
+      timestamp,
+      duration,
+      app_name: app.to_string(),
+      window_title: None,
+      url_domain: None,
+      remote_session: false,
+    }
+  }
+
+  #[test]
+  fn test_downsample_folds_synced_events() {
+    let (db, _temp) = create_test_db();
+    let day = Utc.with_ymd_and_hms(2023, 3, 5, 9, 0, 0).unwrap();
+    let synced = db
+      .insert_events(&[
+        app_event("code.exe", day, 600),
+        app_event("code.exe", day + Duration::hours(2), 300),
+        app_event("chrome.exe", day + Duration::days(1), 120),
+      ])
+      .unwrap();
+    db.mark_as_synced(&synced).unwrap();
+    db.insert_events(&[app_event("slack.exe", day, 60)]).unwrap();
+
+    let folded = db.downsample_before_sync(Utc.with_ymd_and_hms(2023, 4, 1, 12, 0, 0).unwrap()).unwrap();
+
+    assert_eq!(folded, 3);
+    // Unsynced events stay raw
+    assert_eq!(db.get_event_count().unwrap(), 1);
+    assert_eq!(
+      db.get_downsampled_before().unwrap(),
+      Some(Utc.with_ymd_and_hms(2023, 4, 1, 0, 0, 0).unwrap())
+    );
+
+    let first = NaiveDate::from_ymd_opt(2023, 3, 5).unwrap();
+    let usage = db.get_daily_usage_between(first, first + Duration::days(1)).unwrap();
+    assert_eq!(
+      usage,
+      vec![
+        DailyUsage { day: first, app_name: "code.exe".to_string(), duration_seconds: 900, event_count: 2 },
+        DailyUsage {
+          day: first + Duration::days(1),
+          app_name: "chrome.exe".to_string(),
+          duration_seconds: 120,
+          event_count: 1,
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn test_downsample_accumulates_and_boundary_only_moves_forward() {
+    let (db, _temp) = create_test_db();
+    let day = Utc.with_ymd_and_hms(2023, 3, 5, 9, 0, 0).unwrap();
+
+    let ids = db.insert_events(&[app_event("code.exe", day, 600)]).unwrap();
+    db.mark_as_synced(&ids).unwrap();
+    db.downsample_before_sync(Utc.with_ymd_and_hms(2023, 6, 1, 0, 0, 0).unwrap()).unwrap();
+
+    // Synced late, folded by a run with an earlier cutoff
+    let ids = db.insert_events(&[app_event("code.exe", day + Duration::hours(1), 60)]).unwrap();
+    db.mark_as_synced(&ids).unwrap();
+    db.downsample_before_sync(Utc.with_ymd_and_hms(2023, 4, 1, 0, 0, 0).unwrap()).unwrap();
+
+    let usage = db.get_daily_usage_between(day.date_naive(), day.date_naive()).unwrap();
+    assert_eq!(usage[0].duration_seconds, 660);
+    assert_eq!(usage[0].event_count, 2);
+    assert_eq!(
+      db.get_downsampled_before().unwrap(),
+      Some(Utc.with_ymd_and_hms(2023, 6, 1, 0, 0, 0).unwrap())
+    );
+  }
+
+  #[test]
+  fn test_downsample_cutoff_policy() {
+    let (db, _temp) = create_test_db();
+    let now = Utc.with_ymd_and_hms(2024, 6, 15, 13, 30, 0).unwrap();
+
+    assert_eq!(db.downsample_cutoff(now).unwrap(), Utc.with_ymd_and_hms(2023, 6, 16, 0, 0, 0).unwrap());
+
+    db.set_setting(DOWNSAMPLE_AFTER_DAYS_SETTING, "30").unwrap();
+    assert_eq!(db.downsample_cutoff(now).unwrap(), Utc.with_ymd_and_hms(2024, 5, 16, 0, 0, 0).unwrap());
+
+    db.set_setting(DOWNSAMPLE_AFTER_DAYS_SETTING, "0").unwrap();
+    assert!(db.downsample_cutoff(now).is_err());
+  }
+
+  #[test]
+  fn test_downsample_buckets_by_recorded_offset() {
+    let (db, _temp) = create_test_db();
+    // 23:30 UTC is already the next day two hours east of UTC
+    let late = Utc.with_ymd_and_hms(2023, 3, 5, 23, 30, 0).unwrap();
+    let ids = db.insert_events(&[app_event("code.exe", late, 600)]).unwrap();
+    db.mark_as_synced(&ids).unwrap();
+    db.conn
+      .lock()
+      .unwrap()
+      .execute("UPDATE local_events SET utc_offset_minutes = 120 WHERE id = ?1", [&ids[0]])
+      .unwrap();
+
+    db.downsample_before_sync(Utc.with_ymd_and_hms(2023, 4, 1, 0, 0, 0).unwrap()).unwrap();
+
+    let next_day = NaiveDate::from_ymd_opt(2023, 3, 6).unwrap();
+    let usage = db.get_daily_usage_between(late.date_naive(), next_day).unwrap();
+    assert_eq!(usage.len(), 1);
+    assert_eq!(usage[0].day, next_day);
+  }
+
+  #[test]
+  fn test_no_boundary_before_first_run() {
+    let (db, _temp) = create_test_db();
+    assert!(db.get_downsampled_before().unwrap().is_none());
+  }
+}
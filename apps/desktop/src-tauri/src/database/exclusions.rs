@@ -0,0 +1,98 @@
+//! Apps the collector must never record (password managers, medical apps, ...).
+//!
+//! Entries are process names matched exactly, case-insensitively.
+
+use super::Database;
+use anyhow::{bail, Result};
+use chrono::Utc;
+
+fn normalize_process_name(process_name: &str) -> Result<String> {
+  let process_name = process_name.trim().to_lowercase();
+  if process_name.is_empty() {
+    bail!("Process name cannot be empty");
+  }
+  Ok(process_name)
+}
+
+impl Database {
+  /// Excluded process names, alphabetically
+  pub fn get_excluded_apps(&self) -> Result<Vec<String>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached("SELECT process_name FROM excluded_apps ORDER BY process_name")?;
+    let names = stmt.query_map([], |row| row.get(0))?;
+    names.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  pub fn add_excluded_app(&self, process_name: &str) -> Result<()> {
+    let process_name = normalize_process_name(process_name)?;
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      "INSERT OR IGNORE INTO excluded_apps (process_name, added_at) VALUES (?1, ?2)",
+      (process_name, Utc::now().timestamp_millis()),
+    )?;
+    Ok(())
+  }
+
+  /// Returns false if the app was not excluded
+  pub fn remove_excluded_app(&self, process_name: &str) -> Result<bool> {
+    let process_name = normalize_process_name(process_name)?;
+    let conn = self.conn.lock().unwrap();
+    let removed = conn.execute("DELETE FROM excluded_apps WHERE process_name = ?1", [process_name])?;
+    Ok(removed > 0)
+  }
+
+  pub(crate) fn is_app_excluded_sync(&self, process_name: &str) -> Result<bool> {
+    let conn = self.reader()?;
+    let excluded = conn.query_row(
+      "SELECT EXISTS(SELECT 1 FROM excluded_apps WHERE process_name = ?1)",
+      [process_name.trim().to_lowercase()],
+      |row| row.get(0),
+    )?;
+    Ok(excluded)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  #[test]
+  fn test_add_list_remove() {
+    let (db, _temp) = create_test_db();
+    assert!(db.get_excluded_apps().unwrap().is_empty());
+
+    db.add_excluded_app("KeePassXC.exe").unwrap();
+    db.add_excluded_app("1password.exe").unwrap();
+    db.add_excluded_app(" keepassxc.exe ").unwrap();
+
+    assert_eq!(db.get_excluded_apps().unwrap(), vec!["1password.exe", "keepassxc.exe"]);
+
+    assert!(db.remove_excluded_app("KEEPASSXC.EXE").unwrap());
+    assert!(!db.remove_excluded_app("keepassxc.exe").unwrap());
+    assert_eq!(db.get_excluded_apps().unwrap(), vec!["1password.exe"]);
+  }
+
+  #[test]
+  fn test_exclusion_is_case_insensitive_and_exact() {
+    let (db, _temp) = create_test_db();
+    db.add_excluded_app("1Password.exe").unwrap();
+
+    assert!(db.is_app_excluded_sync("1PASSWORD.EXE").unwrap());
+    assert!(!db.is_app_excluded_sync("password.exe").unwrap());
+    assert!(!db.is_app_excluded_sync("1password").unwrap());
+  }
+
+  #[test]
+  fn test_empty_name_rejected() {
+    let (db, _temp) = create_test_db();
+    assert!(db.add_excluded_app("  ").is_err());
+    assert!(db.remove_excluded_app("").is_err());
+  }
+}
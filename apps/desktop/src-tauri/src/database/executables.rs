@@ -0,0 +1,89 @@
+//! Cached SHA-256 hashes of app executables, keyed by path.
+//!
+//! A hash is reused while the file's size and modification time are
+//! unchanged, so each binary is read once per update rather than on every
+//! window switch.
+
+use super::Database;
+use anyhow::Result;
+use rusqlite::OptionalExtension;
+use std::collections::HashMap;
+
+/// A cached hash and the file state it was computed from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutableHash {
+  pub size: u64,
+  /// File modification time, Unix millis
+  pub modified_at: i64,
+  pub sha256: String,
+}
+
+impl Database {
+  pub fn get_executable_hash(&self, path: &str) -> Result<Option<ExecutableHash>> {
+    let conn = self.reader()?;
+    let hash = conn
+      .query_row(
+        "SELECT size, modified_at, sha256 FROM executable_hashes WHERE path = ?1",
+        [path],
+        |row| {
+          Ok(ExecutableHash {
+            size: row.get::<_, i64>(0)? as u64,
+            modified_at: row.get(1)?,
+            sha256: row.get(2)?,
+          })
+        },
+      )
+      .optional()?;
+    Ok(hash)
+  }
+
+  pub fn set_executable_hash(&self, path: &str, hash: &ExecutableHash) -> Result<()> {
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      r#"
+      INSERT INTO executable_hashes (path, size, modified_at, sha256)
+      VALUES (?1, ?2, ?3, ?4)
+      ON CONFLICT(path) DO UPDATE SET
+        size = excluded.size,
+        modified_at = excluded.modified_at,
+        sha256 = excluded.sha256
+      "#,
+      (path, hash.size as i64, hash.modified_at, &hash.sha256),
+    )?;
+    Ok(())
+  }
+
+  /// Latest hash of every executable seen, by path
+  pub fn get_executable_hashes(&self) -> Result<HashMap<String, String>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached("SELECT path, sha256 FROM executable_hashes")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect::<Result<HashMap<_, _>, _>>().map_err(|e| e.into())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  #[test]
+  fn test_executable_hash_upsert() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    assert_eq!(db.get_executable_hash("/usr/bin/code").unwrap(), None);
+
+    let mut hash = ExecutableHash {
+      size: 100,
+      modified_at: 1_700_000_000_000,
+      sha256: "aa".repeat(32),
+    };
+    db.set_executable_hash("/usr/bin/code", &hash).unwrap();
+    hash.size = 200;
+    hash.sha256 = "bb".repeat(32);
+    db.set_executable_hash("/usr/bin/code", &hash).unwrap();
+
+    assert_eq!(db.get_executable_hash("/usr/bin/code").unwrap(), Some(hash.clone()));
+    assert_eq!(db.get_executable_hashes().unwrap().get("/usr/bin/code"), Some(&hash.sha256));
+  }
+}
@@ -0,0 +1,149 @@
+//! Time budgets per category. A category can have a daily and a weekly goal
+//! at the same time; see `crate::goals` for evaluation.
+
+use super::Database;
+use anyhow::{bail, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GoalScope {
+  Daily,
+  Weekly,
+}
+
+impl GoalScope {
+  pub(crate) fn as_str(&self) -> &'static str {
+    match self {
+      GoalScope::Daily => "daily",
+      GoalScope::Weekly => "weekly",
+    }
+  }
+
+  fn parse(value: &str) -> Option<Self> {
+    match value {
+      "daily" => Some(GoalScope::Daily),
+      "weekly" => Some(GoalScope::Weekly),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Goal {
+  pub category: String,
+  pub scope: GoalScope,
+  /// Time allowed per day (daily) or per week (weekly)
+  pub limit_minutes: u32,
+  /// Unused budget rolls forward: into today from yesterday (daily) or
+  /// spread over the rest of the week (weekly)
+  pub carry_over: bool,
+}
+
+impl Database {
+  pub fn get_goals(&self) -> Result<Vec<Goal>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      "SELECT category, scope, limit_minutes, carry_over FROM goals ORDER BY category, scope",
+    )?;
+    let rows = stmt.query_map([], |row| {
+      Ok((
+        row.get::<_, String>(0)?,
+        row.get::<_, String>(1)?,
+        row.get::<_, u32>(2)?,
+        row.get::<_, bool>(3)?,
+      ))
+    })?;
+
+    let mut goals = Vec::new();
+    for row in rows {
+      let (category, scope, limit_minutes, carry_over) = row?;
+      let Some(scope) = GoalScope::parse(&scope) else {
+        continue;
+      };
+      goals.push(Goal { category, scope, limit_minutes, carry_over });
+    }
+    Ok(goals)
+  }
+
+  /// Add or replace the goal for (category, scope)
+  pub fn set_goal(&self, goal: &Goal) -> Result<()> {
+    let category = goal.category.trim();
+    if category.is_empty() {
+      bail!("Goal category cannot be empty");
+    }
+    if goal.limit_minutes == 0 {
+      bail!("Goal limit must be at least one minute");
+    }
+
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      r#"
+      INSERT INTO goals (category, scope, limit_minutes, carry_over, updated_at)
+      VALUES (?1, ?2, ?3, ?4, ?5)
+      ON CONFLICT(category, scope) DO UPDATE SET
+        limit_minutes = excluded.limit_minutes,
+        carry_over = excluded.carry_over,
+        updated_at = excluded.updated_at
+      "#,
+      (category, goal.scope.as_str(), goal.limit_minutes, goal.carry_over, Utc::now().timestamp_millis()),
+    )?;
+    Ok(())
+  }
+
+  /// Returns false if there was no such goal
+  pub fn delete_goal(&self, category: &str, scope: GoalScope) -> Result<bool> {
+    let conn = self.conn.lock().unwrap();
+    let deleted = conn.execute(
+      "DELETE FROM goals WHERE category = ?1 AND scope = ?2",
+      (category.trim(), scope.as_str()),
+    )?;
+    Ok(deleted > 0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn goal(scope: GoalScope, limit_minutes: u32) -> Goal {
+    Goal {
+      category: "entertainment".to_string(),
+      scope,
+      limit_minutes,
+      carry_over: true,
+    }
+  }
+
+  #[test]
+  fn test_daily_and_weekly_goals_coexist() {
+    let (db, _temp) = create_test_db();
+    db.set_goal(&goal(GoalScope::Daily, 60)).unwrap();
+    db.set_goal(&goal(GoalScope::Weekly, 300)).unwrap();
+    db.set_goal(&goal(GoalScope::Daily, 45)).unwrap();
+
+    assert_eq!(db.get_goals().unwrap(), vec![goal(GoalScope::Daily, 45), goal(GoalScope::Weekly, 300)]);
+
+    assert!(db.delete_goal("entertainment", GoalScope::Daily).unwrap());
+    assert!(!db.delete_goal("entertainment", GoalScope::Daily).unwrap());
+    assert_eq!(db.get_goals().unwrap(), vec![goal(GoalScope::Weekly, 300)]);
+  }
+
+  #[test]
+  fn test_invalid_goals_rejected() {
+    let (db, _temp) = create_test_db();
+    assert!(db.set_goal(&goal(GoalScope::Daily, 0)).is_err());
+
+    let mut unnamed = goal(GoalScope::Daily, 30);
+    unnamed.category = "  ".to_string();
+    assert!(db.set_goal(&unnamed).is_err());
+  }
+}
@@ -0,0 +1,329 @@
+//! Versioned history of settings and categorization rule changes.
+//!
+//! Every change is appended to `config_history` with a monotonically
+//! increasing version, so any past state can be reconstructed, diffed
+//! against another version, or restored.
+
+use super::api_tokens::API_TOKEN_KEY_PREFIX;
+use super::Database;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+pub(crate) const SETTING_SCOPE: &str = "setting";
+
+/// Settings that change on their own or hold secrets, and settings whose
+/// setters do more than store the value (rewriting stored names, restarting
+/// the heartbeat listener); none of them are versioned or restored
+const UNVERSIONED_SETTINGS: &[&str] = &[
+  "anonymized_storage",
+  "app_lock_pin_hash",
+  "editor_heartbeat_listener",
+  "editor_heartbeat_port",
+  "last_crash_info",
+  "last_sync_error",
+  "notification_last_digest_date",
+  "server_config",
+  "shell_integration",
+];
+
+pub(crate) fn is_versioned_setting(key: &str) -> bool {
+  !UNVERSIONED_SETTINGS.contains(&key) && !key.starts_with(API_TOKEN_KEY_PREFIX)
+}
+
+pub(crate) fn record_config_change(
+  conn: &Connection,
+  scope: &str,
+  key: &str,
+  old_value: Option<&str>,
+  new_value: Option<&str>,
+  changed_at: i64,
+) -> Result<()> {
+  if old_value == new_value {
+    return Ok(());
+  }
+
+  conn.execute(
+    "INSERT INTO config_history (scope, key, old_value, new_value, changed_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+    (scope, key, old_value, new_value, changed_at),
+  )?;
+
+  Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigChange {
+  pub version: i64,
+  pub scope: String,
+  pub key: String,
+  pub old_value: Option<String>,
+  pub new_value: Option<String>,
+  pub changed_at: DateTime<Utc>,
+}
+
+/// Difference of one key between two versions
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConfigDiff {
+  pub scope: String,
+  pub key: String,
+  pub from_value: Option<String>,
+  pub to_value: Option<String>,
+}
+
+impl Database {
+  /// Change log, newest first, optionally restricted to one scope and a time range
+  pub fn get_config_history(
+    &self,
+    scope: Option<&str>,
+    since: Option<DateTime<Utc>>,
+    limit: i32,
+  ) -> Result<Vec<ConfigChange>> {
+    let conn = self.reader()?;
+
+    let mut stmt = conn.prepare_cached(
+      r#"
+      SELECT version, scope, key, old_value, new_value, changed_at
+      FROM config_history
+      WHERE (?1 IS NULL OR scope = ?1)
+        AND changed_at >= ?2
+      ORDER BY version DESC
+      LIMIT ?3
+      "#,
+    )?;
+
+    let changes = stmt.query_map(
+      (scope, since.map(|t| t.timestamp_millis()).unwrap_or(0), limit),
+      |row| {
+        Ok(ConfigChange {
+          version: row.get(0)?,
+          scope: row.get(1)?,
+          key: row.get(2)?,
+          old_value: row.get(3)?,
+          new_value: row.get(4)?,
+          changed_at: DateTime::from_timestamp_millis(row.get::<_, i64>(5)?).unwrap_or_default(),
+        })
+      },
+    )?;
+
+    changes.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  /// Latest version number (0 if nothing has been recorded)
+  pub fn current_config_version(&self) -> Result<i64> {
+    let conn = self.conn.lock().unwrap();
+    let version: i64 = conn.query_row(
+      "SELECT COALESCE(MAX(version), 0) FROM config_history",
+      [],
+      |row| row.get(0),
+    )?;
+    Ok(version)
+  }
+
+  /// Keys whose value differs between two versions
+  pub fn diff_config_versions(&self, from_version: i64, to_version: i64) -> Result<Vec<ConfigDiff>> {
+    let (low, high) = if from_version <= to_version {
+      (from_version, to_version)
+    } else {
+      (to_version, from_version)
+    };
+
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      r#"
+      SELECT scope, key, old_value, new_value
+      FROM config_history
+      WHERE version > ?1 AND version <= ?2
+      ORDER BY version ASC
+      "#,
+    )?;
+
+    // (value at low, value at high) per key
+    let mut values: BTreeMap<(String, String), (Option<String>, Option<String>)> = BTreeMap::new();
+    let rows = stmt.query_map((low, high), |row| {
+      Ok((
+        row.get::<_, String>(0)?,
+        row.get::<_, String>(1)?,
+        row.get::<_, Option<String>>(2)?,
+        row.get::<_, Option<String>>(3)?,
+      ))
+    })?;
+
+    for row in rows {
+      let (scope, key, old_value, new_value) = row?;
+      values
+        .entry((scope, key))
+        .and_modify(|(_, high_value)| *high_value = new_value.clone())
+        .or_insert((old_value, new_value));
+    }
+
+    let diffs = values
+      .into_iter()
+      .filter(|(_, (low_value, high_value))| low_value != high_value)
+      .map(|((scope, key), (low_value, high_value))| {
+        let (from_value, to_value) = if from_version <= to_version {
+          (low_value, high_value)
+        } else {
+          (high_value, low_value)
+        };
+        ConfigDiff { scope, key, from_value, to_value }
+      })
+      .collect();
+
+    Ok(diffs)
+  }
+
+  /// Restore all versioned settings to their state at `version`. Unversioned
+  /// settings recorded by older builds are left alone. The restore itself is
+  /// recorded as new history entries.
+  pub fn restore_settings_version(&self, version: i64) -> Result<Vec<ConfigDiff>> {
+    let current = self.current_config_version()?;
+    if version < 0 || version > current {
+      bail!("Unknown settings version {}", version);
+    }
+
+    let diffs: Vec<ConfigDiff> = self
+      .diff_config_versions(current, version)?
+      .into_iter()
+      .filter(|diff| diff.scope == SETTING_SCOPE && is_versioned_setting(&diff.key))
+      .collect();
+
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    let now = Utc::now().timestamp_millis();
+
+    for diff in &diffs {
+      let old_value: Option<String> = tx
+        .query_row("SELECT value FROM local_settings WHERE key = ?", [&diff.key], |row| row.get(0))
+        .optional()?;
+
+      match &diff.to_value {
+        Some(value) => {
+          tx.execute(
+            r#"
+            INSERT INTO local_settings (key, value, updated_at)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT(key) DO UPDATE SET
+              value = excluded.value,
+              updated_at = excluded.updated_at
+            "#,
+            (&diff.key, value, now),
+          )?;
+        }
+        None => {
+          tx.execute("DELETE FROM local_settings WHERE key = ?", [&diff.key])?;
+        }
+      }
+
+      record_config_change(&tx, SETTING_SCOPE, &diff.key, old_value.as_deref(), diff.to_value.as_deref(), now)?;
+    }
+
+    tx.commit()?;
+    Ok(diffs)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  #[test]
+  fn test_setting_changes_are_recorded() {
+    let (db, _temp) = create_test_db();
+    db.set_setting("idle_threshold_seconds", "600").unwrap();
+    db.set_setting("idle_threshold_seconds", "600").unwrap(); // no-op
+    db.set_setting("idle_threshold_seconds", "120").unwrap();
+
+    let history = db.get_config_history(Some(SETTING_SCOPE), None, 10).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].old_value.as_deref(), Some("600"));
+    assert_eq!(history[0].new_value.as_deref(), Some("120"));
+    assert_eq!(history[1].old_value.as_deref(), Some("300"));
+  }
+
+  #[test]
+  fn test_unversioned_settings_are_skipped() {
+    let (db, _temp) = create_test_db();
+    db.set_setting("last_sync_error", "timeout").unwrap();
+    db.set_setting("server_config", "{\"jwt_token\":\"secret\"}").unwrap();
+
+    assert_eq!(db.current_config_version().unwrap(), 0);
+  }
+
+  #[test]
+  fn test_diff_between_versions() {
+    let (db, _temp) = create_test_db();
+    db.set_setting("a", "1").unwrap();
+    let v1 = db.current_config_version().unwrap();
+    db.set_setting("a", "2").unwrap();
+    db.set_setting("b", "x").unwrap();
+    db.set_setting("a", "1").unwrap();
+    let v2 = db.current_config_version().unwrap();
+
+    let diff = db.diff_config_versions(v1, v2).unwrap();
+    // "a" went 1 -> 2 -> 1 and is unchanged overall
+    assert_eq!(diff, vec![ConfigDiff {
+      scope: SETTING_SCOPE.to_string(),
+      key: "b".to_string(),
+      from_value: None,
+      to_value: Some("x".to_string()),
+    }]);
+
+    let reverse = db.diff_config_versions(v2, v1).unwrap();
+    assert_eq!(reverse[0].from_value.as_deref(), Some("x"));
+    assert_eq!(reverse[0].to_value, None);
+  }
+
+  #[test]
+  fn test_restore_settings_version() {
+    let (db, _temp) = create_test_db();
+    db.set_setting("idle_threshold_seconds", "600").unwrap();
+    let v1 = db.current_config_version().unwrap();
+    db.set_setting("idle_threshold_seconds", "60").unwrap();
+    db.set_setting("new_key", "value").unwrap();
+
+    let restored = db.restore_settings_version(v1).unwrap();
+    assert_eq!(restored.len(), 2);
+
+    assert_eq!(db.get_setting("idle_threshold_seconds").unwrap(), Some("600".to_string()));
+    assert!(db.get_setting("new_key").unwrap().is_none());
+
+    // Restoring is itself versioned
+    assert!(db.current_config_version().unwrap() > v1 + 2);
+  }
+
+  #[test]
+  fn test_restore_skips_settings_with_side_effects() {
+    let (db, _temp) = create_test_db();
+    db.set_setting("anonymized_storage", "true").unwrap();
+    assert_eq!(db.current_config_version().unwrap(), 0);
+
+    // History written before anonymized_storage stopped being versioned
+    {
+      let conn = db.conn.lock().unwrap();
+      record_config_change(&conn, SETTING_SCOPE, "anonymized_storage", Some("false"), Some("true"), 0).unwrap();
+    }
+    db.set_setting("idle_threshold_seconds", "60").unwrap();
+
+    // Version 0 predates anonymization, but restoring it must not turn it off
+    // behind set_anonymized_storage's back
+    let restored = db.restore_settings_version(0).unwrap();
+    assert_eq!(restored.len(), 1);
+    assert_eq!(restored[0].key, "idle_threshold_seconds");
+    assert!(db.is_anonymized_storage().unwrap());
+  }
+
+  #[test]
+  fn test_restore_unknown_version_fails() {
+    let (db, _temp) = create_test_db();
+    assert!(db.restore_settings_version(42).is_err());
+  }
+}
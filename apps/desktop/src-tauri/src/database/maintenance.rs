@@ -0,0 +1,168 @@
+//! Periodic upkeep so long-running installs don't slow down or rot unnoticed.
+//!
+//! Every MAINTENANCE_INTERVAL the WAL is checkpointed and truncated, the
+//! query planner statistics are refreshed (`PRAGMA optimize`), free pages are
+//! returned to the OS and the file is integrity-checked. Each step takes the
+//! connection lock on its own, so tracking writes can slip in between steps.
+//! The outcome of the last run is kept in sync_state for the UI.
+
+use super::write_buffer::StatusTable;
+use super::Database;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Wait after startup before the first run, to stay out of the way of launch
+const MAINTENANCE_STARTUP_DELAY: Duration = Duration::from_secs(15 * 60);
+
+/// Free pages returned to the OS per run
+const VACUUM_PAGES_PER_RUN: i64 = 2_000;
+
+/// Integrity problems reported at most per run
+const MAX_INTEGRITY_ERRORS: i64 = 20;
+
+/// sync_state key holding the last MaintenanceReport as JSON
+const LAST_MAINTENANCE_KEY: &str = "last_maintenance";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaintenanceReport {
+  pub started_at: DateTime<Utc>,
+  pub duration_ms: i64,
+  /// WAL frames copied into the database file
+  pub checkpointed_frames: i64,
+  /// The checkpoint couldn't finish because a reader held the WAL
+  pub checkpoint_busy: bool,
+  pub freed_pages: i64,
+  pub integrity_ok: bool,
+  pub integrity_errors: Vec<String>,
+}
+
+impl Database {
+  /// Run every maintenance step once and record the outcome
+  pub(crate) fn run_maintenance_sync(&self) -> Result<MaintenanceReport> {
+    let started_at = Utc::now();
+
+    let (checkpoint_busy, checkpointed_frames) = {
+      let conn = self.conn.lock().unwrap();
+      conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| {
+        Ok((row.get::<_, i64>(0)? != 0, row.get::<_, i64>(2)?))
+      })?
+    };
+
+    self.conn.lock().unwrap().execute_batch("PRAGMA optimize;")?;
+
+    let freed_pages = self.incremental_vacuum_sync(VACUUM_PAGES_PER_RUN)?;
+
+    let integrity_errors = {
+      let conn = self.conn.lock().unwrap();
+      let mut stmt = conn.prepare(&format!("PRAGMA integrity_check({})", MAX_INTEGRITY_ERRORS))?;
+      let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+      rows
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|row| row != "ok")
+        .collect::<Vec<_>>()
+    };
+
+    let report = MaintenanceReport {
+      started_at,
+      duration_ms: (Utc::now() - started_at).num_milliseconds(),
+      checkpointed_frames,
+      checkpoint_busy,
+      freed_pages,
+      integrity_ok: integrity_errors.is_empty(),
+      integrity_errors,
+    };
+    self.update_sync_state(LAST_MAINTENANCE_KEY, &serde_json::to_string(&report)?)?;
+    Ok(report)
+  }
+
+  /// Outcome of the most recent maintenance run, if any has finished
+  pub fn get_last_maintenance(&self) -> Result<Option<MaintenanceReport>> {
+    let value = match self.status_writes.get(StatusTable::SyncState, LAST_MAINTENANCE_KEY) {
+      Some(value) => Some(value),
+      None => {
+        let conn = self.reader()?;
+        conn
+          .query_row("SELECT value FROM sync_state WHERE key = ?1", [LAST_MAINTENANCE_KEY], |row| row.get(0))
+          .optional()?
+      }
+    };
+
+    Ok(value.and_then(|json: String| serde_json::from_str(&json).ok()))
+  }
+
+  /// Run maintenance every MAINTENANCE_INTERVAL, starting a while after launch
+  pub fn start_maintenance_scheduler(&self) {
+    let db = self.clone();
+    tauri::async_runtime::spawn(async move {
+      let mut ticker = tokio::time::interval_at(
+        tokio::time::Instant::now() + MAINTENANCE_STARTUP_DELAY,
+        MAINTENANCE_INTERVAL,
+      );
+      ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+      loop {
+        ticker.tick().await;
+
+        let run_db = db.clone();
+        match tokio::task::spawn_blocking(move || run_db.run_maintenance_sync()).await {
+          Ok(Ok(report)) if !report.integrity_ok => {
+            warn!("Database integrity check failed: {}", report.integrity_errors.join("; "))
+          }
+          Ok(Ok(report)) => info!(
+            "Database maintenance done in {} ms ({} WAL frames checkpointed, {} pages freed)",
+            report.duration_ms, report.checkpointed_frames, report.freed_pages
+          ),
+          Ok(Err(e)) => error!("Database maintenance failed: {}", e),
+          Err(e) => error!("Database maintenance task failed: {}", e),
+        }
+      }
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  #[test]
+  fn test_maintenance_runs_and_records_status() {
+    let (db, _temp) = create_test_db();
+    assert!(db.get_last_maintenance().unwrap().is_none());
+
+    for i in 0..50 {
+      let window = WindowInfo {
+        process_name: "code.exe".to_string(),
+        window_title: format!("Window {}", i),
+        timestamp: Utc::now(),
+        ..Default::default()
+      };
+      db.store_event_sync(&window).unwrap();
+    }
+
+    let report = db.run_maintenance_sync().unwrap();
+    assert!(report.integrity_ok);
+    assert!(report.integrity_errors.is_empty());
+    assert!(!report.checkpoint_busy);
+    assert!(report.duration_ms >= 0);
+
+    // Buffered until the next flush, and still readable after it
+    assert_eq!(db.get_last_maintenance().unwrap(), Some(report.clone()));
+    db.flush_status_writes().unwrap();
+    assert_eq!(db.get_last_maintenance().unwrap(), Some(report));
+  }
+}
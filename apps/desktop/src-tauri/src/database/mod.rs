@@ -1,28 +1,151 @@
+mod annotations;
+mod anonymize;
+mod api_tokens;
+mod app_sessions;
+mod backend;
+mod backup;
+#[cfg(feature = "sqlcipher")]
+mod cipher;
 mod connection;
+mod corrections;
+mod custom_events;
+mod deletions;
+mod downsample;
+mod exclusions;
+mod executables;
+mod goals;
+mod history;
+mod maintenance;
+mod notifications;
+mod projects;
+mod readers;
+mod recovery;
+mod redaction;
+mod remote_events;
+mod retention;
+mod rollups;
+mod rules;
+mod search;
+mod statements;
+mod storage;
+mod tags;
+mod title_policies;
+mod write_buffer;
+mod writer;
 
-pub use connection::{Database, StoredEvent};
+pub use annotations::Annotation;
+pub use api_tokens::{is_local_api_host, ApiScope, ApiToken, CreatedApiToken, LaunchToken};
+pub use app_sessions::AppSession;
+pub use backend::{AppUsageTotal, NewEvent, StorageBackend};
+pub use backup::{BackupInfo, RestoreReport};
+pub use connection::{current_utc_offset_minutes, CategoryTotal, Database, DayTotal, EventFilter, StoredEvent};
+pub(crate) use connection::MAX_EVENT_DURATION_SECS;
+pub use corrections::CategoryCorrection;
+pub use custom_events::CustomEvent;
+pub use deletions::{DeletionReason, PendingDeletion};
+pub use downsample::DailyUsage;
+pub use executables::ExecutableHash;
+pub use goals::{Goal, GoalScope};
+pub use history::{ConfigChange, ConfigDiff};
+pub use maintenance::MaintenanceReport;
+pub use notifications::StoredNotification;
+pub use projects::ProjectRule;
+pub use recovery::RecoveryReport;
+pub use redaction::RedactionRule;
+pub use remote_events::{RemoteEvent, TimelineEvent};
+pub use retention::{RetentionAction, RetentionPolicy};
+pub use rollups::{AppRollup, CategoryRollup, RollupGranularity};
+pub use rules::{CategoryRule, CategoryRules, UNCATEGORIZED};
+pub use search::DEFAULT_SEARCH_LIMIT;
+pub use statements::StoredStatement;
+pub use storage::StorageStats;
+pub use tags::Tag;
+pub use title_policies::{AppTitlePolicy, TitlePolicy};
+pub use write_buffer::DbStats;
 
 use crate::collector::window_tracker::WindowInfo;
 
 impl Database {
-  /// Async wrapper for store_event (blocking operation)
-  pub async fn store_event(&self, window_info: &WindowInfo) -> anyhow::Result<()> {
-    let db = self.clone();
+  /// Async wrapper for store_event, run on the writer thread
+  pub async fn store_event(&self, window_info: &WindowInfo) -> anyhow::Result<String> {
     let window_info = window_info.clone();
-    tokio::task::spawn_blocking(move || {
-      db.store_event_sync(&window_info)
-    })
-    .await
-    .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?
+    self.write(move |db| db.store_event_sync(&window_info)).await
+  }
+
+  /// Async wrapper for store_queued_events, run on the writer thread
+  pub async fn store_queued_events(
+    &self,
+    events: Vec<crate::collector::event_queue::QueuedEvent>,
+  ) -> anyhow::Result<()> {
+    self.write(move |db| db.store_queued_events_sync(&events)).await
+  }
+
+  /// Async wrapper for store_marker_event, run on the writer thread
+  pub async fn store_marker_event(
+    &self,
+    event_type: &str,
+    detail: &str,
+    timestamp: chrono::DateTime<chrono::Utc>,
+  ) -> anyhow::Result<()> {
+    let event_type = event_type.to_string();
+    let detail = detail.to_string();
+    self.write(move |db| db.store_marker_event_sync(&event_type, &detail, timestamp)).await
+  }
+
+  /// Async wrapper for open_system_event, run on the writer thread
+  pub async fn open_system_event(
+    &self,
+    event_type: &str,
+    started_at: chrono::DateTime<chrono::Utc>,
+  ) -> anyhow::Result<String> {
+    let event_type = event_type.to_string();
+    self.write(move |db| db.open_system_event_sync(&event_type, started_at)).await
+  }
+
+  /// Async wrapper for open_media_event, run on the writer thread
+  pub async fn open_media_event(
+    &self,
+    app_name: &str,
+    title: Option<&str>,
+    started_at: chrono::DateTime<chrono::Utc>,
+  ) -> anyhow::Result<String> {
+    let app_name = app_name.to_string();
+    let title = title.map(str::to_string);
+    self.write(move |db| db.open_media_event_sync(&app_name, title.as_deref(), started_at)).await
+  }
+
+  /// Async wrapper for open_device_event, run on the writer thread
+  pub async fn open_device_event(
+    &self,
+    event_type: &str,
+    app_name: &str,
+    started_at: chrono::DateTime<chrono::Utc>,
+  ) -> anyhow::Result<String> {
+    let event_type = event_type.to_string();
+    let app_name = app_name.to_string();
+    self.write(move |db| db.open_device_event_sync(&event_type, &app_name, started_at)).await
+  }
+
+  /// Async wrapper for close_event, run on the writer thread
+  pub async fn close_event(&self, id: &str, ended_at: chrono::DateTime<chrono::Utc>) -> anyhow::Result<()> {
+    let id = id.to_string();
+    self.write(move |db| db.close_event_sync(&id, ended_at)).await
+  }
+
+  /// Async wrapper for is_app_excluded, run on the blocking pool
+  pub async fn is_app_excluded(&self, process_name: &str) -> anyhow::Result<bool> {
+    let process_name = process_name.to_string();
+    self.read(move |db| db.is_app_excluded_sync(&process_name)).await
+  }
+
+  /// Async wrapper for title_policy, run on the blocking pool
+  pub async fn title_policy(&self, process_name: &str) -> anyhow::Result<TitlePolicy> {
+    let process_name = process_name.to_string();
+    self.read(move |db| db.title_policy_sync(&process_name)).await
   }
 
   /// Async wrapper for get_last_sync_time
   pub async fn get_last_sync_time(&self) -> anyhow::Result<Option<chrono::DateTime<chrono::Utc>>> {
-    let db = self.clone();
-    tokio::task::spawn_blocking(move || {
-      db.get_last_sync_time_sync()
-    })
-    .await
-    .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?
+    self.read(|db| db.get_last_sync_time_sync()).await
   }
 }
@@ -0,0 +1,146 @@
+use super::Database;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StoredNotification {
+  pub id: String,
+  pub kind: String,
+  pub title: String,
+  pub body: String,
+  pub created_at: DateTime<Utc>,
+  pub is_read: bool,
+}
+
+fn map_notification_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<StoredNotification> {
+  Ok(StoredNotification {
+    id: row.get(0)?,
+    kind: row.get(1)?,
+    title: row.get(2)?,
+    body: row.get(3)?,
+    created_at: DateTime::from_timestamp_millis(row.get::<_, i64>(4)?).unwrap_or_default(),
+    is_read: row.get::<_, i64>(5)? != 0,
+  })
+}
+
+impl Database {
+  pub fn insert_notification(&self, kind: &str, title: &str, body: &str) -> Result<String> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = Utc::now().timestamp_millis();
+
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      "INSERT INTO notifications (id, kind, title, body, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+      (&id, kind, title, body, now),
+    )?;
+
+    Ok(id)
+  }
+
+  /// Notification feed, newest first
+  pub fn get_notifications(&self, unread_only: bool, limit: i32) -> Result<Vec<StoredNotification>> {
+    let conn = self.reader()?;
+
+    let mut stmt = conn.prepare_cached(
+      r#"
+      SELECT id, kind, title, body, created_at, is_read
+      FROM notifications
+      WHERE (?1 = 0 OR is_read = 0)
+      ORDER BY created_at DESC
+      LIMIT ?2
+      "#,
+    )?;
+
+    let notifications = stmt.query_map((unread_only as i64, limit), map_notification_row)?;
+    notifications.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  pub fn mark_notifications_read(&self, ids: &[String]) -> Result<()> {
+    if ids.is_empty() {
+      return Ok(());
+    }
+
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+
+    for id in ids {
+      tx.execute("UPDATE notifications SET is_read = 1 WHERE id = ?", [id])?;
+    }
+
+    tx.commit()?;
+    Ok(())
+  }
+
+  /// Return notifications not yet included in a digest and mark them as digested
+  pub fn take_undigested_notifications(&self) -> Result<Vec<StoredNotification>> {
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+
+    let notifications = {
+      let mut stmt = tx.prepare_cached(
+        r#"
+        SELECT id, kind, title, body, created_at, is_read
+        FROM notifications
+        WHERE digested = 0
+        ORDER BY created_at ASC
+        "#,
+      )?;
+      let rows = stmt.query_map([], map_notification_row)?;
+      rows.collect::<Result<Vec<_>, _>>()?
+    };
+
+    tx.execute("UPDATE notifications SET digested = 1 WHERE digested = 0", [])?;
+    tx.commit()?;
+
+    Ok(notifications)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  #[test]
+  fn test_insert_and_get_notifications() {
+    let (db, _temp) = create_test_db();
+    db.insert_notification("sync_error", "Sync failed", "Network error").unwrap();
+    db.insert_notification("insight", "Focus streak", "3 hours of deep work").unwrap();
+
+    let feed = db.get_notifications(false, 10).unwrap();
+    assert_eq!(feed.len(), 2);
+    assert!(feed.iter().all(|n| !n.is_read));
+  }
+
+  #[test]
+  fn test_mark_notifications_read() {
+    let (db, _temp) = create_test_db();
+    let id = db.insert_notification("sync_error", "Sync failed", "Network error").unwrap();
+    db.insert_notification("insight", "Focus streak", "3 hours of deep work").unwrap();
+
+    db.mark_notifications_read(&[id]).unwrap();
+
+    let unread = db.get_notifications(true, 10).unwrap();
+    assert_eq!(unread.len(), 1);
+    assert_eq!(unread[0].kind, "insight");
+  }
+
+  #[test]
+  fn test_take_undigested_only_once() {
+    let (db, _temp) = create_test_db();
+    db.insert_notification("sync_error", "Sync failed", "Network error").unwrap();
+
+    assert_eq!(db.take_undigested_notifications().unwrap().len(), 1);
+    assert!(db.take_undigested_notifications().unwrap().is_empty());
+
+    // Digesting doesn't mark items as read
+    assert_eq!(db.get_notifications(true, 10).unwrap().len(), 1);
+  }
+}
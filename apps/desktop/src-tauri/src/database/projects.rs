@@ -0,0 +1,116 @@
+//! User-defined project rules (window title regex -> project label).
+//!
+//! The first enabled rule, in creation order, whose pattern matches a
+//! window title gives the event its project; see `crate::collector::projects`.
+
+use super::Database;
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectRule {
+  pub id: i64,
+  pub pattern: String,
+  pub project: String,
+  pub enabled: bool,
+}
+
+fn validate_rule(pattern: &str, project: &str) -> Result<()> {
+  if pattern.is_empty() {
+    bail!("Project pattern cannot be empty");
+  }
+  if project.trim().is_empty() {
+    bail!("Project name cannot be empty");
+  }
+  Regex::new(pattern).with_context(|| format!("Invalid project pattern '{}'", pattern))?;
+  Ok(())
+}
+
+impl Database {
+  /// All rules in the order they are tried
+  pub fn get_project_rules(&self) -> Result<Vec<ProjectRule>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached("SELECT id, pattern, project, enabled FROM project_rules ORDER BY id")?;
+    let rules = stmt.query_map([], |row| {
+      Ok(ProjectRule {
+        id: row.get(0)?,
+        pattern: row.get(1)?,
+        project: row.get(2)?,
+        enabled: row.get(3)?,
+      })
+    })?;
+    rules.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  /// Add an enabled rule; the pattern must be a valid regex
+  pub fn add_project_rule(&self, pattern: &str, project: &str) -> Result<ProjectRule> {
+    validate_rule(pattern, project)?;
+    let project = project.trim();
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      "INSERT INTO project_rules (pattern, project, enabled, created_at) VALUES (?1, ?2, 1, ?3)",
+      (pattern, project, Utc::now().timestamp_millis()),
+    )?;
+    Ok(ProjectRule {
+      id: conn.last_insert_rowid(),
+      pattern: pattern.to_string(),
+      project: project.to_string(),
+      enabled: true,
+    })
+  }
+
+  /// Returns false if there is no such rule
+  pub fn set_project_rule_enabled(&self, id: i64, enabled: bool) -> Result<bool> {
+    let conn = self.conn.lock().unwrap();
+    let updated = conn.execute("UPDATE project_rules SET enabled = ?2 WHERE id = ?1", (id, enabled))?;
+    Ok(updated > 0)
+  }
+
+  /// Returns false if there is no such rule
+  pub fn delete_project_rule(&self, id: i64) -> Result<bool> {
+    let conn = self.conn.lock().unwrap();
+    let deleted = conn.execute("DELETE FROM project_rules WHERE id = ?1", [id])?;
+    Ok(deleted > 0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  #[test]
+  fn test_add_toggle_delete() {
+    let (db, _temp) = create_test_db();
+    let repo = db.add_project_rule("my-repo —", " My repo ").unwrap();
+    let tickets = db.add_project_rule(r"JIRA-\d+", "Jira").unwrap();
+    assert_eq!(repo.project, "My repo");
+
+    assert_eq!(db.get_project_rules().unwrap(), vec![repo.clone(), tickets.clone()]);
+
+    assert!(db.set_project_rule_enabled(repo.id, false).unwrap());
+    assert!(!db.get_project_rules().unwrap()[0].enabled);
+
+    assert!(db.delete_project_rule(tickets.id).unwrap());
+    assert!(!db.delete_project_rule(tickets.id).unwrap());
+    assert!(!db.set_project_rule_enabled(tickets.id, true).unwrap());
+    assert_eq!(db.get_project_rules().unwrap().len(), 1);
+  }
+
+  #[test]
+  fn test_invalid_rule_rejected() {
+    let (db, _temp) = create_test_db();
+    assert!(db.add_project_rule("(unclosed", "x").is_err());
+    assert!(db.add_project_rule("", "x").is_err());
+    assert!(db.add_project_rule("repo", "  ").is_err());
+    assert!(db.get_project_rules().unwrap().is_empty());
+  }
+}
@@ -0,0 +1,163 @@
+//! Read-only connections for queries.
+//!
+//! Writes go through the single write connection (`Database::conn`), from
+//! async code by way of the writer thread (see `writer`). Queries use their
+//! own read-only connections, so reports, the UI and the sync client don't
+//! queue behind the collector's writes; in WAL mode readers and the writer
+//! run concurrently. Readers see committed data only. `Database::read` runs
+//! a query from async code on the blocking pool.
+
+use super::Database;
+use anyhow::Result;
+use rusqlite::{Connection, OpenFlags};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Idle read connections kept for reuse; more are opened under load
+const MAX_IDLE_READERS: usize = 4;
+
+const READER_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) struct ReadPool {
+  path: PathBuf,
+  idle: Mutex<Vec<Connection>>,
+  /// Bumped when the database is re-keyed; older connections aren't reused
+  generation: AtomicU64,
+}
+
+impl ReadPool {
+  pub(super) fn new(path: &Path) -> Self {
+    Self {
+      path: path.to_path_buf(),
+      idle: Mutex::new(Vec::new()),
+      generation: AtomicU64::new(0),
+    }
+  }
+
+  /// Close the idle connections and keep those in use from coming back
+  pub(super) fn discard_idle(&self) {
+    let mut idle = self.idle.lock().unwrap();
+    self.generation.fetch_add(1, Ordering::SeqCst);
+    idle.clear();
+  }
+}
+
+/// A pooled read-only connection, returned to the pool on drop
+pub(crate) struct ReadConnection<'a> {
+  pool: &'a ReadPool,
+  conn: Option<Connection>,
+  generation: u64,
+}
+
+impl Deref for ReadConnection<'_> {
+  type Target = Connection;
+
+  fn deref(&self) -> &Connection {
+    self.conn.as_ref().expect("read connection used after release")
+  }
+}
+
+impl Drop for ReadConnection<'_> {
+  fn drop(&mut self) {
+    if let Some(conn) = self.conn.take() {
+      let mut idle = self.pool.idle.lock().unwrap();
+      if idle.len() < MAX_IDLE_READERS && self.generation == self.pool.generation.load(Ordering::SeqCst) {
+        idle.push(conn);
+      }
+    }
+  }
+}
+
+impl Database {
+  /// Open another database file the way this one was opened (same key)
+  pub(super) fn open_companion(&self, path: &Path, flags: OpenFlags) -> Result<Connection> {
+    let conn = Connection::open_with_flags(path, flags)?;
+    #[cfg(feature = "sqlcipher")]
+    if let Some(key) = &*self.key.read().unwrap() {
+      super::cipher::apply_key(&conn, key)?;
+    }
+    Ok(conn)
+  }
+
+  /// A read-only connection for queries
+  pub(crate) fn reader(&self) -> Result<ReadConnection<'_>> {
+    let generation = self.readers.generation.load(Ordering::SeqCst);
+    let idle = self.readers.idle.lock().unwrap().pop();
+    let conn = match idle {
+      Some(conn) => conn,
+      None => {
+        let conn = self.open_companion(
+          &self.readers.path,
+          OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        conn.busy_timeout(READER_BUSY_TIMEOUT)?;
+        conn
+      }
+    };
+
+    Ok(ReadConnection {
+      pool: &self.readers,
+      conn: Some(conn),
+      generation,
+    })
+  }
+
+  /// Run the blocking query `read` off the async runtime and wait for its result
+  pub async fn read<T, F>(&self, read: F) -> Result<T>
+  where
+    T: Send + 'static,
+    F: FnOnce(&Database) -> Result<T> + Send + 'static,
+  {
+    let db = self.clone();
+    tokio::task::spawn_blocking(move || read(&db))
+      .await
+      .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  #[test]
+  fn test_readers_see_committed_writes_and_are_reused() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+
+    {
+      let first = db.reader().unwrap();
+      let second = db.reader().unwrap();
+      let count: i64 = first.query_row("SELECT COUNT(*) FROM local_events", [], |row| row.get(0)).unwrap();
+      assert_eq!(count, 0);
+      assert!(second.execute("DELETE FROM local_events", []).is_err());
+    }
+    assert_eq!(db.readers.idle.lock().unwrap().len(), 2);
+
+    db.set_setting("idle_threshold_seconds", "90").unwrap();
+    assert_eq!(db.get_setting("idle_threshold_seconds").unwrap().as_deref(), Some("90"));
+
+    // A reader stays usable while the write connection holds a transaction
+    let conn = db.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction().unwrap();
+    tx.execute("UPDATE local_settings SET value = '45' WHERE key = 'idle_threshold_seconds'", [])
+      .unwrap();
+    assert_eq!(db.get_setting("idle_threshold_seconds").unwrap().as_deref(), Some("90"));
+    tx.commit().unwrap();
+    drop(conn);
+    assert_eq!(db.get_setting("idle_threshold_seconds").unwrap().as_deref(), Some("45"));
+  }
+
+  #[tokio::test]
+  async fn test_read_runs_query_from_async_code() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+
+    let count = db.read(|db| db.get_event_count()).await.unwrap();
+    assert_eq!(count, 0);
+    assert!(db.read(|_| -> Result<()> { anyhow::bail!("failed") }).await.is_err());
+  }
+}
@@ -0,0 +1,293 @@
+//! Startup corruption check and salvage.
+//!
+//! The database is checked with `PRAGMA quick_check` when the app starts. If
+//! it can't be opened or the check fails, the damaged file (with its WAL) is
+//! moved aside as `<name>.corrupt-<unix time>`, a fresh database is created in
+//! its place and every row that can still be read is copied across, table by
+//! table, in the spirit of the sqlite3 `.recover` command. The app starts
+//! either way; what was salvaged is kept in sync_state for the UI. A database
+//! the key doesn't open is an error, not damage, and is left untouched.
+
+use super::write_buffer::StatusTable;
+use super::Database;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// sync_state key holding the last RecoveryReport as JSON
+const LAST_RECOVERY_KEY: &str = "last_recovery";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecoveryReport {
+  pub recovered_at: DateTime<Utc>,
+  /// Why the database was considered damaged
+  pub reason: String,
+  /// Where the damaged file was moved
+  pub quarantined_path: String,
+  /// Rows copied into the fresh database, per table
+  pub salvaged_rows: BTreeMap<String, i64>,
+  /// Tables that couldn't be read, or only partly
+  pub incomplete_tables: Vec<String>,
+}
+
+/// `path` with `suffix` appended to the file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+  let mut name = OsString::from(path.as_os_str());
+  name.push(suffix);
+  PathBuf::from(name)
+}
+
+/// Move the database at `db_path` and its WAL aside; returns the new path
+fn quarantine(db_path: &Path) -> Result<PathBuf> {
+  let target = with_suffix(db_path, &format!(".corrupt-{}", Utc::now().timestamp()));
+  std::fs::rename(db_path, &target)?;
+  for sidecar in ["-wal", "-shm"] {
+    let source = with_suffix(db_path, sidecar);
+    if source.exists() {
+      std::fs::rename(&source, with_suffix(&target, sidecar))?;
+    }
+  }
+  Ok(target)
+}
+
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>> {
+  let mut stmt = conn.prepare(&format!("PRAGMA table_info(\"{}\")", table))?;
+  let rows = stmt.query_map([], |row| row.get(1))?;
+  rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+}
+
+impl Database {
+  /// Open the app's database as `open_local` does, but check it first. A
+  /// damaged database is quarantined and salvaged into a fresh one rather
+  /// than failing startup.
+  pub fn open_or_recover(db_path: &Path, sync_key: &[u8; 32]) -> Result<Self> {
+    if !db_path.exists() {
+      return Self::open_local(db_path, sync_key);
+    }
+
+    // Every connection to the file is closed before it is moved
+    let reason = match Self::open_local(db_path, sync_key) {
+      Ok(db) => match db.quick_check() {
+        Ok(None) => return Ok(db),
+        Ok(Some(problem)) => problem,
+        Err(e) => e.to_string(),
+      },
+      // A database under another key isn't damaged; leave it where it is
+      #[cfg(feature = "sqlcipher")]
+      Err(e) if e.is::<super::cipher::WrongKeyError>() => return Err(e),
+      Err(e) => e.to_string(),
+    };
+    warn!("The local database is damaged ({}); moving it aside and salvaging what is readable", reason);
+
+    let quarantined = quarantine(db_path)?;
+    let db = Self::open_local(db_path, sync_key)?;
+    let (salvaged_rows, incomplete_tables) =
+      match db.open_companion(&quarantined, OpenFlags::SQLITE_OPEN_READ_WRITE) {
+        Ok(damaged) => db.salvage_from(&damaged)?,
+        Err(e) => {
+          warn!("Failed to open the damaged database: {}", e);
+          (BTreeMap::new(), vec!["*".to_string()])
+        }
+      };
+
+    let report = RecoveryReport {
+      recovered_at: Utc::now(),
+      reason,
+      quarantined_path: quarantined.to_string_lossy().into_owned(),
+      salvaged_rows,
+      incomplete_tables,
+    };
+    info!(
+      "Recovered the local database: {} rows salvaged, incomplete tables: {:?}",
+      report.salvaged_rows.values().sum::<i64>(),
+      report.incomplete_tables
+    );
+    db.update_sync_state(LAST_RECOVERY_KEY, &serde_json::to_string(&report)?)?;
+    db.flush_status_writes()?;
+    Ok(db)
+  }
+
+  /// First problem `PRAGMA quick_check` finds, or None for a healthy database
+  fn quick_check(&self) -> Result<Option<String>> {
+    let conn = self.conn.lock().unwrap();
+    let result: String = conn.query_row("PRAGMA quick_check(1)", [], |row| row.get(0))?;
+    Ok((result != "ok").then_some(result))
+  }
+
+  /// Copy every readable row of `damaged` into this (fresh) database. The
+  /// damaged file's rows replace seeded defaults; a table is copied up to the
+  /// first unreadable row. Returns rows copied per table and the tables that
+  /// couldn't be read in full.
+  fn salvage_from(&self, damaged: &Connection) -> Result<(BTreeMap<String, i64>, Vec<String>)> {
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+
+    // Events go first so their triggers rebuild rollups and the search index
+    let tables: Vec<String> = tx
+      .prepare(
+        r#"
+        SELECT name FROM sqlite_master
+        WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT LIKE 'events_fts%'
+        ORDER BY name != 'local_events', name
+        "#,
+      )?
+      .query_map([], |row| row.get(0))?
+      .collect::<Result<_, _>>()?;
+
+    let mut salvaged = BTreeMap::new();
+    let mut incomplete = Vec::new();
+    for table in tables {
+      let fresh_columns = table_columns(&tx, &table)?;
+      let columns: Vec<String> = match table_columns(damaged, &table) {
+        Ok(damaged_columns) => fresh_columns.into_iter().filter(|c| damaged_columns.contains(c)).collect(),
+        Err(_) => {
+          incomplete.push(table);
+          continue;
+        }
+      };
+      // Not in the damaged file's schema (an older version): nothing to copy
+      if columns.is_empty() {
+        continue;
+      }
+
+      let column_list = columns.iter().map(|c| format!("\"{}\"", c)).collect::<Vec<_>>().join(", ");
+      let Ok(mut select) = damaged.prepare(&format!("SELECT {} FROM \"{}\"", column_list, table)) else {
+        incomplete.push(table);
+        continue;
+      };
+      // Rollups rebuilt from the salvaged events stay unless the damaged file has them too
+      if table != "usage_rollups" {
+        tx.execute(&format!("DELETE FROM \"{}\"", table), [])?;
+      }
+      let placeholders = (1..=columns.len()).map(|i| format!("?{}", i)).collect::<Vec<_>>().join(", ");
+      let mut insert =
+        tx.prepare(&format!("INSERT OR REPLACE INTO \"{}\" ({}) VALUES ({})", table, column_list, placeholders))?;
+
+      let mut copied = 0;
+      let mut complete = true;
+      match select.query([]) {
+        Ok(mut rows) => loop {
+          let values = match rows.next() {
+            Ok(Some(row)) => (0..columns.len()).map(|i| row.get::<_, Value>(i)).collect::<Result<Vec<_>, _>>(),
+            Ok(None) => break,
+            Err(e) => Err(e),
+          };
+          match values {
+            Ok(values) => copied += insert.execute(rusqlite::params_from_iter(values)).unwrap_or(0) as i64,
+            Err(_) => {
+              complete = false;
+              break;
+            }
+          }
+        },
+        Err(_) => complete = false,
+      }
+
+      if !complete {
+        incomplete.push(table.clone());
+      }
+      salvaged.insert(table, copied);
+    }
+
+    tx.commit()?;
+    Ok((salvaged, incomplete))
+  }
+
+  /// Outcome of the last startup recovery, if the database was ever recovered
+  pub fn get_last_recovery(&self) -> Result<Option<RecoveryReport>> {
+    let value = match self.status_writes.get(StatusTable::SyncState, LAST_RECOVERY_KEY) {
+      Some(value) => Some(value),
+      None => {
+        let conn = self.reader()?;
+        conn
+          .query_row("SELECT value FROM sync_state WHERE key = ?1", [LAST_RECOVERY_KEY], |row| row.get(0))
+          .optional()?
+      }
+    };
+
+    Ok(value.and_then(|json: String| serde_json::from_str(&json).ok()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use crate::encryption::DEFAULT_SYNC_KEY;
+  use tempfile::TempDir;
+
+  fn window(title: &str) -> WindowInfo {
+    WindowInfo {
+      process_name: "code.exe".to_string(),
+      window_title: title.to_string(),
+      timestamp: Utc::now(),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn test_healthy_database_opens_untouched() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("local.db");
+    let id = {
+      let db = Database::open_or_recover(&path, DEFAULT_SYNC_KEY).unwrap();
+      db.store_event_sync(&window("kept")).unwrap()
+    };
+
+    let db = Database::open_or_recover(&path, DEFAULT_SYNC_KEY).unwrap();
+    assert!(db.get_event(&id).unwrap().is_some());
+    assert!(db.get_last_recovery().unwrap().is_none());
+    let quarantined = std::fs::read_dir(dir.path())
+      .unwrap()
+      .filter_map(|entry| entry.ok())
+      .filter(|entry| entry.file_name().to_string_lossy().contains(".corrupt-"))
+      .count();
+    assert_eq!(quarantined, 0);
+  }
+
+  #[test]
+  fn test_unreadable_file_is_quarantined_and_replaced() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("local.db");
+    std::fs::write(&path, vec![0xA5u8; 64 * 1024]).unwrap();
+
+    let db = Database::open_or_recover(&path, DEFAULT_SYNC_KEY).unwrap();
+    db.store_event_sync(&window("after recovery")).unwrap();
+
+    let report = db.get_last_recovery().unwrap().unwrap();
+    assert!(Path::new(&report.quarantined_path).exists());
+    assert_eq!(std::fs::read(&report.quarantined_path).unwrap(), vec![0xA5u8; 64 * 1024]);
+    assert_eq!(report.salvaged_rows.values().sum::<i64>(), 0);
+    assert!(!report.incomplete_tables.is_empty());
+  }
+
+  #[test]
+  fn test_salvage_copies_readable_rows() {
+    let dir = TempDir::new().unwrap();
+    let old_path = dir.path().join("old.db");
+    let kept = {
+      let old = Database::new(&old_path).unwrap();
+      old.delete_category_rule("steam").unwrap();
+      old.set_setting("idle_threshold_seconds", "120").unwrap();
+      old.store_event_sync(&window("salvaged")).unwrap()
+    };
+
+    let db = Database::new(&dir.path().join("local.db")).unwrap();
+    let damaged = Connection::open(&old_path).unwrap();
+    let (salvaged, incomplete) = db.salvage_from(&damaged).unwrap();
+
+    assert!(incomplete.is_empty());
+    assert_eq!(salvaged.get("local_events"), Some(&1));
+    assert!(db.get_event(&kept).unwrap().is_some());
+    assert_eq!(db.search_events("salvaged", None, None, 10).unwrap().len(), 1);
+    assert_eq!(db.get_setting("idle_threshold_seconds").unwrap().as_deref(), Some("120"));
+    // The damaged file's rules win over the fresh database's defaults
+    assert_eq!(db.get_category_rules().unwrap().categorize("steam.exe"), crate::database::UNCATEGORIZED);
+  }
+}
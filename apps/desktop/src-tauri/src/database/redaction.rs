@@ -0,0 +1,111 @@
+//! User-defined window title redaction rules (regex pattern -> replacement).
+//!
+//! Rules apply in creation order on top of the built-in title sanitizing;
+//! see `crate::collector::redaction` for how they are applied.
+
+use super::Database;
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedactionRule {
+  pub id: i64,
+  pub pattern: String,
+  /// May refer to capture groups as `$1` or `${name}`
+  pub replacement: String,
+  pub enabled: bool,
+}
+
+fn validate_pattern(pattern: &str) -> Result<()> {
+  if pattern.is_empty() {
+    bail!("Redaction pattern cannot be empty");
+  }
+  Regex::new(pattern).with_context(|| format!("Invalid redaction pattern '{}'", pattern))?;
+  Ok(())
+}
+
+impl Database {
+  /// All rules in the order they are applied
+  pub fn get_redaction_rules(&self) -> Result<Vec<RedactionRule>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached("SELECT id, pattern, replacement, enabled FROM redaction_rules ORDER BY id")?;
+    let rules = stmt.query_map([], |row| {
+      Ok(RedactionRule {
+        id: row.get(0)?,
+        pattern: row.get(1)?,
+        replacement: row.get(2)?,
+        enabled: row.get(3)?,
+      })
+    })?;
+    rules.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  /// Add an enabled rule; the pattern must be a valid regex
+  pub fn add_redaction_rule(&self, pattern: &str, replacement: &str) -> Result<RedactionRule> {
+    validate_pattern(pattern)?;
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      "INSERT INTO redaction_rules (pattern, replacement, enabled, created_at) VALUES (?1, ?2, 1, ?3)",
+      (pattern, replacement, Utc::now().timestamp_millis()),
+    )?;
+    Ok(RedactionRule {
+      id: conn.last_insert_rowid(),
+      pattern: pattern.to_string(),
+      replacement: replacement.to_string(),
+      enabled: true,
+    })
+  }
+
+  /// Returns false if there is no such rule
+  pub fn set_redaction_rule_enabled(&self, id: i64, enabled: bool) -> Result<bool> {
+    let conn = self.conn.lock().unwrap();
+    let updated = conn.execute("UPDATE redaction_rules SET enabled = ?2 WHERE id = ?1", (id, enabled))?;
+    Ok(updated > 0)
+  }
+
+  /// Returns false if there is no such rule
+  pub fn delete_redaction_rule(&self, id: i64) -> Result<bool> {
+    let conn = self.conn.lock().unwrap();
+    let deleted = conn.execute("DELETE FROM redaction_rules WHERE id = ?1", [id])?;
+    Ok(deleted > 0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  #[test]
+  fn test_add_toggle_delete() {
+    let (db, _temp) = create_test_db();
+    let tickets = db.add_redaction_rule(r"JIRA-\d+", "[ticket]").unwrap();
+    let emails = db.add_redaction_rule(r"[\w.]+@[\w.]+", "[email]").unwrap();
+
+    assert_eq!(db.get_redaction_rules().unwrap(), vec![tickets.clone(), emails.clone()]);
+
+    assert!(db.set_redaction_rule_enabled(tickets.id, false).unwrap());
+    assert!(!db.get_redaction_rules().unwrap()[0].enabled);
+
+    assert!(db.delete_redaction_rule(emails.id).unwrap());
+    assert!(!db.delete_redaction_rule(emails.id).unwrap());
+    assert!(!db.set_redaction_rule_enabled(emails.id, true).unwrap());
+    assert_eq!(db.get_redaction_rules().unwrap().len(), 1);
+  }
+
+  #[test]
+  fn test_invalid_pattern_rejected() {
+    let (db, _temp) = create_test_db();
+    assert!(db.add_redaction_rule("(unclosed", "x").is_err());
+    assert!(db.add_redaction_rule("", "x").is_err());
+    assert!(db.get_redaction_rules().unwrap().is_empty());
+  }
+}
@@ -0,0 +1,218 @@
+//! Events the user's other devices synced, pulled from the server.
+//!
+//! Sync uploads this device's events and pulls everyone else's (see
+//! `SyncClient::pull_events`), decrypted into `remote_events`. They are kept
+//! apart from `local_events` so nothing here is uploaded again, pruned by local
+//! retention or counted in this device's stats; the timeline merges both.
+
+use super::anonymize::Anonymizer;
+use super::{Database, EventFilter};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rusqlite::Row;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteEvent {
+  pub id: String,
+  /// The device that recorded it
+  pub device_id: String,
+  pub event_type: String,
+  pub timestamp: DateTime<Utc>,
+  pub duration: i32,
+  pub app_name: Option<String>,
+  /// The decrypted payload: window title or app name, whichever that device uploads
+  pub title: Option<String>,
+  pub category: Option<String>,
+  pub domain: Option<String>,
+  /// Recorded in a remote desktop / VM viewer
+  pub remote_session: bool,
+}
+
+/// One entry of the cross-device timeline
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TimelineEvent {
+  pub id: String,
+  /// None for events recorded on this device
+  pub device_id: Option<String>,
+  pub event_type: String,
+  pub timestamp: DateTime<Utc>,
+  pub duration: i32,
+  pub app_name: Option<String>,
+  pub title: Option<String>,
+  pub category: Option<String>,
+  pub domain: Option<String>,
+  pub remote_session: bool,
+  /// Seconds credited to the event once time several devices recorded at
+  /// once is shared out (see `reports::timeline`); its duration until then
+  pub attributed_seconds: i64,
+}
+
+impl From<RemoteEvent> for TimelineEvent {
+  fn from(event: RemoteEvent) -> Self {
+    Self {
+      id: event.id,
+      device_id: Some(event.device_id),
+      event_type: event.event_type,
+      timestamp: event.timestamp,
+      duration: event.duration,
+      app_name: event.app_name,
+      title: event.title,
+      category: event.category,
+      domain: event.domain,
+      remote_session: event.remote_session,
+      attributed_seconds: event.duration as i64,
+    }
+  }
+}
+
+fn map_remote_row(row: &Row<'_>) -> rusqlite::Result<RemoteEvent> {
+  Ok(RemoteEvent {
+    id: row.get(0)?,
+    device_id: row.get(1)?,
+    event_type: row.get(2)?,
+    timestamp: DateTime::from_timestamp_millis(row.get(3)?).unwrap_or_default(),
+    duration: row.get(4)?,
+    app_name: row.get(5)?,
+    title: row.get(6)?,
+    category: row.get(7)?,
+    domain: row.get(8)?,
+    remote_session: row.get(9)?,
+  })
+}
+
+impl Database {
+  /// Insert pulled events; an event pulled again (edited on its device)
+  /// replaces the earlier copy. Their names are hashed in anonymized storage
+  /// like this device's. Returns how many were stored.
+  pub fn store_remote_events(&self, events: &[RemoteEvent], received_at: DateTime<Utc>) -> Result<usize> {
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    let anonymizer = Anonymizer::load(&tx, self.display_key().as_deref(), self.hash_key().as_deref())?;
+    {
+      let mut stmt = tx.prepare_cached(
+        r#"
+        INSERT OR REPLACE INTO remote_events
+          (id, device_id, event_type, timestamp, duration, app_name, title, category, domain, remote_session, received_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+        "#,
+      )?;
+      for event in events {
+        let (app_name, title) = match &anonymizer {
+          Some(anonymizer) => (
+            anonymizer.title_token(&tx, event.app_name.as_deref())?,
+            anonymizer.title_token(&tx, event.title.as_deref())?,
+          ),
+          None => (event.app_name.clone(), event.title.clone()),
+        };
+        stmt.execute(rusqlite::params![
+          event.id,
+          event.device_id,
+          event.event_type,
+          event.timestamp.timestamp_millis(),
+          event.duration,
+          app_name,
+          title,
+          event.category,
+          event.domain,
+          event.remote_session,
+          received_at.timestamp_millis(),
+        ])?;
+      }
+    }
+    tx.commit()?;
+    Ok(events.len())
+  }
+
+  /// Pulled events starting in [start, end), oldest first
+  pub fn get_remote_events_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<RemoteEvent>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      r#"
+      SELECT id, device_id, event_type, timestamp, duration, app_name, title, category, domain, remote_session
+      FROM remote_events
+      WHERE timestamp >= ?1 AND timestamp < ?2
+      ORDER BY timestamp ASC
+      "#,
+    )?;
+    let rows = stmt.query_map((start.timestamp_millis(), end.timestamp_millis()), map_remote_row)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  /// This device's and every other device's events starting in [start, end), oldest first
+  pub fn get_timeline(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<TimelineEvent>> {
+    let mut timeline: Vec<TimelineEvent> = self
+      .get_events_between(start, end, &EventFilter::default())?
+      .into_iter()
+      .map(|event| TimelineEvent {
+        id: event.id,
+        device_id: None,
+        event_type: event.event_type,
+        timestamp: event.timestamp,
+        duration: event.duration,
+        app_name: Some(event.app_name),
+        title: event.window_title,
+        category: event.category,
+        domain: event.url_domain,
+        remote_session: event.remote_session,
+        attributed_seconds: event.duration as i64,
+      })
+      .collect();
+    timeline.extend(self.get_remote_events_between(start, end)?.into_iter().map(TimelineEvent::from));
+    timeline.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.id.cmp(&b.id)));
+    Ok(timeline)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use chrono::Duration;
+  use tempfile::NamedTempFile;
+
+  fn remote(id: &str, timestamp: DateTime<Utc>, title: &str) -> RemoteEvent {
+    RemoteEvent {
+      id: id.to_string(),
+      device_id: "phone".to_string(),
+      event_type: "app_usage".to_string(),
+      timestamp,
+      duration: 60,
+      app_name: Some("Maps".to_string()),
+      title: Some(title.to_string()),
+      category: Some("utility".to_string()),
+      domain: None,
+      remote_session: false,
+    }
+  }
+
+  #[test]
+  fn test_timeline_merges_local_and_remote_events() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    let now = Utc::now();
+    db.store_event_sync(&WindowInfo {
+      process_name: "code.exe".to_string(),
+      window_title: "main.rs".to_string(),
+      timestamp: now,
+      ..Default::default()
+    })
+    .unwrap();
+
+    let earlier = remote("a", now - Duration::minutes(5), "Directions");
+    db.store_remote_events(&[earlier.clone(), remote("b", now + Duration::minutes(5), "Old")], now).unwrap();
+    // Pulled again after an edit on the phone
+    db.store_remote_events(&[remote("b", now + Duration::minutes(5), "Search")], now).unwrap();
+
+    let remote_only = db.get_remote_events_between(now - Duration::hours(1), now + Duration::hours(1)).unwrap();
+    assert_eq!(remote_only.len(), 2);
+    assert_eq!(remote_only[0], earlier);
+
+    let timeline = db.get_timeline(now - Duration::hours(1), now + Duration::hours(1)).unwrap();
+    let entries: Vec<(Option<&str>, Option<&str>)> = timeline
+      .iter()
+      .map(|event| (event.device_id.as_deref(), event.title.as_deref()))
+      .collect();
+    assert_eq!(entries, vec![(Some("phone"), Some("Directions")), (None, Some("main.rs")), (Some("phone"), Some("Search"))]);
+  }
+}
@@ -0,0 +1,290 @@
+//! Primitives for pruning old events from the local store.
+//!
+//! Deletion is by explicit id so callers can archive exactly the rows they
+//! read before removing them (see `crate::archive`). The retention policy
+//! bounds how long raw events are kept; retired app usage survives as daily
+//! aggregates (see `downsample`), and freed pages are returned to the OS with
+//! incremental VACUUMs.
+
+use super::connection::{current_utc_offset_minutes, map_event_row, EVENT_COLUMNS};
+use super::downsample::{advance_downsampled_before, utc_day_start};
+use super::search::rebuild_search_index;
+use super::{Database, StoredEvent};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// `PRAGMA auto_vacuum` value for INCREMENTAL
+const INCREMENTAL_AUTO_VACUUM: i64 = 2;
+
+/// Days of raw events to keep; "0" or unset keeps them forever
+pub const RETENTION_DAYS_SETTING: &str = "retention_raw_days";
+/// What happens to events past retention ("delete" or "archive")
+pub const RETENTION_ACTION_SETTING: &str = "retention_action";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionAction {
+  /// Drop raw events; app usage is kept as daily aggregates
+  #[default]
+  Delete,
+  /// Write them to an encrypted archive first
+  Archive,
+}
+
+impl RetentionAction {
+  fn as_str(self) -> &'static str {
+    match self {
+      RetentionAction::Delete => "delete",
+      RetentionAction::Archive => "archive",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+  /// Raw events older than this many days are retired; None keeps them forever
+  pub raw_days: Option<i64>,
+  pub action: RetentionAction,
+}
+
+impl RetentionPolicy {
+  /// Retirement cutoff at `now`, aligned to a UTC day; None when disabled
+  pub fn cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    self.raw_days.map(|days| utc_day_start(now - Duration::days(days)))
+  }
+}
+
+impl Database {
+  pub fn get_retention_policy(&self) -> Result<RetentionPolicy> {
+    let raw_days = self
+      .get_setting(RETENTION_DAYS_SETTING)?
+      .and_then(|value| value.parse::<i64>().ok())
+      .filter(|days| *days > 0);
+    let action = match self.get_setting(RETENTION_ACTION_SETTING)?.as_deref() {
+      Some("archive") => RetentionAction::Archive,
+      _ => RetentionAction::Delete,
+    };
+    Ok(RetentionPolicy { raw_days, action })
+  }
+
+  pub fn set_retention_policy(&self, policy: &RetentionPolicy) -> Result<()> {
+    if policy.raw_days.is_some_and(|days| days < 1) {
+      bail!("Retention must be at least 1 day");
+    }
+    self.set_setting(RETENTION_DAYS_SETTING, &policy.raw_days.unwrap_or(0).to_string())?;
+    self.set_setting(RETENTION_ACTION_SETTING, policy.action.as_str())
+  }
+
+  /// Closed events that reached the server and started before `cutoff`, oldest first
+  pub fn get_synced_closed_events_before(&self, cutoff: DateTime<Utc>) -> Result<Vec<StoredEvent>> {
+    let conn = self.conn.lock().unwrap();
+    let mut stmt = conn.prepare_cached(&format!(
+      "SELECT {} FROM local_events WHERE timestamp < ?1 AND is_open = 0 AND synced = 1 ORDER BY timestamp ASC",
+      EVENT_COLUMNS
+    ))?;
+
+    let events = stmt.query_map([cutoff.timestamp_millis()], map_event_row)?;
+    events.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  /// Delete events past retention in one transaction, folding app usage into
+  /// the daily aggregates first; returns the number removed. `cutoff` becomes
+  /// the aggregated/raw boundary, so every id must be for an event before it.
+  pub(crate) fn retire_events_sync(&self, event_ids: &[String], cutoff: DateTime<Utc>) -> Result<usize> {
+    if event_ids.is_empty() {
+      return Ok(0);
+    }
+
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    let mut retired = 0;
+
+    {
+      let mut fold = tx.prepare_cached(
+        r#"
+        INSERT INTO daily_app_usage (day, app_name, duration_seconds, event_count)
+        SELECT date(timestamp / 1000 + COALESCE(utc_offset_minutes, ?2) * 60, 'unixepoch'), app_name, duration, 1
+        FROM local_events
+        WHERE id = ?1 AND event_type = 'app_usage'
+        ON CONFLICT(day, app_name) DO UPDATE SET
+          duration_seconds = duration_seconds + excluded.duration_seconds,
+          event_count = event_count + excluded.event_count
+        "#,
+      )?;
+      let mut delete = tx.prepare_cached("DELETE FROM local_events WHERE id = ?1")?;
+      let utc_offset = current_utc_offset_minutes();
+      for id in event_ids {
+        fold.execute((id, utc_offset))?;
+        retired += delete.execute([id])?;
+      }
+    }
+
+    advance_downsampled_before(&tx, utc_day_start(cutoff))?;
+    tx.commit()?;
+    Ok(retired)
+  }
+
+  /// Return up to `max_pages` free pages to the OS; returns the number freed.
+  /// Databases created before incremental auto-vacuum get one full VACUUM to
+  /// switch modes.
+  pub(crate) fn incremental_vacuum_sync(&self, max_pages: i64) -> Result<i64> {
+    let conn = self.conn.lock().unwrap();
+    let free_pages = |conn: &rusqlite::Connection| -> Result<i64> {
+      Ok(conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?)
+    };
+    let before = free_pages(&conn)?;
+
+    let auto_vacuum: i64 = conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))?;
+    if auto_vacuum != INCREMENTAL_AUTO_VACUUM {
+      conn.pragma_update(None, "auto_vacuum", "INCREMENTAL")?;
+      conn.execute_batch("VACUUM")?;
+      // A full VACUUM may renumber rowids, which the search index refers to
+      rebuild_search_index(&conn)?;
+    } else {
+      conn.execute_batch(&format!("PRAGMA incremental_vacuum({})", max_pages))?;
+    }
+
+    Ok(before - free_pages(&conn)?)
+  }
+
+  /// Closed events that started before `cutoff`, oldest first
+  pub fn get_closed_events_before(&self, cutoff: DateTime<Utc>) -> Result<Vec<StoredEvent>> {
+    let conn = self.conn.lock().unwrap();
+    let mut stmt = conn.prepare_cached(&format!(
+      "SELECT {} FROM local_events WHERE timestamp < ?1 AND is_open = 0 ORDER BY timestamp ASC",
+      EVENT_COLUMNS
+    ))?;
+
+    let events = stmt.query_map([cutoff.timestamp_millis()], map_event_row)?;
+    events.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  /// Delete events by id in one transaction; returns the number removed
+  pub(crate) fn delete_events_sync(&self, event_ids: &[String]) -> Result<usize> {
+    if event_ids.is_empty() {
+      return Ok(0);
+    }
+
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    let mut deleted = 0;
+
+    {
+      let mut stmt = tx.prepare_cached("DELETE FROM local_events WHERE id = ?1")?;
+      for id in event_ids {
+        deleted += stmt.execute([id])?;
+      }
+    }
+
+    tx.commit()?;
+    Ok(deleted)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::{NewEvent, StorageBackend};
+  use chrono::TimeZone;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn event_at(timestamp: DateTime<Utc>) -> NewEvent {
+    NewEvent {
+      event_type: "app_usage".to_string(),
+      timestamp,
+      duration: 60,
+      app_name: "code.exe".to_string(),
+      window_title: None,
+      url_domain: None,
+      remote_session: false,
+    }
+  }
+
+  #[test]
+  fn test_closed_events_before_cutoff() {
+    let (db, _temp) = create_test_db();
+    let old = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+    let recent = Utc.with_ymd_and_hms(2024, 6, 1, 9, 0, 0).unwrap();
+    db.insert_events(&[event_at(recent), event_at(old)]).unwrap();
+    db.open_system_event_sync("afk", old).unwrap();
+
+    let cutoff = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+    let events = db.get_closed_events_before(cutoff).unwrap();
+
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].timestamp, old);
+  }
+
+  #[test]
+  fn test_delete_events_by_id() {
+    let (db, _temp) = create_test_db();
+    let at = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+    let ids = db.insert_events(&[event_at(at), event_at(at), event_at(at)]).unwrap();
+
+    let deleted = db.delete_events_sync(&ids[..2]).unwrap();
+
+    assert_eq!(deleted, 2);
+    assert_eq!(db.get_event_count().unwrap(), 1);
+    assert!(db.get_event(&ids[2]).unwrap().is_some());
+    assert_eq!(db.delete_events_sync(&[]).unwrap(), 0);
+  }
+
+  #[test]
+  fn test_retention_policy_round_trip() {
+    let (db, _temp) = create_test_db();
+    assert_eq!(db.get_retention_policy().unwrap(), RetentionPolicy::default());
+
+    let policy = RetentionPolicy {
+      raw_days: Some(90),
+      action: RetentionAction::Archive,
+    };
+    db.set_retention_policy(&policy).unwrap();
+    assert_eq!(db.get_retention_policy().unwrap(), policy);
+
+    let now = Utc.with_ymd_and_hms(2024, 6, 15, 13, 30, 0).unwrap();
+    assert_eq!(policy.cutoff(now), Some(Utc.with_ymd_and_hms(2024, 3, 17, 0, 0, 0).unwrap()));
+
+    db.set_retention_policy(&RetentionPolicy::default()).unwrap();
+    assert_eq!(db.get_retention_policy().unwrap().cutoff(now), None);
+    assert!(db
+      .set_retention_policy(&RetentionPolicy {
+        raw_days: Some(0),
+        action: RetentionAction::Delete,
+      })
+      .is_err());
+  }
+
+  #[test]
+  fn test_synced_closed_events_before_cutoff() {
+    let (db, _temp) = create_test_db();
+    let old = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+    let ids = db.insert_events(&[event_at(old), event_at(old)]).unwrap();
+    db.mark_as_synced(&ids[..1]).unwrap();
+
+    let events = db.get_synced_closed_events_before(Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap()).unwrap();
+
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].id, ids[0]);
+  }
+
+  #[test]
+  fn test_incremental_vacuum_frees_pages() {
+    let (db, _temp) = create_test_db();
+    let at = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+    let events: Vec<NewEvent> = (0..2000).map(|_| event_at(at)).collect();
+    let ids = db.insert_events(&events).unwrap();
+    db.delete_events_sync(&ids).unwrap();
+
+    assert!(db.incremental_vacuum_sync(i64::MAX).unwrap() > 0);
+    let conn = db.conn.lock().unwrap();
+    let free_pages: i64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0)).unwrap();
+    assert_eq!(free_pages, 0);
+  }
+}
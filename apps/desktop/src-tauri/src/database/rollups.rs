@@ -0,0 +1,300 @@
+//! Hourly and daily app usage rollups.
+//!
+//! `usage_rollups` is maintained by triggers on local_events (see the v3
+//! migration): a closed app_usage event adds its duration to the hour and
+//! UTC day it started in, and re-closing or editing it moves that
+//! contribution. Events the user deletes are subtracted; retention and
+//! down-sampling leave the rollups alone, so they outlive the raw rows.
+//! Category rollups are derived from app rollups with the current rules at
+//! query time, so rule edits apply to past buckets immediately.
+
+use super::Database;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RollupGranularity {
+  Hour,
+  /// UTC day
+  Day,
+}
+
+impl RollupGranularity {
+  fn as_str(self) -> &'static str {
+    match self {
+      RollupGranularity::Hour => "hour",
+      RollupGranularity::Day => "day",
+    }
+  }
+
+  fn bucket_millis(self) -> i64 {
+    match self {
+      RollupGranularity::Hour => 60 * 60 * 1000,
+      RollupGranularity::Day => 24 * 60 * 60 * 1000,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AppRollup {
+  pub bucket_start: DateTime<Utc>,
+  pub app_name: String,
+  pub process_path: Option<String>,
+  pub duration_seconds: i64,
+  pub event_count: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategoryRollup {
+  pub bucket_start: DateTime<Utc>,
+  pub category: String,
+  pub duration_seconds: i64,
+  pub event_count: i64,
+}
+
+impl Database {
+  /// App rollups for buckets starting in [start, end), oldest first
+  pub fn get_app_rollups(
+    &self,
+    granularity: RollupGranularity,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+  ) -> Result<Vec<AppRollup>> {
+    if end <= start {
+      bail!("Rollup end must be after start");
+    }
+
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      r#"
+      SELECT bucket_start, app_name, process_path, duration_seconds, event_count
+      FROM usage_rollups
+      WHERE granularity = ?1 AND bucket_start >= ?2 AND bucket_start < ?3 AND event_count > 0
+      ORDER BY bucket_start ASC, duration_seconds DESC, app_name ASC
+      "#,
+    )?;
+
+    let rows = stmt.query_map(
+      (granularity.as_str(), start.timestamp_millis(), end.timestamp_millis()),
+      |row| {
+        let process_path: String = row.get(2)?;
+        Ok(AppRollup {
+          bucket_start: DateTime::from_timestamp_millis(row.get(0)?).unwrap_or_default(),
+          app_name: row.get(1)?,
+          process_path: (!process_path.is_empty()).then_some(process_path),
+          duration_seconds: row.get(3)?,
+          event_count: row.get(4)?,
+        })
+      },
+    )?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  /// Category rollups for buckets starting in [start, end), oldest first and
+  /// largest first within a bucket
+  pub fn get_category_rollups(
+    &self,
+    granularity: RollupGranularity,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+  ) -> Result<Vec<CategoryRollup>> {
+    let rules = self.get_category_rules()?;
+    let mut totals: BTreeMap<(DateTime<Utc>, String), (i64, i64)> = BTreeMap::new();
+    for rollup in self.get_app_rollups(granularity, start, end)? {
+      let category = rules.categorize_with_path(&rollup.app_name, rollup.process_path.as_deref());
+      let entry = totals.entry((rollup.bucket_start, category)).or_default();
+      entry.0 += rollup.duration_seconds;
+      entry.1 += rollup.event_count;
+    }
+
+    let mut rollups: Vec<CategoryRollup> = totals
+      .into_iter()
+      .map(|((bucket_start, category), (duration_seconds, event_count))| CategoryRollup {
+        bucket_start,
+        category,
+        duration_seconds,
+        event_count,
+      })
+      .collect();
+    rollups.sort_by(|a, b| {
+      a.bucket_start
+        .cmp(&b.bucket_start)
+        .then(b.duration_seconds.cmp(&a.duration_seconds))
+        .then(a.category.cmp(&b.category))
+    });
+    Ok(rollups)
+  }
+}
+
+/// Take a closed app_usage event out of the rollups before it is deleted
+pub(super) fn subtract_from_rollups(conn: &Connection, event_id: &str) -> Result<()> {
+  let event: Option<(i64, i64, String, String)> = conn
+    .query_row(
+      r#"
+      SELECT timestamp, duration, app_name, COALESCE(process_path, '')
+      FROM local_events
+      WHERE id = ?1 AND event_type = 'app_usage' AND is_open = 0
+      "#,
+      [event_id],
+      |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )
+    .optional()?;
+  let Some((timestamp, duration, app_name, process_path)) = event else {
+    return Ok(());
+  };
+
+  let mut stmt = conn.prepare_cached(
+    r#"
+    UPDATE usage_rollups
+    SET duration_seconds = duration_seconds - ?5, event_count = event_count - 1
+    WHERE granularity = ?1 AND bucket_start = ?2 AND app_name = ?3 AND process_path = ?4
+    "#,
+  )?;
+  for granularity in [RollupGranularity::Hour, RollupGranularity::Day] {
+    let bucket_start = timestamp / granularity.bucket_millis() * granularity.bucket_millis();
+    stmt.execute((granularity.as_str(), bucket_start, &app_name, &process_path, duration))?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::event_queue::QueuedEvent;
+  use crate::collector::window_tracker::WindowInfo;
+  use crate::database::{DeletionReason, NewEvent, StorageBackend};
+  use chrono::{Duration, TimeZone};
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn app_event(app: &str, timestamp: DateTime<Utc>, duration: i32) -> NewEvent {
+    NewEvent {
+      event_type: "app_usage".to_string(),
+      timestamp,
+      duration,
+      app_name: app.to_string(),
+      window_title: None,
+      url_domain: None,
+      remote_session: false,
+    }
+  }
+
+  fn window(app: &str, timestamp: DateTime<Utc>) -> WindowInfo {
+    WindowInfo {
+      process_name: app.to_string(),
+      window_title: "Window".to_string(),
+      timestamp,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn test_inserted_events_roll_up_by_hour_and_day() {
+    let (db, _temp) = create_test_db();
+    let day = Utc.with_ymd_and_hms(2024, 5, 6, 0, 0, 0).unwrap();
+    db.insert_events(&[
+      app_event("code.exe", day + Duration::minutes(10), 600),
+      app_event("code.exe", day + Duration::minutes(40), 300),
+      app_event("slack.exe", day + Duration::hours(3), 120),
+      app_event("afk", day + Duration::hours(3), 999),
+    ])
+    .unwrap();
+    db.store_marker_event_sync("system_suspend", "", day + Duration::hours(4)).unwrap();
+
+    let hourly = db.get_app_rollups(RollupGranularity::Hour, day, day + Duration::days(1)).unwrap();
+    assert_eq!(hourly.len(), 3);
+    assert_eq!(hourly[0].bucket_start, day);
+    assert_eq!(hourly[0].app_name, "code.exe");
+    assert_eq!(hourly[0].duration_seconds, 900);
+    assert_eq!(hourly[0].event_count, 2);
+    assert_eq!(hourly[1].bucket_start, day + Duration::hours(3));
+
+    let daily = db.get_category_rollups(RollupGranularity::Day, day, day + Duration::days(1)).unwrap();
+    assert_eq!(daily.len(), 3);
+    assert_eq!(daily[0].category, "other");
+    assert_eq!(daily[0].duration_seconds, 999);
+    assert_eq!(daily[1].category, "development");
+    assert_eq!(daily[1].duration_seconds, 900);
+    assert_eq!(daily[2].category, "communication");
+  }
+
+  #[test]
+  fn test_open_events_roll_up_when_closed() {
+    let (db, _temp) = create_test_db();
+    let start = Utc::now() - Duration::minutes(5);
+    let day = start.date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+    let range = (day - Duration::days(1), day + Duration::days(2));
+
+    let id = db.store_event_sync(&window("code.exe", start)).unwrap();
+    assert!(db.get_app_rollups(RollupGranularity::Day, range.0, range.1).unwrap().is_empty());
+
+    let started = db.get_event(&id).unwrap().unwrap().timestamp;
+    db.close_event_sync(&id, started + Duration::seconds(90)).unwrap();
+    // Closing again replaces the contribution rather than adding to it
+    db.close_event_sync(&id, started + Duration::seconds(120)).unwrap();
+
+    let daily = db.get_app_rollups(RollupGranularity::Day, range.0, range.1).unwrap();
+    assert_eq!(daily.len(), 1);
+    assert_eq!(daily[0].duration_seconds, 120);
+    assert_eq!(daily[0].event_count, 1);
+  }
+
+  #[test]
+  fn test_queued_events_keep_process_path() {
+    let (db, _temp) = create_test_db();
+    let at = Utc.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+    let mut info = window("python.exe", at);
+    info.process_path = Some("/opt/tools/bin/python".to_string());
+    db.store_queued_events_sync(&[QueuedEvent {
+      id: "queued".to_string(),
+      window_info: info,
+      queued_at: at,
+      retry_count: 0,
+      ended_at: Some(at + Duration::seconds(30)),
+    }])
+    .unwrap();
+
+    let hourly = db.get_app_rollups(RollupGranularity::Hour, at, at + Duration::hours(1)).unwrap();
+    assert_eq!(hourly[0].process_path.as_deref(), Some("/opt/tools/bin/python"));
+    assert_eq!(hourly[0].duration_seconds, 30);
+  }
+
+  #[test]
+  fn test_user_deletions_are_subtracted_but_retention_is_not() {
+    let (db, _temp) = create_test_db();
+    let day = Utc.with_ymd_and_hms(2024, 5, 6, 0, 0, 0).unwrap();
+    let ids = db
+      .insert_events(&[
+        app_event("code.exe", day + Duration::hours(1), 600),
+        app_event("code.exe", day + Duration::hours(2), 300),
+      ])
+      .unwrap();
+
+    db.delete_events_propagated(&ids[..1], DeletionReason::Manual, Utc::now()).unwrap();
+    let daily = db.get_app_rollups(RollupGranularity::Day, day, day + Duration::days(1)).unwrap();
+    assert_eq!(daily[0].duration_seconds, 300);
+    assert_eq!(daily[0].event_count, 1);
+    let hourly = db.get_app_rollups(RollupGranularity::Hour, day, day + Duration::days(1)).unwrap();
+    assert_eq!(hourly.len(), 1);
+
+    db.retire_events_sync(&ids[1..], day + Duration::days(1)).unwrap();
+    let daily = db.get_app_rollups(RollupGranularity::Day, day, day + Duration::days(1)).unwrap();
+    assert_eq!(daily[0].duration_seconds, 300);
+  }
+
+  #[test]
+  fn test_invalid_range_rejected() {
+    let (db, _temp) = create_test_db();
+    let now = Utc::now();
+    assert!(db.get_app_rollups(RollupGranularity::Hour, now, now).is_err());
+  }
+}
@@ -0,0 +1,412 @@
+//! App categorization rules with versioned history.
+//!
+//! A rule maps a case-insensitive substring of the app name to a category.
+//! Patterns containing a '/' match the executable path instead (backslashes
+//! are normalized), so rules can target an install location.
+//! Changes are recorded in `config_history` under the "rule" scope (key =
+//! pattern, value = category), so the rule set in effect at any past moment
+//! can be reconstructed for as-of reports.
+//!
+//! An app can also be re-categorized outright. That stores an override for
+//! the exact app name, which beats every rule, and rewrites the category
+//! already stored on its events.
+
+use super::history::record_config_change;
+use super::Database;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+pub(crate) const RULE_SCOPE: &str = "rule";
+
+/// Built-in rules seeded into a fresh database
+pub(crate) const DEFAULT_CATEGORY_RULES: &[(&str, &str)] = &[
+  ("chrome", "work"),
+  ("firefox", "work"),
+  ("edge", "work"),
+  ("code", "development"),
+  ("idea", "development"),
+  ("visual", "development"),
+  ("slack", "communication"),
+  ("teams", "communication"),
+  ("zoom", "communication"),
+  ("spotify", "entertainment"),
+  ("netflix", "entertainment"),
+  ("vlc", "entertainment"),
+  ("word", "productivity"),
+  ("excel", "productivity"),
+  ("powerpoint", "productivity"),
+  ("steam", "gaming"),
+  ("game", "gaming"),
+];
+
+/// Category for apps no rule matches
+pub const UNCATEGORIZED: &str = "other";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CategoryRule {
+  pub pattern: String,
+  pub category: String,
+}
+
+/// A complete rule set, keyed by lowercase pattern, plus per-app overrides
+/// keyed by lowercase app name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CategoryRules {
+  rules: BTreeMap<String, String>,
+  overrides: BTreeMap<String, String>,
+}
+
+impl CategoryRules {
+  /// Category of `app_name`; an override wins, then the longest matching pattern
+  pub fn categorize(&self, app_name: &str) -> String {
+    self.categorize_with_path(app_name, None)
+  }
+
+  /// Category of an app whose executable path may be known; path patterns
+  /// only match when it is
+  pub fn categorize_with_path(&self, app_name: &str, process_path: Option<&str>) -> String {
+    let app_lower = app_name.to_lowercase();
+    if let Some(category) = self.overrides.get(&app_lower) {
+      return category.clone();
+    }
+    let path_lower = process_path.map(|path| path.to_lowercase().replace('\\', "/"));
+    self
+      .rules
+      .iter()
+      .filter(|(pattern, _)| match is_path_pattern(pattern) {
+        true => path_lower.as_deref().is_some_and(|path| path.contains(pattern.as_str())),
+        false => app_lower.contains(pattern.as_str()),
+      })
+      .max_by_key(|(pattern, _)| pattern.len())
+      .map(|(_, category)| category.clone())
+      .unwrap_or_else(|| UNCATEGORIZED.to_string())
+  }
+
+  pub fn to_vec(&self) -> Vec<CategoryRule> {
+    self
+      .rules
+      .iter()
+      .map(|(pattern, category)| CategoryRule {
+        pattern: pattern.clone(),
+        category: category.clone(),
+      })
+      .collect()
+  }
+
+  /// Set (Some) or remove (None) the rule for `pattern`
+  pub(crate) fn apply(&mut self, pattern: String, category: Option<String>) {
+    match category {
+      Some(category) => {
+        self.rules.insert(pattern, category);
+      }
+      None => {
+        self.rules.remove(&pattern);
+      }
+    }
+  }
+}
+
+/// A recorded rule change, used to replay the rule set forward through a period
+#[derive(Debug, Clone)]
+pub(crate) struct RuleChange {
+  pub pattern: String,
+  pub category: Option<String>,
+  pub changed_at: DateTime<Utc>,
+}
+
+/// Rules and overrides currently stored, read on `conn` so a writer can
+/// categorize inside its own transaction
+pub(crate) fn load_category_rules(conn: &Connection) -> Result<CategoryRules> {
+  let mut rules = CategoryRules::default();
+
+  let mut stmt = conn.prepare_cached("SELECT pattern, category FROM category_rules")?;
+  let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+  for row in rows {
+    let (pattern, category) = row?;
+    rules.apply(pattern, Some(category));
+  }
+
+  let mut stmt = conn.prepare_cached("SELECT app_name, category FROM category_overrides")?;
+  let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+  for row in rows {
+    let (app_name, category) = row?;
+    rules.overrides.insert(app_name.to_lowercase(), category);
+  }
+
+  Ok(rules)
+}
+
+fn is_path_pattern(pattern: &str) -> bool {
+  pattern.contains('/')
+}
+
+fn normalize_pattern(pattern: &str) -> Result<String> {
+  let pattern = pattern.trim().to_lowercase().replace('\\', "/");
+  if pattern.is_empty() {
+    bail!("Rule pattern cannot be empty");
+  }
+  Ok(pattern)
+}
+
+impl Database {
+  /// Rules and overrides currently in effect
+  pub fn get_category_rules(&self) -> Result<CategoryRules> {
+    load_category_rules(&self.reader()?)
+  }
+
+  /// Put every event of `app_name` (any case) in `category`, including the
+  /// ones already recorded, and keep new ones there regardless of the rules.
+  /// Returns the number of events changed.
+  pub fn recategorize_app(&self, app_name: &str, category: &str) -> Result<usize> {
+    let (app_name, category) = (app_name.trim(), category.trim());
+    if app_name.is_empty() {
+      bail!("App name cannot be empty");
+    }
+    if category.is_empty() {
+      bail!("Category cannot be empty");
+    }
+
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+      r#"
+      INSERT INTO category_overrides (app_name, category, updated_at)
+      VALUES (?1, ?2, ?3)
+      ON CONFLICT(app_name) DO UPDATE SET
+        category = excluded.category,
+        updated_at = excluded.updated_at
+      "#,
+      (app_name, category, Utc::now().timestamp_millis()),
+    )?;
+    let changed = tx.execute(
+      "UPDATE local_events SET category = ?1 WHERE app_name = ?2 COLLATE NOCASE",
+      (category, app_name),
+    )?;
+    tx.commit()?;
+    Ok(changed)
+  }
+
+  /// Add or change a rule; the change is recorded in the history
+  pub fn set_category_rule(&self, pattern: &str, category: &str) -> Result<()> {
+    let category = category.trim();
+    if category.is_empty() {
+      bail!("Rule category cannot be empty");
+    }
+    self.write_category_rule(&normalize_pattern(pattern)?, Some(category))
+  }
+
+  /// Remove a rule; the removal is recorded in the history
+  pub fn delete_category_rule(&self, pattern: &str) -> Result<()> {
+    self.write_category_rule(&normalize_pattern(pattern)?, None)
+  }
+
+  fn write_category_rule(&self, pattern: &str, category: Option<&str>) -> Result<()> {
+    let conn = self.conn.lock().unwrap();
+    let now = Utc::now().timestamp_millis();
+    let tx = conn.unchecked_transaction()?;
+
+    let old_category: Option<String> = tx
+      .query_row("SELECT category FROM category_rules WHERE pattern = ?", [pattern], |row| row.get(0))
+      .optional()?;
+
+    match category {
+      Some(category) => {
+        tx.execute(
+          r#"
+          INSERT INTO category_rules (pattern, category, updated_at)
+          VALUES (?1, ?2, ?3)
+          ON CONFLICT(pattern) DO UPDATE SET
+            category = excluded.category,
+            updated_at = excluded.updated_at
+          "#,
+          (pattern, category, now),
+        )?;
+      }
+      None => {
+        tx.execute("DELETE FROM category_rules WHERE pattern = ?", [pattern])?;
+      }
+    }
+
+    record_config_change(&tx, RULE_SCOPE, pattern, old_category.as_deref(), category, now)?;
+
+    tx.commit()?;
+    Ok(())
+  }
+
+  /// Rule set that was in effect at `at`, reconstructed by undoing later changes
+  pub fn get_category_rules_as_of(&self, at: DateTime<Utc>) -> Result<CategoryRules> {
+    let mut rules = self.get_category_rules()?;
+
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      r#"
+      SELECT key, old_value
+      FROM config_history
+      WHERE scope = ?1 AND changed_at > ?2
+      ORDER BY version DESC
+      "#,
+    )?;
+    let rows = stmt.query_map((RULE_SCOPE, at.timestamp_millis()), |row| {
+      Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+    })?;
+
+    for row in rows {
+      let (pattern, old_category) = row?;
+      rules.apply(pattern, old_category);
+    }
+    Ok(rules)
+  }
+
+  /// Rule changes in (start, end], oldest first
+  pub(crate) fn get_rule_changes_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<RuleChange>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      r#"
+      SELECT key, new_value, changed_at
+      FROM config_history
+      WHERE scope = ?1 AND changed_at > ?2 AND changed_at <= ?3
+      ORDER BY version ASC
+      "#,
+    )?;
+    let rows = stmt.query_map((RULE_SCOPE, start.timestamp_millis(), end.timestamp_millis()), |row| {
+      Ok(RuleChange {
+        pattern: row.get(0)?,
+        category: row.get(1)?,
+        changed_at: DateTime::from_timestamp_millis(row.get::<_, i64>(2)?).unwrap_or_default(),
+      })
+    })?;
+
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  #[test]
+  fn test_default_rules_are_seeded() {
+    let (db, _temp) = create_test_db();
+    let rules = db.get_category_rules().unwrap();
+
+    assert_eq!(rules.to_vec().len(), DEFAULT_CATEGORY_RULES.len());
+    assert_eq!(rules.categorize("Chrome.exe"), "work");
+    assert_eq!(rules.categorize("code.exe"), "development");
+    assert_eq!(rules.categorize("unknown.exe"), UNCATEGORIZED);
+  }
+
+  #[test]
+  fn test_longest_pattern_wins() {
+    let mut rules = CategoryRules::default();
+    rules.apply("code".to_string(), Some("development".to_string()));
+    rules.apply("vscode-notes".to_string(), Some("learning".to_string()));
+
+    assert_eq!(rules.categorize("vscode-notes.exe"), "learning");
+    assert_eq!(rules.categorize("code.exe"), "development");
+  }
+
+  #[test]
+  fn test_path_patterns_match_install_location() {
+    let (db, _temp) = create_test_db();
+    db.set_category_rule(r"C:\Program Files\JetBrains\", "development").unwrap();
+    let rules = db.get_category_rules().unwrap();
+
+    let path = r"C:\Program Files\JetBrains\Rider\bin\rider64.exe";
+    assert_eq!(rules.categorize_with_path("rider64.exe", Some(path)), "development");
+    assert_eq!(rules.categorize("rider64.exe"), UNCATEGORIZED);
+    // App-name patterns still apply, and never match against the path
+    assert_eq!(rules.categorize_with_path("chrome.exe", Some(r"D:\Portable\chrome.exe")), "work");
+    assert_eq!(rules.categorize_with_path("tool.exe", Some(r"C:\steam\tool.exe")), UNCATEGORIZED);
+  }
+
+  #[test]
+  fn test_deleted_defaults_stay_deleted() {
+    let temp_file = NamedTempFile::new().unwrap();
+    {
+      let db = Database::new(temp_file.path()).unwrap();
+      db.delete_category_rule("steam").unwrap();
+    }
+
+    let db = Database::new(temp_file.path()).unwrap();
+    assert_eq!(db.get_category_rules().unwrap().categorize("steam.exe"), UNCATEGORIZED);
+  }
+
+  #[test]
+  fn test_rules_as_of_undo_later_changes() {
+    let (db, _temp) = create_test_db();
+    let before = Utc::now() - chrono::Duration::seconds(1);
+
+    db.set_category_rule("Slack", "work").unwrap();
+    db.set_category_rule("figma", "design").unwrap();
+    db.delete_category_rule("steam").unwrap();
+
+    let current = db.get_category_rules().unwrap();
+    assert_eq!(current.categorize("slack.exe"), "work");
+    assert_eq!(current.categorize("figma.exe"), "design");
+    assert_eq!(current.categorize("steam.exe"), UNCATEGORIZED);
+
+    let past = db.get_category_rules_as_of(before).unwrap();
+    assert_eq!(past.categorize("slack.exe"), "communication");
+    assert_eq!(past.categorize("figma.exe"), UNCATEGORIZED);
+    assert_eq!(past.categorize("steam.exe"), "gaming");
+
+    let changes = db.get_rule_changes_between(before, Utc::now()).unwrap();
+    assert_eq!(changes.len(), 3);
+    assert_eq!(changes[0].pattern, "slack");
+    assert_eq!(changes[2].category, None);
+  }
+
+  #[test]
+  fn test_rule_changes_stay_out_of_settings_restore() {
+    let (db, _temp) = create_test_db();
+    db.set_category_rule("figma", "design").unwrap();
+
+    let diffs = db.restore_settings_version(0).unwrap();
+    assert!(diffs.is_empty());
+    assert_eq!(db.get_category_rules().unwrap().categorize("figma"), "design");
+  }
+
+  #[test]
+  fn test_recategorize_app_overrides_rules_and_history() {
+    let (db, _temp) = create_test_db();
+    let window = |app: &str| crate::collector::window_tracker::WindowInfo {
+      process_name: app.to_string(),
+      window_title: String::new(),
+      timestamp: Utc::now(),
+      ..Default::default()
+    };
+    let before = db.store_event_sync(&window("Code.exe")).unwrap();
+    let other = db.store_event_sync(&window("vscode.exe")).unwrap();
+    assert_eq!(db.get_event(&before).unwrap().unwrap().category.as_deref(), Some("development"));
+
+    assert_eq!(db.recategorize_app("code.exe", "writing").unwrap(), 1);
+    let after = db.store_event_sync(&window("code.exe")).unwrap();
+
+    assert_eq!(db.get_event(&before).unwrap().unwrap().category.as_deref(), Some("writing"));
+    assert_eq!(db.get_event(&after).unwrap().unwrap().category.as_deref(), Some("writing"));
+    assert_eq!(db.get_event(&other).unwrap().unwrap().category.as_deref(), Some("development"));
+    let rules = db.get_category_rules().unwrap();
+    assert_eq!(rules.categorize("CODE.EXE"), "writing");
+    assert_eq!(rules.categorize("vscode.exe"), "development");
+
+    assert!(db.recategorize_app(" ", "writing").is_err());
+    assert!(db.recategorize_app("code.exe", "").is_err());
+  }
+
+  #[test]
+  fn test_empty_pattern_rejected() {
+    let (db, _temp) = create_test_db();
+    assert!(db.set_category_rule("  ", "work").is_err());
+    assert!(db.set_category_rule("figma", "").is_err());
+  }
+}
@@ -0,0 +1,195 @@
+//! Full-text search over stored events.
+//!
+//! `events_fts` is an external-content FTS5 index over app names and window
+//! titles, kept in step with local_events by triggers (see the v4 migration).
+//! It refers to events by rowid, so it is rebuilt after anything that can
+//! renumber rows, such as a full VACUUM.
+
+use super::connection::{map_event_row, EVENT_COLUMNS};
+use super::{Database, StoredEvent};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+
+/// Results returned when the caller doesn't ask for a number
+pub const DEFAULT_SEARCH_LIMIT: u32 = 100;
+
+/// FTS5 query matching every word of `query`, the last one as a prefix.
+/// Words are quoted so user input is never parsed as FTS5 syntax; words
+/// without letters or digits can't match anything and are dropped.
+fn match_expression(query: &str) -> Option<String> {
+  let mut words: Vec<String> = query
+    .split_whitespace()
+    .filter(|word| word.chars().any(char::is_alphanumeric))
+    .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+    .collect();
+  words.last_mut()?.push('*');
+  Some(words.join(" "))
+}
+
+/// Reindex every event from local_events
+pub(super) fn rebuild_search_index(conn: &Connection) -> Result<()> {
+  conn.execute_batch("INSERT INTO events_fts (events_fts) VALUES ('rebuild')")?;
+  Ok(())
+}
+
+impl Database {
+  /// Events whose app name or window title contains every word of `query`,
+  /// starting in [start, end) when given; most recent first
+  pub fn search_events(
+    &self,
+    query: &str,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    limit: u32,
+  ) -> Result<Vec<StoredEvent>> {
+    let Some(expression) = match_expression(query) else {
+      bail!("Search query is empty");
+    };
+
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(&format!(
+      r#"
+      SELECT {}
+      FROM local_events
+      WHERE rowid IN (SELECT rowid FROM events_fts WHERE events_fts MATCH ?1)
+        AND timestamp >= ?2 AND timestamp < ?3
+      ORDER BY timestamp DESC
+      LIMIT ?4
+      "#,
+      EVENT_COLUMNS
+    ))?;
+
+    let events = stmt.query_map(
+      (
+        expression,
+        start.map_or(i64::MIN, |start| start.timestamp_millis()),
+        end.map_or(i64::MAX, |end| end.timestamp_millis()),
+        limit,
+      ),
+      map_event_row,
+    )?;
+    events.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::{DeletionReason, NewEvent, StorageBackend};
+  use chrono::{Duration, TimeZone};
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn titled_event(app: &str, title: &str, timestamp: DateTime<Utc>) -> NewEvent {
+    NewEvent {
+      event_type: "app_usage".to_string(),
+      timestamp,
+      duration: 60,
+      app_name: app.to_string(),
+      window_title: Some(title.to_string()),
+      url_domain: None,
+      remote_session: false,
+    }
+  }
+
+  fn search(db: &Database, query: &str) -> Vec<String> {
+    db.search_events(query, None, None, DEFAULT_SEARCH_LIMIT)
+      .unwrap()
+      .into_iter()
+      .map(|event| event.window_title.unwrap_or_default())
+      .collect()
+  }
+
+  #[test]
+  fn test_match_expression_quotes_words() {
+    assert_eq!(match_expression("quarterly report").as_deref(), Some("\"quarterly\" \"report\"*"));
+    assert_eq!(match_expression("say \"hi\"").as_deref(), Some("\"say\" \"\"\"hi\"\"\"*"));
+    assert_eq!(match_expression("  ( "), None);
+  }
+
+  #[test]
+  fn test_search_by_title_and_app_most_recent_first() {
+    let (db, _temp) = create_test_db();
+    let at = Utc.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+    db.insert_events(&[
+      titled_event("code.exe", "lifespan - main.rs", at),
+      titled_event("chrome.exe", "Quarterly Report - Docs", at + Duration::hours(1)),
+      titled_event("code.exe", "lifespan - sync.rs", at + Duration::hours(2)),
+    ])
+    .unwrap();
+
+    assert_eq!(search(&db, "lifespan"), vec!["lifespan - sync.rs", "lifespan - main.rs"]);
+    // Case-insensitive, prefix match on the last word
+    assert_eq!(search(&db, "quarterly rep"), vec!["Quarterly Report - Docs"]);
+    assert_eq!(search(&db, "chrome").len(), 1);
+    assert!(search(&db, "lifespan docs").is_empty());
+    // FTS5 operators in user input are plain words
+    assert!(search(&db, "NOT OR (").is_empty());
+    assert!(db.search_events(" ", None, None, DEFAULT_SEARCH_LIMIT).is_err());
+  }
+
+  #[test]
+  fn test_search_range_and_limit() {
+    let (db, _temp) = create_test_db();
+    let at = Utc.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+    db.insert_events(&[
+      titled_event("code.exe", "budget v1", at),
+      titled_event("code.exe", "budget v2", at + Duration::days(1)),
+      titled_event("code.exe", "budget v3", at + Duration::days(2)),
+    ])
+    .unwrap();
+
+    let in_range = db
+      .search_events("budget", Some(at + Duration::days(1)), Some(at + Duration::days(2)), 10)
+      .unwrap();
+    assert_eq!(in_range.len(), 1);
+    assert_eq!(in_range[0].window_title.as_deref(), Some("budget v2"));
+    assert_eq!(db.search_events("budget", None, None, 2).unwrap().len(), 2);
+  }
+
+  #[test]
+  fn test_index_follows_title_updates_and_deletes() {
+    let (db, _temp) = create_test_db();
+    let at = Utc.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+    let ids = db
+      .insert_events(&[titled_event("code.exe", "draft", at), titled_event("word.exe", "Café menu", at)])
+      .unwrap();
+
+    {
+      let conn = db.conn.lock().unwrap();
+      conn.execute("UPDATE local_events SET window_title = 'final' WHERE id = ?1", [&ids[0]]).unwrap();
+    }
+    assert!(search(&db, "draft").is_empty());
+    assert_eq!(search(&db, "final"), vec!["final"]);
+    // Diacritics are folded
+    assert_eq!(search(&db, "cafe"), vec!["Café menu"]);
+
+    db.delete_events_propagated(&ids[1..], DeletionReason::Manual, Utc::now()).unwrap();
+    assert!(search(&db, "cafe").is_empty());
+  }
+
+  #[test]
+  fn test_rebuild_after_full_vacuum() {
+    let (db, _temp) = create_test_db();
+    let at = Utc.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+    let ids = db
+      .insert_events(&[titled_event("code.exe", "old", at), titled_event("code.exe", "kept", at)])
+      .unwrap();
+    db.delete_event_sync(&ids[0]).unwrap();
+    {
+      let conn = db.conn.lock().unwrap();
+      conn.pragma_update(None, "auto_vacuum", "NONE").unwrap();
+      conn.execute_batch("VACUUM").unwrap();
+    }
+
+    db.incremental_vacuum_sync(0).unwrap();
+    assert_eq!(search(&db, "kept"), vec!["kept"]);
+    assert!(search(&db, "old").is_empty());
+  }
+}
@@ -0,0 +1,114 @@
+//! Storage for frozen monthly statements; see `crate::statements`.
+//!
+//! Rows are insert-only: triggers reject updates and deletes, so a statement
+//! can only change by editing the file outside the app, which breaks its hash.
+
+use super::Database;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rusqlite::OptionalExtension;
+
+/// A statement as stored: its JSON body and the hash over it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredStatement {
+  /// "YYYY-MM"
+  pub month: String,
+  pub body: String,
+  pub hash: String,
+}
+
+impl Database {
+  pub fn insert_monthly_statement(&self, statement: &StoredStatement, created_at: DateTime<Utc>) -> Result<()> {
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      "INSERT INTO monthly_statements (month, body, hash, created_at) VALUES (?1, ?2, ?3, ?4)",
+      (&statement.month, &statement.body, &statement.hash, created_at.timestamp_millis()),
+    )?;
+    Ok(())
+  }
+
+  pub fn get_monthly_statement_row(&self, month: &str) -> Result<Option<StoredStatement>> {
+    let conn = self.reader()?;
+    conn
+      .query_row(
+        "SELECT month, body, hash FROM monthly_statements WHERE month = ?1",
+        [month],
+        |row| {
+          Ok(StoredStatement {
+            month: row.get(0)?,
+            body: row.get(1)?,
+            hash: row.get(2)?,
+          })
+        },
+      )
+      .optional()
+      .map_err(|e| e.into())
+  }
+
+  /// Month and hash of the newest statement before `month` ("YYYY-MM" sorts chronologically)
+  pub fn get_statement_before(&self, month: &str) -> Result<Option<(String, String)>> {
+    let conn = self.reader()?;
+    conn
+      .query_row(
+        "SELECT month, hash FROM monthly_statements WHERE month < ?1 ORDER BY month DESC LIMIT 1",
+        [month],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+      )
+      .optional()
+      .map_err(|e| e.into())
+  }
+
+  /// Months with a statement, oldest first
+  pub fn list_statement_months(&self) -> Result<Vec<String>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached("SELECT month FROM monthly_statements ORDER BY month")?;
+    let months = stmt.query_map([], |row| row.get(0))?;
+    months.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn statement(month: &str) -> StoredStatement {
+    StoredStatement {
+      month: month.to_string(),
+      body: "{}".to_string(),
+      hash: format!("hash-{}", month),
+    }
+  }
+
+  #[test]
+  fn test_statements_are_insert_only() {
+    let (db, _temp) = create_test_db();
+    db.insert_monthly_statement(&statement("2024-05"), Utc::now()).unwrap();
+
+    assert!(db.insert_monthly_statement(&statement("2024-05"), Utc::now()).is_err());
+
+    let conn = db.conn.lock().unwrap();
+    assert!(conn.execute("UPDATE monthly_statements SET body = 'x'", []).is_err());
+    assert!(conn.execute("DELETE FROM monthly_statements", []).is_err());
+  }
+
+  #[test]
+  fn test_statement_before() {
+    let (db, _temp) = create_test_db();
+    db.insert_monthly_statement(&statement("2024-04"), Utc::now()).unwrap();
+    db.insert_monthly_statement(&statement("2024-05"), Utc::now()).unwrap();
+
+    assert_eq!(db.get_statement_before("2024-04").unwrap(), None);
+    assert_eq!(
+      db.get_statement_before("2024-06").unwrap(),
+      Some(("2024-05".to_string(), "hash-2024-05".to_string()))
+    );
+    assert_eq!(db.list_statement_months().unwrap(), vec!["2024-04", "2024-05"]);
+  }
+}
@@ -0,0 +1,109 @@
+//! How much disk the database takes and where it goes, so the UI can warn
+//! before it grows out of hand and point at what to prune.
+
+use super::Database;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageStats {
+  /// Size of the main database file
+  pub db_size_bytes: u64,
+  /// Size of the write-ahead log; it shrinks back at the next maintenance checkpoint
+  pub wal_size_bytes: u64,
+  pub page_size: i64,
+  pub page_count: i64,
+  /// Unused pages inside the database file
+  pub free_pages: i64,
+  /// Estimated space a vacuum and WAL checkpoint would give back to the OS
+  pub reclaimable_bytes: u64,
+  /// Rows per table (the search index's internal tables are left out)
+  pub table_rows: BTreeMap<String, i64>,
+}
+
+fn file_size(path: &Path) -> u64 {
+  std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+impl Database {
+  /// File sizes, page usage and row counts for the database
+  pub fn get_storage_stats(&self) -> Result<StorageStats> {
+    let conn = self.reader()?;
+    let pragma = |name: &str| -> Result<i64> {
+      Ok(conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get(0))?)
+    };
+    let page_size = pragma("page_size")?;
+    let page_count = pragma("page_count")?;
+    let free_pages = pragma("freelist_count")?;
+
+    let tables: Vec<String> = conn
+      .prepare(
+        r#"
+        SELECT name FROM sqlite_master
+        WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT LIKE 'events_fts_%'
+        ORDER BY name
+        "#,
+      )?
+      .query_map([], |row| row.get(0))?
+      .collect::<Result<_, _>>()?;
+    let mut table_rows = BTreeMap::new();
+    for table in tables {
+      let rows: i64 = conn.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| row.get(0))?;
+      table_rows.insert(table, rows);
+    }
+
+    // An in-memory or temporary database has no files to measure
+    let (db_size_bytes, wal_size_bytes) = match conn.path().filter(|path| !path.is_empty()) {
+      Some(path) => {
+        let mut wal_path = OsString::from(path);
+        wal_path.push("-wal");
+        (file_size(Path::new(path)), file_size(Path::new(&wal_path)))
+      }
+      None => (0, 0),
+    };
+
+    Ok(StorageStats {
+      db_size_bytes,
+      wal_size_bytes,
+      page_size,
+      page_count,
+      free_pages,
+      reclaimable_bytes: (free_pages * page_size) as u64 + wal_size_bytes,
+      table_rows,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use chrono::Utc;
+  use tempfile::TempDir;
+
+  #[test]
+  fn test_storage_stats_count_rows_and_files() {
+    let dir = TempDir::new().unwrap();
+    let db = Database::new(&dir.path().join("local.db")).unwrap();
+    for i in 0..3 {
+      let window = WindowInfo {
+        process_name: "code.exe".to_string(),
+        window_title: format!("file {}", i),
+        timestamp: Utc::now(),
+        ..Default::default()
+      };
+      db.store_event_sync(&window).unwrap();
+    }
+
+    let stats = db.get_storage_stats().unwrap();
+    assert_eq!(stats.table_rows.get("local_events"), Some(&3));
+    assert!(stats.table_rows.contains_key("local_settings"));
+    assert!(!stats.table_rows.keys().any(|table| table.starts_with("events_fts_")));
+    assert!(stats.db_size_bytes > 0);
+    assert!(stats.page_count > 0 && stats.free_pages <= stats.page_count);
+    assert!(stats.reclaimable_bytes >= stats.wal_size_bytes);
+  }
+}
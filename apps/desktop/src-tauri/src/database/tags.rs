@@ -0,0 +1,218 @@
+//! User tags on events ("deep work", "billable").
+//!
+//! Tags are free-form labels, independent of categories: an event has one
+//! category but any number of tags. Names are unique regardless of case and
+//! a tag is created the first time it is used. Deleting an event drops its
+//! tags with it (see the trigger in migration v7).
+
+use super::connection::{map_event_row, EVENT_COLUMNS};
+use super::{Database, StoredEvent};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Longest tag name accepted, in characters
+const MAX_TAG_CHARS: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tag {
+  pub id: i64,
+  pub name: String,
+  /// Events currently carrying the tag
+  pub event_count: i64,
+}
+
+fn normalize_tag(name: &str) -> Result<&str> {
+  let name = name.trim();
+  if name.is_empty() {
+    bail!("Tag cannot be empty");
+  }
+  if name.chars().count() > MAX_TAG_CHARS {
+    bail!("Tag is longer than {} characters", MAX_TAG_CHARS);
+  }
+  Ok(name)
+}
+
+impl Database {
+  /// Add `tag` to each of `event_ids`, creating the tag if needed. Unknown
+  /// ids are skipped; returns how many events gained the tag.
+  pub fn tag_events(&self, event_ids: &[String], tag: &str) -> Result<usize> {
+    let tag = normalize_tag(tag)?;
+    let now = Utc::now().timestamp_millis();
+
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+      "INSERT INTO tags (name, created_at) VALUES (?1, ?2) ON CONFLICT(name) DO NOTHING",
+      (tag, now),
+    )?;
+    let tag_id: i64 = tx.query_row("SELECT id FROM tags WHERE name = ?1", [tag], |row| row.get(0))?;
+
+    let mut tagged = 0;
+    {
+      let mut stmt = tx.prepare_cached(
+        r#"
+        INSERT OR IGNORE INTO event_tags (event_id, tag_id, created_at)
+        SELECT id, ?2, ?3 FROM local_events WHERE id = ?1
+        "#,
+      )?;
+      for event_id in event_ids {
+        tagged += stmt.execute((event_id, tag_id, now))?;
+      }
+    }
+    tx.commit()?;
+    Ok(tagged)
+  }
+
+  /// Remove `tag` from each of `event_ids`; returns how many events lost it.
+  /// The tag itself stays, even when no event carries it any more.
+  pub fn untag_events(&self, event_ids: &[String], tag: &str) -> Result<usize> {
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    let mut untagged = 0;
+    {
+      let mut stmt = tx.prepare_cached(
+        "DELETE FROM event_tags WHERE event_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+      )?;
+      for event_id in event_ids {
+        untagged += stmt.execute((event_id, tag.trim()))?;
+      }
+    }
+    tx.commit()?;
+    Ok(untagged)
+  }
+
+  /// Delete a tag and remove it from every event
+  pub fn delete_tag(&self, tag: &str) -> Result<()> {
+    let conn = self.conn.lock().unwrap();
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+      "DELETE FROM event_tags WHERE tag_id IN (SELECT id FROM tags WHERE name = ?1)",
+      [tag.trim()],
+    )?;
+    tx.execute("DELETE FROM tags WHERE name = ?1", [tag.trim()])?;
+    tx.commit()?;
+    Ok(())
+  }
+
+  /// Every tag with its event count, by name
+  pub fn get_tags(&self) -> Result<Vec<Tag>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      r#"
+      SELECT t.id, t.name, COUNT(et.event_id)
+      FROM tags t
+      LEFT JOIN event_tags et ON et.tag_id = t.id
+      GROUP BY t.id
+      ORDER BY t.name COLLATE NOCASE
+      "#,
+    )?;
+    let rows = stmt.query_map([], |row| {
+      Ok(Tag {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        event_count: row.get(2)?,
+      })
+    })?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  /// Names of the tags on one event, by name
+  pub fn get_event_tags(&self, event_id: &str) -> Result<Vec<String>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(
+      r#"
+      SELECT t.name
+      FROM event_tags et
+      JOIN tags t ON t.id = et.tag_id
+      WHERE et.event_id = ?1
+      ORDER BY t.name COLLATE NOCASE
+      "#,
+    )?;
+    let rows = stmt.query_map([event_id], |row| row.get(0))?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+
+  /// Events carrying `tag` with start time in [start, end), oldest first
+  pub fn get_events_with_tag(&self, tag: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<StoredEvent>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached(&format!(
+      r#"
+      SELECT {}
+      FROM local_events
+      WHERE timestamp >= ?2 AND timestamp < ?3
+        AND id IN (
+          SELECT et.event_id FROM event_tags et JOIN tags t ON t.id = et.tag_id WHERE t.name = ?1
+        )
+      ORDER BY timestamp ASC
+      "#,
+      EVENT_COLUMNS
+    ))?;
+    let rows = stmt.query_map((tag.trim(), start.timestamp_millis(), end.timestamp_millis()), map_event_row)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.into())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use chrono::Duration;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn store(db: &Database, title: &str) -> String {
+    let window = WindowInfo {
+      process_name: "code.exe".to_string(),
+      window_title: title.to_string(),
+      timestamp: Utc::now(),
+      ..Default::default()
+    };
+    db.store_event_sync(&window).unwrap()
+  }
+
+  #[test]
+  fn test_tag_untag_and_query() {
+    let (db, _temp) = create_test_db();
+    let first = store(&db, "first");
+    let second = store(&db, "second");
+    let (start, end) = (Utc::now() - Duration::hours(1), Utc::now() + Duration::hours(1));
+
+    let both = vec![first.clone(), second.clone(), "missing".to_string()];
+    assert_eq!(db.tag_events(&both, " Deep work ").unwrap(), 2);
+    assert_eq!(db.tag_events(&[first.clone()], "deep WORK").unwrap(), 0);
+    assert_eq!(db.tag_events(&[first.clone()], "billable").unwrap(), 1);
+
+    let tags = db.get_tags().unwrap();
+    let counts: Vec<_> = tags.iter().map(|t| (t.name.as_str(), t.event_count)).collect();
+    assert_eq!(counts, vec![("billable", 1), ("Deep work", 2)]);
+    assert_eq!(db.get_event_tags(&first).unwrap(), vec!["billable", "Deep work"]);
+    assert_eq!(db.get_events_with_tag("deep work", start, end).unwrap().len(), 2);
+
+    assert_eq!(db.untag_events(&[second.clone()], "Deep work").unwrap(), 1);
+    assert_eq!(db.untag_events(&[second.clone()], "unknown").unwrap(), 0);
+    let tagged = db.get_events_with_tag("Deep work", start, end).unwrap();
+    assert_eq!(tagged.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec![first.as_str()]);
+
+    db.delete_tag("billable").unwrap();
+    assert_eq!(db.get_event_tags(&first).unwrap(), vec!["Deep work"]);
+    assert!(db.tag_events(&[first], "  ").is_err());
+  }
+
+  #[test]
+  fn test_deleting_event_drops_its_tags() {
+    let (db, _temp) = create_test_db();
+    let id = store(&db, "private");
+    db.tag_events(&[id.clone()], "billable").unwrap();
+
+    db.conn.lock().unwrap().execute("DELETE FROM local_events WHERE id = ?1", [&id]).unwrap();
+
+    assert!(db.get_event_tags(&id).unwrap().is_empty());
+    assert_eq!(db.get_tags().unwrap()[0].event_count, 0);
+  }
+}
@@ -0,0 +1,162 @@
+//! Per-app control over how much of the window title is stored.
+//!
+//! Apps without a policy store the full (sanitized) title. Process names are
+//! matched exactly, case-insensitively, as with app exclusions.
+
+use super::Database;
+use anyhow::{bail, Result};
+use chrono::Utc;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TitlePolicy {
+  /// Store the title as captured
+  #[default]
+  Full,
+  /// Store the app name in place of the title
+  AppName,
+  /// Store no title at all
+  None,
+}
+
+impl TitlePolicy {
+  fn as_str(&self) -> &'static str {
+    match self {
+      TitlePolicy::Full => "full",
+      TitlePolicy::AppName => "app_name",
+      TitlePolicy::None => "none",
+    }
+  }
+
+  fn parse(value: &str) -> Option<Self> {
+    match value {
+      "full" => Some(TitlePolicy::Full),
+      "app_name" => Some(TitlePolicy::AppName),
+      "none" => Some(TitlePolicy::None),
+      _ => None,
+    }
+  }
+
+  /// The title to store for a window of `process_name`; empty means none
+  pub fn apply(&self, process_name: &str, title: &str) -> String {
+    match self {
+      TitlePolicy::Full => title.to_string(),
+      TitlePolicy::AppName => process_name.to_string(),
+      TitlePolicy::None => String::new(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppTitlePolicy {
+  pub process_name: String,
+  pub policy: TitlePolicy,
+}
+
+fn normalize_process_name(process_name: &str) -> Result<String> {
+  let process_name = process_name.trim().to_lowercase();
+  if process_name.is_empty() {
+    bail!("Process name cannot be empty");
+  }
+  Ok(process_name)
+}
+
+impl Database {
+  /// Apps with a title policy, alphabetically
+  pub fn get_title_policies(&self) -> Result<Vec<AppTitlePolicy>> {
+    let conn = self.reader()?;
+    let mut stmt = conn.prepare_cached("SELECT process_name, policy FROM title_policies ORDER BY process_name")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+
+    let mut policies = Vec::new();
+    for row in rows {
+      let (process_name, policy) = row?;
+      if let Some(policy) = TitlePolicy::parse(&policy) {
+        policies.push(AppTitlePolicy { process_name, policy });
+      }
+    }
+    Ok(policies)
+  }
+
+  /// Add or replace the policy for an app
+  pub fn set_title_policy(&self, process_name: &str, policy: TitlePolicy) -> Result<()> {
+    let process_name = normalize_process_name(process_name)?;
+    let conn = self.conn.lock().unwrap();
+    conn.execute(
+      r#"
+      INSERT INTO title_policies (process_name, policy, updated_at)
+      VALUES (?1, ?2, ?3)
+      ON CONFLICT(process_name) DO UPDATE SET
+        policy = excluded.policy,
+        updated_at = excluded.updated_at
+      "#,
+      (process_name, policy.as_str(), Utc::now().timestamp_millis()),
+    )?;
+    Ok(())
+  }
+
+  /// Back to storing full titles; returns false if the app had no policy
+  pub fn remove_title_policy(&self, process_name: &str) -> Result<bool> {
+    let process_name = normalize_process_name(process_name)?;
+    let conn = self.conn.lock().unwrap();
+    let removed = conn.execute("DELETE FROM title_policies WHERE process_name = ?1", [process_name])?;
+    Ok(removed > 0)
+  }
+
+  pub(crate) fn title_policy_sync(&self, process_name: &str) -> Result<TitlePolicy> {
+    let conn = self.reader()?;
+    let policy: Option<String> = conn
+      .query_row(
+        "SELECT policy FROM title_policies WHERE process_name = ?1",
+        [process_name.trim().to_lowercase()],
+        |row| row.get(0),
+      )
+      .optional()?;
+    Ok(policy.as_deref().and_then(TitlePolicy::parse).unwrap_or_default())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  #[test]
+  fn test_set_list_remove() {
+    let (db, _temp) = create_test_db();
+    assert_eq!(db.title_policy_sync("slack.exe").unwrap(), TitlePolicy::Full);
+
+    db.set_title_policy("Slack.exe", TitlePolicy::AppName).unwrap();
+    db.set_title_policy("signal.exe", TitlePolicy::None).unwrap();
+    db.set_title_policy("slack.exe", TitlePolicy::None).unwrap();
+
+    assert_eq!(
+      db.get_title_policies().unwrap(),
+      vec![
+        AppTitlePolicy { process_name: "signal.exe".to_string(), policy: TitlePolicy::None },
+        AppTitlePolicy { process_name: "slack.exe".to_string(), policy: TitlePolicy::None },
+      ]
+    );
+    assert_eq!(db.title_policy_sync("SLACK.EXE").unwrap(), TitlePolicy::None);
+
+    assert!(db.remove_title_policy("slack.exe").unwrap());
+    assert!(!db.remove_title_policy("slack.exe").unwrap());
+    assert_eq!(db.title_policy_sync("slack.exe").unwrap(), TitlePolicy::Full);
+    assert!(db.set_title_policy(" ", TitlePolicy::None).is_err());
+  }
+
+  #[test]
+  fn test_apply() {
+    assert_eq!(TitlePolicy::Full.apply("slack.exe", "#general"), "#general");
+    assert_eq!(TitlePolicy::AppName.apply("slack.exe", "#general"), "slack.exe");
+    assert_eq!(TitlePolicy::None.apply("slack.exe", "#general"), "");
+  }
+}
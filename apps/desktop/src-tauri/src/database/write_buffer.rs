@@ -0,0 +1,262 @@
+//! Write-behind buffer for small, frequently rewritten status values.
+//!
+//! Sync bookkeeping (`sync_state`) and status settings such as the last sync
+//! error are rewritten on every sync attempt. Instead of one disk write each,
+//! the latest value per key is kept in memory and written in a single
+//! transaction by a periodic flush and on exit. Reads see buffered values.
+
+use super::Database;
+use anyhow::Result;
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{debug, error};
+
+/// How often buffered status writes are flushed to disk
+pub(crate) const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Settings that are write-behind rather than written (and versioned) immediately
+const BUFFERED_SETTINGS: &[&str] = &["last_sync_error"];
+
+pub(crate) fn is_buffered_setting(key: &str) -> bool {
+  BUFFERED_SETTINGS.contains(&key)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum StatusTable {
+  Settings,
+  SyncState,
+}
+
+impl StatusTable {
+  fn name(self) -> &'static str {
+    match self {
+      StatusTable::Settings => "local_settings",
+      StatusTable::SyncState => "sync_state",
+    }
+  }
+}
+
+/// Latest pending value (and its write time) per table/key
+type PendingWrites = BTreeMap<(StatusTable, String), (String, i64)>;
+
+#[derive(Default)]
+pub(crate) struct WriteBuffer {
+  pending: Mutex<PendingWrites>,
+  requested: AtomicU64,
+  coalesced: AtomicU64,
+  rows_written: AtomicU64,
+  flushes: AtomicU64,
+}
+
+/// Write counters for the status write buffer
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DbStats {
+  /// Status writes requested by callers
+  pub requested_writes: u64,
+  /// Requests replaced by a newer value before reaching disk
+  pub coalesced_writes: u64,
+  /// Rows actually written to disk
+  pub rows_written: u64,
+  /// Flush transactions committed
+  pub flushes: u64,
+  /// Values waiting for the next flush
+  pub pending_writes: u64,
+}
+
+impl WriteBuffer {
+  pub(crate) fn put(&self, table: StatusTable, key: &str, value: &str) {
+    let mut pending = self.pending.lock().unwrap();
+    let replaced = pending.insert((table, key.to_string()), (value.to_string(), Utc::now().timestamp_millis()));
+
+    self.requested.fetch_add(1, Ordering::Relaxed);
+    if replaced.is_some() {
+      self.coalesced.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+
+  pub(crate) fn get(&self, table: StatusTable, key: &str) -> Option<String> {
+    let pending = self.pending.lock().unwrap();
+    pending.get(&(table, key.to_string())).map(|(value, _)| value.clone())
+  }
+
+  fn take(&self) -> PendingWrites {
+    std::mem::take(&mut *self.pending.lock().unwrap())
+  }
+
+  /// Put back writes that failed to flush, unless a newer value arrived meanwhile
+  fn restore(&self, writes: PendingWrites) {
+    let mut pending = self.pending.lock().unwrap();
+    for (key, value) in writes {
+      pending.entry(key).or_insert(value);
+    }
+  }
+
+  fn stats(&self) -> DbStats {
+    DbStats {
+      requested_writes: self.requested.load(Ordering::Relaxed),
+      coalesced_writes: self.coalesced.load(Ordering::Relaxed),
+      rows_written: self.rows_written.load(Ordering::Relaxed),
+      flushes: self.flushes.load(Ordering::Relaxed),
+      pending_writes: self.pending.lock().unwrap().len() as u64,
+    }
+  }
+}
+
+impl Database {
+  /// Write all buffered status values in one transaction; returns the rows written
+  pub fn flush_status_writes(&self) -> Result<usize> {
+    let writes = self.status_writes.take();
+    if writes.is_empty() {
+      return Ok(0);
+    }
+
+    let result = (|| -> Result<usize> {
+      let conn = self.conn.lock().unwrap();
+      let tx = conn.unchecked_transaction()?;
+
+      for ((table, key), (value, updated_at)) in &writes {
+        tx.execute(
+          &format!(
+            r#"
+            INSERT INTO {} (key, value, updated_at)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT(key) DO UPDATE SET
+              value = excluded.value,
+              updated_at = excluded.updated_at
+            "#,
+            table.name()
+          ),
+          (key, value, updated_at),
+        )?;
+      }
+
+      tx.commit()?;
+      Ok(writes.len())
+    })();
+
+    match result {
+      Ok(rows) => {
+        self.status_writes.rows_written.fetch_add(rows as u64, Ordering::Relaxed);
+        self.status_writes.flushes.fetch_add(1, Ordering::Relaxed);
+        Ok(rows)
+      }
+      Err(e) => {
+        self.status_writes.restore(writes);
+        Err(e)
+      }
+    }
+  }
+
+  pub fn get_db_stats(&self) -> DbStats {
+    self.status_writes.stats()
+  }
+
+  /// Flush buffered status writes every FLUSH_INTERVAL
+  pub fn start_write_flusher(&self) {
+    let db = self.clone();
+    tauri::async_runtime::spawn(async move {
+      let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+
+      loop {
+        ticker.tick().await;
+
+        let flush_db = db.clone();
+        match tokio::task::spawn_blocking(move || flush_db.flush_status_writes()).await {
+          Ok(Ok(0)) => {}
+          Ok(Ok(rows)) => debug!("Flushed {} buffered status writes", rows),
+          Ok(Err(e)) => error!("Failed to flush status writes: {}", e),
+          Err(e) => error!("Status write flush task failed: {}", e),
+        }
+      }
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn stored_sync_state(db: &Database, key: &str) -> Option<String> {
+    let conn = db.conn.lock().unwrap();
+    conn
+      .query_row("SELECT value FROM sync_state WHERE key = ?", [key], |row| row.get(0))
+      .ok()
+  }
+
+  #[test]
+  fn test_writes_are_coalesced_until_flush() {
+    let (db, _temp) = create_test_db();
+
+    for i in 0..5 {
+      db.update_sync_state("last_sync_at", &i.to_string()).unwrap();
+    }
+    assert_eq!(stored_sync_state(&db, "last_sync_at"), None);
+
+    assert_eq!(db.flush_status_writes().unwrap(), 1);
+    assert_eq!(stored_sync_state(&db, "last_sync_at").as_deref(), Some("4"));
+
+    let stats = db.get_db_stats();
+    assert_eq!(stats.requested_writes, 5);
+    assert_eq!(stats.coalesced_writes, 4);
+    assert_eq!(stats.rows_written, 1);
+    assert_eq!(stats.flushes, 1);
+    assert_eq!(stats.pending_writes, 0);
+  }
+
+  #[test]
+  fn test_reads_see_buffered_values() {
+    let (db, _temp) = create_test_db();
+    db.set_setting("last_sync_error", "timeout").unwrap();
+
+    assert_eq!(db.get_setting("last_sync_error").unwrap().as_deref(), Some("timeout"));
+    assert_eq!(db.get_db_stats().pending_writes, 1);
+  }
+
+  #[test]
+  fn test_buffered_settings_are_not_versioned() {
+    let (db, _temp) = create_test_db();
+    db.set_setting("last_sync_error", "timeout").unwrap();
+    db.flush_status_writes().unwrap();
+
+    assert!(db.get_config_history(None, None, 10).unwrap().is_empty());
+  }
+
+  #[test]
+  fn test_other_settings_write_through() {
+    let (db, _temp) = create_test_db();
+    db.set_setting("idle_threshold_seconds", "120").unwrap();
+
+    assert_eq!(db.get_db_stats().pending_writes, 0);
+  }
+
+  #[test]
+  fn test_empty_flush_is_noop() {
+    let (db, _temp) = create_test_db();
+    assert_eq!(db.flush_status_writes().unwrap(), 0);
+    assert_eq!(db.get_db_stats().flushes, 0);
+  }
+
+  #[test]
+  fn test_flush_persists_across_reopen() {
+    let temp_file = NamedTempFile::new().unwrap();
+    {
+      let db = Database::new(temp_file.path()).unwrap();
+      db.set_setting("last_sync_error", "offline").unwrap();
+      db.flush_status_writes().unwrap();
+    }
+
+    let db = Database::new(temp_file.path()).unwrap();
+    assert_eq!(db.get_setting("last_sync_error").unwrap().as_deref(), Some("offline"));
+  }
+}
@@ -0,0 +1,135 @@
+//! Dedicated database writer thread.
+//!
+//! Async code used to write through `spawn_blocking`, so the collector, the
+//! sync client and UI commands each tied up a blocking-pool thread that then
+//! waited on the connection mutex. `Database::write` hands the write to one
+//! long-lived thread over a channel and awaits the result instead. Writes run
+//! one at a time, in the order they were submitted.
+//!
+//! With `Database::transaction` for atomic async writes and `Database::read`
+//! (see `readers`) for queries, this gives async callers what an async driver
+//! such as sqlx or tokio-rusqlite would, while the synchronous methods stay
+//! usable from blocking code, tests and the dashboard.
+
+use super::Database;
+use anyhow::{anyhow, Result};
+use rusqlite::Transaction;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{mpsc, Mutex};
+use tracing::error;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+pub(crate) struct DbWriter {
+  jobs: Mutex<mpsc::Sender<Job>>,
+}
+
+impl DbWriter {
+  /// Start the writer thread; it exits once every `Database` handle is dropped
+  pub(super) fn spawn() -> Result<Self> {
+    let (jobs, queue) = mpsc::channel::<Job>();
+    std::thread::Builder::new().name("db-writer".to_string()).spawn(move || {
+      for job in queue {
+        // A panicking write must not take every later write down with it
+        if catch_unwind(AssertUnwindSafe(job)).is_err() {
+          error!("A database write panicked");
+        }
+      }
+    })?;
+
+    Ok(Self { jobs: Mutex::new(jobs) })
+  }
+}
+
+impl Database {
+  /// Run `write` on the writer thread and wait for its result
+  pub async fn write<T, F>(&self, write: F) -> Result<T>
+  where
+    T: Send + 'static,
+    F: FnOnce(&Database) -> Result<T> + Send + 'static,
+  {
+    let (reply, result) = tokio::sync::oneshot::channel();
+    let db = self.clone();
+    let job: Job = Box::new(move || {
+      let _ = reply.send(write(&db));
+    });
+
+    self
+      .writer
+      .jobs
+      .lock()
+      .unwrap()
+      .send(job)
+      .map_err(|_| anyhow!("Database writer has stopped"))?;
+    result.await.map_err(|_| anyhow!("Database write was dropped"))?
+  }
+
+  /// Run `write` in a transaction on the writer thread: committed if it
+  /// returns Ok, rolled back if it returns an error
+  pub async fn transaction<T, F>(&self, write: F) -> Result<T>
+  where
+    T: Send + 'static,
+    F: FnOnce(&Transaction) -> Result<T> + Send + 'static,
+  {
+    self
+      .write(move |db| {
+        let mut conn = db.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let value = write(&tx)?;
+        tx.commit()?;
+        Ok(value)
+      })
+      .await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  #[tokio::test]
+  async fn test_writes_run_on_writer_thread_and_survive_panics() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+
+    for i in 0..5 {
+      let thread = db
+        .write(move |db| {
+          db.set_setting("write_order", &i.to_string())?;
+          Ok(std::thread::current().name().map(str::to_string))
+        })
+        .await
+        .unwrap();
+      assert_eq!(thread.as_deref(), Some("db-writer"));
+    }
+    assert_eq!(db.get_setting("write_order").unwrap().as_deref(), Some("4"));
+
+    let panicked = db.write(|_| -> Result<()> { panic!("boom") }).await;
+    assert!(panicked.is_err());
+    db.write(|db| db.set_setting("write_order", "after")).await.unwrap();
+    assert_eq!(db.get_setting("write_order").unwrap().as_deref(), Some("after"));
+  }
+
+  #[tokio::test]
+  async fn test_transaction_commits_or_rolls_back() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    let set = |tx: &Transaction, value: &str| -> Result<()> {
+      tx.execute("UPDATE local_settings SET value = ?1 WHERE key = 'idle_threshold_seconds'", [value])?;
+      Ok(())
+    };
+
+    db.transaction(move |tx| set(tx, "90")).await.unwrap();
+    assert_eq!(db.get_setting("idle_threshold_seconds").unwrap().as_deref(), Some("90"));
+
+    let failed = db
+      .transaction(move |tx| -> Result<()> {
+        set(tx, "45")?;
+        anyhow::bail!("changed my mind")
+      })
+      .await;
+    assert!(failed.is_err());
+    assert_eq!(db.get_setting("idle_threshold_seconds").unwrap().as_deref(), Some("90"));
+  }
+}
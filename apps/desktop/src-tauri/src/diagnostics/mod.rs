@@ -0,0 +1,201 @@
+use crate::collector::event_queue::EventQueue;
+use crate::collector::window_tracker::WindowInfo;
+use crate::database::Database;
+use crate::sync::SyncClient;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{error, info};
+
+const SELF_TEST_APP: &str = "lifespan-self-test";
+
+/// Outcome of one pipeline stage
+#[derive(Debug, Clone, Serialize)]
+pub struct StageResult {
+  pub stage: String,
+  pub passed: bool,
+  pub duration_ms: u64,
+  pub error: Option<String>,
+}
+
+/// Result of a full pipeline self-test
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+  pub passed: bool,
+  pub stages: Vec<StageResult>,
+  pub total_ms: u64,
+}
+
+/// Runs stages in order and stops at the first failure
+struct StageRunner {
+  stages: Vec<StageResult>,
+}
+
+impl StageRunner {
+  async fn run<T, F, Fut>(&mut self, stage: &str, f: F) -> Option<T>
+  where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<T>>,
+  {
+    if self.stages.iter().any(|s| !s.passed) {
+      return None;
+    }
+
+    let started = Instant::now();
+    let result = f().await;
+    let duration_ms = started.elapsed().as_millis() as u64;
+
+    let (passed, error, value) = match result {
+      Ok(value) => (true, None, Some(value)),
+      Err(e) => (false, Some(e.to_string()), None),
+    };
+
+    self.stages.push(StageResult {
+      stage: stage.to_string(),
+      passed,
+      duration_ms,
+      error,
+    });
+
+    value
+  }
+}
+
+/// Push a synthetic event through queue -> database -> encryption -> (mock) sync -> mark-as-synced.
+/// The synthetic event is removed afterwards so it never reaches reports or the server.
+pub async fn run_self_test(db: Arc<Database>, sync_client: &SyncClient) -> SelfTestReport {
+  let started = Instant::now();
+  let mut runner = StageRunner { stages: Vec::new() };
+
+  let window_info = WindowInfo {
+    process_name: SELF_TEST_APP.to_string(),
+    window_title: "Self-test event".to_string(),
+    timestamp: Utc::now(),
+    ..Default::default()
+  };
+
+  let queued = runner
+    .run("queue", || async {
+      let queue = EventQueue::new(1);
+      queue.enqueue(window_info).await?;
+      queue
+        .drain()
+        .await
+        .pop()
+        .map(|event| event.window_info)
+        .ok_or_else(|| anyhow!("Event was not returned by the queue"))
+    })
+    .await;
+
+  let stored = match queued {
+    Some(window_info) => {
+      runner
+        .run("database", || async {
+          let id = db.store_event(&window_info).await?;
+          let event = db
+            .get_event(&id)?
+            .ok_or_else(|| anyhow!("Stored event could not be read back"))?;
+          Ok(event)
+        })
+        .await
+    }
+    None => None,
+  };
+
+  if let Some(event) = &stored {
+    let id = &event.id;
+
+    let body = runner
+      .run("encryption", || async {
+        Ok(sync_client.build_request_body(std::slice::from_ref(event)).await?)
+      })
+      .await;
+
+    if let Some(body) = body {
+      runner
+        .run("mock_sync", || async {
+          // Check the payload is what the server expects before pretending it accepted it
+          let request: serde_json::Value = serde_json::from_str(&body)?;
+          let sent_id = request["events"][0]["id"].as_str().unwrap_or_default();
+          if sent_id != id.as_str() {
+            return Err(anyhow!("Payload contains event '{}', expected '{}'", sent_id, id));
+          }
+          if request["events"][0]["encrypted_data"].as_str().unwrap_or_default().is_empty() {
+            return Err(anyhow!("Payload is missing encrypted data"));
+          }
+          Ok(())
+        })
+        .await;
+
+      runner
+        .run("mark_synced", || async {
+          db.mark_as_synced(std::slice::from_ref(id))?;
+          if !db.is_event_synced(id)? {
+            return Err(anyhow!("Event is still marked as unsynced"));
+          }
+          Ok(())
+        })
+        .await;
+    }
+
+    if let Err(e) = db.delete_event_sync(id) {
+      error!("Failed to remove self-test event {}: {}", id, e);
+    }
+  }
+
+  let passed = runner.stages.iter().all(|s| s.passed);
+  let total_ms = started.elapsed().as_millis() as u64;
+  info!("Self-test finished: passed={}, {} stages in {}ms", passed, runner.stages.len(), total_ms);
+
+  SelfTestReport {
+    passed,
+    stages: runner.stages,
+    total_ms,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::encryption::SecretKey;
+  use tempfile::NamedTempFile;
+
+  fn create_test_env() -> (Arc<Database>, SyncClient, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Arc::new(Database::new(temp_file.path()).unwrap());
+    let sync_client = SyncClient::new(db.clone());
+    (db, sync_client, temp_file)
+  }
+
+  #[tokio::test]
+  async fn test_self_test_passes_with_crypto_key() {
+    let (db, sync_client, _temp) = create_test_env();
+    sync_client
+      .set_crypto_key(SecretKey::new(*b"test_key_32_bytes_long_123456789"))
+      .await
+      .unwrap();
+
+    let report = run_self_test(db.clone(), &sync_client).await;
+
+    assert!(report.passed, "{:?}", report.stages);
+    assert_eq!(report.stages.len(), 5);
+    // Synthetic event is cleaned up
+    assert_eq!(db.get_event_count().unwrap(), 0);
+  }
+
+  #[tokio::test]
+  async fn test_self_test_reports_missing_crypto_key() {
+    let (db, sync_client, _temp) = create_test_env();
+
+    let report = run_self_test(db.clone(), &sync_client).await;
+
+    assert!(!report.passed);
+    let failed = report.stages.iter().find(|s| !s.passed).unwrap();
+    assert_eq!(failed.stage, "encryption");
+    assert!(failed.error.as_ref().unwrap().contains("Crypto manager not initialized"));
+    assert_eq!(db.get_event_count().unwrap(), 0);
+  }
+}
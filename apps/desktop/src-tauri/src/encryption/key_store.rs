@@ -0,0 +1,286 @@
+//! Unlocking the sync key with a passphrase, and caching it in the platform
+//! credential store.
+//!
+//! The sync key is random, made at a device's first unlock, and stored
+//! wrapped with a key derived from the passphrase with Argon2id (deliberately
+//! slow, and only the passphrase unwraps it). Sync keeps the wrapped key in
+//! step with the account's copy on the server: the first device's key becomes
+//! the account's, other devices take it over and unlock it with the
+//! passphrase, and a changed passphrase replaces it everywhere, so the old
+//! one stops unlocking the key. Accounts from before wrapped keys keep the
+//! key derived from their first passphrase (it encrypts their synced data);
+//! it is wrapped the same way once the passphrase changes. With the
+//! `os-keyring` feature the key can be remembered in Windows Credential
+//! Manager, the macOS Keychain or the Secret Service, one entry per profile,
+//! and loaded at the next start. Without the feature (or without a usable
+//! credential store) the key lives in memory only and the passphrase is
+//! asked for again.
+
+use super::{CryptoManager, EncryptedData, SecretKey};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use anyhow::{anyhow, bail, Result};
+use argon2::Argon2;
+use serde::Serialize;
+use zeroize::Zeroizing;
+
+/// Service name the cached keys are filed under
+#[cfg_attr(not(feature = "os-keyring"), allow(dead_code))]
+const KEYRING_SERVICE: &str = "lifespan-sync-key";
+
+/// Fixed salt of the keys derived for accounts from before wrapped keys
+const PASSPHRASE_SALT: &[u8] = b"lifespan-sync-key-v1";
+
+/// Salt for the key that wraps the sync key
+const WRAPPING_SALT: &[u8] = b"lifespan-key-wrap-v1";
+const WRAPPING_AAD: &[u8] = b"lifespan-wrapped-sync-key-v1";
+
+/// sync_state key of the wrapped sync key; empty once dropped for a derived one
+pub const WRAPPED_KEY_STATE_KEY: &str = "wrapped_sync_key";
+
+/// sync_state key set to "1" while the stored wrapped key must replace the
+/// account's on the server, after the passphrase was changed on this device
+pub const WRAPPED_KEY_PENDING_STATE_KEY: &str = "wrapped_sync_key_pending";
+
+/// sync_state key set to "1" when the account's key is derived from the
+/// passphrase and the device should derive it at the next unlock
+pub const DERIVED_KEY_STATE_KEY: &str = "sync_key_derived";
+
+const MIN_PASSPHRASE_LENGTH: usize = 8;
+
+fn argon2_key(passphrase: &str, salt: &[u8]) -> Result<SecretKey> {
+  if passphrase.chars().count() < MIN_PASSPHRASE_LENGTH {
+    bail!("The passphrase must be at least {} characters", MIN_PASSPHRASE_LENGTH);
+  }
+  let mut key = SecretKey::new([0u8; 32]);
+  Argon2::default()
+    .hash_password_into(passphrase.as_bytes(), salt, &mut key[..])
+    .map_err(|e| anyhow!("Failed to derive key: {}", e))?;
+  Ok(key)
+}
+
+/// Derive the sync key from `passphrase` with Argon2id
+pub fn derive_key_from_passphrase(passphrase: &str) -> Result<SecretKey> {
+  argon2_key(passphrase, PASSPHRASE_SALT)
+}
+
+/// The sync key `passphrase` unlocks: unwrapped from `wrapped`, else derived
+/// from it
+pub fn unlock_with_passphrase(passphrase: &str, wrapped: Option<&str>) -> Result<SecretKey> {
+  use base64::Engine;
+  let Some(wrapped) = wrapped else {
+    return derive_key_from_passphrase(passphrase);
+  };
+
+  let crypto = CryptoManager::new(&argon2_key(passphrase, WRAPPING_SALT)?)?;
+  let encrypted = EncryptedData::decode(&base64::engine::general_purpose::STANDARD.decode(wrapped)?)?;
+  let bytes = Zeroizing::new(
+    crypto
+      .decrypt_with_aad(&encrypted, WRAPPING_AAD)
+      .map_err(|_| anyhow!("Wrong passphrase"))?,
+  );
+  if bytes.len() != 32 {
+    bail!("The stored key has the wrong length");
+  }
+  let mut key = SecretKey::new([0u8; 32]);
+  key.copy_from_slice(&bytes);
+  Ok(key)
+}
+
+/// The sync key `passphrase` unlocks on this device, and the wrapped key to
+/// store when a new one was made: unwrapped from `wrapped` if there is one,
+/// derived from the passphrase when the account's key is (`derived`), else a
+/// new random key for the device's first unlock
+pub fn unlock_or_create(passphrase: &str, wrapped: Option<&str>, derived: bool) -> Result<(SecretKey, Option<String>)> {
+  if wrapped.is_some() || derived {
+    return Ok((unlock_with_passphrase(passphrase, wrapped)?, None));
+  }
+  let mut key = SecretKey::new([0u8; 32]);
+  OsRng.fill_bytes(&mut key[..]);
+  let wrapped = wrap_with_passphrase(&key, passphrase)?;
+  Ok((key, Some(wrapped)))
+}
+
+/// `sync_key` wrapped with a key derived from `passphrase`
+pub(super) fn wrap_with_passphrase(sync_key: &[u8; 32], passphrase: &str) -> Result<String> {
+  use base64::Engine;
+  let crypto = CryptoManager::new(&argon2_key(passphrase, WRAPPING_SALT)?)?;
+  let encrypted = crypto.encrypt_with_aad(sync_key, WRAPPING_AAD)?;
+  Ok(base64::engine::general_purpose::STANDARD.encode(encrypted.encode()))
+}
+
+/// Check that `old_passphrase` unlocks `current_key` (the key in use, with
+/// the stored `wrapped` key if any), then wrap it for `new_passphrase`.
+/// Returns the wrapped key to store under WRAPPED_KEY_STATE_KEY.
+pub fn change_passphrase(
+  current_key: &[u8; 32],
+  old_passphrase: &str,
+  new_passphrase: &str,
+  wrapped: Option<&str>,
+) -> Result<String> {
+  let unlocked = unlock_with_passphrase(old_passphrase, wrapped).ok();
+  if unlocked.as_deref() != Some(current_key) {
+    bail!("The current passphrase is wrong");
+  }
+  wrap_with_passphrase(current_key, new_passphrase)
+}
+
+#[cfg_attr(not(feature = "os-keyring"), allow(dead_code))]
+fn decode_key(encoded: &str) -> Result<SecretKey> {
+  let bytes = Zeroizing::new(hex::decode(encoded)?);
+  if bytes.len() != 32 {
+    bail!("The cached key has the wrong length");
+  }
+  let mut key = SecretKey::new([0u8; 32]);
+  key.copy_from_slice(&bytes);
+  Ok(key)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct KeyStoreStatus {
+  /// This build can use the platform credential store
+  pub keyring_supported: bool,
+  /// A key is remembered for the profile
+  pub key_cached: bool,
+}
+
+/// The credential store entries for one profile
+pub struct KeyStore {
+  #[cfg_attr(not(feature = "os-keyring"), allow(dead_code))]
+  profile: String,
+}
+
+impl KeyStore {
+  pub fn new(profile: &str) -> Self {
+    Self {
+      profile: profile.to_string(),
+    }
+  }
+
+  /// Whether this build can use the platform credential store
+  pub fn is_supported() -> bool {
+    cfg!(feature = "os-keyring")
+  }
+
+  pub fn status(&self) -> Result<KeyStoreStatus> {
+    Ok(KeyStoreStatus {
+      keyring_supported: Self::is_supported(),
+      key_cached: self.load()?.is_some(),
+    })
+  }
+
+  #[cfg(feature = "os-keyring")]
+  fn entry(&self) -> Result<keyring::Entry> {
+    Ok(keyring::Entry::new(KEYRING_SERVICE, &self.profile)?)
+  }
+
+  /// The cached key, if one was remembered
+  #[cfg(feature = "os-keyring")]
+  pub fn load(&self) -> Result<Option<SecretKey>> {
+    match self.entry()?.get_password() {
+      Ok(encoded) => decode_key(&Zeroizing::new(encoded)).map(Some),
+      Err(keyring::Error::NoEntry) => Ok(None),
+      Err(e) => Err(e.into()),
+    }
+  }
+
+  #[cfg(not(feature = "os-keyring"))]
+  pub fn load(&self) -> Result<Option<SecretKey>> {
+    Ok(None)
+  }
+
+  /// Remember `key` until `forget` is called
+  #[cfg(feature = "os-keyring")]
+  pub fn save(&self, key: &[u8; 32]) -> Result<()> {
+    Ok(self.entry()?.set_password(&Zeroizing::new(hex::encode(key)))?)
+  }
+
+  #[cfg(not(feature = "os-keyring"))]
+  pub fn save(&self, _key: &[u8; 32]) -> Result<()> {
+    bail!("This build can't store keys in the system keyring")
+  }
+
+  /// Remove the cached key; nothing cached is not an error
+  #[cfg(feature = "os-keyring")]
+  pub fn forget(&self) -> Result<()> {
+    match self.entry()?.delete_credential() {
+      Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+      Err(e) => Err(e.into()),
+    }
+  }
+
+  #[cfg(not(feature = "os-keyring"))]
+  pub fn forget(&self) -> Result<()> {
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_passphrase_key_is_stable() {
+    let key = derive_key_from_passphrase("correct horse battery").unwrap();
+    assert_eq!(key, derive_key_from_passphrase("correct horse battery").unwrap());
+    assert_ne!(key, derive_key_from_passphrase("correct horse battery!").unwrap());
+    assert!(derive_key_from_passphrase("short").is_err());
+  }
+
+  #[test]
+  fn test_changed_passphrase_unlocks_the_same_key() {
+    let key = derive_key_from_passphrase("first passphrase").unwrap();
+    assert_eq!(unlock_with_passphrase("first passphrase", None).unwrap(), key);
+
+    assert!(change_passphrase(&key, "wrong passphrase", "second passphrase", None).is_err());
+    assert!(change_passphrase(&key, "first passphrase", "short", None).is_err());
+    let wrapped = change_passphrase(&key, "first passphrase", "second passphrase", None).unwrap();
+
+    assert_eq!(unlock_with_passphrase("second passphrase", Some(&wrapped)).unwrap(), key);
+    assert!(unlock_with_passphrase("first passphrase", Some(&wrapped)).is_err());
+
+    // And again, from the wrapped key
+    let rewrapped = change_passphrase(&key, "second passphrase", "third passphrase", Some(&wrapped)).unwrap();
+    assert_eq!(unlock_with_passphrase("third passphrase", Some(&rewrapped)).unwrap(), key);
+  }
+
+  #[test]
+  fn test_first_unlock_makes_a_random_wrapped_key() {
+    let (key, wrapped) = unlock_or_create("first passphrase", None, false).unwrap();
+    let wrapped = wrapped.unwrap();
+    assert_ne!(key, derive_key_from_passphrase("first passphrase").unwrap());
+    assert_ne!(key, unlock_or_create("first passphrase", None, false).unwrap().0);
+
+    let (unlocked, created) = unlock_or_create("first passphrase", Some(&wrapped), false).unwrap();
+    assert_eq!(unlocked, key);
+    assert!(created.is_none());
+    assert!(unlock_or_create("wrong passphrase", Some(&wrapped), false).is_err());
+
+    // Accounts from before wrapped keys keep deriving it
+    let (derived, created) = unlock_or_create("first passphrase", None, true).unwrap();
+    assert_eq!(derived, derive_key_from_passphrase("first passphrase").unwrap());
+    assert!(created.is_none());
+  }
+
+  #[test]
+  fn test_decode_cached_key() {
+    let key = [7u8; 32];
+    assert_eq!(*decode_key(&hex::encode(key)).unwrap(), key);
+    assert!(decode_key("abcd").is_err());
+    assert!(decode_key("not hex").is_err());
+  }
+
+  #[cfg(not(feature = "os-keyring"))]
+  #[test]
+  fn test_without_keyring_nothing_is_cached() {
+    let store = KeyStore::new("default");
+    assert!(store.save(&[1u8; 32]).is_err());
+    assert_eq!(
+      store.status().unwrap(),
+      KeyStoreStatus {
+        keyring_supported: false,
+        key_cached: false
+      }
+    );
+    store.forget().unwrap();
+  }
+}
@@ -0,0 +1,83 @@
+//! Subkeys derived from the sync key with HKDF-SHA256.
+//!
+//! Nothing is encrypted with the sync key itself. Each use gets its own
+//! subkey, labelled by purpose, and sync payloads are further split per
+//! device: any device holding the sync key can derive another device's
+//! payload key, but a leaked payload key, database key or display key
+//! exposes nothing else. A new purpose only needs a new label.
+
+use super::SecretKey;
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+/// Fixed HKDF salt; the sync key is already uniformly random
+const HKDF_SALT: &[u8] = b"lifespan-key-hierarchy-v1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyPurpose {
+  /// Event payloads uploaded by one device (derived per device)
+  SyncPayload,
+  /// The account's key check value on the server
+  KeyCheck,
+  /// SQLCipher key of local.db, which its backups share
+  LocalDatabase,
+  /// Readable names behind anonymized app names and titles
+  DisplayNames,
+  /// Keyed hashes stored for anonymized app names and titles
+  AnonymizedNames,
+  /// The stored server config, which holds the JWT
+  ServerConfig,
+}
+
+impl KeyPurpose {
+  fn label(self) -> &'static str {
+    match self {
+      KeyPurpose::SyncPayload => "lifespan/sync-payload",
+      KeyPurpose::KeyCheck => "lifespan/key-check",
+      KeyPurpose::LocalDatabase => "lifespan/local-database",
+      KeyPurpose::DisplayNames => "lifespan/display-names",
+      KeyPurpose::AnonymizedNames => "lifespan/anonymized-names",
+      KeyPurpose::ServerConfig => "lifespan/server-config",
+    }
+  }
+}
+
+/// The subkey for `purpose`, bound to `device_id` when given
+pub fn derive_subkey(sync_key: &[u8; 32], purpose: KeyPurpose, device_id: Option<&str>) -> SecretKey {
+  let mut info = purpose.label().as_bytes().to_vec();
+  if let Some(device_id) = device_id {
+    info.push(0);
+    info.extend_from_slice(device_id.as_bytes());
+  }
+
+  let mut subkey = SecretKey::new([0u8; 32]);
+  Hkdf::<Sha256>::new(Some(HKDF_SALT), sync_key)
+    .expand(&info, &mut subkey[..])
+    .expect("32 bytes is a valid HKDF-SHA256 output length");
+  subkey
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SYNC_KEY: &[u8; 32] = b"test_key_32_bytes_long_123456789";
+
+  #[test]
+  fn test_subkeys_are_independent() {
+    let payload_a = derive_subkey(SYNC_KEY, KeyPurpose::SyncPayload, Some("device-a"));
+    assert_eq!(payload_a, derive_subkey(SYNC_KEY, KeyPurpose::SyncPayload, Some("device-a")));
+    assert_ne!(payload_a, derive_subkey(SYNC_KEY, KeyPurpose::SyncPayload, Some("device-b")));
+    assert_ne!(payload_a, derive_subkey(SYNC_KEY, KeyPurpose::SyncPayload, None));
+
+    let database = derive_subkey(SYNC_KEY, KeyPurpose::LocalDatabase, None);
+    assert_ne!(database, derive_subkey(SYNC_KEY, KeyPurpose::DisplayNames, None));
+    assert_ne!(
+      derive_subkey(SYNC_KEY, KeyPurpose::DisplayNames, None),
+      derive_subkey(SYNC_KEY, KeyPurpose::AnonymizedNames, None)
+    );
+    assert_ne!(database, derive_subkey(SYNC_KEY, KeyPurpose::KeyCheck, None));
+    assert_ne!(*database, *SYNC_KEY);
+    assert_ne!(database, derive_subkey(&[0u8; 32], KeyPurpose::LocalDatabase, None));
+  }
+}
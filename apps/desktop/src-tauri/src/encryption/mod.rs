@@ -1,12 +1,60 @@
 use aes_gcm::{
-  aead::{Aead, AeadCore, KeyInit, OsRng},
+  aead::{Aead, KeyInit, Payload},
   Aes256Gcm, Key, Nonce,
 };
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use zeroize::Zeroizing;
+
+mod key_store;
+mod keys;
+mod nonce;
+mod recovery;
+mod stream;
+
+pub use key_store::{
+  change_passphrase, unlock_or_create, unlock_with_passphrase, KeyStore, KeyStoreStatus, DERIVED_KEY_STATE_KEY,
+  WRAPPED_KEY_PENDING_STATE_KEY, WRAPPED_KEY_STATE_KEY,
+};
+pub use keys::{derive_subkey, KeyPurpose};
+use nonce::NonceIssuer;
+pub use nonce::{key_id, NonceMode, NonceStore, NONCE_MODE_SETTING};
+pub use recovery::{recover_with_code, recovery_code, RECOVERY_CODE_STATE_KEY};
+
+/// Development key for the local database, and for sync until the user
+/// unlocks with a passphrase (or a key cached in the system keyring)
+pub const DEFAULT_SYNC_KEY: &[u8; 32] = b"lifespan-dev-key-32-bytes-long!!";
+
+/// A 256-bit key, wiped from memory when dropped
+pub type SecretKey = Zeroizing<[u8; 32]>;
+
+/// The key derivation before `derive_subkey`; only used to open data older
+/// versions wrote and move it to the HKDF subkeys
+pub fn derive_key(key: &[u8; 32], purpose: &str) -> SecretKey {
+  let mut hasher = Sha256::new();
+  hasher.update(purpose.as_bytes());
+  hasher.update([0]);
+  hasher.update(key);
+  let mut derived = SecretKey::new([0u8; 32]);
+  // Straight into the zeroizing buffer, leaving no copy behind
+  hasher.finalize_into((&mut derived[..]).into());
+  derived
+}
 
+/// The cipher's key schedule is wiped on drop (aes-gcm's `zeroize` feature)
 pub struct CryptoManager {
   cipher: Aes256Gcm,
+  nonces: Mutex<NonceIssuer>,
+}
+
+/// Never prints key material
+impl fmt::Debug for CryptoManager {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("CryptoManager").finish_non_exhaustive()
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -15,17 +63,77 @@ pub struct EncryptedData {
   pub nonce: Vec<u8>,
 }
 
+/// Known plaintext behind key check values
+const KEY_CHECK_PLAINTEXT: &[u8] = b"lifespan key check";
+const KEY_CHECK_AAD: &[u8] = b"lifespan-key-check-v1";
+
+/// First byte of the compact framing: version || nonce || ciphertext
+const FRAME_VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+/// AES-GCM appends a 16-byte tag to every ciphertext
+const TAG_LEN: usize = 16;
+
+impl EncryptedData {
+  /// Compact framing: version byte, then the nonce, then the ciphertext
+  pub fn encode(&self) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(1 + self.nonce.len() + self.ciphertext.len());
+    bytes.push(FRAME_VERSION);
+    bytes.extend_from_slice(&self.nonce);
+    bytes.extend_from_slice(&self.ciphertext);
+    bytes
+  }
+
+  /// Read the compact framing, or the JSON object `encrypt_to_base64` wrote
+  /// before it (which always starts with `{`)
+  pub fn decode(bytes: &[u8]) -> Result<Self> {
+    match bytes.first() {
+      Some(&FRAME_VERSION) => {
+        if bytes.len() < 1 + NONCE_LEN + TAG_LEN {
+          bail!("Encrypted data is truncated");
+        }
+        Ok(Self {
+          nonce: bytes[1..1 + NONCE_LEN].to_vec(),
+          ciphertext: bytes[1 + NONCE_LEN..].to_vec(),
+        })
+      }
+      Some(b'{') => Ok(serde_json::from_slice(bytes)?),
+      Some(version) => bail!("Unsupported encrypted data version {}", version),
+      None => bail!("Encrypted data is empty"),
+    }
+  }
+}
+
 impl CryptoManager {
   pub fn new(key: &[u8; 32]) -> Result<Self> {
     let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
-    Ok(Self { cipher })
+    Ok(Self {
+      cipher,
+      nonces: Mutex::new(NonceIssuer::random()),
+    })
+  }
+
+  /// A cipher whose nonces come from a counter persisted in `store` for this
+  /// key, for keys that encrypt more than random nonces safely allow
+  pub fn with_nonce_counter(key: &[u8; 32], store: Arc<dyn NonceStore>) -> Result<Self> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    Ok(Self {
+      cipher,
+      nonces: Mutex::new(NonceIssuer::counter(store, key_id(key))),
+    })
   }
 
   pub fn encrypt(&self, plaintext: &[u8]) -> Result<EncryptedData> {
-    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    self.encrypt_with_aad(plaintext, &[])
+  }
+
+  /// Encrypt `plaintext` bound to `aad`: decryption fails unless the same
+  /// associated data is given, so a ciphertext can't be moved to another
+  /// record. The associated data itself is not encrypted or stored.
+  pub fn encrypt_with_aad(&self, plaintext: &[u8], aad: &[u8]) -> Result<EncryptedData> {
+    let nonce = self.nonces.lock().unwrap().next()?;
     let ciphertext = self
       .cipher
-      .encrypt(&nonce, plaintext)
+      .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
       .map_err(|e| anyhow!("Encryption failed: {}", e))?;
 
     Ok(EncryptedData {
@@ -35,10 +143,18 @@ impl CryptoManager {
   }
 
   pub fn decrypt(&self, data: &EncryptedData) -> Result<Vec<u8>> {
+    self.decrypt_with_aad(data, &[])
+  }
+
+  /// Decrypt data from `encrypt_with_aad`; `aad` must match exactly
+  pub fn decrypt_with_aad(&self, data: &EncryptedData, aad: &[u8]) -> Result<Vec<u8>> {
+    if data.nonce.len() != NONCE_LEN {
+      bail!("Decryption failed: invalid nonce length");
+    }
     let nonce = Nonce::from_slice(&data.nonce);
     let plaintext = self
       .cipher
-      .decrypt(nonce, data.ciphertext.as_ref())
+      .decrypt(nonce, Payload { msg: &data.ciphertext, aad })
       .map_err(|e| anyhow!("Decryption failed: {}", e))?;
     Ok(plaintext)
   }
@@ -46,15 +162,33 @@ impl CryptoManager {
   pub fn encrypt_to_base64(&self, plaintext: &[u8]) -> Result<String> {
     use base64::Engine;
     let encrypted = self.encrypt(plaintext)?;
-    let json = serde_json::to_vec(&encrypted)?;
-    Ok(base64::engine::general_purpose::STANDARD.encode(&json))
+    Ok(base64::engine::general_purpose::STANDARD.encode(encrypted.encode()))
   }
 
   pub fn decrypt_from_base64(&self, encoded: &str) -> Result<Vec<u8>> {
     use base64::Engine;
-    let json = base64::engine::general_purpose::STANDARD.decode(encoded)?;
-    let encrypted: EncryptedData = serde_json::from_slice(&json)?;
-    self.decrypt(&encrypted)
+    let bytes = base64::engine::general_purpose::STANDARD.decode(encoded)?;
+    self.decrypt(&EncryptedData::decode(&bytes)?)
+  }
+
+  /// A fresh key check value: a known constant encrypted with this key.
+  /// Stored next to the data, it tells whether a key is the one the data
+  /// was encrypted with, without revealing the key.
+  pub fn key_check_value(&self) -> Result<String> {
+    use base64::Engine;
+    let encrypted = self.encrypt_with_aad(KEY_CHECK_PLAINTEXT, KEY_CHECK_AAD)?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(encrypted.encode()))
+  }
+
+  /// Whether `value` (from `key_check_value`) was made with this key
+  pub fn matches_key_check(&self, value: &str) -> bool {
+    use base64::Engine;
+    base64::engine::general_purpose::STANDARD
+      .decode(value)
+      .ok()
+      .and_then(|bytes| EncryptedData::decode(&bytes).ok())
+      .and_then(|encrypted| self.decrypt_with_aad(&encrypted, KEY_CHECK_AAD).ok())
+      .is_some_and(|plaintext| plaintext == KEY_CHECK_PLAINTEXT)
   }
 }
 
@@ -66,6 +200,20 @@ mod tests {
     b"test_key_32_bytes_long_1234567890".clone()
   }
 
+  #[test]
+  fn test_derived_keys_differ_by_purpose() {
+    let key = DEFAULT_SYNC_KEY;
+    assert_eq!(derive_key(key, "local database"), derive_key(key, "local database"));
+    assert_ne!(derive_key(key, "local database"), derive_key(key, "archives"));
+    assert_ne!(*derive_key(key, "local database"), *key);
+  }
+
+  #[test]
+  fn test_debug_hides_key() {
+    let crypto = CryptoManager::new(&get_test_key()).unwrap();
+    assert_eq!(format!("{:?}", crypto), "CryptoManager { .. }");
+  }
+
   #[test]
   fn test_encrypt_decrypt() {
     let key = get_test_key();
@@ -78,6 +226,19 @@ mod tests {
     assert_eq!(plaintext.to_vec(), decrypted);
   }
 
+  #[test]
+  fn test_counter_nonces_decrypt_like_random_ones() {
+    let temp_file = tempfile::NamedTempFile::new().unwrap();
+    let db = Arc::new(crate::database::Database::new(temp_file.path()).unwrap());
+    let key = get_test_key();
+    let counter = CryptoManager::with_nonce_counter(&key, db).unwrap();
+
+    let first = counter.encrypt(b"first").unwrap();
+    let second = counter.encrypt(b"second").unwrap();
+    assert_ne!(first.nonce, second.nonce);
+    assert_eq!(CryptoManager::new(&key).unwrap().decrypt(&second).unwrap(), b"second");
+  }
+
   #[test]
   fn test_wrong_key_fails() {
     let key1 = b"test_key_32_bytes_long_1234567890";
@@ -242,6 +403,64 @@ mod tests {
     assert_eq!(large_data, decrypted);
   }
 
+  #[test]
+  fn test_aad_binds_ciphertext() {
+    let crypto = CryptoManager::new(&get_test_key()).unwrap();
+    let encrypted = crypto.encrypt_with_aad(b"payload", b"event-1").unwrap();
+
+    assert_eq!(crypto.decrypt_with_aad(&encrypted, b"event-1").unwrap(), b"payload");
+    assert!(crypto.decrypt_with_aad(&encrypted, b"event-2").is_err());
+    assert!(crypto.decrypt(&encrypted).is_err());
+    // No associated data is the same as empty associated data
+    let plain = crypto.encrypt(b"payload").unwrap();
+    assert_eq!(crypto.decrypt_with_aad(&plain, &[]).unwrap(), b"payload");
+  }
+
+  #[test]
+  fn test_key_check_value_identifies_key() {
+    let crypto = CryptoManager::new(&get_test_key()).unwrap();
+    let other = CryptoManager::new(&[9u8; 32]).unwrap();
+    let value = crypto.key_check_value().unwrap();
+
+    assert!(crypto.matches_key_check(&value));
+    assert!(!other.matches_key_check(&value));
+    assert!(!crypto.matches_key_check("garbage"));
+    // Values are randomized, so equal keys can't be spotted by comparing them
+    assert_ne!(value, crypto.key_check_value().unwrap());
+  }
+
+  #[test]
+  fn test_compact_framing_is_smaller_than_json() {
+    use base64::Engine;
+    let crypto = CryptoManager::new(&get_test_key()).unwrap();
+    let plaintext = vec![0xABu8; 1000];
+
+    let encrypted = crypto.encrypt(&plaintext).unwrap();
+    let framed = encrypted.encode();
+    assert_eq!(framed.len(), 1 + 12 + plaintext.len() + 16);
+    assert!(framed.len() * 2 < serde_json::to_vec(&encrypted).unwrap().len());
+
+    let encoded = base64::engine::general_purpose::STANDARD.encode(&framed);
+    assert_eq!(crypto.decrypt_from_base64(&encoded).unwrap(), plaintext);
+  }
+
+  #[test]
+  fn test_legacy_json_framing_still_decrypts() {
+    use base64::Engine;
+    let crypto = CryptoManager::new(&get_test_key()).unwrap();
+    let encrypted = crypto.encrypt(b"written by an older version").unwrap();
+    let legacy = base64::engine::general_purpose::STANDARD.encode(serde_json::to_vec(&encrypted).unwrap());
+
+    assert_eq!(crypto.decrypt_from_base64(&legacy).unwrap(), b"written by an older version");
+  }
+
+  #[test]
+  fn test_unknown_framing_is_rejected() {
+    assert!(EncryptedData::decode(&[]).is_err());
+    assert!(EncryptedData::decode(&[2u8; 64]).is_err());
+    assert!(EncryptedData::decode(&[FRAME_VERSION, 0, 0]).is_err());
+  }
+
   #[test]
   fn test_invalid_base64_fails() {
     let key = get_test_key();
@@ -0,0 +1,235 @@
+//! Where AES-GCM nonces come from, and a check that none repeats.
+//!
+//! Random 96-bit nonces are safe up to about 2^32 messages per key; past
+//! that, a repeat becomes likely, and a repeated nonce exposes the XOR of two
+//! plaintexts and the authentication key. Installs that encrypt very high
+//! event volumes can switch to counter nonces: a random 4-byte prefix
+//! (chosen per cipher, so a database restored from backup can't replay old
+//! counters) followed by a 64-bit counter whose high-water mark is persisted
+//! per key version before any nonce from a range is used.
+//!
+//! Either way, every cipher remembers the last NONCE_HISTORY nonces it issued
+//! and refuses to encrypt with one of them again, which catches a broken RNG
+//! or a counter that went backwards.
+
+use super::NONCE_LEN;
+use crate::database::Database;
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Setting that picks the nonce scheme: "random" (the default) or "counter"
+pub const NONCE_MODE_SETTING: &str = "encryption_nonce_mode";
+
+/// Counter values reserved per persisted write
+const COUNTER_BLOCK: u64 = 4096;
+/// Recently issued nonces each cipher checks new ones against
+const NONCE_HISTORY: usize = 65_536;
+const PREFIX_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonceMode {
+  Random,
+  Counter,
+}
+
+impl NonceMode {
+  /// The mode from NONCE_MODE_SETTING; unknown values fall back to random
+  pub fn load(db: &Database) -> Result<Self> {
+    Ok(match db.get_setting(NONCE_MODE_SETTING)?.as_deref() {
+      Some("counter") => NonceMode::Counter,
+      _ => NonceMode::Random,
+    })
+  }
+}
+
+/// Durable storage for counter high-water marks
+pub trait NonceStore: Send + Sync {
+  /// Reserve `count` counter values for `key_id` and return the first. The
+  /// reservation must be durable before this returns.
+  fn reserve(&self, key_id: &str, count: u64) -> Result<u64>;
+}
+
+/// Serializes reservations, which read and then write the high-water mark
+static RESERVATIONS: Mutex<()> = Mutex::new(());
+
+impl NonceStore for Database {
+  fn reserve(&self, key_id: &str, count: u64) -> Result<u64> {
+    let _reserving = RESERVATIONS.lock().unwrap();
+    let state_key = format!("nonce_counter:{}", key_id);
+    let start = match self.get_sync_state(&state_key)? {
+      Some(value) => value.parse::<u64>()?,
+      None => 0,
+    };
+    let Some(end) = start.checked_add(count) else {
+      bail!("The nonce counter for this key is exhausted; rotate the key");
+    };
+    self.update_sync_state(&state_key, &end.to_string())?;
+    self.flush_status_writes()?;
+    Ok(start)
+  }
+}
+
+/// A key's identity for counter bookkeeping, without revealing the key
+pub fn key_id(key: &[u8; 32]) -> String {
+  let mut hasher = Sha256::new();
+  hasher.update(b"lifespan-nonce-key-id\0");
+  hasher.update(key);
+  hex::encode(&hasher.finalize()[..8])
+}
+
+enum Source {
+  Random,
+  Counter {
+    store: Arc<dyn NonceStore>,
+    key_id: String,
+    prefix: [u8; PREFIX_LEN],
+    next: u64,
+    end: u64,
+  },
+}
+
+/// Hands out nonces for one cipher
+pub(super) struct NonceIssuer {
+  source: Source,
+  issued: HashSet<[u8; NONCE_LEN]>,
+  order: VecDeque<[u8; NONCE_LEN]>,
+}
+
+impl NonceIssuer {
+  pub(super) fn random() -> Self {
+    Self::with_source(Source::Random)
+  }
+
+  pub(super) fn counter(store: Arc<dyn NonceStore>, key_id: String) -> Self {
+    let mut prefix = [0u8; PREFIX_LEN];
+    OsRng.fill_bytes(&mut prefix);
+    Self::with_source(Source::Counter {
+      store,
+      key_id,
+      prefix,
+      next: 0,
+      end: 0,
+    })
+  }
+
+  fn with_source(source: Source) -> Self {
+    Self {
+      source,
+      issued: HashSet::new(),
+      order: VecDeque::new(),
+    }
+  }
+
+  /// A nonce this cipher has not used before
+  pub(super) fn next(&mut self) -> Result<[u8; NONCE_LEN]> {
+    let nonce = self.generate()?;
+    self.record(nonce)?;
+    Ok(nonce)
+  }
+
+  fn generate(&mut self) -> Result<[u8; NONCE_LEN]> {
+    let mut nonce = [0u8; NONCE_LEN];
+    match &mut self.source {
+      Source::Random => OsRng.fill_bytes(&mut nonce),
+      Source::Counter { store, key_id, prefix, next, end } => {
+        if *next == *end {
+          let start = store.reserve(key_id, COUNTER_BLOCK)?;
+          if start < *end {
+            bail!("The nonce counter went backwards; refusing to encrypt");
+          }
+          *next = start;
+          *end = start + COUNTER_BLOCK;
+        }
+        nonce[..PREFIX_LEN].copy_from_slice(prefix);
+        nonce[PREFIX_LEN..].copy_from_slice(&next.to_be_bytes());
+        *next += 1;
+      }
+    }
+    Ok(nonce)
+  }
+
+  fn record(&mut self, nonce: [u8; NONCE_LEN]) -> Result<()> {
+    if !self.issued.insert(nonce) {
+      bail!("Nonce reuse detected; refusing to encrypt");
+    }
+    self.order.push_back(nonce);
+    if self.order.len() > NONCE_HISTORY {
+      if let Some(oldest) = self.order.pop_front() {
+        self.issued.remove(&oldest);
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  /// Counter storage that can be made to forget, like a restored backup
+  #[derive(Default)]
+  struct MemoryStore {
+    high_water: Mutex<u64>,
+  }
+
+  impl NonceStore for MemoryStore {
+    fn reserve(&self, _key_id: &str, count: u64) -> Result<u64> {
+      let mut high_water = self.high_water.lock().unwrap();
+      let start = *high_water;
+      *high_water += count;
+      Ok(start)
+    }
+  }
+
+  #[test]
+  fn test_counter_nonces_are_sequential_and_reserved_in_blocks() {
+    let store = Arc::new(MemoryStore::default());
+    let mut issuer = NonceIssuer::counter(store.clone(), "key".to_string());
+    let first = issuer.next().unwrap();
+    let second = issuer.next().unwrap();
+    assert_eq!(first[..PREFIX_LEN], second[..PREFIX_LEN]);
+    assert_eq!(u64::from_be_bytes(second[PREFIX_LEN..].try_into().unwrap()), 1);
+    assert_eq!(*store.high_water.lock().unwrap(), COUNTER_BLOCK);
+
+    // A second cipher for the same key continues after the reserved block
+    let mut other = NonceIssuer::counter(store.clone(), "key".to_string());
+    let nonce = other.next().unwrap();
+    assert_eq!(u64::from_be_bytes(nonce[PREFIX_LEN..].try_into().unwrap()), COUNTER_BLOCK);
+  }
+
+  #[test]
+  fn test_counter_going_backwards_is_rejected() {
+    let store = Arc::new(MemoryStore::default());
+    let mut issuer = NonceIssuer::counter(store.clone(), "key".to_string());
+    for _ in 0..COUNTER_BLOCK {
+      issuer.next().unwrap();
+    }
+    *store.high_water.lock().unwrap() = 0;
+    assert!(issuer.next().is_err());
+  }
+
+  #[test]
+  fn test_repeated_nonce_is_rejected() {
+    let mut issuer = NonceIssuer::random();
+    let nonce = issuer.next().unwrap();
+    assert!(issuer.record(nonce).is_err());
+  }
+
+  #[test]
+  fn test_database_reservations_persist() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    let id = key_id(&[7u8; 32]);
+    assert_eq!(db.reserve(&id, 10).unwrap(), 0);
+    assert_eq!(db.reserve(&id, 10).unwrap(), 10);
+    assert_eq!(db.reserve(&key_id(&[8u8; 32]), 10).unwrap(), 0);
+    drop(db);
+
+    let reopened = Database::new(temp_file.path()).unwrap();
+    assert_eq!(reopened.reserve(&id, 10).unwrap(), 20);
+  }
+}
@@ -0,0 +1,141 @@
+//! Recovery codes: the sync key written out for safekeeping.
+//!
+//! A recovery code is the 256-bit sync key plus a 16-bit checksum in
+//! Crockford base32, grouped in fives. It reconstructs the key on any device,
+//! after a reinstall or a forgotten passphrase, without anything stored on
+//! this machine or the server. It is as secret as the key itself. Decoding
+//! ignores case, spaces and dashes, reads I/L as 1 and O as 0, and the
+//! checksum catches typos.
+
+use super::key_store::wrap_with_passphrase;
+use super::SecretKey;
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+/// sync_state key recording when the recovery code was first handed out
+pub const RECOVERY_CODE_STATE_KEY: &str = "recovery_code_created_at";
+
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const CHECKSUM_LEN: usize = 2;
+const GROUP_LEN: usize = 5;
+
+fn checksum(key: &[u8; 32]) -> [u8; CHECKSUM_LEN] {
+  let digest = Sha256::digest(key);
+  [digest[0], digest[1]]
+}
+
+fn symbol_value(symbol: char) -> Option<u8> {
+  let symbol = match symbol.to_ascii_uppercase() {
+    'I' | 'L' => '1',
+    'O' => '0',
+    other => other,
+  };
+  ALPHABET.iter().position(|&c| c as char == symbol).map(|i| i as u8)
+}
+
+/// The recovery code for `sync_key`
+pub fn recovery_code(sync_key: &[u8; 32]) -> Zeroizing<String> {
+  let mut bytes = Zeroizing::new(sync_key.to_vec());
+  bytes.extend_from_slice(&checksum(sync_key));
+
+  let mut symbols = Zeroizing::new(String::new());
+  let (mut buffer, mut bits) = (0u32, 0);
+  for &byte in bytes.iter() {
+    buffer = ((buffer << 8) | byte as u32) & 0xFFFF;
+    bits += 8;
+    while bits >= 5 {
+      bits -= 5;
+      symbols.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+    }
+  }
+  if bits > 0 {
+    symbols.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+  }
+
+  let mut code = Zeroizing::new(String::with_capacity(symbols.len() * 6 / 5));
+  for (i, symbol) in symbols.chars().enumerate() {
+    if i > 0 && i % GROUP_LEN == 0 {
+      code.push('-');
+    }
+    code.push(symbol);
+  }
+  code
+}
+
+/// The sync key a recovery code stands for
+pub fn key_from_recovery_code(code: &str) -> Result<SecretKey> {
+  let mut bytes = Zeroizing::new(Vec::with_capacity(32 + CHECKSUM_LEN));
+  let (mut buffer, mut bits) = (0u32, 0);
+  for symbol in code.chars().filter(|c| !c.is_whitespace() && *c != '-') {
+    let Some(value) = symbol_value(symbol) else {
+      bail!("'{}' can't appear in a recovery code", symbol);
+    };
+    buffer = ((buffer << 5) | value as u32) & 0xFFFF;
+    bits += 5;
+    if bits >= 8 {
+      bits -= 8;
+      bytes.push((buffer >> bits) as u8);
+    }
+  }
+
+  if bytes.len() != 32 + CHECKSUM_LEN {
+    bail!("A recovery code has 55 characters");
+  }
+  let mut key = SecretKey::new([0u8; 32]);
+  key.copy_from_slice(&bytes[..32]);
+  if bytes[32..] != checksum(&key) {
+    bail!("This recovery code has a typo");
+  }
+  Ok(key)
+}
+
+/// The sync key behind `code`, wrapped for `new_passphrase`. Returns the key
+/// and the wrapped key to store under WRAPPED_KEY_STATE_KEY.
+pub fn recover_with_code(code: &str, new_passphrase: &str) -> Result<(SecretKey, String)> {
+  let key = key_from_recovery_code(code)?;
+  let wrapped = wrap_with_passphrase(&key, new_passphrase)?;
+  Ok((key, wrapped))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const KEY: &[u8; 32] = b"test_key_32_bytes_long_123456789";
+
+  #[test]
+  fn test_recovery_code_roundtrip() {
+    let code = recovery_code(KEY);
+    assert_eq!(code.len(), 55 + 10);
+    assert!(code.split('-').all(|group| group.len() <= GROUP_LEN));
+    assert_eq!(*key_from_recovery_code(&code).unwrap(), *KEY);
+
+    // Typed by hand: lower case, spaces instead of dashes, O for 0 and l for 1
+    let typed = code.to_lowercase().replace('-', " ").replace('0', "o").replace('1', "l");
+    assert_eq!(*key_from_recovery_code(&typed).unwrap(), *KEY);
+  }
+
+  #[test]
+  fn test_mistyped_codes_are_rejected() {
+    let code = recovery_code(KEY);
+    let swapped: String = code
+      .chars()
+      .enumerate()
+      .map(|(i, c)| if i == 7 { if c == 'A' { 'B' } else { 'A' } } else { c })
+      .collect();
+    assert!(key_from_recovery_code(&swapped).is_err());
+    assert!(key_from_recovery_code(&code[..code.len() - 3]).is_err());
+    assert!(key_from_recovery_code("UUUUU").is_err());
+  }
+
+  #[test]
+  fn test_recovered_key_unlocks_with_the_new_passphrase() {
+    let code = recovery_code(KEY);
+    let (key, wrapped) = recover_with_code(&code, "a new passphrase").unwrap();
+    assert_eq!(*key, *KEY);
+    let unlocked = super::super::unlock_with_passphrase("a new passphrase", Some(&wrapped)).unwrap();
+    assert_eq!(*unlocked, *KEY);
+    assert!(recover_with_code(&code, "short").is_err());
+  }
+}
@@ -0,0 +1,260 @@
+//! Chunked encryption for files too large to hold in memory (exports,
+//! backups).
+//!
+//! Uses the STREAM construction (aead's `EncryptorBE32`): the input is cut
+//! into CHUNK_SIZE pieces, each sealed with AES-GCM under a nonce made of a
+//! random prefix, a chunk counter and a last-chunk flag. Reordered, dropped or
+//! truncated chunks fail to decrypt, so a stream only decrypts if it is whole.
+//! Layout: STREAM_MAGIC, the 7-byte nonce prefix, then the sealed chunks.
+
+use super::CryptoManager;
+use aes_gcm::aead::stream::{DecryptorBE32, EncryptorBE32};
+use aes_gcm::aead::{generic_array::GenericArray, rand_core::RngCore, OsRng};
+use anyhow::{anyhow, bail, Context, Result};
+use std::fs;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+
+const STREAM_MAGIC: &[u8; 8] = b"LSSTRM01";
+/// 12-byte AES-GCM nonce minus STREAM's 4-byte counter and 1-byte flag
+const NONCE_PREFIX_LEN: usize = 7;
+/// Plaintext per chunk
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+
+fn damaged(_: aes_gcm::aead::Error) -> anyhow::Error {
+  anyhow!("Decryption failed: the stream is damaged, truncated or was encrypted with another key")
+}
+
+/// Fill `buf` as far as the reader allows; returns the bytes read
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+  let mut filled = 0;
+  while filled < buf.len() {
+    match reader.read(&mut buf[filled..]) {
+      Ok(0) => break,
+      Ok(n) => filled += n,
+      Err(e) if e.kind() == ErrorKind::Interrupted => {}
+      Err(e) => return Err(e.into()),
+    }
+  }
+  Ok(filled)
+}
+
+/// Reads fixed-size chunks, knowing whether each one is the last
+struct Chunks<R> {
+  reader: R,
+  chunk_size: usize,
+  /// First byte of the next chunk, read to find out whether one follows
+  peeked: Option<u8>,
+}
+
+impl<R: Read> Chunks<R> {
+  fn new(reader: R, chunk_size: usize) -> Self {
+    Self {
+      reader,
+      chunk_size,
+      peeked: None,
+    }
+  }
+
+  /// The next chunk into `buf`; returns its length and whether it is the last
+  fn read_chunk(&mut self, buf: &mut Vec<u8>) -> Result<(usize, bool)> {
+    buf.resize(self.chunk_size, 0);
+    let start = match self.peeked.take() {
+      Some(byte) => {
+        buf[0] = byte;
+        1
+      }
+      None => 0,
+    };
+    let len = start + read_full(&mut self.reader, &mut buf[start..])?;
+    if len < self.chunk_size {
+      buf.truncate(len);
+      return Ok((len, true));
+    }
+
+    let mut next = [0u8; 1];
+    let last = read_full(&mut self.reader, &mut next)? == 0;
+    if !last {
+      self.peeked = Some(next[0]);
+    }
+    Ok((len, last))
+  }
+}
+
+impl CryptoManager {
+  /// Encrypt everything `reader` yields into `writer`, one chunk at a time.
+  /// Returns the number of plaintext bytes.
+  pub fn encrypt_stream(&self, reader: impl Read, mut writer: impl Write) -> Result<u64> {
+    let mut prefix = [0u8; NONCE_PREFIX_LEN];
+    OsRng.fill_bytes(&mut prefix);
+    writer.write_all(STREAM_MAGIC)?;
+    writer.write_all(&prefix)?;
+
+    let mut encryptor = EncryptorBE32::from_aead(self.cipher.clone(), GenericArray::from_slice(&prefix));
+    let mut chunks = Chunks::new(reader, CHUNK_SIZE);
+    let mut buf = Vec::with_capacity(CHUNK_SIZE);
+    let mut total = 0u64;
+    loop {
+      let (len, last) = chunks.read_chunk(&mut buf)?;
+      total += len as u64;
+      if last {
+        let sealed = encryptor
+          .encrypt_last(buf.as_slice())
+          .map_err(|e| anyhow!("Encryption failed: {}", e))?;
+        writer.write_all(&sealed)?;
+        break;
+      }
+      let sealed = encryptor
+        .encrypt_next(buf.as_slice())
+        .map_err(|e| anyhow!("Encryption failed: {}", e))?;
+      writer.write_all(&sealed)?;
+    }
+
+    writer.flush()?;
+    Ok(total)
+  }
+
+  /// Decrypt a stream from `encrypt_stream` into `writer`. Fails if any chunk
+  /// was altered, reordered or cut off; plaintext of the chunks before the
+  /// failure may already have been written. Returns the plaintext bytes.
+  pub fn decrypt_stream(&self, mut reader: impl Read, mut writer: impl Write) -> Result<u64> {
+    let mut header = [0u8; STREAM_MAGIC.len() + NONCE_PREFIX_LEN];
+    if read_full(&mut reader, &mut header)? < header.len() || &header[..STREAM_MAGIC.len()] != STREAM_MAGIC {
+      bail!("Not an encrypted stream");
+    }
+
+    let prefix = &header[STREAM_MAGIC.len()..];
+    let mut decryptor = DecryptorBE32::from_aead(self.cipher.clone(), GenericArray::from_slice(prefix));
+    let mut chunks = Chunks::new(reader, CHUNK_SIZE + TAG_LEN);
+    let mut buf = Vec::with_capacity(CHUNK_SIZE + TAG_LEN);
+    let mut total = 0u64;
+    loop {
+      let (_, last) = chunks.read_chunk(&mut buf)?;
+      if last {
+        let plaintext = decryptor.decrypt_last(buf.as_slice()).map_err(damaged)?;
+        writer.write_all(&plaintext)?;
+        total += plaintext.len() as u64;
+        break;
+      }
+      let plaintext = decryptor.decrypt_next(buf.as_slice()).map_err(damaged)?;
+      writer.write_all(&plaintext)?;
+      total += plaintext.len() as u64;
+    }
+
+    writer.flush()?;
+    Ok(total)
+  }
+
+  /// Encrypt the file at `source` into `target`; `target` only appears once complete
+  pub fn encrypt_file(&self, source: &Path, target: &Path) -> Result<u64> {
+    let reader = BufReader::new(fs::File::open(source).with_context(|| format!("Failed to open {}", source.display()))?);
+    write_atomically(target, |writer| self.encrypt_stream(reader, writer))
+  }
+
+  /// Decrypt the file at `source` into `target`; `target` only appears once complete
+  pub fn decrypt_file(&self, source: &Path, target: &Path) -> Result<u64> {
+    let reader = BufReader::new(fs::File::open(source).with_context(|| format!("Failed to open {}", source.display()))?);
+    write_atomically(target, |writer| self.decrypt_stream(reader, writer))
+  }
+}
+
+/// Write `target` through a temporary file that replaces it only on success
+fn write_atomically(target: &Path, write: impl FnOnce(&mut BufWriter<fs::File>) -> Result<u64>) -> Result<u64> {
+  let mut tmp_name = target.as_os_str().to_owned();
+  tmp_name.push(".tmp");
+  let tmp_path = Path::new(&tmp_name);
+
+  let result: Result<u64> = (|| {
+    let mut writer = BufWriter::new(fs::File::create(tmp_path)?);
+    let written = write(&mut writer)?;
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(written)
+  })();
+  match result {
+    Ok(written) => {
+      fs::rename(tmp_path, target)?;
+      Ok(written)
+    }
+    Err(e) => {
+      let _ = fs::remove_file(tmp_path);
+      Err(e)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::TempDir;
+
+  fn crypto() -> CryptoManager {
+    CryptoManager::new(&[3u8; 32]).unwrap()
+  }
+
+  fn roundtrip(len: usize) {
+    let plaintext: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+    let mut sealed = Vec::new();
+    assert_eq!(crypto().encrypt_stream(plaintext.as_slice(), &mut sealed).unwrap(), len as u64);
+
+    let mut opened = Vec::new();
+    assert_eq!(crypto().decrypt_stream(sealed.as_slice(), &mut opened).unwrap(), len as u64);
+    assert_eq!(opened, plaintext);
+  }
+
+  #[test]
+  fn test_stream_roundtrip_at_chunk_boundaries() {
+    for len in [0, 1, CHUNK_SIZE - 1, CHUNK_SIZE, CHUNK_SIZE + 1, 3 * CHUNK_SIZE, 3 * CHUNK_SIZE + 17] {
+      roundtrip(len);
+    }
+  }
+
+  #[test]
+  fn test_damaged_streams_are_rejected() {
+    let plaintext = vec![0x5Au8; 3 * CHUNK_SIZE + 100];
+    let mut sealed = Vec::new();
+    crypto().encrypt_stream(plaintext.as_slice(), &mut sealed).unwrap();
+    let header = STREAM_MAGIC.len() + NONCE_PREFIX_LEN;
+    let sealed_chunk = CHUNK_SIZE + TAG_LEN;
+
+    // Cut off after a whole chunk: the remaining last chunk isn't marked last
+    let truncated = &sealed[..header + 2 * sealed_chunk];
+    assert!(crypto().decrypt_stream(truncated, Vec::new()).is_err());
+
+    // Two chunks swapped
+    let mut swapped = sealed[..header].to_vec();
+    swapped.extend_from_slice(&sealed[header + sealed_chunk..header + 2 * sealed_chunk]);
+    swapped.extend_from_slice(&sealed[header..header + sealed_chunk]);
+    swapped.extend_from_slice(&sealed[header + 2 * sealed_chunk..]);
+    assert!(crypto().decrypt_stream(swapped.as_slice(), Vec::new()).is_err());
+
+    let mut flipped = sealed.clone();
+    flipped[header + 10] ^= 1;
+    assert!(crypto().decrypt_stream(flipped.as_slice(), Vec::new()).is_err());
+
+    let other = CryptoManager::new(&[4u8; 32]).unwrap();
+    assert!(other.decrypt_stream(sealed.as_slice(), Vec::new()).is_err());
+    assert!(crypto().decrypt_stream(&b"not a stream"[..], Vec::new()).is_err());
+  }
+
+  #[test]
+  fn test_encrypt_and_decrypt_files() {
+    let dir = TempDir::new().unwrap();
+    let source = dir.path().join("backup.db");
+    let sealed = dir.path().join("backup.db.enc");
+    let opened = dir.path().join("restored.db");
+    let contents: Vec<u8> = (0..200_000u32).flat_map(|i| i.to_le_bytes()).collect();
+    fs::write(&source, &contents).unwrap();
+
+    crypto().encrypt_file(&source, &sealed).unwrap();
+    assert_ne!(fs::read(&sealed).unwrap()[STREAM_MAGIC.len()..], contents[..]);
+    crypto().decrypt_file(&sealed, &opened).unwrap();
+    assert_eq!(fs::read(&opened).unwrap(), contents);
+
+    // A failed decryption leaves nothing behind
+    let wrong = dir.path().join("wrong.db");
+    assert!(CryptoManager::new(&[4u8; 32]).unwrap().decrypt_file(&sealed, &wrong).is_err());
+    assert!(!wrong.exists());
+    assert!(!dir.path().join("wrong.db.tmp").exists());
+  }
+}
@@ -0,0 +1,212 @@
+//! Parquet export of local events for analysis in Python, DuckDB and the like.
+//!
+//! One row per event, in start-time order, with the columns of
+//! `event_schema`. Events are read and written in chunks, each chunk one row
+//! group, so exporting millions of rows doesn't hold them all in memory. The
+//! file is written next to the target and renamed into place when complete.
+
+use crate::database::{Database, StoredEvent};
+use anyhow::Result;
+use arrow_array::{ArrayRef, BooleanArray, Int32Array, RecordBatch, StringArray, TimestampMillisecondArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, Utc};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Events per chunk (and row group)
+const CHUNK_ROWS: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ParquetExport {
+  pub path: PathBuf,
+  pub row_count: usize,
+  pub row_groups: usize,
+}
+
+/// Column schema of exported files. Timestamps are UTC milliseconds; local
+/// time is `timestamp` plus `utc_offset_minutes` where that is known.
+pub fn event_schema() -> SchemaRef {
+  let text = |name: &str, nullable: bool| Field::new(name, DataType::Utf8, nullable);
+  Arc::new(Schema::new(vec![
+    text("id", false),
+    text("event_type", false),
+    Field::new("timestamp", DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())), false),
+    Field::new("duration_seconds", DataType::Int32, false),
+    text("app_name", false),
+    text("window_title", true),
+    Field::new("utc_offset_minutes", DataType::Int32, true),
+    Field::new("remote_session", DataType::Boolean, false),
+    text("url_domain", true),
+    Field::new("fullscreen", DataType::Boolean, false),
+    text("document", true),
+    text("project", true),
+    text("virtual_desktop", true),
+    text("process_path", true),
+  ]))
+}
+
+fn record_batch(schema: &SchemaRef, events: &[StoredEvent]) -> Result<RecordBatch> {
+  let text = |value: fn(&StoredEvent) -> Option<&str>| -> ArrayRef {
+    Arc::new(events.iter().map(value).collect::<StringArray>())
+  };
+
+  let columns: Vec<ArrayRef> = vec![
+    text(|e| Some(e.id.as_str())),
+    text(|e| Some(e.event_type.as_str())),
+    Arc::new(
+      events
+        .iter()
+        .map(|e| Some(e.timestamp.timestamp_millis()))
+        .collect::<TimestampMillisecondArray>()
+        .with_timezone("UTC"),
+    ),
+    Arc::new(events.iter().map(|e| Some(e.duration)).collect::<Int32Array>()),
+    text(|e| Some(e.app_name.as_str())),
+    text(|e| e.window_title.as_deref()),
+    Arc::new(events.iter().map(|e| e.utc_offset_minutes).collect::<Int32Array>()),
+    Arc::new(events.iter().map(|e| Some(e.remote_session)).collect::<BooleanArray>()),
+    text(|e| e.url_domain.as_deref()),
+    Arc::new(events.iter().map(|e| Some(e.fullscreen)).collect::<BooleanArray>()),
+    text(|e| e.document.as_deref()),
+    text(|e| e.project.as_deref()),
+    text(|e| e.virtual_desktop.as_deref()),
+    text(|e| e.process_path.as_deref()),
+  ];
+  Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+/// Export events starting in [start, end) to a Parquet file at `path`
+pub fn export_events_parquet(
+  db: &Database,
+  path: &Path,
+  start: Option<DateTime<Utc>>,
+  end: Option<DateTime<Utc>>,
+) -> Result<ParquetExport> {
+  write_events_parquet(db, path, start, end, CHUNK_ROWS)
+}
+
+fn write_events_parquet(
+  db: &Database,
+  path: &Path,
+  start: Option<DateTime<Utc>>,
+  end: Option<DateTime<Utc>>,
+  chunk_rows: usize,
+) -> Result<ParquetExport> {
+  let start = start.unwrap_or(DateTime::<Utc>::MIN_UTC);
+  let end = end.unwrap_or(DateTime::<Utc>::MAX_UTC);
+  let schema = event_schema();
+  let properties = WriterProperties::builder()
+    .set_compression(Compression::ZSTD(ZstdLevel::default()))
+    .set_max_row_group_size(chunk_rows)
+    .build();
+
+  let tmp_path = path.with_extension("parquet.tmp");
+  let mut writer = ArrowWriter::try_new(fs::File::create(&tmp_path)?, schema.clone(), Some(properties))?;
+  let mut row_count = 0;
+  let mut row_groups = 0;
+  let mut last: Option<StoredEvent> = None;
+
+  loop {
+    let events = db.get_events_page(start, end, last.as_ref(), chunk_rows)?;
+    if events.is_empty() {
+      break;
+    }
+
+    writer.write(&record_batch(&schema, &events)?)?;
+    writer.flush()?;
+    row_count += events.len();
+    row_groups += 1;
+    last = events.into_iter().last();
+  }
+
+  writer.close()?;
+  fs::rename(&tmp_path, path)?;
+  Ok(ParquetExport {
+    path: path.to_path_buf(),
+    row_count,
+    row_groups,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::{NewEvent, StorageBackend};
+  use arrow_array::Array;
+  use chrono::{Duration, TimeZone};
+  use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+  use tempfile::{NamedTempFile, TempDir};
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn app_event(app: &str, title: Option<&str>, timestamp: DateTime<Utc>) -> NewEvent {
+    NewEvent {
+      event_type: "app_usage".to_string(),
+      timestamp,
+      duration: 60,
+      app_name: app.to_string(),
+      window_title: title.map(str::to_string),
+      url_domain: None,
+      remote_session: false,
+    }
+  }
+
+  #[test]
+  fn test_export_in_chunks_reads_back() {
+    let (db, _temp) = create_test_db();
+    let at = Utc.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+    let events: Vec<NewEvent> = (0..5)
+      .map(|i| app_event("code.exe", (i % 2 == 0).then_some("main.rs"), at + Duration::minutes(i)))
+      .collect();
+    db.insert_events(&events).unwrap();
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("events.parquet");
+    let export = write_events_parquet(&db, &path, None, None, 2).unwrap();
+    assert_eq!(export.row_count, 5);
+    assert_eq!(export.row_groups, 3);
+    assert!(!path.with_extension("parquet.tmp").exists());
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new(fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(reader.metadata().num_row_groups(), 3);
+    assert_eq!(reader.schema().fields(), event_schema().fields());
+
+    let batches = reader.build().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(batches.iter().map(RecordBatch::num_rows).sum::<usize>(), 5);
+    let timestamps = batches[0]
+      .column_by_name("timestamp")
+      .unwrap()
+      .as_any()
+      .downcast_ref::<TimestampMillisecondArray>()
+      .unwrap();
+    assert_eq!(timestamps.value(0), at.timestamp_millis());
+    let titles = batches[0].column_by_name("window_title").unwrap();
+    assert_eq!(titles.null_count(), 1);
+  }
+
+  #[test]
+  fn test_export_range() {
+    let (db, _temp) = create_test_db();
+    let at = Utc.with_ymd_and_hms(2024, 5, 6, 9, 0, 0).unwrap();
+    db.insert_events(&[app_event("a", None, at), app_event("b", None, at + Duration::days(1))])
+      .unwrap();
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("events.parquet");
+    let export = export_events_parquet(&db, &path, Some(at + Duration::hours(1)), None).unwrap();
+    assert_eq!(export.row_count, 1);
+
+    let empty = export_events_parquet(&db, &path, None, Some(at)).unwrap();
+    assert_eq!(empty.row_count, 0);
+    assert!(path.exists());
+  }
+}
@@ -0,0 +1,269 @@
+//! Goal evaluation: how much of a category's time budget is left today.
+//!
+//! Daily goals allow `limit` per day; with carry-over, yesterday's unused
+//! time is added to today's budget. Weekly goals allow `limit` per week
+//! (Monday to Sunday, local time); with carry-over, whatever is left of the
+//! week is spread evenly over the remaining days, so under-spending early in
+//! the week raises the daily budget and over-spending lowers it. Days are
+//! local calendar days in the timezone each event was recorded in.
+
+use crate::database::{CategoryRules, Database, Goal, GoalScope, StorageBackend, StoredEvent};
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GoalStatus {
+  #[serde(flatten)]
+  pub goal: Goal,
+  /// First and last day (inclusive) of the goal's period
+  pub period_start: NaiveDate,
+  pub period_end: NaiveDate,
+  pub used_today_seconds: i64,
+  pub budget_today_seconds: i64,
+  /// Negative when today's budget is exceeded
+  pub remaining_today_seconds: i64,
+  pub period_used_seconds: i64,
+  pub period_limit_seconds: i64,
+  pub over_budget: bool,
+}
+
+/// Monday of the week containing `day`
+fn week_start(day: NaiveDate) -> NaiveDate {
+  day - Duration::days(day.weekday().num_days_from_monday() as i64)
+}
+
+/// Evaluate one goal for `today` given seconds used per local day
+pub fn evaluate(goal: &Goal, usage: &BTreeMap<NaiveDate, i64>, today: NaiveDate) -> GoalStatus {
+  let used_on = |day: NaiveDate| usage.get(&day).copied().unwrap_or(0);
+  let limit = goal.limit_minutes as i64 * 60;
+  let used_today = used_on(today);
+
+  let (period_start, period_end, budget_today, period_used, period_limit) = match goal.scope {
+    GoalScope::Daily => {
+      let carried = if goal.carry_over {
+        (limit - used_on(today - Duration::days(1))).max(0)
+      } else {
+        0
+      };
+      let budget = limit + carried;
+      (today, today, budget, used_today, budget)
+    }
+    GoalScope::Weekly => {
+      let start = week_start(today);
+      let used_before_today: i64 = usage.range(start..today).map(|(_, seconds)| seconds).sum();
+      let days_left = 7 - today.weekday().num_days_from_monday() as i64;
+      let budget = if goal.carry_over {
+        (limit - used_before_today).max(0) / days_left
+      } else {
+        limit / 7
+      };
+      (start, start + Duration::days(6), budget, used_before_today + used_today, limit)
+    }
+  };
+
+  GoalStatus {
+    goal: goal.clone(),
+    period_start,
+    period_end,
+    used_today_seconds: used_today,
+    budget_today_seconds: budget_today,
+    remaining_today_seconds: budget_today - used_today,
+    period_used_seconds: period_used,
+    period_limit_seconds: period_limit,
+    over_budget: used_today > budget_today || period_used > period_limit,
+  }
+}
+
+/// Seconds of `category` app usage per local day
+pub fn usage_by_day(events: &[StoredEvent], rules: &CategoryRules, category: &str) -> BTreeMap<NaiveDate, i64> {
+  let mut usage = BTreeMap::new();
+  for event in events.iter().filter(|e| e.event_type == "app_usage") {
+    if rules.categorize_with_path(&event.app_name, event.process_path.as_deref()) == category {
+      *usage.entry(event.local_date()).or_insert(0) += event.duration as i64;
+    }
+  }
+  usage
+}
+
+/// Status of every goal as of `now`
+pub fn goal_statuses(db: &Database, now: DateTime<Utc>) -> Result<Vec<GoalStatus>> {
+  let goals = db.get_goals()?;
+  if goals.is_empty() {
+    return Ok(Vec::new());
+  }
+
+  let today = now.with_timezone(&Local).date_naive();
+  // Daily carry-over on a Monday looks back into the previous week
+  let first_day = week_start(today).min(today - Duration::days(1));
+  // Pad by a day on either side; events are bucketed by their own local date
+  let start = first_day.and_hms_opt(0, 0, 0).unwrap().and_utc() - Duration::days(1);
+  let events = db.events_between(start, now + Duration::days(1))?;
+  let rules = db.get_category_rules()?;
+
+  let mut usage_cache: HashMap<&str, BTreeMap<NaiveDate, i64>> = HashMap::new();
+  Ok(
+    goals
+      .iter()
+      .map(|goal| {
+        let usage = usage_cache
+          .entry(goal.category.as_str())
+          .or_insert_with(|| usage_by_day(&events, &rules, &goal.category));
+        evaluate(goal, usage, today)
+      })
+      .collect(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::TimeZone;
+
+  fn goal(scope: GoalScope, limit_minutes: u32, carry_over: bool) -> Goal {
+    Goal {
+      category: "entertainment".to_string(),
+      scope,
+      limit_minutes,
+      carry_over,
+    }
+  }
+
+  fn day(d: u32) -> NaiveDate {
+    // June 2024: the 10th is a Monday, the 16th a Sunday
+    NaiveDate::from_ymd_opt(2024, 6, d).unwrap()
+  }
+
+  fn usage(days: &[(u32, i64)]) -> BTreeMap<NaiveDate, i64> {
+    days.iter().map(|(d, minutes)| (day(*d), minutes * 60)).collect()
+  }
+
+  #[test]
+  fn test_daily_without_carry_over() {
+    let status = evaluate(&goal(GoalScope::Daily, 60, false), &usage(&[(11, 0), (12, 20)]), day(12));
+
+    assert_eq!(status.budget_today_seconds, 3600);
+    assert_eq!(status.remaining_today_seconds, 2400);
+    assert_eq!(status.period_start, day(12));
+    assert!(!status.over_budget);
+  }
+
+  #[test]
+  fn test_daily_carry_over_from_yesterday() {
+    // 20 minutes unused yesterday
+    let status = evaluate(&goal(GoalScope::Daily, 60, true), &usage(&[(11, 40), (12, 70)]), day(12));
+
+    assert_eq!(status.budget_today_seconds, 80 * 60);
+    assert_eq!(status.remaining_today_seconds, 10 * 60);
+    assert!(!status.over_budget);
+
+    // Over-spending yesterday never shrinks today's budget
+    let status = evaluate(&goal(GoalScope::Daily, 60, true), &usage(&[(11, 90), (12, 70)]), day(12));
+    assert_eq!(status.budget_today_seconds, 3600);
+    assert!(status.over_budget);
+  }
+
+  #[test]
+  fn test_daily_carry_over_crosses_week_boundary() {
+    // Monday carries Sunday's unused time
+    let status = evaluate(&goal(GoalScope::Daily, 60, true), &usage(&[(9, 30)]), day(10));
+    assert_eq!(status.budget_today_seconds, 90 * 60);
+  }
+
+  #[test]
+  fn test_weekly_spreads_remaining_budget() {
+    // 7h per week; Mon-Wed used 30 min in total, so Thursday gets (420 - 30) / 4
+    let weekly = goal(GoalScope::Weekly, 420, true);
+    let status = evaluate(&weekly, &usage(&[(10, 10), (11, 10), (12, 10), (13, 5)]), day(13));
+
+    assert_eq!(status.period_start, day(10));
+    assert_eq!(status.period_end, day(16));
+    assert_eq!(status.budget_today_seconds, (390 * 60) / 4);
+    assert_eq!(status.period_used_seconds, 35 * 60);
+    assert_eq!(status.period_limit_seconds, 420 * 60);
+    assert!(!status.over_budget);
+
+    // Without carry-over every day gets a flat seventh
+    let flat = evaluate(&goal(GoalScope::Weekly, 420, false), &usage(&[(10, 10)]), day(13));
+    assert_eq!(flat.budget_today_seconds, 3600);
+  }
+
+  #[test]
+  fn test_weekly_over_spend_shrinks_budget_to_zero() {
+    let weekly = goal(GoalScope::Weekly, 120, true);
+    let status = evaluate(&weekly, &usage(&[(10, 100), (11, 30), (12, 1)]), day(12));
+
+    assert_eq!(status.budget_today_seconds, 0);
+    assert_eq!(status.remaining_today_seconds, -60);
+    assert!(status.over_budget);
+  }
+
+  #[test]
+  fn test_weekly_resets_at_week_boundary() {
+    // Last week's Sunday usage doesn't count against this week
+    let weekly = goal(GoalScope::Weekly, 70, true);
+    let status = evaluate(&weekly, &usage(&[(9, 500), (10, 5)]), day(10));
+
+    assert_eq!(status.period_start, day(10));
+    assert_eq!(status.period_used_seconds, 5 * 60);
+    assert_eq!(status.budget_today_seconds, 600);
+
+    // Sunday is the last day of its week: everything left is today's budget
+    let sunday = evaluate(&weekly, &usage(&[(10, 20), (16, 0)]), day(16));
+    assert_eq!(sunday.budget_today_seconds, 50 * 60);
+  }
+
+  fn event_at(timestamp: DateTime<Utc>, utc_offset_minutes: i32, minutes: i32) -> StoredEvent {
+    StoredEvent {
+      id: uuid::Uuid::new_v4().to_string(),
+      event_type: "app_usage".to_string(),
+      timestamp,
+      duration: minutes * 60,
+      app_name: "spotify.exe".to_string(),
+      window_title: None,
+      utc_offset_minutes: Some(utc_offset_minutes),
+      remote_session: false,
+      url_domain: None,
+      fullscreen: false,
+      document: None,
+      project: None,
+      virtual_desktop: None,
+      process_path: None,
+      category: None,
+      session_id: None,
+    }
+  }
+
+  #[test]
+  fn test_usage_is_bucketed_by_recorded_timezone() {
+    let mut rules = CategoryRules::default();
+    rules.apply("spotify".to_string(), Some("entertainment".to_string()));
+
+    // Sunday 20:00 UTC is already Monday in Tokyo (+09:00) but still Sunday in New York (-04:00)
+    let sunday_evening = Utc.with_ymd_and_hms(2024, 6, 9, 20, 0, 0).unwrap();
+    let events = vec![event_at(sunday_evening, 540, 30), event_at(sunday_evening, -240, 15)];
+
+    let usage = usage_by_day(&events, &rules, "entertainment");
+    assert_eq!(usage.get(&day(10)), Some(&(30 * 60)));
+    assert_eq!(usage.get(&day(9)), Some(&(15 * 60)));
+
+    // The Tokyo session lands in the new week
+    let weekly = evaluate(&goal(GoalScope::Weekly, 70, true), &usage, day(10));
+    assert_eq!(weekly.period_used_seconds, 30 * 60);
+  }
+
+  #[test]
+  fn test_other_categories_and_event_types_ignored() {
+    let mut rules = CategoryRules::default();
+    rules.apply("spotify".to_string(), Some("entertainment".to_string()));
+
+    let at = Utc.with_ymd_and_hms(2024, 6, 12, 12, 0, 0).unwrap();
+    let mut afk = event_at(at, 0, 10);
+    afk.event_type = "afk".to_string();
+    let mut editor = event_at(at, 0, 10);
+    editor.app_name = "code.exe".to_string();
+
+    assert!(usage_by_day(&[afk, editor], &rules, "entertainment").is_empty());
+  }
+}
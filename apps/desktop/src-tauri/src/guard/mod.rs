@@ -0,0 +1,246 @@
+//! Authorization and rate limiting for Tauri commands.
+//!
+//! Guarded commands call `CommandGuard::check` first. The policy table below
+//! decides what each one needs: destructive commands need the app lock to
+//! have been unlocked within UNLOCK_WINDOW, and expensive or high-frequency
+//! commands are limited to a number of calls per sliding window. Commands not
+//! in the table are unrestricted.
+//!
+//! The app lock is an optional PIN, stored as an Argon2 hash in settings.
+//! Without a PIN there is nothing to unlock, so only rate limits apply.
+
+use crate::database::Database;
+use anyhow::{anyhow, bail, Result};
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const APP_LOCK_PIN_SETTING: &str = "app_lock_pin_hash";
+
+/// How long an unlock authorizes destructive commands
+const UNLOCK_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+const MIN_PIN_LENGTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandPolicy {
+  /// Needs a recent unlock when an app lock PIN is set
+  RequiresUnlock,
+  /// At most `max_calls` within any `per` window
+  RateLimited { max_calls: usize, per: Duration },
+}
+
+const POLICIES: &[(&str, CommandPolicy)] = &[
+  ("archive_events_before", CommandPolicy::RequiresUnlock),
+  ("delete_events", CommandPolicy::RequiresUnlock),
+  ("delete_events_matching", CommandPolicy::RequiresUnlock),
+  ("purge_events", CommandPolicy::RequiresUnlock),
+  ("set_retention_policy", CommandPolicy::RequiresUnlock),
+  ("restore_database", CommandPolicy::RequiresUnlock),
+  ("restore_settings_version", CommandPolicy::RequiresUnlock),
+  ("create_api_token", CommandPolicy::RequiresUnlock),
+  ("revoke_api_token", CommandPolicy::RequiresUnlock),
+  ("set_event_recording_enabled", CommandPolicy::RequiresUnlock),
+  ("set_app_lock_pin", CommandPolicy::RequiresUnlock),
+  ("get_recovery_code", CommandPolicy::RequiresUnlock),
+  ("forget_sync_key", CommandPolicy::RequiresUnlock),
+  ("set_anonymized_storage", CommandPolicy::RequiresUnlock),
+  ("set_server_config", CommandPolicy::RequiresUnlock),
+  ("unlock_app", CommandPolicy::RateLimited { max_calls: 5, per: Duration::from_secs(60) }),
+  ("change_passphrase", CommandPolicy::RateLimited { max_calls: 5, per: Duration::from_secs(60) }),
+  ("recover_sync_key", CommandPolicy::RateLimited { max_calls: 5, per: Duration::from_secs(60) }),
+  ("sync_now", CommandPolicy::RateLimited { max_calls: 6, per: Duration::from_secs(60) }),
+  ("self_test", CommandPolicy::RateLimited { max_calls: 2, per: Duration::from_secs(60) }),
+];
+
+pub fn policy_for(command: &str) -> Option<CommandPolicy> {
+  POLICIES.iter().find(|(name, _)| *name == command).map(|(_, policy)| *policy)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct AppLockStatus {
+  pub pin_set: bool,
+  /// Unlocked within UNLOCK_WINDOW
+  pub unlocked: bool,
+}
+
+#[derive(Default)]
+pub struct CommandGuard {
+  unlocked_at: Mutex<Option<Instant>>,
+  calls: Mutex<HashMap<&'static str, VecDeque<Instant>>>,
+}
+
+impl CommandGuard {
+  /// Whether `command` may run now; the error is shown to the user
+  pub fn check(&self, db: &Database, command: &'static str) -> Result<()> {
+    self.check_at(db, command, Instant::now())
+  }
+
+  fn check_at(&self, db: &Database, command: &'static str, now: Instant) -> Result<()> {
+    match policy_for(command) {
+      None => Ok(()),
+      Some(CommandPolicy::RequiresUnlock) => {
+        if db.get_setting(APP_LOCK_PIN_SETTING)?.is_none() || self.is_unlocked_at(now) {
+          Ok(())
+        } else {
+          bail!("Unlock the app to run {}", command)
+        }
+      }
+      Some(CommandPolicy::RateLimited { max_calls, per }) => {
+        let mut calls = self.calls.lock().unwrap();
+        let recent = calls.entry(command).or_default();
+        while recent.front().is_some_and(|at| now.duration_since(*at) >= per) {
+          recent.pop_front();
+        }
+        if recent.len() >= max_calls {
+          bail!("{} is rate limited, try again shortly", command);
+        }
+        recent.push_back(now);
+        Ok(())
+      }
+    }
+  }
+
+  pub fn status(&self, db: &Database) -> Result<AppLockStatus> {
+    Ok(AppLockStatus {
+      pin_set: db.get_setting(APP_LOCK_PIN_SETTING)?.is_some(),
+      unlocked: self.is_unlocked_at(Instant::now()),
+    })
+  }
+
+  fn is_unlocked_at(&self, now: Instant) -> bool {
+    self
+      .unlocked_at
+      .lock()
+      .unwrap()
+      .is_some_and(|at| now.duration_since(at) < UNLOCK_WINDOW)
+  }
+
+  /// Verify the PIN and open the unlock window
+  pub fn unlock(&self, db: &Database, pin: &str) -> Result<()> {
+    self.unlock_at(db, pin, Instant::now())
+  }
+
+  fn unlock_at(&self, db: &Database, pin: &str, now: Instant) -> Result<()> {
+    let Some(stored) = db.get_setting(APP_LOCK_PIN_SETTING)? else {
+      bail!("No app lock PIN is set");
+    };
+    let hash = PasswordHash::new(&stored).map_err(|e| anyhow!("Stored PIN hash is invalid: {}", e))?;
+    if Argon2::default().verify_password(pin.as_bytes(), &hash).is_err() {
+      bail!("Incorrect PIN");
+    }
+    *self.unlocked_at.lock().unwrap() = Some(now);
+    Ok(())
+  }
+
+  /// End the unlock window early
+  pub fn lock(&self) {
+    *self.unlocked_at.lock().unwrap() = None;
+  }
+
+  /// Set, change or (with None) remove the app lock PIN
+  pub fn set_pin(&self, db: &Database, pin: Option<&str>) -> Result<()> {
+    match pin {
+      Some(pin) => {
+        if pin.chars().count() < MIN_PIN_LENGTH {
+          bail!("PIN must be at least {} characters", MIN_PIN_LENGTH);
+        }
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+          .hash_password(pin.as_bytes(), &salt)
+          .map_err(|e| anyhow!("Failed to hash PIN: {}", e))?;
+        db.set_setting(APP_LOCK_PIN_SETTING, &hash.to_string())?;
+      }
+      None => {
+        db.delete_setting(APP_LOCK_PIN_SETTING)?;
+      }
+    }
+    self.lock();
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  #[test]
+  fn test_policy_table() {
+    assert_eq!(policy_for("restore_settings_version"), Some(CommandPolicy::RequiresUnlock));
+    assert_eq!(policy_for("revoke_api_token"), Some(CommandPolicy::RequiresUnlock));
+    assert_eq!(policy_for("set_event_recording_enabled"), Some(CommandPolicy::RequiresUnlock));
+    assert_eq!(policy_for("set_anonymized_storage"), Some(CommandPolicy::RequiresUnlock));
+    assert_eq!(policy_for("forget_sync_key"), Some(CommandPolicy::RequiresUnlock));
+    assert_eq!(policy_for("set_server_config"), Some(CommandPolicy::RequiresUnlock));
+    assert!(matches!(policy_for("sync_now"), Some(CommandPolicy::RateLimited { .. })));
+    assert_eq!(policy_for("get_status"), None);
+  }
+
+  #[test]
+  fn test_destructive_commands_need_recent_unlock() {
+    let (db, _temp) = create_test_db();
+    let guard = CommandGuard::default();
+    let now = Instant::now();
+
+    // No PIN set: nothing to unlock
+    assert!(guard.check_at(&db, "archive_events_before", now).is_ok());
+
+    guard.set_pin(&db, Some("2468")).unwrap();
+    assert!(guard.check_at(&db, "archive_events_before", now).is_err());
+
+    assert!(guard.unlock_at(&db, "1357", now).is_err());
+    assert!(guard.check_at(&db, "archive_events_before", now).is_err());
+
+    guard.unlock_at(&db, "2468", now).unwrap();
+    assert!(guard.check_at(&db, "archive_events_before", now + Duration::from_secs(60)).is_ok());
+    assert!(guard.check_at(&db, "archive_events_before", now + UNLOCK_WINDOW).is_err());
+
+    guard.unlock_at(&db, "2468", now).unwrap();
+    guard.lock();
+    assert!(guard.check_at(&db, "restore_settings_version", now).is_err());
+  }
+
+  #[test]
+  fn test_rate_limit_uses_sliding_window() {
+    let (db, _temp) = create_test_db();
+    let guard = CommandGuard::default();
+    let now = Instant::now();
+
+    for second in 0..6 {
+      assert!(guard.check_at(&db, "sync_now", now + Duration::from_secs(second)).is_ok());
+    }
+    assert!(guard.check_at(&db, "sync_now", now + Duration::from_secs(30)).is_err());
+    // Limits are per command
+    assert!(guard.check_at(&db, "self_test", now + Duration::from_secs(30)).is_ok());
+    // The first call has left the window
+    assert!(guard.check_at(&db, "sync_now", now + Duration::from_secs(60)).is_ok());
+    assert!(guard.check_at(&db, "sync_now", now + Duration::from_secs(60)).is_err());
+  }
+
+  #[test]
+  fn test_pin_management() {
+    let (db, _temp) = create_test_db();
+    let guard = CommandGuard::default();
+
+    assert!(guard.unlock(&db, "2468").is_err());
+    assert!(guard.set_pin(&db, Some("12")).is_err());
+
+    guard.set_pin(&db, Some("2468")).unwrap();
+    let stored = db.get_setting(APP_LOCK_PIN_SETTING).unwrap().unwrap();
+    assert!(!stored.contains("2468"));
+    guard.unlock(&db, "2468").unwrap();
+
+    guard.set_pin(&db, None).unwrap();
+    assert!(db.get_setting(APP_LOCK_PIN_SETTING).unwrap().is_none());
+  }
+}
@@ -0,0 +1,452 @@
+//! Localhost listener for editor plugin heartbeats and shell integration.
+//!
+//! VS Code and JetBrains plugins POST a heartbeat (editor, file, language,
+//! project) to `http://127.0.0.1:<port>/heartbeat` while the user types.
+//! Heartbeats for the same file that arrive within `MERGE_GAP` of each other
+//! extend one open "coding" event; a different file, language or project, or
+//! a longer pause, closes it at the last heartbeat and starts another. Only
+//! the file name is kept, never its path.
+//!
+//! Shell hooks POST to `/terminal` on the same port; see `terminal`.
+//!
+//! Each route is off unless its setting ("editor_heartbeat_listener",
+//! "shell_integration") is "true". Requests must send an API token with the
+//! `heartbeats` scope and be addressed to localhost (see `is_local_api_host`).
+//! Only JSON bodies are accepted, so web pages can't post without a CORS
+//! preflight, which the listener never answers.
+
+mod terminal;
+
+use crate::database::{is_local_api_host, ApiScope, Database};
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tracing::{error, info};
+
+pub use terminal::{ShellPing, TerminalSessions, SHELL_INTEGRATION_SETTING};
+
+/// Setting that enables editor heartbeats ("true"); off by default
+pub const HEARTBEAT_LISTENER_SETTING: &str = "editor_heartbeat_listener";
+pub const HEARTBEAT_PORT_SETTING: &str = "editor_heartbeat_port";
+const DEFAULT_PORT: u16 = 7421;
+
+/// Longest pause between heartbeats that still counts as the same coding stretch
+const MERGE_GAP: chrono::Duration = chrono::Duration::minutes(2);
+
+/// How often a stretch without heartbeats is closed so it can sync
+const STALE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Heartbeat {
+  /// e.g. "vscode", "intellij"
+  pub editor: String,
+  /// Path or name of the file being edited
+  pub file: Option<String>,
+  pub language: Option<String>,
+  pub project: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeartbeatSettings {
+  pub enabled: bool,
+  /// Accept command pings from shell hooks
+  pub terminal: bool,
+  pub port: u16,
+}
+
+impl HeartbeatSettings {
+  pub fn load(db: &Database) -> Result<Self> {
+    let enabled = db
+      .get_setting(HEARTBEAT_LISTENER_SETTING)?
+      .is_some_and(|value| value == "true");
+    let terminal = db
+      .get_setting(SHELL_INTEGRATION_SETTING)?
+      .is_some_and(|value| value == "true");
+    let port = db
+      .get_setting(HEARTBEAT_PORT_SETTING)?
+      .and_then(|value| value.parse().ok())
+      .unwrap_or(DEFAULT_PORT);
+    Ok(Self { enabled, terminal, port })
+  }
+
+  pub fn save(&self, db: &Database) -> Result<()> {
+    db.set_setting(HEARTBEAT_LISTENER_SETTING, if self.enabled { "true" } else { "false" })?;
+    db.set_setting(SHELL_INTEGRATION_SETTING, if self.terminal { "true" } else { "false" })?;
+    db.set_setting(HEARTBEAT_PORT_SETTING, &self.port.to_string())
+  }
+}
+
+/// What makes heartbeats part of the same coding event
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CodingKey {
+  editor: String,
+  file: Option<String>,
+  language: Option<String>,
+  project: Option<String>,
+}
+
+impl CodingKey {
+  fn from_heartbeat(heartbeat: &Heartbeat) -> Option<Self> {
+    let clean = |value: &Option<String>| value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string);
+    let editor = heartbeat.editor.trim();
+    if editor.is_empty() {
+      return None;
+    }
+    Some(Self {
+      editor: editor.to_string(),
+      file: clean(&heartbeat.file).map(|path| path.rsplit(['/', '\\']).next().unwrap_or(&path).to_string()),
+      language: clean(&heartbeat.language),
+      project: clean(&heartbeat.project),
+    })
+  }
+}
+
+struct CodingStretch {
+  event_id: String,
+  key: CodingKey,
+  last_at: DateTime<Utc>,
+}
+
+/// Merges heartbeats into coding events
+pub struct HeartbeatMerger {
+  db: Arc<Database>,
+  current: Mutex<Option<CodingStretch>>,
+}
+
+impl HeartbeatMerger {
+  pub fn new(db: Arc<Database>) -> Self {
+    Self {
+      db,
+      current: Mutex::new(None),
+    }
+  }
+
+  /// Record a heartbeat received at `at`; returns the coding event it belongs to
+  pub fn record(&self, heartbeat: &Heartbeat, at: DateTime<Utc>) -> Result<String> {
+    let key = CodingKey::from_heartbeat(heartbeat).context("Heartbeat has no editor")?;
+    let mut current = self.current.lock().unwrap();
+
+    if let Some(stretch) = current.as_mut() {
+      if stretch.key == key && at - stretch.last_at <= MERGE_GAP {
+        stretch.last_at = at.max(stretch.last_at);
+        return Ok(stretch.event_id.clone());
+      }
+    }
+    if let Some(stretch) = current.take() {
+      self.db.close_event_sync(&stretch.event_id, stretch.last_at)?;
+    }
+
+    let event_id = self.db.open_coding_event_sync(
+      &key.editor,
+      key.language.as_deref(),
+      key.file.as_deref(),
+      key.project.as_deref(),
+      at,
+    )?;
+    *current = Some(CodingStretch {
+      event_id: event_id.clone(),
+      key,
+      last_at: at,
+    });
+    Ok(event_id)
+  }
+
+  /// Close the open coding event if no heartbeat arrived within the merge gap
+  fn close_stale(&self, now: DateTime<Utc>) -> Result<()> {
+    let mut current = self.current.lock().unwrap();
+    if current.as_ref().is_some_and(|stretch| now - stretch.last_at > MERGE_GAP) {
+      let stretch = current.take().unwrap();
+      self.db.close_event_sync(&stretch.event_id, stretch.last_at)?;
+    }
+    Ok(())
+  }
+
+  /// Close the open coding event at its last heartbeat
+  fn flush(&self) -> Result<()> {
+    if let Some(stretch) = self.current.lock().unwrap().take() {
+      self.db.close_event_sync(&stretch.event_id, stretch.last_at)?;
+    }
+    Ok(())
+  }
+}
+
+#[derive(Debug)]
+enum HeartbeatError {
+  Unauthorized,
+  ForeignHost,
+  Invalid(anyhow::Error),
+}
+
+impl IntoResponse for HeartbeatError {
+  fn into_response(self) -> Response {
+    match self {
+      Self::Unauthorized => (StatusCode::UNAUTHORIZED, "Missing or invalid API token").into_response(),
+      Self::ForeignHost => (StatusCode::FORBIDDEN, "The listener only answers requests to localhost").into_response(),
+      Self::Invalid(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+  }
+}
+
+/// Require a request to localhost with a bearer token with the heartbeats scope
+fn authorize(state: &ListenerState, headers: &HeaderMap) -> Result<(), HeartbeatError> {
+  let host = headers.get(header::HOST).and_then(|value| value.to_str().ok());
+  if !is_local_api_host(host, state.port.load(Ordering::Relaxed)) {
+    return Err(HeartbeatError::ForeignHost);
+  }
+
+  let token = headers
+    .get(header::AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Bearer "));
+  match token {
+    Some(token) if state.db.verify_api_token(token.trim(), ApiScope::Heartbeats, Utc::now()).unwrap_or(false) => Ok(()),
+    _ => Err(HeartbeatError::Unauthorized),
+  }
+}
+
+struct ListenerState {
+  db: Arc<Database>,
+  /// The port currently listened on, which requests must be addressed to
+  port: AtomicU16,
+  merger: HeartbeatMerger,
+  terminals: TerminalSessions,
+}
+
+async fn receive_heartbeat(
+  State(state): State<Arc<ListenerState>>,
+  headers: HeaderMap,
+  Json(heartbeat): Json<Heartbeat>,
+) -> Result<StatusCode, HeartbeatError> {
+  authorize(&state, &headers)?;
+  state.merger.record(&heartbeat, Utc::now()).map_err(HeartbeatError::Invalid)?;
+  Ok(StatusCode::NO_CONTENT)
+}
+
+async fn receive_shell_ping(
+  State(state): State<Arc<ListenerState>>,
+  headers: HeaderMap,
+  Json(ping): Json<ShellPing>,
+) -> Result<StatusCode, HeartbeatError> {
+  authorize(&state, &headers)?;
+  state.terminals.record(&ping, Utc::now()).map_err(HeartbeatError::Invalid)?;
+  Ok(StatusCode::NO_CONTENT)
+}
+
+/// Routes for whichever integrations are turned on
+fn router(state: Arc<ListenerState>, settings: &HeartbeatSettings) -> Router {
+  let mut router = Router::new();
+  if settings.enabled {
+    router = router.route("/heartbeat", post(receive_heartbeat));
+  }
+  if settings.terminal {
+    router = router.route("/terminal", post(receive_shell_ping));
+  }
+  router.with_state(state)
+}
+
+/// The listener, started and stopped as the settings change
+pub struct HeartbeatListener {
+  state: Arc<ListenerState>,
+  shutdown: Mutex<Option<oneshot::Sender<()>>>,
+}
+
+impl HeartbeatListener {
+  pub fn new(db: Arc<Database>) -> Self {
+    Self {
+      state: Arc::new(ListenerState {
+        merger: HeartbeatMerger::new(db.clone()),
+        terminals: TerminalSessions::new(db.clone()),
+        port: AtomicU16::new(DEFAULT_PORT),
+        db,
+      }),
+      shutdown: Mutex::new(None),
+    }
+  }
+
+  /// Start or stop the listener to match the stored settings
+  pub async fn apply_settings(&self) -> Result<()> {
+    let settings = HeartbeatSettings::load(&self.state.db)?;
+    self.stop();
+    if settings.enabled || settings.terminal {
+      self.start(&settings).await?;
+    }
+    Ok(())
+  }
+
+  async fn start(&self, settings: &HeartbeatSettings) -> Result<()> {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, settings.port));
+    let listener = tokio::net::TcpListener::bind(addr)
+      .await
+      .with_context(|| format!("Failed to bind heartbeat listener to {}", addr))?;
+    info!("Heartbeat listener on http://{}", addr);
+    self.state.port.store(settings.port, Ordering::Relaxed);
+
+    let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+    *self.shutdown.lock().unwrap() = Some(shutdown_tx);
+
+    let state = self.state.clone();
+    let router = router(state.clone(), settings);
+    tauri::async_runtime::spawn(async move {
+      let (stop_tx, stop_rx) = oneshot::channel::<()>();
+      let server = axum::serve(listener, router).with_graceful_shutdown(async {
+        stop_rx.await.ok();
+      });
+      let server = tokio::spawn(async move { server.await });
+
+      let mut ticker = tokio::time::interval(STALE_CHECK_INTERVAL);
+      loop {
+        tokio::select! {
+          _ = &mut shutdown_rx => break,
+          _ = ticker.tick() => {
+            if let Err(e) = state.merger.close_stale(Utc::now()) {
+              error!("Failed to close coding event: {}", e);
+            }
+          }
+        }
+      }
+
+      stop_tx.send(()).ok();
+      if let Ok(Err(e)) = server.await {
+        error!("Heartbeat listener failed: {}", e);
+      }
+      if let Err(e) = state.merger.flush() {
+        error!("Failed to close coding event: {}", e);
+      }
+      if let Err(e) = state.terminals.flush(Utc::now()) {
+        error!("Failed to close terminal events: {}", e);
+      }
+      info!("Heartbeat listener stopped");
+    });
+    Ok(())
+  }
+
+  fn stop(&self) {
+    if let Some(shutdown) = self.shutdown.lock().unwrap().take() {
+      shutdown.send(()).ok();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_merger() -> (HeartbeatMerger, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Arc::new(Database::new(temp_file.path()).unwrap());
+    (HeartbeatMerger::new(db), temp_file)
+  }
+
+  fn heartbeat(file: &str) -> Heartbeat {
+    Heartbeat {
+      editor: "vscode".to_string(),
+      file: Some(file.to_string()),
+      language: Some("rust".to_string()),
+      project: Some("lifespan".to_string()),
+    }
+  }
+
+  #[test]
+  fn test_heartbeats_merge_into_coding_events() {
+    let (merger, _temp) = create_test_merger();
+    let start = Utc::now() - chrono::Duration::hours(1);
+    let at = |seconds: i64| start + chrono::Duration::seconds(seconds);
+
+    let first = merger.record(&heartbeat("/home/me/lifespan/src/main.rs"), at(0)).unwrap();
+    assert_eq!(merger.record(&heartbeat("/home/me/lifespan/src/main.rs"), at(60)).unwrap(), first);
+    assert_eq!(merger.record(&heartbeat("C:\\lifespan\\src\\main.rs"), at(150)).unwrap(), first);
+
+    // Another file starts a new event; the first closes at its last heartbeat
+    let second = merger.record(&heartbeat("lib.rs"), at(170)).unwrap();
+    assert_ne!(second, first);
+    let event = merger.db.get_event(&first).unwrap().unwrap();
+    assert_eq!(event.event_type, "coding");
+    assert_eq!(event.app_name, "vscode");
+    assert_eq!(event.window_title.as_deref(), Some("rust"));
+    assert_eq!(event.document.as_deref(), Some("main.rs"));
+    assert_eq!(event.project.as_deref(), Some("lifespan"));
+    assert_eq!(event.duration, 150);
+
+    // A long pause also starts a new event
+    let third = merger.record(&heartbeat("lib.rs"), at(170 + 600)).unwrap();
+    assert_ne!(third, second);
+
+    merger.close_stale(at(170 + 600 + 60)).unwrap();
+    assert!(merger.current.lock().unwrap().is_some());
+    merger.close_stale(at(170 + 600 + 300)).unwrap();
+    assert!(merger.current.lock().unwrap().is_none());
+  }
+
+  #[test]
+  fn test_heartbeat_without_editor_rejected() {
+    let (merger, _temp) = create_test_merger();
+    let mut invalid = heartbeat("main.rs");
+    invalid.editor = " ".to_string();
+    assert!(merger.record(&invalid, Utc::now()).is_err());
+  }
+
+  #[test]
+  fn test_requests_need_a_token_and_a_local_host() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Arc::new(Database::new(temp_file.path()).unwrap());
+    let listener = HeartbeatListener::new(db.clone());
+    let request = |host: &str, token: Option<&str>| {
+      let mut headers = HeaderMap::new();
+      headers.insert(header::HOST, host.parse().unwrap());
+      if let Some(token) = token {
+        headers.insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+      }
+      headers
+    };
+    let local = format!("localhost:{}", DEFAULT_PORT);
+
+    // No token has been created yet: still refused
+    assert!(matches!(authorize(&listener.state, &request(&local, None)), Err(HeartbeatError::Unauthorized)));
+
+    let created = db.create_api_token("vscode", &[ApiScope::Heartbeats], None).unwrap();
+    assert!(authorize(&listener.state, &request(&local, Some(&created.token))).is_ok());
+    let rebound = format!("attacker.example:{}", DEFAULT_PORT);
+    assert!(matches!(
+      authorize(&listener.state, &request(&rebound, Some(&created.token))),
+      Err(HeartbeatError::ForeignHost)
+    ));
+
+    let summary_only = db.create_api_token("widget", &[ApiScope::Summary], None).unwrap();
+    assert!(matches!(
+      authorize(&listener.state, &request(&local, Some(&summary_only.token))),
+      Err(HeartbeatError::Unauthorized)
+    ));
+  }
+
+  #[test]
+  fn test_settings_round_trip() {
+    let (merger, _temp) = create_test_merger();
+    assert_eq!(
+      HeartbeatSettings::load(&merger.db).unwrap(),
+      HeartbeatSettings {
+        enabled: false,
+        terminal: false,
+        port: DEFAULT_PORT
+      }
+    );
+
+    let settings = HeartbeatSettings {
+      enabled: true,
+      terminal: true,
+      port: 9000,
+    };
+    settings.save(&merger.db).unwrap();
+    assert_eq!(HeartbeatSettings::load(&merger.db).unwrap(), settings);
+  }
+}
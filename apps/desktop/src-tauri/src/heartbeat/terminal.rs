@@ -0,0 +1,158 @@
+//! Shell integration: "command started/finished" pings from a shell hook.
+//!
+//! A prompt hook (zsh `preexec`/`precmd`, PowerShell `PSReadLine`, ...) POSTs
+//! to `/terminal` with its session id. Each command becomes a "terminal"
+//! event with the shell as app, the program as title and the working
+//! directory as document. Arguments are dropped before anything is stored.
+
+use crate::database::Database;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Setting that enables shell integration ("true"); off by default
+pub const SHELL_INTEGRATION_SETTING: &str = "shell_integration";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "phase", rename_all = "snake_case")]
+pub enum ShellPing {
+  Started {
+    /// Identifies the shell instance, e.g. its PID
+    session: String,
+    /// e.g. "zsh", "pwsh"
+    shell: String,
+    /// The command line as typed; only the program name is kept
+    command: String,
+    cwd: Option<String>,
+  },
+  Finished {
+    session: String,
+  },
+}
+
+/// The program a command line runs: skips `VAR=value` prefixes, drops
+/// arguments and any directory
+pub fn program_name(command: &str) -> Option<String> {
+  let mut rest = command.trim_start();
+  let program = loop {
+    if let Some(quoted) = rest.strip_prefix(['"', '\'']) {
+      break quoted.split(['"', '\'']).next().unwrap_or(quoted);
+    }
+    let word = rest.split_whitespace().next()?;
+    if !word.contains('=') || word.starts_with('=') {
+      break word;
+    }
+    rest = rest[word.len()..].trim_start();
+  };
+  let name = program.rsplit(['/', '\\']).next().unwrap_or(program);
+  (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Open terminal events by shell session
+pub struct TerminalSessions {
+  db: Arc<Database>,
+  open: Mutex<HashMap<String, String>>,
+}
+
+impl TerminalSessions {
+  pub fn new(db: Arc<Database>) -> Self {
+    Self {
+      db,
+      open: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Record a ping received at `at`
+  pub fn record(&self, ping: &ShellPing, at: DateTime<Utc>) -> Result<()> {
+    let mut open = self.open.lock().unwrap();
+    match ping {
+      ShellPing::Started {
+        session,
+        shell,
+        command,
+        cwd,
+      } => {
+        let program = program_name(command).context("Command is empty")?;
+        // A missed "finished" (e.g. the shell was killed) ends at the next command
+        if let Some(event_id) = open.remove(session) {
+          self.db.close_event_sync(&event_id, at)?;
+        }
+        let cwd = cwd.as_deref().map(str::trim).filter(|cwd| !cwd.is_empty());
+        let event_id = self.db.open_terminal_event_sync(shell.trim(), &program, cwd, at)?;
+        open.insert(session.clone(), event_id);
+      }
+      ShellPing::Finished { session } => {
+        if let Some(event_id) = open.remove(session) {
+          self.db.close_event_sync(&event_id, at)?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Close every running command at `at`
+  pub fn flush(&self, at: DateTime<Utc>) -> Result<()> {
+    for (_, event_id) in self.open.lock().unwrap().drain() {
+      self.db.close_event_sync(&event_id, at)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn started(session: &str, command: &str) -> ShellPing {
+    ShellPing::Started {
+      session: session.to_string(),
+      shell: "zsh".to_string(),
+      command: command.to_string(),
+      cwd: Some("/home/me/lifespan".to_string()),
+    }
+  }
+
+  #[test]
+  fn test_program_name_drops_arguments() {
+    assert_eq!(program_name("cargo test --workspace").as_deref(), Some("cargo"));
+    assert_eq!(program_name("RUST_LOG=debug /usr/bin/git push --force").as_deref(), Some("git"));
+    assert_eq!(program_name("\"C:\\Program Files\\nodejs\\npm.cmd\" install").as_deref(), Some("npm.cmd"));
+    assert_eq!(program_name("   "), None);
+  }
+
+  #[test]
+  fn test_commands_become_terminal_events() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Arc::new(Database::new(temp_file.path()).unwrap());
+    let sessions = TerminalSessions::new(db.clone());
+    let start = Utc::now() - chrono::Duration::hours(1);
+    let at = |seconds: i64| start + chrono::Duration::seconds(seconds);
+
+    sessions.record(&started("1", "cargo build --release"), at(0)).unwrap();
+    sessions.record(&started("2", "npm run dev"), at(5)).unwrap();
+    sessions
+      .record(&ShellPing::Finished { session: "1".to_string() }, at(40))
+      .unwrap();
+    // Session 2 never reported finishing; its next command closes it
+    sessions.record(&started("2", "ls -la"), at(100)).unwrap();
+    sessions.flush(at(101)).unwrap();
+
+    let events = db.get_events_between(at(-1), at(200), &Default::default()).unwrap();
+    let summary: Vec<_> = events
+      .iter()
+      .map(|e| (e.event_type.as_str(), e.app_name.as_str(), e.window_title.as_deref(), e.duration))
+      .collect();
+    assert_eq!(
+      summary,
+      vec![
+        ("terminal", "zsh", Some("cargo"), 40),
+        ("terminal", "zsh", Some("npm"), 95),
+        ("terminal", "zsh", Some("ls"), 1),
+      ]
+    );
+    assert!(events.iter().all(|e| e.document.as_deref() == Some("/home/me/lifespan")));
+  }
+}
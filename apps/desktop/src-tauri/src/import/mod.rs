@@ -0,0 +1,366 @@
+//! Backfilling the local database from other trackers' CSV exports.
+//!
+//! A small column mapping says which columns hold the start time, app,
+//! window title and duration (or end time); presets cover RescueTime and
+//! ManicTime exports. Times without an offset are read as local time. Rows
+//! that fail to parse are reported and skipped, and rows matching an event
+//! already stored (same start and app) are skipped, so re-running an import
+//! doesn't double-count.
+
+use crate::database::{Database, NewEvent, StorageBackend, MAX_EVENT_DURATION_SECS};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Duration, Local, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::Path;
+
+/// Events inserted per transaction
+const INSERT_BATCH: usize = 1000;
+
+/// Row errors kept in the report; the rest are only counted
+const MAX_REPORTED_ERRORS: usize = 20;
+
+/// Formats tried, in order, for times that aren't RFC 3339
+const TIMESTAMP_FORMATS: &[&str] = &[
+  "%Y-%m-%d %H:%M:%S",
+  "%Y-%m-%dT%H:%M:%S",
+  "%Y-%m-%d %H:%M",
+  "%m/%d/%Y %I:%M:%S %p",
+  "%m/%d/%Y %H:%M:%S",
+  "%m/%d/%Y %H:%M",
+  "%d.%m.%Y %H:%M:%S",
+];
+
+/// Which CSV columns (by header name, case-insensitive) hold each field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CsvColumnMapping {
+  pub timestamp: String,
+  pub app: String,
+  pub title: Option<String>,
+  /// Seconds, or H:MM:SS / MM:SS
+  pub duration: Option<String>,
+  /// End time, used when there is no duration column
+  pub end: Option<String>,
+  /// chrono format for times, tried before the built-in ones
+  pub timestamp_format: Option<String>,
+  /// Field delimiter; "," when unset
+  pub delimiter: Option<char>,
+}
+
+/// Mappings for known exports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsvPreset {
+  /// Activity export: Date, Time Spent (seconds), Activity, Category, ...
+  RescueTime,
+  /// Timeline export: Name, Start, End, Duration
+  ManicTime,
+}
+
+impl CsvPreset {
+  pub fn mapping(self) -> CsvColumnMapping {
+    match self {
+      CsvPreset::RescueTime => CsvColumnMapping {
+        timestamp: "Date".to_string(),
+        app: "Activity".to_string(),
+        title: None,
+        duration: Some("Time Spent (seconds)".to_string()),
+        end: None,
+        timestamp_format: None,
+        delimiter: None,
+      },
+      CsvPreset::ManicTime => CsvColumnMapping {
+        timestamp: "Start".to_string(),
+        app: "Name".to_string(),
+        title: None,
+        duration: Some("Duration".to_string()),
+        end: Some("End".to_string()),
+        timestamp_format: None,
+        delimiter: None,
+      },
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportRowError {
+  /// 1-based line number in the file, counting the header
+  pub line: u64,
+  pub message: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ImportReport {
+  pub imported: usize,
+  pub duplicates: usize,
+  pub failed: usize,
+  /// The first few failures
+  pub errors: Vec<ImportRowError>,
+}
+
+/// Column indexes resolved from the header row
+struct Columns {
+  timestamp: usize,
+  app: usize,
+  title: Option<usize>,
+  duration: Option<usize>,
+  end: Option<usize>,
+}
+
+fn resolve_columns(headers: &csv::StringRecord, mapping: &CsvColumnMapping) -> Result<Columns> {
+  let find = |name: &str| {
+    headers
+      .iter()
+      .position(|header| header.trim().eq_ignore_ascii_case(name.trim()))
+      .ok_or_else(|| anyhow!("Column \"{}\" not found", name))
+  };
+  let find_optional = |name: &Option<String>| name.as_deref().map(find).transpose();
+
+  let columns = Columns {
+    timestamp: find(mapping.timestamp.as_str())?,
+    app: find(mapping.app.as_str())?,
+    title: find_optional(&mapping.title)?,
+    duration: find_optional(&mapping.duration)?,
+    end: find_optional(&mapping.end)?,
+  };
+  if columns.duration.is_none() && columns.end.is_none() {
+    bail!("The mapping needs a duration or end column");
+  }
+  Ok(columns)
+}
+
+fn parse_timestamp(value: &str, format: Option<&str>) -> Result<DateTime<Utc>> {
+  let value = value.trim();
+  if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+    return Ok(at.with_timezone(&Utc));
+  }
+
+  let naive = format
+    .into_iter()
+    .chain(TIMESTAMP_FORMATS.iter().copied())
+    .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    .ok_or_else(|| anyhow!("Unrecognized time \"{}\"", value))?;
+  Local
+    .from_local_datetime(&naive)
+    .earliest()
+    .map(|at| at.with_timezone(&Utc))
+    .ok_or_else(|| anyhow!("Time \"{}\" doesn't exist locally", value))
+}
+
+/// Seconds from "90", "90.5", "1:30" or "0:01:30"
+fn parse_duration(value: &str) -> Result<i64> {
+  let value = value.trim();
+  if let Some(seconds) = value.parse::<f64>().ok().filter(|seconds| seconds.is_finite()) {
+    return Ok(seconds.round() as i64);
+  }
+
+  let parts = value
+    .split(':')
+    .map(|part| part.trim().parse::<i64>())
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|_| anyhow!("Unrecognized duration \"{}\"", value))?;
+  match parts[..] {
+    [minutes, seconds] => Ok(minutes * 60 + seconds),
+    [hours, minutes, seconds] => Ok(hours * 3600 + minutes * 60 + seconds),
+    _ => bail!("Unrecognized duration \"{}\"", value),
+  }
+}
+
+fn parse_row(record: &csv::StringRecord, columns: &Columns, mapping: &CsvColumnMapping) -> Result<NewEvent> {
+  let field = |index: usize| record.get(index).unwrap_or_default().trim();
+  let format = mapping.timestamp_format.as_deref();
+
+  let timestamp = parse_timestamp(field(columns.timestamp), format)?;
+  let app_name = field(columns.app);
+  if app_name.is_empty() {
+    bail!("App is empty");
+  }
+
+  let duration = match (columns.duration.map(field), columns.end.map(field)) {
+    (Some(duration), _) if !duration.is_empty() => parse_duration(duration)?,
+    (_, Some(end)) if !end.is_empty() => (parse_timestamp(end, format)? - timestamp).num_seconds(),
+    _ => bail!("Duration is empty"),
+  };
+  if !(0..=MAX_EVENT_DURATION_SECS).contains(&duration) {
+    bail!("Duration of {}s is out of range", duration);
+  }
+
+  Ok(NewEvent {
+    event_type: "app_usage".to_string(),
+    timestamp,
+    duration: duration as i32,
+    app_name: app_name.to_string(),
+    window_title: columns.title.map(field).filter(|title| !title.is_empty()).map(str::to_string),
+    url_domain: None,
+    remote_session: false,
+  })
+}
+
+/// Import app usage rows from CSV data
+pub fn import_csv(db: &Database, data: impl Read, mapping: &CsvColumnMapping) -> Result<ImportReport> {
+  let mut reader = csv::ReaderBuilder::new()
+    .delimiter(mapping.delimiter.map_or(b',', |delimiter| delimiter as u8))
+    .flexible(true)
+    .trim(csv::Trim::Headers)
+    .from_reader(data);
+  let columns = resolve_columns(reader.headers()?, mapping)?;
+
+  let mut report = ImportReport::default();
+  let mut events = Vec::new();
+  for (index, record) in reader.records().enumerate() {
+    // Records spanning lines know their position; otherwise count from the header
+    let line = record
+      .as_ref()
+      .ok()
+      .and_then(|record| record.position())
+      .map_or(index as u64 + 2, |position| position.line());
+    match record
+      .map_err(anyhow::Error::from)
+      .and_then(|record| parse_row(&record, &columns, mapping))
+    {
+      Ok(event) => events.push(event),
+      Err(e) => {
+        report.failed += 1;
+        if report.errors.len() < MAX_REPORTED_ERRORS {
+          report.errors.push(ImportRowError {
+            line,
+            message: e.to_string(),
+          });
+        }
+      }
+    }
+  }
+
+  let Some(first) = events.iter().map(|event| event.timestamp).min() else {
+    return Ok(report);
+  };
+  let last = events.iter().map(|event| event.timestamp).max().unwrap_or(first);
+  let mut seen: HashSet<(i64, String)> = db
+    .events_between(first, last + Duration::milliseconds(1))?
+    .into_iter()
+    .map(|event| (event.timestamp.timestamp_millis(), event.app_name))
+    .collect();
+
+  let before = events.len();
+  events.retain(|event| seen.insert((event.timestamp.timestamp_millis(), event.app_name.clone())));
+  report.duplicates = before - events.len();
+
+  for batch in events.chunks(INSERT_BATCH) {
+    report.imported += db.insert_events(batch)?.len();
+  }
+  Ok(report)
+}
+
+/// Import the CSV file at `path`
+pub fn import_csv_file(db: &Database, path: &Path, mapping: &CsvColumnMapping) -> Result<ImportReport> {
+  let file = std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+  import_csv(db, file, mapping)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn local(value: &str) -> DateTime<Utc> {
+    parse_timestamp(value, None).unwrap()
+  }
+
+  #[test]
+  fn test_parse_duration_formats() {
+    assert_eq!(parse_duration("90").unwrap(), 90);
+    assert_eq!(parse_duration("89.6").unwrap(), 90);
+    assert_eq!(parse_duration("1:30").unwrap(), 90);
+    assert_eq!(parse_duration("1:00:05").unwrap(), 3605);
+    assert!(parse_duration("NaN").is_err());
+    assert!(parse_duration("soon").is_err());
+  }
+
+  #[test]
+  fn test_parse_timestamp_formats() {
+    assert_eq!(
+      parse_timestamp("2024-05-06T09:00:00+02:00", None).unwrap(),
+      Utc.with_ymd_and_hms(2024, 5, 6, 7, 0, 0).unwrap()
+    );
+    assert_eq!(local("05/06/2024 09:00:00 AM"), local("2024-05-06 09:00:00"));
+    assert_eq!(
+      parse_timestamp("06|05|2024 09:00", Some("%d|%m|%Y %H:%M")).unwrap(),
+      local("2024-05-06 09:00:00")
+    );
+    assert!(parse_timestamp("yesterday", None).is_err());
+  }
+
+  #[test]
+  fn test_rescuetime_import() {
+    let (db, _temp) = create_test_db();
+    let csv = "Date,Time Spent (seconds),Number of People,Activity,Category,Productivity\n\
+      2024-05-06T09:00:00,600,1,code,Editing & IDEs,2\n\
+      2024-05-06T09:00:00,120,1,slack,Instant Message,0\n\
+      2024-05-06T10:00:00,,1,chrome,Browsers,0\n";
+
+    let report = import_csv(&db, csv.as_bytes(), &CsvPreset::RescueTime.mapping()).unwrap();
+
+    assert_eq!(report.imported, 2);
+    assert_eq!(report.failed, 1);
+    assert_eq!(report.errors[0].line, 4);
+    let events = db
+      .events_between(local("2024-05-06 00:00:00"), local("2024-05-07 00:00:00"))
+      .unwrap();
+    assert_eq!(events.len(), 2);
+    assert!(events.iter().any(|e| e.app_name == "code" && e.duration == 600));
+  }
+
+  #[test]
+  fn test_manictime_import_uses_end_without_duration() {
+    let (db, _temp) = create_test_db();
+    let mapping = CsvColumnMapping {
+      duration: None,
+      title: Some("Title".to_string()),
+      delimiter: Some(';'),
+      ..CsvPreset::ManicTime.mapping()
+    };
+    let csv = "Name;Title;Start;End\n\
+      Visual Studio Code;main.rs - lifespan;2024-05-06 09:00:00;2024-05-06 09:30:00\n";
+
+    let report = import_csv(&db, csv.as_bytes(), &mapping).unwrap();
+
+    assert_eq!(report.imported, 1);
+    let event = &db.events_between(local("2024-05-06 00:00:00"), local("2024-05-07 00:00:00")).unwrap()[0];
+    assert_eq!(event.duration, 1800);
+    assert_eq!(event.window_title.as_deref(), Some("main.rs - lifespan"));
+  }
+
+  #[test]
+  fn test_reimport_skips_duplicates() {
+    let (db, _temp) = create_test_db();
+    let csv = "Name,Start,End,Duration\nExcel,2024-05-06 09:00:00,2024-05-06 09:10:00,0:10:00\n";
+    let mapping = CsvPreset::ManicTime.mapping();
+
+    assert_eq!(import_csv(&db, csv.as_bytes(), &mapping).unwrap().imported, 1);
+    let again = import_csv(&db, csv.as_bytes(), &mapping).unwrap();
+    assert_eq!(again.imported, 0);
+    assert_eq!(again.duplicates, 1);
+    assert_eq!(db.get_event_count().unwrap(), 1);
+  }
+
+  #[test]
+  fn test_missing_columns_rejected() {
+    let (db, _temp) = create_test_db();
+    let mapping = CsvPreset::ManicTime.mapping();
+    assert!(import_csv(&db, "Start,End\n".as_bytes(), &mapping).is_err());
+
+    let no_length = CsvColumnMapping {
+      duration: None,
+      end: None,
+      ..mapping
+    };
+    assert!(import_csv(&db, "Name,Start\n".as_bytes(), &no_length).is_err());
+  }
+}
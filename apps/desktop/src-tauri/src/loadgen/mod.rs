@@ -0,0 +1,208 @@
+//! Synthetic load for stress testing storage and sync.
+//!
+//! Only built with the `load-generator` feature. `generate_load` inserts
+//! `events_per_sec` synthetic events every second for the requested duration,
+//! optionally drains them through sync, and reports how well storage (and the
+//! server) kept up. The synthetic events are deleted afterwards, through the
+//! normal deletion queue so copies on the server go too.
+
+use crate::database::{Database, DeletionReason, NewEvent, StorageBackend};
+use crate::sync::SyncClient;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+/// App name of every synthetic event
+pub const LOADGEN_APP: &str = "lifespan-loadgen";
+
+pub const MAX_EVENTS_PER_SEC: u32 = 10_000;
+pub const MAX_DURATION_SECS: u32 = 600;
+
+/// Most sync rounds spent draining the generated events
+const MAX_SYNC_ROUNDS: usize = 1_000;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LoadReport {
+  pub events_requested: u64,
+  pub events_inserted: u64,
+  pub duration_ms: u64,
+  pub achieved_events_per_sec: f64,
+  /// Slowest one-second batch insert
+  pub max_batch_ms: u64,
+  /// Batches that took longer than their second, i.e. storage fell behind
+  pub slow_batches: u32,
+  /// Set when the run included sync
+  pub synced_events: Option<u64>,
+  pub sync_ms: Option<u64>,
+  pub sync_error: Option<String>,
+}
+
+/// One second's worth of synthetic events, spread evenly over that second
+fn synthetic_batch(second_start: DateTime<Utc>, count: u32) -> Vec<NewEvent> {
+  let spacing_ms = 1_000 / count.max(1) as i64;
+  (0..count)
+    .map(|i| NewEvent {
+      event_type: "app_usage".to_string(),
+      timestamp: second_start + chrono::Duration::milliseconds(i as i64 * spacing_ms),
+      duration: 1,
+      app_name: LOADGEN_APP.to_string(),
+      window_title: Some(format!("Synthetic window {}", i % 50)),
+      url_domain: None,
+      remote_session: false,
+    })
+    .collect()
+}
+
+/// Insert synthetic events at the given rate, optionally sync them, then delete them
+pub async fn generate_load(
+  db: Arc<Database>,
+  sync_client: Option<&SyncClient>,
+  events_per_sec: u32,
+  duration_secs: u32,
+) -> Result<LoadReport> {
+  if events_per_sec == 0 || events_per_sec > MAX_EVENTS_PER_SEC {
+    bail!("events_per_sec must be between 1 and {}", MAX_EVENTS_PER_SEC);
+  }
+  if duration_secs == 0 || duration_secs > MAX_DURATION_SECS {
+    bail!("duration must be between 1 and {} seconds", MAX_DURATION_SECS);
+  }
+
+  info!("Generating load: {} events/s for {}s", events_per_sec, duration_secs);
+  let mut report = LoadReport {
+    events_requested: events_per_sec as u64 * duration_secs as u64,
+    ..LoadReport::default()
+  };
+  let mut ids = Vec::with_capacity(report.events_requested as usize);
+  let mut ticker = tokio::time::interval(Duration::from_secs(1));
+  let started = Instant::now();
+
+  let mut result = Ok(());
+  for _ in 0..duration_secs {
+    ticker.tick().await;
+
+    let batch = synthetic_batch(Utc::now(), events_per_sec);
+    let batch_db = db.clone();
+    let batch_started = Instant::now();
+    match tokio::task::spawn_blocking(move || batch_db.insert_events(&batch)).await? {
+      Ok(batch_ids) => ids.extend(batch_ids),
+      Err(e) => {
+        result = Err(e);
+        break;
+      }
+    }
+
+    let batch_ms = batch_started.elapsed().as_millis() as u64;
+    report.max_batch_ms = report.max_batch_ms.max(batch_ms);
+    if batch_ms > 1_000 {
+      report.slow_batches += 1;
+    }
+  }
+
+  let elapsed = started.elapsed();
+  report.events_inserted = ids.len() as u64;
+  report.duration_ms = elapsed.as_millis() as u64;
+  report.achieved_events_per_sec = ids.len() as f64 / elapsed.as_secs_f64().max(0.001);
+
+  if result.is_ok() {
+    if let Some(sync_client) = sync_client {
+      drain_through_sync(&db, sync_client, &ids, &mut report).await;
+    }
+  }
+
+  // Never leave synthetic data behind, even after a failed run
+  let cleanup_db = db.clone();
+  let deleted = tokio::task::spawn_blocking(move || {
+    cleanup_db.delete_events_propagated(&ids, DeletionReason::Purge, Utc::now())
+  })
+  .await??;
+  info!("Load run finished, removed {} synthetic events", deleted);
+
+  // Sync with nothing pending only sends the queued deletions
+  if let Some(sync_client) = sync_client.filter(|_| report.synced_events.unwrap_or(0) > 0) {
+    if let Err(e) = sync_client.sync_events().await {
+      error!("Failed to remove synthetic events from the server: {}", e);
+    }
+  }
+
+  result.map(|_| report)
+}
+
+/// Sync until none of `ids` is pending, recording throughput in `report`
+async fn drain_through_sync(db: &Arc<Database>, sync_client: &SyncClient, ids: &[String], report: &mut LoadReport) {
+  let started = Instant::now();
+  let mut rounds = 0;
+
+  let pending = |db: Arc<Database>| async move {
+    let remaining = tokio::task::spawn_blocking(move || db.count_unsynced_for_app(LOADGEN_APP)).await??;
+    anyhow::Ok(remaining)
+  };
+
+  loop {
+    match pending(db.clone()).await {
+      Ok(0) => break,
+      Ok(_) if rounds >= MAX_SYNC_ROUNDS => {
+        report.sync_error = Some(format!("Gave up after {} sync rounds", rounds));
+        break;
+      }
+      Ok(_) => {}
+      Err(e) => {
+        report.sync_error = Some(e.to_string());
+        break;
+      }
+    }
+
+    rounds += 1;
+    if let Err(e) = sync_client.sync_events().await {
+      report.sync_error = Some(e.to_string());
+      break;
+    }
+  }
+
+  let remaining = pending(db.clone()).await.unwrap_or(ids.len());
+  report.synced_events = Some(ids.len().saturating_sub(remaining) as u64);
+  report.sync_ms = Some(started.elapsed().as_millis() as u64);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Arc<Database>, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (Arc::new(db), temp_file)
+  }
+
+  #[test]
+  fn test_synthetic_batch_spreads_over_one_second() {
+    let start = Utc::now();
+    let batch = synthetic_batch(start, 4);
+
+    assert_eq!(batch.len(), 4);
+    assert!(batch.iter().all(|event| event.app_name == LOADGEN_APP));
+    assert_eq!(batch[3].timestamp - start, chrono::Duration::milliseconds(750));
+  }
+
+  #[tokio::test]
+  async fn test_generated_events_are_removed() {
+    let (db, _temp) = create_test_db();
+
+    let report = generate_load(db.clone(), None, 20, 1).await.unwrap();
+
+    assert_eq!(report.events_requested, 20);
+    assert_eq!(report.events_inserted, 20);
+    assert!(report.synced_events.is_none());
+    assert_eq!(db.get_event_count().unwrap(), 0);
+  }
+
+  #[tokio::test]
+  async fn test_rejects_out_of_range_load() {
+    let (db, _temp) = create_test_db();
+    assert!(generate_load(db.clone(), None, 0, 1).await.is_err());
+    assert!(generate_load(db, None, 1, MAX_DURATION_SECS + 1).await.is_err());
+  }
+}
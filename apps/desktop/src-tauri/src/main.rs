@@ -1,16 +1,41 @@
 // Prevents additional console window on Windows in release builds
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod archive;
+mod classifier;
 mod collector;
 mod commands;
+#[cfg(feature = "dashboard")]
+mod dashboard;
 mod database;
+mod diagnostics;
 mod encryption;
+#[cfg(feature = "parquet-export")]
+mod export;
+mod goals;
+mod guard;
+#[cfg(feature = "editor-heartbeats")]
+mod heartbeat;
+mod import;
+#[cfg(feature = "load-generator")]
+mod loadgen;
+mod notifications;
+mod profiles;
+mod reports;
+mod retention;
+mod session;
+mod statements;
 mod sync;
+mod theme;
 
+use collector::focus::FocusTimer;
+use collector::window_tracker::WindowTracker;
 use collector::Collector;
+use notifications::NotificationCenter;
 use std::sync::Arc;
 use sync::SyncClient;
 use tauri::Manager;
+use theme::ThemeService;
 
 fn init_tracing() {
   use tracing_subscriber::{EnvFilter, fmt, prelude::*};
@@ -25,58 +50,309 @@ fn init_tracing() {
 }
 
 fn main() {
+  // Window capture helper mode: no tracing (stdout is the IPC channel), no DB, no network
+  if std::env::args().any(|arg| arg == collector::capture_helper::HELPER_ARG) {
+    if let Err(e) = collector::capture_helper::run_helper() {
+      eprintln!("Capture helper failed: {}", e);
+      std::process::exit(1);
+    }
+    return;
+  }
+
   // Initialize tracing
   init_tracing();
 
+  // Headless dashboard mode: serve the web UI from an existing database, no Tauri window
+  #[cfg(feature = "dashboard")]
+  if std::env::args().any(|arg| arg == dashboard::DASHBOARD_ARG) {
+    let result = dashboard::DashboardOptions::from_args(std::env::args()).and_then(dashboard::run);
+    if let Err(e) = result {
+      eprintln!("Dashboard failed: {}", e);
+      std::process::exit(1);
+    }
+    return;
+  }
+
+  // Relaunched into another profile: start once the previous process is gone
+  if let Some(pid) = profiles::after_pid_from_args(std::env::args()) {
+    if !profiles::wait_for_exit(pid, std::time::Duration::from_secs(30)) {
+      eprintln!("Process {} is still running; starting anyway", pid);
+    }
+  }
+
   tauri::Builder::default()
+    .plugin(tauri_plugin_notification::init())
     .setup(|app| {
-      // Initialize database
-      let app_data_dir = app.path().app_local_data_dir()
+      // Resolve the profile; each one keeps its own database and files
+      let base_data_dir = app.path().app_local_data_dir()
         .expect("Failed to get app data dir");
+      let profile = profiles::ProfileStore::new(&base_data_dir)
+        .startup_profile(std::env::args())
+        .expect("Failed to select profile");
+      let app_data_dir = profile.data_dir.clone();
+      let profile_name = profile.name.clone();
+      app.manage(profile);
 
+      // Initialize database
       let db_path = app_data_dir.join("local.db");
 
-      // Initialize database in a blocking task
-      let db = database::Database::new(&db_path)
+      // Sync key: one remembered in the system keyring from an earlier unlock,
+      // else the development key until the user unlocks with their passphrase
+      let sync_key = match encryption::KeyStore::new(&profile_name).load() {
+        Ok(Some(key)) => key,
+        Ok(None) => encryption::SecretKey::new(*encryption::DEFAULT_SYNC_KEY),
+        Err(e) => {
+          eprintln!("Failed to read the cached sync key: {}", e);
+          encryption::SecretKey::new(*encryption::DEFAULT_SYNC_KEY)
+        }
+      };
+
+      // Initialize database, keyed from the sync key; a damaged one is salvaged into a fresh file
+      let db = database::Database::open_or_recover(&db_path, &sync_key)
+        .or_else(|e| {
+          // The app stopped between remembering the key and re-keying the database
+          if *sync_key == *encryption::DEFAULT_SYNC_KEY {
+            return Err(e);
+          }
+          let db = database::Database::open_or_recover(&db_path, encryption::DEFAULT_SYNC_KEY)?;
+          db.rekey_local(&sync_key)?;
+          Ok(db)
+        })
         .expect("Failed to initialize database");
 
       let db_arc = Arc::new(db);
+      // Events from this run reference the machine and app version
+      if let Err(e) = db_arc.start_app_session(&sync::ClientInfo::current(), chrono::Utc::now()) {
+        eprintln!("Failed to record app session: {}", e);
+      }
+      db_arc.start_write_flusher();
+      db_arc.start_downsampler();
+      db_arc.start_maintenance_scheduler();
+      statements::start_statement_scheduler(db_arc.clone());
 
-      // Initialize collector
-      let collector = Collector::new(db_arc.clone())
+      // Detect an unclean previous exit before the collector opens new events
+      let session_lock = Arc::new(
+        session::SessionLock::acquire(&app_data_dir, &db_arc).expect("Failed to acquire session lock"),
+      );
+      session_lock.start_heartbeat();
+      app.manage(session_lock);
+
+      // Initialize collector; on Windows window capture runs in a separate helper process
+      let window_tracker = if cfg!(windows) {
+        WindowTracker::isolated()
+      } else {
+        WindowTracker::new().expect("Failed to initialize window tracker")
+      };
+      let collector = Collector::with_window_tracker(db_arc.clone(), window_tracker)
         .expect("Failed to initialize collector");
 
+      let activity_meter = collector.activity_meter();
+      let event_recorder = collector.event_recorder();
+
       // Initialize sync client
       let sync_client = SyncClient::new(db_arc.clone());
 
-      // Initialize crypto key for sync (use default key for development)
-      // In production, this should be derived from user password using Argon2id
-      let default_key = b"lifespan-dev-key-32-bytes-long!!";  // 32 bytes for AES-256
-
       // Initialize crypto key synchronously using block_on
       let rt = tokio::runtime::Runtime::new()
         .expect("Failed to create tokio runtime");
       rt.block_on(async {
-        if let Err(e) = sync_client.set_crypto_key(*default_key).await {
+        if let Err(e) = sync_client.set_crypto_key(sync_key).await {
           eprintln!("Failed to initialize crypto key: {}", e);
         }
       });
 
       // Store in app state
       app.manage(Arc::new(tokio::sync::Mutex::new(collector)));
+      app.manage(event_recorder);
       app.manage(sync_client);
+      app.manage(db_arc.clone());
+      app.manage(ThemeService::new(db_arc.clone()));
+      app.manage(guard::CommandGuard::default());
+
+      // Daily retention pass; needs the sync client for archiving
+      retention::start_retention_scheduler(app.handle().clone(), app_data_dir.join("archives"));
+
+      // Focus timer alongside the collector; finishes sessions and suggests breaks
+      let focus_timer = Arc::new(FocusTimer::new(db_arc.clone(), Some(app.handle().clone())));
+      focus_timer.clone().start_scheduler();
+      app.manage(focus_timer);
+
+      // Notification feed with daily digest delivery
+      let notification_center = Arc::new(NotificationCenter::new(db_arc.clone(), Some(app.handle().clone())));
+      notification_center.clone().start_digest_scheduler();
+      app.manage(notification_center);
+
+      // Optional "what are you doing?" prompts
+      collector::self_report::start_prompt_scheduler(db_arc.clone(), activity_meter, app.handle().clone());
+
+      // Localhost listener for editor heartbeats and shell hooks, if turned on
+      #[cfg(feature = "editor-heartbeats")]
+      {
+        let heartbeat_listener = Arc::new(heartbeat::HeartbeatListener::new(db_arc.clone()));
+        let listener = heartbeat_listener.clone();
+        tauri::async_runtime::spawn(async move {
+          if let Err(e) = listener.apply_settings().await {
+            eprintln!("Failed to start heartbeat listener: {}", e);
+          }
+        });
+        app.manage(heartbeat_listener);
+      }
 
       Ok(())
     })
     .invoke_handler(tauri::generate_handler![
       commands::start_tracking,
       commands::stop_tracking,
+      commands::pause_tracking,
+      commands::resume_tracking,
       commands::get_status,
+      commands::start_focus,
+      commands::stop_focus,
+      commands::get_focus_status,
       commands::sync_now,
       commands::get_sync_status,
       commands::get_server_config,
       commands::set_server_config,
+      commands::get_sync_field_policy,
+      commands::set_sync_field_policy,
+      commands::set_sync_batch_size,
+      commands::get_system_theme,
+      commands::get_report_theme,
+      commands::set_report_theme,
+      commands::get_notification_feed,
+      commands::mark_notifications_read,
+      commands::get_notification_settings,
+      commands::set_notification_settings,
+      commands::self_test,
+      commands::generate_load,
+      commands::get_settings_history,
+      commands::diff_settings_versions,
+      commands::restore_settings_version,
+      commands::get_category_rules,
+      commands::set_category_rule,
+      commands::delete_category_rule,
+      commands::recategorize_app,
+      commands::record_category_correction,
+      commands::get_category_corrections,
+      commands::delete_category_correction,
+      commands::suggest_category,
+      commands::get_category_classifier_enabled,
+      commands::set_category_classifier_enabled,
+      commands::get_excluded_apps,
+      commands::add_excluded_app,
+      commands::remove_excluded_app,
+      commands::get_title_policies,
+      commands::set_title_policy,
+      commands::remove_title_policy,
+      commands::get_redaction_rules,
+      commands::add_redaction_rule,
+      commands::set_redaction_rule_enabled,
+      commands::delete_redaction_rule,
+      commands::get_project_rules,
+      commands::add_project_rule,
+      commands::set_project_rule_enabled,
+      commands::delete_project_rule,
+      commands::get_tracking_schedule,
+      commands::set_tracking_schedule,
+      commands::get_self_report_settings,
+      commands::set_self_report_settings,
+      commands::add_annotation,
+      commands::get_annotations,
+      commands::get_tags,
+      commands::tag_events,
+      commands::untag_events,
+      commands::delete_tag,
+      commands::get_event_tags,
+      commands::get_events_with_tag,
+      commands::get_category_summary,
+      commands::get_app_totals,
+      commands::get_daily_totals,
+      commands::get_document_summary,
+      commands::get_desktop_summary,
+      commands::get_executable_summary,
+      commands::get_events_between,
+      commands::get_timeline,
+      commands::get_device_summary,
+      commands::get_overlap_policy,
+      commands::set_overlap_policy,
+      commands::search_events,
+      commands::export_events_parquet,
+      commands::import_csv,
+      commands::get_app_rollups,
+      commands::get_category_rollups,
+      commands::get_executable_hashing_enabled,
+      commands::set_executable_hashing_enabled,
+      commands::get_anonymized_storage,
+      commands::set_anonymized_storage,
+      commands::resolve_display_names,
+      commands::get_usage_trend,
+      commands::get_forecast,
+      commands::get_monthly_statement,
+      commands::list_monthly_statements,
+      commands::get_goals,
+      commands::set_goal,
+      commands::delete_goal,
+      commands::get_goal_status,
+      commands::get_db_stats,
+      commands::get_storage_stats,
+      commands::get_app_sessions,
+      commands::get_maintenance_status,
+      commands::get_last_recovery,
+      commands::archive_events_before,
+      commands::get_retention_policy,
+      commands::set_retention_policy,
+      commands::backup_database,
+      commands::restore_database,
+      commands::delete_events,
+      commands::delete_events_matching,
+      commands::purge_events,
+      commands::set_event_recording_enabled,
+      commands::start_event_recording,
+      commands::stop_event_recording,
+      commands::get_event_recording_status,
+      commands::replay_event_recording,
+      commands::get_unconfirmed_deletions,
+      commands::get_last_crash_info,
+      commands::create_api_token,
+      commands::add_custom_event,
+      commands::get_custom_events,
+      commands::list_api_tokens,
+      commands::get_editor_heartbeat_settings,
+      commands::set_editor_heartbeat_settings,
+      commands::revoke_api_token,
+      commands::get_app_lock_status,
+      commands::unlock_app,
+      commands::lock_app,
+      commands::set_app_lock_pin,
+      commands::unlock_sync_key,
+      commands::change_passphrase,
+      commands::get_recovery_code,
+      commands::recover_sync_key,
+      commands::forget_sync_key,
+      commands::get_sync_key_status,
+      commands::list_profiles,
+      commands::create_profile,
+      commands::get_current_profile,
+      commands::switch_profile,
     ])
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
+    .build(tauri::generate_context!())
+    .expect("error while building tauri application")
+    .run(|app, event| {
+      if let tauri::RunEvent::Exit = event {
+        // Close the events still open, then persist write-behind status values
+        if let Some(db) = app.try_state::<Arc<database::Database>>() {
+          if let Err(e) = db.close_open_events_sync(chrono::Utc::now()) {
+            eprintln!("Failed to close open events on exit: {}", e);
+          }
+          if let Err(e) = db.flush_status_writes() {
+            eprintln!("Failed to flush status writes on exit: {}", e);
+          }
+        }
+        if let Some(lock) = app.try_state::<Arc<session::SessionLock>>() {
+          if let Err(e) = lock.release() {
+            eprintln!("Failed to release session lock: {}", e);
+          }
+        }
+      }
+    });
 }
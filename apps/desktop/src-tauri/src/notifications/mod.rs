@@ -0,0 +1,381 @@
+use crate::database::{Database, StoredNotification};
+use crate::goals::{self, GoalStatus};
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::AppHandle;
+use tauri_plugin_notification::NotificationExt;
+use tracing::{debug, error, info};
+
+const MODE_KEY: &str = "notification_mode";
+const DIGEST_TIME_KEY: &str = "notification_digest_time";
+const LAST_DIGEST_KEY: &str = "notification_last_digest_date";
+/// Goals already reported as breached, as "<local date>;<goal>,<goal>"
+const GOAL_BREACHES_KEY: &str = "notification_goal_breaches";
+const DEFAULT_DIGEST_TIME: &str = "18:00";
+
+/// How often the scheduler evaluates goals for breaches
+const GOAL_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+  GoalBreach,
+  SyncError,
+}
+
+impl NotificationKind {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      NotificationKind::GoalBreach => "goal_breach",
+      NotificationKind::SyncError => "sync_error",
+    }
+  }
+}
+
+/// How notifications are delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationMode {
+  /// Show a popup for every item
+  Immediate,
+  /// Collect items into one notification per day
+  Digest,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationSettings {
+  pub mode: NotificationMode,
+  /// Local time of the daily digest, "HH:MM"
+  pub digest_time: String,
+}
+
+/// A summary of accumulated notifications
+#[derive(Debug, Clone, Serialize)]
+pub struct Digest {
+  pub title: String,
+  pub body: String,
+  pub items: Vec<StoredNotification>,
+}
+
+/// Collects goal breaches and sync errors into a queryable feed
+pub struct NotificationCenter {
+  db: Arc<Database>,
+  app_handle: Option<AppHandle>,
+}
+
+impl NotificationCenter {
+  pub fn new(db: Arc<Database>, app_handle: Option<AppHandle>) -> Self {
+    Self { db, app_handle }
+  }
+
+  pub fn get_settings(&self) -> Result<NotificationSettings> {
+    let mode = match self.db.get_setting(MODE_KEY)?.as_deref() {
+      Some("immediate") => NotificationMode::Immediate,
+      _ => NotificationMode::Digest,
+    };
+    let digest_time = self
+      .db
+      .get_setting(DIGEST_TIME_KEY)?
+      .filter(|t| parse_digest_time(t).is_some())
+      .unwrap_or_else(|| DEFAULT_DIGEST_TIME.to_string());
+
+    Ok(NotificationSettings { mode, digest_time })
+  }
+
+  pub fn set_settings(&self, settings: &NotificationSettings) -> Result<()> {
+    if parse_digest_time(&settings.digest_time).is_none() {
+      anyhow::bail!("Invalid digest time '{}', expected HH:MM", settings.digest_time);
+    }
+
+    let mode = match settings.mode {
+      NotificationMode::Immediate => "immediate",
+      NotificationMode::Digest => "digest",
+    };
+    self.db.set_setting(MODE_KEY, mode)?;
+    self.db.set_setting(DIGEST_TIME_KEY, &settings.digest_time)
+  }
+
+  /// Record a notification; it is shown right away only in immediate mode
+  pub fn push(&self, kind: NotificationKind, title: &str, body: &str) -> Result<()> {
+    self.db.insert_notification(kind.as_str(), title, body)?;
+
+    if self.get_settings()?.mode == NotificationMode::Immediate {
+      // Immediate items are delivered now, so keep them out of the digest
+      self.db.take_undigested_notifications()?;
+      self.show(title, body);
+    }
+
+    Ok(())
+  }
+
+  /// Notify about goals over today's budget, each once per day; returns how
+  /// many were new
+  pub fn check_goals(&self, now: DateTime<Utc>) -> Result<usize> {
+    let statuses = goals::goal_statuses(&self.db, now)?;
+    let today = now.with_timezone(&Local).date_naive().to_string();
+
+    let stored = self.db.get_setting(GOAL_BREACHES_KEY)?;
+    let mut reported: Vec<String> = match stored.as_deref().and_then(|value| value.split_once(';')) {
+      Some((day, goals)) if day == today => goals.split(',').filter(|goal| !goal.is_empty()).map(str::to_string).collect(),
+      _ => Vec::new(),
+    };
+
+    let mut pushed = 0;
+    for status in statuses.iter().filter(|status| status.over_budget) {
+      let key = format!("{}:{}", status.goal.scope.as_str(), status.goal.category);
+      if reported.contains(&key) {
+        continue;
+      }
+      let (title, body) = breach_message(status);
+      self.push(NotificationKind::GoalBreach, &title, &body)?;
+      reported.push(key);
+      pushed += 1;
+    }
+
+    if pushed > 0 {
+      self.db.set_setting(GOAL_BREACHES_KEY, &format!("{};{}", today, reported.join(",")))?;
+    }
+    Ok(pushed)
+  }
+
+  pub fn feed(&self, unread_only: bool, limit: i32) -> Result<Vec<StoredNotification>> {
+    self.db.get_notifications(unread_only, limit)
+  }
+
+  pub fn mark_read(&self, ids: &[String]) -> Result<()> {
+    self.db.mark_notifications_read(ids)
+  }
+
+  /// Build today's digest if the configured time has passed and it hasn't been sent yet
+  pub fn take_due_digest(&self, now: DateTime<Local>) -> Result<Option<Digest>> {
+    let settings = self.get_settings()?;
+    if settings.mode != NotificationMode::Digest {
+      return Ok(None);
+    }
+
+    let digest_time = parse_digest_time(&settings.digest_time)
+      .unwrap_or_else(|| parse_digest_time(DEFAULT_DIGEST_TIME).unwrap());
+    let today = now.date_naive().to_string();
+
+    if now.time() < digest_time || self.db.get_setting(LAST_DIGEST_KEY)?.as_deref() == Some(today.as_str()) {
+      return Ok(None);
+    }
+
+    self.db.set_setting(LAST_DIGEST_KEY, &today)?;
+
+    let items = self.db.take_undigested_notifications()?;
+    Ok(build_digest(items))
+  }
+
+  fn show(&self, title: &str, body: &str) {
+    if let Some(app) = &self.app_handle {
+      if let Err(e) = app.notification().builder().title(title).body(body).show() {
+        error!("Failed to show notification: {}", e);
+      }
+    }
+  }
+
+  /// Check once a minute whether the daily digest is due, and every few
+  /// minutes whether a goal has been breached
+  pub fn start_digest_scheduler(self: Arc<Self>) {
+    tauri::async_runtime::spawn(async move {
+      let mut ticker = tokio::time::interval(Duration::from_secs(60));
+      let mut last_goal_check: Option<std::time::Instant> = None;
+
+      loop {
+        ticker.tick().await;
+
+        if last_goal_check.map_or(true, |checked| checked.elapsed() >= GOAL_CHECK_INTERVAL) {
+          last_goal_check = Some(std::time::Instant::now());
+          match self.check_goals(Utc::now()) {
+            Ok(0) => debug!("No new goal breaches"),
+            Ok(count) => info!("{} goals breached", count),
+            Err(e) => error!("Failed to check goals: {}", e),
+          }
+        }
+
+        match self.take_due_digest(Local::now()) {
+          Ok(Some(digest)) => {
+            info!("Delivering notification digest with {} items", digest.items.len());
+            self.show(&digest.title, &digest.body);
+          }
+          Ok(None) => debug!("No notification digest due"),
+          Err(e) => error!("Failed to build notification digest: {}", e),
+        }
+      }
+    });
+  }
+}
+
+fn parse_digest_time(value: &str) -> Option<NaiveTime> {
+  NaiveTime::parse_from_str(value, "%H:%M").ok()
+}
+
+/// Title and body of a goal breach notification
+fn breach_message(status: &GoalStatus) -> (String, String) {
+  let title = format!("Over your {} goal", status.goal.category);
+  let body = if status.used_today_seconds > status.budget_today_seconds {
+    format!(
+      "{} min used today of a {} min budget",
+      status.used_today_seconds / 60,
+      status.budget_today_seconds / 60
+    )
+  } else {
+    format!(
+      "{} min used this week of {} min",
+      status.period_used_seconds / 60,
+      status.period_limit_seconds / 60
+    )
+  };
+  (title, body)
+}
+
+/// Summarize items as e.g. "2 sync errors, 1 goal breach"
+fn build_digest(items: Vec<StoredNotification>) -> Option<Digest> {
+  if items.is_empty() {
+    return None;
+  }
+
+  let count = |kind: NotificationKind| items.iter().filter(|n| n.kind == kind.as_str()).count();
+  let parts: Vec<String> = [
+    (count(NotificationKind::GoalBreach), "goal breach", "goal breaches"),
+    (count(NotificationKind::SyncError), "sync error", "sync errors"),
+  ]
+  .iter()
+  .filter(|(n, _, _)| *n > 0)
+  .map(|(n, one, many)| format!("{} {}", n, if *n == 1 { one } else { many }))
+  .collect();
+
+  Some(Digest {
+    title: "Your daily Lifespan digest".to_string(),
+    body: parts.join(", "),
+    items,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::{Goal, GoalScope, NewEvent, StorageBackend};
+  use chrono::TimeZone;
+  use tempfile::NamedTempFile;
+
+  fn create_test_center() -> (NotificationCenter, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (NotificationCenter::new(Arc::new(db), None), temp_file)
+  }
+
+  fn at(hour: u32, minute: u32) -> DateTime<Local> {
+    Local.with_ymd_and_hms(2024, 3, 1, hour, minute, 0).unwrap()
+  }
+
+  #[test]
+  fn test_default_settings() {
+    let (center, _temp) = create_test_center();
+    let settings = center.get_settings().unwrap();
+
+    assert_eq!(settings.mode, NotificationMode::Digest);
+    assert_eq!(settings.digest_time, "18:00");
+  }
+
+  #[test]
+  fn test_invalid_digest_time_rejected() {
+    let (center, _temp) = create_test_center();
+    let result = center.set_settings(&NotificationSettings {
+      mode: NotificationMode::Digest,
+      digest_time: "25:99".to_string(),
+    });
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn test_digest_not_due_before_configured_time() {
+    let (center, _temp) = create_test_center();
+    center.push(NotificationKind::SyncError, "Sync failed", "Network error").unwrap();
+
+    assert!(center.take_due_digest(at(9, 0)).unwrap().is_none());
+  }
+
+  #[test]
+  fn test_digest_delivered_once_per_day() {
+    let (center, _temp) = create_test_center();
+    center.push(NotificationKind::SyncError, "Sync failed", "Network error").unwrap();
+    center.push(NotificationKind::SyncError, "Sync failed", "Timeout").unwrap();
+    center.push(NotificationKind::GoalBreach, "Goal", "Over budget").unwrap();
+
+    let digest = center.take_due_digest(at(18, 30)).unwrap().unwrap();
+    assert_eq!(digest.items.len(), 3);
+    assert_eq!(digest.body, "1 goal breach, 2 sync errors");
+
+    center.push(NotificationKind::GoalBreach, "Goal", "Over budget").unwrap();
+    assert!(center.take_due_digest(at(19, 0)).unwrap().is_none());
+  }
+
+  #[test]
+  fn test_feed_keeps_items_after_digest() {
+    let (center, _temp) = create_test_center();
+    center.push(NotificationKind::GoalBreach, "Goal", "Over budget").unwrap();
+    center.take_due_digest(at(20, 0)).unwrap();
+
+    let feed = center.feed(true, 10).unwrap();
+    assert_eq!(feed.len(), 1);
+
+    center.mark_read(&[feed[0].id.clone()]).unwrap();
+    assert!(center.feed(true, 10).unwrap().is_empty());
+  }
+
+  #[test]
+  fn test_immediate_mode_skips_digest() {
+    let (center, _temp) = create_test_center();
+    center.set_settings(&NotificationSettings {
+      mode: NotificationMode::Immediate,
+      digest_time: "18:00".to_string(),
+    }).unwrap();
+
+    center.push(NotificationKind::GoalBreach, "Goal", "Over budget").unwrap();
+    assert!(center.take_due_digest(at(20, 0)).unwrap().is_none());
+  }
+
+  #[test]
+  fn test_goal_breaches_are_reported_once_a_day() {
+    let (center, _temp) = create_test_center();
+    center
+      .db
+      .set_goal(&Goal {
+        category: "entertainment".to_string(),
+        scope: GoalScope::Daily,
+        limit_minutes: 10,
+        carry_over: false,
+      })
+      .unwrap();
+    // Midday, so the usage falls on the day checked
+    let started = Local::now().date_naive().and_hms_opt(12, 0, 0).unwrap().and_local_timezone(Local).unwrap().with_timezone(&Utc);
+    let now = started + chrono::Duration::minutes(25);
+    assert_eq!(center.check_goals(now).unwrap(), 0);
+
+    center
+      .db
+      .insert_events(&[NewEvent {
+        event_type: "app_usage".to_string(),
+        timestamp: started,
+        duration: 20 * 60,
+        app_name: "spotify.exe".to_string(),
+        window_title: None,
+        url_domain: None,
+        remote_session: false,
+      }])
+      .unwrap();
+
+    assert_eq!(center.check_goals(now).unwrap(), 1);
+    assert_eq!(center.check_goals(now).unwrap(), 0);
+
+    let feed = center.feed(false, 10).unwrap();
+    assert_eq!(feed.len(), 1);
+    assert_eq!(feed[0].kind, "goal_breach");
+    assert_eq!(feed[0].title, "Over your entertainment goal");
+  }
+}
@@ -0,0 +1,355 @@
+//! Named profiles (e.g. "work" and "personal"), each with its own data.
+//!
+//! A profile is a data directory: its own local.db (and with it its own
+//! server config, settings and rules), archives, recordings and session lock.
+//! The "default" profile uses the app data directory itself, so installs from
+//! before profiles keep their data. `profiles.json` in the app data directory
+//! lists the profiles and which one is active; `--profile <name>` on the
+//! command line picks one for a single run. Switching profiles at runtime
+//! restarts the app into the new profile, so the collector, sync client and
+//! schedulers all come up against its database: every command holds the
+//! database as managed state, which Tauri can't swap in a running app. The
+//! old process stops the collector, flushes and releases its session lock
+//! before starting the new one, which waits (`--after-pid`) for it to exit.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Command-line flag selecting the profile for this run
+pub const PROFILE_ARG: &str = "--profile";
+
+/// Command-line flag naming the process a relaunch waits for
+pub const AFTER_PID_ARG: &str = "--after-pid";
+
+const REGISTRY_FILE_NAME: &str = "profiles.json";
+const PROFILES_DIR_NAME: &str = "profiles";
+const MAX_PROFILE_NAME_CHARS: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+  pub name: String,
+  pub data_dir: PathBuf,
+  /// Selected for the next start (not necessarily the running profile)
+  pub active: bool,
+}
+
+/// Contents of profiles.json
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Registry {
+  #[serde(default)]
+  active: Option<String>,
+  /// Profiles besides the default one, in creation order
+  #[serde(default)]
+  profiles: Vec<String>,
+}
+
+/// The profile this process is running; managed as app state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunningProfile {
+  pub name: String,
+  pub data_dir: PathBuf,
+}
+
+pub struct ProfileStore {
+  base_dir: PathBuf,
+}
+
+fn validate_name(name: &str) -> Result<String> {
+  let name = name.trim().to_lowercase();
+  if name.is_empty() || name.chars().count() > MAX_PROFILE_NAME_CHARS {
+    bail!("Profile names must be 1 to {} characters", MAX_PROFILE_NAME_CHARS);
+  }
+  if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+    bail!("Profile names may only contain letters, digits, '-' and '_'");
+  }
+  Ok(name)
+}
+
+/// Value of `--profile <name>` or `--profile=<name>`, if given
+fn profile_from_args(args: impl IntoIterator<Item = String>) -> Option<String> {
+  let mut args = args.into_iter();
+  while let Some(arg) = args.next() {
+    if arg == PROFILE_ARG {
+      return args.next();
+    }
+    if let Some(name) = arg.strip_prefix(PROFILE_ARG).and_then(|rest| rest.strip_prefix('=')) {
+      return Some(name.to_string());
+    }
+  }
+  None
+}
+
+/// `args` (including the program) with any `--profile` and `--after-pid`
+/// flags replaced by `--profile <name> --after-pid <pid>`, for relaunching
+/// into another profile once process `pid` has exited
+pub fn relaunch_args(args: impl IntoIterator<Item = String>, name: &str, pid: u32) -> Vec<String> {
+  let mut relaunch = Vec::new();
+  let mut args = args.into_iter();
+  while let Some(arg) = args.next() {
+    if arg == PROFILE_ARG || arg == AFTER_PID_ARG {
+      args.next();
+    } else if !arg.starts_with(&format!("{}=", PROFILE_ARG)) {
+      relaunch.push(arg);
+    }
+  }
+  relaunch.extend([PROFILE_ARG.to_string(), name.to_string(), AFTER_PID_ARG.to_string(), pid.to_string()]);
+  relaunch
+}
+
+/// Value of `--after-pid <pid>`, if given
+pub fn after_pid_from_args(args: impl IntoIterator<Item = String>) -> Option<u32> {
+  let mut args = args.into_iter();
+  while let Some(arg) = args.next() {
+    if arg == AFTER_PID_ARG {
+      return args.next().and_then(|pid| pid.parse().ok());
+    }
+  }
+  None
+}
+
+/// Wait until process `pid` has exited; returns false if it is still running after `timeout`
+pub fn wait_for_exit(pid: u32, timeout: Duration) -> bool {
+  let deadline = Instant::now() + timeout;
+  while process_running(pid) {
+    if Instant::now() >= deadline {
+      return false;
+    }
+    std::thread::sleep(Duration::from_millis(100));
+  }
+  true
+}
+
+#[cfg(windows)]
+fn process_running(pid: u32) -> bool {
+  use windows::Win32::Foundation::{CloseHandle, WAIT_TIMEOUT};
+  use windows::Win32::System::Threading::{OpenProcess, WaitForSingleObject, PROCESS_SYNCHRONIZE};
+
+  unsafe {
+    let Ok(handle) = OpenProcess(PROCESS_SYNCHRONIZE, false, pid) else {
+      return false;
+    };
+    let running = WaitForSingleObject(handle, 0) == WAIT_TIMEOUT;
+    let _ = CloseHandle(handle);
+    running
+  }
+}
+
+#[cfg(target_os = "linux")]
+fn process_running(pid: u32) -> bool {
+  Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+#[cfg(target_os = "macos")]
+fn process_running(pid: u32) -> bool {
+  // Signal 0 only checks that the process exists
+  unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+#[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
+fn process_running(_pid: u32) -> bool {
+  false
+}
+
+impl ProfileStore {
+  /// Profiles kept under the app data directory `base_dir`
+  pub fn new(base_dir: &Path) -> Self {
+    Self {
+      base_dir: base_dir.to_path_buf(),
+    }
+  }
+
+  fn registry_path(&self) -> PathBuf {
+    self.base_dir.join(REGISTRY_FILE_NAME)
+  }
+
+  fn load(&self) -> Result<Registry> {
+    match fs::read_to_string(self.registry_path()) {
+      Ok(json) => serde_json::from_str(&json).context("profiles.json is not valid"),
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Registry::default()),
+      Err(e) => Err(e.into()),
+    }
+  }
+
+  fn save(&self, registry: &Registry) -> Result<()> {
+    fs::create_dir_all(&self.base_dir)?;
+    let tmp_path = self.registry_path().with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_string_pretty(registry)?)?;
+    fs::rename(&tmp_path, self.registry_path())?;
+    Ok(())
+  }
+
+  pub fn data_dir(&self, name: &str) -> PathBuf {
+    match name {
+      DEFAULT_PROFILE => self.base_dir.clone(),
+      name => self.base_dir.join(PROFILES_DIR_NAME).join(name),
+    }
+  }
+
+  /// All profiles, the default one first
+  pub fn list(&self) -> Result<Vec<Profile>> {
+    let registry = self.load()?;
+    let active = registry.active.as_deref().unwrap_or(DEFAULT_PROFILE);
+    Ok(
+      std::iter::once(DEFAULT_PROFILE.to_string())
+        .chain(registry.profiles)
+        .map(|name| Profile {
+          data_dir: self.data_dir(&name),
+          active: name == active,
+          name,
+        })
+        .collect(),
+    )
+  }
+
+  /// Add a profile with an empty data directory
+  pub fn create(&self, name: &str) -> Result<Profile> {
+    let name = validate_name(name)?;
+    let mut registry = self.load()?;
+    if name == DEFAULT_PROFILE || registry.profiles.contains(&name) {
+      bail!("Profile {} already exists", name);
+    }
+
+    fs::create_dir_all(self.data_dir(&name))?;
+    registry.profiles.push(name.clone());
+    self.save(&registry)?;
+    Ok(Profile {
+      data_dir: self.data_dir(&name),
+      active: false,
+      name,
+    })
+  }
+
+  /// The existing profile called `name`
+  pub fn get(&self, name: &str) -> Result<Profile> {
+    let name = validate_name(name)?;
+    self
+      .list()?
+      .into_iter()
+      .find(|profile| profile.name == name)
+      .with_context(|| format!("Profile {} does not exist", name))
+  }
+
+  /// Select the profile to use from the next start on
+  pub fn set_active(&self, name: &str) -> Result<()> {
+    let name = validate_name(name)?;
+    let mut registry = self.load()?;
+    if name != DEFAULT_PROFILE && !registry.profiles.contains(&name) {
+      bail!("Profile {} does not exist", name);
+    }
+    registry.active = Some(name);
+    self.save(&registry)
+  }
+
+  /// The profile to run: `--profile` if given (it must exist), else the active one
+  pub fn startup_profile(&self, args: impl IntoIterator<Item = String>) -> Result<RunningProfile> {
+    let registry = self.load()?;
+    let name = match profile_from_args(args) {
+      Some(name) => {
+        let name = validate_name(&name)?;
+        if name != DEFAULT_PROFILE && !registry.profiles.contains(&name) {
+          bail!("Profile {} does not exist", name);
+        }
+        name
+      }
+      // A profile removed from the registry by hand falls back to the default
+      None => registry
+        .active
+        .filter(|name| registry.profiles.contains(name))
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string()),
+    };
+
+    let data_dir = self.data_dir(&name);
+    fs::create_dir_all(&data_dir)?;
+    Ok(RunningProfile { name, data_dir })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::TempDir;
+
+  fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|arg| arg.to_string()).collect()
+  }
+
+  #[test]
+  fn test_default_profile_uses_app_data_dir() {
+    let dir = TempDir::new().unwrap();
+    let store = ProfileStore::new(dir.path());
+
+    let running = store.startup_profile(args(&["lifespan"])).unwrap();
+    assert_eq!(running.name, DEFAULT_PROFILE);
+    assert_eq!(running.data_dir, dir.path());
+
+    let profiles = store.list().unwrap();
+    assert_eq!(profiles.len(), 1);
+    assert!(profiles[0].active);
+  }
+
+  #[test]
+  fn test_create_and_switch_profiles() {
+    let dir = TempDir::new().unwrap();
+    let store = ProfileStore::new(dir.path());
+
+    let work = store.create(" Work ").unwrap();
+    assert_eq!(work.name, "work");
+    assert!(work.data_dir.is_dir());
+    assert!(store.create("work").is_err());
+    assert!(store.create("default").is_err());
+    assert!(store.create("../escape").is_err());
+
+    store.set_active("work").unwrap();
+    let running = store.startup_profile(args(&["lifespan"])).unwrap();
+    assert_eq!(running.name, "work");
+    assert_eq!(running.data_dir, dir.path().join("profiles").join("work"));
+    assert!(store.list().unwrap()[1].active);
+
+    // The command line wins for one run without changing the active profile
+    let running = store.startup_profile(args(&["lifespan", "--profile=default"])).unwrap();
+    assert_eq!(running.name, DEFAULT_PROFILE);
+    assert!(store.startup_profile(args(&["lifespan", "--profile", "missing"])).is_err());
+    assert!(store.set_active("missing").is_err());
+    assert_eq!(store.startup_profile(args(&["lifespan"])).unwrap().name, "work");
+
+    // Looking a profile up doesn't select it
+    assert_eq!(store.get(" DEFAULT ").unwrap().data_dir, dir.path());
+    assert!(store.get("missing").is_err());
+    assert_eq!(store.startup_profile(args(&["lifespan"])).unwrap().name, "work");
+  }
+
+  #[test]
+  fn test_relaunch_args_replace_profile_flag() {
+    assert_eq!(
+      relaunch_args(args(&["lifespan"]), "work", 42),
+      args(&["lifespan", "--profile", "work", "--after-pid", "42"])
+    );
+    let relaunch = relaunch_args(
+      args(&["lifespan", "--profile", "work", "--after-pid", "7", "--minimized", "--profile=x"]),
+      "default",
+      42,
+    );
+    assert_eq!(relaunch, args(&["lifespan", "--minimized", "--profile", "default", "--after-pid", "42"]));
+    assert_eq!(after_pid_from_args(relaunch), Some(42));
+    assert_eq!(after_pid_from_args(args(&["lifespan"])), None);
+  }
+
+  #[test]
+  fn test_wait_for_exit() {
+    assert!(!wait_for_exit(std::process::id(), Duration::from_millis(200)));
+
+    let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+      .arg("--list")
+      .stdout(std::process::Stdio::null())
+      .spawn()
+      .unwrap();
+    let pid = child.id();
+    child.wait().unwrap();
+    assert!(wait_for_exit(pid, Duration::from_secs(5)));
+  }
+}
@@ -0,0 +1,111 @@
+//! Time per virtual desktop / workspace.
+//!
+//! For users who keep work and personal apps on separate desktops. Windows
+//! desktops are identified by GUID, Linux workspaces by number; events
+//! recorded where the desktop couldn't be read are left out.
+
+use crate::database::{AppUsageTotal, Database, StorageBackend};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DesktopTotal {
+  pub desktop: String,
+  pub duration_seconds: i64,
+  pub event_count: i64,
+  /// App usage on this desktop, largest first
+  pub apps: Vec<AppUsageTotal>,
+}
+
+/// Time per virtual desktop for app_usage events in [start, end), largest first
+pub fn desktop_totals(db: &Database, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<DesktopTotal>> {
+  if end <= start {
+    bail!("Report end must be after start");
+  }
+
+  let mut totals: BTreeMap<String, BTreeMap<String, (i64, i64)>> = BTreeMap::new();
+  for event in db.events_between(start, end)? {
+    if event.event_type != "app_usage" {
+      continue;
+    }
+    let Some(desktop) = event.virtual_desktop else {
+      continue;
+    };
+    let entry = totals.entry(desktop).or_default().entry(event.app_name).or_default();
+    entry.0 += event.duration as i64;
+    entry.1 += 1;
+  }
+
+  let mut totals: Vec<DesktopTotal> = totals
+    .into_iter()
+    .map(|(desktop, apps)| {
+      let mut apps: Vec<AppUsageTotal> = apps
+        .into_iter()
+        .map(|(app_name, (duration_seconds, event_count))| AppUsageTotal {
+          app_name,
+          duration_seconds,
+          event_count,
+        })
+        .collect();
+      apps.sort_by(|a, b| b.duration_seconds.cmp(&a.duration_seconds).then(a.app_name.cmp(&b.app_name)));
+      DesktopTotal {
+        desktop,
+        duration_seconds: apps.iter().map(|app| app.duration_seconds).sum(),
+        event_count: apps.iter().map(|app| app.event_count).sum(),
+        apps,
+      }
+    })
+    .collect();
+  totals.sort_by(|a, b| b.duration_seconds.cmp(&a.duration_seconds).then(a.desktop.cmp(&b.desktop)));
+  Ok(totals)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use tempfile::NamedTempFile;
+
+  fn store_desktop_event(db: &Database, app: &str, desktop: Option<&str>, seconds: i64) {
+    let id = db
+      .store_event_sync(&WindowInfo {
+        process_name: app.to_string(),
+        window_title: "Window".to_string(),
+        timestamp: Utc::now(),
+        virtual_desktop: desktop.map(str::to_string),
+        ..Default::default()
+      })
+      .unwrap();
+    let started = db.get_event(&id).unwrap().unwrap().timestamp;
+    db.close_event_sync(&id, started + chrono::Duration::seconds(seconds)).unwrap();
+  }
+
+  #[test]
+  fn test_desktop_totals() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    let start = Utc::now() - chrono::Duration::seconds(1);
+    store_desktop_event(&db, "Code.exe", Some("1"), 300);
+    store_desktop_event(&db, "Slack.exe", Some("1"), 60);
+    store_desktop_event(&db, "Code.exe", Some("1"), 100);
+    store_desktop_event(&db, "Spotify.exe", Some("2"), 500);
+    store_desktop_event(&db, "Discord.exe", None, 900);
+    let end = Utc::now() + chrono::Duration::seconds(1);
+
+    let totals = desktop_totals(&db, start, end).unwrap();
+    let summary: Vec<(&str, i64, i64)> = totals
+      .iter()
+      .map(|t| (t.desktop.as_str(), t.duration_seconds, t.event_count))
+      .collect();
+    assert_eq!(summary, vec![("2", 500, 1), ("1", 460, 3)]);
+
+    let apps: Vec<(&str, i64)> = totals[1]
+      .apps
+      .iter()
+      .map(|app| (app.app_name.as_str(), app.duration_seconds))
+      .collect();
+    assert_eq!(apps, vec![("Code.exe", 400), ("Slack.exe", 60)]);
+  }
+}
@@ -0,0 +1,155 @@
+//! The cross-device timeline and time per device.
+//!
+//! This device's events and the ones other devices synced can cover the same
+//! minutes, e.g. when remoting from a laptop into a desktop. App usage is
+//! credited with `sync::overlap` under the user's overlap policy, so the
+//! combined time counts nothing twice.
+
+use crate::database::{Database, TimelineEvent};
+use crate::sync::overlap::{resolve_overlaps, OverlapPolicy, TimedEvent};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeviceTotal {
+  /// None for this device
+  pub device_id: Option<String>,
+  /// App usage the device recorded
+  pub recorded_seconds: i64,
+  /// What of it counts once time recorded on several devices is shared out
+  pub attributed_seconds: i64,
+  pub event_count: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeviceTotals {
+  /// Largest attributed time first
+  pub devices: Vec<DeviceTotal>,
+  /// Time during which more than one device was recording
+  pub overlap_seconds: i64,
+}
+
+/// Every device's events starting in [start, end), oldest first, each
+/// app_usage event with the time credited to it
+pub fn timeline(db: &Database, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<TimelineEvent>> {
+  let mut events = db.get_timeline(start, end)?;
+  credit_overlaps(&mut events, OverlapPolicy::load(db)?);
+  Ok(events)
+}
+
+/// App usage per device for events in [start, end)
+pub fn device_totals(db: &Database, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<DeviceTotals> {
+  if end <= start {
+    bail!("Report end must be after start");
+  }
+
+  let mut events = db.get_timeline(start, end)?;
+  let overlap_seconds = credit_overlaps(&mut events, OverlapPolicy::load(db)?);
+
+  let mut totals: BTreeMap<Option<String>, (i64, i64, i64)> = BTreeMap::new();
+  for event in events.into_iter().filter(|event| event.event_type == "app_usage") {
+    let entry = totals.entry(event.device_id).or_default();
+    entry.0 += event.duration as i64;
+    entry.1 += event.attributed_seconds;
+    entry.2 += 1;
+  }
+
+  let mut devices: Vec<DeviceTotal> = totals
+    .into_iter()
+    .map(|(device_id, (recorded_seconds, attributed_seconds, event_count))| DeviceTotal {
+      device_id,
+      recorded_seconds,
+      attributed_seconds,
+      event_count,
+    })
+    .collect();
+  devices.sort_by(|a, b| b.attributed_seconds.cmp(&a.attributed_seconds).then(a.device_id.cmp(&b.device_id)));
+  Ok(DeviceTotals { devices, overlap_seconds })
+}
+
+/// Set `attributed_seconds` of the app_usage events; returns the seconds
+/// during which more than one device was recording
+fn credit_overlaps(events: &mut [TimelineEvent], policy: OverlapPolicy) -> i64 {
+  let usage: Vec<usize> = (0..events.len()).filter(|&i| events[i].event_type == "app_usage").collect();
+  let timed: Vec<TimedEvent> = usage
+    .iter()
+    .map(|&i| {
+      let event = &events[i];
+      let start_ms = event.timestamp.timestamp_millis();
+      TimedEvent {
+        event_id: event.id.clone(),
+        // This device's events all share the empty id
+        device_id: event.device_id.clone().unwrap_or_default(),
+        start_ms,
+        end_ms: start_ms + event.duration as i64 * 1000,
+        remote_session: event.remote_session,
+      }
+    })
+    .collect();
+
+  let resolution = resolve_overlaps(&timed, policy);
+  for (&i, attribution) in usage.iter().zip(&resolution.attributions) {
+    events[i].attributed_seconds = (attribution.attributed_ms + 500) / 1000;
+  }
+  (resolution.overlap_ms + 500) / 1000
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use crate::database::RemoteEvent;
+  use chrono::Duration;
+  use tempfile::NamedTempFile;
+
+  #[test]
+  fn test_remote_viewer_loses_overlap_to_the_console() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    let id = db
+      .store_event_sync(&WindowInfo {
+        process_name: "code.exe".to_string(),
+        window_title: "main.rs".to_string(),
+        timestamp: Utc::now(),
+        ..Default::default()
+      })
+      .unwrap();
+    let started = db.get_event(&id).unwrap().unwrap().timestamp;
+    db.close_event_sync(&id, started + Duration::minutes(30)).unwrap();
+
+    // The laptop showed the remote desktop for the whole hour around it
+    let viewer = RemoteEvent {
+      id: "viewer".to_string(),
+      device_id: "laptop".to_string(),
+      event_type: "app_usage".to_string(),
+      timestamp: started - Duration::minutes(15),
+      duration: 3600,
+      app_name: Some("mstsc.exe".to_string()),
+      title: None,
+      category: None,
+      domain: None,
+      remote_session: true,
+    };
+    db.store_remote_events(&[viewer], Utc::now()).unwrap();
+
+    let (from, to) = (started - Duration::hours(1), started + Duration::hours(1));
+    let timeline = timeline(&db, from, to).unwrap();
+    let credited = |id: &str| timeline.iter().find(|event| event.id == id).unwrap().attributed_seconds;
+    assert_eq!(credited(&id), 30 * 60);
+    assert_eq!(credited("viewer"), 30 * 60);
+
+    let totals = device_totals(&db, from, to).unwrap();
+    assert_eq!(totals.overlap_seconds, 30 * 60);
+    let laptop = totals.devices.iter().find(|device| device.device_id.as_deref() == Some("laptop")).unwrap();
+    assert_eq!((laptop.recorded_seconds, laptop.attributed_seconds), (3600, 30 * 60));
+
+    // Split evenly, each device gets half of the shared half hour
+    OverlapPolicy::Split.save(&db).unwrap();
+    let timeline = super::timeline(&db, from, to).unwrap();
+    let credited = |id: &str| timeline.iter().find(|event| event.id == id).unwrap().attributed_seconds;
+    assert_eq!(credited(&id), 15 * 60);
+    assert_eq!(credited("viewer"), 45 * 60);
+  }
+}
@@ -0,0 +1,124 @@
+//! Time per document or per project.
+//!
+//! Built from the `document` field the collector parses out of editor window
+//! titles. Documents look like "project/file" when the editor shows its
+//! workspace, so grouping by project uses the part before the '/'; documents
+//! without a project are left out of project totals.
+
+use crate::database::{Database, StorageBackend};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentGrouping {
+  #[default]
+  Document,
+  /// Repository / workspace
+  Project,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DocumentTotal {
+  pub name: String,
+  pub duration_seconds: i64,
+  pub event_count: i64,
+}
+
+/// Time per document or project for events in [start, end), largest first
+pub fn document_totals(
+  db: &Database,
+  start: DateTime<Utc>,
+  end: DateTime<Utc>,
+  grouping: DocumentGrouping,
+) -> Result<Vec<DocumentTotal>> {
+  if end <= start {
+    bail!("Report end must be after start");
+  }
+
+  let mut totals: BTreeMap<String, (i64, i64)> = BTreeMap::new();
+  for event in db.events_between(start, end)? {
+    let Some(document) = event.document.as_deref() else {
+      continue;
+    };
+    let name = match grouping {
+      DocumentGrouping::Document => document,
+      DocumentGrouping::Project => match document.split_once('/') {
+        Some((project, _file)) => project,
+        None => continue,
+      },
+    };
+
+    let entry = totals.entry(name.to_string()).or_default();
+    entry.0 += event.duration as i64;
+    entry.1 += 1;
+  }
+
+  let mut totals: Vec<DocumentTotal> = totals
+    .into_iter()
+    .map(|(name, (duration_seconds, event_count))| DocumentTotal {
+      name,
+      duration_seconds,
+      event_count,
+    })
+    .collect();
+  totals.sort_by(|a, b| b.duration_seconds.cmp(&a.duration_seconds).then(a.name.cmp(&b.name)));
+  Ok(totals)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn store_document_event(db: &Database, document: Option<&str>, seconds: i64) {
+    let id = db
+      .store_event_sync(&WindowInfo {
+        process_name: "Code.exe".to_string(),
+        window_title: "Window".to_string(),
+        timestamp: Utc::now(),
+        document: document.map(str::to_string),
+        ..Default::default()
+      })
+      .unwrap();
+    let started = db.get_event(&id).unwrap().unwrap().timestamp;
+    db.close_event_sync(&id, started + chrono::Duration::seconds(seconds)).unwrap();
+  }
+
+  #[test]
+  fn test_document_and_project_totals() {
+    let (db, _temp) = create_test_db();
+    let start = Utc::now() - chrono::Duration::seconds(1);
+    store_document_event(&db, Some("lifespan/main.rs"), 120);
+    store_document_event(&db, Some("lifespan/lib.rs"), 60);
+    store_document_event(&db, Some("lifespan/main.rs"), 30);
+    store_document_event(&db, Some("Report.docx"), 90);
+    store_document_event(&db, None, 600);
+    let end = Utc::now() + chrono::Duration::seconds(1);
+
+    let documents = document_totals(&db, start, end, DocumentGrouping::Document).unwrap();
+    let names: Vec<&str> = documents.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, vec!["lifespan/main.rs", "Report.docx", "lifespan/lib.rs"]);
+    assert_eq!(documents[0].duration_seconds, 150);
+    assert_eq!(documents[0].event_count, 2);
+
+    let projects = document_totals(&db, start, end, DocumentGrouping::Project).unwrap();
+    assert_eq!(
+      projects,
+      vec![DocumentTotal {
+        name: "lifespan".to_string(),
+        duration_seconds: 210,
+        event_count: 3,
+      }]
+    );
+  }
+}
@@ -0,0 +1,124 @@
+//! App usage grouped by executable rather than process name.
+//!
+//! Events with the same binary (by cached SHA-256) are one app even when the
+//! file was renamed or copied elsewhere; without a hash the executable path
+//! is the key, and without a path the process name.
+
+use crate::database::{Database, StorageBackend};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExecutableTotal {
+  /// Process names the executable ran under
+  pub app_names: Vec<String>,
+  pub paths: Vec<String>,
+  pub sha256: Option<String>,
+  pub duration_seconds: i64,
+  pub event_count: i64,
+}
+
+#[derive(Default)]
+struct Group {
+  app_names: BTreeSet<String>,
+  paths: BTreeSet<String>,
+  sha256: Option<String>,
+  duration_seconds: i64,
+  event_count: i64,
+}
+
+/// app_usage time per executable for events in [start, end), largest first
+pub fn executable_totals(db: &Database, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<ExecutableTotal>> {
+  if end <= start {
+    bail!("Report end must be after start");
+  }
+
+  let hashes = db.get_executable_hashes()?;
+  let mut groups: BTreeMap<String, Group> = BTreeMap::new();
+  for event in db.events_between(start, end)? {
+    if event.event_type != "app_usage" {
+      continue;
+    }
+    let sha256 = event.process_path.as_ref().and_then(|path| hashes.get(path));
+    let key = match (sha256, &event.process_path) {
+      (Some(sha256), _) => format!("sha256:{}", sha256),
+      (None, Some(path)) => format!("path:{}", path),
+      (None, None) => format!("name:{}", event.app_name),
+    };
+
+    let group = groups.entry(key).or_default();
+    group.sha256 = sha256.cloned();
+    group.app_names.insert(event.app_name);
+    group.paths.extend(event.process_path);
+    group.duration_seconds += event.duration as i64;
+    group.event_count += 1;
+  }
+
+  let mut totals: Vec<ExecutableTotal> = groups
+    .into_values()
+    .map(|group| ExecutableTotal {
+      app_names: group.app_names.into_iter().collect(),
+      paths: group.paths.into_iter().collect(),
+      sha256: group.sha256,
+      duration_seconds: group.duration_seconds,
+      event_count: group.event_count,
+    })
+    .collect();
+  totals.sort_by(|a, b| b.duration_seconds.cmp(&a.duration_seconds).then(a.app_names.cmp(&b.app_names)));
+  Ok(totals)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use crate::database::ExecutableHash;
+  use tempfile::NamedTempFile;
+
+  fn store_app_event(db: &Database, app: &str, path: Option<&str>, seconds: i64) {
+    let id = db
+      .store_event_sync(&WindowInfo {
+        process_name: app.to_string(),
+        window_title: "Window".to_string(),
+        timestamp: Utc::now(),
+        process_path: path.map(str::to_string),
+        ..Default::default()
+      })
+      .unwrap();
+    let started = db.get_event(&id).unwrap().unwrap().timestamp;
+    db.close_event_sync(&id, started + chrono::Duration::seconds(seconds)).unwrap();
+  }
+
+  #[test]
+  fn test_renamed_executables_grouped_by_hash() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    let hash = |sha256: &str| ExecutableHash {
+      size: 1,
+      modified_at: 0,
+      sha256: sha256.to_string(),
+    };
+    db.set_executable_hash(r"C:\Program Files\Notepad++\notepad++.exe", &hash("abc")).unwrap();
+    db.set_executable_hash(r"D:\Tools\npp-portable.exe", &hash("abc")).unwrap();
+
+    let start = Utc::now() - chrono::Duration::seconds(1);
+    store_app_event(&db, "notepad++.exe", Some(r"C:\Program Files\Notepad++\notepad++.exe"), 100);
+    store_app_event(&db, "npp-portable.exe", Some(r"D:\Tools\npp-portable.exe"), 50);
+    store_app_event(&db, "code.exe", Some(r"C:\VS Code\code.exe"), 120);
+    store_app_event(&db, "Safari", None, 10);
+    let end = Utc::now() + chrono::Duration::seconds(1);
+
+    let totals = executable_totals(&db, start, end).unwrap();
+    assert_eq!(totals.len(), 3);
+    assert_eq!(totals[0].app_names, vec!["notepad++.exe", "npp-portable.exe"]);
+    assert_eq!(totals[0].sha256.as_deref(), Some("abc"));
+    assert_eq!(totals[0].duration_seconds, 150);
+    assert_eq!(totals[0].paths.len(), 2);
+    assert_eq!(totals[1].app_names, vec!["code.exe"]);
+    assert_eq!(totals[1].sha256, None);
+    assert_eq!(totals[2].app_names, vec!["Safari"]);
+    assert!(totals[2].paths.is_empty());
+  }
+}
@@ -0,0 +1,270 @@
+//! Usage forecasts from seasonal (same weekday) averages.
+//!
+//! Each weekday's expected hours for a category is its average over the
+//! previous LOOKBACK_WEEKS full weeks. The current week is projected by adding
+//! the expected hours still to come to what has been recorded so far, which
+//! drives the "on track / behind" widget.
+
+use crate::database::{Database, StorageBackend};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Timelike, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Full weeks of history the weekday averages are taken over
+const LOOKBACK_WEEKS: i64 = 4;
+
+/// Longest horizon a forecast can be asked for
+pub const MAX_HORIZON_DAYS: u32 = 28;
+
+/// Week-to-date usage at or above this share of the expectation counts as on track
+const ON_TRACK_RATIO: f64 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForecastStatus {
+  OnTrack,
+  Behind,
+  /// No usage in the lookback period to compare against
+  NoHistory,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DayForecast {
+  pub date: NaiveDate,
+  pub expected_hours: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Forecast {
+  pub category: String,
+  /// Monday of the current week
+  pub week_start: NaiveDate,
+  /// Hours recorded this week so far
+  pub actual_hours: f64,
+  /// Hours the weekday averages expected by now
+  pub expected_to_date_hours: f64,
+  /// Recorded hours plus the expected remainder of the week
+  pub projected_week_hours: f64,
+  /// Average of the lookback weeks' totals
+  pub typical_week_hours: f64,
+  pub status: ForecastStatus,
+  /// Expected hours for today and the following days
+  pub days: Vec<DayForecast>,
+}
+
+/// Forecast `category` usage for `horizon_days` days starting today (local time)
+pub fn forecast(db: &Database, category: &str, horizon_days: u32, now: DateTime<Utc>) -> Result<Forecast> {
+  if horizon_days == 0 || horizon_days > MAX_HORIZON_DAYS {
+    bail!("Forecast horizon must be between 1 and {} days", MAX_HORIZON_DAYS);
+  }
+
+  let local_now = now.with_timezone(&Local);
+  let today = local_now.date_naive();
+  let week_start = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+  let lookback_start = week_start - Duration::weeks(LOOKBACK_WEEKS);
+
+  let daily_hours = daily_category_hours(db, category, lookback_start, now)?;
+  let hours_on = |date: NaiveDate| daily_hours.get(&date).copied().unwrap_or(0.0);
+
+  // Same-weekday average over the lookback weeks (days without usage count as zero)
+  let has_history = daily_hours.keys().any(|date| *date < week_start);
+  let mut weekday_average = [0.0; 7];
+  for (weekday, average) in weekday_average.iter_mut().enumerate() {
+    let total: f64 = (1..=LOOKBACK_WEEKS)
+      .map(|week| hours_on(week_start - Duration::weeks(week) + Duration::days(weekday as i64)))
+      .sum();
+    *average = total / LOOKBACK_WEEKS as f64;
+  }
+  let expected_on = |date: NaiveDate| weekday_average[date.weekday().num_days_from_monday() as usize];
+
+  let elapsed_today = local_now.num_seconds_from_midnight() as f64 / 86_400.0;
+  let mut actual_hours = 0.0;
+  let mut expected_to_date_hours = 0.0;
+  let mut date = week_start;
+  while date < today {
+    actual_hours += hours_on(date);
+    expected_to_date_hours += expected_on(date);
+    date = date.succ_opt().unwrap();
+  }
+  let today_actual = hours_on(today);
+  actual_hours += today_actual;
+  expected_to_date_hours += expected_on(today) * elapsed_today;
+
+  let remaining_today = (expected_on(today) - today_actual).max(0.0);
+  let remaining_week: f64 = (1..7 - today.weekday().num_days_from_monday() as i64)
+    .map(|offset| expected_on(today + Duration::days(offset)))
+    .sum();
+
+  let status = if !has_history {
+    ForecastStatus::NoHistory
+  } else if actual_hours >= expected_to_date_hours * ON_TRACK_RATIO {
+    ForecastStatus::OnTrack
+  } else {
+    ForecastStatus::Behind
+  };
+
+  let days = (0..horizon_days as i64)
+    .map(|offset| {
+      let date = today + Duration::days(offset);
+      DayForecast {
+        date,
+        expected_hours: round_hours(expected_on(date)),
+      }
+    })
+    .collect();
+
+  Ok(Forecast {
+    category: category.to_string(),
+    week_start,
+    actual_hours: round_hours(actual_hours),
+    expected_to_date_hours: round_hours(expected_to_date_hours),
+    projected_week_hours: round_hours(actual_hours + remaining_today + remaining_week),
+    typical_week_hours: round_hours(weekday_average.iter().sum()),
+    status,
+    days,
+  })
+}
+
+/// Hours of `category` app usage per local day from `first_day` up to `now`
+fn daily_category_hours(
+  db: &Database,
+  category: &str,
+  first_day: NaiveDate,
+  now: DateTime<Utc>,
+) -> Result<HashMap<NaiveDate, f64>> {
+  let rules = db.get_category_rules()?;
+
+  // Pad by a day on either side; events are bucketed by their own local date
+  let start = first_day.and_hms_opt(0, 0, 0).unwrap().and_utc() - Duration::days(1);
+  let events = db.events_between(start, now + Duration::days(1))?;
+
+  let mut hours = HashMap::new();
+  for event in events.iter().filter(|e| e.event_type == "app_usage") {
+    let date = event.local_date();
+    if date < first_day || rules.categorize_with_path(&event.app_name, event.process_path.as_deref()) != category {
+      continue;
+    }
+    *hours.entry(date).or_insert(0.0) += event.duration as f64 / 3600.0;
+  }
+  Ok(hours)
+}
+
+fn round_hours(hours: f64) -> f64 {
+  (hours * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::NewEvent;
+  use chrono::TimeZone;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  /// Local noon on `date`, so the event's local date is unambiguous
+  fn local_noon(date: NaiveDate) -> DateTime<Utc> {
+    Local
+      .from_local_datetime(&date.and_hms_opt(12, 0, 0).unwrap())
+      .unwrap()
+      .with_timezone(&Utc)
+  }
+
+  fn code_event(date: NaiveDate, hours: i32) -> NewEvent {
+    NewEvent {
+      event_type: "app_usage".to_string(),
+      timestamp: local_noon(date),
+      duration: hours * 3600,
+      app_name: "code.exe".to_string(),
+      window_title: None,
+      url_domain: None,
+      remote_session: false,
+    }
+  }
+
+  /// Wednesday 2024-06-12
+  fn wednesday() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2024, 6, 12).unwrap()
+  }
+
+  /// Four past weeks with 4h of development every weekday
+  fn seed_history(db: &Database) {
+    let week_start = NaiveDate::from_ymd_opt(2024, 6, 10).unwrap();
+    let mut events = Vec::new();
+    for week in 1..=LOOKBACK_WEEKS {
+      for day in 0..5 {
+        events.push(code_event(week_start - Duration::weeks(week) + Duration::days(day), 4));
+      }
+    }
+    db.insert_events(&events).unwrap();
+  }
+
+  #[test]
+  fn test_weekday_averages_and_projection() {
+    let (db, _temp) = create_test_db();
+    seed_history(&db);
+    // Monday and Tuesday of the current week: 4h each
+    db.insert_events(&[
+      code_event(NaiveDate::from_ymd_opt(2024, 6, 10).unwrap(), 4),
+      code_event(NaiveDate::from_ymd_opt(2024, 6, 11).unwrap(), 4),
+    ])
+    .unwrap();
+
+    let result = forecast(&db, "development", 7, local_noon(wednesday())).unwrap();
+
+    assert_eq!(result.week_start, NaiveDate::from_ymd_opt(2024, 6, 10).unwrap());
+    assert_eq!(result.typical_week_hours, 20.0);
+    assert_eq!(result.actual_hours, 8.0);
+    assert_eq!(result.expected_to_date_hours, 10.0);
+    // 8h so far + 4h expected today + Thursday and Friday
+    assert_eq!(result.projected_week_hours, 20.0);
+    assert_eq!(result.status, ForecastStatus::Behind);
+
+    assert_eq!(result.days.len(), 7);
+    assert_eq!(result.days[0], DayForecast { date: wednesday(), expected_hours: 4.0 });
+    // Saturday has no history
+    assert_eq!(result.days[3].expected_hours, 0.0);
+  }
+
+  #[test]
+  fn test_on_track() {
+    let (db, _temp) = create_test_db();
+    seed_history(&db);
+    db.insert_events(&[
+      code_event(NaiveDate::from_ymd_opt(2024, 6, 10).unwrap(), 4),
+      code_event(NaiveDate::from_ymd_opt(2024, 6, 11).unwrap(), 4),
+      code_event(wednesday(), 2),
+    ])
+    .unwrap();
+
+    let result = forecast(&db, "development", 1, local_noon(wednesday())).unwrap();
+
+    assert_eq!(result.actual_hours, 10.0);
+    assert_eq!(result.status, ForecastStatus::OnTrack);
+  }
+
+  #[test]
+  fn test_other_categories_ignored_and_no_history() {
+    let (db, _temp) = create_test_db();
+    seed_history(&db);
+
+    let result = forecast(&db, "entertainment", 3, local_noon(wednesday())).unwrap();
+
+    assert_eq!(result.typical_week_hours, 0.0);
+    assert_eq!(result.status, ForecastStatus::NoHistory);
+  }
+
+  #[test]
+  fn test_horizon_bounds() {
+    let (db, _temp) = create_test_db();
+    let now = Utc.with_ymd_and_hms(2024, 6, 12, 12, 0, 0).unwrap();
+
+    assert!(forecast(&db, "development", 0, now).is_err());
+    assert!(forecast(&db, "development", MAX_HORIZON_DAYS + 1, now).is_err());
+  }
+}
@@ -0,0 +1,185 @@
+//! Aggregate reports over local events, and over every device's (see
+//! `devices`).
+//!
+//! Category totals can be computed with the current categorization rules or
+//! "as of" the rules that were in effect when each event happened, so past
+//! reports don't shift every time a rule is edited.
+
+mod desktops;
+mod devices;
+mod documents;
+mod executables;
+mod forecast;
+mod trends;
+
+pub use desktops::{desktop_totals, DesktopTotal};
+pub use devices::{device_totals, timeline, DeviceTotal, DeviceTotals};
+pub use documents::{document_totals, DocumentGrouping, DocumentTotal};
+pub use executables::{executable_totals, ExecutableTotal};
+pub use forecast::{forecast, Forecast};
+pub use trends::{usage_trend, UsageTrend};
+
+pub use crate::database::CategoryTotal;
+
+use crate::database::{Database, StorageBackend};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Which rule set to categorize events with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RulesMode {
+  /// Rules as they are now
+  #[default]
+  Current,
+  /// Rules that were active when each event was recorded
+  AsOf,
+}
+
+/// Total app usage per category for events in [start, end), largest first
+pub fn category_totals(
+  db: &Database,
+  start: DateTime<Utc>,
+  end: DateTime<Utc>,
+  mode: RulesMode,
+) -> Result<Vec<CategoryTotal>> {
+  if end <= start {
+    bail!("Report end must be after start");
+  }
+
+  // With the current rules the totals can be summed in SQL
+  if mode == RulesMode::Current {
+    return db.get_category_totals(start, end);
+  }
+
+  let events = db.events_between(start, end)?;
+  let mut rules = db.get_category_rules_as_of(start)?;
+  let mut pending_changes = db.get_rule_changes_between(start, end)?;
+  pending_changes.reverse();
+
+  let mut totals: BTreeMap<String, (i64, i64)> = BTreeMap::new();
+  for event in events.iter().filter(|e| e.event_type == "app_usage") {
+    // Replay rule changes made before this event (events are oldest first)
+    while pending_changes.last().is_some_and(|change| change.changed_at <= event.timestamp) {
+      let change = pending_changes.pop().unwrap();
+      rules.apply(change.pattern, change.category);
+    }
+
+    let entry = totals.entry(rules.categorize_with_path(&event.app_name, event.process_path.as_deref())).or_default();
+    entry.0 += event.duration as i64;
+    entry.1 += 1;
+  }
+
+  Ok(sorted_totals(totals))
+}
+
+fn sorted_totals(totals: BTreeMap<String, (i64, i64)>) -> Vec<CategoryTotal> {
+  let mut totals: Vec<CategoryTotal> = totals
+    .into_iter()
+    .map(|(category, (duration_seconds, event_count))| CategoryTotal {
+      category,
+      duration_seconds,
+      event_count,
+    })
+    .collect();
+  totals.sort_by(|a, b| b.duration_seconds.cmp(&a.duration_seconds).then(a.category.cmp(&b.category)));
+  totals
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn store_app_event(db: &Database, app: &str, seconds: i64) {
+    let id = db
+      .store_event_sync(&WindowInfo {
+        process_name: app.to_string(),
+        window_title: "Window".to_string(),
+        timestamp: Utc::now(),
+        ..Default::default()
+      })
+      .unwrap();
+    let started = db.get_event(&id).unwrap().unwrap().timestamp;
+    db.close_event_sync(&id, started + chrono::Duration::seconds(seconds)).unwrap();
+  }
+
+  fn total_for(totals: &[CategoryTotal], category: &str) -> Option<i64> {
+    totals.iter().find(|t| t.category == category).map(|t| t.duration_seconds)
+  }
+
+  #[test]
+  fn test_category_totals_current_rules() {
+    let (db, _temp) = create_test_db();
+    let start = Utc::now() - chrono::Duration::seconds(1);
+    store_app_event(&db, "slack.exe", 60);
+    store_app_event(&db, "code.exe", 120);
+    store_app_event(&db, "teams.exe", 30);
+    db.store_marker_event_sync("system_suspend", "", Utc::now()).unwrap();
+    let end = Utc::now() + chrono::Duration::seconds(1);
+
+    let totals = category_totals(&db, start, end, RulesMode::Current).unwrap();
+    assert_eq!(totals.len(), 2);
+    assert_eq!(totals[0].category, "development");
+    assert_eq!(total_for(&totals, "communication"), Some(90));
+    assert_eq!(totals.iter().map(|t| t.event_count).sum::<i64>(), 3);
+  }
+
+  #[test]
+  fn test_as_of_keeps_historical_categories() {
+    let (db, _temp) = create_test_db();
+    let start = Utc::now() - chrono::Duration::seconds(1);
+    store_app_event(&db, "slack.exe", 60);
+    std::thread::sleep(std::time::Duration::from_millis(5));
+
+    // Recategorize after the event happened
+    db.set_category_rule("slack", "work").unwrap();
+    let end = Utc::now() + chrono::Duration::seconds(1);
+
+    let current = category_totals(&db, start, end, RulesMode::Current).unwrap();
+    assert_eq!(total_for(&current, "work"), Some(60));
+
+    let as_of = category_totals(&db, start, end, RulesMode::AsOf).unwrap();
+    assert_eq!(total_for(&as_of, "communication"), Some(60));
+    assert_eq!(total_for(&as_of, "work"), None);
+  }
+
+  #[test]
+  fn test_as_of_applies_changes_within_period() {
+    let (db, _temp) = create_test_db();
+    let start = Utc::now() - chrono::Duration::seconds(1);
+    store_app_event(&db, "figma.exe", 10);
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    db.set_category_rule("figma", "design").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    store_app_event(&db, "figma.exe", 20);
+    let end = Utc::now() + chrono::Duration::seconds(1);
+
+    let as_of = category_totals(&db, start, end, RulesMode::AsOf).unwrap();
+    assert_eq!(total_for(&as_of, "other"), Some(10));
+    assert_eq!(total_for(&as_of, "design"), Some(20));
+  }
+
+  #[test]
+  fn test_rules_mode_deserialization() {
+    let mode: RulesMode = serde_json::from_str("\"as_of\"").unwrap();
+    assert_eq!(mode, RulesMode::AsOf);
+    assert_eq!(RulesMode::default(), RulesMode::Current);
+  }
+
+  #[test]
+  fn test_invalid_range_rejected() {
+    let (db, _temp) = create_test_db();
+    let now = Utc::now();
+    assert!(category_totals(&db, now, now, RulesMode::Current).is_err());
+  }
+}
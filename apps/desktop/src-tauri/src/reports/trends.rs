@@ -0,0 +1,231 @@
+//! Long-term app usage across raw and down-sampled history.
+//!
+//! Ranges before the down-sampling boundary are answered from daily
+//! aggregates. Totals stay exact for whole days; days the query only partly
+//! covers are pro-rated, which lowers the reported confidence.
+
+use crate::database::{AppUsageTotal, Database, StorageBackend};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+  /// Individual events
+  Raw,
+  /// Per-app daily totals
+  Daily,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CoverageSegment {
+  pub start: DateTime<Utc>,
+  pub end: DateTime<Utc>,
+  pub resolution: Resolution,
+  /// Share of this segment whose totals are exact rather than pro-rated (0.0 - 1.0)
+  pub confidence: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageTrend {
+  pub totals: Vec<AppUsageTotal>,
+  pub coverage: Vec<CoverageSegment>,
+  /// Share of the whole range whose totals are exact (0.0 - 1.0)
+  pub confidence: f64,
+}
+
+/// App usage for [start, end), combining raw events with daily aggregates
+pub fn usage_trend(db: &Database, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<UsageTrend> {
+  if end <= start {
+    bail!("Report end must be after start");
+  }
+
+  // Events that were never down-sampled (e.g. unsynced) are still raw anywhere in the range
+  let mut totals: BTreeMap<String, (i64, i64)> = BTreeMap::new();
+  for total in db.app_usage_totals(start, end)? {
+    totals.insert(total.app_name, (total.duration_seconds, total.event_count));
+  }
+
+  let mut coverage = Vec::new();
+  let mut estimated_seconds = 0.0;
+  let raw_start = match db.get_downsampled_before()?.filter(|boundary| *boundary > start) {
+    Some(boundary) => {
+      let daily_end = end.min(boundary);
+      let last_day = (daily_end - Duration::milliseconds(1)).date_naive();
+      let daily = db.get_daily_usage_between(start.date_naive(), last_day)?;
+
+      for usage in daily {
+        let fraction = day_overlap_seconds(usage.day.and_hms_opt(0, 0, 0).unwrap().and_utc(), start, daily_end)
+          / SECONDS_PER_DAY;
+        let entry = totals.entry(usage.app_name).or_default();
+        entry.0 += (usage.duration_seconds as f64 * fraction).round() as i64;
+        entry.1 += (usage.event_count as f64 * fraction).round() as i64;
+      }
+
+      // Partly covered days: at most the first and the last
+      let mut segment_estimated = 0.0;
+      let mut day = start.date_naive();
+      while day <= last_day {
+        let overlap = day_overlap_seconds(day.and_hms_opt(0, 0, 0).unwrap().and_utc(), start, daily_end);
+        if overlap < SECONDS_PER_DAY {
+          segment_estimated += overlap;
+        }
+        day = day.succ_opt().unwrap();
+      }
+      estimated_seconds += segment_estimated;
+
+      coverage.push(CoverageSegment {
+        start,
+        end: daily_end,
+        resolution: Resolution::Daily,
+        confidence: 1.0 - segment_estimated / seconds_between(start, daily_end),
+      });
+      daily_end
+    }
+    None => start,
+  };
+
+  if raw_start < end {
+    coverage.push(CoverageSegment {
+      start: raw_start,
+      end,
+      resolution: Resolution::Raw,
+      confidence: 1.0,
+    });
+  }
+
+  let mut totals: Vec<AppUsageTotal> = totals
+    .into_iter()
+    .map(|(app_name, (duration_seconds, event_count))| AppUsageTotal {
+      app_name,
+      duration_seconds,
+      event_count,
+    })
+    .collect();
+  totals.sort_by(|a, b| b.duration_seconds.cmp(&a.duration_seconds).then(a.app_name.cmp(&b.app_name)));
+
+  Ok(UsageTrend {
+    totals,
+    coverage,
+    confidence: 1.0 - estimated_seconds / seconds_between(start, end),
+  })
+}
+
+/// Seconds of the UTC day starting at `day_start` that fall inside [start, end)
+fn day_overlap_seconds(day_start: DateTime<Utc>, start: DateTime<Utc>, end: DateTime<Utc>) -> f64 {
+  let from = day_start.max(start);
+  let to = (day_start + Duration::days(1)).min(end);
+  if to <= from {
+    0.0
+  } else {
+    seconds_between(from, to)
+  }
+}
+
+fn seconds_between(start: DateTime<Utc>, end: DateTime<Utc>) -> f64 {
+  (end - start).num_milliseconds() as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::NewEvent;
+  use chrono::TimeZone;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn app_event(app: &str, timestamp: DateTime<Utc>, duration: i32) -> NewEvent {
+    NewEvent {
+      event_type: "app_usage".to_string(),
+      timestamp,
+      duration,
+      app_name: app.to_string(),
+      window_title: None,
+      url_domain: None,
+      remote_session: false,
+    }
+  }
+
+  /// code.exe: 2h on 2023-03-05 (down-sampled) and 1h on 2023-03-07 (raw)
+  fn seed(db: &Database) {
+    let old = db
+      .insert_events(&[app_event("code.exe", Utc.with_ymd_and_hms(2023, 3, 5, 9, 0, 0).unwrap(), 7200)])
+      .unwrap();
+    db.mark_as_synced(&old).unwrap();
+    db.downsample_before_sync(Utc.with_ymd_and_hms(2023, 3, 6, 0, 0, 0).unwrap()).unwrap();
+    db.insert_events(&[app_event("code.exe", Utc.with_ymd_and_hms(2023, 3, 7, 9, 0, 0).unwrap(), 3600)])
+      .unwrap();
+  }
+
+  #[test]
+  fn test_raw_only_range() {
+    let (db, _temp) = create_test_db();
+    seed(&db);
+
+    let start = Utc.with_ymd_and_hms(2023, 3, 7, 0, 0, 0).unwrap();
+    let trend = usage_trend(&db, start, start + Duration::days(1)).unwrap();
+
+    assert_eq!(trend.totals[0].duration_seconds, 3600);
+    assert_eq!(trend.confidence, 1.0);
+    assert_eq!(trend.coverage.len(), 1);
+    assert_eq!(trend.coverage[0].resolution, Resolution::Raw);
+  }
+
+  #[test]
+  fn test_range_spanning_boundary_is_exact_on_whole_days() {
+    let (db, _temp) = create_test_db();
+    seed(&db);
+
+    let start = Utc.with_ymd_and_hms(2023, 3, 1, 0, 0, 0).unwrap();
+    let end = Utc.with_ymd_and_hms(2023, 3, 10, 0, 0, 0).unwrap();
+    let trend = usage_trend(&db, start, end).unwrap();
+
+    assert_eq!(
+      trend.totals,
+      vec![AppUsageTotal { app_name: "code.exe".to_string(), duration_seconds: 10800, event_count: 2 }]
+    );
+    assert_eq!(trend.confidence, 1.0);
+
+    let boundary = Utc.with_ymd_and_hms(2023, 3, 6, 0, 0, 0).unwrap();
+    assert_eq!(
+      trend.coverage,
+      vec![
+        CoverageSegment { start, end: boundary, resolution: Resolution::Daily, confidence: 1.0 },
+        CoverageSegment { start: boundary, end, resolution: Resolution::Raw, confidence: 1.0 },
+      ]
+    );
+  }
+
+  #[test]
+  fn test_partial_aggregated_day_is_prorated() {
+    let (db, _temp) = create_test_db();
+    seed(&db);
+
+    // Second half of 2023-03-05 only
+    let start = Utc.with_ymd_and_hms(2023, 3, 5, 12, 0, 0).unwrap();
+    let end = Utc.with_ymd_and_hms(2023, 3, 6, 12, 0, 0).unwrap();
+    let trend = usage_trend(&db, start, end).unwrap();
+
+    assert_eq!(trend.totals[0].duration_seconds, 3600);
+    assert_eq!(trend.coverage[0].resolution, Resolution::Daily);
+    assert_eq!(trend.coverage[0].confidence, 0.0);
+    assert_eq!(trend.coverage[1].resolution, Resolution::Raw);
+    assert_eq!(trend.confidence, 0.5);
+  }
+
+  #[test]
+  fn test_invalid_range() {
+    let (db, _temp) = create_test_db();
+    let at = Utc.with_ymd_and_hms(2023, 3, 5, 0, 0, 0).unwrap();
+    assert!(usage_trend(&db, at, at).is_err());
+  }
+}
@@ -0,0 +1,177 @@
+//! Enforcement of the local retention policy.
+//!
+//! Once a day, synced events older than the policy allows are retired: app
+//! usage is folded into daily aggregates, which are kept forever, and the raw
+//! rows are deleted, optionally after being written to an encrypted archive.
+//! Unsynced events are never retired. Each run ends with an incremental
+//! VACUUM so an always-on machine's local.db shrinks rather than only
+//! stopping growth.
+
+use crate::archive::{self, ExportedArchive};
+use crate::database::{Database, RetentionAction};
+use crate::encryption::CryptoManager;
+use crate::sync::SyncClient;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+use tracing::{debug, error, info};
+
+const RETENTION_INTERVAL: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// Free pages returned to the OS per run (16 MB at the 4 KB page size)
+const VACUUM_PAGES_PER_RUN: i64 = 4096;
+
+/// Outcome of one retention pass
+#[derive(Debug, Clone, Serialize)]
+pub struct RetentionRun {
+  pub cutoff: DateTime<Utc>,
+  pub retired_count: usize,
+  /// Written when the policy archives rather than deletes
+  pub archive: Option<ExportedArchive>,
+  pub freed_pages: i64,
+}
+
+/// Retire synced events before `cutoff`. Archiving needs `crypto`; nothing is
+/// deleted unless the archive was written and verified.
+pub fn apply_retention(
+  db: &Database,
+  action: RetentionAction,
+  crypto: Option<&CryptoManager>,
+  archive_dir: &Path,
+  cutoff: DateTime<Utc>,
+) -> Result<RetentionRun> {
+  let events = db.get_synced_closed_events_before(cutoff)?;
+
+  let written = match (action, crypto) {
+    (RetentionAction::Delete, _) => None,
+    (RetentionAction::Archive, Some(crypto)) => archive::write_verified_archive(crypto, archive_dir, cutoff, &events)?,
+    (RetentionAction::Archive, None) => bail!("Archiving needs the sync key"),
+  };
+
+  let event_ids: Vec<String> = events.into_iter().map(|event| event.id).collect();
+  let retired_count = db.retire_events_sync(&event_ids, cutoff)?;
+  let freed_pages = db.incremental_vacuum_sync(VACUUM_PAGES_PER_RUN)?;
+
+  Ok(RetentionRun {
+    cutoff,
+    retired_count,
+    archive: written.map(|(path, header)| ExportedArchive {
+      path,
+      header,
+      pruned_count: retired_count,
+    }),
+    freed_pages,
+  })
+}
+
+/// Apply the retention policy once a day; archives go to `archive_dir`
+pub fn start_retention_scheduler(app: tauri::AppHandle, archive_dir: PathBuf) {
+  tauri::async_runtime::spawn(async move {
+    let mut ticker = tokio::time::interval(RETENTION_INTERVAL);
+
+    loop {
+      ticker.tick().await;
+
+      let sync_client = app.state::<SyncClient>();
+      match sync_client.apply_retention(&archive_dir).await {
+        Ok(None) => debug!("No retention policy set"),
+        Ok(Some(run)) => info!(
+          "Retired {} events before {}, freed {} pages",
+          run.retired_count, run.cutoff, run.freed_pages
+        ),
+        Err(e) => error!("Failed to apply retention policy: {}", e),
+      }
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::{NewEvent, StorageBackend};
+  use chrono::{NaiveDate, TimeZone};
+  use tempfile::{NamedTempFile, TempDir};
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn event_at(event_type: &str, timestamp: DateTime<Utc>) -> NewEvent {
+    NewEvent {
+      event_type: event_type.to_string(),
+      timestamp,
+      duration: 60,
+      app_name: "code.exe".to_string(),
+      window_title: None,
+      url_domain: None,
+      remote_session: false,
+    }
+  }
+
+  #[test]
+  fn test_delete_keeps_aggregates_and_unsynced_events() {
+    let (db, _temp) = create_test_db();
+    let old = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+    let recent = Utc.with_ymd_and_hms(2024, 6, 1, 9, 0, 0).unwrap();
+    let synced = db
+      .insert_events(&[event_at("app_usage", old), event_at("afk", old), event_at("app_usage", recent)])
+      .unwrap();
+    db.mark_as_synced(&synced).unwrap();
+    db.insert_events(&[event_at("app_usage", old)]).unwrap();
+
+    let cutoff = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+    let archive_dir = TempDir::new().unwrap();
+    let run = apply_retention(&db, RetentionAction::Delete, None, archive_dir.path(), cutoff).unwrap();
+
+    assert_eq!(run.retired_count, 2);
+    assert!(run.archive.is_none());
+    // The unsynced old event and the recent one remain
+    assert_eq!(db.get_event_count().unwrap(), 2);
+    assert!(db.get_event(&synced[2]).unwrap().is_some());
+
+    let day = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+    let usage = db.get_daily_usage_between(day, day).unwrap();
+    assert_eq!(usage.len(), 1);
+    assert_eq!(usage[0].duration_seconds, 60);
+    assert_eq!(db.get_downsampled_before().unwrap(), Some(cutoff));
+  }
+
+  #[test]
+  fn test_archive_action_writes_archive_first() {
+    let (db, _temp) = create_test_db();
+    let old = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+    let synced = db.insert_events(&[event_at("app_usage", old), event_at("afk", old)]).unwrap();
+    db.mark_as_synced(&synced).unwrap();
+
+    let cutoff = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+    let archive_dir = TempDir::new().unwrap();
+    assert!(apply_retention(&db, RetentionAction::Archive, None, archive_dir.path(), cutoff).is_err());
+    assert_eq!(db.get_event_count().unwrap(), 2);
+
+    let crypto = CryptoManager::new(b"test_key_32_bytes_long_123456789").unwrap();
+    let run = apply_retention(&db, RetentionAction::Archive, Some(&crypto), archive_dir.path(), cutoff).unwrap();
+
+    let exported = run.archive.unwrap();
+    assert_eq!(exported.pruned_count, 2);
+    let (_, archived) = archive::decode_archive(&crypto, &std::fs::read(&exported.path).unwrap()).unwrap();
+    assert_eq!(archived.len(), 2);
+    assert_eq!(db.get_event_count().unwrap(), 0);
+  }
+
+  #[test]
+  fn test_nothing_to_retire() {
+    let (db, _temp) = create_test_db();
+    let archive_dir = TempDir::new().unwrap();
+    let crypto = CryptoManager::new(b"test_key_32_bytes_long_123456789").unwrap();
+
+    let run = apply_retention(&db, RetentionAction::Archive, Some(&crypto), archive_dir.path(), Utc::now()).unwrap();
+
+    assert_eq!(run.retired_count, 0);
+    assert!(run.archive.is_none());
+    assert!(db.get_downsampled_before().unwrap().is_none());
+  }
+}
@@ -0,0 +1,303 @@
+//! Crash detection through a session lock file.
+//!
+//! While the app runs, `session.lock` in the data directory holds a heartbeat
+//! refreshed every HEARTBEAT_INTERVAL and is removed on clean exit. Finding it
+//! at startup means the previous run ended uncleanly: events it left open are
+//! closed at the last heartbeat and a recovery report is stored for
+//! `get_last_crash_info`. The heartbeat is also kept in sync_state, so events
+//! left open without a lock file (a lost lock, an older version) are still
+//! closed at the last sign of life rather than staying zero-length.
+
+use crate::database::Database;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+const LOCK_FILE_NAME: &str = "session.lock";
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const LAST_CRASH_SETTING: &str = "last_crash_info";
+
+/// sync_state key mirroring the lock file's heartbeat (Unix millis)
+const HEARTBEAT_STATE_KEY: &str = "session_heartbeat_at";
+
+/// Contents of the lock file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Heartbeat {
+  pid: u32,
+  started_at: DateTime<Utc>,
+  heartbeat_at: DateTime<Utc>,
+}
+
+/// What is known about the previous, uncleanly ended session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CrashReport {
+  /// When the crashed session started, if its lock file was readable
+  pub session_started_at: Option<DateTime<Utc>>,
+  /// Last time the crashed session was known to be alive
+  pub last_heartbeat_at: Option<DateTime<Utc>>,
+  pub detected_at: DateTime<Utc>,
+  /// Start time and app of the newest event that made it to the database
+  pub last_event_at: Option<DateTime<Utc>>,
+  pub last_event_app: Option<String>,
+  /// Events left open by the crash, now closed at the last heartbeat
+  pub recovered_open_events: usize,
+  /// Tracking time that may be missing: at most one heartbeat interval
+  pub max_data_loss_seconds: u64,
+}
+
+pub struct SessionLock {
+  path: PathBuf,
+  started_at: DateTime<Utc>,
+  db: Database,
+  released: AtomicBool,
+}
+
+impl SessionLock {
+  /// Check for an unclean previous exit, record a report if there was one,
+  /// then claim the lock for this session
+  pub fn acquire(data_dir: &Path, db: &Database) -> Result<Self> {
+    let path = data_dir.join(LOCK_FILE_NAME);
+    let now = Utc::now();
+
+    if path.exists() {
+      let previous = fs::read_to_string(&path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<Heartbeat>(&json).ok());
+      let report = recover(db, previous.as_ref(), now)?;
+      warn!(
+        "Previous session ended uncleanly (last heartbeat {:?}); closed {} open events",
+        report.last_heartbeat_at, report.recovered_open_events
+      );
+      db.set_setting(LAST_CRASH_SETTING, &serde_json::to_string(&report)?)?;
+    } else {
+      // Nothing crashed as far as we know, but nothing can be open before the collector starts
+      let closed = db.close_open_events_sync(last_sign_of_life(db, None, now)?)?;
+      if closed > 0 {
+        info!("Closed {} events left open by the previous session", closed);
+      }
+    }
+
+    let lock = Self {
+      path,
+      started_at: now,
+      db: db.clone(),
+      released: AtomicBool::new(false),
+    };
+    lock.beat()?;
+    Ok(lock)
+  }
+
+  /// Refresh the heartbeat in the lock file; a released lock stays released
+  fn beat(&self) -> Result<()> {
+    if self.released.load(Ordering::SeqCst) {
+      return Ok(());
+    }
+    let heartbeat = Heartbeat {
+      pid: std::process::id(),
+      started_at: self.started_at,
+      heartbeat_at: Utc::now(),
+    };
+    let tmp_path = self.path.with_extension("tmp");
+    fs::write(&tmp_path, serde_json::to_vec(&heartbeat)?)?;
+    fs::rename(&tmp_path, &self.path)?;
+    self
+      .db
+      .update_sync_state(HEARTBEAT_STATE_KEY, &heartbeat.heartbeat_at.timestamp_millis().to_string())?;
+    Ok(())
+  }
+
+  /// Refresh the heartbeat every HEARTBEAT_INTERVAL
+  pub fn start_heartbeat(self: &std::sync::Arc<Self>) {
+    let lock = self.clone();
+    tauri::async_runtime::spawn(async move {
+      let mut ticker = tokio::time::interval(HEARTBEAT_INTERVAL);
+      loop {
+        ticker.tick().await;
+        if let Err(e) = lock.beat() {
+          error!("Failed to write session heartbeat: {}", e);
+        }
+      }
+    });
+  }
+
+  /// Clean exit: remove the lock so the next start doesn't report a crash.
+  /// The heartbeat stops writing it from here on.
+  pub fn release(&self) -> Result<()> {
+    self.released.store(true, Ordering::SeqCst);
+    match fs::remove_file(&self.path) {
+      Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+      _ => Ok(()),
+    }
+  }
+}
+
+/// When the previous session was last known to be alive: the lock file's
+/// heartbeat, else the later of the heartbeat kept in sync_state and the
+/// newest event start
+fn last_sign_of_life(db: &Database, previous: Option<&Heartbeat>, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+  if let Some(heartbeat) = previous {
+    return Ok(heartbeat.heartbeat_at);
+  }
+  let stored_heartbeat = db
+    .get_sync_state(HEARTBEAT_STATE_KEY)?
+    .and_then(|millis| millis.parse().ok())
+    .and_then(DateTime::from_timestamp_millis);
+  let newest_event = db.get_events(1, 0)?.into_iter().next().map(|event| event.timestamp);
+  Ok(stored_heartbeat.max(newest_event).unwrap_or(now))
+}
+
+/// Close dangling events and describe the crashed session
+fn recover(db: &Database, previous: Option<&Heartbeat>, now: DateTime<Utc>) -> Result<CrashReport> {
+  let last_event = db.get_events(1, 0)?.into_iter().next();
+  let recovered_open_events = db.close_open_events_sync(last_sign_of_life(db, previous, now)?)?;
+
+  Ok(CrashReport {
+    session_started_at: previous.map(|heartbeat| heartbeat.started_at),
+    last_heartbeat_at: previous.map(|heartbeat| heartbeat.heartbeat_at),
+    detected_at: now,
+    last_event_at: last_event.as_ref().map(|event| event.timestamp),
+    last_event_app: last_event.map(|event| event.app_name),
+    recovered_open_events,
+    max_data_loss_seconds: HEARTBEAT_INTERVAL.as_secs(),
+  })
+}
+
+/// Report about the most recent unclean exit, if there ever was one
+pub fn last_crash_info(db: &Database) -> Result<Option<CrashReport>> {
+  Ok(
+    db.get_setting(LAST_CRASH_SETTING)?
+      .and_then(|json| serde_json::from_str(&json).ok()),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collector::window_tracker::WindowInfo;
+  use chrono::Duration as ChronoDuration;
+  use tempfile::{NamedTempFile, TempDir};
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn write_heartbeat(dir: &Path, heartbeat: &Heartbeat) {
+    fs::write(dir.join(LOCK_FILE_NAME), serde_json::to_vec(heartbeat).unwrap()).unwrap();
+  }
+
+  #[test]
+  fn test_clean_exit_reports_nothing() {
+    let (db, _temp) = create_test_db();
+    let dir = TempDir::new().unwrap();
+
+    let lock = SessionLock::acquire(dir.path(), &db).unwrap();
+    assert!(dir.path().join(LOCK_FILE_NAME).exists());
+    lock.release().unwrap();
+    assert!(!dir.path().join(LOCK_FILE_NAME).exists());
+    // A heartbeat still in flight doesn't bring it back
+    lock.beat().unwrap();
+    assert!(!dir.path().join(LOCK_FILE_NAME).exists());
+
+    SessionLock::acquire(dir.path(), &db).unwrap();
+    assert!(last_crash_info(&db).unwrap().is_none());
+  }
+
+  #[test]
+  fn test_crash_closes_open_events_at_last_heartbeat() {
+    let (db, _temp) = create_test_db();
+    let dir = TempDir::new().unwrap();
+
+    let id = db
+      .store_event_sync(&WindowInfo {
+        process_name: "code.exe".to_string(),
+        window_title: "main.rs".to_string(),
+        timestamp: Utc::now(),
+        ..Default::default()
+      })
+      .unwrap();
+    let started = db.get_event(&id).unwrap().unwrap().timestamp;
+
+    let heartbeat = Heartbeat {
+      pid: 1,
+      started_at: started - ChronoDuration::hours(1),
+      heartbeat_at: started + ChronoDuration::seconds(90),
+    };
+    write_heartbeat(dir.path(), &heartbeat);
+
+    SessionLock::acquire(dir.path(), &db).unwrap();
+
+    let report = last_crash_info(&db).unwrap().unwrap();
+    assert_eq!(report.session_started_at, Some(heartbeat.started_at));
+    assert_eq!(report.last_heartbeat_at, Some(heartbeat.heartbeat_at));
+    assert_eq!(report.last_event_app.as_deref(), Some("code.exe"));
+    assert_eq!(report.last_event_at, Some(started));
+    assert_eq!(report.recovered_open_events, 1);
+    assert_eq!(report.max_data_loss_seconds, 30);
+
+    assert_eq!(db.get_event(&id).unwrap().unwrap().duration, 90);
+    // The recovered event is closed, so it can sync
+    assert_eq!(db.count_unsynced().unwrap(), 1);
+  }
+
+  #[test]
+  fn test_events_left_open_without_lock_are_closed() {
+    let (db, _temp) = create_test_db();
+    let dir = TempDir::new().unwrap();
+    let started = Utc::now() - ChronoDuration::minutes(10);
+    let window = |title: &str, timestamp: DateTime<Utc>| WindowInfo {
+      process_name: "code.exe".to_string(),
+      window_title: title.to_string(),
+      timestamp,
+      ..Default::default()
+    };
+    let first = db.store_event_sync(&window("lib.rs", started)).unwrap();
+    let last = db.store_event_sync(&window("main.rs", started + ChronoDuration::seconds(60))).unwrap();
+    let heartbeat_at = started + ChronoDuration::seconds(200);
+    db.update_sync_state(HEARTBEAT_STATE_KEY, &heartbeat_at.timestamp_millis().to_string()).unwrap();
+
+    SessionLock::acquire(dir.path(), &db).unwrap();
+
+    assert!(last_crash_info(&db).unwrap().is_none());
+    // Each ends at the next event's start or at the last heartbeat
+    assert_eq!(db.get_event(&first).unwrap().unwrap().duration, 60);
+    assert_eq!(db.get_event(&last).unwrap().unwrap().duration, 140);
+    assert_eq!(db.count_unsynced().unwrap(), 2);
+    // The new session's heartbeat replaces the old one
+    let stored: i64 = db.get_sync_state(HEARTBEAT_STATE_KEY).unwrap().unwrap().parse().unwrap();
+    assert!(stored > heartbeat_at.timestamp_millis());
+  }
+
+  #[test]
+  fn test_unreadable_lock_still_reports_crash() {
+    let (db, _temp) = create_test_db();
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join(LOCK_FILE_NAME), b"garbage").unwrap();
+
+    SessionLock::acquire(dir.path(), &db).unwrap();
+
+    let report = last_crash_info(&db).unwrap().unwrap();
+    assert!(report.last_heartbeat_at.is_none());
+    assert!(report.last_event_at.is_none());
+    assert_eq!(report.recovered_open_events, 0);
+  }
+
+  #[test]
+  fn test_lock_is_rewritten_for_new_session() {
+    let (db, _temp) = create_test_db();
+    let dir = TempDir::new().unwrap();
+
+    SessionLock::acquire(dir.path(), &db).unwrap();
+    let json = fs::read_to_string(dir.path().join(LOCK_FILE_NAME)).unwrap();
+    let heartbeat: Heartbeat = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(heartbeat.pid, std::process::id());
+    assert!(heartbeat.heartbeat_at >= heartbeat.started_at);
+  }
+}
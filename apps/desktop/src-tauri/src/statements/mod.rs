@@ -0,0 +1,264 @@
+//! Immutable monthly statements.
+//!
+//! Once a month (local time) is over, its category and app totals are frozen
+//! into a statement that later rule edits, deletions and down-sampling can't
+//! change. Each statement's hash covers its contents and the previous
+//! statement's hash, so altering or removing an earlier statement breaks
+//! verification of every later one.
+
+use crate::database::{AppUsageTotal, Database, StorageBackend, StoredStatement};
+use crate::reports::{self, CategoryTotal, RulesMode};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, Local, Months, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info};
+
+/// How often the scheduler looks for months to freeze
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Time after month end before it is frozen, so events still open at midnight get closed
+const FREEZE_GRACE: chrono::Duration = chrono::Duration::hours(1);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MonthlyStatement {
+  /// "YYYY-MM"
+  pub month: String,
+  pub period_start: DateTime<Utc>,
+  pub period_end: DateTime<Utc>,
+  pub total_seconds: i64,
+  /// Categorized with the rules in effect at the time of each event
+  pub categories: Vec<CategoryTotal>,
+  pub apps: Vec<AppUsageTotal>,
+  pub generated_at: DateTime<Utc>,
+  /// Hash of the preceding statement; None for the first one
+  pub previous_hash: Option<String>,
+  /// SHA-256 (hex) over this statement with an empty `hash`
+  pub hash: String,
+}
+
+fn parse_month(month: &str) -> Result<NaiveDate> {
+  NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d")
+    .map_err(|_| anyhow!("Invalid month '{}', expected YYYY-MM", month))
+}
+
+fn month_key(first_day: NaiveDate) -> String {
+  first_day.format("%Y-%m").to_string()
+}
+
+fn next_month(first_day: NaiveDate) -> NaiveDate {
+  first_day + Months::new(1)
+}
+
+/// Local midnight at the start of `day`, in UTC
+fn local_midnight(day: NaiveDate) -> DateTime<Utc> {
+  let midnight = day.and_time(NaiveTime::MIN);
+  midnight
+    .and_local_timezone(Local)
+    .earliest()
+    .map(|local| local.with_timezone(&Utc))
+    .unwrap_or_else(|| midnight.and_utc())
+}
+
+/// First day of the local month containing `at`
+fn month_containing(at: DateTime<Utc>) -> NaiveDate {
+  let day = at.with_timezone(&Local).date_naive();
+  day.with_day(1).unwrap_or(day)
+}
+
+fn statement_hash(statement: &MonthlyStatement) -> Result<String> {
+  let unsigned = MonthlyStatement {
+    hash: String::new(),
+    ..statement.clone()
+  };
+  Ok(hex::encode(Sha256::digest(serde_json::to_vec(&unsigned)?)))
+}
+
+/// Freeze one ended month, chained to the newest statement before it
+fn freeze_month(db: &Database, first_day: NaiveDate, now: DateTime<Utc>) -> Result<MonthlyStatement> {
+  let month = month_key(first_day);
+  let period_start = local_midnight(first_day);
+  let period_end = local_midnight(next_month(first_day));
+  if now < period_end {
+    bail!("{} has not ended yet", month);
+  }
+  if db.get_monthly_statement_row(&month)?.is_some() {
+    bail!("Statement for {} already exists", month);
+  }
+
+  let categories = reports::category_totals(db, period_start, period_end, RulesMode::AsOf)?;
+  let apps = db.app_usage_totals(period_start, period_end)?;
+  let mut statement = MonthlyStatement {
+    month: month.clone(),
+    period_start,
+    period_end,
+    total_seconds: categories.iter().map(|c| c.duration_seconds).sum(),
+    categories,
+    apps,
+    generated_at: now,
+    previous_hash: db.get_statement_before(&month)?.map(|(_, hash)| hash),
+    hash: String::new(),
+  };
+  statement.hash = statement_hash(&statement)?;
+
+  db.insert_monthly_statement(
+    &StoredStatement {
+      month,
+      body: serde_json::to_string(&statement)?,
+      hash: statement.hash.clone(),
+    },
+    now,
+  )?;
+  Ok(statement)
+}
+
+/// Freeze every month that has ended since the newest statement (only the
+/// previous month on first run); returns the months frozen, oldest first
+pub fn freeze_due_statements(db: &Database, now: DateTime<Utc>) -> Result<Vec<String>> {
+  let current = month_containing(now);
+  let mut month = match db.list_statement_months()?.last() {
+    Some(last) => next_month(parse_month(last)?),
+    None => current - Months::new(1),
+  };
+
+  let mut frozen = Vec::new();
+  while month < current && local_midnight(next_month(month)) + FREEZE_GRACE <= now {
+    frozen.push(freeze_month(db, month, now)?.month);
+    month = next_month(month);
+  }
+  Ok(frozen)
+}
+
+/// The frozen statement for `month` ("YYYY-MM"), after checking its hash and
+/// its link to the previous statement
+pub fn get_monthly_statement(db: &Database, month: &str) -> Result<Option<MonthlyStatement>> {
+  let month = month_key(parse_month(month)?);
+  let Some(row) = db.get_monthly_statement_row(&month)? else {
+    return Ok(None);
+  };
+
+  let statement: MonthlyStatement = serde_json::from_str(&row.body)?;
+  let previous_hash = db.get_statement_before(&month)?.map(|(_, hash)| hash);
+  if statement.hash != row.hash || statement_hash(&statement)? != row.hash || statement.previous_hash != previous_hash {
+    bail!("Statement for {} failed its integrity check", month);
+  }
+  Ok(Some(statement))
+}
+
+/// Check hourly for months that have ended and freeze them
+pub fn start_statement_scheduler(db: Arc<Database>) {
+  tauri::async_runtime::spawn(async move {
+    let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+      ticker.tick().await;
+
+      let run_db = db.clone();
+      let result = tokio::task::spawn_blocking(move || freeze_due_statements(&run_db, Utc::now())).await;
+
+      match result {
+        Ok(Ok(months)) if months.is_empty() => debug!("No monthly statement due"),
+        Ok(Ok(months)) => info!("Froze monthly statements for {}", months.join(", ")),
+        Ok(Err(e)) => error!("Failed to freeze monthly statements: {}", e),
+        Err(e) => error!("Monthly statement task failed: {}", e),
+      }
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::NewEvent;
+  use chrono::TimeZone;
+  use tempfile::NamedTempFile;
+
+  fn create_test_db() -> (Database, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (db, temp_file)
+  }
+
+  fn usage(app_name: &str, timestamp: DateTime<Utc>, duration: i32) -> NewEvent {
+    NewEvent {
+      event_type: "app_usage".to_string(),
+      timestamp,
+      duration,
+      app_name: app_name.to_string(),
+      window_title: None,
+      url_domain: None,
+      remote_session: false,
+    }
+  }
+
+  // Mid-month instants stay in the same month in every local timezone
+  fn may() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 5, 15, 12, 0, 0).unwrap()
+  }
+
+  fn now() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 7, 10, 12, 0, 0).unwrap()
+  }
+
+  #[test]
+  fn test_first_run_freezes_previous_month_only() {
+    let (db, _temp) = create_test_db();
+    db.insert_events(&[usage("code.exe", may(), 600)]).unwrap();
+
+    assert_eq!(freeze_due_statements(&db, now()).unwrap(), vec!["2024-06"]);
+    assert!(freeze_due_statements(&db, now()).unwrap().is_empty());
+    assert!(get_monthly_statement(&db, "2024-05").unwrap().is_none());
+  }
+
+  #[test]
+  fn test_statements_chain_and_stay_frozen() {
+    let (db, _temp) = create_test_db();
+    let ids = db
+      .insert_events(&[usage("code.exe", may(), 600), usage("slack.exe", may(), 300)])
+      .unwrap();
+
+    let first = freeze_month(&db, parse_month("2024-05").unwrap(), now()).unwrap();
+    assert_eq!(first.total_seconds, 900);
+    assert_eq!(first.apps.len(), 2);
+    assert!(first.previous_hash.is_none());
+
+    assert_eq!(freeze_due_statements(&db, now()).unwrap(), vec!["2024-06"]);
+    let second = get_monthly_statement(&db, "2024-06").unwrap().unwrap();
+    assert_eq!(second.previous_hash.as_deref(), Some(first.hash.as_str()));
+    assert_eq!(second.total_seconds, 0);
+
+    // Later edits to the raw data don't touch the frozen numbers
+    db.delete_events_sync(&ids).unwrap();
+    let reread = get_monthly_statement(&db, "2024-05").unwrap().unwrap();
+    assert_eq!(reread, first);
+  }
+
+  #[test]
+  fn test_unfinished_month_is_not_frozen() {
+    let (db, _temp) = create_test_db();
+    assert!(freeze_month(&db, parse_month("2024-07").unwrap(), now()).is_err());
+    assert!(parse_month("July").is_err());
+  }
+
+  #[test]
+  fn test_tampered_statement_fails_verification() {
+    let (db, _temp) = create_test_db();
+    db.insert_events(&[usage("code.exe", may(), 600)]).unwrap();
+    let statement = freeze_month(&db, parse_month("2024-05").unwrap(), now()).unwrap();
+
+    let forged = serde_json::to_string(&MonthlyStatement {
+      total_seconds: 60,
+      ..statement
+    })
+    .unwrap();
+    {
+      let conn = db.conn.lock().unwrap();
+      conn.execute_batch("DROP TRIGGER monthly_statements_no_update").unwrap();
+      conn.execute("UPDATE monthly_statements SET body = ?1", [forged]).unwrap();
+    }
+
+    assert!(get_monthly_statement(&db, "2024-05").is_err());
+  }
+}
@@ -1,15 +1,28 @@
-use crate::database::{Database, StoredEvent};
-use crate::encryption::CryptoManager;
+use super::connectivity::{default_probe_url, Connectivity, ConnectivityProbe, PROBE_URL_SETTING};
+use super::device_info::ClientInfo;
+use super::fields::{self, SyncFieldPolicy, UploadedField};
+use crate::archive::{self, ExportedArchive, ARCHIVE_UPLOAD_SETTING};
+use crate::collector::power_profile::current_power_profile;
+use crate::collector::recorder::{self, Recording, SavedRecording, RECORDING_EXTENSION};
+use crate::database::{AppSession, CategoryRules, Database, RemoteEvent, StoredEvent, UNCATEGORIZED};
+use crate::encryption::{
+    derive_subkey, CryptoManager, EncryptedData, KeyPurpose, NonceMode, SecretKey, DERIVED_KEY_STATE_KEY,
+    RECOVERY_CODE_STATE_KEY, WRAPPED_KEY_PENDING_STATE_KEY, WRAPPED_KEY_STATE_KEY,
+};
+use crate::retention::{self, RetentionRun};
 use anyhow::Result;
 use base64::Engine;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
-use tracing::{info, error, debug};
+use tracing::{info, error, debug, warn};
+use zeroize::Zeroizing;
 
 /// Server configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,6 +32,29 @@ pub struct ServerConfig {
     pub device_id: String,
 }
 
+/// local_settings key of the server config, sealed with its subkey
+const SERVER_CONFIG_SETTING: &str = "server_config";
+/// Marks a sealed server config; older versions stored plain JSON
+const SEALED_CONFIG_PREFIX: &str = "sealed:v1:";
+
+/// The server config encrypted for local_settings, so the JWT is not at rest in plaintext
+fn seal_config(sync_key: &[u8; 32], config: &ServerConfig) -> Result<String> {
+    let crypto = CryptoManager::new(&derive_subkey(sync_key, KeyPurpose::ServerConfig, None))?;
+    let json = Zeroizing::new(serde_json::to_vec(config)?);
+    Ok(format!("{}{}", SEALED_CONFIG_PREFIX, crypto.encrypt_to_base64(&json)?))
+}
+
+/// A stored server config; plain JSON from older versions is read as is
+fn open_config(sync_key: Option<&[u8; 32]>, stored: &str) -> Result<ServerConfig> {
+    let Some(sealed) = stored.strip_prefix(SEALED_CONFIG_PREFIX) else {
+        return Ok(serde_json::from_str(stored)?);
+    };
+    let sync_key = sync_key.ok_or_else(|| anyhow::anyhow!("The sync key is needed to read the server config"))?;
+    let crypto = CryptoManager::new(&derive_subkey(sync_key, KeyPurpose::ServerConfig, None))?;
+    let json = Zeroizing::new(crypto.decrypt_from_base64(sealed)?);
+    Ok(serde_json::from_slice(&json)?)
+}
+
 /// Sync status
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncStatus {
@@ -26,6 +62,35 @@ pub struct SyncStatus {
     pub last_sync_at: Option<String>,
     pub pending_events: i64,
     pub last_error: Option<String>,
+    /// A captive portal was detected; sync resumes once the network is usable
+    #[serde(default)]
+    pub waiting_for_connectivity: bool,
+    /// Which event fields uploads include, per the sync field policy
+    #[serde(default)]
+    pub uploaded_fields: Vec<UploadedField>,
+    /// Local deletions the server has not acknowledged yet
+    #[serde(default)]
+    pub unconfirmed_deletions: i64,
+    /// Events uploaded per request
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// How far the running sync is through the backlog
+    #[serde(default)]
+    pub progress: Option<SyncProgress>,
+}
+
+fn default_batch_size() -> usize {
+    SYNC_BATCH_SIZE
+}
+
+/// Progress of a sync that uploads the backlog batch by batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncProgress {
+    /// Events uploaded so far in this sync
+    pub synced_events: usize,
+    /// Unsynced events when the sync started
+    pub total_events: usize,
+    pub batches: usize,
 }
 
 /// Sync result from server (matches backend API response)
@@ -34,6 +99,8 @@ struct SyncResponse {
     synced_at: i64,           // Timestamp when sync completed
     processed_count: i32,     // Number of events processed
     conflicts: Vec<serde_json::Value>,  // Array of conflict objects (usually empty)
+    #[serde(default)]
+    deleted_ids: Vec<String>, // Tombstones the server applied (or never held the event for)
 }
 
 /// Event to send to server
@@ -46,18 +113,146 @@ struct SyncEvent {
     encrypted_data: String,                    // Required
     nonce: String,                             // 12 bytes in hex (24 chars)
     tag: String,                               // 16 bytes base64 STANDARD with padding (24 chars)
-    app_name: String,
+    aad_version: u8,                           // How the ciphertext is bound to the event (see event_aad)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    app_name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     category: Option<String>,
+    remote_session: bool,                      // Used via RDP/Citrix/VM viewer
+    fullscreen: bool,                          // Full-screen app (game, video) in the foreground
+    #[serde(skip_serializing_if = "Option::is_none")]
+    domain: Option<String>,                    // Active browser tab domain (opt-in)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session_id: Option<String>,                // App session (machine, app version) that recorded it
 }
 
 /// Request body for sync API
 #[derive(Debug, Serialize)]
 struct SyncRequest {
     device_id: String,
+    client: ClientInfo,
     events: Vec<SyncEvent>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    deleted: Vec<SyncTombstone>,
+    /// The app sessions the events reference
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    sessions: Vec<AppSession>,
+}
+
+/// A locally deleted event the server should drop as well
+#[derive(Debug, Serialize)]
+struct SyncTombstone {
+    id: String,
+    deleted_at: i64,
+}
+
+/// Request body for uploading a pruning archive as an opaque blob
+#[derive(Debug, Serialize)]
+struct ArchiveUploadRequest {
+    device_id: String,
+    file_name: String,
+    range_start: i64,
+    range_end: i64,
+    event_count: usize,
+    data: String,                              // Whole archive file, base64 STANDARD
+}
+
+/// Request body for removing locally deleted events from the server
+#[derive(Debug, Serialize)]
+struct DeletionRequest {
+    device_id: String,
+    event_ids: Vec<String>,
+}
+
+/// Server acknowledgement: ids it no longer holds (deleted now or never stored)
+#[derive(Debug, Deserialize)]
+struct DeletionResponse {
+    confirmed_ids: Vec<String>,
+}
+
+/// Body of POST /api/v1/sync/key-check, and the server's answer
+#[derive(Debug, Serialize, Deserialize)]
+struct KeyCheck {
+    key_check: String,
+}
+
+/// sync_state key of the last key check value the server confirmed
+const KEY_CHECK_STATE_KEY: &str = "key_check";
+
+/// Body of POST /api/v1/sync/wrapped-key
+#[derive(Debug, Serialize)]
+struct StoreWrappedKey<'a> {
+    wrapped_key: &'a str,
+    /// The account's key check value, proving the key is the account's
+    key_check: &'a str,
+    replace: bool,
+}
+
+/// The account's passphrase-wrapped sync key, as /api/v1/sync/wrapped-key returns it
+#[derive(Debug, Deserialize)]
+struct WrappedKey {
+    wrapped_key: Option<String>,
+}
+
+/// An event another device synced, as GET /api/v1/sync/events returns it
+#[derive(Debug, Clone, Deserialize)]
+struct PulledEvent {
+    id: String,
+    device_id: String,
+    event_type: String,
+    timestamp: i64,
+    duration: i32,
+    encrypted_data: String,
+    nonce: String,
+    tag: String,
+    #[serde(default)]
+    app_name: Option<String>,
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default)]
+    domain: Option<String>,
+    /// Recorded in a remote desktop / VM viewer
+    #[serde(default)]
+    remote_session: bool,
+    /// The uploader's `aad_version`; None for events the server stored
+    /// before it kept the version
+    #[serde(default)]
+    aad_version: Option<u8>,
 }
 
+/// Response of GET /api/v1/sync/events
+#[derive(Debug, Deserialize)]
+struct PullResponse {
+    events: Vec<PulledEvent>,
+    has_more: bool,
+    /// Where the next pull continues
+    next_cursor: i64,
+}
+
+/// sync_state key of the server's cursor after the last pulled event
+const PULL_CURSOR_STATE_KEY: &str = "pull_cursor";
+
+/// Events downloaded per pull request
+const PULL_BATCH_SIZE: usize = 500;
+
+/// Upload bodies from this size on are sent gzip-compressed
+const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// sync_state key set once the server refused a compressed upload (415)
+const COMPRESSION_REFUSED_STATE_KEY: &str = "upload_compression_refused";
+
+/// Events uploaded per sync request, unless the batch size setting says otherwise
+const SYNC_BATCH_SIZE: usize = 100;
+
+/// Most events the server accepts per request
+const MAX_SYNC_BATCH_SIZE: usize = 500;
+
+/// Setting for the events uploaded per request
+pub const SYNC_BATCH_SIZE_SETTING: &str = "sync_batch_size";
+
+/// Most deletions sent per request
+const DELETION_BATCH_SIZE: usize = 500;
+
 /// Sync errors
 #[derive(Debug, thiserror::Error)]
 pub enum SyncError {
@@ -76,6 +271,12 @@ pub enum SyncError {
     #[error("Database error: {0}")]
     Database(String),
 
+    #[error("Encryption key mismatch: this passphrase doesn't match the key your other devices sync with")]
+    KeyMismatch,
+
+    #[error("Waiting for real connectivity: captive portal detected")]
+    CaptivePortal,
+
     #[error("Unknown error: {0}")]
     Unknown(String),
 }
@@ -86,10 +287,18 @@ pub type SyncResult = std::result::Result<(), SyncError>;
 /// Sync client for uploading events to server
 pub struct SyncClient {
     db: Arc<Database>,
+    /// Keyed with the sync key itself: recordings and archives
     crypto: Arc<Mutex<Option<CryptoManager>>>,
+    /// For deriving per-purpose and per-device subkeys
+    sync_key: Arc<Mutex<Option<SecretKey>>>,
     http_client: Client,
+    client_info: ClientInfo,
+    probe: ConnectivityProbe,
+    connectivity: Arc<Mutex<Connectivity>>,
     config: Arc<Mutex<Option<ServerConfig>>>,
     is_syncing: Arc<Mutex<bool>>,
+    /// A std mutex so the end of a sync can clear it synchronously
+    progress: Arc<std::sync::Mutex<Option<SyncProgress>>>,
     auto_sync_handle: Arc<Mutex<Option<JoinHandle<()>>>>,
 }
 
@@ -124,26 +333,72 @@ impl SyncClient {
         Self {
             db,
             crypto: Arc::new(Mutex::new(None)),
+            sync_key: Arc::new(Mutex::new(None)),
             http_client,
+            client_info: ClientInfo::current(),
+            probe: ConnectivityProbe::new(),
+            connectivity: Arc::new(Mutex::new(Connectivity::Online)),
             config: Arc::new(Mutex::new(None)),
             is_syncing: Arc::new(Mutex::new(false)),
+            progress: Arc::new(std::sync::Mutex::new(None)),
             auto_sync_handle: Arc::new(Mutex::new(None)),
         }
     }
 
-    /// Set encryption key
-    pub async fn set_crypto_key(&self, key: [u8; 32]) -> Result<()> {
+    /// Set encryption key; it is wiped from memory when replaced. The stored
+    /// server config is sealed again with the new key.
+    pub async fn set_crypto_key(&self, key: SecretKey) -> Result<()> {
         let crypto = CryptoManager::new(&key)?;
+        let config = match self.get_config().await {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Failed to read the server config to seal it with the new key: {}", e);
+                None
+            }
+        };
+
         let mut crypto_guard = self.crypto.lock().await;
         *crypto_guard = Some(crypto);
+        *self.sync_key.lock().await = Some(key.clone());
+        drop(crypto_guard);
+
+        if let Some(config) = config {
+            self.db.set_setting(SERVER_CONFIG_SETTING, &seal_config(&key, &config)?)?;
+        }
         Ok(())
     }
 
+    /// The sync key in use, if one was set
+    pub async fn current_key(&self) -> Option<SecretKey> {
+        self.sync_key.lock().await.clone()
+    }
+
+    /// A cipher keyed with the sync key's subkey for `purpose` (and `device_id`).
+    /// Payload ciphers take counter nonces when the nonce mode setting asks for them.
+    async fn subkey_crypto(&self, purpose: KeyPurpose, device_id: Option<&str>) -> std::result::Result<CryptoManager, SyncError> {
+        let nonce_mode = NonceMode::load(&self.db)
+            .map_err(|e| SyncError::Database(e.to_string()))?;
+        let sync_key = self.sync_key.lock().await;
+        let sync_key = sync_key.as_ref()
+            .ok_or_else(|| SyncError::Encryption("Crypto manager not initialized".to_string()))?;
+        let subkey = derive_subkey(sync_key, purpose, device_id);
+        match (purpose, nonce_mode) {
+            (KeyPurpose::SyncPayload, NonceMode::Counter) => CryptoManager::with_nonce_counter(&subkey, self.db.clone()),
+            _ => CryptoManager::new(&subkey),
+        }
+        .map_err(|e| SyncError::Encryption(e.to_string()))
+    }
+
     /// Set server configuration
     pub async fn set_config(&self, config: ServerConfig) -> Result<()> {
-        // Store config in database first
-        let config_json = serde_json::to_string(&config)?;
-        self.db.set_setting("server_config", &config_json)?;
+        // Store config in database first, sealed with the sync key
+        let sealed = {
+            let sync_key = self.sync_key.lock().await;
+            let sync_key = sync_key.as_ref()
+                .ok_or_else(|| anyhow::anyhow!("The sync key is needed to store the server config"))?;
+            seal_config(sync_key, &config)?
+        };
+        self.db.set_setting(SERVER_CONFIG_SETTING, &sealed)?;
 
         // Update in-memory config
         let mut config_guard = self.config.lock().await;
@@ -152,12 +407,21 @@ impl SyncClient {
         Ok(())
     }
 
-    /// Get server configuration
+    /// Get server configuration. A plaintext config left by an older version
+    /// is sealed on first read.
     pub async fn get_config(&self) -> Result<Option<ServerConfig>> {
         // Try to load from database first
-        if let Some(config_json) = self.db.get_setting("server_config")? {
-            if let Ok(config) = serde_json::from_str::<ServerConfig>(&config_json) {
-                return Ok(Some(config));
+        if let Some(stored) = self.db.get_setting(SERVER_CONFIG_SETTING)? {
+            let sync_key = self.sync_key.lock().await.clone();
+            match open_config(sync_key.as_deref(), &stored) {
+                Ok(config) => {
+                    if let (Some(sync_key), false) = (&sync_key, stored.starts_with(SEALED_CONFIG_PREFIX)) {
+                        self.db.set_setting(SERVER_CONFIG_SETTING, &seal_config(sync_key, &config)?)?;
+                        info!("Sealed the plaintext server config");
+                    }
+                    return Ok(Some(config));
+                }
+                Err(e) => debug!("Stored server config is unreadable: {}", e),
             }
         }
 
@@ -171,42 +435,68 @@ impl SyncClient {
         let is_syncing = *self.is_syncing.lock().await;
         let last_sync_at = self.db.get_last_sync_time().await?;
 
-        // Get count of unsynced events using spawn_blocking for async safety
-        let db = self.db.clone();
-        let unsynced_events = tokio::task::spawn_blocking(move || {
-            db.get_unsynced_events_sync()
-        })
-        .await
-        .map_err(|e| anyhow::anyhow!("Task join error: {}", e))??;
-        let pending_events = unsynced_events.len() as i64;
+        let pending_events = self.db.read(|db| db.count_unsynced()).await?;
 
         // Get last error from database
         let last_error = self.db
             .get_setting("last_sync_error")
             .unwrap_or(None);
 
+        let waiting_for_connectivity = *self.connectivity.lock().await == Connectivity::CaptivePortal;
+
+        let uploaded_fields = fields::current_uploaded_fields(&self.db)?;
+        let unconfirmed_deletions = self.db.count_unconfirmed_deletions()?;
+        let progress = *self.progress.lock().unwrap();
+
         Ok(SyncStatus {
             is_syncing,
             last_sync_at: last_sync_at.map(|t| t.to_rfc3339()),
             pending_events,
             last_error,
+            waiting_for_connectivity,
+            uploaded_fields,
+            unconfirmed_deletions,
+            batch_size: self.batch_size()?,
+            progress,
         })
     }
 
+    /// Events uploaded per request, from the batch size setting
+    pub fn batch_size(&self) -> Result<usize> {
+        Ok(self.db.get_setting(SYNC_BATCH_SIZE_SETTING)?
+            .and_then(|value| value.parse::<usize>().ok())
+            .map(|size| size.clamp(1, MAX_SYNC_BATCH_SIZE))
+            .unwrap_or(SYNC_BATCH_SIZE))
+    }
+
+    /// Change the events uploaded per request (1 to MAX_SYNC_BATCH_SIZE); smaller
+    /// batches suit slow or metered connections
+    pub fn set_batch_size(&self, size: usize) -> Result<()> {
+        if !(1..=MAX_SYNC_BATCH_SIZE).contains(&size) {
+            anyhow::bail!("The batch size must be between 1 and {}", MAX_SYNC_BATCH_SIZE);
+        }
+        self.db.set_setting(SYNC_BATCH_SIZE_SETTING, &size.to_string())
+    }
+
+    /// Get which event fields are uploaded
+    pub fn get_field_policy(&self) -> Result<SyncFieldPolicy> {
+        SyncFieldPolicy::load(&self.db)
+    }
+
+    /// Change which event fields are uploaded; applies from the next sync
+    pub fn set_field_policy(&self, policy: &SyncFieldPolicy) -> Result<()> {
+        policy.save(&self.db)
+    }
+
     /// Check if auto-sync is needed (based on pending event count)
     pub async fn check_and_sync_if_needed(&self, threshold: usize) -> Result<(), SyncError> {
-        let db = self.db.clone();
-        let unsynced_events = tokio::task::spawn_blocking(move || {
-            db.get_unsynced_events_sync()
-        })
-        .await
-        .map_err(|e| SyncError::Database(format!("Failed to check pending events: {}", e)))
-        .and_then(|r| r.map_err(|e| SyncError::Database(format!("Failed to get events: {}", e))))?;
-        let pending_count = unsynced_events.len();
+        let pending_count = self.db.read(|db| db.count_unsynced())
+            .await
+            .map_err(|e| SyncError::Database(format!("Failed to count events: {}", e)))?;
 
         debug!("Pending events: {}, threshold: {}", pending_count, threshold);
 
-        if pending_count >= threshold {
+        if pending_count >= threshold as i64 {
             info!("Auto-sync triggered: {} events pending", pending_count);
             self.sync_events().await?;
         }
@@ -247,20 +537,18 @@ impl SyncClient {
                     }
                 }
 
+                // Hold off on battery; manual sync still works
+                let profile = tokio::task::spawn_blocking(current_power_profile).await.unwrap_or_default();
+                if profile.is_saving() {
+                    debug!("Auto-sync skipped: saving power ({:?})", profile);
+                    continue;
+                }
+
                 // Check pending count
-                let db_clone = db.clone();
-                let pending_count = match tokio::task::spawn_blocking(move || {
-                    db_clone.get_unsynced_events_sync()
-                })
-                .await
-                {
-                    Ok(Ok(events)) => events.len(),
-                    Ok(Err(e)) => {
-                        error!("Failed to check pending events: {}", e);
-                        continue;
-                    }
+                let pending_count = match db.read(|db| db.count_unsynced()).await {
+                    Ok(count) => count,
                     Err(e) => {
-                        error!("Task join error: {}", e);
+                        error!("Failed to check pending events: {}", e);
                         continue;
                     }
                 };
@@ -288,7 +576,9 @@ impl SyncClient {
         }
     }
 
-    /// Sync events to server
+    /// Sync events to server: upload the backlog batch by batch until it is
+    /// drained or a batch fails, then exchange deletions and pull other
+    /// devices' events. Progress shows in SyncStatus while it runs.
     pub async fn sync_events(&self) -> SyncResult {
         let start_time = std::time::Instant::now();
 
@@ -301,10 +591,13 @@ impl SyncClient {
             *syncing = true;
         }
 
-        // Ensure we reset syncing flag when done (even on error)
+        // Ensure we reset syncing flag and progress when done (even on error).
+        // Progress is cleared right away, before the flag lets another sync start.
         let is_syncing = self.is_syncing.clone();
+        let progress = self.progress.clone();
         let _guard = scopeguard::guard((), move |_| {
             // This will run when the guard is dropped
+            *progress.lock().unwrap() = None;
             tokio::spawn(async move {
                 let mut syncing = is_syncing.lock().await;
                 *syncing = false;
@@ -316,62 +609,590 @@ impl SyncClient {
             .map_err(|e| SyncError::Unknown(format!("Failed to get config: {}", e)))?
             .ok_or_else(|| SyncError::Unknown("Server not configured".to_string()))?;
 
-        // Get unsynced events using spawn_blocking for async safety
-        let db = self.db.clone();
-        let events = tokio::task::spawn_blocking(move || {
-            db.get_unsynced_events_sync()
-        })
-        .await
-        .map_err(|e| SyncError::Database(format!("Task join error: {}", e)))
-        .and_then(|r| r.map_err(|e| SyncError::Database(format!("Failed to get events: {}", e))))?;
+        let batch_size = self.batch_size()
+            .map_err(|e| SyncError::Database(format!("Failed to get batch size: {}", e)))?;
+        let total_events = self.db.read(|db| db.count_unsynced())
+            .await
+            .map_err(|e| SyncError::Database(format!("Failed to count events: {}", e)))?
+            .max(0) as usize;
+
+        if total_events > 0 {
+            // Never send the token through a captive portal
+            self.check_connectivity(&config).await?;
+            info!("Syncing {} events to {} in batches of {}", total_events, config.server_url, batch_size);
 
-        if events.is_empty() {
+            if let Err(e) = self.upload_backlog(&config, batch_size, total_events).await {
+                // Store error for UI display
+                let error_msg = e.to_string();
+                let _ = self.db.set_setting("last_sync_error", &error_msg);
+
+                let elapsed = start_time.elapsed();
+                error!("Sync failed after {:?}: {}", elapsed, error_msg);
+
+                return Err(e);
+            }
+        } else {
             info!("No events to sync");
+        }
+
+        // A passphrase changed here or on another device
+        if let Err(e) = self.share_wrapped_key(&config).await {
+            error!("Failed to sync the wrapped key: {}", e);
+        }
+
+        // Unconfirmed deletions stay queued and show up in SyncStatus
+        if let Err(e) = self.propagate_deletions(&config).await {
+            error!("Failed to propagate deletions: {}", e);
+        }
+
+        // Other devices' events; the next sync picks up where this one stopped
+        if let Err(e) = self.pull_events(&config).await {
+            error!("Failed to pull events: {}", e);
+        }
+
+        let elapsed = start_time.elapsed();
+        info!("Sync completed in {:?}", elapsed);
+        Ok(())
+    }
+
+    /// Upload the oldest unsynced events `batch_size` at a time, marking each
+    /// batch synced as the server accepts it. Stops at the first failure (what
+    /// was uploaded stays synced) or once a batch comes back short, so events
+    /// recorded meanwhile don't keep the loop going.
+    async fn upload_backlog(&self, config: &ServerConfig, batch_size: usize, total_events: usize) -> SyncResult {
+        // Encrypt and send events only once the key is known to be the right one
+        self.verify_key(config).await?;
+
+        let mut synced_events = 0;
+        let mut batches = 0;
+        *self.progress.lock().unwrap() = Some(SyncProgress { synced_events, total_events, batches });
+
+        loop {
+            let batch = self.db.read(move |db| db.get_unsynced_events_sync(batch_size))
+                .await
+                .map_err(|e| SyncError::Database(format!("Failed to get events: {}", e)))?;
+            if batch.is_empty() {
+                break;
+            }
+
+            let event_ids: Vec<String> = batch.iter().map(|e| e.id.clone()).collect();
+            self.sync_with_retry(config, &batch, 3).await?;
+
+            // Mark events as synced
+            self.db.write(move |db| db.mark_as_synced(&event_ids))
+                .await
+                .map_err(|e| SyncError::Database(format!("Failed to mark as synced: {}", e)))?;
+
+            // Update last sync time
+            let now = Utc::now().timestamp_millis().to_string();
+            self.db.update_sync_state("last_sync_at", &now)
+                .map_err(|e| SyncError::Database(format!("Failed to update sync state: {}", e)))?;
+
+            // Clear last error
+            let _ = self.db.set_setting("last_sync_error", "");
+
+            synced_events += batch.len();
+            batches += 1;
+            // Events recorded during the sync can push the count past the starting total
+            let total_events = total_events.max(synced_events);
+            *self.progress.lock().unwrap() = Some(SyncProgress { synced_events, total_events, batches });
+            debug!("Synced batch {}: {} of {} events", batches, synced_events, total_events);
+
+            if batch.len() < batch_size {
+                break;
+            }
+        }
+
+        info!("Uploaded {} events in {} batches", synced_events, batches);
+        Ok(())
+    }
+
+    /// Make sure the sync key is the one the account's data is encrypted with,
+    /// so a wrong passphrase (e.g. after a reinstall) stops sync instead of
+    /// uploading data no other device can read. The first device to sync sets
+    /// the account's key check value; a value the server confirmed is kept
+    /// locally so later syncs skip the round trip.
+    async fn verify_key(&self, config: &ServerConfig) -> SyncResult {
+        let confirmed = self.db.get_sync_state(KEY_CHECK_STATE_KEY)
+            .map_err(|e| SyncError::Database(format!("Failed to get key check: {}", e)))?;
+        let crypto = self.subkey_crypto(KeyPurpose::KeyCheck, None).await?;
+        if confirmed.is_some_and(|value| crypto.matches_key_check(&value)) {
             return Ok(());
         }
+        let ours = crypto.key_check_value()
+            .map_err(|e| SyncError::Encryption(format!("Failed to create key check: {}", e)))?;
 
-        // Take only first 100 events
-        let batch: Vec<_> = events.into_iter().take(100).collect();
-        let batch_size = batch.len();
-        let event_ids: Vec<String> = batch.iter().map(|e| e.id.clone()).collect();
+        let url = format!("{}/api/v1/sync/key-check", config.server_url.trim_end_matches('/'));
+        let response = self.http_client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", config.jwt_token))
+            .json(&KeyCheck { key_check: ours })
+            .send()
+            .await
+            .map_err(|e| SyncError::Network(format!("Failed to connect: {}", e)))?;
 
-        info!("Syncing {} events to {}", batch_size, config.server_url);
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return match status.as_u16() {
+                // A server from before key checks: nothing to verify against
+                404 => {
+                    warn!("Server doesn't support key checks; uploading unverified");
+                    Ok(())
+                }
+                401 | 403 => Err(SyncError::Auth(format!("Authentication failed: {}", error_text))),
+                500..=599 => Err(SyncError::Server(format!("Server error: {}", error_text))),
+                code => Err(SyncError::Unknown(format!("HTTP {}: {}", code, error_text))),
+            };
+        }
 
-        // Encrypt and send events with retry logic
-        let result = self.sync_with_retry(&config, &batch, 3).await;
+        let stored: KeyCheck = response
+            .json()
+            .await
+            .map_err(|e| SyncError::Unknown(format!("Failed to parse response: {}", e)))?;
+        if !crypto.matches_key_check(&stored.key_check) {
+            if let Err(e) = self.adopt_account_key(config).await {
+                warn!("Failed to fetch the account's wrapped key: {}", e);
+            }
+            return Err(SyncError::KeyMismatch);
+        }
 
-        match result {
-            Ok(_) => {
-                // Mark events as synced
-                self.db.mark_as_synced(&event_ids)
-                    .map_err(|e| SyncError::Database(format!("Failed to mark as synced: {}", e)))?;
+        self.db.update_sync_state(KEY_CHECK_STATE_KEY, &stored.key_check)
+            .map_err(|e| SyncError::Database(format!("Failed to store key check: {}", e)))
+    }
 
-                // Update last sync time
-                let now = Utc::now().timestamp_millis().to_string();
-                self.db.update_sync_state("last_sync_at", &now)
-                    .map_err(|e| SyncError::Database(format!("Failed to update sync state: {}", e)))?;
+    /// Verify the sync key against the configured server's account; without
+    /// a server there is no account to check it against yet
+    pub async fn verify_account_key(&self) -> SyncResult {
+        let config = self.get_config().await
+            .map_err(|e| SyncError::Unknown(format!("Failed to get config: {}", e)))?;
+        let Some(config) = config else {
+            return Ok(());
+        };
+        self.check_connectivity(&config).await?;
+        self.verify_key(&config).await
+    }
 
-                // Clear last error
-                let _ = self.db.set_setting("last_sync_error", "");
+    /// After a key mismatch, point the next unlock at the account's key: its
+    /// wrapped copy on the server, or for an account from before wrapped keys
+    /// the key derived from the passphrase. The key this device made at its
+    /// first unlock, before it knew the account, is dropped; the recovery code
+    /// is shown again for the account's key.
+    async fn adopt_account_key(&self, config: &ServerConfig) -> SyncResult {
+        let url = format!("{}/api/v1/sync/wrapped-key", config.server_url.trim_end_matches('/'));
+        let request = self.http_client
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", config.jwt_token));
+        let Some(account) = self.wrapped_key_request(request).await? else {
+            return Ok(());
+        };
 
-                let elapsed = start_time.elapsed();
-                info!("Sync completed: {} events in {:?}", batch_size, elapsed);
+        let set = |key: &str, value: &str| self.db.update_sync_state(key, value)
+            .map_err(|e| SyncError::Database(format!("Failed to store the account key: {}", e)));
+        match account.wrapped_key {
+            Some(wrapped) => set(WRAPPED_KEY_STATE_KEY, &wrapped)?,
+            None => {
+                set(WRAPPED_KEY_STATE_KEY, "")?;
+                set(DERIVED_KEY_STATE_KEY, "1")?;
+            }
+        }
+        set(WRAPPED_KEY_PENDING_STATE_KEY, "")?;
+        set(RECOVERY_CODE_STATE_KEY, "")?;
+        self.db.flush_status_writes()
+            .map_err(|e| SyncError::Database(format!("Failed to store the account key: {}", e)))?;
+        info!("Stored the account's key; it applies from the next unlock");
+        Ok(())
+    }
+
+    /// Keep the wrapped sync key in step with the account's copy on the
+    /// server: after a passphrase change here ours replaces it, otherwise the
+    /// server's is taken over (the passphrase changed on another device) or,
+    /// if it has none yet, ours becomes the account's. Only the passphrase
+    /// unwraps it, so from then on the old passphrase unlocks the key nowhere.
+    async fn share_wrapped_key(&self, config: &ServerConfig) -> SyncResult {
+        self.check_connectivity(config).await?;
+        self.verify_key(config).await?;
+        let get = |key: &str| self.db.get_sync_state(key)
+            .map(|value| value.filter(|value| !value.is_empty()))
+            .map_err(|e| SyncError::Database(format!("Failed to get the wrapped key: {}", e)));
+        // A server from before key checks can't tell whose key it would get
+        let Some(key_check) = get(KEY_CHECK_STATE_KEY)? else {
+            return Ok(());
+        };
+        let ours = get(WRAPPED_KEY_STATE_KEY)?;
+        let pending = get(WRAPPED_KEY_PENDING_STATE_KEY)?.is_some();
 
-                Ok(())
+        let url = format!("{}/api/v1/sync/wrapped-key", config.server_url.trim_end_matches('/'));
+        let store = |wrapped_key: &str, replace: bool| self.http_client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", config.jwt_token))
+            .json(&StoreWrappedKey { wrapped_key, key_check: &key_check, replace });
+        let stored = match (&ours, pending) {
+            (Some(ours), true) => self.wrapped_key_request(store(ours, true)).await?,
+            _ => {
+                let request = self.http_client
+                    .get(&url)
+                    .header("Authorization", format!("Bearer {}", config.jwt_token));
+                match (self.wrapped_key_request(request).await?, &ours) {
+                    (Some(WrappedKey { wrapped_key: None }), Some(ours)) => {
+                        self.wrapped_key_request(store(ours, false)).await?
+                    }
+                    (account, _) => account,
+                }
             }
-            Err(e) => {
-                // Store error for UI display
-                let error_msg = e.to_string();
-                let _ = self.db.set_setting("last_sync_error", &error_msg);
+        };
+        // A server from before wrapped keys
+        let Some(stored) = stored else {
+            return Ok(());
+        };
 
-                let elapsed = start_time.elapsed();
-                error!("Sync failed after {:?}: {}", elapsed, error_msg);
+        if pending {
+            self.db.update_sync_state(WRAPPED_KEY_PENDING_STATE_KEY, "")
+                .map_err(|e| SyncError::Database(format!("Failed to store the wrapped key: {}", e)))?;
+        }
+        if let Some(account) = stored.wrapped_key.filter(|account| ours.as_ref() != Some(account)) {
+            self.db.update_sync_state(WRAPPED_KEY_STATE_KEY, &account)
+                .map_err(|e| SyncError::Database(format!("Failed to store the wrapped key: {}", e)))?;
+            info!("Took over the account's wrapped key; its passphrase unlocks from now on");
+        }
+        self.db.flush_status_writes()
+            .map_err(|e| SyncError::Database(format!("Failed to store the wrapped key: {}", e)))
+    }
+
+    /// Send a wrapped-key request; None from a server without wrapped keys
+    async fn wrapped_key_request(&self, request: reqwest::RequestBuilder) -> std::result::Result<Option<WrappedKey>, SyncError> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| SyncError::Network(format!("Failed to connect: {}", e)))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return match status.as_u16() {
+                404 => Ok(None),
+                409 => Err(SyncError::KeyMismatch),
+                401 | 403 => Err(SyncError::Auth(format!("Authentication failed: {}", error_text))),
+                500..=599 => Err(SyncError::Server(format!("Server error: {}", error_text))),
+                code => Err(SyncError::Unknown(format!("HTTP {}: {}", code, error_text))),
+            };
+        }
+
+        response
+            .json()
+            .await
+            .map(Some)
+            .map_err(|e| SyncError::Unknown(format!("Failed to parse response: {}", e)))
+    }
+
+    /// Send queued local deletions so the server drops its copies too
+    async fn propagate_deletions(&self, config: &ServerConfig) -> SyncResult {
+        let event_ids = self.db.get_deletions_to_send(DELETION_BATCH_SIZE)
+            .map_err(|e| SyncError::Database(format!("Failed to get pending deletions: {}", e)))?;
+        if event_ids.is_empty() {
+            return Ok(());
+        }
+
+        self.check_connectivity(config).await?;
+
+        self.db.record_deletions_sent(&event_ids, Utc::now())
+            .map_err(|e| SyncError::Database(format!("Failed to record deletion attempt: {}", e)))?;
+
+        let request = DeletionRequest {
+            device_id: config.device_id.clone(),
+            event_ids,
+        };
+
+        let url = format!("{}/api/v1/sync/deletions", config.server_url.trim_end_matches('/'));
+
+        let response = self.http_client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", config.jwt_token))
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| SyncError::Network(format!("Failed to connect: {}", e)))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return match status.as_u16() {
+                401 | 403 => Err(SyncError::Auth(format!("Authentication failed: {}", error_text))),
+                500..=599 => Err(SyncError::Server(format!("Server error: {}", error_text))),
+                code => Err(SyncError::Unknown(format!("HTTP {}: {}", code, error_text))),
+            };
+        }
+
+        let acknowledged: DeletionResponse = response.json().await
+            .map_err(|e| SyncError::Unknown(format!("Failed to parse deletion response: {}", e)))?;
+
+        self.db.confirm_deletions(&acknowledged.confirmed_ids, Utc::now())
+            .map_err(|e| SyncError::Database(format!("Failed to confirm deletions: {}", e)))?;
+
+        info!(
+            "Server confirmed {} of {} deletions",
+            acknowledged.confirmed_ids.len(),
+            request.event_ids.len()
+        );
+        Ok(())
+    }
 
-                Err(e)
+    /// Download the events the user's other devices synced since the last
+    /// pull, decrypt them and store them in `remote_events`. Events that don't
+    /// decrypt under the verified key are skipped rather than retried forever.
+    async fn pull_events(&self, config: &ServerConfig) -> SyncResult {
+        self.check_connectivity(config).await?;
+        self.verify_key(config).await?;
+        let sync_key = self.current_key().await
+            .ok_or_else(|| SyncError::Encryption("Crypto manager not initialized".to_string()))?;
+
+        let mut cursor: i64 = self.db.get_sync_state(PULL_CURSOR_STATE_KEY)
+            .map_err(|e| SyncError::Database(format!("Failed to get pull cursor: {}", e)))?
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+        let url = format!("{}/api/v1/sync/events", config.server_url.trim_end_matches('/'));
+        let mut pulled = 0;
+
+        loop {
+            let response = self.http_client
+                .get(&url)
+                .header("Authorization", format!("Bearer {}", config.jwt_token))
+                .query(&[
+                    ("cursor", cursor.to_string()),
+                    ("limit", PULL_BATCH_SIZE.to_string()),
+                    ("exclude_own", "true".to_string()),
+                ])
+                .send()
+                .await
+                .map_err(|e| SyncError::Network(format!("Failed to connect: {}", e)))?;
+
+            let status = response.status();
+            if !status.is_success() {
+                let error_text = response.text().await.unwrap_or_default();
+                return match status.as_u16() {
+                    401 | 403 => Err(SyncError::Auth(format!("Authentication failed: {}", error_text))),
+                    500..=599 => Err(SyncError::Server(format!("Server error: {}", error_text))),
+                    code => Err(SyncError::Unknown(format!("HTTP {}: {}", code, error_text))),
+                };
+            }
+
+            let page: PullResponse = response.json().await
+                .map_err(|e| SyncError::Unknown(format!("Failed to parse pulled events: {}", e)))?;
+            // An empty last page may leave the cursor where it was; anything
+            // else has to move it forward or the pull would never end
+            if page.next_cursor < cursor || (page.has_more && page.next_cursor == cursor) {
+                return Err(SyncError::Server(format!(
+                    "Pull cursor didn't advance past {} (server returned {})",
+                    cursor, page.next_cursor
+                )));
+            }
+            let events: Vec<RemoteEvent> = page.events.iter()
+                .filter_map(|event| match open_pulled_event(&sync_key, event) {
+                    Ok(opened) => Some(opened),
+                    Err(e) => {
+                        warn!("Skipping pulled event {} from {}: {}", event.id, event.device_id, e);
+                        None
+                    }
+                })
+                .collect();
+
+            self.db.store_remote_events(&events, Utc::now())
+                .map_err(|e| SyncError::Database(format!("Failed to store pulled events: {}", e)))?;
+            cursor = page.next_cursor;
+            self.db.update_sync_state(PULL_CURSOR_STATE_KEY, &cursor.to_string())
+                .map_err(|e| SyncError::Database(format!("Failed to store pull cursor: {}", e)))?;
+            pulled += events.len();
+
+            if !page.has_more || page.events.is_empty() {
+                break;
+            }
+        }
+
+        info!("Pulled {} events from other devices", pulled);
+        Ok(())
+    }
+
+    /// Probe for a captive portal and record the result for SyncStatus
+    async fn check_connectivity(&self, config: &ServerConfig) -> SyncResult {
+        let probe_url = self.db
+            .get_setting(PROBE_URL_SETTING)
+            .ok()
+            .flatten()
+            .filter(|url| !url.is_empty())
+            .unwrap_or_else(|| default_probe_url(&config.server_url));
+
+        let connectivity = self.probe.check(&probe_url).await;
+        *self.connectivity.lock().await = connectivity;
+
+        match connectivity {
+            Connectivity::CaptivePortal => {
+                let error = SyncError::CaptivePortal;
+                let _ = self.db.set_setting("last_sync_error", &error.to_string());
+                Err(error)
+            }
+            // Offline: let the request itself fail with a network error
+            Connectivity::Online | Connectivity::Offline => Ok(()),
+        }
+    }
+
+    /// Archive closed events before `cutoff` into `archive_dir` and prune them
+    /// locally. When archive upload is enabled the file is also sent to the
+    /// server; an upload failure is logged but the local archive is kept.
+    pub async fn archive_and_prune(
+        &self,
+        archive_dir: &Path,
+        cutoff: DateTime<Utc>,
+    ) -> std::result::Result<Option<ExportedArchive>, SyncError> {
+        let exported = {
+            let crypto = self.crypto.lock().await;
+            let crypto = crypto
+                .as_ref()
+                .ok_or_else(|| SyncError::Encryption("Crypto manager not initialized".to_string()))?;
+            archive::export_and_prune(&self.db, crypto, archive_dir, cutoff)
+                .map_err(|e| SyncError::Database(format!("Failed to archive events: {}", e)))?
+        };
+
+        let Some(exported) = exported else {
+            return Ok(None);
+        };
+
+        self.upload_archive_if_enabled(&exported).await;
+        Ok(Some(exported))
+    }
+
+    /// Apply the retention policy, if one is set. Archives it writes are
+    /// uploaded like manual ones when archive upload is enabled.
+    pub async fn apply_retention(
+        &self,
+        archive_dir: &Path,
+    ) -> std::result::Result<Option<RetentionRun>, SyncError> {
+        let policy = self.db
+            .get_retention_policy()
+            .map_err(|e| SyncError::Database(format!("Failed to read retention policy: {}", e)))?;
+        let Some(cutoff) = policy.cutoff(Utc::now()) else {
+            return Ok(None);
+        };
+
+        let run = {
+            let crypto = self.crypto.lock().await;
+            retention::apply_retention(&self.db, policy.action, crypto.as_ref(), archive_dir, cutoff)
+                .map_err(|e| SyncError::Database(format!("Failed to apply retention policy: {}", e)))?
+        };
+
+        if let Some(exported) = &run.archive {
+            self.upload_archive_if_enabled(exported).await;
+        }
+        Ok(Some(run))
+    }
+
+    async fn upload_archive_if_enabled(&self, exported: &ExportedArchive) {
+        let upload_enabled = self.db
+            .get_setting(ARCHIVE_UPLOAD_SETTING)
+            .ok()
+            .flatten()
+            .is_some_and(|value| value == "true");
+
+        if upload_enabled {
+            match self.get_config().await.ok().flatten() {
+                Some(config) => {
+                    if let Err(e) = self.upload_archive(&config, exported).await {
+                        error!("Archive upload failed, kept locally at {}: {}", exported.path.display(), e);
+                    }
+                }
+                None => debug!("Archive upload enabled but server not configured"),
             }
         }
     }
 
+    /// Encrypt a tracker event recording with the sync key and write it to `dir`
+    pub async fn save_recording(
+        &self,
+        dir: &Path,
+        recording: &Recording,
+    ) -> std::result::Result<SavedRecording, SyncError> {
+        let bytes = {
+            let crypto = self.crypto.lock().await;
+            let crypto = crypto
+                .as_ref()
+                .ok_or_else(|| SyncError::Encryption("Crypto manager not initialized".to_string()))?;
+            recorder::encode_recording(crypto, recording)
+                .map_err(|e| SyncError::Encryption(format!("Failed to encrypt recording: {}", e)))?
+        };
+
+        let path = dir.join(format!(
+            "recording-{}.{}",
+            recording.started_at.timestamp_millis(),
+            RECORDING_EXTENSION
+        ));
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| SyncError::Unknown(format!("Failed to create recordings directory: {}", e)))?;
+        tokio::fs::write(&path, &bytes)
+            .await
+            .map_err(|e| SyncError::Unknown(format!("Failed to write recording: {}", e)))?;
+        info!("Saved event recording with {} samples to {}", recording.samples.len(), path.display());
+
+        Ok(SavedRecording { path, sample_count: recording.samples.len() })
+    }
+
+    /// Read and decrypt a recording written by `save_recording`
+    pub async fn load_recording(&self, path: &Path) -> std::result::Result<Recording, SyncError> {
+        let bytes = tokio::fs::read(path)
+            .await
+            .map_err(|e| SyncError::Unknown(format!("Failed to read recording: {}", e)))?;
+
+        let crypto = self.crypto.lock().await;
+        let crypto = crypto
+            .as_ref()
+            .ok_or_else(|| SyncError::Encryption("Crypto manager not initialized".to_string()))?;
+        recorder::decode_recording(crypto, &bytes)
+            .map_err(|e| SyncError::Encryption(format!("Failed to decrypt recording: {}", e)))
+    }
+
+    /// Upload an archive file as an opaque blob; it is already encrypted
+    async fn upload_archive(&self, config: &ServerConfig, exported: &ExportedArchive) -> SyncResult {
+        self.check_connectivity(config).await?;
+
+        let bytes = tokio::fs::read(&exported.path)
+            .await
+            .map_err(|e| SyncError::Unknown(format!("Failed to read archive: {}", e)))?;
+
+        let request = ArchiveUploadRequest {
+            device_id: config.device_id.clone(),
+            file_name: exported.path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            range_start: exported.header.range_start.timestamp_millis(),
+            range_end: exported.header.range_end.timestamp_millis(),
+            event_count: exported.header.event_count,
+            data: base64::engine::general_purpose::STANDARD.encode(&bytes),
+        };
+
+        let url = format!("{}/api/v1/sync/archives", config.server_url.trim_end_matches('/'));
+
+        let response = self.http_client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", config.jwt_token))
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| SyncError::Network(format!("Failed to connect: {}", e)))?;
+
+        let status = response.status();
+        if status.is_success() {
+            info!("Uploaded archive {} ({} events)", request.file_name, request.event_count);
+            return Ok(());
+        }
+
+        let error_text = response.text().await.unwrap_or_default();
+        match status.as_u16() {
+            401 | 403 => Err(SyncError::Auth(format!("Authentication failed: {}", error_text))),
+            500..=599 => Err(SyncError::Server(format!("Server error: {}", error_text))),
+            code => Err(SyncError::Unknown(format!("HTTP {}: {}", code, error_text))),
+        }
+    }
+
     /// Sync with retry logic (exponential backoff)
     async fn sync_with_retry(&self, config: &ServerConfig, events: &[StoredEvent], max_retries: u32) -> SyncResult {
         let mut attempt = 0;
@@ -411,25 +1232,26 @@ impl SyncClient {
     /// Send events to server
     async fn send_events(&self, config: &ServerConfig, events: &[StoredEvent]) -> SyncResult {
         // Build sync events with encryption
-        let sync_events = self.build_sync_events(events).await?;
+        let sync_events = self.build_sync_events(events, &config.device_id).await?;
+        let deleted = self.pending_tombstones()?;
+        let tombstone_ids: Vec<String> = deleted.iter().map(|t| t.id.clone()).collect();
+        self.db.record_deletions_sent(&tombstone_ids, Utc::now())
+            .map_err(|e| SyncError::Database(format!("Failed to record deletion attempt: {}", e)))?;
 
         // Build request
         let request = SyncRequest {
             device_id: config.device_id.clone(),
+            client: self.client_info.clone(),
             events: sync_events,
+            deleted,
+            sessions: self.referenced_sessions(events)?,
         };
 
         // Send to server
         let url = format!("{}/api/v1/sync/events", config.server_url.trim_end_matches('/'));
-
-        let response = self.http_client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", config.jwt_token))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| SyncError::Network(format!("Failed to connect: {}", e)))?;
+        let body = serde_json::to_vec(&request)
+            .map_err(|e| SyncError::Unknown(format!("Failed to serialize request: {}", e)))?;
+        let response = self.post_json(&url, config, body).await?;
 
         // Handle response
         let status = response.status();
@@ -440,10 +1262,15 @@ impl SyncClient {
                 .await
                 .map_err(|e| SyncError::Unknown(format!("Failed to parse response: {}", e)))?;
 
+            self.db.confirm_deletions(&sync_response.deleted_ids, Utc::now())
+                .map_err(|e| SyncError::Database(format!("Failed to confirm deletions: {}", e)))?;
+
             tracing::info!(
-                "Sync successful: {} events processed at {}",
+                "Sync successful: {} events processed at {}, {} of {} deletions confirmed",
                 sync_response.processed_count,
-                sync_response.synced_at
+                sync_response.synced_at,
+                sync_response.deleted_ids.len(),
+                tombstone_ids.len()
             );
             Ok(())
         } else {
@@ -464,100 +1291,276 @@ impl SyncClient {
         }
     }
 
-    /// Build sync events with encryption
-    async fn build_sync_events(&self, events: &[StoredEvent]) -> std::result::Result<Vec<SyncEvent>, SyncError> {
-        let mut sync_events = Vec::with_capacity(events.len());
-        let crypto = self.crypto.lock().await;
-
-        let crypto_ref = crypto.as_ref()
-            .ok_or_else(|| SyncError::Encryption("Crypto manager not initialized".to_string()))?;
+    /// POST a JSON body, gzip-compressed when it is large enough to be worth
+    /// it. A server that refuses compressed bodies (415) gets this one again
+    /// uncompressed, and all later ones too.
+    async fn post_json(&self, url: &str, config: &ServerConfig, body: Vec<u8>) -> std::result::Result<reqwest::Response, SyncError> {
+        let refused = self.db.get_sync_state(COMPRESSION_REFUSED_STATE_KEY)
+            .map_err(|e| SyncError::Database(format!("Failed to get sync state: {}", e)))?
+            .is_some();
+
+        if body.len() >= COMPRESSION_THRESHOLD && !refused {
+            let compressed = gzip(&body)
+                .map_err(|e| SyncError::Unknown(format!("Failed to compress request: {}", e)))?;
+            debug!("Compressed upload from {} to {} bytes", body.len(), compressed.len());
+            let response = self.http_client
+                .post(url)
+                .header("Authorization", format!("Bearer {}", config.jwt_token))
+                .header("Content-Type", "application/json")
+                .header("Content-Encoding", "gzip")
+                .body(compressed)
+                .send()
+                .await
+                .map_err(|e| SyncError::Network(format!("Failed to connect: {}", e)))?;
+            if response.status() != reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE {
+                return Ok(response);
+            }
 
-        for event in events {
-            // Use database event ID instead of generating new UUID
-            let id = event.id.clone();
+            warn!("Server doesn't accept compressed uploads; sending them uncompressed");
+            self.db.update_sync_state(COMPRESSION_REFUSED_STATE_KEY, &Utc::now().to_rfc3339())
+                .map_err(|e| SyncError::Database(format!("Failed to update sync state: {}", e)))?;
+        }
 
-            // Prepare data to encrypt (use app_name or window_title)
-            let plaintext = event.window_title.as_ref()
-                .map(|s| s.as_bytes())
-                .unwrap_or_else(|| event.app_name.as_bytes());
+        self.http_client
+            .post(url)
+            .header("Authorization", format!("Bearer {}", config.jwt_token))
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| SyncError::Network(format!("Failed to connect: {}", e)))
+    }
 
-            // Encrypt data
-            let encrypted = crypto_ref.encrypt(plaintext)
-                .map_err(|e| SyncError::Encryption(format!("Failed to encrypt: {}", e)))?;
+    /// Build the exact JSON body an upload would send, without sending it
+    pub(crate) async fn build_request_body(&self, events: &[StoredEvent]) -> std::result::Result<String, SyncError> {
+        let device_id = self.get_config().await
+            .ok()
+            .flatten()
+            .map(|config| config.device_id)
+            .unwrap_or_default();
+        let sync_events = self.build_sync_events(events, &device_id).await?;
 
-            // Extract nonce (12 bytes) and encode as hex (24 chars)
-            let nonce = hex::encode(&encrypted.nonce);
+        let request = SyncRequest {
+            device_id,
+            client: self.client_info.clone(),
+            events: sync_events,
+            deleted: self.pending_tombstones()?,
+            sessions: self.referenced_sessions(events)?,
+        };
 
-            // Extract tag from ciphertext (last 16 bytes of AES-GCM)
-            // Note: aes_gcm crate appends the tag to the ciphertext
-            let tag_len = 16;
-            let ciphertext_len = encrypted.ciphertext.len();
-            if ciphertext_len < tag_len {
-                return Err(SyncError::Encryption("Invalid ciphertext length".to_string()));
-            }
-            let tag_bytes = &encrypted.ciphertext[ciphertext_len - tag_len..];
+        serde_json::to_string(&request)
+            .map_err(|e| SyncError::Unknown(format!("Failed to serialize request: {}", e)))
+    }
 
-            // Encode tag as base64 STANDARD with padding: 16 bytes -> 24 chars
-            let tag = base64::engine::general_purpose::STANDARD.encode(tag_bytes);
+    /// The app sessions `events` were recorded in
+    fn referenced_sessions(&self, events: &[StoredEvent]) -> std::result::Result<Vec<AppSession>, SyncError> {
+        let mut ids: Vec<String> = events.iter().filter_map(|event| event.session_id.clone()).collect();
+        ids.sort();
+        ids.dedup();
+        self.db.get_app_sessions_by_id(&ids)
+            .map_err(|e| SyncError::Database(format!("Failed to get app sessions: {}", e)))
+    }
 
-            // Encode ciphertext WITHOUT the tag (just the encrypted payload)
-            // The tag is sent separately for verification
-            let payload_len = ciphertext_len - tag_len;
-            let encrypted_data = base64::engine::general_purpose::STANDARD.encode(&encrypted.ciphertext[..payload_len]);
+    /// Unconfirmed local deletions to ride along with an upload, oldest first
+    fn pending_tombstones(&self) -> std::result::Result<Vec<SyncTombstone>, SyncError> {
+        let pending = self.db.get_unconfirmed_deletions()
+            .map_err(|e| SyncError::Database(format!("Failed to get pending deletions: {}", e)))?;
+        Ok(pending
+            .into_iter()
+            .take(DELETION_BATCH_SIZE)
+            .map(|deletion| SyncTombstone {
+                id: deletion.event_id,
+                deleted_at: deletion.deleted_at.timestamp_millis(),
+            })
+            .collect())
+    }
 
-            // Determine category
-            let category = self.categorize_app(&event.app_name);
+    /// Build sync events with encryption, each payload bound to its event on
+    /// `device_id` and encrypted with that device's payload subkey
+    async fn build_sync_events(&self, events: &[StoredEvent], device_id: &str) -> std::result::Result<Vec<SyncEvent>, SyncError> {
+        let crypto = self.subkey_crypto(KeyPurpose::SyncPayload, Some(device_id)).await?;
 
-            // Ensure timestamp is not in the future (max 1 minute ahead allowed)
-            let now_millis = Utc::now().timestamp_millis();
-            let event_timestamp = event.timestamp.timestamp_millis();
-            let timestamp = if event_timestamp > now_millis + 60000 {
-                // If event is more than 1 minute in the future, use current time
-                now_millis
-            } else {
-                event_timestamp
-            };
+        let rules = self.db.get_category_rules()
+            .map_err(|e| SyncError::Database(e.to_string()))?;
 
-            let sync_event = SyncEvent {
-                id,
-                event_type: event.event_type.clone(),
-                timestamp,
-                duration: event.duration,
-                encrypted_data,
-                nonce,
-                tag,
-                app_name: event.app_name.clone(),
-                category,
-            };
+        let policy = SyncFieldPolicy::load(&self.db)
+            .map_err(|e| SyncError::Database(e.to_string()))?;
 
-            sync_events.push(sync_event);
-        }
+        let sync_events = encrypt_sync_events(&crypto, &rules, &policy, device_id, events, Utc::now().timestamp_millis())?;
 
         debug!("Built {} sync events with encryption", sync_events.len());
         Ok(sync_events)
     }
+}
 
-    /// Categorize app based on name
-    fn categorize_app(&self, app_name: &str) -> Option<String> {
-        let app_lower = app_name.to_lowercase();
-
-        let category = if app_lower.contains("chrome") || app_lower.contains("firefox") || app_lower.contains("edge") {
-            "work"
-        } else if app_lower.contains("code") || app_lower.contains("idea") || app_lower.contains("visual") {
-            "development"
-        } else if app_lower.contains("slack") || app_lower.contains("teams") || app_lower.contains("zoom") {
-            "communication"
-        } else if app_lower.contains("spotify") || app_lower.contains("netflix") || app_lower.contains("vlc") {
-            "entertainment"
-        } else if app_lower.contains("word") || app_lower.contains("excel") || app_lower.contains("powerpoint") {
-            "productivity"
-        } else if app_lower.contains("steam") || app_lower.contains("game") {
-            "gaming"
-        } else {
-            "other"
-        };
+/// Encrypt and convert events on the rayon pool; output keeps the input order
+fn encrypt_sync_events(
+    crypto: &CryptoManager,
+    rules: &CategoryRules,
+    policy: &SyncFieldPolicy,
+    device_id: &str,
+    events: &[StoredEvent],
+    now_millis: i64,
+) -> std::result::Result<Vec<SyncEvent>, SyncError> {
+    events
+        .par_iter()
+        .map(|event| build_sync_event(crypto, rules, policy, device_id, event, now_millis))
+        .collect()
+}
+
+/// Version of the associated data layout below, sent as `aad_version`
+const EVENT_AAD_VERSION: u8 = 1;
+
+/// Associated data binding an event's ciphertext to its id, device and
+/// timestamp: a payload swapped onto another event (by the server or anyone
+/// with access to its database) no longer decrypts
+fn event_aad(event_id: &str, device_id: &str, timestamp: i64) -> Vec<u8> {
+    format!("lifespan-event-v{}\0{}\0{}\0{}", EVENT_AAD_VERSION, event_id, device_id, timestamp).into_bytes()
+}
 
-        Some(category.to_string())
+fn build_sync_event(
+    crypto: &CryptoManager,
+    rules: &CategoryRules,
+    policy: &SyncFieldPolicy,
+    device_id: &str,
+    event: &StoredEvent,
+    now_millis: i64,
+) -> std::result::Result<SyncEvent, SyncError> {
+    // Use database event ID instead of generating new UUID
+    let id = event.id.clone();
+
+    // Prepare data to encrypt (use window_title or app_name, whichever the policy allows).
+    // The server requires a payload, so an opted-out event encrypts an empty one
+    let plaintext = event.window_title.as_ref()
+        .filter(|_| policy.window_title)
+        .or(Some(&event.app_name).filter(|_| policy.app_name))
+        .map(|s| s.as_bytes())
+        .unwrap_or_default();
+
+    // Ensure timestamp is not in the future (max 1 minute ahead allowed)
+    let event_timestamp = event.timestamp.timestamp_millis();
+    let timestamp = if event_timestamp > now_millis + 60000 {
+        // If event is more than 1 minute in the future, use current time
+        now_millis
+    } else {
+        event_timestamp
+    };
+
+    // Encrypt data, bound to the event as uploaded
+    let encrypted = crypto.encrypt_with_aad(plaintext, &event_aad(&id, device_id, timestamp))
+        .map_err(|e| SyncError::Encryption(format!("Failed to encrypt: {}", e)))?;
+
+    // Extract nonce (12 bytes) and encode as hex (24 chars)
+    let nonce = hex::encode(&encrypted.nonce);
+
+    // Extract tag from ciphertext (last 16 bytes of AES-GCM)
+    // Note: aes_gcm crate appends the tag to the ciphertext
+    let tag_len = 16;
+    let ciphertext_len = encrypted.ciphertext.len();
+    if ciphertext_len < tag_len {
+        return Err(SyncError::Encryption("Invalid ciphertext length".to_string()));
+    }
+    let tag_bytes = &encrypted.ciphertext[ciphertext_len - tag_len..];
+
+    // Encode tag as base64 STANDARD with padding: 16 bytes -> 24 chars
+    let tag = base64::engine::general_purpose::STANDARD.encode(tag_bytes);
+
+    // Encode ciphertext WITHOUT the tag (just the encrypted payload)
+    // The tag is sent separately for verification
+    let payload_len = ciphertext_len - tag_len;
+    let encrypted_data = base64::engine::general_purpose::STANDARD.encode(&encrypted.ciphertext[..payload_len]);
+
+    // Determine category: the one stored with the event, else from the rules,
+    // as one of the server's categories
+    let category = event.category.clone()
+        .or_else(|| categorize_app(rules, &event.app_name, event.process_path.as_deref()))
+        .filter(|_| policy.category)
+        .map(|category| server_category(&category).to_string());
+
+    Ok(SyncEvent {
+        id,
+        event_type: event.event_type.clone(),
+        timestamp,
+        duration: event.duration,
+        encrypted_data,
+        nonce,
+        tag,
+        aad_version: EVENT_AAD_VERSION,
+        app_name: Some(event.app_name.clone()).filter(|_| policy.app_name),
+        category,
+        remote_session: event.remote_session,
+        fullscreen: event.fullscreen,
+        domain: event.url_domain.clone().filter(|_| policy.domain),
+        session_id: event.session_id.clone(),
+    })
+}
+
+/// Decrypt an event another device uploaded. Current clients encrypt with
+/// that device's payload subkey, bound to the event; older ones used the
+/// sync key itself, at first without binding. The event's `aad_version`
+/// picks the scheme, so a bound payload grafted onto an event claiming
+/// version 0 doesn't open; only events stored before the server kept the
+/// version try every scheme.
+fn open_pulled_event(sync_key: &[u8; 32], event: &PulledEvent) -> std::result::Result<RemoteEvent, SyncError> {
+    let invalid = |e: String| SyncError::Encryption(format!("Invalid pulled event: {}", e));
+    let mut ciphertext = base64::engine::general_purpose::STANDARD.decode(&event.encrypted_data)
+        .map_err(|e| invalid(e.to_string()))?;
+    ciphertext.extend(base64::engine::general_purpose::STANDARD.decode(&event.tag).map_err(|e| invalid(e.to_string()))?);
+    let encrypted = EncryptedData { ciphertext, nonce: hex::decode(&event.nonce).map_err(|e| invalid(e.to_string()))? };
+    let aad = event_aad(&event.id, &event.device_id, event.timestamp);
+
+    let payload_key = derive_subkey(sync_key, KeyPurpose::SyncPayload, Some(&event.device_id));
+    let current = CryptoManager::new(&payload_key).map_err(|e| SyncError::Encryption(e.to_string()))?;
+    let legacy = CryptoManager::new(sync_key).map_err(|e| SyncError::Encryption(e.to_string()))?;
+    let bound = || current.decrypt_with_aad(&encrypted, &aad).or_else(|_| legacy.decrypt_with_aad(&encrypted, &aad));
+    let plaintext = match event.aad_version {
+        Some(0) => legacy.decrypt(&encrypted),
+        Some(EVENT_AAD_VERSION) => bound(),
+        Some(version) => return Err(invalid(format!("unknown aad_version {}", version))),
+        None => bound().or_else(|_| legacy.decrypt(&encrypted)),
+    }
+        .map_err(|e| SyncError::Encryption(e.to_string()))?;
+
+    Ok(RemoteEvent {
+        id: event.id.clone(),
+        device_id: event.device_id.clone(),
+        event_type: event.event_type.clone(),
+        timestamp: DateTime::from_timestamp_millis(event.timestamp).ok_or_else(|| invalid("timestamp".to_string()))?,
+        duration: event.duration,
+        app_name: event.app_name.clone(),
+        title: String::from_utf8(plaintext).ok().filter(|title| !title.is_empty()),
+        category: event.category.clone(),
+        domain: event.domain.clone(),
+        remote_session: event.remote_session,
+    })
+}
+
+fn gzip(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    use std::io::Write;
+    let mut encoder = flate2::write::GzEncoder::new(Vec::with_capacity(bytes.len() / 2), flate2::Compression::default());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+/// Categorize an event's app (by name or executable path) with the user-editable rules
+fn categorize_app(rules: &CategoryRules, app_name: &str, process_path: Option<&str>) -> Option<String> {
+    Some(rules.categorize_with_path(app_name, process_path))
+}
+
+/// Categories the server accepts; see `EncryptedEventSchema` in the API
+const SERVER_CATEGORIES: &[&str] = &["work", "communication", "entertainment", "learning", "utility", "other"];
+
+/// The server category a local one is uploaded as. Built-in local categories
+/// map to their nearest server category; user-defined ones become "other"
+fn server_category(category: &str) -> &'static str {
+    match category {
+        "development" | "productivity" => "work",
+        "gaming" => "entertainment",
+        _ => SERVER_CATEGORIES
+            .iter()
+            .find(|server| **server == category)
+            .copied()
+            .unwrap_or(UNCATEGORIZED),
     }
 }
 
@@ -589,13 +1592,45 @@ mod tests {
         assert_eq!(config.device_id, config2.device_id);
     }
 
+    #[tokio::test]
+    async fn test_server_config_is_sealed_at_rest() {
+        let (db, _temp) = create_test_db();
+        let db = Arc::new(db);
+        let config = ServerConfig {
+            server_url: "https://api.example.com".to_string(),
+            jwt_token: "secret_token".to_string(),
+            device_id: "device-1".to_string(),
+        };
+        // As an older version left it
+        db.set_setting(SERVER_CONFIG_SETTING, &serde_json::to_string(&config).unwrap()).unwrap();
+
+        let client = SyncClient::new(db.clone());
+        client.set_crypto_key(SecretKey::new([7u8; 32])).await.unwrap();
+        let stored = db.get_setting(SERVER_CONFIG_SETTING).unwrap().unwrap();
+        assert!(stored.starts_with(SEALED_CONFIG_PREFIX));
+        assert!(!stored.contains("secret_token"));
+        assert_eq!(client.get_config().await.unwrap().unwrap().jwt_token, "secret_token");
+
+        // A new key seals it again, so it survives unlocking with a passphrase
+        client.set_crypto_key(SecretKey::new([8u8; 32])).await.unwrap();
+        let restarted = SyncClient::new(db.clone());
+        restarted.set_crypto_key(SecretKey::new([8u8; 32])).await.unwrap();
+        assert_eq!(restarted.get_config().await.unwrap().unwrap().jwt_token, "secret_token");
+        assert!(open_config(Some(&[7u8; 32]), &db.get_setting(SERVER_CONFIG_SETTING).unwrap().unwrap()).is_err());
+    }
+
     #[test]
     fn test_sync_status_serialization() {
         let status = SyncStatus {
             is_syncing: true,
             last_sync_at: Some("2024-01-01T00:00:00Z".to_string()),
             pending_events: 100,
+            waiting_for_connectivity: false,
             last_error: Some("Network error".to_string()),
+            uploaded_fields: Vec::new(),
+            unconfirmed_deletions: 0,
+            batch_size: SYNC_BATCH_SIZE,
+            progress: Some(SyncProgress { synced_events: 200, total_events: 1000, batches: 2 }),
         };
 
         let json = serde_json::to_string(&status).unwrap();
@@ -609,6 +1644,7 @@ mod tests {
     fn test_sync_request_serialization() {
         let request = SyncRequest {
             device_id: Uuid::new_v4().to_string(),
+            client: ClientInfo::current(),
             events: vec![
                 SyncEvent {
                     id: Uuid::new_v4().to_string(),
@@ -618,15 +1654,25 @@ mod tests {
                     encrypted_data: "encrypted_base64_data".to_string(),
                     nonce: "00112233445566778899aa".to_string(), // 12 bytes hex
                     tag: "tag_base64".to_string(),
-                    app_name: "Chrome".to_string(),
+                    aad_version: 1,
+                    app_name: Some("Chrome".to_string()),
                     category: Some("work".to_string()),
+                    remote_session: false,
+                    fullscreen: false,
+                    domain: None,
+                    session_id: None,
                 }
             ],
+            deleted: Vec::new(),
+            sessions: Vec::new(),
         };
 
         let json = serde_json::to_string(&request).unwrap();
+        assert!(!json.contains("\"deleted\""));
+        assert!(!json.contains("session"));
         assert!(json.contains("app_usage"));
         assert!(json.contains("Chrome"));
+        assert!(json.contains("app_version"));
     }
 
     #[test]
@@ -639,19 +1685,92 @@ mod tests {
         assert!(response.conflicts.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_deleted_synced_events_ride_along_as_tombstones() {
+        // Confirms the tombstones it is sent, as POST /api/v1/sync/events does
+        let server_url = spawn_stub_server(|_, path, body| {
+            if path == "/health" {
+                return (204, String::new());
+            }
+            let mut json = String::new();
+            if body.starts_with(&[0x1f, 0x8b]) {
+                std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(body), &mut json).unwrap();
+            } else {
+                json = String::from_utf8(body.to_vec()).unwrap();
+            }
+            let request: serde_json::Value = serde_json::from_str(&json).unwrap();
+            let deleted: Vec<&str> = request["deleted"].as_array()
+                .map(|tombstones| tombstones.iter().filter_map(|t| t["id"].as_str()).collect())
+                .unwrap_or_default();
+            let response = serde_json::json!({
+                "synced_at": 1,
+                "processed_count": request["events"].as_array().unwrap().len(),
+                "conflicts": [],
+                "deleted_ids": deleted,
+            });
+            (200, response.to_string())
+        });
+
+        let (db, _temp) = create_test_db();
+        let db = Arc::new(db);
+        let window_info = crate::collector::window_tracker::WindowInfo {
+            process_name: "code.exe".to_string(),
+            window_title: "main.rs".to_string(),
+            timestamp: Utc::now(),
+            ..Default::default()
+        };
+        let synced = db.store_event_sync(&window_info).unwrap();
+        db.mark_as_synced(&[synced.clone()]).unwrap();
+        db.delete_event_sync(&synced).unwrap();
+        let unsynced = db.store_event_sync(&window_info).unwrap();
+
+        let (client, config) = stub_client(db.clone(), &server_url).await;
+        let tombstones = client.pending_tombstones().unwrap();
+        assert_eq!(tombstones.len(), 1);
+        assert_eq!(tombstones[0].id, synced);
+
+        let batch = vec![db.get_event(&unsynced).unwrap().unwrap()];
+        client.send_events(&config, &batch).await.unwrap();
+        assert!(client.pending_tombstones().unwrap().is_empty());
+        assert_eq!(db.count_unconfirmed_deletions().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_confirmed_key_check_skips_the_server() {
+        let (db, _temp) = create_test_db();
+        let db = Arc::new(db);
+        let client = SyncClient::new(db.clone());
+        client.set_crypto_key(SecretKey::new([7u8; 32])).await.unwrap();
+        // Nothing listens here, so any request fails with a network error
+        let config = ServerConfig {
+            server_url: "http://127.0.0.1:9".to_string(),
+            jwt_token: "test_token".to_string(),
+            device_id: "device-1".to_string(),
+        };
+
+        let key_check_key = derive_subkey(&[7u8; 32], KeyPurpose::KeyCheck, None);
+        let confirmed = CryptoManager::new(&key_check_key).unwrap().key_check_value().unwrap();
+        db.update_sync_state(KEY_CHECK_STATE_KEY, &confirmed).unwrap();
+        assert!(client.verify_key(&config).await.is_ok());
+
+        // A different key has to ask the server again
+        client.set_crypto_key(SecretKey::new([8u8; 32])).await.unwrap();
+        assert!(matches!(client.verify_key(&config).await, Err(SyncError::Network(_))));
+    }
+
     #[test]
     fn test_app_categorization() {
         let temp_file = NamedTempFile::new().unwrap();
         let db = Database::new(temp_file.path()).unwrap();
-        let client = SyncClient::new(std::sync::Arc::new(db));
-
-        assert_eq!(client.categorize_app("chrome.exe"), Some("work".to_string()));
-        assert_eq!(client.categorize_app("code.exe"), Some("development".to_string()));
-        assert_eq!(client.categorize_app("slack.exe"), Some("communication".to_string()));
-        assert_eq!(client.categorize_app("spotify.exe"), Some("entertainment".to_string()));
-        assert_eq!(client.categorize_app("word.exe"), Some("productivity".to_string()));
-        assert_eq!(client.categorize_app("steam.exe"), Some("gaming".to_string()));
-        assert_eq!(client.categorize_app("unknown.exe"), Some("other".to_string()));
+        let rules = db.get_category_rules().unwrap();
+
+        assert_eq!(categorize_app(&rules, "chrome.exe", None), Some("work".to_string()));
+        assert_eq!(categorize_app(&rules, "code.exe", None), Some("development".to_string()));
+        assert_eq!(categorize_app(&rules, "slack.exe", None), Some("communication".to_string()));
+        assert_eq!(categorize_app(&rules, "spotify.exe", None), Some("entertainment".to_string()));
+        assert_eq!(categorize_app(&rules, "word.exe", None), Some("productivity".to_string()));
+        assert_eq!(categorize_app(&rules, "steam.exe", None), Some("gaming".to_string()));
+        assert_eq!(categorize_app(&rules, "unknown.exe", None), Some("other".to_string()));
     }
 
     #[test]
@@ -665,4 +1784,430 @@ mod tests {
         let err = SyncError::Server("Internal error".to_string());
         assert_eq!(err.to_string(), "Server error: Internal error");
     }
+
+    fn backlog(count: usize) -> Vec<StoredEvent> {
+        let start = Utc::now() - chrono::Duration::days(1);
+        (0..count)
+            .map(|i| StoredEvent {
+                id: format!("event-{}", i),
+                event_type: "app_usage".to_string(),
+                timestamp: start + chrono::Duration::seconds(i as i64),
+                duration: 30,
+                app_name: "code.exe".to_string(),
+                window_title: Some(format!("file_{}.rs - lifespan - Visual Studio Code", i)),
+                utc_offset_minutes: Some(0),
+                remote_session: false,
+                url_domain: None,
+                fullscreen: false,
+                document: None,
+                project: None,
+                virtual_desktop: None,
+                process_path: None,
+                category: None,
+                session_id: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_encrypt_sync_events_keeps_order() {
+        let (db, _temp) = create_test_db();
+        let rules = db.get_category_rules().unwrap();
+        let crypto = CryptoManager::new(&[7u8; 32]).unwrap();
+        let events = backlog(500);
+
+        let sync_events = encrypt_sync_events(&crypto, &rules, &SyncFieldPolicy::default(), "device-1", &events, Utc::now().timestamp_millis()).unwrap();
+
+        assert_eq!(sync_events.len(), events.len());
+        for (event, sync_event) in events.iter().zip(&sync_events) {
+            assert_eq!(sync_event.id, event.id);
+            assert_eq!(sync_event.timestamp, event.timestamp.timestamp_millis());
+            assert_eq!(sync_event.category.as_deref(), Some("work"));
+        }
+    }
+
+    #[test]
+    fn test_build_sync_event_follows_field_policy() {
+        let (db, _temp) = create_test_db();
+        let rules = db.get_category_rules().unwrap();
+        let crypto = CryptoManager::new(&[7u8; 32]).unwrap();
+        let mut event = backlog(1).remove(0);
+        event.url_domain = Some("github.com".to_string());
+        let now_millis = Utc::now().timestamp_millis();
+
+        let full = build_sync_event(&crypto, &rules, &SyncFieldPolicy::default(), "device-1", &event, now_millis).unwrap();
+        assert_eq!(full.app_name.as_deref(), Some("code.exe"));
+        assert_eq!(full.domain.as_deref(), Some("github.com"));
+        // Uploaded as the server's nearest category
+        assert_eq!(full.category.as_deref(), Some("work"));
+
+        // A category stored with the event wins over the rules
+        event.category = Some("learning".to_string());
+        let stored = build_sync_event(&crypto, &rules, &SyncFieldPolicy::default(), "device-1", &event, now_millis).unwrap();
+        assert_eq!(stored.category.as_deref(), Some("learning"));
+
+        // The server rejects categories it doesn't know, so user-defined ones go as "other"
+        event.category = Some("writing".to_string());
+        let custom = build_sync_event(&crypto, &rules, &SyncFieldPolicy::default(), "device-1", &event, now_millis).unwrap();
+        assert_eq!(custom.category.as_deref(), Some("other"));
+
+        let policy = SyncFieldPolicy {
+            app_name: false,
+            window_title: false,
+            domain: false,
+            category: false,
+        };
+        let minimal = build_sync_event(&crypto, &rules, &policy, "device-1", &event, now_millis).unwrap();
+        assert!(minimal.app_name.is_none());
+        assert!(minimal.domain.is_none());
+        assert!(minimal.category.is_none());
+        // Only the GCM tag remains: nothing about the title is uploaded
+        assert!(minimal.encrypted_data.is_empty());
+        assert_eq!(minimal.tag.len(), 24);
+
+        let json = serde_json::to_string(&minimal).unwrap();
+        assert!(!json.contains("code.exe"));
+        assert!(!json.contains("github.com"));
+    }
+
+    #[test]
+    fn test_event_payload_is_bound_to_its_event() {
+        let (db, _temp) = create_test_db();
+        let rules = db.get_category_rules().unwrap();
+        let crypto = CryptoManager::new(&[7u8; 32]).unwrap();
+        let event = backlog(1).remove(0);
+        let now_millis = Utc::now().timestamp_millis();
+        let sync_event = build_sync_event(&crypto, &rules, &SyncFieldPolicy::default(), "device-1", &event, now_millis).unwrap();
+        assert_eq!(sync_event.aad_version, EVENT_AAD_VERSION);
+
+        // Reassemble what the server stores: ciphertext || tag, hex nonce
+        let mut ciphertext = base64::engine::general_purpose::STANDARD.decode(&sync_event.encrypted_data).unwrap();
+        ciphertext.extend(base64::engine::general_purpose::STANDARD.decode(&sync_event.tag).unwrap());
+        let encrypted = EncryptedData { ciphertext, nonce: hex::decode(&sync_event.nonce).unwrap() };
+
+        let aad = event_aad(&event.id, "device-1", sync_event.timestamp);
+        assert!(crypto.decrypt_with_aad(&encrypted, &aad).is_ok());
+        assert!(crypto.decrypt_with_aad(&encrypted, &event_aad("other-event", "device-1", sync_event.timestamp)).is_err());
+        assert!(crypto.decrypt_with_aad(&encrypted, &event_aad(&event.id, "device-2", sync_event.timestamp)).is_err());
+        assert!(crypto.decrypt_with_aad(&encrypted, &event_aad(&event.id, "device-1", sync_event.timestamp + 1)).is_err());
+    }
+
+    #[test]
+    fn test_pulled_events_decrypt_with_the_uploading_devices_key() {
+        let (db, _temp) = create_test_db();
+        let rules = db.get_category_rules().unwrap();
+        let sync_key = [7u8; 32];
+        let crypto = CryptoManager::new(&derive_subkey(&sync_key, KeyPurpose::SyncPayload, Some("device-2"))).unwrap();
+        let event = backlog(1).remove(0);
+        let now_millis = Utc::now().timestamp_millis();
+        let uploaded = build_sync_event(&crypto, &rules, &SyncFieldPolicy::default(), "device-2", &event, now_millis).unwrap();
+
+        let pulled = PulledEvent {
+            id: uploaded.id.clone(),
+            device_id: "device-2".to_string(),
+            event_type: uploaded.event_type.clone(),
+            timestamp: uploaded.timestamp,
+            duration: uploaded.duration,
+            encrypted_data: uploaded.encrypted_data.clone(),
+            nonce: uploaded.nonce.clone(),
+            tag: uploaded.tag.clone(),
+            app_name: uploaded.app_name.clone(),
+            category: uploaded.category.clone(),
+            domain: None,
+            remote_session: false,
+            aad_version: Some(uploaded.aad_version),
+        };
+        let opened = open_pulled_event(&sync_key, &pulled).unwrap();
+        assert_eq!(opened.title, event.window_title);
+        assert_eq!(opened.timestamp.timestamp_millis(), uploaded.timestamp);
+
+        // Stored before the server kept the version: every scheme is tried
+        let unversioned = PulledEvent { aad_version: None, ..pulled.clone() };
+        assert_eq!(open_pulled_event(&sync_key, &unversioned).unwrap().title, event.window_title);
+        assert!(open_pulled_event(&sync_key, &PulledEvent { aad_version: Some(2), ..pulled.clone() }).is_err());
+
+        // An unbound payload opens only as version 0, not grafted onto a bound event
+        let unbound = CryptoManager::new(&sync_key).unwrap().encrypt(b"old title").unwrap();
+        let (ciphertext, tag) = unbound.ciphertext.split_at(unbound.ciphertext.len() - 16);
+        let grafted = PulledEvent {
+            encrypted_data: base64::engine::general_purpose::STANDARD.encode(ciphertext),
+            tag: base64::engine::general_purpose::STANDARD.encode(tag),
+            nonce: hex::encode(&unbound.nonce),
+            ..pulled.clone()
+        };
+        assert!(open_pulled_event(&sync_key, &grafted).is_err());
+        let legacy = PulledEvent { aad_version: Some(0), ..grafted };
+        assert_eq!(open_pulled_event(&sync_key, &legacy).unwrap().title.as_deref(), Some("old title"));
+
+        // Another account's key, or the event claimed by another device, fails
+        assert!(open_pulled_event(&[8u8; 32], &pulled).is_err());
+        let moved = PulledEvent { device_id: "device-3".to_string(), ..pulled };
+        assert!(open_pulled_event(&sync_key, &moved).is_err());
+    }
+
+    /// A stand-in sync server on 127.0.0.1 that answers each request with
+    /// `respond(method, path and query, body)`; returns its base URL
+    fn spawn_stub_server<F>(respond: F) -> String
+    where
+        F: Fn(&str, &str, &[u8]) -> (u16, String) + Send + 'static,
+    {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { break };
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                if reader.read_line(&mut request_line).is_err() {
+                    continue;
+                }
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap_or(0);
+                        }
+                    }
+                }
+                let mut body = vec![0u8; content_length];
+                if reader.read_exact(&mut body).is_err() {
+                    continue;
+                }
+
+                let mut parts = request_line.split_whitespace();
+                let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+                let (status, response) = respond(method, path, &body);
+                let _ = write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {} Stub\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    response.len(),
+                    response
+                );
+            }
+        });
+        url
+    }
+
+    /// A client whose key check is already confirmed; its connectivity probe
+    /// asks the stub's `/health`
+    async fn stub_client(db: Arc<Database>, server_url: &str) -> (SyncClient, ServerConfig) {
+        let client = SyncClient::new(db.clone());
+        client.set_crypto_key(SecretKey::new([7u8; 32])).await.unwrap();
+        let key_check_key = derive_subkey(&[7u8; 32], KeyPurpose::KeyCheck, None);
+        let confirmed = CryptoManager::new(&key_check_key).unwrap().key_check_value().unwrap();
+        db.update_sync_state(KEY_CHECK_STATE_KEY, &confirmed).unwrap();
+
+        let config = ServerConfig {
+            server_url: server_url.to_string(),
+            jwt_token: "test_token".to_string(),
+            device_id: "device-1".to_string(),
+        };
+        (client, config)
+    }
+
+    #[tokio::test]
+    async fn test_wrapped_key_follows_the_account() {
+        // The server's copy of the account's wrapped key
+        let account: Arc<std::sync::Mutex<Option<String>>> = Arc::default();
+        let stored = account.clone();
+        let server_url = spawn_stub_server(move |method, path, body| {
+            if path == "/health" {
+                return (204, String::new());
+            }
+            assert_eq!(path, "/api/v1/sync/wrapped-key");
+            let mut stored = stored.lock().unwrap();
+            if method == "POST" {
+                let request: serde_json::Value = serde_json::from_slice(body).unwrap();
+                if request["replace"].as_bool().unwrap() || stored.is_none() {
+                    *stored = request["wrapped_key"].as_str().map(str::to_string);
+                }
+            }
+            (200, serde_json::json!({ "wrapped_key": *stored }).to_string())
+        });
+        let (db, _temp) = create_test_db();
+        let db = Arc::new(db);
+        let (client, config) = stub_client(db.clone(), &server_url).await;
+        let local = || db.get_sync_state(WRAPPED_KEY_STATE_KEY).unwrap();
+
+        // The first device's wrapped key becomes the account's
+        db.update_sync_state(WRAPPED_KEY_STATE_KEY, "first").unwrap();
+        client.share_wrapped_key(&config).await.unwrap();
+        assert_eq!(account.lock().unwrap().as_deref(), Some("first"));
+
+        // A passphrase changed on another device is taken over
+        *account.lock().unwrap() = Some("changed elsewhere".to_string());
+        client.share_wrapped_key(&config).await.unwrap();
+        assert_eq!(local().as_deref(), Some("changed elsewhere"));
+
+        // One changed here replaces the account's
+        db.update_sync_state(WRAPPED_KEY_STATE_KEY, "changed here").unwrap();
+        db.update_sync_state(WRAPPED_KEY_PENDING_STATE_KEY, "1").unwrap();
+        client.share_wrapped_key(&config).await.unwrap();
+        assert_eq!(account.lock().unwrap().as_deref(), Some("changed here"));
+        assert_eq!(db.get_sync_state(WRAPPED_KEY_PENDING_STATE_KEY).unwrap().as_deref(), Some(""));
+        assert_eq!(local().as_deref(), Some("changed here"));
+    }
+
+    #[tokio::test]
+    async fn test_pull_stops_when_the_cursor_does_not_advance() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let pulls = Arc::new(AtomicUsize::new(0));
+        let counted = pulls.clone();
+        // Always "more to come", always the same page and cursor
+        let server_url = spawn_stub_server(move |_, path, _| {
+            if path == "/health" {
+                return (204, String::new());
+            }
+            counted.fetch_add(1, Ordering::SeqCst);
+            let page = serde_json::json!({
+                "events": [{
+                    "id": "e1",
+                    "device_id": "device-2",
+                    "event_type": "app_usage",
+                    "timestamp": 0,
+                    "duration": 60,
+                    "encrypted_data": "AAAA",
+                    "nonce": "AAAA",
+                    "tag": "AAAA",
+                }],
+                "has_more": true,
+                "next_cursor": 7,
+            });
+            (200, page.to_string())
+        });
+
+        let (db, _temp) = create_test_db();
+        let db = Arc::new(db);
+        let (client, config) = stub_client(db.clone(), &server_url).await;
+
+        let result = client.pull_events(&config).await;
+        assert!(matches!(result, Err(SyncError::Server(_))), "{:?}", result);
+        assert_eq!(pulls.load(Ordering::SeqCst), 2);
+        assert_eq!(db.get_sync_state(PULL_CURSOR_STATE_KEY).unwrap().as_deref(), Some("7"));
+    }
+
+    #[tokio::test]
+    async fn test_sync_drains_the_backlog_in_batches() {
+        use crate::collector::window_tracker::WindowInfo;
+
+        // Events per upload, in order
+        let uploads = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = uploads.clone();
+        let server_url = spawn_stub_server(move |method, path, body| {
+            if path == "/health" {
+                return (204, String::new());
+            }
+            if method == "GET" {
+                return (200, r#"{"events":[],"has_more":false,"next_cursor":0}"#.to_string());
+            }
+            let mut json = String::new();
+            if body.starts_with(&[0x1f, 0x8b]) {
+                std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(body), &mut json).unwrap();
+            } else {
+                json = String::from_utf8(body.to_vec()).unwrap();
+            }
+            let request: serde_json::Value = serde_json::from_str(&json).unwrap();
+            let count = request["events"].as_array().unwrap().len();
+            recorded.lock().unwrap().push(count);
+            (200, format!(r#"{{"synced_at":0,"processed_count":{},"conflicts":[]}}"#, count))
+        });
+
+        let (db, _temp) = create_test_db();
+        let db = Arc::new(db);
+        for i in 0..5 {
+            let id = db.store_event_sync(&WindowInfo {
+                process_name: "code.exe".to_string(),
+                window_title: format!("file_{}.rs", i),
+                timestamp: Utc::now() - chrono::Duration::minutes(10 - i),
+                ..Default::default()
+            }).unwrap();
+            db.close_event_sync(&id, Utc::now()).unwrap();
+        }
+        let (client, config) = stub_client(db.clone(), &server_url).await;
+        client.set_config(config).await.unwrap();
+        client.set_batch_size(2).unwrap();
+
+        client.sync_events().await.unwrap();
+        assert_eq!(*uploads.lock().unwrap(), vec![2, 2, 1]);
+        assert_eq!(db.count_unsynced().unwrap(), 0);
+        assert!(db.get_unsynced_events_sync(10).unwrap().is_empty());
+        // Cleared as the sync returned, not some time after
+        assert_eq!(client.get_status().await.unwrap().progress, None);
+    }
+
+    #[tokio::test]
+    async fn test_upload_bodies_compress() {
+        let (db, _temp) = create_test_db();
+        let client = SyncClient::new(Arc::new(db));
+        client.set_crypto_key(SecretKey::new([7u8; 32])).await.unwrap();
+        let body = client.build_request_body(&backlog(SYNC_BATCH_SIZE)).await.unwrap();
+
+        let compressed = gzip(body.as_bytes()).unwrap();
+        assert!(compressed.len() < body.len() * 3 / 4, "{} -> {} bytes", body.len(), compressed.len());
+
+        let mut decompressed = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(compressed.as_slice()), &mut decompressed).unwrap();
+        assert_eq!(decompressed, body);
+    }
+
+    #[test]
+    fn test_batch_size_setting() {
+        let (db, _temp) = create_test_db();
+        let db = Arc::new(db);
+        let client = SyncClient::new(db.clone());
+        assert_eq!(client.batch_size().unwrap(), SYNC_BATCH_SIZE);
+
+        client.set_batch_size(250).unwrap();
+        assert_eq!(client.batch_size().unwrap(), 250);
+        assert!(client.set_batch_size(0).is_err());
+        assert!(client.set_batch_size(MAX_SYNC_BATCH_SIZE + 1).is_err());
+
+        // Values edited outside the app are clamped
+        db.set_setting(SYNC_BATCH_SIZE_SETTING, "100000").unwrap();
+        assert_eq!(client.batch_size().unwrap(), MAX_SYNC_BATCH_SIZE);
+    }
+
+    /// Throughput of serial vs. parallel encryption for a 10k-event backlog.
+    /// Run with `cargo test --release -- --ignored --nocapture bench_encrypt_sync_events`
+    #[test]
+    #[ignore]
+    fn bench_encrypt_sync_events() {
+        let (db, _temp) = create_test_db();
+        let rules = db.get_category_rules().unwrap();
+        let crypto = CryptoManager::new(&[7u8; 32]).unwrap();
+        let policy = SyncFieldPolicy::default();
+        let events = backlog(10_000);
+        let now_millis = Utc::now().timestamp_millis();
+
+        let started = std::time::Instant::now();
+        let serial: Vec<SyncEvent> = events
+            .iter()
+            .map(|event| build_sync_event(&crypto, &rules, &policy, "device-1", event, now_millis).unwrap())
+            .collect();
+        let serial_time = started.elapsed();
+
+        let started = std::time::Instant::now();
+        let parallel = encrypt_sync_events(&crypto, &rules, &policy, "device-1", &events, now_millis).unwrap();
+        let parallel_time = started.elapsed();
+
+        assert_eq!(serial.len(), parallel.len());
+        let per_second = |elapsed: Duration| events.len() as f64 / elapsed.as_secs_f64();
+        println!(
+            "10k events: serial {:?} ({:.0}/s), parallel {:?} ({:.0}/s) on {} threads, {:.1}x",
+            serial_time,
+            per_second(serial_time),
+            parallel_time,
+            per_second(parallel_time),
+            rayon::current_num_threads(),
+            serial_time.as_secs_f64() / parallel_time.as_secs_f64(),
+        );
+    }
 }
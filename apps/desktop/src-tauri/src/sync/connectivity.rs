@@ -0,0 +1,126 @@
+//! Captive portal detection.
+//!
+//! Before sending authenticated requests, the sync client fetches the sync
+//! server's unauthenticated `/health` endpoint. Hotel and guest networks
+//! intercept that request with a redirect or a login page, in which case
+//! syncing would hand the bearer token to the portal, so sync waits for real
+//! connectivity.
+
+use reqwest::redirect::Policy;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Path of the server's health check, probed unless a probe URL is configured
+pub const HEALTH_PATH: &str = "/health";
+
+/// Setting that overrides the probe URL (e.g. a URL that answers `204 No Content`)
+pub const PROBE_URL_SETTING: &str = "connectivity_probe_url";
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Connectivity {
+    /// Probe reached the server: the network is not intercepting traffic
+    Online,
+    /// Probe was redirected or answered with content: a portal is in the way
+    CaptivePortal,
+    /// Probe could not reach anything
+    Offline,
+}
+
+/// The probe URL for the server at `server_url`
+pub fn default_probe_url(server_url: &str) -> String {
+    format!("{}{}", server_url.trim_end_matches('/'), HEALTH_PATH)
+}
+
+/// Classify the probe's response (redirects are not followed). Only a
+/// redirect, `511 Network Authentication Required` or a 200 whose body is not
+/// the server's health report point to a portal; any other status came from
+/// the server itself, and the sync request reports it if it matters.
+pub fn classify_probe_response(status: u16, body: &str) -> Connectivity {
+    match status {
+        300..=399 | 511 => Connectivity::CaptivePortal,
+        200 if !body.trim().is_empty() && !is_health_report(body) => Connectivity::CaptivePortal,
+        _ => Connectivity::Online,
+    }
+}
+
+/// Whether `body` is the JSON the server's health check answers with
+fn is_health_report(body: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(body)
+        .map(|report| report.get("status").map_or(false, serde_json::Value::is_string))
+        .unwrap_or(false)
+}
+
+pub struct ConnectivityProbe {
+    client: Client,
+}
+
+impl ConnectivityProbe {
+    pub fn new() -> Self {
+        let client = Client::builder()
+            .redirect(Policy::none())
+            .timeout(PROBE_TIMEOUT)
+            .build()
+            .expect("Failed to create probe HTTP client");
+
+        Self { client }
+    }
+
+    /// Probe `url`; never sends credentials
+    pub async fn check(&self, url: &str) -> Connectivity {
+        match self.client.get(url).send().await {
+            Ok(response) => {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                let connectivity = classify_probe_response(status.as_u16(), &body);
+                if connectivity == Connectivity::CaptivePortal {
+                    warn!("Connectivity probe returned HTTP {}, captive portal suspected", status);
+                }
+                connectivity
+            }
+            Err(e) => {
+                debug!("Connectivity probe failed: {}", e);
+                Connectivity::Offline
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_probe_response() {
+        let health = r#"{"status":"ok","version":"1.0.0","timestamp":"2024-01-01T00:00:00.000Z"}"#;
+        assert_eq!(classify_probe_response(200, health), Connectivity::Online);
+        assert_eq!(classify_probe_response(204, ""), Connectivity::Online);
+        assert_eq!(classify_probe_response(200, ""), Connectivity::Online);
+        assert_eq!(classify_probe_response(403, "Forbidden"), Connectivity::Online);
+        assert_eq!(classify_probe_response(503, health), Connectivity::Online);
+        assert_eq!(classify_probe_response(200, "<html>Sign in to Wi-Fi</html>"), Connectivity::CaptivePortal);
+        assert_eq!(classify_probe_response(302, ""), Connectivity::CaptivePortal);
+        assert_eq!(classify_probe_response(307, ""), Connectivity::CaptivePortal);
+        assert_eq!(classify_probe_response(511, ""), Connectivity::CaptivePortal);
+    }
+
+    #[test]
+    fn test_default_probe_url() {
+        assert_eq!(default_probe_url("https://sync.example.com/"), "https://sync.example.com/health");
+    }
+
+    #[test]
+    fn test_connectivity_serialization() {
+        assert_eq!(serde_json::to_string(&Connectivity::CaptivePortal).unwrap(), "\"captive_portal\"");
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_probe_is_offline() {
+        let probe = ConnectivityProbe::new();
+        assert_eq!(probe.check("http://127.0.0.1:1/health").await, Connectivity::Offline);
+    }
+}
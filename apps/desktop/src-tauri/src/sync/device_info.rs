@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+
+/// Longest os_version the server accepts
+const MAX_OS_VERSION_LEN: usize = 50;
+
+/// Client build and platform metadata sent with every sync request, so the
+/// server can attribute data to client versions and filter buggy builds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientInfo {
+    pub app_version: String,
+    pub os: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os_version: Option<String>,
+    pub arch: String,
+    /// Name of the machine, so multi-device users can tell their devices apart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+}
+
+impl ClientInfo {
+    /// Collect metadata for the running client
+    pub fn current() -> Self {
+        Self {
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            os_version: os_version(),
+            arch: std::env::consts::ARCH.to_string(),
+            hostname: hostname(),
+        }
+    }
+}
+
+#[cfg(windows)]
+fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok().filter(|name| !name.is_empty())
+}
+
+#[cfg(target_os = "linux")]
+fn hostname() -> Option<String> {
+    let name = std::fs::read_to_string("/proc/sys/kernel/hostname").ok()?;
+    Some(name.trim().to_string()).filter(|name| !name.is_empty())
+}
+
+#[cfg(target_os = "macos")]
+fn hostname() -> Option<String> {
+    let output = std::process::Command::new("scutil")
+        .args(["--get", "ComputerName"])
+        .output()
+        .ok()?;
+    let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+fn hostname() -> Option<String> {
+    None
+}
+
+#[cfg(windows)]
+fn os_version() -> Option<String> {
+    use windows::core::w;
+    use windows::Win32::System::Registry::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_SZ};
+
+    let mut buffer = [0u16; 64];
+    let mut size = (buffer.len() * std::mem::size_of::<u16>()) as u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            w!("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion"),
+            w!("CurrentBuildNumber"),
+            RRF_RT_REG_SZ,
+            None,
+            Some(buffer.as_mut_ptr() as *mut std::ffi::c_void),
+            Some(&mut size),
+        )
+    };
+    if status.is_err() {
+        return None;
+    }
+
+    // Size includes the trailing NUL
+    let len = (size as usize / std::mem::size_of::<u16>()).saturating_sub(1);
+    Some(format!("build {}", String::from_utf16_lossy(&buffer[..len])))
+}
+
+#[cfg(target_os = "macos")]
+fn os_version() -> Option<String> {
+    let output = std::process::Command::new("sw_vers")
+        .arg("-productVersion")
+        .output()
+        .ok()?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!version.is_empty()).then_some(version)
+}
+
+#[cfg(target_os = "linux")]
+fn os_version() -> Option<String> {
+    let os_release = std::fs::read_to_string("/etc/os-release").ok()?;
+    parse_os_release(&os_release)
+}
+
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+fn os_version() -> Option<String> {
+    None
+}
+
+/// Extract PRETTY_NAME from an os-release file, cut to what the server accepts
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_os_release(contents: &str) -> Option<String> {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("PRETTY_NAME="))
+        .map(|value| value.trim_matches('"').chars().take(MAX_OS_VERSION_LEN).collect::<String>())
+        .map(|value| value.trim_end().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current_client_info() {
+        let info = ClientInfo::current();
+        assert_eq!(info.app_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.os, std::env::consts::OS);
+        assert_eq!(info.arch, std::env::consts::ARCH);
+    }
+
+    #[test]
+    fn test_parse_os_release() {
+        let contents = "NAME=\"Ubuntu\"\nVERSION_ID=\"22.04\"\nPRETTY_NAME=\"Ubuntu 22.04.3 LTS\"\n";
+        assert_eq!(parse_os_release(contents), Some("Ubuntu 22.04.3 LTS".to_string()));
+        assert_eq!(parse_os_release("NAME=Arch\n"), None);
+
+        let long = format!("PRETTY_NAME=\"{}\"\n", "Distro ".repeat(20));
+        let parsed = parse_os_release(&long).unwrap();
+        assert!(parsed.chars().count() <= MAX_OS_VERSION_LEN);
+        assert!(parsed.starts_with("Distro Distro"));
+    }
+
+    #[test]
+    fn test_client_info_serialization_skips_missing_os_version() {
+        let info = ClientInfo {
+            app_version: "0.1.0".to_string(),
+            os: "linux".to_string(),
+            os_version: None,
+            arch: "x86_64".to_string(),
+            hostname: None,
+        };
+
+        let json = serde_json::to_string(&info).unwrap();
+        assert!(!json.contains("os_version"));
+        assert!(!json.contains("hostname"));
+    }
+}
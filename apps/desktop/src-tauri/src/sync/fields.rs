@@ -0,0 +1,161 @@
+//! Per-field sync opt-outs.
+//!
+//! The policy decides which event fields leave the device. `build_sync_events`
+//! follows it when building the upload, and `SyncStatus.uploaded_fields` is
+//! derived from the same policy so the UI shows what is actually sent.
+
+use crate::collector::browser::BROWSER_DOMAIN_SETTING;
+use crate::database::Database;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Setting holding the JSON-encoded SyncFieldPolicy
+pub const SYNC_FIELDS_SETTING: &str = "sync_field_policy";
+
+/// Which optional fields are included in uploads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncFieldPolicy {
+    /// Process name, sent as plaintext so the server can aggregate by app
+    pub app_name: bool,
+    /// Window title, sent end-to-end encrypted
+    pub window_title: bool,
+    /// Browser tab domain, sent as plaintext (only captured when opted in)
+    pub domain: bool,
+    /// Category from the local rules, sent as plaintext
+    pub category: bool,
+}
+
+impl Default for SyncFieldPolicy {
+    fn default() -> Self {
+        Self {
+            app_name: true,
+            window_title: true,
+            domain: true,
+            category: true,
+        }
+    }
+}
+
+impl SyncFieldPolicy {
+    pub fn load(db: &Database) -> Result<Self> {
+        Ok(db
+            .get_setting(SYNC_FIELDS_SETTING)?
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default())
+    }
+
+    pub fn save(&self, db: &Database) -> Result<()> {
+        db.set_setting(SYNC_FIELDS_SETTING, &serde_json::to_string(self)?)
+    }
+}
+
+/// How a field appears in uploads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldUpload {
+    Plaintext,
+    Encrypted,
+    /// Not uploaded (opted out, or never collected)
+    Off,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadedField {
+    pub field: String,
+    pub upload: FieldUpload,
+}
+
+/// Describe every event field and whether it is uploaded under `policy`
+pub fn uploaded_fields(policy: &SyncFieldPolicy, domain_capture: bool) -> Vec<UploadedField> {
+    let plain = |on: bool| if on { FieldUpload::Plaintext } else { FieldUpload::Off };
+    let fields = [
+        ("app_name", plain(policy.app_name)),
+        (
+            "window_title",
+            if policy.window_title { FieldUpload::Encrypted } else { FieldUpload::Off },
+        ),
+        ("domain", plain(policy.domain && domain_capture)),
+        ("category", plain(policy.category)),
+        // Timing and the remote session / fullscreen flags are needed for the server timeline
+        ("timing", FieldUpload::Plaintext),
+        ("remote_session", FieldUpload::Plaintext),
+        ("fullscreen", FieldUpload::Plaintext),
+        // Only the domain is ever captured, never the full URL
+        ("url", FieldUpload::Off),
+        // Editor documents are for local reports only
+        ("document", FieldUpload::Off),
+        // Project labels are for local reports only
+        ("project", FieldUpload::Off),
+        // Virtual desktops are for local reports only
+        ("virtual_desktop", FieldUpload::Off),
+        // Executable paths can include the user name
+        ("process_path", FieldUpload::Off),
+        // Metadata on user-logged custom events stays on the device
+        ("metadata", FieldUpload::Off),
+        // Input intensity is not collected
+        ("intensity", FieldUpload::Off),
+    ];
+
+    fields
+        .into_iter()
+        .map(|(field, upload)| UploadedField { field: field.to_string(), upload })
+        .collect()
+}
+
+/// Uploaded fields for the policy currently stored in the database
+pub fn current_uploaded_fields(db: &Database) -> Result<Vec<UploadedField>> {
+    let policy = SyncFieldPolicy::load(db)?;
+    let domain_capture = db
+        .get_setting(BROWSER_DOMAIN_SETTING)?
+        .is_some_and(|value| value == "true");
+    Ok(uploaded_fields(&policy, domain_capture))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    fn create_test_db() -> (Database, NamedTempFile) {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path()).unwrap();
+        (db, temp_file)
+    }
+
+    fn upload_of(fields: &[UploadedField], name: &str) -> FieldUpload {
+        fields.iter().find(|f| f.field == name).unwrap().upload
+    }
+
+    #[test]
+    fn test_default_policy_matches_previous_uploads() {
+        let (db, _temp) = create_test_db();
+        let fields = current_uploaded_fields(&db).unwrap();
+
+        assert_eq!(upload_of(&fields, "app_name"), FieldUpload::Plaintext);
+        assert_eq!(upload_of(&fields, "window_title"), FieldUpload::Encrypted);
+        // Domain capture is opt-in, so nothing is sent until it is enabled
+        assert_eq!(upload_of(&fields, "domain"), FieldUpload::Off);
+        assert_eq!(upload_of(&fields, "url"), FieldUpload::Off);
+        assert_eq!(upload_of(&fields, "intensity"), FieldUpload::Off);
+    }
+
+    #[test]
+    fn test_opt_outs_are_reflected() {
+        let (db, _temp) = create_test_db();
+        db.set_setting(BROWSER_DOMAIN_SETTING, "true").unwrap();
+        assert_eq!(upload_of(&current_uploaded_fields(&db).unwrap(), "domain"), FieldUpload::Plaintext);
+
+        SyncFieldPolicy {
+            window_title: false,
+            domain: false,
+            ..SyncFieldPolicy::default()
+        }
+        .save(&db)
+        .unwrap();
+
+        let fields = current_uploaded_fields(&db).unwrap();
+        assert_eq!(upload_of(&fields, "app_name"), FieldUpload::Plaintext);
+        assert_eq!(upload_of(&fields, "window_title"), FieldUpload::Off);
+        assert_eq!(upload_of(&fields, "domain"), FieldUpload::Off);
+    }
+}
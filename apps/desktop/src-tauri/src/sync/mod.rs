@@ -1,3 +1,9 @@
 pub mod client;
+pub mod connectivity;
+pub mod device_info;
+pub mod fields;
+pub mod overlap;
 
-pub use client::{SyncClient, SyncStatus, ServerConfig};
+pub use client::{SyncClient, SyncProgress, SyncStatus, ServerConfig};
+pub use device_info::ClientInfo;
+pub use fields::{SyncFieldPolicy, UploadedField};
@@ -0,0 +1,251 @@
+//! Cross-device overlap resolution.
+//!
+//! The same minutes can be recorded on two devices: remoting from a laptop
+//! into a desktop records mstsc.exe (tagged `remote_session`) on the laptop
+//! and the apps actually used on the desktop. Given usage events from several
+//! devices, `resolve_overlaps` decides how much of each event counts so the
+//! combined timeline never double-counts. The cross-device timeline and
+//! reports run it over local events and the ones pulled from the server
+//! (see `reports::timeline`).
+
+use crate::database::Database;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Setting holding the JSON-encoded OverlapPolicy
+pub const OVERLAP_POLICY_SETTING: &str = "overlap_policy";
+
+/// Who gets time that several devices recorded at once
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlapPolicy {
+    /// Devices where the user sat at the console win over remote viewers;
+    /// time is split only between equals (two consoles, or two viewers)
+    #[default]
+    PreferConsole,
+    /// Every overlapping device gets an equal share
+    Split,
+}
+
+impl OverlapPolicy {
+    pub fn load(db: &Database) -> Result<Self> {
+        Ok(db
+            .get_setting(OVERLAP_POLICY_SETTING)?
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default())
+    }
+
+    pub fn save(&self, db: &Database) -> Result<()> {
+        db.set_setting(OVERLAP_POLICY_SETTING, &serde_json::to_string(self)?)
+    }
+}
+
+/// A usage event reduced to what overlap resolution needs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedEvent {
+    pub event_id: String,
+    pub device_id: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    /// Recorded in a remote desktop / VM viewer
+    pub remote_session: bool,
+}
+
+/// Time credited to one event after resolution
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Attribution {
+    pub event_id: String,
+    pub attributed_ms: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OverlapResolution {
+    /// One entry per input event, in input order
+    pub attributions: Vec<Attribution>,
+    /// Time during which more than one device was recording
+    pub overlap_ms: i64,
+}
+
+/// Credit each event with its share of time under `policy`. Overlaps between
+/// events of the same device are left alone; only cross-device time is shared.
+pub fn resolve_overlaps(events: &[TimedEvent], policy: OverlapPolicy) -> OverlapResolution {
+    let mut attributed = vec![0i64; events.len()];
+    let mut overlap_ms = 0;
+
+    let bounds: BTreeSet<i64> = events
+        .iter()
+        .filter(|event| event.end_ms > event.start_ms)
+        .flat_map(|event| [event.start_ms, event.end_ms])
+        .collect();
+    let bounds: Vec<i64> = bounds.into_iter().collect();
+
+    let mut by_start: Vec<usize> = (0..events.len())
+        .filter(|&i| events[i].end_ms > events[i].start_ms)
+        .collect();
+    by_start.sort_by_key(|&i| events[i].start_ms);
+    let mut next = 0;
+    let mut active: Vec<usize> = Vec::new();
+
+    for segment in bounds.windows(2) {
+        let (from, to) = (segment[0], segment[1]);
+        active.retain(|&i| events[i].end_ms > from);
+        while next < by_start.len() && events[by_start[next]].start_ms <= from {
+            active.push(by_start[next]);
+            next += 1;
+        }
+        if active.is_empty() {
+            continue;
+        }
+
+        let length = to - from;
+        let mut devices: Vec<&str> = active.iter().map(|&i| events[i].device_id.as_str()).collect();
+        devices.sort_unstable();
+        devices.dedup();
+        if devices.len() > 1 {
+            overlap_ms += length;
+        }
+
+        let winners: Vec<&str> = match policy {
+            OverlapPolicy::PreferConsole => {
+                let consoles: Vec<&str> = devices
+                    .iter()
+                    .copied()
+                    .filter(|device| active.iter().any(|&i| events[i].device_id == *device && !events[i].remote_session))
+                    .collect();
+                if consoles.is_empty() { devices } else { consoles }
+            }
+            OverlapPolicy::Split => devices,
+        };
+
+        // Equal shares per winning device; the first gets any leftover milliseconds
+        let share = length / winners.len() as i64;
+        let leftover = length % winners.len() as i64;
+        for (rank, device) in winners.iter().enumerate() {
+            let credit = share + if rank == 0 { leftover } else { 0 };
+            for &i in active.iter().filter(|&&i| events[i].device_id == *device) {
+                attributed[i] += credit;
+            }
+        }
+    }
+
+    OverlapResolution {
+        attributions: events
+            .iter()
+            .zip(attributed)
+            .map(|(event, attributed_ms)| Attribution { event_id: event.event_id.clone(), attributed_ms })
+            .collect(),
+        overlap_ms,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    const MINUTE: i64 = 60_000;
+
+    fn event(id: &str, device: &str, start_min: i64, end_min: i64, remote_session: bool) -> TimedEvent {
+        TimedEvent {
+            event_id: id.to_string(),
+            device_id: device.to_string(),
+            start_ms: start_min * MINUTE,
+            end_ms: end_min * MINUTE,
+            remote_session,
+        }
+    }
+
+    fn minutes(resolution: &OverlapResolution, id: &str) -> f64 {
+        let attribution = resolution.attributions.iter().find(|a| a.event_id == id).unwrap();
+        attribution.attributed_ms as f64 / MINUTE as f64
+    }
+
+    #[test]
+    fn test_disjoint_events_keep_their_time() {
+        let events = [event("a", "laptop", 0, 30, false), event("b", "desktop", 30, 50, false)];
+        let resolution = resolve_overlaps(&events, OverlapPolicy::PreferConsole);
+
+        assert_eq!(minutes(&resolution, "a"), 30.0);
+        assert_eq!(minutes(&resolution, "b"), 20.0);
+        assert_eq!(resolution.overlap_ms, 0);
+    }
+
+    #[test]
+    fn test_rdp_session_prefers_console() {
+        // Laptop shows mstsc for an hour; the desktop records the apps used inside it
+        let events = [
+            event("viewer", "laptop", 0, 60, true),
+            event("code", "desktop", 15, 30, false),
+            event("browser", "desktop", 30, 45, false),
+        ];
+        let resolution = resolve_overlaps(&events, OverlapPolicy::PreferConsole);
+
+        assert_eq!(minutes(&resolution, "viewer"), 30.0);
+        assert_eq!(minutes(&resolution, "code"), 15.0);
+        assert_eq!(minutes(&resolution, "browser"), 15.0);
+        assert_eq!(resolution.overlap_ms, 30 * MINUTE);
+    }
+
+    #[test]
+    fn test_rdp_session_split_attribution() {
+        let events = [event("viewer", "laptop", 0, 60, true), event("code", "desktop", 15, 45, false)];
+        let resolution = resolve_overlaps(&events, OverlapPolicy::Split);
+
+        assert_eq!(minutes(&resolution, "viewer"), 45.0);
+        assert_eq!(minutes(&resolution, "code"), 15.0);
+    }
+
+    #[test]
+    fn test_equal_devices_share_under_either_policy() {
+        let consoles = [event("a", "laptop", 0, 20, false), event("b", "desktop", 10, 30, false)];
+        let viewers = [event("a", "laptop", 0, 20, true), event("b", "desktop", 10, 30, true)];
+
+        for events in [consoles, viewers] {
+            let resolution = resolve_overlaps(&events, OverlapPolicy::PreferConsole);
+            assert_eq!(minutes(&resolution, "a"), 15.0);
+            assert_eq!(minutes(&resolution, "b"), 15.0);
+            assert_eq!(resolution.overlap_ms, 10 * MINUTE);
+        }
+    }
+
+    #[test]
+    fn test_same_device_overlap_is_not_deduplicated() {
+        let events = [event("a", "desktop", 0, 20, false), event("b", "desktop", 10, 30, false)];
+        let resolution = resolve_overlaps(&events, OverlapPolicy::Split);
+
+        assert_eq!(minutes(&resolution, "a"), 20.0);
+        assert_eq!(minutes(&resolution, "b"), 20.0);
+        assert_eq!(resolution.overlap_ms, 0);
+    }
+
+    #[test]
+    fn test_total_matches_covered_time() {
+        // Three devices with staggered overlaps; nothing is counted twice or lost
+        let events = [
+            event("a", "laptop", 0, 40, false),
+            event("b", "desktop", 10, 50, true),
+            event("c", "tablet", 20, 30, false),
+            TimedEvent {
+                end_ms: 50 * MINUTE + 1,
+                ..event("d", "phone", 50, 50, false)
+            },
+        ];
+
+        for policy in [OverlapPolicy::PreferConsole, OverlapPolicy::Split] {
+            let resolution = resolve_overlaps(&events, policy);
+            let total: i64 = resolution.attributions.iter().map(|a| a.attributed_ms).sum();
+            assert_eq!(total, 50 * MINUTE + 1);
+        }
+    }
+
+    #[test]
+    fn test_policy_round_trip() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path()).unwrap();
+
+        assert_eq!(OverlapPolicy::load(&db).unwrap(), OverlapPolicy::PreferConsole);
+        OverlapPolicy::Split.save(&db).unwrap();
+        assert_eq!(OverlapPolicy::load(&db).unwrap(), OverlapPolicy::Split);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::database::Database;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+const THEME_OVERRIDE_KEY: &str = "report_theme_override";
+
+/// Light or dark appearance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeMode {
+  Light,
+  Dark,
+}
+
+/// Appearance reported by the operating system
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SystemTheme {
+  pub mode: ThemeMode,
+  /// Accent color as "#rrggbb", if the OS exposes one
+  pub accent_color: Option<String>,
+}
+
+/// Theme applied to generated HTML/PDF reports
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportTheme {
+  pub mode: ThemeMode,
+  pub accent_color: String,
+  pub background_color: String,
+  pub text_color: String,
+}
+
+impl ReportTheme {
+  pub fn from_system(system: &SystemTheme) -> Self {
+    let (background_color, text_color) = match system.mode {
+      ThemeMode::Light => ("#ffffff", "#1f2937"),
+      ThemeMode::Dark => ("#111827", "#f9fafb"),
+    };
+
+    Self {
+      mode: system.mode,
+      accent_color: system.accent_color.clone().unwrap_or_else(|| "#3b82f6".to_string()),
+      background_color: background_color.to_string(),
+      text_color: text_color.to_string(),
+    }
+  }
+
+  /// Reject colors that aren't "#rgb" or "#rrggbb"; they end up verbatim in report CSS
+  pub fn validate(&self) -> Result<()> {
+    for (field, color) in [
+      ("accent_color", &self.accent_color),
+      ("background_color", &self.background_color),
+      ("text_color", &self.text_color),
+    ] {
+      if !is_hex_color(color) {
+        bail!("{} must be a #rgb or #rrggbb color, got {:?}", field, color);
+      }
+    }
+    Ok(())
+  }
+
+  /// Render the theme as CSS custom properties for report templates
+  pub fn to_css_variables(&self) -> String {
+    format!(
+      ":root {{ --report-accent: {}; --report-bg: {}; --report-text: {}; color-scheme: {}; }}",
+      self.accent_color,
+      self.background_color,
+      self.text_color,
+      match self.mode {
+        ThemeMode::Light => "light",
+        ThemeMode::Dark => "dark",
+      }
+    )
+  }
+}
+
+fn is_hex_color(value: &str) -> bool {
+  match value.strip_prefix('#') {
+    Some(hex) => (hex.len() == 3 || hex.len() == 6) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+    None => false,
+  }
+}
+
+/// Resolves the report theme from OS appearance and user overrides
+pub struct ThemeService {
+  db: Arc<Database>,
+}
+
+impl ThemeService {
+  pub fn new(db: Arc<Database>) -> Self {
+    Self { db }
+  }
+
+  /// Theme reports should use: the user override if set, otherwise the OS theme
+  pub fn report_theme(&self) -> Result<ReportTheme> {
+    if let Some(theme) = self.get_override()? {
+      return Ok(theme);
+    }
+    Ok(ReportTheme::from_system(&get_system_theme()))
+  }
+
+  pub fn get_override(&self) -> Result<Option<ReportTheme>> {
+    Ok(self
+      .db
+      .get_setting(THEME_OVERRIDE_KEY)?
+      .filter(|json| !json.is_empty())
+      .and_then(|json| serde_json::from_str::<ReportTheme>(&json).ok())
+      .filter(|theme| theme.validate().is_ok()))
+  }
+
+  /// Persist a theme override; `None` reverts to following the OS
+  pub fn set_override(&self, theme: Option<&ReportTheme>) -> Result<()> {
+    let value = match theme {
+      Some(theme) => {
+        theme.validate()?;
+        serde_json::to_string(theme)?
+      }
+      None => String::new(),
+    };
+    self.db.set_setting(THEME_OVERRIDE_KEY, &value)
+  }
+}
+
+/// Read the current OS appearance
+#[cfg(windows)]
+pub fn get_system_theme() -> SystemTheme {
+  use windows::core::w;
+  use windows::Win32::Foundation::BOOL;
+  use windows::Win32::Graphics::Dwm::DwmGetColorizationColor;
+  use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
+
+  unsafe {
+    // AppsUseLightTheme = 0 means dark mode
+    let mut light: u32 = 1;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let status = RegGetValueW(
+      HKEY_CURRENT_USER,
+      w!("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize"),
+      w!("AppsUseLightTheme"),
+      RRF_RT_REG_DWORD,
+      None,
+      Some(&mut light as *mut u32 as *mut std::ffi::c_void),
+      Some(&mut size),
+    );
+    let mode = if status.is_ok() && light == 0 { ThemeMode::Dark } else { ThemeMode::Light };
+
+    // Colorization color is 0xAARRGGBB
+    let mut color: u32 = 0;
+    let mut opaque = BOOL::default();
+    let accent_color = DwmGetColorizationColor(&mut color, &mut opaque)
+      .ok()
+      .map(|_| format!("#{:06x}", color & 0x00ff_ffff));
+
+    SystemTheme { mode, accent_color }
+  }
+}
+
+#[cfg(not(windows))]
+pub fn get_system_theme() -> SystemTheme {
+  // No OS theme query on other platforms yet
+  SystemTheme {
+    mode: ThemeMode::Light,
+    accent_color: None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tempfile::NamedTempFile;
+
+  fn create_test_service() -> (ThemeService, NamedTempFile) {
+    let temp_file = NamedTempFile::new().unwrap();
+    let db = Database::new(temp_file.path()).unwrap();
+    (ThemeService::new(Arc::new(db)), temp_file)
+  }
+
+  #[test]
+  fn test_report_theme_from_system_dark() {
+    let theme = ReportTheme::from_system(&SystemTheme {
+      mode: ThemeMode::Dark,
+      accent_color: Some("#ff0000".to_string()),
+    });
+
+    assert_eq!(theme.mode, ThemeMode::Dark);
+    assert_eq!(theme.accent_color, "#ff0000");
+    assert_eq!(theme.background_color, "#111827");
+  }
+
+  #[test]
+  fn test_report_theme_default_accent() {
+    let theme = ReportTheme::from_system(&SystemTheme {
+      mode: ThemeMode::Light,
+      accent_color: None,
+    });
+
+    assert_eq!(theme.accent_color, "#3b82f6");
+  }
+
+  #[test]
+  fn test_css_variables() {
+    let theme = ReportTheme::from_system(&SystemTheme {
+      mode: ThemeMode::Dark,
+      accent_color: None,
+    });
+
+    let css = theme.to_css_variables();
+    assert!(css.contains("--report-accent: #3b82f6"));
+    assert!(css.contains("color-scheme: dark"));
+  }
+
+  #[test]
+  fn test_override_roundtrip() {
+    let (service, _temp) = create_test_service();
+    assert!(service.get_override().unwrap().is_none());
+
+    let theme = ReportTheme {
+      mode: ThemeMode::Dark,
+      accent_color: "#00ff00".to_string(),
+      background_color: "#000000".to_string(),
+      text_color: "#ffffff".to_string(),
+    };
+    service.set_override(Some(&theme)).unwrap();
+
+    assert_eq!(service.get_override().unwrap(), Some(theme.clone()));
+    assert_eq!(service.report_theme().unwrap(), theme);
+  }
+
+  #[test]
+  fn test_override_rejects_non_hex_colors() {
+    let (service, _temp) = create_test_service();
+    let valid = ReportTheme {
+      mode: ThemeMode::Light,
+      accent_color: "#0af".to_string(),
+      background_color: "#ffffff".to_string(),
+      text_color: "#1F2937".to_string(),
+    };
+    service.set_override(Some(&valid)).unwrap();
+
+    for color in ["red;} body{display:none", "red", "#12345", "#ggg", "#fff;", ""] {
+      let theme = ReportTheme {
+        accent_color: color.to_string(),
+        ..valid.clone()
+      };
+      assert!(service.set_override(Some(&theme)).is_err(), "accepted {:?}", color);
+    }
+    assert_eq!(service.get_override().unwrap(), Some(valid.clone()));
+
+    // An injected value that reached the settings some other way is ignored
+    let tampered = ReportTheme {
+      text_color: "#000} body{display:none".to_string(),
+      ..valid
+    };
+    service.db.set_setting(THEME_OVERRIDE_KEY, &serde_json::to_string(&tampered).unwrap()).unwrap();
+    assert!(service.get_override().unwrap().is_none());
+  }
+
+  #[test]
+  fn test_clear_override() {
+    let (service, _temp) = create_test_service();
+
+    let theme = ReportTheme::from_system(&SystemTheme {
+      mode: ThemeMode::Dark,
+      accent_color: None,
+    });
+    service.set_override(Some(&theme)).unwrap();
+    service.set_override(None).unwrap();
+
+    assert!(service.get_override().unwrap().is_none());
+  }
+}
@@ -13,6 +13,7 @@ import request from 'supertest';
 import { v4 as uuidv4 } from 'uuid';
 import app from '../../index.js';
 import { generateAccessToken } from '../../middleware/auth.js';
+import { EncryptedEventSchema } from '../../validators/sync.schema.js';
 import { createTestUser, createTestDevice } from '../setup.js';
 
 describe('Sync API', () => {
@@ -186,8 +187,8 @@ describe('Sync API', () => {
       expect(response.status).toBe(400);
     });
 
-    it('should reject upload with more than 100 events', async () => {
-      const events = Array.from({ length: 101 }, () => ({
+    it('should reject upload with more than 500 events', async () => {
+      const events = Array.from({ length: 501 }, () => ({
         id: uuidv4(),
         event_type: 'app_usage' as const,
         timestamp: Date.now(),
@@ -269,7 +270,8 @@ describe('Sync API', () => {
     });
 
     it('should accept all valid event types', async () => {
-      const eventTypes = ['app_usage', 'web_activity', 'file_activity', 'communication'] as const;
+      // Every type the validator lets through must also pass the events table's CHECK
+      const eventTypes = EncryptedEventSchema.shape.event_type.options;
 
       for (const eventType of eventTypes) {
         const response = await request(app)
@@ -322,6 +324,117 @@ describe('Sync API', () => {
     });
   });
 
+  describe('POST /api/v1/sync/events with tombstones', () => {
+    const event = (id: string) => ({
+      id,
+      event_type: 'app_usage',
+      timestamp: Date.now(),
+      duration: 300,
+      encrypted_data: 'encrypted_data',
+      nonce: 'a1b2c3d4e5f6a1b2c3d4e5f6',
+      tag: 'auth_tag_here_16bytes_base',
+    });
+
+    it('should delete tombstoned events and confirm their ids', async () => {
+      const deletedId = uuidv4();
+      const neverUploadedId = uuidv4();
+      await request(app)
+        .post('/api/v1/sync/events')
+        .set('Authorization', `Bearer ${authToken}`)
+        .send({ events: [event(deletedId)], last_sync_at: 0 });
+
+      const response = await request(app)
+        .post('/api/v1/sync/events')
+        .set('Authorization', `Bearer ${authToken}`)
+        .send({
+          events: [event(uuidv4())],
+          deleted: [
+            { id: deletedId, deleted_at: Date.now() },
+            { id: neverUploadedId, deleted_at: Date.now() },
+          ],
+          last_sync_at: 0,
+        });
+
+      expect(response.status).toBe(200);
+      expect(response.body.processed_count).toBe(1);
+      expect(response.body.deleted_ids).toEqual([deletedId, neverUploadedId]);
+
+      const download = await request(app)
+        .get('/api/v1/sync/events?limit=1000')
+        .set('Authorization', `Bearer ${authToken}`);
+      const ids = download.body.events.map((e: any) => e.id);
+      expect(ids).not.toContain(deletedId);
+      expect(ids).toHaveLength(1);
+    });
+
+    it('should confirm no deletions when none are sent', async () => {
+      const response = await request(app)
+        .post('/api/v1/sync/events')
+        .set('Authorization', `Bearer ${authToken}`)
+        .send({ events: [event(uuidv4())], last_sync_at: 0 });
+
+      expect(response.status).toBe(200);
+      expect(response.body.deleted_ids).toEqual([]);
+    });
+
+    it('should reject malformed tombstones', async () => {
+      const response = await request(app)
+        .post('/api/v1/sync/events')
+        .set('Authorization', `Bearer ${authToken}`)
+        .send({
+          events: [event(uuidv4())],
+          deleted: [{ id: 'not-a-uuid', deleted_at: Date.now() }],
+          last_sync_at: 0,
+        });
+
+      expect(response.status).toBe(400);
+    });
+  });
+
+  describe('/api/v1/sync/wrapped-key', () => {
+    const store = (body: object) => request(app)
+      .post('/api/v1/sync/wrapped-key')
+      .set('Authorization', `Bearer ${authToken}`)
+      .send(body);
+
+    it('should return nulls before anything is stored', async () => {
+      const response = await request(app)
+        .get('/api/v1/sync/wrapped-key')
+        .set('Authorization', `Bearer ${authToken}`);
+
+      expect(response.status).toBe(200);
+      expect(response.body).toEqual({ wrapped_key: null, key_check: null });
+    });
+
+    it('should keep the first wrapped key unless replacing it', async () => {
+      expect((await store({ wrapped_key: 'first', key_check: 'check' })).body.wrapped_key).toBe('first');
+      expect((await store({ wrapped_key: 'second', key_check: 'check' })).body.wrapped_key).toBe('first');
+
+      const replaced = await store({ wrapped_key: 'second', key_check: 'check', replace: true });
+      expect(replaced.status).toBe(200);
+      expect(replaced.body.wrapped_key).toBe('second');
+
+      const response = await request(app)
+        .get('/api/v1/sync/wrapped-key')
+        .set('Authorization', `Bearer ${authToken}`);
+      expect(response.body).toEqual({ wrapped_key: 'second', key_check: 'check' });
+    });
+
+    it('should refuse a wrapped key for another account key', async () => {
+      await store({ wrapped_key: 'first', key_check: 'check' });
+
+      const response = await store({ wrapped_key: 'other', key_check: 'other check', replace: true });
+      expect(response.status).toBe(409);
+      expect(response.body.error).toBe('key_mismatch');
+    });
+
+    it('should require authentication', async () => {
+      const response = await request(app).get('/api/v1/sync/wrapped-key');
+
+      expect(response.status).toBe(401);
+    });
+  });
+
   describe('GET /api/v1/sync/events', () => {
     beforeEach(async () => {
       // Create some test events
@@ -356,6 +469,38 @@ describe('Sync API', () => {
       expect(response.body.events.length).toBeLessThanOrEqual(100);
     });
 
+    it('should return the aad_version and remote_session each event was uploaded with', async () => {
+      const boundId = uuidv4();
+      await request(app)
+        .post('/api/v1/sync/events')
+        .set('Authorization', `Bearer ${authToken}`)
+        .send({
+          events: [{
+            id: boundId,
+            event_type: 'app_usage',
+            timestamp: Date.now(),
+            duration: 60,
+            encrypted_data: 'encrypted_data',
+            nonce: 'a1b2c3d4e5f6a1b2c3d4e5f6',
+            tag: 'auth_tag_here_16bytes_base',
+            aad_version: 1,
+            remote_session: true,
+          }],
+          last_sync_at: 0,
+        });
+
+      const response = await request(app)
+        .get('/api/v1/sync/events?limit=1000')
+        .set('Authorization', `Bearer ${authToken}`);
+
+      expect(response.status).toBe(200);
+      response.body.events.forEach((event: any) => {
+        // Omitted on upload means unbound, and not in a remote session
+        expect(event.aad_version).toBe(event.id === boundId ? 1 : 0);
+        expect(event.remote_session).toBe(event.id === boundId);
+      });
+    });
+
     it('should download events with custom limit', async () => {
       const response = await request(app)
         .get('/api/v1/sync/events?limit=3')
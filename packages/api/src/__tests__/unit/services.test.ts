@@ -276,6 +276,42 @@ describe('SyncService', () => {
       expect(result.events).toHaveLength(0);
     });
 
+    it('should page other devices\' events by sync cursor', async () => {
+      mockedQuery.mockResolvedValueOnce({
+        rows: [
+          {
+            id: 'event-1',
+            device_id: 'device-456',
+            sync_seq: '42',
+            event_type: 'app_usage',
+            timestamp: new Date('2024-01-01T10:00:00Z'),
+            duration: 300,
+            encrypted_data: 'encrypted_data',
+            nonce: 'a1b2c3d4e5f6a1b2c3d4e5f6',
+            tag: 'auth_tag',
+          },
+        ],
+        rowCount: 1,
+      } as never);
+      mockedQuery.mockResolvedValueOnce({ rows: [], rowCount: 1 } as never); // Insert sync record
+      mockedQuery.mockResolvedValueOnce({ rows: [], rowCount: 1 } as never); // Update user
+      mockedQuery.mockResolvedValueOnce({ rows: [], rowCount: 1 } as never); // Update device
+      mockedQuery.mockResolvedValueOnce({ rows: [], rowCount: 0 } as never); // COMMIT
+
+      const result = await syncService.downloadEvents(mockUserId, mockDeviceId, {
+        cursor: 40,
+        exclude_own: true,
+        limit: 100,
+      });
+
+      expect(mockedQuery).toHaveBeenCalledWith(
+        expect.stringContaining('sync_seq > $'),
+        [mockUserId, mockDeviceId, 40, 101]
+      );
+      expect(result.events[0].device_id).toBe('device-456');
+      expect(result.nextCursor).toBe(42);
+    });
+
     it('should handle hasMore correctly', async () => {
       // Return 101 events when limit is 100
       const mockEvents = Array.from({ length: 101 }, (_, i) => ({
@@ -1,13 +1,12 @@
 import type { Response } from 'express';
 import { Router } from 'express';
-import { z } from 'zod';
-import { UploadEventsSchema } from '../validators/sync.schema.js';
+import { UploadEventsSchema, DownloadEventsSchema, UploadArchiveSchema, DeleteEventsSchema, KeyCheckSchema, WrappedKeySchema } from '../validators/sync.schema.js';
 import { validateBody, validateQuery } from '../middleware/validation.js';
 import { authMiddleware, type AuthenticatedRequest } from '../middleware/auth.js';
 import { syncRateLimiter } from '../middleware/rateLimit.js';
 import { syncService } from '../services/sync.service.js';
 import { logger } from '../utils/logger.js';
-import { NotFoundError, DatabaseError } from '../utils/errors.js';
+import { NotFoundError, DatabaseError, ConflictError } from '../utils/errors.js';
 
 const router = Router();
 
@@ -57,6 +56,7 @@ router.post(
           processed_count: result.processedCount,
           conflicts: result.conflicts,
           synced_at: result.syncedAt,
+          deleted_ids: result.deletedIds,
         });
       }
 
@@ -71,6 +71,7 @@ router.post(
         synced_at: result.syncedAt,
         processed_count: result.processedCount,
         conflicts: [],
+        deleted_ids: result.deletedIds,
       });
     } catch (error) {
       if (error instanceof NotFoundError) {
@@ -122,12 +123,7 @@ router.get(
   '/events',
   authMiddleware,
   syncRateLimiter,
-  validateQuery(
-    z.object({
-      since: z.coerce.number().int().min(0).optional(),
-      limit: z.coerce.number().int().min(1).max(1000).default(100),
-    })
-  ),
+  validateQuery(DownloadEventsSchema),
   async (req, res: Response): Promise<Response | void> => {
     const requestId = generateRequestId();
     const authReq = req as AuthenticatedRequest;
@@ -140,11 +136,14 @@ router.get(
         userId,
         deviceId,
         since: req.query.since,
+        cursor: req.query.cursor,
         limit: req.query.limit,
       }, 'Event sync download request');
 
       const queryOptions = {
         since: typeof req.query.since === 'number' ? req.query.since : undefined,
+        cursor: typeof req.query.cursor === 'number' ? req.query.cursor : undefined,
+        exclude_own: typeof req.query.exclude_own === 'boolean' ? req.query.exclude_own : undefined,
         limit: typeof req.query.limit === 'number' ? req.query.limit : 100,
       };
       const result = await syncService.downloadEvents(userId, deviceId, queryOptions);
@@ -161,6 +160,7 @@ router.get(
         events: result.events,
         has_more: result.hasMore,
         latest_timestamp: result.latestTimestamp,
+        next_cursor: result.nextCursor,
       });
     } catch (error) {
       if (error instanceof NotFoundError) {
@@ -204,6 +204,369 @@ router.get(
   }
 );
 
+/**
+ * POST /api/v1/sync/archives
+ * Store an encrypted archive exported by the client before pruning local data
+ */
+router.post(
+  '/archives',
+  authMiddleware,
+  syncRateLimiter,
+  validateBody(UploadArchiveSchema),
+  async (req, res: Response): Promise<Response | void> => {
+    const requestId = generateRequestId();
+    const authReq = req as AuthenticatedRequest;
+    const userId = authReq.user.id;
+    const deviceId = authReq.user.deviceId;
+
+    try {
+      logger.info({
+        requestId,
+        userId,
+        deviceId,
+        fileName: req.body.file_name,
+        eventCount: req.body.event_count,
+      }, 'Archive upload request');
+
+      const result = await syncService.storeArchive(userId, deviceId, req.body);
+
+      res.status(201).json({
+        archive_id: result.archiveId,
+        stored_at: result.storedAt,
+      });
+    } catch (error) {
+      if (error instanceof NotFoundError) {
+        logger.warn({
+          requestId,
+          userId,
+          deviceId,
+          error: error.message,
+        }, 'Archive upload failed: not found');
+
+        return res.status(404).json({
+          error: 'not_found',
+          message: error.message,
+        });
+      } else if (error instanceof DatabaseError) {
+        logger.error({
+          requestId,
+          userId,
+          deviceId,
+          err: error,
+        }, 'Archive upload failed: database error');
+
+        return res.status(500).json({
+          error: 'database_error',
+          message: 'Failed to store archive',
+        });
+      } else if (error instanceof Error) {
+        logger.error({
+          requestId,
+          userId,
+          deviceId,
+          err: error,
+        }, 'Archive upload failed: unexpected error');
+
+        return res.status(500).json({
+          error: 'internal_error',
+          message: 'An unexpected error occurred',
+        });
+      }
+    }
+  }
+);
+
+/**
+ * POST /api/v1/sync/deletions
+ * Remove events the client deleted locally; confirmed ids are no longer stored
+ */
+router.post(
+  '/deletions',
+  authMiddleware,
+  syncRateLimiter,
+  validateBody(DeleteEventsSchema),
+  async (req, res: Response): Promise<Response | void> => {
+    const requestId = generateRequestId();
+    const authReq = req as AuthenticatedRequest;
+    const userId = authReq.user.id;
+    const deviceId = authReq.user.deviceId;
+
+    try {
+      logger.info({
+        requestId,
+        userId,
+        deviceId,
+        eventCount: req.body.event_ids.length,
+      }, 'Event deletion request');
+
+      const result = await syncService.deleteEvents(userId, deviceId, req.body);
+
+      res.status(200).json({
+        deleted_count: result.deletedCount,
+        confirmed_ids: result.confirmedIds,
+      });
+    } catch (error) {
+      if (error instanceof NotFoundError) {
+        logger.warn({
+          requestId,
+          userId,
+          deviceId,
+          error: error.message,
+        }, 'Event deletion failed: not found');
+
+        return res.status(404).json({
+          error: 'not_found',
+          message: error.message,
+        });
+      } else if (error instanceof DatabaseError) {
+        logger.error({
+          requestId,
+          userId,
+          deviceId,
+          err: error,
+        }, 'Event deletion failed: database error');
+
+        return res.status(500).json({
+          error: 'database_error',
+          message: 'Failed to delete events',
+        });
+      } else if (error instanceof Error) {
+        logger.error({
+          requestId,
+          userId,
+          deviceId,
+          err: error,
+        }, 'Event deletion failed: unexpected error');
+
+        return res.status(500).json({
+          error: 'internal_error',
+          message: 'An unexpected error occurred',
+        });
+      }
+    }
+  }
+);
+
+/**
+ * POST /api/v1/sync/key-check
+ * Store the user's key check value if none is stored yet; returns the stored value
+ */
+router.post(
+  '/key-check',
+  authMiddleware,
+  syncRateLimiter,
+  validateBody(KeyCheckSchema),
+  async (req, res: Response): Promise<Response | void> => {
+    const requestId = generateRequestId();
+    const authReq = req as AuthenticatedRequest;
+    const userId = authReq.user.id;
+    const deviceId = authReq.user.deviceId;
+
+    try {
+      logger.info({
+        requestId,
+        userId,
+        deviceId,
+      }, 'Key check request');
+
+      const keyCheck = await syncService.registerKeyCheck(userId, deviceId, req.body);
+
+      res.status(200).json({
+        key_check: keyCheck,
+      });
+    } catch (error) {
+      if (error instanceof NotFoundError) {
+        logger.warn({
+          requestId,
+          userId,
+          deviceId,
+          error: error.message,
+        }, 'Key check failed: not found');
+
+        return res.status(404).json({
+          error: 'not_found',
+          message: error.message,
+        });
+      } else if (error instanceof DatabaseError) {
+        logger.error({
+          requestId,
+          userId,
+          deviceId,
+          err: error,
+        }, 'Key check failed: database error');
+
+        return res.status(500).json({
+          error: 'database_error',
+          message: 'Failed to register key check',
+        });
+      } else if (error instanceof Error) {
+        logger.error({
+          requestId,
+          userId,
+          deviceId,
+          err: error,
+        }, 'Key check failed: unexpected error');
+
+        return res.status(500).json({
+          error: 'internal_error',
+          message: 'An unexpected error occurred',
+        });
+      }
+    }
+  }
+);
+
+/**
+ * GET /api/v1/sync/wrapped-key
+ * Get the user's passphrase-wrapped sync key and key check value (null until stored)
+ */
+router.get(
+  '/wrapped-key',
+  authMiddleware,
+  syncRateLimiter,
+  async (req, res: Response): Promise<Response | void> => {
+    const requestId = generateRequestId();
+    const authReq = req as AuthenticatedRequest;
+    const userId = authReq.user.id;
+    const deviceId = authReq.user.deviceId;
+
+    try {
+      logger.info({
+        requestId,
+        userId,
+        deviceId,
+      }, 'Wrapped key request');
+
+      const stored = await syncService.getWrappedKey(userId, deviceId);
+
+      res.status(200).json({
+        wrapped_key: stored.wrappedKey,
+        key_check: stored.keyCheck,
+      });
+    } catch (error) {
+      if (error instanceof NotFoundError) {
+        logger.warn({
+          requestId,
+          userId,
+          deviceId,
+          error: error.message,
+        }, 'Wrapped key request failed: not found');
+
+        return res.status(404).json({
+          error: 'not_found',
+          message: error.message,
+        });
+      } else if (error instanceof DatabaseError) {
+        logger.error({
+          requestId,
+          userId,
+          deviceId,
+          err: error,
+        }, 'Wrapped key request failed: database error');
+
+        return res.status(500).json({
+          error: 'database_error',
+          message: 'Failed to get wrapped key',
+        });
+      } else if (error instanceof Error) {
+        logger.error({
+          requestId,
+          userId,
+          deviceId,
+          err: error,
+        }, 'Wrapped key request failed: unexpected error');
+
+        return res.status(500).json({
+          error: 'internal_error',
+          message: 'An unexpected error occurred',
+        });
+      }
+    }
+  }
+);
+
+/**
+ * POST /api/v1/sync/wrapped-key
+ * Store the user's passphrase-wrapped sync key (kept unless `replace`); returns the stored value
+ */
+router.post(
+  '/wrapped-key',
+  authMiddleware,
+  syncRateLimiter,
+  validateBody(WrappedKeySchema),
+  async (req, res: Response): Promise<Response | void> => {
+    const requestId = generateRequestId();
+    const authReq = req as AuthenticatedRequest;
+    const userId = authReq.user.id;
+    const deviceId = authReq.user.deviceId;
+
+    try {
+      logger.info({
+        requestId,
+        userId,
+        deviceId,
+        replace: req.body.replace ?? false,
+      }, 'Store wrapped key request');
+
+      const wrappedKey = await syncService.storeWrappedKey(userId, deviceId, req.body);
+
+      res.status(200).json({
+        wrapped_key: wrappedKey,
+      });
+    } catch (error) {
+      if (error instanceof ConflictError) {
+        logger.warn({
+          requestId,
+          userId,
+          deviceId,
+          error: error.message,
+        }, 'Store wrapped key failed: key mismatch');
+
+        return res.status(409).json({
+          error: 'key_mismatch',
+          message: error.message,
+        });
+      } else if (error instanceof NotFoundError) {
+        logger.warn({
+          requestId,
+          userId,
+          deviceId,
+          error: error.message,
+        }, 'Store wrapped key failed: not found');
+
+        return res.status(404).json({
+          error: 'not_found',
+          message: error.message,
+        });
+      } else if (error instanceof DatabaseError) {
+        logger.error({
+          requestId,
+          userId,
+          deviceId,
+          err: error,
+        }, 'Store wrapped key failed: database error');
+
+        return res.status(500).json({
+          error: 'database_error',
+          message: 'Failed to store wrapped key',
+        });
+      } else if (error instanceof Error) {
+        logger.error({
+          requestId,
+          userId,
+          deviceId,
+          err: error,
+        }, 'Store wrapped key failed: unexpected error');
+
+        return res.status(500).json({
+          error: 'internal_error',
+          message: 'An unexpected error occurred',
+        });
+      }
+    }
+  }
+);
+
 /**
  * GET /api/v1/sync/status
  * Get sync status for the current user/device
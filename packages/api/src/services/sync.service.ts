@@ -1,14 +1,16 @@
 import { v4 as uuidv4 } from 'uuid';
 import { query } from '../utils/database.js';
-import { DatabaseError, NotFoundError } from '../utils/errors.js';
+import { ConflictError, DatabaseError, NotFoundError } from '../utils/errors.js';
 import { logger } from '../utils/logger.js';
 import { verifyDeviceOwnership, invalidateDeviceCache } from '../cache/device.cache.js';
-import type { EncryptedEvent, UploadEventsInput, DownloadEventsInput } from '../validators/sync.schema.js';
+import type { EncryptedEvent, UploadEventsInput, DownloadEventsInput, UploadArchiveInput, DeleteEventsInput, KeyCheckInput, WrappedKeyInput } from '../validators/sync.schema.js';
 
 export interface UploadResult {
   processedCount: number;
   conflicts: ConflictInfo[];
   syncedAt: number;
+  /** Tombstoned ids this device's events no longer exist under */
+  deletedIds: string[];
 }
 
 export interface ConflictInfo {
@@ -20,10 +22,31 @@ export interface ConflictInfo {
   };
 }
 
+/**
+ * An event as other devices download it: which device recorded it, and its
+ * aad_version (null if stored before the server kept it)
+ */
+export type DownloadedEvent = Omit<EncryptedEvent, 'aad_version'> & {
+  device_id: string;
+  aad_version: number | null;
+};
+
 export interface DownloadResult {
-  events: EncryptedEvent[];
+  events: DownloadedEvent[];
   hasMore: boolean;
   latestTimestamp: number;
+  /** Sync cursor after the last returned event; pass it as `cursor` to continue */
+  nextCursor: number;
+}
+
+export interface ArchiveResult {
+  archiveId: string;
+  storedAt: number;
+}
+
+export interface DeletionResult {
+  deletedCount: number;
+  confirmedIds: string[];
 }
 
 export interface SyncStatus {
@@ -123,11 +146,13 @@ export class SyncService {
           event.app_name || null,
           event.category || null,
           event.domain || null,
+          event.aad_version ?? 0,
+          event.remote_session ?? false,
         ]);
 
         // Build the query dynamically
         const rows = insertValues.map((_, i) =>
-          `($${i * 12 + 1}, $${i * 12 + 2}, $${i * 12 + 3}, $${i * 12 + 4}, $${i * 12 + 5}, $${i * 12 + 6}, $${i * 12 + 7}, $${i * 12 + 8}, $${i * 12 + 9}, $${i * 12 + 10}, $${i * 12 + 11}, $${i * 12 + 12})`
+          `($${i * 14 + 1}, $${i * 14 + 2}, $${i * 14 + 3}, $${i * 14 + 4}, $${i * 14 + 5}, $${i * 14 + 6}, $${i * 14 + 7}, $${i * 14 + 8}, $${i * 14 + 9}, $${i * 14 + 10}, $${i * 14 + 11}, $${i * 14 + 12}, $${i * 14 + 13}, $${i * 14 + 14})`
         ).join(', ');
 
         const flatValues = insertValues.flat();
@@ -135,7 +160,8 @@ export class SyncService {
         await query(
           `INSERT INTO events (
             id, user_id, device_id, event_type, timestamp, duration,
-            encrypted_data, iv, auth_tag, app_name, category, domain
+            encrypted_data, iv, auth_tag, app_name, category, domain, aad_version,
+            remote_session
           ) VALUES ${rows}
           ON CONFLICT (id) DO UPDATE SET
             timestamp = EXCLUDED.timestamp,
@@ -146,13 +172,27 @@ export class SyncService {
             app_name = EXCLUDED.app_name,
             category = EXCLUDED.category,
             domain = EXCLUDED.domain,
-            synced_at = CURRENT_TIMESTAMP`,
+            aad_version = EXCLUDED.aad_version,
+            remote_session = EXCLUDED.remote_session,
+            synced_at = CURRENT_TIMESTAMP,
+            sync_seq = nextval('events_sync_seq')`,
           flatValues
         );
 
         processedCount = eventsToInsert.length;
       }
 
+      // Tombstones go after the upserts, so a deletion wins over a stale copy
+      // in the same batch. As with deleteEvents, every id is confirmed.
+      const deletedIds = (input.deleted ?? []).map(tombstone => tombstone.id);
+      if (deletedIds.length > 0) {
+        await query(
+          `DELETE FROM events
+             WHERE user_id = $1 AND device_id = $2 AND id = ANY($3::uuid[])`,
+          [userId, deviceId, deletedIds]
+        );
+      }
+
       // Wrap sync record and updates in a transaction
       await query('BEGIN');
 
@@ -177,10 +217,14 @@ export class SyncService {
           [userId]
         );
 
-        // Update device last seen
+        // Update device last seen (and client metadata when provided)
         await query(
-          'UPDATE devices SET last_seen_at = CURRENT_TIMESTAMP WHERE id = $1',
-          [deviceId]
+          `UPDATE devices
+             SET last_seen_at = CURRENT_TIMESTAMP,
+                 app_version = COALESCE($2, app_version),
+                 os_version = COALESCE($3, os_version)
+           WHERE id = $1`,
+          [deviceId, input.client?.app_version ?? null, input.client?.os_version ?? null]
         );
 
         await query('COMMIT');
@@ -199,6 +243,7 @@ export class SyncService {
         deviceId,
         processedCount,
         conflictCount: conflicts.length,
+        deletedCount: deletedIds.length,
         duration,
       }, 'Event upload completed');
 
@@ -206,6 +251,7 @@ export class SyncService {
         processedCount,
         conflicts,
         syncedAt: Date.now(),
+        deletedIds,
       };
     } catch (error) {
       if (error instanceof NotFoundError) {
@@ -247,6 +293,8 @@ export class SyncService {
       let queryText = `
         SELECT
           id,
+          device_id,
+          sync_seq,
           event_type,
           timestamp,
           duration,
@@ -255,7 +303,9 @@ export class SyncService {
           auth_tag as tag,
           app_name,
           category,
-          domain
+          domain,
+          aad_version,
+          remote_session
         FROM events
         WHERE user_id = $1
       `;
@@ -263,22 +313,36 @@ export class SyncService {
       const params: (string | number)[] = [userId];
       let paramIndex = 2;
 
-      // Add time filter if provided
-      if (options.since) {
+      if (options.exclude_own) {
+        queryText += ` AND device_id <> $${paramIndex}`;
+        params.push(deviceId);
+        paramIndex++;
+      }
+
+      // A cursor pages by write order, so events uploaded late are not missed;
+      // otherwise filter by event time
+      const byCursor = options.cursor !== undefined;
+      if (byCursor) {
+        queryText += ` AND sync_seq > $${paramIndex}`;
+        params.push(options.cursor as number);
+        paramIndex++;
+      } else if (options.since) {
         queryText += ` AND timestamp > $${paramIndex}`;
         params.push(options.since);
         paramIndex++;
       }
 
-      // Order by timestamp and limit
-      queryText += ` ORDER BY timestamp ASC LIMIT $${paramIndex}`;
+      // Order and limit
+      queryText += ` ORDER BY ${byCursor ? 'sync_seq' : 'timestamp'} ASC LIMIT $${paramIndex}`;
       params.push(options.limit + 1); // Fetch one extra to check if there are more
 
       const result = await query(queryText, params);
 
       const hasMore = result.rows.length > options.limit;
-      const events = result.rows.slice(0, options.limit).map(row => ({
+      const rows = result.rows.slice(0, options.limit);
+      const events = rows.map(row => ({
         id: row.id,
+        device_id: row.device_id,
         event_type: row.event_type,
         timestamp: new Date(row.timestamp).getTime(),
         duration: row.duration,
@@ -288,12 +352,20 @@ export class SyncService {
         app_name: row.app_name || undefined,
         category: row.category || undefined,
         domain: row.domain || undefined,
+        // null for events stored before the version was kept
+        aad_version: row.aad_version,
+        remote_session: row.remote_session,
       }));
 
       const latestTimestamp = events.length > 0
         ? events[events.length - 1].timestamp
         : options.since || 0;
 
+      // bigint columns arrive as strings
+      const nextCursor = rows.length > 0
+        ? Number(rows[rows.length - 1].sync_seq)
+        : options.cursor ?? 0;
+
       // Wrap sync record and updates in a transaction
       await query('BEGIN');
 
@@ -347,6 +419,7 @@ export class SyncService {
         events,
         hasMore,
         latestTimestamp,
+        nextCursor,
       };
     } catch (error) {
       if (error instanceof NotFoundError) {
@@ -424,6 +497,245 @@ export class SyncService {
       throw new DatabaseError('Failed to get sync status', error as Error);
     }
   }
+
+  /**
+   * Store an encrypted archive the client exported before pruning local data.
+   * The archive is opaque to the server; re-uploading the same file replaces it.
+   */
+  async storeArchive(
+    userId: string,
+    deviceId: string,
+    input: UploadArchiveInput
+  ): Promise<ArchiveResult> {
+    try {
+      // Verify device belongs to user (with caching)
+      await verifyDeviceOwnership(deviceId, userId);
+
+      const data = Buffer.from(input.data, 'base64');
+
+      const result = await query(
+        `INSERT INTO event_archives (id, user_id, device_id, file_name, range_start, range_end, event_count, data)
+           VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+           ON CONFLICT (device_id, file_name) DO UPDATE
+             SET range_start = EXCLUDED.range_start,
+                 range_end = EXCLUDED.range_end,
+                 event_count = EXCLUDED.event_count,
+                 data = EXCLUDED.data,
+                 created_at = CURRENT_TIMESTAMP
+           RETURNING id`,
+        [
+          uuidv4(),
+          userId,
+          deviceId,
+          input.file_name,
+          new Date(input.range_start),
+          new Date(input.range_end),
+          input.event_count,
+          data,
+        ]
+      );
+
+      logger.info({
+        userId,
+        deviceId,
+        fileName: input.file_name,
+        eventCount: input.event_count,
+        bytes: data.length,
+      }, 'Event archive stored');
+
+      return {
+        archiveId: result.rows[0].id,
+        storedAt: Date.now(),
+      };
+    } catch (error) {
+      if (error instanceof NotFoundError) {
+        throw error;
+      }
+
+      logger.error({
+        err: error,
+        userId,
+        deviceId,
+      }, 'Failed to store event archive');
+
+      throw new DatabaseError('Failed to store event archive', error as Error);
+    }
+  }
+
+  /**
+   * Delete events the client removed locally.
+   * Afterwards none of the ids exist for this device, so all of them are confirmed,
+   * including ids that were never uploaded or were already deleted.
+   */
+  async deleteEvents(
+    userId: string,
+    deviceId: string,
+    input: DeleteEventsInput
+  ): Promise<DeletionResult> {
+    try {
+      // Verify device belongs to user (with caching)
+      await verifyDeviceOwnership(deviceId, userId);
+
+      const result = await query(
+        `DELETE FROM events
+           WHERE user_id = $1 AND device_id = $2 AND id = ANY($3::uuid[])`,
+        [userId, deviceId, input.event_ids]
+      );
+
+      const deletedCount = result.rowCount ?? 0;
+
+      logger.info({
+        userId,
+        deviceId,
+        requestedCount: input.event_ids.length,
+        deletedCount,
+      }, 'Events deleted on client request');
+
+      return {
+        deletedCount,
+        confirmedIds: input.event_ids,
+      };
+    } catch (error) {
+      if (error instanceof NotFoundError) {
+        throw error;
+      }
+
+      logger.error({
+        err: error,
+        userId,
+        deviceId,
+      }, 'Failed to delete events');
+
+      throw new DatabaseError('Failed to delete events', error as Error);
+    }
+  }
+
+  /**
+   * Return the user's key check value, storing the given one if none is stored yet.
+   * The value is opaque to the server; clients compare it against their own key.
+   */
+  async registerKeyCheck(
+    userId: string,
+    deviceId: string,
+    input: KeyCheckInput
+  ): Promise<string> {
+    try {
+      // Verify device belongs to user (with caching)
+      await verifyDeviceOwnership(deviceId, userId);
+
+      const result = await query(
+        `UPDATE users SET key_check = COALESCE(key_check, $2)
+           WHERE id = $1
+           RETURNING key_check`,
+        [userId, input.key_check]
+      );
+
+      if (result.rows.length === 0) {
+        throw new NotFoundError('User not found');
+      }
+
+      return result.rows[0].key_check;
+    } catch (error) {
+      if (error instanceof NotFoundError) {
+        throw error;
+      }
+
+      logger.error({
+        err: error,
+        userId,
+        deviceId,
+      }, 'Failed to register key check');
+
+      throw new DatabaseError('Failed to register key check', error as Error);
+    }
+  }
+
+  /**
+   * Return the user's passphrase-wrapped sync key and key check value, if stored.
+   * Both are opaque to the server; only the passphrase unwraps the key.
+   */
+  async getWrappedKey(
+    userId: string,
+    deviceId: string
+  ): Promise<{ wrappedKey: string | null; keyCheck: string | null }> {
+    try {
+      // Verify device belongs to user (with caching)
+      await verifyDeviceOwnership(deviceId, userId);
+
+      const result = await query(
+        'SELECT wrapped_key, key_check FROM users WHERE id = $1',
+        [userId]
+      );
+
+      if (result.rows.length === 0) {
+        throw new NotFoundError('User not found');
+      }
+
+      return {
+        wrappedKey: result.rows[0].wrapped_key,
+        keyCheck: result.rows[0].key_check,
+      };
+    } catch (error) {
+      if (error instanceof NotFoundError) {
+        throw error;
+      }
+
+      logger.error({
+        err: error,
+        userId,
+        deviceId,
+      }, 'Failed to get wrapped key');
+
+      throw new DatabaseError('Failed to get wrapped key', error as Error);
+    }
+  }
+
+  /**
+   * Store the user's passphrase-wrapped sync key, keeping a stored one unless
+   * `replace` is set; returns the stored value. The client must send the
+   * account's key check value, so a device with another key can't replace it.
+   */
+  async storeWrappedKey(
+    userId: string,
+    deviceId: string,
+    input: WrappedKeyInput
+  ): Promise<string> {
+    try {
+      // Verify device belongs to user (with caching)
+      await verifyDeviceOwnership(deviceId, userId);
+
+      const result = await query(
+        `UPDATE users SET
+             key_check = COALESCE(key_check, $3),
+             wrapped_key = CASE WHEN $4 THEN $2 ELSE COALESCE(wrapped_key, $2) END
+           WHERE id = $1 AND (key_check IS NULL OR key_check = $3)
+           RETURNING wrapped_key`,
+        [userId, input.wrapped_key, input.key_check, input.replace ?? false]
+      );
+
+      if (result.rows.length === 0) {
+        const user = await query('SELECT 1 FROM users WHERE id = $1', [userId]);
+        if (user.rows.length === 0) {
+          throw new NotFoundError('User not found');
+        }
+        throw new ConflictError('Key check does not match the account');
+      }
+
+      return result.rows[0].wrapped_key;
+    } catch (error) {
+      if (error instanceof NotFoundError || error instanceof ConflictError) {
+        throw error;
+      }
+
+      logger.error({
+        err: error,
+        userId,
+        deviceId,
+      }, 'Failed to store wrapped key');
+
+      throw new DatabaseError('Failed to store wrapped key', error as Error);
+    }
+  }
 }
 
 // Export singleton instance
@@ -51,7 +51,7 @@ export type RefreshTokenInput = z.infer<typeof RefreshTokenSchema>;
 
 export const EncryptedEventSchema = z.object({
   id: z.string().uuid('Invalid event ID format'),
-  event_type: z.enum(['app_usage', 'web_activity', 'file_activity', 'communication'], {
+  event_type: z.enum(['app_usage', 'web_activity', 'file_activity', 'communication', 'timezone_change', 'system_suspend', 'system_resume', 'afk', 'media_playback', 'camera_on', 'mic_on', 'focus_session', 'coding', 'terminal', 'custom'], {
     errorMap: () => ({ message: 'Invalid event type' }),
   }),
   timestamp: z.number()
@@ -72,13 +72,40 @@ export const EncryptedEventSchema = z.object({
   app_name: z.string().max(255).optional(),
   category: z.enum(['work', 'communication', 'entertainment', 'learning', 'utility', 'other']).optional(),
   domain: z.string().max(255).optional(),
+  remote_session: z.boolean().optional(),
+  fullscreen: z.boolean().optional(),
+  // How the ciphertext is bound to the event; 0 (unbound) when omitted
+  aad_version: z.number()
+    .int('AAD version must be an integer')
+    .min(0, 'AAD version cannot be negative')
+    .max(255, 'AAD version cannot exceed 255')
+    .optional(),
+});
+
+export const ClientInfoSchema = z.object({
+  app_version: z.string().max(20),
+  os: z.string().max(20),
+  os_version: z.string().max(50).optional(),
+  arch: z.string().max(20),
+});
+
+export const SyncTombstoneSchema = z.object({
+  id: z.string().uuid('Invalid event ID format'),
+  deleted_at: z.number()
+    .int('Deletion timestamp must be an integer')
+    .min(0, 'Deletion timestamp cannot be negative'),
 });
 
 export const UploadEventsSchema = z.object({
   device_id: z.string().uuid('Invalid device ID format'),
+  client: ClientInfoSchema.optional(),
   events: z.array(EncryptedEventSchema)
     .min(1, 'At least one event is required')
-    .max(100, 'Cannot upload more than 100 events at once'),
+    .max(500, 'Cannot upload more than 500 events at once'),
+  // Events the client deleted locally; the server drops its copies too
+  deleted: z.array(SyncTombstoneSchema)
+    .max(500, 'Cannot delete more than 500 events at once')
+    .optional(),
   last_sync_at: z.number()
     .int('Last sync timestamp must be an integer')
     .min(0, 'Last sync timestamp cannot be negative')
@@ -90,6 +117,15 @@ export const DownloadEventsSchema = z.object({
     .int('Since timestamp must be an integer')
     .min(0, 'Since timestamp cannot be negative')
     .optional(),
+  // Sync cursor from a previous download's next_cursor; takes precedence over since
+  cursor: z.coerce.number()
+    .int('Cursor must be an integer')
+    .min(0, 'Cursor cannot be negative')
+    .optional(),
+  // Leave out the requesting device's own events
+  exclude_own: z.enum(['true', 'false'])
+    .transform((value) => value === 'true')
+    .optional(),
   limit: z.coerce.number()
     .int('Limit must be an integer')
     .min(1, 'Limit must be at least 1')
@@ -97,9 +133,62 @@ export const DownloadEventsSchema = z.object({
     .default(100),
 });
 
+export const UploadArchiveSchema = z.object({
+  device_id: z.string().uuid('Invalid device ID format'),
+  file_name: z.string()
+    .min(1, 'File name is required')
+    .max(255, 'File name must not exceed 255 characters'),
+  range_start: z.number()
+    .int('Range start must be an integer')
+    .min(0, 'Range start cannot be negative'),
+  range_end: z.number()
+    .int('Range end must be an integer')
+    .min(0, 'Range end cannot be negative'),
+  event_count: z.number()
+    .int('Event count must be an integer')
+    .min(0, 'Event count cannot be negative'),
+  data: z.string()
+    .min(1, 'Archive data is required')
+    .max(8 * 1024 * 1024, 'Archive data cannot exceed 8MB'), // Base64 encoded
+}).refine((archive) => archive.range_end >= archive.range_start, {
+  message: 'Range end must not be before range start',
+  path: ['range_end'],
+});
+
+export const DeleteEventsSchema = z.object({
+  device_id: z.string().uuid('Invalid device ID format'),
+  event_ids: z.array(z.string().uuid('Invalid event ID format'))
+    .min(1, 'At least one event ID is required')
+    .max(500, 'Cannot delete more than 500 events at once'),
+});
+
+export const KeyCheckSchema = z.object({
+  key_check: z.string()
+    .min(1, 'Key check value is required')
+    .max(1024, 'Key check value must not exceed 1024 characters'), // Base64 encoded
+});
+
+export const WrappedKeySchema = z.object({
+  wrapped_key: z.string()
+    .min(1, 'Wrapped key is required')
+    .max(1024, 'Wrapped key must not exceed 1024 characters'), // Base64 encoded
+  // The account key check the client confirmed its key against
+  key_check: z.string()
+    .min(1, 'Key check value is required')
+    .max(1024, 'Key check value must not exceed 1024 characters'),
+  // Replace a stored wrapped key (after a passphrase change) instead of keeping it
+  replace: z.boolean().optional(),
+});
+
 export type EncryptedEvent = z.infer<typeof EncryptedEventSchema>;
+export type ClientInfo = z.infer<typeof ClientInfoSchema>;
+export type SyncTombstone = z.infer<typeof SyncTombstoneSchema>;
 export type UploadEventsInput = z.infer<typeof UploadEventsSchema>;
 export type DownloadEventsInput = z.infer<typeof DownloadEventsSchema>;
+export type UploadArchiveInput = z.infer<typeof UploadArchiveSchema>;
+export type DeleteEventsInput = z.infer<typeof DeleteEventsSchema>;
+export type KeyCheckInput = z.infer<typeof KeyCheckSchema>;
+export type WrappedKeyInput = z.infer<typeof WrappedKeySchema>;
 
 // ============================================================================
 // Response Schemas
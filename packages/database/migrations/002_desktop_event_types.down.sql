@@ -0,0 +1,10 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 回滚桌面端系统事件类型
+-- 注意: 回滚前需删除或转换使用新事件类型的行
+-- ============================================================================
+
+ALTER TABLE events DROP CONSTRAINT IF EXISTS events_event_type_check;
+
+ALTER TABLE events ADD CONSTRAINT events_event_type_check CHECK (event_type IN (
+    'app_usage', 'web_activity', 'file_activity', 'communication'
+));
@@ -0,0 +1,13 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 桌面端系统事件类型
+-- 允许桌面端同步的系统事件: 时区变化、休眠/唤醒、离开(AFK)
+-- 注意: 约束须与 API 校验器 (validators/sync.schema.ts) 的 event_type 枚举一致,
+--       否则校验通过的事件会在插入时失败; 新增事件类型时两处同时修改
+-- ============================================================================
+
+ALTER TABLE events DROP CONSTRAINT IF EXISTS events_event_type_check;
+
+ALTER TABLE events ADD CONSTRAINT events_event_type_check CHECK (event_type IN (
+    'app_usage', 'web_activity', 'file_activity', 'communication',
+    'timezone_change', 'system_suspend', 'system_resume', 'afk'
+));
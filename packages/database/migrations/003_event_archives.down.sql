@@ -0,0 +1,5 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 回滚事件归档
+-- ============================================================================
+
+DROP TABLE IF EXISTS event_archives;
@@ -0,0 +1,28 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 事件归档
+-- 桌面端在本地清理旧数据前导出的加密归档文件（服务器只保存不透明的密文）
+-- ============================================================================
+
+CREATE TABLE event_archives (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    device_id UUID NOT NULL REFERENCES devices(id) ON DELETE CASCADE,
+
+    -- 归档信息
+    file_name VARCHAR(255) NOT NULL,
+    range_start TIMESTAMP WITH TIME ZONE NOT NULL,
+    range_end TIMESTAMP WITH TIME ZONE NOT NULL,
+    event_count INTEGER NOT NULL CHECK (event_count >= 0),
+
+    -- 加密数据（客户端加密，服务器无法解密）
+    data BYTEA NOT NULL,
+
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+
+    UNIQUE (device_id, file_name),
+    CHECK (range_end >= range_start)
+);
+
+CREATE INDEX idx_event_archives_user ON event_archives(user_id, range_start);
+
+COMMENT ON TABLE event_archives IS '桌面端清理前导出的加密事件归档';
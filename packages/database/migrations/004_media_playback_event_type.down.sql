@@ -0,0 +1,11 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 回滚媒体播放事件类型
+-- 注意: 回滚前需删除或转换 media_playback 行
+-- ============================================================================
+
+ALTER TABLE events DROP CONSTRAINT IF EXISTS events_event_type_check;
+
+ALTER TABLE events ADD CONSTRAINT events_event_type_check CHECK (event_type IN (
+    'app_usage', 'web_activity', 'file_activity', 'communication',
+    'timezone_change', 'system_suspend', 'system_resume', 'afk'
+));
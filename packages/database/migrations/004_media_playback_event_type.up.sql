@@ -0,0 +1,12 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 媒体播放事件类型
+-- 桌面端记录正在播放的音乐/播客（即使焦点在其他窗口）
+-- ============================================================================
+
+ALTER TABLE events DROP CONSTRAINT IF EXISTS events_event_type_check;
+
+ALTER TABLE events ADD CONSTRAINT events_event_type_check CHECK (event_type IN (
+    'app_usage', 'web_activity', 'file_activity', 'communication',
+    'timezone_change', 'system_suspend', 'system_resume', 'afk',
+    'media_playback'
+));
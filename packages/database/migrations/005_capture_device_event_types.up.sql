@@ -0,0 +1,12 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 摄像头/麦克风事件类型
+-- 桌面端记录摄像头和麦克风的占用时段（会议、录制）
+-- ============================================================================
+
+ALTER TABLE events DROP CONSTRAINT IF EXISTS events_event_type_check;
+
+ALTER TABLE events ADD CONSTRAINT events_event_type_check CHECK (event_type IN (
+    'app_usage', 'web_activity', 'file_activity', 'communication',
+    'timezone_change', 'system_suspend', 'system_resume', 'afk',
+    'media_playback', 'camera_on', 'mic_on'
+));
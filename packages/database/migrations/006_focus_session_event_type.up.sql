@@ -0,0 +1,12 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 专注时段事件类型
+-- 桌面端番茄钟记录的专注时段，标题为期间使用的应用
+-- ============================================================================
+
+ALTER TABLE events DROP CONSTRAINT IF EXISTS events_event_type_check;
+
+ALTER TABLE events ADD CONSTRAINT events_event_type_check CHECK (event_type IN (
+    'app_usage', 'web_activity', 'file_activity', 'communication',
+    'timezone_change', 'system_suspend', 'system_resume', 'afk',
+    'media_playback', 'camera_on', 'mic_on', 'focus_session'
+));
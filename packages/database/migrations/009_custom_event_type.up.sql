@@ -0,0 +1,13 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 自定义事件类型
+-- 用户或脚本手动记录的事件，app_name 为用户定义的类型
+-- ============================================================================
+
+ALTER TABLE events DROP CONSTRAINT IF EXISTS events_event_type_check;
+
+ALTER TABLE events ADD CONSTRAINT events_event_type_check CHECK (event_type IN (
+    'app_usage', 'web_activity', 'file_activity', 'communication',
+    'timezone_change', 'system_suspend', 'system_resume', 'afk',
+    'media_playback', 'camera_on', 'mic_on', 'focus_session', 'coding', 'terminal',
+    'custom'
+));
@@ -0,0 +1,5 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 回滚加密密钥校验值
+-- ============================================================================
+
+ALTER TABLE users DROP COLUMN IF EXISTS key_check;
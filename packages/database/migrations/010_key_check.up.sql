@@ -0,0 +1,6 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 加密密钥校验值
+-- 客户端用同步密钥加密的已知常量；第一台设备写入，其余设备上传前用它校验密钥
+-- ============================================================================
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS key_check TEXT;
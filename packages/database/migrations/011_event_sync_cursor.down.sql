@@ -0,0 +1,7 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 回滚事件同步游标
+-- ============================================================================
+
+DROP INDEX IF EXISTS idx_events_user_sync_seq;
+ALTER TABLE events DROP COLUMN IF EXISTS sync_seq;
+DROP SEQUENCE IF EXISTS events_sync_seq;
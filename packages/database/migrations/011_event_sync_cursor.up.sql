@@ -0,0 +1,13 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 事件同步游标
+-- 每次写入或更新事件都取一个递增序号，设备按序号增量拉取其他设备的事件，
+-- 晚上传的旧事件也不会漏掉
+-- ============================================================================
+
+CREATE SEQUENCE IF NOT EXISTS events_sync_seq;
+
+ALTER TABLE events ADD COLUMN IF NOT EXISTS sync_seq BIGINT NOT NULL DEFAULT nextval('events_sync_seq');
+
+CREATE INDEX IF NOT EXISTS idx_events_user_sync_seq ON events(user_id, sync_seq);
+
+COMMENT ON COLUMN events.sync_seq IS '同步游标：事件最后一次写入的序号';
@@ -0,0 +1,5 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 回滚口令包装的同步主密钥
+-- ============================================================================
+
+ALTER TABLE users DROP COLUMN IF EXISTS wrapped_key;
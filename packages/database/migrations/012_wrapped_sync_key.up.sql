@@ -0,0 +1,6 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 口令包装的同步主密钥
+-- 客户端用口令派生密钥包装的随机主密钥；服务器无法解开，修改口令后由客户端替换
+-- ============================================================================
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS wrapped_key TEXT;
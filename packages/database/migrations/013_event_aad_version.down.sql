@@ -0,0 +1,5 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 回滚事件密文的关联数据版本
+-- ============================================================================
+
+ALTER TABLE events DROP COLUMN IF EXISTS aad_version;
@@ -0,0 +1,10 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 事件密文的关联数据版本
+-- 客户端上传的 aad_version：0 为未绑定事件的旧密文，1 起绑定事件 ID/设备/时间戳；
+-- 迁移前存入的事件为 NULL（版本未知）
+-- ============================================================================
+
+ALTER TABLE events ADD COLUMN IF NOT EXISTS aad_version SMALLINT
+  CHECK (aad_version IS NULL OR aad_version BETWEEN 0 AND 255);
+
+COMMENT ON COLUMN events.aad_version IS '密文关联数据版本：0 未绑定，NULL 为迁移前存入';
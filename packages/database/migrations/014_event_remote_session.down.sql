@@ -0,0 +1,5 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 回滚远程会话标记
+-- ============================================================================
+
+ALTER TABLE events DROP COLUMN IF EXISTS remote_session;
@@ -0,0 +1,8 @@
+-- ============================================================================
+-- Lifespan 数据库架构 - 远程会话标记
+-- 事件是否记录在远程桌面/虚拟机查看器中；跨设备时间线据此在重叠时段优先计入本地操作的设备
+-- ============================================================================
+
+ALTER TABLE events ADD COLUMN IF NOT EXISTS remote_session BOOLEAN NOT NULL DEFAULT FALSE;
+
+COMMENT ON COLUMN events.remote_session IS '记录于远程桌面/虚拟机查看器中';
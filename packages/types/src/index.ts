@@ -6,6 +6,17 @@ export enum EventType {
   WEB_ACTIVITY = 'web_activity',
   FILE_ACTIVITY = 'file_activity',
   COMMUNICATION = 'communication',
+  TIMEZONE_CHANGE = 'timezone_change',
+  SYSTEM_SUSPEND = 'system_suspend',
+  SYSTEM_RESUME = 'system_resume',
+  AFK = 'afk',
+  MEDIA_PLAYBACK = 'media_playback',
+  CAMERA_ON = 'camera_on',
+  MIC_ON = 'mic_on',
+  FOCUS_SESSION = 'focus_session',
+  CODING = 'coding',
+  TERMINAL = 'terminal',
+  CUSTOM = 'custom',
 }
 
 // 应用分类